@@ -0,0 +1,648 @@
+// Warning/alarm/trip limits for the analog channels, the classification of readings
+// against them with hysteresis and debounce, rate-of-change alarms, and the list of
+// raised alarms awaiting acknowledgment.
+use crate::clock::format_datetime;
+use crate::i18n::{tr, tr_args};
+use crate::json::Value;
+use crate::sensors::{Channel, ChannelKind};
+use crate::units::Units;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+// Alarms raised longer than this before a shutdown are taken to have nothing to do with it
+pub const FIRST_OUT_WINDOW: Duration = Duration::from_secs(60);
+
+// Ordered by severity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AlarmLevel {
+    #[default]
+    Normal,
+    Warning,
+    Alarm,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub low_alarm: f32,
+    pub low_warning: f32,
+    pub high_warning: f32,
+    pub high_alarm: f32,
+    // Beyond these the system shuts itself down; None means no trip on that side
+    pub low_trip: Option<f32>,
+    pub high_trip: Option<f32>,
+}
+
+impl Limits {
+    pub fn new(low_alarm: f32, low_warning: f32, high_warning: f32, high_alarm: f32) -> Self {
+        Limits { low_alarm, low_warning, high_warning, high_alarm, low_trip: None, high_trip: None }
+    }
+
+    pub fn with_trips(self, low_trip: Option<f32>, high_trip: Option<f32>) -> Self {
+        Limits { low_trip, high_trip, ..self }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.low_alarm <= self.low_warning
+            && self.low_warning < self.high_warning
+            && self.high_warning <= self.high_alarm)
+        {
+            return Err(format!(
+                "limits must satisfy low alarm <= low warning < high warning <= high alarm (got {:.1} / {:.1} / {:.1} / {:.1})",
+                self.low_alarm, self.low_warning, self.high_warning, self.high_alarm
+            ));
+        }
+        if self.low_trip.is_some_and(|trip| trip > self.low_alarm) || self.high_trip.is_some_and(|trip| trip < self.high_alarm) {
+            return Err(format!(
+                "trip limits must lie outside the alarm limits (low trip <= {:.1}, high trip >= {:.1})",
+                self.low_alarm, self.high_alarm
+            ));
+        }
+        Ok(())
+    }
+
+    // The trip limit the value is beyond, if any. An open circuit (NaN) never trips.
+    pub fn trip_limit(&self, value: f32) -> Option<f32> {
+        match (self.low_trip, self.high_trip) {
+            (_, Some(high)) if value >= high => Some(high),
+            (Some(low), _) if value <= low => Some(low),
+            _ => None,
+        }
+    }
+
+    // A reading that can't be trusted (NaN from an open circuit) is an alarm in itself
+    pub fn classify(&self, value: f32) -> AlarmLevel {
+        if value.is_nan() || value <= self.low_alarm || value >= self.high_alarm {
+            AlarmLevel::Alarm
+        } else if value <= self.low_warning || value >= self.high_warning {
+            AlarmLevel::Warning
+        } else {
+            AlarmLevel::Normal
+        }
+    }
+
+    // As classify, but a reading only drops below the current level once it is the deadband
+    // back inside the limit it crossed
+    pub fn classify_from(&self, value: f32, current: AlarmLevel, deadband: f32) -> AlarmLevel {
+        let level = self.classify(value);
+        if level >= current {
+            return level;
+        }
+        let narrowed = Limits::new(
+            self.low_alarm + deadband,
+            self.low_warning + deadband,
+            self.high_warning - deadband,
+            self.high_alarm - deadband,
+        );
+        narrowed.classify(value).min(current)
+    }
+
+    // Updates one limit by name, leaving the others untouched if the result would be invalid
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let mut updated = *self;
+        match name {
+            "low-alarm" => updated.low_alarm = value,
+            "low-warning" => updated.low_warning = value,
+            "high-warning" => updated.high_warning = value,
+            "high-alarm" => updated.high_alarm = value,
+            "low-trip" => updated.low_trip = Some(value),
+            "high-trip" => updated.high_trip = Some(value),
+            _ => return Err(format!("unknown limit '{}' (use low-alarm, low-warning, high-warning, high-alarm, low-trip or high-trip)", name)),
+        }
+        updated.validate()?;
+        *self = updated;
+        Ok(())
+    }
+
+    // As saved in snapshots; unset trip limits are null
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("low_alarm".to_string(), self.low_alarm.into()),
+            ("low_warning".to_string(), self.low_warning.into()),
+            ("high_warning".to_string(), self.high_warning.into()),
+            ("high_alarm".to_string(), self.high_alarm.into()),
+            ("low_trip".to_string(), self.low_trip.map_or(Value::Null, Value::from)),
+            ("high_trip".to_string(), self.high_trip.map_or(Value::Null, Value::from)),
+        ])
+    }
+
+    // Trip limits are absent from older snapshots. Not validated.
+    pub fn from_json(value: &Value) -> Option<Limits> {
+        let trip = |key: &str| value.get(key).and_then(Value::as_f64).map(|limit| limit as f32);
+        Some(Limits::new(
+            value.f64_field("low_alarm").ok()? as f32,
+            value.f64_field("low_warning").ok()? as f32,
+            value.f64_field("high_warning").ok()? as f32,
+            value.f64_field("high_alarm").ok()? as f32,
+        ).with_trips(trip("low_trip"), trip("high_trip")))
+    }
+}
+
+// Keeps a reading jittering around a limit from raising and clearing its alarm on every
+// sample. Configured per channel type under [alarms.temperature] and [alarms.pressure].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlarmFilter {
+    // How far back inside a limit, in °C or kPa, a reading must be before it clears
+    pub deadband: f32,
+    // Samples in a row at a worse level before it is reported
+    pub raise_samples: u32,
+    // Samples in a row at a better level, deadband included, before it is reported
+    pub clear_samples: u32,
+}
+
+impl Default for AlarmFilter {
+    fn default() -> Self {
+        AlarmFilter { deadband: 0.0, raise_samples: 1, clear_samples: 1 }
+    }
+}
+
+impl AlarmFilter {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.deadband >= 0.0 && self.deadband.is_finite()) {
+            return Err("deadband must be zero or more".to_string());
+        }
+        if !(1..=100).contains(&self.raise_samples) || !(1..=100).contains(&self.clear_samples) {
+            return Err("raise_samples and clear_samples must be between 1 and 100".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A channel's alarm level after filtering, as the status colors and the alarm list show
+// it. The sensor thread updates it once per sample, so the debounce counts samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlarmState {
+    level: AlarmLevel,
+    // The level readings have been heading to, and for how many samples
+    pending: AlarmLevel,
+    count: u32,
+    // When the sample that settled the current level was taken; None for the level it started at
+    changed_at: Option<SystemTime>,
+}
+
+impl AlarmState {
+    // Already at the level, without waiting out the debounce
+    pub fn settled(level: AlarmLevel) -> Self {
+        AlarmState { level, pending: level, count: 0, changed_at: None }
+    }
+
+    pub fn level(&self) -> AlarmLevel {
+        self.level
+    }
+
+    pub fn changed_at(&self) -> Option<SystemTime> {
+        self.changed_at
+    }
+
+    pub fn update(&mut self, limits: &Limits, filter: &AlarmFilter, value: f32, now: SystemTime) -> AlarmLevel {
+        let target = limits.classify_from(value, self.level, filter.deadband);
+        if target == self.level {
+            self.count = 0;
+            return self.level;
+        }
+        if target == self.pending {
+            self.count += 1;
+        } else {
+            self.pending = target;
+            self.count = 1;
+        }
+        let needed = if target > self.level { filter.raise_samples } else { filter.clear_samples };
+        if self.count >= needed {
+            *self = AlarmState { changed_at: Some(now), ..AlarmState::settled(target) };
+        }
+        self.level
+    }
+}
+
+// A reading moving faster than its channel should, whether or not it is still inside
+// its limits. Configured per channel type under [alarms.temperature] and
+// [alarms.pressure], with a limit per channel if needed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    // °C or kPa per second, rising or falling; None for no rate-of-change alarm
+    pub limit: Option<f32>,
+    // The rate is the change across this much of the latest readings
+    pub window: Duration,
+    // Warning or Alarm
+    pub severity: AlarmLevel,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit { limit: None, window: Duration::from_secs(10), severity: AlarmLevel::Alarm }
+    }
+}
+
+// Readings must cover this share of the window before a rate is worked out, so a
+// channel that has only just started reporting, or came back after a gap, can't alarm
+// on a couple of samples
+const RATE_MIN_COVER: f32 = 0.5;
+const RATE_MIN_SAMPLES: usize = 3;
+
+// A channel's rate of change over its window and the level that puts it at, debounced
+// like the limit alarms. The sensor thread updates it once per sample.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateState {
+    // Readings within the window, oldest first
+    samples: VecDeque<(SystemTime, f32)>,
+    // Per second; None until the readings cover enough of the window
+    rate: Option<f32>,
+    level: AlarmLevel,
+    pending: AlarmLevel,
+    count: u32,
+    changed_at: Option<SystemTime>,
+}
+
+impl RateState {
+    pub fn rate(&self) -> Option<f32> {
+        self.rate
+    }
+
+    pub fn level(&self) -> AlarmLevel {
+        self.level
+    }
+
+    pub fn changed_at(&self) -> Option<SystemTime> {
+        self.changed_at
+    }
+
+    // `reading` is None for one that can't be trusted - an open circuit, a forced value or
+    // a faulted instrument - which starts the window again rather than making a step of
+    // it. Without a rate the level stays as it was.
+    pub fn update(&mut self, limit: &RateLimit, filter: &AlarmFilter, reading: Option<f32>, now: SystemTime) -> AlarmLevel {
+        let value = match reading {
+            Some(value) => value,
+            None => {
+                self.samples.clear();
+                self.rate = None;
+                return self.level;
+            }
+        };
+        let since = now.checked_sub(limit.window).unwrap_or(now);
+        // A clock set back leaves readings from the future; they go too
+        self.samples.retain(|(time, _)| *time >= since && *time <= now);
+        self.samples.push_back((now, value));
+        self.rate = match (self.samples.front(), self.samples.len()) {
+            (Some((first, oldest)), count) if count >= RATE_MIN_SAMPLES => {
+                let span = now.duration_since(*first).unwrap_or_default().as_secs_f32();
+                (span >= limit.window.as_secs_f32() * RATE_MIN_COVER).then(|| (value - oldest) / span)
+            }
+            _ => None,
+        };
+        let (rate, threshold) = match (self.rate, limit.limit) {
+            (Some(rate), Some(threshold)) => (rate, threshold),
+            (_, None) => (0.0, f32::INFINITY),
+            (None, Some(_)) => return self.level,
+        };
+        let target = if rate.abs() >= threshold { limit.severity } else { AlarmLevel::Normal };
+        if target == self.level {
+            self.count = 0;
+            return self.level;
+        }
+        if target == self.pending {
+            self.count += 1;
+        } else {
+            self.pending = target;
+            self.count = 1;
+        }
+        let needed = if target > self.level { filter.raise_samples } else { filter.clear_samples };
+        if self.count >= needed {
+            self.level = target;
+            self.count = 0;
+            self.changed_at = Some(now);
+        }
+        self.level
+    }
+}
+
+// The channel that made the system shut itself down
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoTrip {
+    pub channel: String,
+    pub value: f32,
+    pub limit: f32,
+    pub kind: ChannelKind,
+}
+
+impl AutoTrip {
+    pub fn describe(&self, units: Units) -> String {
+        let direction = if self.value >= self.limit { "over" } else { "under" };
+        format!("{} {} {}", self.channel, direction, units.format(self.kind, self.limit))
+    }
+
+    // describe, in the console's language
+    pub fn shown(&self, units: Units) -> String {
+        let key = if self.value >= self.limit { "trip.over" } else { "trip.under" };
+        tr_args(key, &[&self.channel, &units.format(self.kind, self.limit)])
+    }
+}
+
+// In SI units, as saved in snapshots
+impl fmt::Display for AutoTrip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(Units::default()))
+    }
+}
+
+// Trips only when a channel stays beyond its trip limit for several samples in a
+// row, so a single noisy reading doesn't shut the plant down.
+pub struct TripMonitor {
+    samples: u32,
+    counts: Vec<u32>,
+}
+
+impl TripMonitor {
+    pub fn new(samples: u32) -> Self {
+        TripMonitor { samples, counts: Vec::new() }
+    }
+
+    // Called once per sample; returns the first channel to reach the count
+    pub fn check(&mut self, readings: &[Channel]) -> Option<AutoTrip> {
+        self.counts.resize(readings.len(), 0);
+        let mut tripped = None;
+        for (reading, count) in readings.iter().zip(&mut self.counts) {
+            match reading.limits.trip_limit(reading.value) {
+                Some(limit) => {
+                    *count += 1;
+                    if *count >= self.samples && tripped.is_none() {
+                        tripped = Some(AutoTrip { channel: reading.name.clone(), value: reading.value, limit, kind: reading.kind });
+                    }
+                }
+                None => *count = 0,
+            }
+        }
+        if tripped.is_some() {
+            self.counts.fill(0);
+        }
+        tripped
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Alarm {
+    pub id: u32,
+    pub source: String,
+    pub message: String,
+    pub raised_at: SystemTime,
+    pub cleared_at: Option<SystemTime>,
+    pub acknowledged: bool,
+    // The alarm that came in first before a trip or emergency shutdown
+    pub first_out: bool,
+}
+
+impl Alarm {
+    pub fn is_active(&self) -> bool {
+        self.cleared_at.is_none()
+    }
+
+    pub fn state(&self) -> &'static str {
+        match (self.is_active(), self.acknowledged) {
+            (true, false) => tr("alarm.active_unacked"),
+            (true, true) => tr("alarm.active_acked"),
+            (false, false) => tr("alarm.cleared_unacked"),
+            (false, true) => tr("alarm.cleared_acked"),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("id".to_string(), (self.id as u64).into()),
+            ("source".to_string(), self.source.as_str().into()),
+            ("message".to_string(), self.message.as_str().into()),
+            ("active".to_string(), self.is_active().into()),
+            ("acknowledged".to_string(), self.acknowledged.into()),
+            ("raised_at".to_string(), format_datetime(self.raised_at).into()),
+            ("cleared_at".to_string(), self.cleared_at.map_or(Value::Null, |time| format_datetime(time).into())),
+            ("first_out".to_string(), self.first_out.into()),
+        ])
+    }
+}
+
+// Alarm list following ISA-18.2: an alarm stays listed until it has both
+// cleared and been acknowledged, in either order.
+#[derive(Default)]
+pub struct AlarmList {
+    alarms: Vec<Alarm>,
+    next_id: u32,
+}
+
+impl AlarmList {
+    pub fn raise(&mut self, source: &str, message: String) -> u32 {
+        self.raise_at(source, message, SystemTime::now())
+    }
+
+    // For an alarm detected earlier than it is raised, e.g. on the sensor thread's sample
+    pub fn raise_at(&mut self, source: &str, message: String, raised_at: SystemTime) -> u32 {
+        self.next_id += 1;
+        self.alarms.push(Alarm {
+            id: self.next_id,
+            source: source.to_string(),
+            message,
+            raised_at,
+            cleared_at: None,
+            acknowledged: false,
+            first_out: false,
+        });
+        self.next_id
+    }
+
+    // The earliest alarm raised since `since` from a source starting with `prefix` (a
+    // device's label). Alarms raised at the same instant go by id, which follows the
+    // order they were raised in, so the choice is the same every time.
+    pub fn first_out(&self, prefix: &str, since: SystemTime) -> Option<&Alarm> {
+        self.alarms.iter()
+            .filter(|alarm| alarm.source.starts_with(prefix) && alarm.raised_at >= since)
+            .min_by_key(|alarm| (alarm.raised_at, alarm.id))
+    }
+
+    pub fn mark_first_out(&mut self, id: u32) {
+        if let Some(alarm) = self.alarms.iter_mut().find(|alarm| alarm.id == id) {
+            alarm.first_out = true;
+        }
+    }
+
+    pub fn clear(&mut self, source: &str) {
+        for alarm in self.alarms.iter_mut().filter(|alarm| alarm.source == source && alarm.is_active()) {
+            alarm.cleared_at = Some(SystemTime::now());
+        }
+        self.retain_relevant();
+    }
+
+    pub fn acknowledge(&mut self, id: u32) -> bool {
+        let found = match self.alarms.iter_mut().find(|alarm| alarm.id == id) {
+            Some(alarm) => {
+                alarm.acknowledged = true;
+                true
+            }
+            None => false,
+        };
+        self.retain_relevant();
+        found
+    }
+
+    pub fn acknowledge_all(&mut self) -> usize {
+        let mut count = 0;
+        for alarm in self.alarms.iter_mut().filter(|alarm| !alarm.acknowledged) {
+            alarm.acknowledged = true;
+            count += 1;
+        }
+        self.retain_relevant();
+        count
+    }
+
+    pub fn unacknowledged(&self) -> usize {
+        self.alarms.iter().filter(|alarm| !alarm.acknowledged).count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Alarm> {
+        self.alarms.iter()
+    }
+
+    fn retain_relevant(&mut self) {
+        self.alarms.retain(|alarm| alarm.is_active() || !alarm.acknowledged);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    const LIMITS: Limits = Limits { low_alarm: 10.0, low_warning: 15.0, high_warning: 26.0, high_alarm: 30.0, low_trip: None, high_trip: None };
+
+    fn at(second: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(second)
+    }
+
+    // The filtered level after each value, one sample a second
+    fn levels(filter: &AlarmFilter, values: impl IntoIterator<Item = f32>) -> Vec<AlarmLevel> {
+        let mut state = AlarmState::settled(AlarmLevel::Normal);
+        values.into_iter().enumerate().map(|(second, value)| state.update(&LIMITS, filter, value, at(second as u64))).collect()
+    }
+
+    fn changes(levels: &[AlarmLevel]) -> usize {
+        levels.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    // ±0.1 around the high warning limit, crossing it on every sample
+    fn jitter(samples: usize) -> impl Iterator<Item = f32> {
+        (0..samples).map(|i| if i % 2 == 0 { 25.9 } else { 26.1 })
+    }
+
+    #[test]
+    fn unfiltered_readings_chatter_across_a_limit() {
+        assert_eq!(changes(&levels(&AlarmFilter::default(), jitter(100))), 99);
+    }
+
+    #[test]
+    fn a_deadband_holds_the_level_until_the_reading_is_well_inside() {
+        let filter = AlarmFilter { deadband: 0.5, ..AlarmFilter::default() };
+        let seen = levels(&filter, jitter(100));
+        assert_eq!(seen[0], AlarmLevel::Normal);
+        assert!(seen[1..].iter().all(|level| *level == AlarmLevel::Warning));
+
+        let seen = levels(&filter, [26.1, 25.6, 25.4, 28.0, 31.0, 29.6, 29.4]);
+        use AlarmLevel::*;
+        assert_eq!(seen, [Warning, Warning, Normal, Warning, Alarm, Alarm, Warning]);
+    }
+
+    #[test]
+    fn debounce_needs_samples_in_a_row() {
+        let filter = AlarmFilter { deadband: 0.0, raise_samples: 3, clear_samples: 4 };
+        assert!(levels(&filter, jitter(100)).iter().all(|level| *level == AlarmLevel::Normal), "never three in a row");
+
+        // Two samples out, one back in, then three out
+        let seen = levels(&filter, [26.5, 26.5, 25.0, 26.5, 26.5, 26.5]);
+        assert_eq!(seen[..5], [AlarmLevel::Normal; 5]);
+        assert_eq!(seen[5], AlarmLevel::Warning);
+
+        let mut state = AlarmState::settled(AlarmLevel::Warning);
+        for (second, value) in [25.0, 25.0, 25.0, 26.5, 25.0, 25.0, 25.0].into_iter().enumerate() {
+            assert_eq!(state.update(&LIMITS, &filter, value, at(second as u64)), AlarmLevel::Warning);
+        }
+        assert_eq!(state.changed_at(), None);
+        assert_eq!(state.update(&LIMITS, &filter, 25.0, at(7)), AlarmLevel::Normal);
+        assert_eq!(state.changed_at(), Some(at(7)), "when it settled");
+    }
+
+    #[test]
+    fn deadband_and_debounce_together_settle_a_noisy_crossing() {
+        // A slow ramp up through 26°C and back, with ±0.1 on every sample
+        let filter = AlarmFilter { deadband: 0.5, raise_samples: 3, clear_samples: 3 };
+        let ramp = (0..200).map(|i| {
+            let base = 25.0 + 2.0 * (i as f32 / 200.0 * std::f32::consts::PI).sin();
+            base + if i % 2 == 0 { 0.1 } else { -0.1 }
+        });
+        let seen = levels(&filter, ramp);
+        assert_eq!(changes(&seen), 2, "raised once and cleared once");
+        assert_eq!(seen[199], AlarmLevel::Normal);
+    }
+
+    #[test]
+    fn an_open_circuit_is_an_alarm() {
+        assert_eq!(LIMITS.classify(f32::NAN), AlarmLevel::Alarm);
+        let filter = AlarmFilter { deadband: 0.5, raise_samples: 2, clear_samples: 2 };
+        assert_eq!(levels(&filter, [f32::NAN, f32::NAN, 20.0, 20.0]), [AlarmLevel::Normal, AlarmLevel::Alarm, AlarmLevel::Alarm, AlarmLevel::Normal]);
+    }
+
+    #[test]
+    fn rates_alarm_once_the_window_is_covered() {
+        let limit = RateLimit { limit: Some(1.0), window: Duration::from_secs(10), severity: AlarmLevel::Warning };
+        let mut state = RateState::default();
+        // Climbing 2°C a second, but too little of the window for a rate at first
+        for second in 0..5 {
+            assert_eq!(state.update(&limit, &AlarmFilter::default(), Some(20.0 + 2.0 * second as f32), at(second)), AlarmLevel::Normal);
+        }
+        assert_eq!(state.rate(), None);
+        assert_eq!(state.update(&limit, &AlarmFilter::default(), Some(30.0), at(5)), AlarmLevel::Warning);
+        assert_eq!(state.rate(), Some(2.0));
+        // A reading that can't be trusted starts the window again
+        state.update(&limit, &AlarmFilter::default(), None, at(6));
+        assert_eq!(state.rate(), None);
+        assert_eq!(state.level(), AlarmLevel::Warning);
+    }
+
+    // Three channels crossing their alarm limits microseconds apart, detected in the
+    // order given and raised at the samples that crossed
+    fn cascade(order: [usize; 3]) -> AlarmList {
+        let start = at(1_000);
+        let crossed = [(35.0, 3), (32.0, 1), (31.0, 2)];
+        let mut alarms = AlarmList::default();
+        // Earlier, but outside the window and on another device
+        alarms.raise_at("Line 2: Temperature Sensor 1", "old alarm".to_string(), at(900));
+        alarms.raise("Line 1: Temperature Sensor 9", "another device".to_string());
+        for channel in order {
+            let (value, micros) = crossed[channel];
+            let mut state = AlarmState::settled(AlarmLevel::Normal);
+            assert_eq!(state.update(&LIMITS, &AlarmFilter::default(), value, start + Duration::from_micros(micros)), AlarmLevel::Alarm);
+            let source = format!("Line 2: Temperature Sensor {}", channel + 1);
+            alarms.raise_at(&source, format!("{} over limit", source), state.changed_at().unwrap());
+        }
+        alarms
+    }
+
+    #[test]
+    fn the_earliest_alarm_is_first_out_whatever_order_they_are_raised_in() {
+        let since = at(1_000) - FIRST_OUT_WINDOW;
+        for order in [[0, 1, 2], [2, 1, 0], [1, 2, 0], [0, 2, 1]] {
+            let mut alarms = cascade(order);
+            let first = alarms.first_out("Line 2: ", since).unwrap().clone();
+            assert_eq!(first.source, "Line 2: Temperature Sensor 2", "raised in order {:?}", order);
+            assert_eq!(first.raised_at, at(1_000) + Duration::from_micros(1));
+            alarms.mark_first_out(first.id);
+            assert_eq!(alarms.iter().filter(|alarm| alarm.first_out).map(|alarm| alarm.id).collect::<Vec<_>>(), [first.id]);
+        }
+    }
+
+    #[test]
+    fn simultaneous_alarms_go_by_the_order_they_were_raised_in() {
+        let mut alarms = AlarmList::default();
+        let second = alarms.raise_at("Line 2: Pressure Gauge 1", "low".to_string(), at(1_000));
+        alarms.raise_at("Line 2: Pressure Gauge 2", "low".to_string(), at(1_000));
+        assert_eq!(alarms.first_out("Line 2: ", at(0)).map(|alarm| alarm.id), Some(second));
+        assert!(alarms.first_out("Line 3: ", at(0)).is_none());
+        assert!(alarms.first_out("Line 2: ", at(1_001)).is_none(), "too long before the shutdown");
+    }
+}
@@ -0,0 +1,82 @@
+// Weather around the simulated plant: the air temperature, which follows a day/night
+// cycle around the process model's ambient, and the barometric pressure, which wanders
+// over a few days. The process model settles every temperature from the air temperature
+// and adds the barometer's departure from its average to every pressure gauge, and the
+// control loop's sensor warms and cools with the air, so the loop has a disturbance to
+// reject. Either can be held at a value from the console ('ambient'). Time is the
+// simulation's own, so a paused plant doesn't see the day go by.
+use crate::json::Value;
+
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+// Days the barometer takes to rise and fall again; not a whole number, so the weather
+// doesn't repeat with the days
+const WEATHER_DAYS: f32 = 3.3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weather {
+    // °C from the coolest time of day to the warmest; 0 keeps the air temperature steady
+    pub daily_swing: f32,
+    // Length of a simulated day; shorter to show a whole cycle in a demo
+    pub day: Duration,
+    // kPa the barometer averages; the pressure gauges read as configured at this pressure
+    pub barometer: f32,
+    // kPa from the lowest barometer reading to the highest; 0 keeps it steady
+    pub barometer_swing: f32,
+}
+
+impl Default for Weather {
+    // Steady, so the readings are what they were before the weather was modelled
+    fn default() -> Self {
+        Weather { daily_swing: 0.0, day: Duration::from_secs(86_400), barometer: 101.3, barometer_swing: 0.0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ambient {
+    // °C
+    pub temperature: f32,
+    // kPa
+    pub pressure: f32,
+    // Set by hand, and no longer following the weather
+    pub temperature_held: bool,
+    pub pressure_held: bool,
+    // Since the plant started, which was at the coolest time of day
+    elapsed: Duration,
+}
+
+impl Ambient {
+    // `average` is the process model's ambient temperature
+    pub fn new(average: f32, weather: &Weather) -> Ambient {
+        let mut ambient = Ambient { temperature: average, pressure: weather.barometer, temperature_held: false, pressure_held: false, elapsed: Duration::ZERO };
+        ambient.advance(average, weather, Duration::ZERO);
+        ambient
+    }
+
+    pub fn advance(&mut self, average: f32, weather: &Weather, dt: Duration) {
+        self.elapsed += dt;
+        let days = self.elapsed.as_secs_f32() / weather.day.as_secs_f32();
+        if !self.temperature_held {
+            self.temperature = average - weather.daily_swing / 2.0 * (TAU * days).cos();
+        }
+        if !self.pressure_held {
+            self.pressure = weather.barometer + weather.barometer_swing / 2.0 * (TAU * days / WEATHER_DAYS).sin();
+        }
+    }
+
+    // Readings held by hand go back to following the weather at the next step
+    pub fn release(&mut self) {
+        self.temperature_held = false;
+        self.pressure_held = false;
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("temperature".to_string(), self.temperature.into()),
+            ("pressure".to_string(), self.pressure.into()),
+            ("temperature_held".to_string(), self.temperature_held.into()),
+            ("pressure_held".to_string(), self.pressure_held.into()),
+        ])
+    }
+}
@@ -0,0 +1,137 @@
+// Audible alarm annunciation: a horn that keeps sounding while there is a new
+// unacknowledged alarm until someone silences it, and a short chime for a reading
+// entering its warning band. Tones are played on their own thread so a slow or
+// missing sound device never holds up the console.
+use crate::platform;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const ALARM_BEEP: Duration = Duration::from_millis(400);
+const ALARM_PAUSE: Duration = Duration::from_millis(600);
+const WARNING_BEEP: Duration = Duration::from_millis(150);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnnunciatorConfig {
+    // Pitch of the alarm horn; the second beep of each pair is a fifth lower
+    pub alarm_tone_hz: u32,
+    pub warning_tone_hz: u32,
+}
+
+impl Default for AnnunciatorConfig {
+    fn default() -> Self {
+        AnnunciatorConfig { alarm_tone_hz: 1200, warning_tone_hz: 880 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tone {
+    // Two short beeps, once
+    Warning,
+    // Alternating beeps, repeated until silenced
+    Alarm,
+}
+
+enum HornCommand {
+    Sound(Tone),
+    Silence,
+}
+
+// Owns the sound thread. Dropping it silences the horn and waits for the thread.
+pub struct Annunciator {
+    commands: Option<Sender<HornCommand>>,
+    thread: Option<JoinHandle<()>>,
+    sounding: bool,
+    // Cleared by the sound thread once the sound device has failed
+    available: Arc<AtomicBool>,
+}
+
+impl Annunciator {
+    // A sound device that can't be used is reported once through `reports`, after
+    // which alarms are only shown
+    pub fn start(config: AnnunciatorConfig, reports: Sender<Result<String, String>>) -> Annunciator {
+        let (commands, queued) = mpsc::channel();
+        let available = Arc::new(AtomicBool::new(true));
+        let thread_available = Arc::clone(&available);
+        let thread = thread::spawn(move || run_horn(config, queued, thread_available, reports));
+        Annunciator { commands: Some(commands), thread: Some(thread), sounding: false, available }
+    }
+
+    // True while the alarm horn is sounding; never once the sound device has failed
+    pub fn is_sounding(&self) -> bool {
+        self.sounding && self.available.load(Ordering::Relaxed)
+    }
+
+    pub fn sound(&mut self, tone: Tone) {
+        if tone == Tone::Alarm {
+            self.sounding = true;
+        }
+        self.send(HornCommand::Sound(tone));
+    }
+
+    // Mutes the horn until the next alarm; nothing is acknowledged
+    pub fn silence(&mut self) {
+        self.sounding = false;
+        self.send(HornCommand::Silence);
+    }
+
+    fn send(&self, command: HornCommand) {
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.commands = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Annunciator {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_horn(config: AnnunciatorConfig, commands: Receiver<HornCommand>, available: Arc<AtomicBool>, reports: Sender<Result<String, String>>) {
+    let mut sounding = false;
+    let beep = |frequency: u32, duration: Duration| {
+        if available.load(Ordering::Relaxed) && !platform::beep(frequency, duration) {
+            available.store(false, Ordering::Relaxed);
+            let _ = reports.send(Err("Alarm horn unavailable: no sound device - alarms are shown only".to_string()));
+        }
+    };
+    loop {
+        // While sounding, the pause between beeps is the time left to hear a command
+        let command = if sounding {
+            match commands.recv_timeout(ALARM_PAUSE) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            }
+        };
+        match command {
+            Some(HornCommand::Sound(Tone::Alarm)) => sounding = true,
+            Some(HornCommand::Sound(Tone::Warning)) => {
+                beep(config.warning_tone_hz, WARNING_BEEP);
+                beep(config.warning_tone_hz, WARNING_BEEP);
+            }
+            Some(HornCommand::Silence) => sounding = false,
+            None => {}
+        }
+        if sounding {
+            beep(config.alarm_tone_hz, ALARM_BEEP);
+            beep(config.alarm_tone_hz * 2 / 3, ALARM_BEEP);
+        }
+    }
+}
@@ -0,0 +1,625 @@
+// Embedded HTTP API for remote monitoring dashboards. Readings are served straight
+// from the shared sensor data or pushed over a WebSocket; anything that needs the
+// console's state (alarms, emergency shutdown) is handed to the main loop so it goes
+// through the same code as the operator's commands. Writes name a revision with
+// If-Match: the console counts every change to setpoints, limits and motor states, so a
+// client working from stale readings is refused rather than undoing another's change.
+use crate::auth::Role;
+use crate::json::Value;
+use crate::prometheus;
+use crate::sensors::SensorData;
+use crate::websocket::{self, Broadcast};
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections served at once, WebSocket clients included; more are answered 503 and closed
+const MAX_CONNECTIONS: usize = 32;
+// The main loop answers within a tick; anything slower means it is stuck
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+// The role a write is made with, which must be one the client's token has
+pub const ROLE_HEADER: &str = "X-Forlenza-Role";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiConfig {
+    pub bind: String,
+    // Allows the emergency shutdown endpoint; without it (or a client) it is disabled
+    pub token: Option<String>,
+    // Allowed the write endpoints, up to their role; with none they are disabled
+    pub clients: Vec<ApiClient>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiClient {
+    pub name: String,
+    pub role: Role,
+    pub token: String,
+}
+
+// A change a client asks for, with the motor or channel as given in the path
+#[derive(Clone, Debug, PartialEq)]
+pub enum WriteCommand {
+    Setpoint { motor: String, rpm: u16 },
+    MotorRunning { motor: String, running: bool },
+    Limit { channel: String, limit: String, value: f32 },
+}
+
+// How the console answered a write
+#[derive(Clone, Debug, PartialEq)]
+pub enum WriteOutcome {
+    // Made; the revision it produced
+    Done(u64),
+    // The client's revision is not the current one
+    Conflict(u64),
+    // The claimed role may not do it
+    Forbidden(String),
+    // Refused while the emergency shutdown is active
+    Locked(String),
+    NotFound(String),
+    Rejected(String),
+}
+
+// Requests the console's main loop answers
+pub enum ApiRequest {
+    Alarms { reply: Sender<Value> },
+    Metrics { reply: Sender<Value> },
+    // Prometheus text for /metrics
+    Scrape { reply: Sender<String> },
+    EmergencyShutdown { client: SocketAddr, reply: Sender<()> },
+    Write { client: String, role: Role, revision: u64, command: WriteCommand, reply: Sender<WriteOutcome> },
+}
+
+// Owns the listener and broadcast threads. Dropping it stops accepting connections
+// and closes WebSocket clients.
+pub struct ApiServer {
+    address: SocketAddr,
+    requests: Receiver<ApiRequest>,
+    context: Arc<Context>,
+    telemetry: Arc<Broadcast>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ApiServer {
+    // Returns the server and the sender the sensor thread feeds WebSocket clients through.
+    // `revision` is the console's, read for the ETag of every answer.
+    pub fn start(config: ApiConfig, sensor_data: Arc<Mutex<SensorData>>, system_id: String, revision: Arc<AtomicU64>) -> io::Result<(ApiServer, SyncSender<SensorData>)> {
+        let listener = TcpListener::bind(&config.bind)?;
+        let address = listener.local_addr()?;
+        // Polled so stop() doesn't have to wait for a client to connect
+        listener.set_nonblocking(true)?;
+        let (requests, console) = mpsc::channel();
+        let (updates, latest) = mpsc::sync_channel::<SensorData>(1);
+        let telemetry = Arc::new(Broadcast::default());
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let context = Arc::new(Context {
+            config,
+            sensor_data: Mutex::new(sensor_data),
+            system_id: system_id.clone(),
+            revision,
+            requests: Mutex::new(requests),
+            telemetry: Arc::clone(&telemetry),
+            stop: Arc::clone(&stop),
+            connections: AtomicUsize::new(0),
+        });
+        let listener_context = Arc::clone(&context);
+        let listener_thread = thread::spawn(move || {
+            while !listener_context.stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((mut stream, client)) => {
+                        if listener_context.connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                            listener_context.connections.fetch_sub(1, Ordering::Relaxed);
+                            let _ = stream.set_nonblocking(false).and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)));
+                            let _ = Response::error(503, "too many connections; try again shortly").write_to(&mut stream);
+                            continue;
+                        }
+                        let context = Arc::clone(&listener_context);
+                        // One thread per connection keeps a slow client from holding up the others
+                        thread::spawn(move || {
+                            let _ = context.serve(stream, client);
+                            context.connections.fetch_sub(1, Ordering::Relaxed);
+                        });
+                    }
+                    // WouldBlock when nobody is waiting to connect
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        });
+
+        // Serializes each update once for every WebSocket client
+        let server_context = Arc::clone(&context);
+        let broadcast_thread = thread::spawn(move || {
+            while !context.stop.load(Ordering::Relaxed) {
+                match latest.recv_timeout(Duration::from_millis(200)) {
+                    Ok(data) if context.telemetry.connected() > 0 => context.telemetry.send(&data.to_telemetry(&system_id).to_string()),
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        let server = ApiServer { address, requests: console, context: server_context, telemetry, stop, threads: vec![listener_thread, broadcast_thread] };
+        Ok((server, updates))
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    // Serves the readings from `sensor_data` from now on, after a restart has renewed its lock
+    pub fn follow(&self, sensor_data: Arc<Mutex<SensorData>>) {
+        if let Ok(mut shared) = self.context.sensor_data.lock() {
+            *shared = sensor_data;
+        }
+    }
+
+    pub fn websocket_clients(&self) -> usize {
+        self.telemetry.connected()
+    }
+
+    // Requests waiting for the console to answer
+    pub fn pending_requests(&self) -> Vec<ApiRequest> {
+        self.requests.try_iter().collect()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        // Give WebSocket clients a moment to receive their close frame before the process exits
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while self.telemetry.connected() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    // Header names are case-insensitive
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    fn read(reader: &mut impl BufRead) -> io::Result<Request> {
+        let mut lines = Vec::new();
+        let mut total = 0;
+        loop {
+            let mut line = String::new();
+            total += reader.by_ref().take((MAX_HEADER_BYTES - total) as u64 + 1).read_line(&mut line)?;
+            if total > MAX_HEADER_BYTES || line.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "request header too large or incomplete"));
+            }
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+
+        let request_line = lines.first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty request"))?;
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method.to_string(), target),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line")),
+        };
+        // The query string is ignored
+        let path = target.split('?').next().unwrap_or(target).to_string();
+        let headers = lines[1..]
+            .iter()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Ok(Request { method, path, headers, body: Vec::new() })
+    }
+
+    fn json_body(&self) -> Result<Value, Response> {
+        let text = std::str::from_utf8(&self.body).map_err(|_| Response::error(400, "body is not UTF-8"))?;
+        Value::parse(text).map_err(|e| Response::error(400, &format!("body is not valid JSON: {}", e)))
+    }
+}
+
+// A stream whose reads fail once `at` has passed
+struct Deadline {
+    stream: TcpStream,
+    at: Instant,
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.at.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request not received in time"));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+    // Sent as the ETag
+    revision: Option<u64>,
+}
+
+impl Response {
+    fn json(status: u16, body: &Value) -> Response {
+        Response { status, content_type: "application/json", body: body.to_string(), revision: None }
+    }
+
+    fn text(status: u16, content_type: &'static str, body: String) -> Response {
+        Response { status, content_type, body, revision: None }
+    }
+
+    fn with_revision(self, revision: u64) -> Response {
+        Response { revision: Some(revision), ..self }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &Value::Object(vec![("error".to_string(), message.into())]))
+    }
+
+    fn write_to(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            422 => "Unprocessable Entity",
+            423 => "Locked",
+            428 => "Precondition Required",
+            503 => "Service Unavailable",
+            _ => "Error",
+        };
+        let etag = self.revision.map_or(String::new(), |revision| format!("ETag: \"{}\"\r\n", revision));
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len(),
+            etag
+        );
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(self.body.as_bytes())?;
+        stream.flush()
+    }
+}
+
+struct Context {
+    config: ApiConfig,
+    // Replaced when a restart renews the readings' lock
+    sensor_data: Mutex<Arc<Mutex<SensorData>>>,
+    system_id: String,
+    revision: Arc<AtomicU64>,
+    requests: Mutex<Sender<ApiRequest>>,
+    telemetry: Arc<Broadcast>,
+    stop: Arc<AtomicBool>,
+    // Being served now
+    connections: AtomicUsize,
+}
+
+impl Context {
+    fn serve(&self, stream: TcpStream, client: SocketAddr) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        // The whole request has IO_TIMEOUT to arrive, so a client can't hold the thread by
+        // sending it a byte at a time
+        let mut reader = BufReader::new(Deadline { stream: stream.try_clone()?, at: Instant::now() + IO_TIMEOUT });
+        let mut stream = stream;
+
+        let request = Request::read(&mut reader).and_then(|mut request| {
+            // Read even where it isn't wanted, as an unread body would reset the connection on close
+            let length = request.header("Content-Length").and_then(|length| length.parse().ok()).unwrap_or(0u64);
+            reader.by_ref().take(length.min(MAX_BODY_BYTES)).read_to_end(&mut request.body)?;
+            Ok(request)
+        });
+        let response = match request {
+            Ok(request) => {
+                if request.path == "/ws/telemetry" {
+                    return self.upgrade(&request, stream);
+                }
+                self.route(&request, client)
+            }
+            // A read timeout is WouldBlock on Unix and TimedOut on Windows
+            Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => Response::error(408, "request not received in time"),
+            Err(e) => Response::error(400, &e.to_string()),
+        };
+        response.write_to(&mut stream)
+    }
+
+    // Completes the WebSocket handshake and streams telemetry until the client leaves
+    fn upgrade(&self, request: &Request, mut stream: TcpStream) -> io::Result<()> {
+        let is_upgrade = request.method == "GET"
+            && request.header("Upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+            && request.header("Sec-WebSocket-Version") == Some("13");
+        let key = match request.header("Sec-WebSocket-Key") {
+            Some(key) if is_upgrade => key,
+            _ => return Response::error(400, "expected a WebSocket upgrade request").write_to(&mut stream),
+        };
+        let head = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            websocket::accept_key(key)
+        );
+        stream.write_all(head.as_bytes())?;
+        websocket::serve(stream, &self.telemetry, &self.stop)
+    }
+
+    fn route(&self, request: &Request, client: SocketAddr) -> Response {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/api/v1/sensors") => {
+                // Copied out so the lock isn't held while the response is written
+                let sensor_data = match self.sensor_data.lock() {
+                    Ok(sensor_data) => Arc::clone(&sensor_data),
+                    Err(_) => return Response::error(503, "sensor data is unavailable"),
+                };
+                let data = match sensor_data.lock() {
+                    Ok(data) => data.clone(),
+                    Err(_) => return Response::error(503, "sensor data is unavailable"),
+                };
+                Response::json(200, &data.to_telemetry(&self.system_id)).with_revision(self.revision.load(Ordering::Relaxed))
+            }
+            ("GET", "/api/v1/alarms") => {
+                let (reply, answer) = mpsc::channel();
+                self.ask(ApiRequest::Alarms { reply }, answer).map_or_else(|e| e, |alarms| Response::json(200, &alarms))
+            }
+            ("GET", "/api/v1/metrics") => {
+                let (reply, answer) = mpsc::channel();
+                self.ask(ApiRequest::Metrics { reply }, answer).map_or_else(|e| e, |metrics| Response::json(200, &metrics))
+            }
+            ("GET", "/metrics") => {
+                let (reply, answer) = mpsc::channel();
+                self.ask(ApiRequest::Scrape { reply }, answer).map_or_else(|e| e, |text| Response::text(200, prometheus::CONTENT_TYPE, text))
+            }
+            ("POST", "/api/v1/emergency-shutdown") => {
+                if let Err(response) = self.authorize(request) {
+                    return response;
+                }
+                let (reply, answer) = mpsc::channel();
+                self.ask(ApiRequest::EmergencyShutdown { client, reply }, answer)
+                    .map_or_else(|e| e, |()| Response::json(200, &Value::Object(vec![("emergency_shutdown".to_string(), true.into())])))
+            }
+            (_, "/api/v1/sensors" | "/api/v1/alarms" | "/api/v1/metrics" | "/metrics" | "/api/v1/emergency-shutdown") => Response::error(405, "method not allowed"),
+            (method, path) => {
+                let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+                match segments.as_slice() {
+                    ["api", "v1", "motors", id, "setpoint"] if method == "PUT" => match decode_segment(id) {
+                        Some(motor) => self.write(request, |body| match body.get("rpm").and_then(Value::as_f64) {
+                            Some(rpm) if (0.0..=u16::MAX as f64).contains(&rpm) && rpm.fract() == 0.0 => Ok(WriteCommand::Setpoint { motor, rpm: rpm as u16 }),
+                            _ => Err("body must be {\"rpm\": N}".to_string()),
+                        }),
+                        None => Response::error(400, "malformed motor ID"),
+                    },
+                    ["api", "v1", "motors", id, action @ ("start" | "stop")] if method == "POST" => match decode_segment(id) {
+                        Some(motor) => self.write(request, |_| Ok(WriteCommand::MotorRunning { motor, running: *action == "start" })),
+                        None => Response::error(400, "malformed motor ID"),
+                    },
+                    ["api", "v1", "limits", channel] if method == "PUT" => match decode_segment(channel) {
+                        Some(channel) => self.write(request, |body| match (body.str_field("limit"), body.get("value").and_then(Value::as_f64)) {
+                            (Ok(limit), Some(value)) => Ok(WriteCommand::Limit { channel, limit: limit.to_string(), value: value as f32 }),
+                            _ => Err("body must be {\"limit\": NAME, \"value\": N}".to_string()),
+                        }),
+                        None => Response::error(400, "malformed channel"),
+                    },
+                    ["api", "v1", "motors", _, "setpoint" | "start" | "stop"] | ["api", "v1", "limits", _] => Response::error(405, "method not allowed"),
+                    _ => Response::error(404, "not found"),
+                }
+            }
+        }
+    }
+
+    // The emergency shutdown is allowed with the api.token or any client's
+    fn authorize(&self, request: &Request) -> Result<(), Response> {
+        if self.config.token.is_none() && self.config.clients.is_empty() {
+            return Err(Response::error(403, "control endpoints are disabled (no api.token configured)"));
+        }
+        let presented = bearer(request);
+        let matches = self.config.token.iter().chain(self.config.clients.iter().map(|client| &client.token))
+            .fold(false, |found, token| same_token(presented, token) | found);
+        if matches {
+            Ok(())
+        } else {
+            Err(Response::error(401, "missing or invalid bearer token"))
+        }
+    }
+
+    // The client a write comes from and the role it claims
+    fn authorize_client(&self, request: &Request) -> Result<(String, Role), Response> {
+        if self.config.clients.is_empty() {
+            return Err(Response::error(403, "write endpoints are disabled (no [api.clients] configured)"));
+        }
+        let presented = bearer(request);
+        // Every token is compared so the response time doesn't reveal which one matched
+        let client = self.config.clients.iter().fold(None, |found, client| if same_token(presented, &client.token) { Some(client) } else { found });
+        let client = match client {
+            Some(client) => client,
+            None => return Err(Response::error(401, "missing or invalid bearer token")),
+        };
+        let claimed = match request.header(ROLE_HEADER) {
+            Some(claim) => Role::parse(&claim.to_ascii_lowercase()).map_err(|e| Response::error(400, &format!("{}: {}", ROLE_HEADER, e)))?,
+            None => return Err(Response::error(401, &format!("missing {} header (operator or supervisor)", ROLE_HEADER))),
+        };
+        if claimed > client.role {
+            return Err(Response::error(403, &format!("client '{}' can act as {} at most", client.name, client.role)));
+        }
+        Ok((client.name.clone(), claimed))
+    }
+
+    // Checks the client and its revision, reads the command from the body and has the
+    // console carry it out
+    fn write(&self, request: &Request, command: impl FnOnce(&Value) -> Result<WriteCommand, String>) -> Response {
+        let (client, role) = match self.authorize_client(request) {
+            Ok(client) => client,
+            Err(response) => return response,
+        };
+        let revision = match request.header("If-Match").map(|tag| tag.trim_start_matches("W/").trim_matches('"').parse::<u64>()) {
+            Some(Ok(revision)) => revision,
+            Some(Err(_)) => return Response::error(400, "If-Match must be a revision number, as given in the ETag"),
+            None => return Response::error(428, "If-Match with the current revision is required (GET /api/v1/sensors gives it as the ETag)"),
+        };
+        // Start and stop take no body
+        let body = if request.body.is_empty() { Ok(Value::Object(Vec::new())) } else { request.json_body() };
+        let body = match body {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+        let command = match command(&body) {
+            Ok(command) => command,
+            Err(reason) => return Response::error(400, &reason),
+        };
+        let (reply, answer) = mpsc::channel();
+        let outcome = match self.ask(ApiRequest::Write { client, role, revision, command, reply }, answer) {
+            Ok(outcome) => outcome,
+            Err(response) => return response,
+        };
+        let current = self.revision.load(Ordering::Relaxed);
+        match outcome {
+            WriteOutcome::Done(revision) => Response::json(200, &Value::Object(vec![("revision".to_string(), revision.into())])).with_revision(revision),
+            WriteOutcome::Conflict(revision) => Response::error(409, &format!("revision {} is not the current one; read again and retry", revision)).with_revision(current),
+            WriteOutcome::Forbidden(reason) => Response::error(403, &reason),
+            WriteOutcome::Locked(reason) => Response::error(423, &reason).with_revision(current),
+            WriteOutcome::NotFound(reason) => Response::error(404, &reason),
+            WriteOutcome::Rejected(reason) => Response::error(422, &reason).with_revision(current),
+        }
+    }
+
+    fn ask<T>(&self, request: ApiRequest, answer: Receiver<T>) -> Result<T, Response> {
+        let sent = self.requests.lock().map(|requests| requests.send(request).is_ok()).unwrap_or(false);
+        if !sent {
+            return Err(Response::error(503, "control system is shutting down"));
+        }
+        answer.recv_timeout(REPLY_TIMEOUT).map_err(|_| Response::error(503, "control system did not respond"))
+    }
+}
+
+fn bearer(request: &Request) -> &str {
+    request.header("Authorization").and_then(|value| value.strip_prefix("Bearer ")).unwrap_or_default()
+}
+
+// Compared in full every time so the response time doesn't reveal a matching prefix
+fn same_token(presented: &str, token: &str) -> bool {
+    presented.len() == token.len() && presented.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// A path segment with its %XX escapes undone, e.g. a channel name with spaces
+fn decode_segment(segment: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = segment.as_bytes();
+    while let Some((first, tail)) = rest.split_first() {
+        if *first == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(*first);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().filter(|decoded| !decoded.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(body: &str) -> Request {
+        Request { method: "POST".to_string(), path: "/api/v1/motors/1/setpoint".to_string(), headers: Vec::new(), body: body.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn deeply_nested_body_is_a_bad_request() {
+        // Well inside the 64 KiB body limit, and deep enough to overflow the stack unchecked
+        let response = match post(&"[".repeat(30_000)).json_body() {
+            Ok(_) => panic!("a nested body was accepted"),
+            Err(response) => response,
+        };
+        assert_eq!(response.status, 400);
+        assert!(response.body.contains("nesting too deep"), "{}", response.body);
+
+        assert_eq!(post(r#"{"rpm": 1500}"#).json_body().ok().and_then(|body| body.f64_field("rpm").ok()), Some(1500.0));
+    }
+
+    fn status(stream: &mut TcpStream) -> String {
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn connections_are_capped_and_slow_requests_time_out() {
+        let config = ApiConfig { bind: "127.0.0.1:0".to_string(), token: None, clients: Vec::new() };
+        let sensor_data = Arc::new(Mutex::new(SensorData::from_config(&crate::config::Config::default())));
+        let (server, _updates) = ApiServer::start(config, sensor_data, "test".to_string(), Arc::new(AtomicU64::new(0))).unwrap();
+
+        // One client sends its request a byte at a time, never quite stalling; the rest sit idle
+        let mut slow = TcpStream::connect(server.address()).unwrap();
+        let _idle: Vec<TcpStream> = (1..MAX_CONNECTIONS).map(|_| TcpStream::connect(server.address()).unwrap()).collect();
+        let mut refused = TcpStream::connect(server.address()).unwrap();
+        refused.set_read_timeout(Some(IO_TIMEOUT)).unwrap();
+        assert_eq!(status(&mut refused), "HTTP/1.1 503 Service Unavailable");
+
+        let started = Instant::now();
+        slow.set_nonblocking(true).unwrap();
+        let mut response = Vec::new();
+        for byte in b"GET /api/v1/sensors HTTP/1.1\r\n".iter().cycle() {
+            let _ = slow.write_all(&[*byte]);
+            let mut buf = [0u8; 256];
+            match slow.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buf[..n]),
+                Err(_) if started.elapsed() < IO_TIMEOUT * 2 => thread::sleep(Duration::from_millis(200)),
+                Err(e) => panic!("still open after {:?}: {}", started.elapsed(), e),
+            }
+        }
+        assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 408 Request Timeout"));
+        // The idle ones have timed out too, which makes room again
+        thread::sleep(Duration::from_millis(300));
+        let mut next = TcpStream::connect(server.address()).unwrap();
+        next.write_all(b"GET /api/v1/sensors HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(status(&mut next), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn a_stalled_body_times_out() {
+        let config = ApiConfig { bind: "127.0.0.1:0".to_string(), token: None, clients: Vec::new() };
+        let sensor_data = Arc::new(Mutex::new(SensorData::from_config(&crate::config::Config::default())));
+        let (server, _updates) = ApiServer::start(config, sensor_data, "test".to_string(), Arc::new(AtomicU64::new(0))).unwrap();
+
+        // Promises 100 bytes of body and sends 12
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream.write_all(b"PUT /api/v1/motors/1/setpoint HTTP/1.1\r\nContent-Length: 100\r\n\r\n{\"rpm\": 1500").unwrap();
+        stream.set_read_timeout(Some(IO_TIMEOUT * 2)).unwrap();
+        let started = Instant::now();
+        assert_eq!(status(&mut stream), "HTTP/1.1 408 Request Timeout");
+        assert!(started.elapsed() <= IO_TIMEOUT + Duration::from_secs(1), "answered after {:?}", started.elapsed());
+    }
+}
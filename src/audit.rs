@@ -0,0 +1,264 @@
+// Operator audit trail: every control action, who took it and when, appended to a
+// JSON-lines file that is never rewritten. Writes happen on a background thread; the
+// session's events are also kept in memory for the console to show.
+use crate::clock::{format_datetime, parse_datetime};
+use crate::json::Value;
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Diagnostic,
+    EmergencyShutdown,
+    Reset,
+    Restart,
+    MotorStart,
+    MotorStop,
+    Setpoint,
+    AlarmAck,
+    LimitChange,
+    Login,
+    Logout,
+    Maintenance,
+    Output,
+    LoopChange,
+    Recipe,
+    Notification,
+    StatisticsReset,
+    Calibration,
+    ChannelSetup,
+    ShiftClose,
+    SourceSwap,
+    Force,
+    ModeChange,
+    Bypass,
+    Configuration,
+    EnergyReset,
+    Undo,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 27] = [
+        AuditAction::Diagnostic,
+        AuditAction::EmergencyShutdown,
+        AuditAction::Reset,
+        AuditAction::Restart,
+        AuditAction::MotorStart,
+        AuditAction::MotorStop,
+        AuditAction::Setpoint,
+        AuditAction::AlarmAck,
+        AuditAction::LimitChange,
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::Maintenance,
+        AuditAction::Output,
+        AuditAction::LoopChange,
+        AuditAction::Recipe,
+        AuditAction::Notification,
+        AuditAction::StatisticsReset,
+        AuditAction::Calibration,
+        AuditAction::ChannelSetup,
+        AuditAction::ShiftClose,
+        AuditAction::SourceSwap,
+        AuditAction::Force,
+        AuditAction::ModeChange,
+        AuditAction::Bypass,
+        AuditAction::Configuration,
+        AuditAction::EnergyReset,
+        AuditAction::Undo,
+    ];
+
+    // As written to the file and typed to filter
+    pub fn name(self) -> &'static str {
+        match self {
+            AuditAction::Diagnostic => "diagnostic",
+            AuditAction::EmergencyShutdown => "estop",
+            AuditAction::Reset => "reset",
+            AuditAction::Restart => "restart",
+            AuditAction::MotorStart => "motor-start",
+            AuditAction::MotorStop => "motor-stop",
+            AuditAction::Setpoint => "setpoint",
+            AuditAction::AlarmAck => "ack",
+            AuditAction::LimitChange => "limit",
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::Maintenance => "maintenance",
+            AuditAction::Output => "output",
+            AuditAction::LoopChange => "loop",
+            AuditAction::Recipe => "recipe",
+            AuditAction::Notification => "notify",
+            AuditAction::StatisticsReset => "stats-reset",
+            AuditAction::Calibration => "calibrate",
+            AuditAction::ChannelSetup => "channel",
+            AuditAction::ShiftClose => "shift",
+            AuditAction::SourceSwap => "source",
+            AuditAction::Force => "force",
+            AuditAction::ModeChange => "mode",
+            AuditAction::Bypass => "bypass",
+            AuditAction::Configuration => "config",
+            AuditAction::EnergyReset => "energy-reset",
+            AuditAction::Undo => "undo",
+        }
+    }
+
+    // Whether it changes what the REST API's revision stands for: setpoints, limits, motor
+    // states and the rest of the plant's configuration, as opposed to sign-ins,
+    // acknowledgments and bookkeeping
+    pub fn is_change(self) -> bool {
+        !matches!(
+            self,
+            AuditAction::Diagnostic | AuditAction::AlarmAck | AuditAction::Login | AuditAction::Logout | AuditAction::Maintenance
+                | AuditAction::Notification | AuditAction::StatisticsReset | AuditAction::ShiftClose | AuditAction::EnergyReset
+        )
+    }
+
+    pub fn parse(name: &str) -> Option<AuditAction> {
+        AuditAction::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    pub timestamp: SystemTime,
+    pub user: String,
+    pub action: AuditAction,
+    pub detail: String,
+}
+
+impl AuditEvent {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("timestamp".to_string(), format_datetime(self.timestamp).into()),
+            ("user".to_string(), self.user.as_str().into()),
+            ("action".to_string(), self.action.name().into()),
+            ("detail".to_string(), self.detail.as_str().into()),
+        ])
+    }
+
+    // None for anything to_json didn't write
+    pub fn from_json(value: &Value) -> Option<AuditEvent> {
+        Some(AuditEvent {
+            timestamp: parse_datetime(value.str_field("timestamp").ok()?)?,
+            user: value.str_field("user").ok()?.to_string(),
+            action: AuditAction::parse(value.str_field("action").ok()?)?,
+            detail: value.str_field("detail").ok()?.to_string(),
+        })
+    }
+}
+
+// Reads the events recorded between `since` and `until` back from an audit file, oldest
+// first. Lines that can't be read as events, e.g. from a newer version, are skipped.
+pub fn query(path: &Path, since: SystemTime, until: SystemTime) -> io::Result<Vec<AuditEvent>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut events = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let event = match Value::parse(&line?).ok().as_ref().and_then(AuditEvent::from_json) {
+            Some(event) => event,
+            None => continue,
+        };
+        if event.timestamp >= since && event.timestamp <= until {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+// The account the console runs under; actions are attributed to it when nobody is logged in
+pub fn os_user() -> String {
+    env::var("USERNAME").or_else(|_| env::var("USER")).unwrap_or_else(|_| "operator".to_string())
+}
+
+// Owns the writer thread. Dropping it writes out everything still queued.
+pub struct AuditLog {
+    path: PathBuf,
+    events: Vec<AuditEvent>,
+    writer: Option<(Sender<AuditEvent>, JoinHandle<()>)>,
+}
+
+impl AuditLog {
+    // Fails if the file can't be opened for appending; the caller can still keep the
+    // session's events in memory with `in_memory`. Later write failures are reported
+    // once through `reports`.
+    pub fn open(path: PathBuf, reports: Sender<Result<String, String>>) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let (events, queued) = mpsc::channel();
+        let thread_path = path.clone();
+        let thread = thread::spawn(move || run_writer(BufWriter::new(file), &thread_path, queued, reports));
+        Ok(AuditLog { path, events: Vec::new(), writer: Some((events, thread)) })
+    }
+
+    pub fn in_memory(path: PathBuf) -> AuditLog {
+        AuditLog { path, events: Vec::new(), writer: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_writing(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn record(&mut self, user: &str, action: AuditAction, detail: &str) {
+        let event = AuditEvent { timestamp: SystemTime::now(), user: user.to_string(), action, detail: detail.to_string() };
+        if let Some((events, _)) = &self.writer {
+            let _ = events.send(event.clone());
+        }
+        self.events.push(event);
+    }
+
+    // This session's events, oldest first
+    pub fn events(&self) -> &[AuditEvent] {
+        &self.events
+    }
+
+    // Closing the channel lets the writer drain the queue and flush before it exits
+    pub fn close(&mut self) {
+        if let Some((events, thread)) = self.writer.take() {
+            drop(events);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn run_writer(mut file: BufWriter<File>, path: &Path, events: Receiver<AuditEvent>, reports: Sender<Result<String, String>>) {
+    let mut failing = false;
+    // Ends when the log is closed and the queue is empty
+    while let Ok(event) = events.recv() {
+        let mut result = writeln!(file, "{}", event.to_json());
+        for event in events.try_iter() {
+            result = result.and_then(|()| writeln!(file, "{}", event.to_json()));
+        }
+        // Flushed per batch so a crash loses at most the events being written
+        result = result.and_then(|()| file.flush());
+
+        // Reported once when writing starts failing and once when it recovers
+        match result {
+            Err(e) if !failing => {
+                failing = true;
+                let _ = reports.send(Err(format!("Audit trail write to {} failed: {} - events are kept for this session only", path.display(), e)));
+            }
+            Ok(()) if failing => {
+                failing = false;
+                let _ = reports.send(Ok(format!("Audit trail writing to {} again", path.display())));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// Operator accounts and roles. PINs are kept in forlenza.toml only as salted,
+// stretched SHA-1 hashes; `--hash-pin NAME PIN` prints the value to paste in.
+use crate::sha1::sha1;
+
+use std::fmt;
+use std::time::Instant;
+
+// Rounds of hashing per check, so a copied config can't be brute-forced instantly
+const HASH_ROUNDS: u32 = 10_000;
+
+// Ordered so a Supervisor can do everything an Operator can
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Operator,
+    Supervisor,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Result<Role, String> {
+        match name {
+            "operator" => Ok(Role::Operator),
+            "supervisor" => Ok(Role::Supervisor),
+            _ => Err(format!("unknown role '{}' (use operator or supervisor)", name)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Operator => write!(f, "Operator"),
+            Role::Supervisor => write!(f, "Supervisor"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    pub name: String,
+    pub role: Role,
+    // Hex digest from hash_pin
+    pub pin_hash: String,
+}
+
+impl Account {
+    pub fn verify(&self, pin: &str) -> bool {
+        let hash = hash_pin(&self.name, pin);
+        // Compared in full every time so the time taken doesn't reveal a matching prefix
+        hash.len() == self.pin_hash.len() && hash.bytes().zip(self.pin_hash.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+// 4-12 digits, like a keypad PIN
+pub fn check_pin(pin: &str) -> Result<(), String> {
+    if (4..=12).contains(&pin.len()) && pin.bytes().all(|byte| byte.is_ascii_digit()) {
+        Ok(())
+    } else {
+        Err("a PIN must be 4-12 digits".to_string())
+    }
+}
+
+// The account name salts the hash, so two users with the same PIN don't share a hash
+pub fn hash_pin(name: &str, pin: &str) -> String {
+    let salt = format!("forlenza:{}:", name);
+    let mut digest = sha1(format!("{}{}", salt, pin).as_bytes());
+    for _ in 1..HASH_ROUNDS {
+        let mut block = digest.to_vec();
+        block.extend_from_slice(salt.as_bytes());
+        block.extend_from_slice(pin.as_bytes());
+        digest = sha1(&block);
+    }
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn is_pin_hash(text: &str) -> bool {
+    text.len() == 40 && text.bytes().all(|byte| byte.is_ascii_hexdigit() && !byte.is_ascii_uppercase())
+}
+
+// Who is logged in at the console
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub name: String,
+    pub role: Role,
+    pub last_activity: Instant,
+}
@@ -0,0 +1,203 @@
+// Configuration bundles for commissioning another panel PC like this one: forlenza.toml
+// (channels, limits, interlocks, users, network and data source settings), the settings
+// changed at the console and the recipes, in one versioned JSON file. 'config export'
+// writes one; 'config import' checks all of it before anything is written, lists what
+// would change and, once confirmed, replaces the files together. Calibrations and
+// maintenance counters belong to one station's instruments and motors and are left out,
+// as are the files of further devices.
+use crate::clock::format_datetime;
+use crate::config::Config;
+use crate::json::Value;
+use crate::recipes::RecipeBook;
+use crate::schema;
+use crate::settings::Settings;
+use crate::toml;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const FORMAT: &str = "forlenza-configuration";
+// Raised whenever a part is added or changes meaning. A bundle from a newer version is
+// refused whole, since this console can't know what it would leave out.
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub schema_version: u64,
+    pub system_id: String,
+    pub exported_at: String,
+    // forlenza.toml as written, comments and all; empty for a station on built-in defaults
+    pub config_text: String,
+    pub settings: Settings,
+    pub recipes: RecipeBook,
+}
+
+impl Bundle {
+    pub fn new(system_id: &str, config_text: String, settings: Settings, recipes: RecipeBook) -> Bundle {
+        Bundle { schema_version: SCHEMA_VERSION, system_id: system_id.to_string(), exported_at: format_datetime(SystemTime::now()), config_text, settings, recipes }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("format".to_string(), FORMAT.into()),
+            ("schema_version".to_string(), self.schema_version.into()),
+            ("system_id".to_string(), self.system_id.as_str().into()),
+            ("exported_at".to_string(), self.exported_at.as_str().into()),
+            ("config".to_string(), self.config_text.as_str().into()),
+            ("settings".to_string(), self.settings.to_json()),
+            ("recipes".to_string(), self.recipes.to_json()),
+        ])
+    }
+
+    // Every part is checked here, so a bundle that loads can be applied whole
+    pub fn from_json(value: &Value) -> Result<Bundle, String> {
+        if value.get("format").and_then(Value::as_str) != Some(FORMAT) {
+            return Err("not a Forlenza configuration bundle".to_string());
+        }
+        let schema_version = value.f64_field("schema_version").ok().filter(|version| version.fract() == 0.0 && *version >= 1.0).ok_or("schema_version is missing or not a version number")? as u64;
+        if schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "it is schema version {}, newer than the version {} this console reads - import it with the console version that exported it or a later one",
+                schema_version, SCHEMA_VERSION,
+            ));
+        }
+        let config_text = value.str_field("config")?.to_string();
+        parse_config(&config_text).map_err(|e| format!("its forlenza.toml is invalid: {}", e))?;
+        Ok(Bundle {
+            schema_version,
+            system_id: value.str_field("system_id")?.to_string(),
+            exported_at: value.str_field("exported_at")?.to_string(),
+            config_text,
+            settings: Settings::parse(value.field("settings")?).map_err(|e| format!("its settings are invalid: {}", e))?,
+            recipes: RecipeBook::from_json(value.field("recipes")?).map_err(|e| format!("its recipes are invalid: {}", e))?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Bundle, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let value = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+        Bundle::from_json(&value)
+    }
+
+    // The configuration the bundle's forlenza.toml gives, which says where the settings
+    // and recipes go
+    pub fn config(&self) -> Config {
+        parse_config(&self.config_text).unwrap_or_default()
+    }
+
+    // What importing would change on a station running with these, one line each; empty
+    // when it would change nothing
+    pub fn changes(&self, config_text: &str, settings: &Settings, recipes: &RecipeBook) -> Vec<String> {
+        let mut changes = Vec::new();
+        let document = |text: &str| toml::parse(text).unwrap_or(Value::Object(Vec::new()));
+        let (current, imported) = (document(config_text), document(&self.config_text));
+        for (table, difference) in differences(&current, &imported) {
+            // Accounts are named, never shown
+            if table == "users" {
+                let users = differences(current.get("users").unwrap_or(&Value::Null), imported.get("users").unwrap_or(&Value::Null));
+                for (name, difference) in users {
+                    changes.push(format!("forlenza.toml: user {} {}", name, difference));
+                }
+            } else {
+                changes.push(format!("forlenza.toml: [{}] {}", table, difference));
+            }
+        }
+        for (key, difference) in differences(&settings.to_json(), &self.settings.to_json()) {
+            changes.push(format!("settings: {} {}", key.replace('_', " "), difference));
+        }
+        for recipe in self.recipes.recipes() {
+            match recipes.find(&recipe.name) {
+                None => changes.push(format!("recipes: {} added", recipe.name)),
+                Some(current) if current != recipe => changes.push(format!("recipes: {} replaced", recipe.name)),
+                Some(_) => {}
+            }
+        }
+        for recipe in recipes.recipes().iter().filter(|recipe| self.recipes.find(&recipe.name).is_none()) {
+            changes.push(format!("recipes: {} removed", recipe.name));
+        }
+        changes
+    }
+
+    // Writes forlenza.toml to `config_path` and the settings and recipes where that file
+    // says, all or none: each is written beside its target first, and the files replaced
+    // are kept with .bak added to their names, which also lets a failed replacement be
+    // undone.
+    pub fn apply(&self, config_path: &Path) -> Result<Vec<PathBuf>, String> {
+        let config = self.config();
+        let targets = [
+            (config_path.to_path_buf(), self.config_text.clone()),
+            (config.settings_file.clone(), self.settings.to_json().to_pretty_string()),
+            (config.recipes_file.clone(), self.recipes.to_json().to_pretty_string()),
+        ];
+        let staged = |path: &Path| suffixed(path, ".import");
+        for (path, text) in &targets {
+            if let Err(e) = fs::write(staged(path), text) {
+                for (written, _) in &targets {
+                    let _ = fs::remove_file(staged(written));
+                }
+                return Err(format!("{} could not be written: {}", staged(path).display(), e));
+            }
+        }
+        let mut replaced: Vec<(&PathBuf, bool)> = Vec::new();
+        for (path, _) in &targets {
+            let backup = suffixed(path, ".bak");
+            let existed = path.exists();
+            let result = match existed {
+                true => fs::rename(path, &backup).and_then(|()| fs::rename(staged(path), path)),
+                false => fs::rename(staged(path), path),
+            };
+            if let Err(e) = result {
+                // Back the way it was: this file and every one replaced before it
+                if existed && !path.exists() {
+                    let _ = fs::rename(&backup, path);
+                }
+                for (done, existed) in replaced {
+                    let _ = match existed {
+                        true => fs::rename(suffixed(done, ".bak"), done),
+                        false => fs::remove_file(done),
+                    };
+                }
+                for (written, _) in &targets {
+                    let _ = fs::remove_file(staged(written));
+                }
+                return Err(format!("{} could not be replaced: {}", path.display(), e));
+            }
+            replaced.push((path, existed));
+        }
+        Ok(targets.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+// "forlenza.toml" becomes "forlenza.toml.bak" rather than "forlenza.bak"
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn parse_config(text: &str) -> Result<Config, String> {
+    toml::parse(text).and_then(schema::migrate).and_then(|(document, _)| Config::from_document(&document, None))
+}
+
+// The members of two objects that differ, by name: added, removed or changed
+fn differences(current: &Value, imported: &Value) -> Vec<(String, &'static str)> {
+    let members = |value: &Value| match value {
+        Value::Object(members) => members.clone(),
+        _ => Vec::new(),
+    };
+    let (current, imported) = (members(current), members(imported));
+    let find = |members: &[(String, Value)], key: &str| members.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone());
+    let mut differences = Vec::new();
+    for (key, value) in &imported {
+        match find(&current, key) {
+            None => differences.push((key.clone(), "added")),
+            Some(existing) if existing != *value => differences.push((key.clone(), "changed")),
+            Some(_) => {}
+        }
+    }
+    for (key, _) in current.iter().filter(|(key, _)| find(&imported, key).is_none()) {
+        differences.push((key.clone(), "removed"));
+    }
+    differences
+}
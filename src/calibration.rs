@@ -0,0 +1,147 @@
+// Per-channel calibration: the offset and gain found when a transmitter was last checked
+// against a reference, with when and by whom. Applied to every raw reading before anything
+// else sees it, so displays, alarms, history and exports all carry calibrated values.
+// Kept in a small JSON file by channel name, like the stored alarm limits.
+use crate::clock::{format_datetime, parse_datetime};
+use crate::json::Value;
+use crate::sensors::SensorData;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+// Gains outside this range usually mean a typo or the wrong transmitter
+pub const SANE_GAIN: std::ops::RangeInclusive<f32> = 0.5..=2.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    // In SI (°C or kPa), added after the gain
+    pub offset: f32,
+    pub gain: f32,
+    // None for a channel never calibrated at the console
+    pub calibrated: Option<SystemTime>,
+    // Initials of whoever did it
+    pub technician: String,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration { offset: 0.0, gain: 1.0, calibrated: None, technician: String::new() }
+    }
+}
+
+impl Calibration {
+    pub fn apply(&self, raw: f32) -> f32 {
+        raw * self.gain + self.offset
+    }
+
+    // The raw reading that calibrates to `value`
+    pub fn raw_for(&self, value: f32) -> f32 {
+        (value - self.offset) / self.gain
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.offset == 0.0 && self.gain == 1.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.gain.is_finite() || self.gain <= 0.0 {
+            return Err("gain must be a positive number".to_string());
+        }
+        if !self.offset.is_finite() {
+            return Err("offset must be a number".to_string());
+        }
+        Ok(())
+    }
+
+    // Outside SANE_GAIN; warned about, but still applied
+    pub fn implausible_gain(&self) -> bool {
+        !SANE_GAIN.contains(&self.gain)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut members = vec![
+            ("offset".to_string(), self.offset.into()),
+            ("gain".to_string(), self.gain.into()),
+        ];
+        if let Some(calibrated) = self.calibrated {
+            members.push(("calibrated".to_string(), format_datetime(calibrated).into()));
+        }
+        members.push(("technician".to_string(), self.technician.as_str().into()));
+        Value::Object(members)
+    }
+
+    fn from_json(item: &Value) -> Option<Calibration> {
+        let calibration = Calibration {
+            offset: item.get("offset")?.as_f64()? as f32,
+            gain: item.get("gain")?.as_f64()? as f32,
+            calibrated: item.get("calibrated").and_then(Value::as_str).and_then(parse_datetime),
+            technician: item.get("technician").and_then(Value::as_str).unwrap_or_default().to_string(),
+        };
+        calibration.validate().ok().map(|()| calibration)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CalibrationBook {
+    // By channel name; channels of devices after the first as "DEVICE/channel"
+    entries: Vec<(String, Calibration)>,
+}
+
+impl CalibrationBook {
+    // A missing file means nothing has been calibrated; a damaged one is an error rather
+    // than being silently replaced at the next save
+    pub fn load(path: &Path) -> Result<CalibrationBook, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(CalibrationBook::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let state = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if state.get("format").and_then(Value::as_str) != Some("forlenza-calibration") {
+            return Err("not a Forlenza calibration file".to_string());
+        }
+        let entries = state.array_field("channels", |item| Some((item.str_field("channel").ok()?.to_string(), Calibration::from_json(item)?)))?;
+        Ok(CalibrationBook { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let channels = self.entries.iter().map(|(channel, calibration)| {
+            let mut item = vec![("channel".to_string(), channel.as_str().into())];
+            if let Value::Object(members) = calibration.to_json() {
+                item.extend(members);
+            }
+            Value::Object(item)
+        }).collect();
+        Value::Object(vec![
+            ("format".to_string(), "forlenza-calibration".into()),
+            ("version".to_string(), 1u16.into()),
+            ("channels".to_string(), Value::Array(channels)),
+        ]).save(path)
+    }
+
+    pub fn set(&mut self, channel: &str, calibration: Calibration) {
+        match self.entries.iter_mut().find(|(name, _)| name == channel) {
+            Some((_, stored)) => *stored = calibration,
+            None => self.entries.push((channel.to_string(), calibration)),
+        }
+    }
+
+    // Puts the stored calibrations on the channels of the same name whose names start
+    // with `prefix`; returns how many matched
+    pub fn apply(&self, data: &mut SensorData, prefix: &str) -> usize {
+        let mut applied = 0;
+        for (name, calibration) in &self.entries {
+            let name = match name.strip_prefix(prefix) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some(channel) = data.channels.iter_mut().find(|channel| channel.name == name) {
+                channel.set_calibration(calibration.clone());
+                applied += 1;
+            }
+        }
+        applied
+    }
+}
@@ -0,0 +1,133 @@
+// Wall-clock formatting for log entries, exports and snapshots. Times are UTC
+// so logs from sites in different time zones line up.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Wall-clock time of day (UTC) for log entries
+pub fn timestamp() -> String {
+    format_time(SystemTime::now())
+}
+
+// ISO 8601 UTC with milliseconds, e.g. 2024-03-01T14:05:09.250Z
+pub fn format_datetime(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day,
+        secs / 3600 % 24, secs / 60 % 60, secs % 60, elapsed.subsec_millis())
+}
+
+// Days since 1970-01-01 to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    format!("{:02}:{:02}:{:02}", secs / 3600 % 24, secs / 60 % 60, secs % 60)
+}
+
+// Time of day with microseconds, for telling apart alarms raised in the same second
+pub fn format_time_precise(time: SystemTime) -> String {
+    let micros = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.subsec_micros());
+    format!("{}.{:06}", format_time(time), micros)
+}
+
+// Inverse of format_datetime; None for anything it didn't produce, including a field out
+// of range and a time before 1970
+pub fn parse_datetime(text: &str) -> Option<SystemTime> {
+    let field = |range: std::ops::Range<usize>, max: u64| {
+        let digits = text.get(range)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse::<u64>().ok().filter(|value| *value <= max)
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':'), (19, b'.'), (23, b'Z')];
+    if text.len() != 24 || separators.iter().any(|(at, separator)| text.as_bytes()[*at] != *separator) {
+        return None;
+    }
+    let year = field(0..4, 9999)? as i64;
+    let month = field(5..7, 12)? as u32;
+    let day = field(8..10, days_in_month(year, month))? as u32;
+    if month == 0 || day == 0 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days.checked_mul(86400)?
+        .checked_add(field(11..13, 23)? * 3600 + field(14..16, 59)? * 60 + field(17..19, 59)?)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))?.checked_add(Duration::from_millis(field(20..23, 999)?))
+}
+
+fn days_in_month(year: i64, month: u32) -> u64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// (year, month, day) civil date to days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetimes_round_trip() {
+        for text in ["1970-01-01T00:00:00.000Z", "2024-02-29T23:59:59.999Z", "2024-03-01T14:05:09.250Z", "2100-12-31T12:00:00.001Z"] {
+            assert_eq!(parse_datetime(text).map(format_datetime).as_deref(), Some(text));
+        }
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_301_909_250);
+        assert_eq!(parse_datetime(&format_datetime(time)), Some(time));
+    }
+
+    #[test]
+    fn times_before_1970_are_refused() {
+        assert_eq!(parse_datetime("1969-12-31T00:00:00.000Z"), None);
+        assert_eq!(parse_datetime("1969-12-31T23:59:59.999Z"), None);
+        assert_eq!(parse_datetime("0000-01-01T00:00:00.000Z"), None);
+    }
+
+    #[test]
+    fn fields_out_of_range_are_refused() {
+        for text in [
+            "2024-13-01T00:00:00.000Z",
+            "2024-00-01T00:00:00.000Z",
+            "2024-01-00T00:00:00.000Z",
+            "2024-04-31T00:00:00.000Z",
+            "2023-02-29T00:00:00.000Z",
+            "2024-01-01T99:00:00.000Z",
+            "2024-01-01T24:00:00.000Z",
+            "2024-01-01T00:60:00.000Z",
+            "2024-01-01T00:00:60.000Z",
+            "2024-01-01T00:00:00.+00Z",
+            "2024-01-01 00:00:00.000Z",
+            "2024-01-01T00:00:00.000",
+            "2024-1-01T00:00:00.000Z",
+        ] {
+            assert_eq!(parse_datetime(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+// Control commands. Everything that starts, stops or re-sets the plant (a typed command,
+// a shortcut key, a REST API write, a recipe, a scenario event, an automatic trip, the
+// staggered restart) is a Command carried out by the console's `execute` for the actor
+// that asked, so the role and mode checks, the interlocks and the audit entry are the
+// same whichever way it came in. What each mode allows a command is decided by
+// `permitted` alone.
+use crate::alarms::Limits;
+use crate::auth::Role;
+use crate::i18n::{tr, tr_args};
+use crate::mode::SystemMode;
+use crate::recipes::Recipe;
+use crate::sensors::ChannelKind;
+
+use std::fmt;
+
+// Devices are indexes into the console's list; motors and channels are numbered from 1
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    StartMotor { device: usize, motor: usize },
+    StopMotor { device: usize, motor: usize },
+    SetSetpoint { device: usize, motor: usize, rpm: u16 },
+    // The value is in the display unit
+    SetLimit { device: usize, kind: ChannelKind, channel: usize, limit: String, value: f32 },
+    // A channel's whole set of limits, in SI, as undo puts them back
+    RestoreLimits { device: usize, kind: ChannelKind, channel: usize, limits: Limits },
+    // Every setpoint and limit in the recipe, applied together or not at all
+    LoadRecipe { device: usize, recipe: Recipe },
+    ResetOverload { device: usize, motor: usize },
+    EmergencyShutdown { device: usize, reason: String },
+    Reset { device: usize },
+    Restart { device: usize },
+}
+
+// Who a command is carried out for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Actor {
+    // Whoever is at the console: the logged-in user, or anyone when no accounts are configured
+    Console,
+    // A REST API client, with the role it claimed
+    Api { client: String, role: Role },
+    // Whoever is at the console, undoing a change; the command isn't itself kept for undo
+    Undo,
+    // The console itself: an automatic trip, a restart a user began, or a scenario event
+    System,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandError {
+    // The actor's role doesn't allow it
+    Forbidden(String),
+    // Locked out until the emergency shutdown is reset
+    EmergencyShutdown,
+    // The device's mode doesn't allow it
+    Mode(String),
+    // Refused by the plant: an interlock, a tripped overload, a value out of range
+    Refused(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Forbidden(reason) | CommandError::Mode(reason) | CommandError::Refused(reason) => write!(f, "{}", reason),
+            CommandError::EmergencyShutdown => write!(f, "emergency shutdown is active - 'reset' clears it first"),
+        }
+    }
+}
+
+impl Command {
+    // The console's form of a command, as typed, for `device`. None for words that aren't
+    // a control command; the usage for one that is but is mistyped.
+    pub fn parse(words: &[&str], device: usize) -> Option<Result<Command, String>> {
+        let motor_number = |motor: &str| motor.parse().map_err(|_| tr_args("command.invalid_motor", &[&motor]));
+        let command = match words {
+            ["start", motor] => motor_number(motor).map(|motor| Command::StartMotor { device, motor }),
+            ["stop", motor] => motor_number(motor).map(|motor| Command::StopMotor { device, motor }),
+            ["setpoint", motor, rpm] => match (motor.parse(), rpm.parse()) {
+                (Ok(motor), Ok(rpm)) => Ok(Command::SetSetpoint { device, motor, rpm }),
+                _ => Err(tr("command.usage_setpoint").to_string()),
+            },
+            ["limit", kind, channel, limit, value] => match (ChannelKind::parse(kind), channel.parse(), value.parse()) {
+                (Ok(kind), Ok(channel), Ok(value)) => Ok(Command::SetLimit { device, kind, channel, limit: limit.to_string(), value }),
+                (Err(reason), _, _) => Err(reason),
+                _ => Err(tr("command.usage_limit").to_string()),
+            },
+            ["reset", "motor", motor] => motor_number(motor).map(|motor| Command::ResetOverload { device, motor }),
+            ["reset"] => Ok(Command::Reset { device }),
+            ["restart"] => Ok(Command::Restart { device }),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    pub fn device(&self) -> usize {
+        match self {
+            Command::StartMotor { device, .. }
+            | Command::StopMotor { device, .. }
+            | Command::SetSetpoint { device, .. }
+            | Command::SetLimit { device, .. }
+            | Command::RestoreLimits { device, .. }
+            | Command::LoadRecipe { device, .. }
+            | Command::ResetOverload { device, .. }
+            | Command::EmergencyShutdown { device, .. }
+            | Command::Reset { device }
+            | Command::Restart { device } => *device,
+        }
+    }
+
+    // None for the emergency shutdown, which anyone may trip
+    pub fn required_role(&self) -> Option<Role> {
+        match self {
+            Command::EmergencyShutdown { .. } => None,
+            Command::StartMotor { .. } | Command::StopMotor { .. } | Command::ResetOverload { .. } => Some(Role::Operator),
+            Command::SetSetpoint { .. }
+            | Command::SetLimit { .. }
+            | Command::RestoreLimits { .. }
+            | Command::LoadRecipe { .. }
+            | Command::Reset { .. }
+            | Command::Restart { .. } => Some(Role::Supervisor),
+        }
+    }
+
+    // The word it is typed with
+    pub fn word(&self) -> &'static str {
+        match self {
+            Command::StartMotor { .. } => "start",
+            Command::StopMotor { .. } => "stop",
+            Command::SetSetpoint { .. } => "setpoint",
+            Command::SetLimit { .. } => "limit",
+            Command::RestoreLimits { .. } => "undo",
+            Command::LoadRecipe { .. } => "recipe",
+            Command::ResetOverload { .. } | Command::Reset { .. } => "reset",
+            Command::EmergencyShutdown { .. } => "estop!",
+            Command::Restart { .. } => "restart",
+        }
+    }
+}
+
+// As typed at the console; putting limits back is named for the channel it undoes
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind_word = |kind: &ChannelKind| match kind {
+            ChannelKind::Temperature => "temp",
+            ChannelKind::Pressure => "pressure",
+        };
+        match self {
+            Command::StartMotor { motor, .. } => write!(f, "start {}", motor),
+            Command::StopMotor { motor, .. } => write!(f, "stop {}", motor),
+            Command::SetSetpoint { motor, rpm, .. } => write!(f, "setpoint {} {}", motor, rpm),
+            Command::SetLimit { kind, channel, limit, value, .. } => write!(f, "limit {} {} {} {}", kind_word(kind), channel, limit, value),
+            Command::RestoreLimits { kind, channel, .. } => write!(f, "undo limits {} {}", kind_word(kind), channel),
+            Command::LoadRecipe { recipe, .. } => write!(f, "recipe load {}", recipe.name),
+            Command::ResetOverload { motor, .. } => write!(f, "reset motor {}", motor),
+            Command::EmergencyShutdown { reason, .. } => write!(f, "estop! {}", reason),
+            Command::Reset { .. } => write!(f, "reset"),
+            Command::Restart { .. } => write!(f, "restart"),
+        }
+    }
+}
+
+// Whether a device in `mode`, with the emergency shutdown active or not, takes the
+// command. Stopping, setpoints, limits and overload resets are always taken; starting
+// waits for the shutdown to be reset and for maintenance to end, and a recipe for the
+// shutdown to be reset.
+pub fn permitted(command: &Command, mode: SystemMode, shut_down: bool) -> Result<(), CommandError> {
+    let shut_down = shut_down || mode == SystemMode::EStop;
+    match command {
+        Command::EmergencyShutdown { .. }
+        | Command::StopMotor { .. }
+        | Command::SetSetpoint { .. }
+        | Command::SetLimit { .. }
+        | Command::RestoreLimits { .. }
+        | Command::ResetOverload { .. } => Ok(()),
+        Command::StartMotor { .. } | Command::Restart { .. } | Command::LoadRecipe { .. } if shut_down => Err(CommandError::EmergencyShutdown),
+        Command::LoadRecipe { .. } => Ok(()),
+        Command::StartMotor { .. } | Command::Restart { .. } => mode.motor_starts_allowed().map_err(CommandError::Mode),
+        Command::Reset { .. } if shut_down => Ok(()),
+        Command::Reset { .. } => Err(CommandError::Mode(format!("there is no emergency shutdown to reset ({})", mode))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use SystemMode::{Degraded, EStop, Maintenance, Running, Startup};
+
+    const MODES: [SystemMode; 5] = [Startup, Running, Degraded, EStop, Maintenance];
+
+    fn commands() -> Vec<Command> {
+        let limits = Limits::new(10.0, 20.0, 80.0, 90.0);
+        let recipe = Recipe { name: "base".to_string(), setpoints: vec![1500], limits: Vec::new() };
+        vec![
+            Command::StartMotor { device: 0, motor: 1 },
+            Command::StopMotor { device: 0, motor: 1 },
+            Command::SetSetpoint { device: 0, motor: 1, rpm: 1500 },
+            Command::SetLimit { device: 0, kind: ChannelKind::Temperature, channel: 1, limit: "high_alarm".to_string(), value: 90.0 },
+            Command::RestoreLimits { device: 0, kind: ChannelKind::Pressure, channel: 1, limits },
+            Command::LoadRecipe { device: 0, recipe },
+            Command::ResetOverload { device: 0, motor: 1 },
+            Command::EmergencyShutdown { device: 0, reason: "test".to_string() },
+            Command::Reset { device: 0 },
+            Command::Restart { device: 0 },
+        ]
+    }
+
+    // What each command gets in each mode, without and then with the emergency shutdown
+    // latched: 'Y' taken, 'E' emergency shutdown, 'M' refused by the mode
+    const TABLE: [(&str, [&str; 5]); 10] = [
+        //            Startup  Running  Degraded  EStop   Maintenance
+        ("start", ["YE", "YE", "YE", "EE", "ME"]),
+        ("stop", ["YY", "YY", "YY", "YY", "YY"]),
+        ("setpoint", ["YY", "YY", "YY", "YY", "YY"]),
+        ("limit", ["YY", "YY", "YY", "YY", "YY"]),
+        ("undo", ["YY", "YY", "YY", "YY", "YY"]),
+        ("recipe", ["YE", "YE", "YE", "EE", "YE"]),
+        ("reset motor", ["YY", "YY", "YY", "YY", "YY"]),
+        ("estop!", ["YY", "YY", "YY", "YY", "YY"]),
+        ("reset", ["MY", "MY", "MY", "YY", "MY"]),
+        ("restart", ["YE", "YE", "YE", "EE", "ME"]),
+    ];
+
+    fn outcome(result: Result<(), CommandError>) -> char {
+        match result {
+            Ok(()) => 'Y',
+            Err(CommandError::EmergencyShutdown) => 'E',
+            Err(CommandError::Mode(_)) => 'M',
+            Err(other) => panic!("permitted only refuses by mode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn command_by_mode() {
+        for (command, (name, row)) in commands().iter().zip(TABLE) {
+            for (mode, expected) in MODES.iter().zip(row) {
+                let got: String = [false, true].iter().map(|shut_down| outcome(permitted(command, *mode, *shut_down))).collect();
+                assert_eq!(got, expected, "'{}' in {} mode", name, mode);
+            }
+        }
+    }
+
+    #[test]
+    fn roles() {
+        let roles: Vec<Option<Role>> = commands().iter().map(Command::required_role).collect();
+        let (operator, supervisor) = (Some(Role::Operator), Some(Role::Supervisor));
+        assert_eq!(roles, [operator, operator, supervisor, supervisor, supervisor, supervisor, operator, None, supervisor, supervisor]);
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for command in commands().into_iter().filter(|command| !matches!(command, Command::RestoreLimits { .. } | Command::LoadRecipe { .. })) {
+            let typed = command.to_string();
+            let words: Vec<&str> = typed.split_whitespace().collect();
+            match Command::parse(&words, 0) {
+                Some(Ok(parsed)) => assert_eq!(parsed, command),
+                // The emergency shutdown is a key of its own, not parsed here
+                None if matches!(command, Command::EmergencyShutdown { .. }) => {}
+                other => panic!("'{}' parsed as {:?}", typed, other),
+            }
+        }
+        assert!(matches!(Command::parse(&["setpoint", "1", "fast"], 0), Some(Err(_))));
+        assert_eq!(Command::parse(&["status"], 0), None);
+    }
+}
@@ -0,0 +1,294 @@
+// Comparison of the live system with a saved snapshot, e.g. a "golden" one taken at
+// commissioning: each channel's reading and limits and each motor's setpoint and run
+// state side by side. Channels and motors are matched by name, so a snapshot from a
+// differently configured system still compares; what is only on one side is listed
+// apart. Values are in SI here; the console and the CSV convert to the display units.
+use crate::alarms::Limits;
+use crate::csv;
+use crate::json::Value;
+use crate::sensors::{ChannelKind, SensorData};
+use crate::units::{Unit, Units};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// The snapshot compared against, kept until another is loaded
+#[derive(Clone, Debug)]
+pub struct Baseline {
+    pub path: String,
+    pub system_id: String,
+    pub captured_at: String,
+    pub data: SensorData,
+}
+
+impl Baseline {
+    pub fn load(path: &str) -> Result<Baseline, String> {
+        let snapshot = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e)))?;
+        let (data, _) = SensorData::from_snapshot(&snapshot)?;
+        let text = |key: &str| snapshot.get(key).and_then(Value::as_str).unwrap_or("unknown").to_string();
+        Ok(Baseline { path: path.to_string(), system_id: text("system_id"), captured_at: text("captured_at"), data })
+    }
+}
+
+// Differences up to these are expected drift; beyond them are flagged, and beyond twice
+// them flagged as serious
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerances {
+    // °C
+    pub temperature: f32,
+    // kPa
+    pub pressure: f32,
+    // RPM
+    pub setpoint: u16,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances { temperature: 0.5, pressure: 1.0, setpoint: 50 }
+    }
+}
+
+impl Tolerances {
+    pub fn channel(&self, kind: ChannelKind) -> f32 {
+        match kind {
+            ChannelKind::Temperature => self.temperature,
+            ChannelKind::Pressure => self.pressure,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Deviation {
+    Same,
+    Within,
+    Beyond,
+    FarBeyond,
+}
+
+impl Deviation {
+    pub fn of(difference: f32, tolerance: f32) -> Deviation {
+        let difference = difference.abs();
+        if difference == 0.0 {
+            Deviation::Same
+        } else if difference <= tolerance {
+            Deviation::Within
+        } else if difference <= 2.0 * tolerance {
+            Deviation::Beyond
+        } else {
+            Deviation::FarBeyond
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Deviation::Same => "same",
+            Deviation::Within => "within tolerance",
+            Deviation::Beyond => "beyond tolerance",
+            Deviation::FarBeyond => "beyond twice the tolerance",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChannelComparison {
+    pub name: String,
+    // Tag and name as the live system has them
+    pub label: String,
+    pub kind: ChannelKind,
+    pub snapshot: f32,
+    pub live: f32,
+    pub snapshot_limits: Limits,
+    pub live_limits: Limits,
+}
+
+impl ChannelComparison {
+    // A reading that is not a number on only one side (an open circuit) is as far off as can be
+    pub fn deviation(&self, tolerances: &Tolerances) -> Deviation {
+        match (self.snapshot.is_finite(), self.live.is_finite()) {
+            (true, true) => Deviation::of(self.live - self.snapshot, tolerances.channel(self.kind)),
+            (false, false) => Deviation::Same,
+            _ => Deviation::FarBeyond,
+        }
+    }
+
+    // Every limit, as (name, snapshot, live); a trip limit set on neither side is left out
+    pub fn limits(&self) -> Vec<(&'static str, Option<f32>, Option<f32>)> {
+        let (snapshot, live) = (&self.snapshot_limits, &self.live_limits);
+        [
+            ("low alarm", Some(snapshot.low_alarm), Some(live.low_alarm)),
+            ("low warning", Some(snapshot.low_warning), Some(live.low_warning)),
+            ("high warning", Some(snapshot.high_warning), Some(live.high_warning)),
+            ("high alarm", Some(snapshot.high_alarm), Some(live.high_alarm)),
+            ("low trip", snapshot.low_trip, live.low_trip),
+            ("high trip", snapshot.high_trip, live.high_trip),
+        ]
+        .into_iter()
+        .filter(|(_, snapshot, live)| snapshot.is_some() || live.is_some())
+        .collect()
+    }
+
+    // A trip limit set on one side only counts as far off
+    pub fn limit_deviation(&self, snapshot: Option<f32>, live: Option<f32>, tolerances: &Tolerances) -> Deviation {
+        match (snapshot, live) {
+            (Some(snapshot), Some(live)) => Deviation::of(live - snapshot, tolerances.channel(self.kind)),
+            _ => Deviation::FarBeyond,
+        }
+    }
+
+    // The worst of the limits
+    pub fn limits_deviation(&self, tolerances: &Tolerances) -> Deviation {
+        self.limits().into_iter()
+            .map(|(_, snapshot, live)| self.limit_deviation(snapshot, live, tolerances))
+            .max()
+            .unwrap_or(Deviation::Same)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MotorComparison {
+    pub name: String,
+    pub label: String,
+    pub snapshot_setpoint: u16,
+    pub live_setpoint: u16,
+    pub snapshot_running: bool,
+    pub live_running: bool,
+}
+
+impl MotorComparison {
+    pub fn setpoint_deviation(&self, tolerances: &Tolerances) -> Deviation {
+        Deviation::of(self.live_setpoint as f32 - self.snapshot_setpoint as f32, tolerances.setpoint as f32)
+    }
+
+    // A motor running on one side only is flagged, like a setpoint beyond tolerance
+    pub fn running_deviation(&self) -> Deviation {
+        if self.snapshot_running == self.live_running {
+            Deviation::Same
+        } else {
+            Deviation::Beyond
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Comparison {
+    pub channels: Vec<ChannelComparison>,
+    pub motors: Vec<MotorComparison>,
+    // Channels and motors found on one side only, by tag and name
+    pub only_in_snapshot: Vec<String>,
+    pub only_live: Vec<String>,
+}
+
+impl Comparison {
+    pub fn new(snapshot: &SensorData, live: &SensorData) -> Comparison {
+        let mut comparison = Comparison::default();
+        for channel in &live.channels {
+            match snapshot.channels.iter().find(|saved| saved.name == channel.name && saved.kind == channel.kind) {
+                Some(saved) => comparison.channels.push(ChannelComparison {
+                    name: channel.name.clone(),
+                    label: channel.label(),
+                    kind: channel.kind,
+                    snapshot: saved.value,
+                    live: channel.value,
+                    snapshot_limits: saved.limits,
+                    live_limits: channel.limits,
+                }),
+                None => comparison.only_live.push(channel.label()),
+            }
+        }
+        for motor in &live.motors {
+            match snapshot.motors.iter().find(|saved| saved.name == motor.name) {
+                Some(saved) => comparison.motors.push(MotorComparison {
+                    name: motor.name.clone(),
+                    label: motor.label(),
+                    snapshot_setpoint: saved.setpoint,
+                    live_setpoint: motor.setpoint,
+                    snapshot_running: saved.running,
+                    live_running: motor.running,
+                }),
+                None => comparison.only_live.push(motor.label()),
+            }
+        }
+        let matched = |name: &String| comparison.channels.iter().any(|channel| channel.name == *name) || comparison.motors.iter().any(|motor| motor.name == *name);
+        let only_in_snapshot: Vec<String> = snapshot.channels.iter().map(|channel| (&channel.name, channel.label()))
+            .chain(snapshot.motors.iter().map(|motor| (&motor.name, motor.label())))
+            .filter(|(name, _)| !matched(name))
+            .map(|(_, label)| label)
+            .collect();
+        comparison.only_in_snapshot = only_in_snapshot;
+        comparison
+    }
+
+    // How many readings, limits, setpoints and run states are beyond tolerance
+    pub fn flagged(&self, tolerances: &Tolerances) -> usize {
+        let channels = self.channels.iter().map(|channel| {
+            let limits = channel.limits().into_iter().filter(|(_, snapshot, live)| channel.limit_deviation(*snapshot, *live, tolerances) >= Deviation::Beyond).count();
+            limits + usize::from(channel.deviation(tolerances) >= Deviation::Beyond)
+        });
+        let motors = self.motors.iter().map(|motor| usize::from(motor.setpoint_deviation(tolerances) >= Deviation::Beyond) + usize::from(motor.running_deviation() >= Deviation::Beyond));
+        channels.chain(motors).sum()
+    }
+
+    // One row per reading, limit, setpoint and run state, then one per unmatched name,
+    // in the display units; one place more than the display, like the history export
+    pub fn write_csv(&self, path: &Path, units: Units, tolerances: &Tolerances) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let header = ["item", "field", "unit", "snapshot", "live", "difference", "result"];
+        csv::write_row(&mut writer, &header.map(str::to_string))?;
+        for channel in &self.channels {
+            let unit = units.get(channel.kind);
+            let deviation = channel.deviation(tolerances);
+            csv::write_row(&mut writer, &channel_row(&channel.label, "value", unit, Some(channel.snapshot), Some(channel.live), deviation))?;
+            for (field, snapshot, live) in channel.limits() {
+                let deviation = channel.limit_deviation(snapshot, live, tolerances);
+                csv::write_row(&mut writer, &channel_row(&channel.label, field, unit, snapshot, live, deviation))?;
+            }
+        }
+        for motor in &self.motors {
+            let difference = motor.live_setpoint as i32 - motor.snapshot_setpoint as i32;
+            csv::write_row(&mut writer, &[
+                motor.label.clone(),
+                "setpoint".to_string(),
+                "RPM".to_string(),
+                motor.snapshot_setpoint.to_string(),
+                motor.live_setpoint.to_string(),
+                difference.to_string(),
+                motor.setpoint_deviation(tolerances).name().to_string(),
+            ])?;
+            csv::write_row(&mut writer, &[
+                motor.label.clone(),
+                "running".to_string(),
+                String::new(),
+                motor.snapshot_running.to_string(),
+                motor.live_running.to_string(),
+                String::new(),
+                motor.running_deviation().name().to_string(),
+            ])?;
+        }
+        for (names, result) in [(&self.only_in_snapshot, "only in snapshot"), (&self.only_live, "only in live system")] {
+            for name in names {
+                csv::write_row(&mut writer, &[name.clone(), String::new(), String::new(), String::new(), String::new(), String::new(), result.to_string()])?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+fn channel_row(name: &str, field: &str, unit: Unit, snapshot: Option<f32>, live: Option<f32>, deviation: Deviation) -> Vec<String> {
+    let number = |value: Option<f32>| value.filter(|value| value.is_finite()).map_or(String::new(), |value| format!("{:.*}", unit.decimals() + 1, unit.from_si(value)));
+    let difference = match (snapshot, live) {
+        (Some(snapshot), Some(live)) if snapshot.is_finite() && live.is_finite() => format!("{:.*}", unit.decimals() + 1, unit.from_si(live) - unit.from_si(snapshot)),
+        _ => String::new(),
+    };
+    vec![
+        name.to_string(),
+        field.to_string(),
+        unit.symbol().trim().to_string(),
+        number(snapshot),
+        number(live),
+        difference,
+        deviation.name().to_string(),
+    ]
+}
@@ -0,0 +1,1357 @@
+// Startup settings loaded from forlenza.toml next to the executable. Every key is
+// optional; anything missing keeps the built-in default.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::alarms::{AlarmFilter, AlarmLevel, Limits, RateLimit};
+use crate::ambient::Weather;
+use crate::annunciator::AnnunciatorConfig;
+use crate::api::{ApiClient, ApiConfig};
+use crate::auth::{self, Account, Role};
+use crate::compare::Tolerances;
+use crate::error::FisError;
+use crate::heartbeat::LinkThresholds;
+use crate::historian::{ChangeOfValue, HistorianConfig, MAX_HEARTBEAT};
+use crate::i18n::Language;
+use crate::interlocks::{Condition, Interlock};
+use crate::json::Value;
+use crate::logfile::LogFileConfig;
+use crate::modbus::{ModbusRtuConfig, ModbusTcpConfig, RegisterMap};
+use crate::mqtt::MqttConfig;
+use crate::notify::{EmailConfig, NotifyConfig, WebhookConfig};
+use crate::pid::{ControlLoop, LoopMode, PidController};
+use crate::platform::{LogLevel, Theme};
+use crate::replay::{ReplayConfig, ReplaySpeed};
+use crate::schema;
+use crate::energy::Supply;
+use crate::sensors::{self, ChannelKind, Tagging, DEFAULT_OVERLOAD_TIME, MAX_MOTOR_SETPOINT, RATED_MOTOR_SPEED};
+use crate::serial::{self, Parity};
+use crate::simulation::{self, ProcessModel, SimulationModel};
+use crate::toml;
+use crate::units::{Unit, Units};
+use crate::vibration;
+
+pub const CONFIG_FILE_NAME: &str = "forlenza.toml";
+
+// Where live readings come from
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceConfig {
+    Simulation,
+    ModbusTcp(ModbusTcpConfig),
+    ModbusRtu(ModbusRtuConfig),
+    Replay(ReplayConfig),
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub system_id: String,
+    pub title: String,
+    pub update_interval_ms: u64,
+    pub seed: Option<u64>,
+    // How long the sensor thread may go without running before acquisition counts as halted
+    pub watchdog_timeout: Duration,
+    // How the built-in simulation moves the readings
+    pub simulation_model: SimulationModel,
+    pub temperatures: Vec<f32>,
+    pub pressures: Vec<f32>,
+    // Display names; channels past the end of a list get a numbered default
+    pub temperature_names: Vec<String>,
+    pub pressure_names: Vec<String>,
+    pub motor_names: Vec<String>,
+    // Instrument tags and descriptions, in the same order; no tag unless given
+    pub temperature_tagging: Vec<Tagging>,
+    pub pressure_tagging: Vec<Tagging>,
+    pub motor_tagging: Vec<Tagging>,
+    pub motor_setpoints: Vec<u16>,
+    pub motor_running: Vec<bool>,
+    pub motor_ramp_rate: u16,
+    // Electrical ratings; motors past the end of a list get the defaults
+    pub motor_full_load_amps: Vec<f32>,
+    pub motor_load_factors: Vec<f32>,
+    // Time above full-load amps before a motor's overload relay trips it
+    pub motor_overload_time: Duration,
+    // Simulated digital I/O, e.g. guard door switches and valve solenoids; points past
+    // the end of their list of states start off
+    pub inputs: Vec<String>,
+    pub input_states: Vec<bool>,
+    pub outputs: Vec<String>,
+    pub output_states: Vec<bool>,
+    pub interlocks: Vec<Interlock>,
+    // None unless enabled; only the built-in simulation runs it
+    pub control_loop: Option<ControlLoop>,
+    pub temperature_limits: Limits,
+    pub pressure_limits: Limits,
+    pub temperature_filter: AlarmFilter,
+    pub pressure_filter: AlarmFilter,
+    // Rate-of-change alarms for each type, and the limit for each channel where it differs
+    // (None for none)
+    pub temperature_rate: RateLimit,
+    pub pressure_rate: RateLimit,
+    pub temperature_rate_limits: Vec<Option<f32>>,
+    pub pressure_rate_limits: Vec<Option<f32>>,
+    // Consecutive samples beyond a trip limit before the system shuts down
+    pub trip_samples: u32,
+    // What readings are shown and limits typed in; everything in this file stays °C and kPa
+    pub units: Units,
+    // How status colors are drawn
+    pub theme: Theme,
+    // Console text and decimal separator
+    pub language: Language,
+    pub source: SourceConfig,
+    // When the link to the source counts as degraded or offline
+    pub link_thresholds: LinkThresholds,
+    // How long alarms and automatic trips are held off after the source is switched at runtime
+    pub swap_grace: Duration,
+    // None unless enabled
+    pub mqtt: Option<MqttConfig>,
+    pub api: Option<ApiConfig>,
+    pub historian: Option<HistorianConfig>,
+    // None unless enabled with at least one sink
+    pub notifications: Option<NotifyConfig>,
+    // None when the alarm horn is turned off
+    pub annunciator: Option<AnnunciatorConfig>,
+    pub audit_file: PathBuf,
+    // Lowest level of log line shown and written to the log file
+    pub log_level: LogLevel,
+    // None when disabled
+    pub log_file: Option<LogFileConfig>,
+    // Motor runtime counters, kept across restarts
+    pub maintenance_file: PathBuf,
+    // Running time between services before a motor is flagged for maintenance
+    pub service_interval: Duration,
+    // What motor power is worked out from
+    pub supply: Supply,
+    // Per kWh, in `energy_currency`, for the cost estimate; 0 shows none
+    pub energy_price: f64,
+    pub energy_currency: String,
+    // Energy totals, kept across restarts
+    pub energy_file: PathBuf,
+    // Overall vibration (mm/s RMS) above which a motor is in alarm
+    pub vibration_limit: f32,
+    // Units, interval and limits changed at the console, kept across restarts
+    pub settings_file: PathBuf,
+    // Named sets of motor setpoints and alarm limits
+    pub recipes_file: PathBuf,
+    // Channel offsets and gains set at the console
+    pub calibration_file: PathBuf,
+    // Where 'report' writes incident reports
+    pub reports_directory: PathBuf,
+    // Where trip records are captured after each shutdown
+    pub trips_directory: PathBuf,
+    // Offsets larger than these (°C, kPa) are warned about when set
+    pub temperature_max_offset: f32,
+    pub pressure_max_offset: f32,
+    // Time between automatic diagnostics; None runs them only at startup and on demand
+    pub diagnostic_interval: Option<Duration>,
+    // Times of day (UTC, from midnight) at which channel statistics start again
+    pub shift_changes: Vec<Duration>,
+    // Differences from a snapshot that 'compare' lets pass
+    pub compare_tolerances: Tolerances,
+    // Configuration files of further devices shown on this console, each with its own
+    // system ID, channels, motors and data source
+    pub device_files: Vec<PathBuf>,
+    // Empty means no login: anyone at the console has full control
+    pub accounts: Vec<Account>,
+    // Idle time before a logged-in user is logged out
+    pub session_timeout: Duration,
+    // What was done to bring a file for an older schema version up to date, for the log
+    pub schema_upgrade: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            system_id: "HIS-CTRL-7001".to_string(),
+            title: "Forlenza Industrial Control System v2.1".to_string(),
+            update_interval_ms: 1000,
+            watchdog_timeout: Duration::from_secs(10),
+            simulation_model: SimulationModel::Process(ProcessModel::default()),
+            seed: None,
+            temperatures: vec![23.5, 24.1, 22.8, 25.0],
+            pressures: vec![101.3, 98.7, 102.1],
+            temperature_names: Vec::new(),
+            pressure_names: Vec::new(),
+            motor_names: Vec::new(),
+            temperature_tagging: Vec::new(),
+            pressure_tagging: Vec::new(),
+            motor_tagging: Vec::new(),
+            motor_setpoints: vec![1750, 1800, 1500, 2200],
+            motor_running: vec![true, true, false, true],
+            motor_ramp_rate: 200,
+            motor_full_load_amps: Vec::new(),
+            motor_load_factors: Vec::new(),
+            motor_overload_time: DEFAULT_OVERLOAD_TIME,
+            inputs: Vec::new(),
+            input_states: Vec::new(),
+            outputs: Vec::new(),
+            output_states: Vec::new(),
+            interlocks: Vec::new(),
+            control_loop: None,
+            temperature_limits: Limits::new(20.5, 21.0, 26.0, 28.0),
+            pressure_limits: Limits::new(96.0, 98.0, 103.0, 104.0),
+            temperature_filter: AlarmFilter { deadband: 0.2, raise_samples: 2, clear_samples: 3 },
+            pressure_filter: AlarmFilter { deadband: 0.5, raise_samples: 2, clear_samples: 3 },
+            temperature_rate: RateLimit::default(),
+            pressure_rate: RateLimit::default(),
+            temperature_rate_limits: Vec::new(),
+            pressure_rate_limits: Vec::new(),
+            trip_samples: 3,
+            units: Units::default(),
+            theme: Theme::Dark,
+            language: Language::English,
+            source: SourceConfig::Simulation,
+            link_thresholds: LinkThresholds::default(),
+            swap_grace: Duration::from_secs(10),
+            mqtt: None,
+            api: None,
+            historian: None,
+            notifications: None,
+            annunciator: Some(AnnunciatorConfig::default()),
+            audit_file: beside_executable("audit.jsonl"),
+            log_level: LogLevel::Info,
+            log_file: Some(LogFileConfig { directory: beside_executable("logs"), max_bytes: 1024 * 1024, keep_files: 5 }),
+            maintenance_file: beside_executable("maintenance.json"),
+            service_interval: Duration::from_secs(2000 * 3600),
+            supply: Supply::default(),
+            energy_price: 0.0,
+            energy_currency: "$".to_string(),
+            energy_file: beside_executable("energy.json"),
+            vibration_limit: vibration::DEFAULT_LIMIT,
+            settings_file: beside_executable("settings.json"),
+            recipes_file: beside_executable("recipes.json"),
+            calibration_file: beside_executable("calibration.json"),
+            reports_directory: beside_executable("reports"),
+            trips_directory: beside_executable("trips"),
+            temperature_max_offset: 2.0,
+            pressure_max_offset: 5.0,
+            diagnostic_interval: None,
+            shift_changes: Vec::new(),
+            compare_tolerances: Tolerances::default(),
+            device_files: Vec::new(),
+            accounts: Vec::new(),
+            session_timeout: Duration::from_secs(15 * 60),
+            schema_upgrade: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        beside_executable(CONFIG_FILE_NAME)
+    }
+
+    // Never fails: a missing file silently means defaults, an invalid one means
+    // defaults plus a warning for the diagnostic log. A source kind given on the
+    // command line takes the place of source.kind.
+    pub fn load(path: &PathBuf, source: Option<&str>) -> (Config, Option<FisError>) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => match source {
+                Some(kind) if kind != "simulation" => return (
+                    Config::default(),
+                    Some(FisError::ConfigInvalid { path: path.clone(), reason: format!("not found, and --source {} needs its [source.{}] table", kind, source_table(kind)) }),
+                ),
+                _ => return (Config::default(), None),
+            },
+        };
+        let parsed = toml::parse(&text).and_then(schema::migrate).and_then(|(document, version)| {
+            Config::from_document(&document, source).map(|config| (config, document, version))
+        });
+        match parsed {
+            Ok((mut config, document, version)) if version < schema::CURRENT_VERSION => {
+                config.schema_upgrade = Some(upgrade_file(path, &text, &document, version));
+                (config, None)
+            }
+            Ok((config, _, _)) => (config, None),
+            Err(reason) => (Config::default(), Some(FisError::ConfigInvalid { path: path.clone(), reason })),
+        }
+    }
+
+    pub fn from_document(document: &Value, source_kind: Option<&str>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let table = |name: &str| document.get(name).unwrap_or(&Value::Null);
+
+        let system = table("system");
+        if let Some(value) = system.get("system_id") {
+            config.system_id = value.as_str().ok_or("system.system_id must be a string")?.to_string();
+        }
+        if let Some(value) = system.get("title") {
+            config.title = value.as_str().ok_or("system.title must be a string")?.to_string();
+        }
+
+        let simulation = table("simulation");
+        if simulation.get("update_interval_ms").is_some() {
+            config.update_interval_ms = integer(simulation, "update_interval_ms", "simulation")?;
+        }
+        if simulation.get("seed").is_some() {
+            config.seed = Some(integer(simulation, "seed", "simulation")?);
+        }
+        if simulation.get("watchdog_seconds").is_some() {
+            let seconds = integer(simulation, "watchdog_seconds", "simulation")
+                .ok()
+                .filter(|seconds| (2..=600).contains(seconds))
+                .ok_or("simulation.watchdog_seconds must be an integer between 2 and 600")?;
+            config.watchdog_timeout = Duration::from_secs(seconds);
+        }
+        config.simulation_model = match simulation.get("model").map(Value::as_str) {
+            None | Some(Some("process")) => {
+                let mut process = parse_process_model(simulation.get("process").unwrap_or(&Value::Null)).map_err(|e| format!("simulation.process: {}", e))?;
+                process.weather = parse_weather(simulation.get("ambient").unwrap_or(&Value::Null)).map_err(|e| format!("simulation.ambient: {}", e))?;
+                SimulationModel::Process(process)
+            }
+            Some(Some("random")) => SimulationModel::RandomWalk,
+            Some(_) => return Err("simulation.model must be \"process\" or \"random\"".to_string()),
+        };
+
+        let sensors = table("sensors");
+        if sensors.get("temperatures").is_some() {
+            config.temperatures = sensors.array_field("temperatures", |v| v.as_f64().map(|v| v as f32)).map_err(|e| format!("sensors: {}", e))?;
+        }
+        if sensors.get("pressures").is_some() {
+            config.pressures = sensors.array_field("pressures", |v| v.as_f64().map(|v| v as f32)).map_err(|e| format!("sensors: {}", e))?;
+        }
+        if sensors.get("temperature_names").is_some() {
+            config.temperature_names = sensors.array_field("temperature_names", as_name).map_err(|e| format!("sensors: {}", e))?;
+        }
+        if sensors.get("pressure_names").is_some() {
+            config.pressure_names = sensors.array_field("pressure_names", as_name).map_err(|e| format!("sensors: {}", e))?;
+        }
+        config.temperature_tagging = parse_tagging(sensors, "temperature_tags", "temperature_descriptions", "temperature_assets").map_err(|e| format!("sensors: {}", e))?;
+        config.pressure_tagging = parse_tagging(sensors, "pressure_tags", "pressure_descriptions", "pressure_assets").map_err(|e| format!("sensors: {}", e))?;
+
+        let motors = table("motors");
+        if motors.get("setpoints").is_some() {
+            config.motor_setpoints = motors.array_field("setpoints", as_u16).map_err(|e| format!("motors: {}", e))?;
+        }
+        if motors.get("running").is_some() {
+            config.motor_running = motors.array_field("running", Value::as_bool).map_err(|e| format!("motors: {}", e))?;
+        }
+        if motors.get("names").is_some() {
+            config.motor_names = motors.array_field("names", as_name).map_err(|e| format!("motors: {}", e))?;
+        }
+        config.motor_tagging = parse_tagging(motors, "tags", "descriptions", "assets").map_err(|e| format!("motors: {}", e))?;
+        if motors.get("ramp_rate").is_some() {
+            config.motor_ramp_rate = motors.field("ramp_rate").ok().and_then(as_u16).ok_or("motors.ramp_rate must be 0-65535")?;
+        }
+        let positive = |value: &Value| value.as_f64().filter(|value| *value > 0.0 && value.is_finite()).map(|value| value as f32);
+        if motors.get("full_load_amps").is_some() {
+            config.motor_full_load_amps = motors.array_field("full_load_amps", positive).map_err(|e| format!("motors: {}", e))?;
+        }
+        if motors.get("load_factors").is_some() {
+            config.motor_load_factors = motors.array_field("load_factors", positive).map_err(|e| format!("motors: {}", e))?;
+        }
+        if motors.get("overload_seconds").is_some() {
+            let seconds = integer(motors, "overload_seconds", "motors")
+                .ok()
+                .filter(|seconds| (1..=600).contains(seconds))
+                .ok_or("motors.overload_seconds must be an integer between 1 and 600")?;
+            config.motor_overload_time = Duration::from_secs(seconds);
+        }
+
+        let io = table("io");
+        if io.get("inputs").is_some() {
+            config.inputs = io.array_field("inputs", as_name).map_err(|e| format!("io: {}", e))?;
+        }
+        if io.get("input_states").is_some() {
+            config.input_states = io.array_field("input_states", Value::as_bool).map_err(|e| format!("io: {}", e))?;
+        }
+        if io.get("outputs").is_some() {
+            config.outputs = io.array_field("outputs", as_name).map_err(|e| format!("io: {}", e))?;
+        }
+        if io.get("output_states").is_some() {
+            config.output_states = io.array_field("output_states", Value::as_bool).map_err(|e| format!("io: {}", e))?;
+        }
+        if let Value::Object(interlocks) = table("interlocks") {
+            for (key, interlock) in interlocks {
+                config.interlocks.push(parse_interlock(key, interlock).map_err(|e| format!("interlocks.{}: {}", key, e))?);
+            }
+        }
+
+        let control_loop = table("control_loop");
+        if control_loop.get("enabled").is_some() && control_loop.bool_field("enabled").map_err(|e| format!("control_loop: {}", e))? {
+            config.control_loop = Some(parse_control_loop(control_loop).map_err(|e| format!("control_loop: {}", e))?);
+        }
+
+        let alarms = table("alarms");
+        if let Some(limits) = alarms.get("temperature") {
+            config.temperature_limits = parse_limits(limits, config.temperature_limits).map_err(|e| format!("alarms.temperature: {}", e))?;
+            config.temperature_filter = parse_alarm_filter(limits, config.temperature_filter).map_err(|e| format!("alarms.temperature: {}", e))?;
+            (config.temperature_rate, config.temperature_rate_limits) = parse_rate_limit(limits).map_err(|e| format!("alarms.temperature: {}", e))?;
+        }
+        if let Some(limits) = alarms.get("pressure") {
+            config.pressure_limits = parse_limits(limits, config.pressure_limits).map_err(|e| format!("alarms.pressure: {}", e))?;
+            config.pressure_filter = parse_alarm_filter(limits, config.pressure_filter).map_err(|e| format!("alarms.pressure: {}", e))?;
+            (config.pressure_rate, config.pressure_rate_limits) = parse_rate_limit(limits).map_err(|e| format!("alarms.pressure: {}", e))?;
+        }
+        if alarms.get("trip_samples").is_some() {
+            config.trip_samples = integer(alarms, "trip_samples", "alarms")
+                .ok()
+                .filter(|samples| (1..=100).contains(samples))
+                .ok_or("alarms.trip_samples must be an integer between 1 and 100")? as u32;
+        }
+
+        let units = table("units");
+        for (key, kind, choices) in [("temperature", ChannelKind::Temperature, "C or F"), ("pressure", ChannelKind::Pressure, "kPa, psi or bar")] {
+            if units.get(key).is_some() {
+                let unit = Unit::parse(units.str_field(key).map_err(|e| format!("units: {}", e))?)
+                    .ok()
+                    .filter(|unit| unit.kind() == kind)
+                    .ok_or_else(|| format!("units.{} must be {}", key, choices))?;
+                config.units.set(unit);
+            }
+        }
+
+        let display = table("display");
+        if display.get("theme").is_some() {
+            config.theme = Theme::parse(display.str_field("theme").map_err(|e| format!("display: {}", e))?).map_err(|e| format!("display.theme: {}", e))?;
+        }
+        if display.get("language").is_some() {
+            config.language = Language::parse(display.str_field("language").map_err(|e| format!("display: {}", e))?).map_err(|e| format!("display.language: {}", e))?;
+        }
+
+        let source = table("source");
+        let kind = match source_kind {
+            Some(kind) => Some(kind),
+            None => source.get("kind").map(|kind| kind.as_str().ok_or("source.kind must be a string")).transpose()?,
+        };
+        match kind {
+            None | Some("simulation") => {}
+            Some(kind @ ("modbus-tcp" | "modbus-rtu")) => {
+                let modbus = source.get("modbus").ok_or_else(|| format!("source.kind is {} but there is no [source.modbus] table", kind))?;
+                let parsed = if kind == "modbus-tcp" { parse_modbus_tcp(modbus) } else { parse_modbus_rtu(modbus) };
+                config.source = parsed.map_err(|e| format!("source.modbus: {}", e))?;
+            }
+            Some("replay") => {
+                let replay = source.get("replay").ok_or("source.kind is replay but there is no [source.replay] table")?;
+                config.source = parse_replay(replay).map_err(|e| format!("source.replay: {}", e))?;
+            }
+            Some(kind) => return Err(format!("unknown source.kind '{}' (use simulation, modbus-tcp, modbus-rtu or replay)", kind)),
+        }
+        let link_setting = |key: &str, range: std::ops::RangeInclusive<u64>| -> Result<Option<u64>, String> {
+            match source.get(key) {
+                Some(_) => integer(source, key, "source")
+                    .ok()
+                    .filter(|value| range.contains(value))
+                    .map(Some)
+                    .ok_or_else(|| format!("source.{} must be an integer between {} and {}", key, range.start(), range.end())),
+                None => Ok(None),
+            }
+        };
+        if let Some(millis) = link_setting("degraded_latency_ms", 1..=60_000)? {
+            config.link_thresholds.degraded_latency = Duration::from_millis(millis);
+        }
+        if let Some(percent) = link_setting("degraded_loss_percent", 1..=100)? {
+            config.link_thresholds.degraded_loss_percent = percent as u32;
+        }
+        if let Some(failures) = link_setting("offline_failures", 1..=100)? {
+            config.link_thresholds.offline_failures = failures as u32;
+        }
+        if let Some(seconds) = link_setting("swap_grace_secs", 0..=600)? {
+            config.swap_grace = Duration::from_secs(seconds);
+        }
+
+        let mqtt = table("mqtt");
+        if mqtt.get("enabled").is_some() && mqtt.bool_field("enabled").map_err(|e| format!("mqtt: {}", e))? {
+            config.mqtt = Some(parse_mqtt(mqtt, &config.system_id).map_err(|e| format!("mqtt: {}", e))?);
+        }
+
+        let api = table("api");
+        if api.get("enabled").is_some() && api.bool_field("enabled").map_err(|e| format!("api: {}", e))? {
+            let bind = match api.get("bind") {
+                Some(_) => api.str_field("bind").map_err(|e| format!("api: {}", e))?.to_string(),
+                None => "127.0.0.1:8080".to_string(),
+            };
+            let token = match api.get("token") {
+                Some(_) => Some(api.str_field("token").map_err(|e| format!("api: {}", e))?.to_string()),
+                None => None,
+            };
+            if token.as_ref().is_some_and(|token| token.len() < 16) {
+                return Err("api.token must be at least 16 characters".to_string());
+            }
+            let mut clients = Vec::new();
+            if let Some(Value::Object(tables)) = api.get("clients") {
+                for (name, client) in tables {
+                    clients.push(parse_api_client(name, client).map_err(|e| format!("api.clients.{}: {}", name, e))?);
+                }
+            }
+            let tokens: Vec<&String> = token.iter().chain(clients.iter().map(|client| &client.token)).collect();
+            if tokens.iter().enumerate().any(|(i, token)| tokens[..i].contains(token)) {
+                return Err("api: every client needs a token of its own, different from api.token".to_string());
+            }
+            config.api = Some(ApiConfig { bind, token, clients });
+        }
+
+        let historian = table("historian");
+        if historian.get("enabled").is_some() && historian.bool_field("enabled").map_err(|e| format!("historian: {}", e))? {
+            let directory = match historian.get("directory") {
+                Some(_) => historian.str_field("directory").map_err(|e| format!("historian: {}", e))?,
+                None => "history",
+            };
+            let retention_days = match historian.get("retention_days") {
+                Some(_) => integer(historian, "retention_days", "historian")?,
+                None => 7,
+            };
+            if !(1..=3650).contains(&retention_days) {
+                return Err("historian.retention_days must be between 1 and 3650".to_string());
+            }
+            config.historian = Some(HistorianConfig { directory: beside_executable(directory), retention_days, change_of_value: parse_change_of_value(historian)? });
+        }
+
+        let notifications = table("notifications");
+        if notifications.get("enabled").is_some() && notifications.bool_field("enabled").map_err(|e| format!("notifications: {}", e))? {
+            config.notifications = Some(parse_notifications(notifications)?);
+        }
+
+        let annunciator = table("annunciator");
+        if annunciator.get("enabled").is_some() && !annunciator.bool_field("enabled").map_err(|e| format!("annunciator: {}", e))? {
+            config.annunciator = None;
+        }
+        if let Some(horn) = &mut config.annunciator {
+            for (key, tone) in [("alarm_tone_hz", &mut horn.alarm_tone_hz), ("warning_tone_hz", &mut horn.warning_tone_hz)] {
+                if annunciator.get(key).is_some() {
+                    *tone = integer(annunciator, key, "annunciator")
+                        .ok()
+                        .filter(|hz| (37..=32767).contains(hz))
+                        .ok_or_else(|| format!("annunciator.{} must be an integer between 37 and 32767", key))? as u32;
+                }
+            }
+        }
+
+        let audit = table("audit");
+        if audit.get("file").is_some() {
+            config.audit_file = beside_executable(audit.str_field("file").map_err(|e| format!("audit: {}", e))?);
+        }
+
+        let logging = table("logging");
+        if logging.get("level").is_some() {
+            config.log_level = LogLevel::parse(logging.str_field("level").map_err(|e| format!("logging: {}", e))?).map_err(|e| format!("logging.level: {}", e))?;
+        }
+        if logging.get("enabled").is_some() && !logging.bool_field("enabled").map_err(|e| format!("logging: {}", e))? {
+            config.log_file = None;
+        }
+        if let Some(log_file) = &mut config.log_file {
+            if logging.get("directory").is_some() {
+                log_file.directory = beside_executable(logging.str_field("directory").map_err(|e| format!("logging: {}", e))?);
+            }
+            if logging.get("max_size_kb").is_some() {
+                let kilobytes = integer(logging, "max_size_kb", "logging")
+                    .ok()
+                    .filter(|kilobytes| (1..=1_048_576).contains(kilobytes))
+                    .ok_or("logging.max_size_kb must be an integer between 1 and 1048576")?;
+                log_file.max_bytes = kilobytes * 1024;
+            }
+            if logging.get("files").is_some() {
+                log_file.keep_files = integer(logging, "files", "logging")
+                    .ok()
+                    .filter(|files| (1..=100).contains(files))
+                    .ok_or("logging.files must be an integer between 1 and 100")? as usize;
+            }
+        }
+
+        let devices = table("devices");
+        if devices.get("files").is_some() {
+            config.device_files = devices.array_field("files", |file| file.as_str().map(beside_executable)).map_err(|e| format!("devices: {}", e))?;
+        }
+
+        let maintenance = table("maintenance");
+        if maintenance.get("file").is_some() {
+            config.maintenance_file = beside_executable(maintenance.str_field("file").map_err(|e| format!("maintenance: {}", e))?);
+        }
+        if maintenance.get("service_hours").is_some() {
+            let hours = integer(maintenance, "service_hours", "maintenance")
+                .ok()
+                .filter(|hours| (1..=100_000).contains(hours))
+                .ok_or("maintenance.service_hours must be an integer between 1 and 100000")?;
+            config.service_interval = Duration::from_secs(hours * 3600);
+        }
+
+        let energy = table("energy");
+        if energy.get("file").is_some() {
+            config.energy_file = beside_executable(energy.str_field("file").map_err(|e| format!("energy: {}", e))?);
+        }
+        config.supply = parse_supply(energy).map_err(|e| format!("energy: {}", e))?;
+        if energy.get("price_per_kwh").is_some() {
+            config.energy_price = energy.f64_field("price_per_kwh")
+                .ok()
+                .filter(|price| price.is_finite() && (0.0..=100.0).contains(price))
+                .ok_or("energy.price_per_kwh must be a number between 0 and 100")?;
+        }
+        if energy.get("currency").is_some() {
+            config.energy_currency = energy.str_field("currency")
+                .ok()
+                .filter(|currency| (1..=8).contains(&currency.chars().count()))
+                .ok_or("energy.currency must be 1-8 characters")?
+                .to_string();
+        }
+
+        let vibration = table("vibration");
+        if vibration.get("alarm_limit").is_some() {
+            config.vibration_limit = vibration.f64_field("alarm_limit")
+                .ok()
+                .filter(|limit| (0.1..=100.0).contains(limit))
+                .ok_or("vibration.alarm_limit must be a number between 0.1 and 100")? as f32;
+        }
+
+        let settings = table("settings");
+        if settings.get("file").is_some() {
+            config.settings_file = beside_executable(settings.str_field("file").map_err(|e| format!("settings: {}", e))?);
+        }
+
+        let recipes = table("recipes");
+        if recipes.get("file").is_some() {
+            config.recipes_file = beside_executable(recipes.str_field("file").map_err(|e| format!("recipes: {}", e))?);
+        }
+
+        let calibration = table("calibration");
+        if calibration.get("file").is_some() {
+            config.calibration_file = beside_executable(calibration.str_field("file").map_err(|e| format!("calibration: {}", e))?);
+        }
+        for (key, bound) in [("temperature_max_offset", &mut config.temperature_max_offset), ("pressure_max_offset", &mut config.pressure_max_offset)] {
+            if calibration.get(key).is_some() {
+                *bound = calibration.f64_field(key)
+                    .ok()
+                    .filter(|offset| *offset > 0.0)
+                    .ok_or_else(|| format!("calibration.{} must be a positive number", key))? as f32;
+            }
+        }
+
+        let reports = table("reports");
+        if reports.get("directory").is_some() {
+            config.reports_directory = beside_executable(reports.str_field("directory").map_err(|e| format!("reports: {}", e))?);
+        }
+        let trips = table("trips");
+        if trips.get("directory").is_some() {
+            config.trips_directory = beside_executable(trips.str_field("directory").map_err(|e| format!("trips: {}", e))?);
+        }
+
+        let diagnostics = table("diagnostics");
+        if diagnostics.get("interval_hours").is_some() {
+            let hours = integer(diagnostics, "interval_hours", "diagnostics")
+                .ok()
+                .filter(|hours| *hours <= 720)
+                .ok_or("diagnostics.interval_hours must be an integer between 0 (off) and 720")?;
+            config.diagnostic_interval = (hours > 0).then(|| Duration::from_secs(hours * 3600));
+        }
+
+        let statistics = table("statistics");
+        if statistics.get("shift_changes").is_some() {
+            config.shift_changes = statistics.array_field("shift_changes", |time| time.as_str().and_then(parse_time_of_day))
+                .map_err(|_| "statistics.shift_changes must be a list of times like \"06:00\"")?;
+        }
+
+        let compare = table("compare");
+        let tolerance = |key: &str, default: f32| -> Result<f32, String> {
+            match compare.get(key) {
+                Some(_) => compare.f64_field(key)
+                    .ok()
+                    .filter(|tolerance| tolerance.is_finite() && *tolerance >= 0.0)
+                    .map(|tolerance| tolerance as f32)
+                    .ok_or_else(|| format!("compare.{} must be a non-negative number", key)),
+                None => Ok(default),
+            }
+        };
+        config.compare_tolerances.temperature = tolerance("temperature_tolerance", config.compare_tolerances.temperature)?;
+        config.compare_tolerances.pressure = tolerance("pressure_tolerance", config.compare_tolerances.pressure)?;
+        if compare.get("setpoint_tolerance").is_some() {
+            config.compare_tolerances.setpoint = compare.field("setpoint_tolerance").ok().and_then(as_u16).ok_or("compare.setpoint_tolerance must be 0-65535")?;
+        }
+
+        if let Value::Object(users) = table("users") {
+            for (name, user) in users {
+                config.accounts.push(parse_account(name, user).map_err(|e| format!("users.{}: {}", name, e))?);
+            }
+        }
+        let login = table("login");
+        if login.get("timeout_minutes").is_some() {
+            let minutes = integer(login, "timeout_minutes", "login")
+                .ok()
+                .filter(|minutes| (1..=1440).contains(minutes))
+                .ok_or("login.timeout_minutes must be an integer between 1 and 1440")?;
+            config.session_timeout = Duration::from_secs(minutes * 60);
+        }
+
+        config.validate()?;
+        config.resolve_interlocks()?;
+        Ok(config)
+    }
+
+    // Channel names as the sensor data will have them, with their kinds
+    fn channel_names(&self) -> Vec<(String, ChannelKind)> {
+        let mut names = Vec::new();
+        for (kind, count, configured) in [
+            (ChannelKind::Temperature, self.temperatures.len(), &self.temperature_names),
+            (ChannelKind::Pressure, self.pressures.len(), &self.pressure_names),
+        ] {
+            names.extend((0..count).map(|i| (configured.get(i).cloned().unwrap_or_else(|| kind.default_name(i)), kind)));
+        }
+        names
+    }
+
+    fn resolve_interlocks(&mut self) -> Result<(), String> {
+        let channels = self.channel_names();
+        let points: Vec<&String> = self.inputs.iter().chain(&self.outputs).collect();
+        let motor_count = self.motor_setpoints.len();
+        for interlock in &mut self.interlocks {
+            if let Some(motor) = interlock.motors.iter().find(|motor| **motor >= motor_count) {
+                return Err(format!("interlocks.{}: no motor {} (valid motors are 1-{})", interlock.name, motor + 1, motor_count));
+            }
+            interlock.condition.resolve(&channels, &points).map_err(|e| format!("interlocks.{}: {}", interlock.name, e))?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.temperatures.is_empty() || self.pressures.is_empty() || self.motor_setpoints.is_empty() {
+            return Err("at least one temperature sensor, pressure gauge and motor is required".to_string());
+        }
+        if self.motor_running.len() != self.motor_setpoints.len() {
+            return Err(format!(
+                "motors.running has {} entries but motors.setpoints has {}",
+                self.motor_running.len(),
+                self.motor_setpoints.len()
+            ));
+        }
+        for (entries, count, key) in [
+            (self.temperature_names.len(), self.temperatures.len(), "sensors.temperature_names"),
+            (self.pressure_names.len(), self.pressures.len(), "sensors.pressure_names"),
+            (self.motor_names.len(), self.motor_setpoints.len(), "motors.names"),
+            (self.temperature_tagging.len(), self.temperatures.len(), "sensors.temperature_tags, temperature_descriptions and temperature_assets"),
+            (self.pressure_tagging.len(), self.pressures.len(), "sensors.pressure_tags, pressure_descriptions and pressure_assets"),
+            (self.motor_tagging.len(), self.motor_setpoints.len(), "motors.tags, descriptions and assets"),
+            (self.motor_full_load_amps.len(), self.motor_setpoints.len(), "motors.full_load_amps"),
+            (self.motor_load_factors.len(), self.motor_setpoints.len(), "motors.load_factors"),
+            (self.input_states.len(), self.inputs.len(), "io.input_states"),
+            (self.output_states.len(), self.outputs.len(), "io.output_states"),
+        ] {
+            if entries > count {
+                return Err(format!("{} has {} entries but there are only {} channels", key, entries, count));
+            }
+        }
+        // Alarms, trips, interlocks and the audit trail refer to channels by name
+        let interlock_names: Vec<&String> = self.interlocks.iter().map(|interlock| &interlock.name).collect();
+        let mut all_names: Vec<&String> = self.temperature_names.iter()
+            .chain(&self.pressure_names)
+            .chain(&self.motor_names)
+            .chain(&self.inputs)
+            .chain(&self.outputs)
+            .chain(interlock_names)
+            .collect();
+        all_names.sort();
+        if let Some(pair) = all_names.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("channel name '{}' is used more than once", pair[0]));
+        }
+        // Each list of tags lines up with the channels or motors of its kind
+        let channels = self.channel_names();
+        let named = |kind: ChannelKind| channels.iter().filter(move |(_, of)| *of == kind).map(|(name, _)| name.clone());
+        let motors = (0..self.motor_setpoints.len()).map(|i| self.motor_names.get(i).cloned().unwrap_or_else(|| format!("Motor {}", i + 1)));
+        let tagged: Vec<(&Tagging, String)> = self.temperature_tagging.iter().zip(named(ChannelKind::Temperature))
+            .chain(self.pressure_tagging.iter().zip(named(ChannelKind::Pressure)))
+            .chain(self.motor_tagging.iter().zip(motors))
+            .collect();
+        let duplicates = sensors::duplicate_tags(tagged.iter().map(|(tagging, name)| (tagging.tag.as_str(), name.as_str())));
+        if !duplicates.is_empty() {
+            return Err(format!("tags used more than once: {}", duplicates.join("; ")));
+        }
+        let duplicates = sensors::duplicate_tags(tagged.iter().map(|(tagging, name)| (tagging.asset.as_str(), name.as_str())));
+        if !duplicates.is_empty() {
+            return Err(format!("asset IDs used more than once: {}", duplicates.join("; ")));
+        }
+        simulation::check_update_interval(self.update_interval_ms).map_err(|e| format!("simulation.update_interval_ms: {}", e))?;
+        self.temperature_limits.validate().map_err(|e| format!("alarms.temperature: {}", e))?;
+        self.pressure_limits.validate().map_err(|e| format!("alarms.pressure: {}", e))?;
+        let map = match &self.source {
+            SourceConfig::Simulation | SourceConfig::Replay(_) => None,
+            SourceConfig::ModbusTcp(modbus) => Some(&modbus.map),
+            SourceConfig::ModbusRtu(modbus) => Some(&modbus.map),
+        };
+        if let Some(map) = map {
+            map.validate(self.temperatures.len(), self.pressures.len(), self.motor_setpoints.len())
+                .map_err(|e| format!("source.modbus: {}", e))?;
+        }
+        if let SimulationModel::Process(process) = &self.simulation_model {
+            let motors = self.motor_setpoints.len();
+            if let Some(motor) = process.heat_motors.iter().flatten().chain(&process.pump_motor).find(|motor| **motor >= motors) {
+                return Err(format!("simulation.process: no motor {} (valid motors are 1-{})", motor + 1, motors));
+            }
+        }
+        if let Some(control) = &self.control_loop {
+            if self.source != SourceConfig::Simulation {
+                return Err("control_loop needs source.kind = \"simulation\"; a PLC runs its own loops".to_string());
+            }
+            if control.temperature >= self.temperatures.len() {
+                return Err(format!("control_loop.temperature: no sensor {} (valid sensors are 1-{})", control.temperature + 1, self.temperatures.len()));
+            }
+            if control.motor >= self.motor_setpoints.len() {
+                return Err(format!("control_loop.motor: no motor {} (valid motors are 1-{})", control.motor + 1, self.motor_setpoints.len()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// HH:MM as time since midnight
+pub fn parse_time_of_day(text: &str) -> Option<Duration> {
+    let (hours, minutes) = text.split_once(':')?;
+    let (hours, minutes): (u64, u64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60 && text.len() == 5).then(|| Duration::from_secs(hours * 3600 + minutes * 60))
+}
+
+// Rewrites a file for an older schema version as the current one, keeping the original
+// beside it; if that can't be done the upgraded settings apply until exit all the same
+fn upgrade_file(path: &Path, original: &str, document: &Value, version: u64) -> String {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    let backup = PathBuf::from(backup);
+    let temporary = path.with_extension("tmp");
+    let written = fs::write(&backup, original)
+        .and_then(|()| fs::write(&temporary, toml::write(document)))
+        .and_then(|()| fs::rename(&temporary, path));
+    match written {
+        Ok(()) => format!(
+            "{} upgraded from schema version {} to {} - the original is kept as {} (its comments are not carried over)",
+            path.display(), version, schema::CURRENT_VERSION, backup.display(),
+        ),
+        Err(e) => format!(
+            "{} is for schema version {} and was read as version {}, but could not be rewritten ({}) - it will be upgraded again at the next start",
+            path.display(), version, schema::CURRENT_VERSION, e,
+        ),
+    }
+}
+
+// Relative paths are taken from the executable's directory, like forlenza.toml itself
+fn beside_executable(path: &str) -> PathBuf {
+    if Path::new(path).is_absolute() {
+        return PathBuf::from(path);
+    }
+    env::current_exe()
+        .map(|exe| exe.with_file_name(path))
+        .unwrap_or_else(|_| PathBuf::from(path))
+}
+
+// A channel name: not empty, and short enough to line up in the sensor panel
+fn as_name(value: &Value) -> Option<String> {
+    value.as_str()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && name.chars().count() <= 32)
+        .map(str::to_string)
+}
+
+fn as_u16(value: &Value) -> Option<u16> {
+    value.as_f64()
+        .filter(|number| number.fract() == 0.0 && (0.0..=u16::MAX as f64).contains(number))
+        .map(|number| number as u16)
+}
+
+fn integer(table: &Value, key: &str, table_name: &str) -> Result<u64, String> {
+    table.f64_field(key)
+        .ok()
+        .filter(|number| number.fract() == 0.0 && *number >= 0.0)
+        .map(|number| number as u64)
+        .ok_or_else(|| format!("{}.{} must be a non-negative integer", table_name, key))
+}
+
+fn parse_limits(table: &Value, defaults: Limits) -> Result<Limits, String> {
+    let field = |key: &str, default: f32| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key).map(|value| value as f32),
+            None => Ok(default),
+        }
+    };
+    let trip = |key: &str, default: Option<f32>| -> Result<Option<f32>, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key).map(|value| Some(value as f32)),
+            None => Ok(default),
+        }
+    };
+    Ok(Limits::new(
+        field("low_alarm", defaults.low_alarm)?,
+        field("low_warning", defaults.low_warning)?,
+        field("high_warning", defaults.high_warning)?,
+        field("high_alarm", defaults.high_alarm)?,
+    ).with_trips(trip("low_trip", defaults.low_trip)?, trip("high_trip", defaults.high_trip)?))
+}
+
+fn parse_alarm_filter(table: &Value, defaults: AlarmFilter) -> Result<AlarmFilter, String> {
+    let samples = |key: &str, default: u32| -> Result<u32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .filter(|samples| samples.fract() == 0.0 && (1.0..=100.0).contains(samples))
+                .map(|samples| samples as u32)
+                .ok_or_else(|| format!("{} must be an integer between 1 and 100", key)),
+            None => Ok(default),
+        }
+    };
+    let filter = AlarmFilter {
+        deadband: match table.get("deadband") {
+            Some(_) => table.f64_field("deadband")? as f32,
+            None => defaults.deadband,
+        },
+        raise_samples: samples("raise_samples", defaults.raise_samples)?,
+        clear_samples: samples("clear_samples", defaults.clear_samples)?,
+    };
+    filter.validate()?;
+    Ok(filter)
+}
+
+// None unless a dead-band is set
+fn parse_change_of_value(table: &Value) -> Result<Option<ChangeOfValue>, String> {
+    let deadband = |value: &Value| value.as_f64().filter(|deadband| deadband.is_finite() && *deadband >= 0.0).map(|deadband| deadband as f32);
+    let single = |key: &str| match table.get(key) {
+        Some(value) => deadband(value).map(Some).ok_or_else(|| format!("historian.{} must be a number of at least 0", key)),
+        None => Ok(None),
+    };
+    let channels = |key: &str| match table.get(key) {
+        Some(_) => table.array_field(key, deadband).map_err(|e| format!("historian: {}", e)),
+        None => Ok(Vec::new()),
+    };
+    let (temperature, pressure) = (single("temperature_deadband")?, single("pressure_deadband")?);
+    let (temperatures, pressures) = (channels("temperature_deadbands")?, channels("pressure_deadbands")?);
+    let speed = single("speed_deadband")?;
+    let heartbeat = match table.get("heartbeat_secs") {
+        Some(_) => integer(table, "heartbeat_secs", "historian")?,
+        None => 30,
+    };
+    if !(1..=MAX_HEARTBEAT.as_secs()).contains(&heartbeat) {
+        return Err(format!("historian.heartbeat_secs must be between 1 and {}", MAX_HEARTBEAT.as_secs()));
+    }
+    if temperature.is_none() && pressure.is_none() && temperatures.is_empty() && pressures.is_empty() && speed.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(ChangeOfValue { temperature, pressure, temperatures, pressures, speed, heartbeat: Duration::from_secs(heartbeat) }))
+}
+
+// Limits are per second, and a channel's limit of 0 turns its alarm off
+fn parse_rate_limit(table: &Value) -> Result<(RateLimit, Vec<Option<f32>>), String> {
+    let defaults = RateLimit::default();
+    let limit = |value: &Value| value.as_f64().filter(|limit| limit.is_finite() && *limit >= 0.0).map(|limit| (limit > 0.0).then_some(limit as f32));
+    let rate = RateLimit {
+        limit: match table.get("rate_limit") {
+            Some(value) => limit(value).ok_or("rate_limit must be a number of at least 0")?,
+            None => defaults.limit,
+        },
+        window: match table.get("rate_window") {
+            Some(_) => table.f64_field("rate_window")
+                .ok()
+                .filter(|seconds| (1.0..=600.0).contains(seconds))
+                .map(Duration::from_secs_f64)
+                .ok_or("rate_window must be between 1 and 600 seconds")?,
+            None => defaults.window,
+        },
+        severity: match table.get("rate_severity").map(Value::as_str) {
+            None => defaults.severity,
+            Some(Some("warning")) => AlarmLevel::Warning,
+            Some(Some("alarm")) => AlarmLevel::Alarm,
+            Some(_) => return Err("rate_severity must be \"warning\" or \"alarm\"".to_string()),
+        },
+    };
+    let limits = match table.get("rate_limits") {
+        Some(_) => table.array_field("rate_limits", limit)?,
+        None => Vec::new(),
+    };
+    Ok((rate, limits))
+}
+
+fn parse_notifications(table: &Value) -> Result<NotifyConfig, String> {
+    let webhook = match table.get("webhook_url") {
+        Some(_) => Some(WebhookConfig::parse_url(table.str_field("webhook_url").map_err(|e| format!("notifications: {}", e))?).map_err(|e| format!("notifications.webhook_url: {}", e))?),
+        None => None,
+    };
+    let email = match table.get("email") {
+        Some(email) => Some(parse_email(email).map_err(|e| format!("notifications.email: {}", e))?),
+        None => None,
+    };
+    if webhook.is_none() && email.is_none() {
+        return Err("notifications are enabled but there is no webhook_url or [notifications.email]".to_string());
+    }
+    let repeat_minutes = match table.get("repeat_minutes") {
+        Some(_) => integer(table, "repeat_minutes", "notifications")?,
+        None => 15,
+    };
+    if !(1..=1440).contains(&repeat_minutes) {
+        return Err("notifications.repeat_minutes must be between 1 and 1440".to_string());
+    }
+    let retries = match table.get("retries") {
+        Some(_) => integer(table, "retries", "notifications")?,
+        None => 3,
+    };
+    if retries > 10 {
+        return Err("notifications.retries must be between 0 and 10".to_string());
+    }
+    Ok(NotifyConfig { webhook, email, repeat_interval: Duration::from_secs(repeat_minutes * 60), retries: retries as u32 })
+}
+
+fn parse_email(table: &Value) -> Result<EmailConfig, String> {
+    let server = table.str_field("server")?;
+    let (host, port) = match server.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid server port '{}'", port))?),
+        None => (server, 25),
+    };
+    if host.is_empty() {
+        return Err(format!("invalid server address '{}'", server));
+    }
+    let to = table.array_field("to", |to| to.as_str().map(str::to_string))?;
+    if to.is_empty() {
+        return Err("to must list at least one address".to_string());
+    }
+    let text = |key: &str| -> Result<Option<String>, String> {
+        match table.get(key) {
+            Some(_) => table.str_field(key).map(|value| Some(value.to_string())),
+            None => Ok(None),
+        }
+    };
+    Ok(EmailConfig { host: host.to_string(), port, from: table.str_field("from")?.to_string(), to, username: text("username")?, password: text("password")? })
+}
+
+fn parse_account(name: &str, table: &Value) -> Result<Account, String> {
+    let role = Role::parse(table.str_field("role")?)?;
+    let pin_hash = table.str_field("pin_hash")?;
+    if !auth::is_pin_hash(pin_hash) {
+        return Err("pin_hash must be the 40 hex digits printed by --hash-pin".to_string());
+    }
+    Ok(Account { name: name.to_string(), role, pin_hash: pin_hash.to_string() })
+}
+
+fn parse_api_client(name: &str, table: &Value) -> Result<ApiClient, String> {
+    let role = Role::parse(table.str_field("role")?)?;
+    let token = table.str_field("token")?;
+    if token.len() < 16 {
+        return Err("token must be at least 16 characters".to_string());
+    }
+    Ok(ApiClient { name: name.to_string(), role, token: token.to_string() })
+}
+
+// The table key names the interlock unless it has a name of its own. Motors are
+// numbered from 1 in the file; none listed protects them all.
+fn parse_interlock(key: &str, table: &Value) -> Result<Interlock, String> {
+    let name = match table.get("name") {
+        Some(value) => as_name(value).ok_or("name must be 1-32 characters")?,
+        None => key.to_string(),
+    };
+    let condition = Condition::parse(table.str_field("condition")?)?;
+    let motors = match table.get("motors") {
+        Some(_) => table.array_field("motors", |value| as_u16(value).filter(|motor| *motor > 0).map(|motor| motor as usize - 1))?,
+        None => Vec::new(),
+    };
+    Ok(Interlock::new(&name, condition, motors))
+}
+
+// Sensor and motor are numbered from 1 in the file. The fan cools, so the loop is direct
+// acting: a rising temperature speeds it up. By default it stays within rated speed,
+// where a motor at the default load can run without tripping its overload.
+fn parse_control_loop(table: &Value) -> Result<ControlLoop, String> {
+    let number = |key: &str, default: f32| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .map(|value| value as f32)
+                .ok_or_else(|| format!("{} must be a non-negative number", key)),
+            None => Ok(default),
+        }
+    };
+    let numbered = |key: &str| -> Result<usize, String> {
+        match table.get(key) {
+            Some(value) => as_u16(value).filter(|number| *number > 0).map(|number| number as usize - 1).ok_or_else(|| format!("{} must be a number from 1", key)),
+            None => Ok(0),
+        }
+    };
+    let output_limits = (number("output_min", 0.0)?, number("output_max", RATED_MOTOR_SPEED as f32)?);
+    if output_limits.0 >= output_limits.1 || output_limits.1 > MAX_MOTOR_SETPOINT as f32 {
+        return Err(format!("output_min must be below output_max, which can't exceed {} RPM", MAX_MOTOR_SETPOINT));
+    }
+    let mut pid = PidController::new(number("kp", 400.0)?, number("ki", 20.0)?, number("kd", 0.0)?, number("setpoint", 24.0)?, output_limits);
+    pid.direct = true;
+    if table.get("mode").is_some() {
+        pid.mode = LoopMode::parse(table.str_field("mode")?)?;
+    }
+    Ok(ControlLoop { temperature: numbered("temperature")?, motor: numbered("motor")?, pid })
+}
+
+// Lists of tags, descriptions and asset IDs, any of which may be missing or shorter than
+// the others; an empty tag or asset ID is none
+fn parse_tagging(table: &Value, tags_key: &str, descriptions_key: &str, assets_key: &str) -> Result<Vec<Tagging>, String> {
+    let text = |key: &str, check: fn(&str) -> Result<(), String>| -> Result<Vec<String>, String> {
+        match table.get(key) {
+            Some(_) => {
+                let texts = table.array_field(key, |value| value.as_str().map(|text| text.trim().to_string()))?;
+                texts.iter().try_for_each(|text| check(text).map_err(|e| format!("{}: {}", key, e)))?;
+                Ok(texts)
+            }
+            None => Ok(Vec::new()),
+        }
+    };
+    let tags = text(tags_key, Tagging::check_tag)?;
+    let descriptions = text(descriptions_key, Tagging::check_description)?;
+    let assets = text(assets_key, Tagging::check_asset)?;
+    Ok((0..tags.len().max(descriptions.len()).max(assets.len()))
+        .map(|i| Tagging {
+            tag: tags.get(i).cloned().unwrap_or_default(),
+            description: descriptions.get(i).cloned().unwrap_or_default(),
+            asset: assets.get(i).cloned().unwrap_or_default(),
+        })
+        .collect())
+}
+
+// Sensors and motors are numbered from 1 in the file, and a heating motor of 0 is none;
+// time constants are in seconds
+fn parse_process_model(table: &Value) -> Result<ProcessModel, String> {
+    let defaults = ProcessModel::default();
+    let number = |key: &str, default: f32, minimum: f32| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .map(|value| value as f32)
+                .filter(|value| value.is_finite() && *value >= minimum)
+                .ok_or_else(|| format!("{} must be a number of at least {}", key, minimum)),
+            None => Ok(default),
+        }
+    };
+    let time_constant = |key: &str, default: Duration| -> Result<Duration, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .filter(|seconds| (0.1..=3600.0).contains(seconds))
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("{} must be between 0.1 and 3600 seconds", key)),
+            None => Ok(default),
+        }
+    };
+    let heat_motors = match table.get("heat_motors") {
+        Some(_) => table.array_field("heat_motors", |value| as_u16(value).map(|motor| (motor as usize).checked_sub(1)))?,
+        None => Vec::new(),
+    };
+    let pump_motor = match table.get("pump_motor") {
+        Some(value) => Some(as_u16(value).filter(|motor| *motor > 0).map(|motor| motor as usize - 1).ok_or("pump_motor must be a motor number from 1")?),
+        None => None,
+    };
+    Ok(ProcessModel {
+        ambient: number("ambient", defaults.ambient, -50.0)?,
+        // From its own table
+        weather: Weather::default(),
+        heat_rise: number("heat_rise", defaults.heat_rise, 0.0)?,
+        heat_motors,
+        temperature_time_constant: time_constant("temperature_time_constant", defaults.temperature_time_constant)?,
+        temperature_noise: number("temperature_noise", defaults.temperature_noise, 0.0)?,
+        pump_motor,
+        static_pressure: number("static_pressure", defaults.static_pressure, 0.0)?,
+        pump_head: number("pump_head", defaults.pump_head, 0.0)?,
+        line_loss: number("line_loss", defaults.line_loss, 0.0)?,
+        pressure_time_constant: time_constant("pressure_time_constant", defaults.pressure_time_constant)?,
+        pressure_noise: number("pressure_noise", defaults.pressure_noise, 0.0)?,
+    })
+}
+
+// The day's length is in hours
+fn parse_weather(table: &Value) -> Result<Weather, String> {
+    let defaults = Weather::default();
+    let number = |key: &str, default: f32, range: std::ops::RangeInclusive<f32>| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .map(|value| value as f32)
+                .filter(|value| range.contains(value))
+                .ok_or_else(|| format!("{} must be a number between {} and {}", key, range.start(), range.end())),
+            None => Ok(default),
+        }
+    };
+    Ok(Weather {
+        daily_swing: number("daily_swing", defaults.daily_swing, 0.0..=40.0)?,
+        day: Duration::from_secs_f32(number("day_hours", defaults.day.as_secs_f32() / 3600.0, 0.1..=240.0)? * 3600.0),
+        barometer: number("barometer", defaults.barometer, 50.0..=150.0)?,
+        barometer_swing: number("barometer_swing", defaults.barometer_swing, 0.0..=20.0)?,
+    })
+}
+
+// Motors are taken as three-phase, drawing their current at the line voltage
+fn parse_supply(table: &Value) -> Result<Supply, String> {
+    let defaults = Supply::default();
+    let number = |key: &str, default: f32, range: std::ops::RangeInclusive<f32>| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .map(|value| value as f32)
+                .filter(|value| range.contains(value))
+                .ok_or_else(|| format!("{} must be a number between {} and {}", key, range.start(), range.end())),
+            None => Ok(default),
+        }
+    };
+    Ok(Supply {
+        voltage: number("voltage", defaults.voltage, 100.0..=15_000.0)?,
+        power_factor: number("power_factor", defaults.power_factor, 0.1..=1.0)?,
+    })
+}
+
+fn bounded(table: &Value, key: &str, default: u64, range: std::ops::RangeInclusive<u64>) -> Result<u64, String> {
+    match table.get(key) {
+        Some(_) => integer(table, key, "source.modbus")
+            .ok()
+            .filter(|value| range.contains(value))
+            .ok_or_else(|| format!("{} must be an integer between {} and {}", key, range.start(), range.end())),
+        None => Ok(default),
+    }
+}
+
+fn parse_register_map(table: &Value) -> Result<RegisterMap, String> {
+    let scale = |key: &str| -> Result<f32, String> {
+        match table.get(key) {
+            Some(_) => table.f64_field(key)
+                .ok()
+                .filter(|scale| scale.is_finite() && *scale != 0.0)
+                .map(|scale| scale as f32)
+                .ok_or_else(|| format!("{} must be a non-zero number", key)),
+            None => Ok(1.0),
+        }
+    };
+    Ok(RegisterMap {
+        temperature_registers: table.array_field("temperature_registers", as_u16)?,
+        temperature_scale: scale("temperature_scale")?,
+        pressure_registers: table.array_field("pressure_registers", as_u16)?,
+        pressure_scale: scale("pressure_scale")?,
+        motor_speed_registers: table.array_field("motor_speed_registers", as_u16)?,
+        motor_coils: table.array_field("motor_coils", as_u16)?,
+        motor_setpoint_registers: match table.get("motor_setpoint_registers") {
+            Some(_) => table.array_field("motor_setpoint_registers", as_u16)?,
+            None => Vec::new(),
+        },
+    })
+}
+
+fn parse_modbus_tcp(table: &Value) -> Result<SourceConfig, String> {
+    Ok(SourceConfig::ModbusTcp(ModbusTcpConfig {
+        host: table.str_field("host")?.to_string(),
+        port: bounded(table, "port", 502, 1..=u16::MAX as u64)? as u16,
+        unit_id: bounded(table, "unit_id", 1, 0..=u8::MAX as u64)? as u8,
+        timeout_ms: bounded(table, "timeout_ms", 1000, 1..=60_000)?,
+        map: parse_register_map(table)?,
+    }))
+}
+
+fn parse_modbus_rtu(table: &Value) -> Result<SourceConfig, String> {
+    let baud_rate = bounded(table, "baud_rate", 9600, 0..=u32::MAX as u64)? as u32;
+    if !serial::BAUD_RATES.contains(&baud_rate) {
+        return Err(format!("baud_rate must be one of {:?}", serial::BAUD_RATES));
+    }
+    let parity = match table.get("parity") {
+        Some(_) => Parity::parse(table.str_field("parity")?)?,
+        None => Parity::Even,
+    };
+    Ok(SourceConfig::ModbusRtu(ModbusRtuConfig {
+        serial_port: table.str_field("serial_port")?.to_string(),
+        baud_rate,
+        parity,
+        // 0 is broadcast, which never gets a response
+        unit_id: bounded(table, "unit_id", 1, 1..=247)? as u8,
+        timeout_ms: bounded(table, "timeout_ms", 1000, 1..=25_500)?,
+        map: parse_register_map(table)?,
+    }))
+}
+
+fn parse_replay(table: &Value) -> Result<SourceConfig, String> {
+    Ok(SourceConfig::Replay(ReplayConfig {
+        file: beside_executable(table.str_field("file")?),
+        speed: match table.get("speed") {
+            Some(_) => ReplaySpeed::parse(table.str_field("speed")?)?,
+            None => ReplaySpeed::Times(1),
+        },
+    }))
+}
+
+// The table a --source kind reads its settings from
+fn source_table(kind: &str) -> &'static str {
+    if kind == "replay" {
+        "replay"
+    } else {
+        "modbus"
+    }
+}
+
+fn parse_mqtt(table: &Value, system_id: &str) -> Result<MqttConfig, String> {
+    let string = |key: &str| -> Result<Option<String>, String> {
+        table.get(key).map(|_| table.str_field(key).map(str::to_string)).transpose()
+    };
+    let (host, port) = MqttConfig::parse_broker(table.str_field("broker")?)?;
+    // Topics default to forlenza/<system id>/...
+    let prefix = format!("forlenza/{}", system_id.to_lowercase());
+
+    let qos = match table.get("qos") {
+        Some(_) => integer(table, "qos", "mqtt")?,
+        None => 0,
+    };
+    if qos > 1 {
+        return Err("qos must be 0 or 1".to_string());
+    }
+    let publish_interval_ms = match table.get("publish_interval_ms") {
+        Some(_) => integer(table, "publish_interval_ms", "mqtt")?,
+        None => 1000,
+    };
+    if !(100..=60_000).contains(&publish_interval_ms) {
+        return Err("publish_interval_ms must be between 100 and 60000".to_string());
+    }
+
+    let (username, password) = (string("username")?, string("password")?);
+    if password.is_some() && username.is_none() {
+        return Err("password needs a username".to_string());
+    }
+
+    Ok(MqttConfig {
+        host,
+        port,
+        client_id: string("client_id")?.unwrap_or_else(|| system_id.to_string()),
+        username,
+        password,
+        telemetry_topic: string("topic")?.unwrap_or_else(|| format!("{}/telemetry", prefix)),
+        status_topic: string("status_topic")?.unwrap_or_else(|| format!("{}/status", prefix)),
+        qos: qos as u8,
+        publish_interval_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("forlenza-config-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn upgrade_keeps_the_original_as_a_backup() {
+        let directory = temp_dir("upgrade");
+        let path = directory.join("forlenza.toml");
+        let original = "# Line 2\n[system]\ntitle = \"Line 2\"\n";
+        fs::write(&path, original).unwrap();
+        let (document, version) = schema::migrate(toml::parse(original).unwrap()).unwrap();
+
+        let message = upgrade_file(&path, original, &document, version);
+        let backup = directory.join("forlenza.toml.v1.bak");
+        assert!(message.contains("upgraded from schema version 1") && message.contains(&backup.display().to_string()), "{}", message);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert_eq!(rewritten, toml::write(&document));
+        assert_eq!(schema::version(&toml::parse(&rewritten).unwrap()), Ok(schema::CURRENT_VERSION));
+        assert!(!path.with_extension("tmp").exists());
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn failed_upgrade_leaves_the_file_alone() {
+        let directory = temp_dir("unwritable");
+        // A directory where the backup should go can't be overwritten
+        let path = directory.join("forlenza.toml");
+        let original = "[system]\ntitle = \"Line 2\"\n";
+        fs::write(&path, original).unwrap();
+        fs::create_dir(directory.join("forlenza.toml.v1.bak")).unwrap();
+        let (document, version) = schema::migrate(toml::parse(original).unwrap()).unwrap();
+
+        let message = upgrade_file(&path, original, &document, version);
+        assert!(message.contains("could not be rewritten"), "{}", message);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,37 @@
+// Minimal RFC 4180 writer: CRLF line endings, fields quoted only when they need to be.
+// Rows can be read back one line at a time, as long as no field holds a line break.
+use std::io::{self, Write};
+
+pub fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn write_row<W: Write>(writer: &mut W, fields: &[String]) -> io::Result<()> {
+    let row: Vec<String> = fields.iter().map(|field| escape_field(field)).collect();
+    write!(writer, "{}\r\n", row.join(","))
+}
+
+// Splits one line written by write_row back into its fields
+pub fn parse_row(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,457 @@
+// Control cabinets shown on one console. Each device has its own readings, data source
+// and sensor thread, motor counters, energy totals, vibration and training scenario,
+// plus what the console last reported about it. The first device is configured by
+// forlenza.toml, any others by their own files listed under [devices]; MQTT, the REST
+// API and the historian follow the first.
+use crate::alarms::{Alarm, AlarmLevel};
+use crate::config::{Config, SourceConfig};
+use crate::energy::EnergyMeter;
+use crate::error::{self, FisError};
+use crate::heartbeat::{LinkHealth, LinkStats};
+use crate::history::SensorHistory;
+use crate::maintenance::Maintenance;
+use crate::mode::SystemMode;
+use crate::modbus::{ModbusRtuSource, ModbusTcpSource};
+use crate::replay::{Replay, ReplayConfig, ReplaySource};
+use crate::scenario::ScenarioPlayer;
+use crate::sensors::SensorData;
+use crate::simulation::{start_sensor_thread, SharedState, SimulatedSource, SimulationConfig, SimulationHandle};
+use crate::source::{ConnectionStatus, DataSource};
+use crate::vibration::Vibration;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// What the shared state is called when its lock is found poisoned
+pub const SENSOR_DATA: &str = "sensor readings";
+pub const HISTORY: &str = "sensor history";
+pub const MAINTENANCE: &str = "motor maintenance counters";
+pub const ENERGY: &str = "energy totals";
+pub const VIBRATION: &str = "vibration samples";
+pub const SCENARIO: &str = "scenario player";
+
+pub struct Device {
+    // The system ID from the device's configuration
+    pub name: String,
+    pub sensor_data: Arc<Mutex<SensorData>>,
+    pub history: Arc<Mutex<SensorHistory>>,
+    pub maintenance: Arc<Mutex<Maintenance>>,
+    pub maintenance_file: PathBuf,
+    // Motors already reported due for maintenance
+    pub maintenance_due: Vec<bool>,
+    pub maintenance_saved: Instant,
+    pub maintenance_save_failing: bool,
+    // Saved with the maintenance counters
+    pub energy: Arc<Mutex<EnergyMeter>>,
+    pub energy_file: PathBuf,
+    pub energy_save_failing: bool,
+    pub vibration: Arc<Mutex<Vibration>>,
+    pub scenario: Arc<Mutex<ScenarioPlayer>>,
+    pub simulation: SimulationConfig,
+    pub simulation_thread: SimulationHandle,
+    pub source_name: String,
+    // What a restarted sensor thread is built from, and what the device's configuration gives
+    source_config: SourceConfig,
+    configured_source: SourceConfig,
+    // Transport of the recording being replayed, when that is the source
+    pub replay: Option<Arc<Mutex<Replay>>>,
+    // A source being tried out before the sensor thread is switched to it
+    swap: Option<PendingSwap>,
+    pub swap_grace: Duration,
+    // Alarms aren't raised until then after a source switch
+    pub alarm_grace_until: Option<Instant>,
+    // The first alarm before the last trip or emergency shutdown, until it is reset
+    pub first_out: Option<Alarm>,
+    // Changed only through the console's transition()
+    pub mode: SystemMode,
+    reports: Sender<Result<String, String>>,
+    listeners: Vec<SyncSender<SensorData>>,
+    pub watchdog_timeout: Duration,
+    // Why the watchdog gave up on the sensor thread, until it is restarted
+    pub acquisition_halted: Option<FisError>,
+    // Last link health reported to the operator
+    pub link_health: Option<LinkHealth>,
+    // Shared state whose lock has been found poisoned and reported
+    pub poisoned: Vec<&'static str>,
+    pub data_stale: bool,
+    // Whether the scan overrun warning has been given and not yet cleared
+    pub scan_overrun: bool,
+    pub alarm_levels: Vec<AlarmLevel>,
+    // Each channel's rate-of-change level at the last poll
+    pub rate_levels: Vec<AlarmLevel>,
+    // Which motors' overload relays were tripped at the last poll
+    pub motor_overloads: Vec<bool>,
+    // Which motors were over their vibration limit at the last poll
+    pub vibration_alarms: Vec<bool>,
+    // Which interlocks were satisfied at the last poll
+    pub interlock_states: Vec<bool>,
+    // Motors still to be restarted after a shutdown, one every RESTART_STAGGER
+    pub restart_queue: VecDeque<(usize, u16)>,
+    pub next_restart: Instant,
+}
+
+impl Device {
+    // Starts the sensor thread on readings already set up from the device's configuration.
+    // Motor counters and energy totals that can't be read start again from zero; the
+    // reasons are returned.
+    pub fn start(
+        config: &Config,
+        simulation: SimulationConfig,
+        sensor_data: Arc<Mutex<SensorData>>,
+        maintenance_file: PathBuf,
+        energy_file: PathBuf,
+        reports: Sender<Result<String, String>>,
+        listeners: Vec<SyncSender<SensorData>>,
+    ) -> (Device, Vec<String>) {
+        let replay = replay_for(&config.source);
+        let source = build_source(&config.source, &simulation, replay.as_ref(), &reports);
+        let source_name = source.describe();
+
+        let motor_names: Vec<String> = match sensor_data.lock() {
+            Ok(data) => data.motors.iter().map(|motor| motor.name.clone()).collect(),
+            Err(_) => Vec::new(),
+        };
+        let mut maintenance = Maintenance::new(&motor_names, config.service_interval);
+        let maintenance_warning = maintenance.load(&maintenance_file).err().map(|e| {
+            format!("Motor runtime counters not loaded from {} ({}) - counting from zero", maintenance_file.display(), e)
+        });
+        let maintenance = Arc::new(Mutex::new(maintenance));
+        let mut energy = EnergyMeter::new(&motor_names, SystemTime::now());
+        let energy_warning = energy.load(&energy_file, SystemTime::now()).err().map(|e| {
+            format!("Energy totals not loaded from {} ({}) - counting from zero", energy_file.display(), e)
+        });
+        let energy = Arc::new(Mutex::new(energy));
+        let vibration = Arc::new(Mutex::new(Vibration::new(motor_names.len(), config.vibration_limit)));
+        let history = Arc::new(Mutex::new(SensorHistory::new()));
+        let scenario = Arc::new(Mutex::new(ScenarioPlayer::default()));
+
+        let simulation_thread = start_sensor_thread(
+            source,
+            SharedState {
+                sensor_data: Arc::clone(&sensor_data),
+                history: Arc::clone(&history),
+                maintenance: Arc::clone(&maintenance),
+                energy: Arc::clone(&energy),
+                vibration: Arc::clone(&vibration),
+                scenario: Arc::clone(&scenario),
+            },
+            simulation.clone(),
+            reports.clone(),
+            listeners.clone(),
+        );
+
+        let device = Device {
+            name: config.system_id.clone(),
+            sensor_data,
+            history,
+            maintenance,
+            maintenance_file,
+            maintenance_due: Vec::new(),
+            maintenance_saved: Instant::now(),
+            maintenance_save_failing: false,
+            energy,
+            energy_file,
+            energy_save_failing: false,
+            vibration,
+            scenario,
+            simulation,
+            simulation_thread,
+            source_name,
+            source_config: config.source.clone(),
+            configured_source: config.source.clone(),
+            replay,
+            swap: None,
+            swap_grace: config.swap_grace,
+            alarm_grace_until: None,
+            first_out: None,
+            mode: SystemMode::Startup,
+            reports,
+            listeners,
+            watchdog_timeout: config.watchdog_timeout,
+            acquisition_halted: None,
+            link_health: None,
+            poisoned: Vec::new(),
+            data_stale: false,
+            scan_overrun: false,
+            alarm_levels: Vec::new(),
+            rate_levels: Vec::new(),
+            motor_overloads: Vec::new(),
+            vibration_alarms: Vec::new(),
+            interlock_states: Vec::new(),
+            restart_queue: VecDeque::new(),
+            next_restart: Instant::now(),
+        };
+        (device, maintenance_warning.into_iter().chain(energy_warning).collect())
+    }
+
+    pub fn update_interval(&self) -> Duration {
+        Duration::from_millis(self.simulation_thread.update_interval_ms())
+    }
+
+    // How long the sensor thread may go without a heartbeat before the watchdog calls it
+    // hung. Never less than two update intervals, so a slow interval isn't taken for a hang.
+    pub fn watchdog_limit(&self) -> Duration {
+        self.watchdog_timeout.max(self.update_interval() * 2)
+    }
+
+    // Replaces a hung or crashed sensor thread with a new one on a fresh connection to the
+    // data source, keeping the update interval. The old thread is left to exit by itself.
+    // Poisoned shared state is renewed, so the readings may now be behind a new lock.
+    pub fn restart_acquisition(&mut self) {
+        self.simulation_thread.abandon();
+        error::renew(&mut self.sensor_data);
+        error::renew(&mut self.history);
+        error::renew(&mut self.maintenance);
+        error::renew(&mut self.energy);
+        error::renew(&mut self.vibration);
+        error::renew(&mut self.scenario);
+        self.start_acquisition();
+        self.acquisition_halted = None;
+        self.poisoned.clear();
+    }
+
+    pub fn lock_data(&self) -> Result<MutexGuard<'_, SensorData>, FisError> {
+        error::lock(&self.sensor_data, SENSOR_DATA)
+    }
+
+    // The name of each piece of shared state whose lock is poisoned, readings first
+    pub fn poisoned_locks(&self) -> Vec<&'static str> {
+        [
+            (self.sensor_data.is_poisoned(), SENSOR_DATA),
+            (self.history.is_poisoned(), HISTORY),
+            (self.maintenance.is_poisoned(), MAINTENANCE),
+            (self.energy.is_poisoned(), ENERGY),
+            (self.vibration.is_poisoned(), VIBRATION),
+            (self.scenario.is_poisoned(), SCENARIO),
+        ]
+        .into_iter()
+        .filter_map(|(poisoned, name)| poisoned.then_some(name))
+        .collect()
+    }
+
+    // Switches to replaying a recording in place of the configured source
+    pub fn start_replay(&mut self, replay: ReplayConfig) {
+        self.change_source(SourceConfig::Replay(replay));
+    }
+
+    // Back to the configured source, or to the simulation if that is itself a replay
+    pub fn end_replay(&mut self) {
+        let source = match &self.configured_source {
+            SourceConfig::Replay(_) => SourceConfig::Simulation,
+            source => source.clone(),
+        };
+        self.change_source(source);
+    }
+
+    fn change_source(&mut self, source: SourceConfig) {
+        if self.acquisition_halted.is_some() {
+            self.simulation_thread.abandon();
+        } else {
+            self.simulation_thread.stop();
+        }
+        self.replay = replay_for(&source);
+        self.source_config = source;
+        self.start_acquisition();
+    }
+
+    // Whether the device runs on the source from its configuration rather than the
+    // simulation; a replay is neither
+    pub fn source_choice(&self) -> Option<SourceChoice> {
+        match &self.source_config {
+            SourceConfig::Replay(_) => None,
+            source if *source == self.configured_source => Some(SourceChoice::Configured),
+            _ => Some(SourceChoice::Simulation),
+        }
+    }
+
+    pub fn swap_pending(&self) -> Option<&str> {
+        self.swap.as_ref().map(|swap| swap.name.as_str())
+    }
+
+    // Starts switching the sensor thread to another source while the plant runs. The new
+    // source is polled once on a background thread, into a copy of the readings; only when
+    // that works does finish_swap() stop the current thread and hand over, so a source that
+    // can't be reached leaves the device where it was. Returns the new source's description.
+    pub fn begin_swap(&mut self, choice: SourceChoice) -> Result<String, String> {
+        let source = match choice {
+            SourceChoice::Simulation => SourceConfig::Simulation,
+            SourceChoice::Configured => match &self.configured_source {
+                SourceConfig::Replay(_) => return Err("the configured source is a replay; use 'replay load' for recordings".to_string()),
+                source => source.clone(),
+            },
+        };
+        if self.replay.is_some() {
+            return Err("a replay is running ('replay stop' ends it)".to_string());
+        }
+        if let Some(swap) = &self.swap {
+            return Err(format!("already switching to {}", swap.name));
+        }
+        if self.source_choice() == Some(choice) {
+            return Err(format!("readings already come from {}", self.source_name));
+        }
+        let scratch = match self.lock_data() {
+            Ok(data) => Mutex::new(data.clone()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let interval = self.update_interval();
+        let mut built = build_source(&source, &self.simulation, None, &self.reports);
+        let name = built.describe();
+        let (done, result) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = done.send(built.poll(&scratch, interval).map(|()| built).map_err(|e| e.to_string()));
+        });
+        self.swap = Some(PendingSwap { source, name: name.clone(), result });
+        Ok(name)
+    }
+
+    // The outcome of a switch begun by begin_swap(), once its trial poll is over: the
+    // source handed over from on success, or why the switch was given up. Readings count
+    // as stale until the new source's first poll, and alarms are held off for swap_grace.
+    pub fn finish_swap(&mut self) -> Option<Result<String, String>> {
+        let swap = self.swap.as_ref()?;
+        let outcome = match swap.result.try_recv() {
+            Ok(outcome) => outcome,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("the trial poll ended without an answer".to_string()),
+        };
+        let swap = self.swap.take()?;
+        let source = match outcome {
+            Ok(source) => source,
+            Err(reason) => return Some(Err(format!("{} did not answer ({})", swap.name, reason))),
+        };
+        let previous = std::mem::replace(&mut self.source_name, swap.name);
+        if self.acquisition_halted.is_some() {
+            self.simulation_thread.abandon();
+        } else {
+            self.simulation_thread.stop();
+        }
+        self.source_config = swap.source;
+        if let Ok(mut data) = self.lock_data() {
+            data.connection = ConnectionStatus::Connecting;
+            data.last_update = None;
+            data.link = LinkStats::new(data.link.thresholds);
+        }
+        self.data_stale = true;
+        self.alarm_grace_until = Some(Instant::now() + self.swap_grace);
+        self.run_acquisition(source, self.swap_grace);
+        self.acquisition_halted = None;
+        Some(Ok(previous))
+    }
+
+    // Starts a sensor thread on the current source, keeping the update interval
+    fn start_acquisition(&mut self) {
+        let source = build_source(&self.source_config, &self.simulation, self.replay.as_ref(), &self.reports);
+        self.run_acquisition(source, Duration::ZERO);
+    }
+
+    fn run_acquisition(&mut self, source: Box<dyn DataSource>, trip_grace: Duration) {
+        // Only the simulation has weather; a PLC or a replay would leave it standing still
+        if self.source_config != SourceConfig::Simulation {
+            if let Ok(mut data) = self.lock_data() {
+                data.ambient = None;
+            }
+        }
+        let interval_ms = self.simulation_thread.update_interval_ms();
+        let simulation = SimulationConfig { update_interval_ms: interval_ms, trip_grace, ..self.simulation.clone() };
+        self.source_name = source.describe();
+        self.simulation_thread = start_sensor_thread(
+            source,
+            SharedState {
+                sensor_data: Arc::clone(&self.sensor_data),
+                history: Arc::clone(&self.history),
+                maintenance: Arc::clone(&self.maintenance),
+                energy: Arc::clone(&self.energy),
+                vibration: Arc::clone(&self.vibration),
+                scenario: Arc::clone(&self.scenario),
+            },
+            simulation,
+            self.reports.clone(),
+            self.listeners.clone(),
+        );
+    }
+
+    // The alarm tracking starts again from the current readings, e.g. after a snapshot load
+    pub fn reset_alarm_tracking(&mut self) {
+        self.alarm_levels.clear();
+        self.rate_levels.clear();
+        self.motor_overloads.clear();
+        self.interlock_states.clear();
+    }
+}
+
+// The sources 'source' switches between at runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceChoice {
+    Simulation,
+    Configured,
+}
+
+impl SourceChoice {
+    pub fn parse(name: &str) -> Result<SourceChoice, String> {
+        match name {
+            "sim" | "simulation" => Ok(SourceChoice::Simulation),
+            "configured" | "plc" => Ok(SourceChoice::Configured),
+            _ => Err(format!("unknown source '{}' (use sim or configured)", name)),
+        }
+    }
+}
+
+struct PendingSwap {
+    source: SourceConfig,
+    name: String,
+    // The source itself once its trial poll has worked, ready to take over
+    result: Receiver<Result<Box<dyn DataSource>, String>>,
+}
+
+// A new replay starts from the beginning of its file
+fn replay_for(source: &SourceConfig) -> Option<Arc<Mutex<Replay>>> {
+    match source {
+        SourceConfig::Replay(replay) => Some(Arc::new(Mutex::new(Replay::new(replay.clone())))),
+        _ => None,
+    }
+}
+
+// A replay source shares the device's transport, so a restarted thread carries on where it was
+fn build_source(source: &SourceConfig, simulation: &SimulationConfig, replay: Option<&Arc<Mutex<Replay>>>, reports: &Sender<Result<String, String>>) -> Box<dyn DataSource> {
+    match (source, replay) {
+        (SourceConfig::ModbusTcp(modbus), _) => Box::new(ModbusTcpSource::new(modbus.clone())),
+        (SourceConfig::ModbusRtu(modbus), _) => Box::new(ModbusRtuSource::new(modbus.clone())),
+        (SourceConfig::Replay(_), Some(replay)) => Box::new(ReplaySource::new(Arc::clone(replay), reports.clone())),
+        (SourceConfig::Simulation | SourceConfig::Replay(_), _) => Box::new(SimulatedSource::new(simulation.seed, simulation.model.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarting_renews_a_poisoned_vibration_lock() {
+        let config = Config::default();
+        let directory = std::env::temp_dir().join(format!("forlenza-devices-{}", std::process::id()));
+        let sensor_data = Arc::new(Mutex::new(SensorData::from_config(&config)));
+        let (reports, _) = mpsc::channel();
+        let (mut device, _) = Device::start(&config, SimulationConfig::new(&config, Some(1)), sensor_data, directory.join("maintenance.json"), directory.join("energy.json"), reports, Vec::new());
+
+        // The sensor thread dies while analysing vibration
+        let vibration = Arc::clone(&device.vibration);
+        let crashed = thread::spawn(move || {
+            let _held = vibration.lock().unwrap();
+            panic!("sensor thread failed mid-analysis");
+        }).join();
+        assert!(crashed.is_err());
+        assert_eq!(device.poisoned_locks(), [VIBRATION]);
+
+        device.restart_acquisition();
+        assert!(device.poisoned_locks().is_empty());
+        assert!(device.vibration.lock().is_ok());
+        // Shared with the new sensor thread rather than left to the old lock
+        assert!(Arc::strong_count(&device.vibration) > 1);
+        device.simulation_thread.stop();
+        assert_eq!(Arc::strong_count(&device.vibration), 1);
+    }
+}
@@ -0,0 +1,405 @@
+// System diagnostic sequence. Each step checks something real - the sensor thread, the
+// data source, limits, historian storage, interlocks - on a worker thread and reports
+// back over a channel that the runner drains on each poll. A failed check doesn't stop
+// the ones after it, so one run shows everything that is wrong. The runner keeps the
+// outcome of recent runs, whether the operator or the schedule started them.
+use crate::i18n::tr;
+use crate::sensors::{ChannelKind, SensorData, STALE_INTERVALS};
+use crate::source::ConnectionStatus;
+use crate::units::Units;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the sensor thread check looks for a fresh update
+const UPDATE_POLL: Duration = Duration::from_millis(50);
+const HISTORIAN_PROBE: &str = ".diagnostic-check";
+// Completed runs kept for 'diag history'
+const RUN_HISTORY: usize = 20;
+
+#[derive(Clone, Debug)]
+pub enum CheckResult {
+    Pass,
+    Warn(String),
+    Fail(String),
+}
+
+pub struct DiagnosticEvent {
+    pub step: &'static str,
+    pub result: CheckResult,
+    pub elapsed: Duration,
+    pub details: Vec<String>,
+}
+
+enum DiagnosticMessage {
+    Step(DiagnosticEvent),
+    Finished(Result<(), String>),
+}
+
+// How the checks went, for the summary line
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiagnosticSummary {
+    pub warnings: usize,
+    pub failures: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticTrigger {
+    Startup,
+    Manual,
+    Scheduled,
+}
+
+impl DiagnosticTrigger {
+    // As shown on the console, in its language
+    pub fn label(self) -> &'static str {
+        match self {
+            DiagnosticTrigger::Startup => tr("diag.trigger_startup"),
+            DiagnosticTrigger::Manual => tr("diag.trigger_manual"),
+            DiagnosticTrigger::Scheduled => tr("diag.trigger_scheduled"),
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            DiagnosticTrigger::Startup => "startup",
+            DiagnosticTrigger::Manual => "manual",
+            DiagnosticTrigger::Scheduled => "scheduled",
+        })
+    }
+}
+
+// A completed run, for the history
+#[derive(Clone, Debug)]
+pub struct DiagnosticRun {
+    pub started: SystemTime,
+    pub trigger: DiagnosticTrigger,
+    pub result: Result<DiagnosticSummary, String>,
+    pub elapsed: Duration,
+    // What each step found, in order; steps of an aborted run that never ran are missing
+    pub steps: Vec<(&'static str, CheckResult)>,
+}
+
+pub enum DiagnosticUpdate {
+    // done includes this step, for the progress bar
+    Step { event: DiagnosticEvent, done: usize },
+    // An Err result means the run was cut short and `skipped` steps never ran
+    Finished { trigger: DiagnosticTrigger, result: Result<DiagnosticSummary, String>, elapsed: Duration, skipped: usize },
+}
+
+pub const DIAGNOSTIC_STEPS: [&str; 8] = [
+    "Checking sensor update thread",
+    "Communicating with data source",
+    "Reading temperature sensors",
+    "Reading pressure gauges",
+    "Reading motor status",
+    "Checking alarm limits",
+    "Checking historian storage",
+    "Verifying safety interlocks",
+];
+
+// What the checks look at. The sensor data is shared rather than copied so the thread
+// check can watch it change.
+pub struct DiagnosticContext {
+    pub sensor_data: Arc<Mutex<SensorData>>,
+    pub thread_running: bool,
+    pub paused: bool,
+    pub update_interval: Duration,
+    // The configured directory, even if the historian couldn't start in it
+    pub historian: Option<PathBuf>,
+    // Readings are listed in the operator's units
+    pub units: Units,
+}
+
+// One run at a time: a run started while another is in progress is refused, so manual
+// and scheduled runs never interleave.
+pub struct DiagnosticRunner {
+    started: Option<(Instant, SystemTime)>,
+    trigger: DiagnosticTrigger,
+    rx: Option<Receiver<DiagnosticMessage>>,
+    steps_done: usize,
+    summary: DiagnosticSummary,
+    steps: Vec<(&'static str, CheckResult)>,
+    // Most recent last
+    history: VecDeque<DiagnosticRun>,
+}
+
+impl Default for DiagnosticRunner {
+    fn default() -> Self {
+        DiagnosticRunner {
+            started: None,
+            trigger: DiagnosticTrigger::Startup,
+            rx: None,
+            steps_done: 0,
+            summary: DiagnosticSummary::default(),
+            steps: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl DiagnosticRunner {
+    pub fn is_running(&self) -> bool {
+        self.rx.is_some()
+    }
+
+    // What started the run in progress, if any
+    pub fn running(&self) -> Option<DiagnosticTrigger> {
+        self.is_running().then_some(self.trigger)
+    }
+
+    pub fn history(&self) -> &VecDeque<DiagnosticRun> {
+        &self.history
+    }
+
+    // Returns false without starting anything if a diagnostic is already running
+    pub fn start(&mut self, context: DiagnosticContext, trigger: DiagnosticTrigger) -> bool {
+        if self.is_running() {
+            return false;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        self.started = Some((Instant::now(), SystemTime::now()));
+        self.trigger = trigger;
+        self.rx = Some(rx);
+        self.steps_done = 0;
+        self.summary = DiagnosticSummary::default();
+        self.steps.clear();
+        thread::spawn(move || {
+            let result = diagnostic_worker(&context, &tx);
+            // The receiver may be gone if the application is exiting
+            let _ = tx.send(DiagnosticMessage::Finished(result));
+        });
+        true
+    }
+
+    // Drains whatever the worker has reported so far without blocking
+    pub fn poll(&mut self) -> Vec<DiagnosticUpdate> {
+        let mut updates = Vec::new();
+        let rx = match &self.rx {
+            Some(rx) => rx,
+            None => return updates,
+        };
+
+        let finished = loop {
+            match rx.try_recv() {
+                Ok(DiagnosticMessage::Step(event)) => {
+                    self.steps_done += 1;
+                    match event.result {
+                        CheckResult::Pass => {}
+                        CheckResult::Warn(_) => self.summary.warnings += 1,
+                        CheckResult::Fail(_) => self.summary.failures += 1,
+                    }
+                    self.steps.push((event.step, event.result.clone()));
+                    updates.push(DiagnosticUpdate::Step { event, done: self.steps_done });
+                }
+                Ok(DiagnosticMessage::Finished(result)) => break Some(result),
+                Err(TryRecvError::Empty) => break None,
+                Err(TryRecvError::Disconnected) => break Some(Err("diagnostic worker exited unexpectedly".to_string())),
+            }
+        };
+
+        if let Some(result) = finished {
+            let (elapsed, started) = self.started.take().map_or((Duration::ZERO, SystemTime::now()), |(start, time)| (start.elapsed(), time));
+            self.rx = None;
+            let result = result.map(|()| self.summary);
+            if self.history.len() == RUN_HISTORY {
+                self.history.pop_front();
+            }
+            let steps = std::mem::take(&mut self.steps);
+            self.history.push_back(DiagnosticRun { started, trigger: self.trigger, result: result.clone(), elapsed, steps });
+            updates.push(DiagnosticUpdate::Finished { trigger: self.trigger, result, elapsed, skipped: DIAGNOSTIC_STEPS.len() - self.steps_done });
+        }
+        updates
+    }
+}
+
+fn diagnostic_worker(context: &DiagnosticContext, tx: &Sender<DiagnosticMessage>) -> Result<(), String> {
+    for step in DIAGNOSTIC_STEPS {
+        let started = Instant::now();
+        let mut details = Vec::new();
+        let result = run_diagnostic_step(step, context, &mut details);
+        let event = DiagnosticEvent { step, result, elapsed: started.elapsed(), details };
+        tx.send(DiagnosticMessage::Step(event)).map_err(|_| "diagnostic cancelled".to_string())?;
+    }
+    Ok(())
+}
+
+fn run_diagnostic_step(step: &str, context: &DiagnosticContext, details: &mut Vec<String>) -> CheckResult {
+    // Each step looks at the data as it is when the step runs
+    let snapshot = match context.sensor_data.lock() {
+        Ok(data) => data.clone(),
+        Err(_) => return CheckResult::Fail("sensor data is unavailable (the sensor thread crashed)".to_string()),
+    };
+    let units = context.units;
+    match step {
+        "Checking sensor update thread" => check_update_thread(context, &snapshot, details),
+        "Communicating with data source" => check_data_source(context, &snapshot, details),
+        "Reading temperature sensors" | "Reading pressure gauges" => {
+            let kind = if step == "Reading temperature sensors" { ChannelKind::Temperature } else { ChannelKind::Pressure };
+            let mut faulted = Vec::new();
+            for channel in snapshot.channels_of(kind) {
+                details.push(format!("  {}: {}", channel.label(), units.format(kind, channel.value)));
+                if !channel.value.is_finite() {
+                    faulted.push(channel.label());
+                }
+            }
+            if faulted.is_empty() {
+                CheckResult::Pass
+            } else {
+                CheckResult::Warn(format!("no valid reading from {}", faulted.join(", ")))
+            }
+        }
+        "Reading motor status" => {
+            let mut tripped = Vec::new();
+            for motor in &snapshot.motors {
+                let status = if motor.overloaded {
+                    tripped.push(motor.label());
+                    "OVERLOAD TRIP"
+                } else if motor.speed == 0 {
+                    "STOPPED"
+                } else {
+                    "RUNNING"
+                };
+                details.push(format!("  {}: {} ({} RPM, {:.1} A)", motor.label(), status, motor.speed, motor.current));
+            }
+            if tripped.is_empty() {
+                CheckResult::Pass
+            } else {
+                CheckResult::Warn(format!("overload tripped on {}", tripped.join(", ")))
+            }
+        }
+        "Checking alarm limits" => {
+            let mut invalid = 0;
+            for channel in &snapshot.channels {
+                if let Err(e) = channel.limits.validate() {
+                    details.push(format!("  {}: {}", channel.label(), e));
+                    invalid += 1;
+                }
+            }
+            if invalid == 0 {
+                details.push(format!("  {} channels consistent", snapshot.channels.len()));
+                CheckResult::Pass
+            } else {
+                CheckResult::Fail(format!("{} of {} channels have inconsistent limits", invalid, snapshot.channels.len()))
+            }
+        }
+        "Checking historian storage" => check_historian(context, details),
+        "Verifying safety interlocks" => {
+            if snapshot.interlocks.is_empty() {
+                details.push("  Safety Interlocks: none configured".to_string());
+            }
+            for interlock in &snapshot.interlocks {
+                let state = if interlock.satisfied { "SATISFIED" } else { "NOT SATISFIED" };
+                details.push(format!("  {}: {} ({})", interlock.name, state, interlock.condition));
+            }
+            let dropped: Vec<&str> = snapshot.interlocks.iter().filter(|interlock| !interlock.satisfied).map(|interlock| interlock.name.as_str()).collect();
+            if dropped.is_empty() {
+                CheckResult::Pass
+            } else {
+                CheckResult::Fail(format!("not satisfied: {}", dropped.join(", ")))
+            }
+        }
+        _ => CheckResult::Fail(format!("unknown diagnostic step '{}'", step)),
+    }
+}
+
+// Waits for the sensor thread to stamp a new update, for as long as it takes readings to go stale
+fn check_update_thread(context: &DiagnosticContext, snapshot: &SensorData, details: &mut Vec<String>) -> CheckResult {
+    if !context.thread_running {
+        return CheckResult::Fail("the sensor thread has stopped".to_string());
+    }
+    if context.paused {
+        return CheckResult::Warn("updates are paused ('pause' again to continue)".to_string());
+    }
+    if snapshot.frozen {
+        return CheckResult::Warn("a loaded snapshot is frozen on screen ('resume' to continue)".to_string());
+    }
+
+    let timeout = context.update_interval * STALE_INTERVALS;
+    let started = Instant::now();
+    while started.elapsed() < timeout {
+        thread::sleep(UPDATE_POLL);
+        match context.sensor_data.lock() {
+            Ok(data) if data.last_update != snapshot.last_update => {
+                details.push(format!(
+                    "  New readings after {} ms (update interval {} ms)",
+                    started.elapsed().as_millis(),
+                    context.update_interval.as_millis()
+                ));
+                return CheckResult::Pass;
+            }
+            Ok(_) => {}
+            Err(_) => return CheckResult::Fail("sensor data is unavailable (the sensor thread crashed)".to_string()),
+        }
+    }
+    // The thread keeps ticking while the source is offline; the next step reports why
+    match context.sensor_data.lock().map(|data| data.connection.clone()) {
+        Ok(ConnectionStatus::Offline { .. }) => CheckResult::Warn(format!("running, but no new readings in {} ms while the data source is offline", timeout.as_millis())),
+        _ => CheckResult::Fail(format!("no new readings in {} ms", timeout.as_millis())),
+    }
+}
+
+fn check_data_source(context: &DiagnosticContext, snapshot: &SensorData, details: &mut Vec<String>) -> CheckResult {
+    if snapshot.plc_link_down() {
+        return CheckResult::Fail("no response from PLC".to_string());
+    }
+    if let ConnectionStatus::Offline { reason, .. } = &snapshot.connection {
+        return CheckResult::Fail(reason.clone());
+    }
+    let last_update = match snapshot.last_update {
+        Some(time) => time,
+        None => return CheckResult::Fail("no readings received yet".to_string()),
+    };
+    let age = SystemTime::now().duration_since(last_update).unwrap_or_default();
+    details.push(format!("  Last response {} ms ago", age.as_millis()));
+    if let Some(average) = snapshot.link.average_latency() {
+        details.push(format!("  Round trip {} ms on average, {:.0}% of recent polls lost", average.as_millis(), snapshot.link.loss_percent()));
+    }
+    // Paused or frozen readings are old for a reason the thread check has already given
+    if context.paused || snapshot.frozen {
+        CheckResult::Pass
+    } else if let Some(age) = snapshot.staleness(SystemTime::now(), context.update_interval) {
+        CheckResult::Fail(format!("no response in {} ms", age.as_millis()))
+    } else {
+        CheckResult::Pass
+    }
+}
+
+// Writes and removes a probe file, as the historian would write its day files
+fn check_historian(context: &DiagnosticContext, details: &mut Vec<String>) -> CheckResult {
+    let directory = match &context.historian {
+        Some(directory) => directory,
+        None => {
+            details.push("  Historian: disabled".to_string());
+            return CheckResult::Pass;
+        }
+    };
+    let probe = directory.join(HISTORIAN_PROBE);
+    let result = fs::create_dir_all(directory)
+        .and_then(|()| fs::File::create(&probe))
+        .and_then(|mut file| file.write_all(b"ok\n").and_then(|()| file.sync_all()))
+        .and_then(|()| fs::remove_file(&probe));
+    match result {
+        Ok(()) => {
+            details.push(format!("  {} is writable", directory.display()));
+            CheckResult::Pass
+        }
+        Err(e) => CheckResult::Fail(format!("can't write to {}: {}", directory.display(), e)),
+    }
+}
+
+pub fn progress_bar(done: usize, total: usize) -> String {
+    const WIDTH: usize = 12;
+    let filled = (done * WIDTH).checked_div(total).unwrap_or(WIDTH).min(WIDTH);
+    format!("[{}{}] {}/{}", "#".repeat(filled), "-".repeat(WIDTH - filled), done, total)
+}
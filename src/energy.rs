@@ -0,0 +1,215 @@
+// Electrical power and energy for energy audits. Each motor's power follows from its
+// current on a three-phase supply of the configured voltage and power factor, and the
+// sensor thread adds up kWh sample by sample, per motor, for today (UTC), the current
+// shift and since the totals were last reset. The totals are kept across restarts in a
+// small JSON state file, with the time they were last added to, so a restart loses at
+// most what came after the last save and never counts the time the console was down;
+// a total whose day or shift ended in the meantime starts again from zero.
+use crate::clock::{format_datetime, parse_datetime};
+use crate::json::Value;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Supply {
+    // Line to line, volts
+    pub voltage: f32,
+    pub power_factor: f32,
+}
+
+impl Default for Supply {
+    fn default() -> Self {
+        Supply { voltage: 400.0, power_factor: 0.85 }
+    }
+}
+
+impl Supply {
+    // kW drawn by a three-phase motor taking `current` amps per phase
+    pub fn power(&self, current: f32) -> f32 {
+        3f32.sqrt() * self.voltage * current * self.power_factor / 1000.0
+    }
+}
+
+// kWh
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Totals {
+    pub today: f64,
+    pub shift: f64,
+    pub since_reset: f64,
+}
+
+impl Totals {
+    fn add(&mut self, kwh: f64) {
+        self.today += kwh;
+        self.shift += kwh;
+        self.since_reset += kwh;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotorEnergy {
+    pub name: String,
+    pub totals: Totals,
+}
+
+#[derive(Clone)]
+pub struct EnergyMeter {
+    motors: Vec<MotorEnergy>,
+    // The UTC day `today` counts, as YYYY-MM-DD
+    day: String,
+    shift_started: SystemTime,
+    reset_at: SystemTime,
+    // When energy was last added
+    updated: Option<SystemTime>,
+}
+
+impl EnergyMeter {
+    pub fn new(names: &[String], now: SystemTime) -> Self {
+        EnergyMeter {
+            motors: names.iter().map(|name| MotorEnergy { name: name.clone(), totals: Totals::default() }).collect(),
+            day: day_of(now),
+            shift_started: now,
+            reset_at: now,
+            updated: None,
+        }
+    }
+
+    pub fn motors(&self) -> &[MotorEnergy] {
+        &self.motors
+    }
+
+    // Every motor's together
+    pub fn total(&self) -> Totals {
+        let mut total = Totals::default();
+        for motor in &self.motors {
+            total.today += motor.totals.today;
+            total.shift += motor.totals.shift;
+            total.since_reset += motor.totals.since_reset;
+        }
+        total
+    }
+
+    pub fn shift_started(&self) -> SystemTime {
+        self.shift_started
+    }
+
+    pub fn reset_at(&self) -> SystemTime {
+        self.reset_at
+    }
+
+    pub fn updated(&self) -> Option<SystemTime> {
+        self.updated
+    }
+
+    // Called by the sensor thread with each sample's motor power (kW), the time it covers
+    // and when it was taken
+    pub fn record(&mut self, power: &[f32], dt: Duration, time: SystemTime) {
+        self.roll_day(time);
+        let hours = dt.as_secs_f64() / 3600.0;
+        for (motor, kw) in self.motors.iter_mut().zip(power) {
+            let kwh = *kw as f64 * hours;
+            if kwh.is_finite() && kwh > 0.0 {
+                motor.totals.add(kwh);
+            }
+        }
+        self.updated = Some(time);
+    }
+
+    // Counts the shift totals from `start`, starting them again from zero unless they were
+    // already counting then or later
+    pub fn begin_shift(&mut self, start: SystemTime) {
+        if self.shift_started < start {
+            for motor in &mut self.motors {
+                motor.totals.shift = 0.0;
+            }
+        }
+        self.shift_started = start;
+    }
+
+    pub fn reset(&mut self, now: SystemTime) {
+        for motor in &mut self.motors {
+            motor.totals.since_reset = 0.0;
+        }
+        self.reset_at = now;
+    }
+
+    fn roll_day(&mut self, time: SystemTime) {
+        let day = day_of(time);
+        if day != self.day {
+            for motor in &mut self.motors {
+                motor.totals.today = 0.0;
+            }
+            self.day = day;
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let motors = self.motors.iter().map(|motor| Value::Object(vec![
+            ("name".to_string(), motor.name.as_str().into()),
+            ("today_kwh".to_string(), motor.totals.today.into()),
+            ("shift_kwh".to_string(), motor.totals.shift.into()),
+            ("since_reset_kwh".to_string(), motor.totals.since_reset.into()),
+        ])).collect();
+        Value::Object(vec![
+            ("format".to_string(), "forlenza-energy".into()),
+            ("version".to_string(), 1u16.into()),
+            ("day".to_string(), self.day.as_str().into()),
+            ("shift_started".to_string(), format_datetime(self.shift_started).into()),
+            ("reset_at".to_string(), format_datetime(self.reset_at).into()),
+            ("updated".to_string(), self.updated.map_or(Value::Null, |time| format_datetime(time).into())),
+            ("motors".to_string(), Value::Array(motors)),
+        ])
+    }
+
+    // Takes the saved totals of every motor still configured under the same name and
+    // returns how many there were; a missing file leaves everything at zero. Today's totals
+    // are dropped if the file is from an earlier day; the caller starts the current shift.
+    pub fn load(&mut self, path: &Path, now: SystemTime) -> Result<usize, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.to_string()),
+        };
+        let state = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if state.get("format").and_then(Value::as_str) != Some("forlenza-energy") {
+            return Err("not a Forlenza energy file".to_string());
+        }
+        let time = |key: &str| state.str_field(key).ok().and_then(parse_datetime).ok_or_else(|| format!("field '{}' is not a time", key));
+        let shift_started = time("shift_started")?;
+        let reset_at = time("reset_at")?;
+        let updated = match state.field("updated")? {
+            Value::Null => None,
+            _ => Some(time("updated")?),
+        };
+        let day = state.str_field("day")?.to_string();
+        let saved = state.array_field("motors", |item| {
+            let kwh = |key: &str| item.f64_field(key).ok().filter(|kwh| kwh.is_finite() && *kwh >= 0.0);
+            Some(MotorEnergy {
+                name: item.str_field("name").ok()?.to_string(),
+                totals: Totals { today: kwh("today_kwh")?, shift: kwh("shift_kwh")?, since_reset: kwh("since_reset_kwh")? },
+            })
+        })?;
+
+        let mut matched = 0;
+        for motor in &mut self.motors {
+            if let Some(found) = saved.iter().find(|found| found.name == motor.name) {
+                motor.totals = found.totals;
+                matched += 1;
+            }
+        }
+        self.day = day;
+        self.shift_started = shift_started;
+        self.reset_at = reset_at;
+        self.updated = updated;
+        self.roll_day(now);
+        Ok(matched)
+    }
+}
+
+// YYYY-MM-DD in UTC
+fn day_of(time: SystemTime) -> String {
+    format_datetime(time)[..10].to_string()
+}
@@ -0,0 +1,137 @@
+// Failures the console has to tell the operator about, as opposed to a command that was
+// refused. Most modules still describe what went wrong in a String; these are the ones
+// the console acts on by kind - a lost lock, a halted sensor thread, a configuration
+// that could not be used.
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FisError {
+    // A thread panicked while holding the lock, so what it guards may be half updated
+    LockPoisoned(&'static str),
+    // The sensor thread has gone this long without a heartbeat, e.g. stuck in a poll
+    SourceTimeout(Duration),
+    // The sensor thread has exited, stopped or crashed
+    ThreadStopped,
+    ConfigInvalid { path: PathBuf, reason: String },
+    Io(String),
+}
+
+impl fmt::Display for FisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FisError::LockPoisoned(what) => write!(f, "the {} can't be trusted: a thread failed while updating them", what),
+            FisError::SourceTimeout(age) => write!(f, "no heartbeat from the sensor thread for {:.0} s", age.as_secs_f32()),
+            FisError::ThreadStopped => write!(f, "the sensor thread has stopped"),
+            FisError::ConfigInvalid { path, reason } => write!(f, "Invalid configuration file {} ({}) - using built-in defaults", path.display(), reason),
+            FisError::Io(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for FisError {
+    fn from(error: io::Error) -> Self {
+        FisError::Io(error.to_string())
+    }
+}
+
+// `what` names the data for the error, e.g. "sensor readings"
+pub fn lock<'a, T>(mutex: &'a Mutex<T>, what: &'static str) -> Result<MutexGuard<'a, T>, FisError> {
+    mutex.lock().map_err(|_| FisError::LockPoisoned(what))
+}
+
+// For the safe action, which goes ahead on whatever the lock holds; whether it was
+// poisoned is returned alongside
+pub fn lock_or_recover<T>(mutex: &Mutex<T>) -> (MutexGuard<'_, T>, bool) {
+    match mutex.lock() {
+        Ok(guard) => (guard, false),
+        Err(poisoned) => (PoisonError::into_inner(poisoned), true),
+    }
+}
+
+// A poisoned lock stays poisoned, so it is replaced by a new one holding what the old one
+// held. Anything else sharing the old lock keeps seeing it poisoned and has to be handed
+// the new one. Returns whether it was replaced.
+pub fn renew<T: Clone>(shared: &mut Arc<Mutex<T>>) -> bool {
+    if !shared.is_poisoned() {
+        return false;
+    }
+    let contents = lock_or_recover(shared).0.clone();
+    *shared = Arc::new(Mutex::new(contents));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::sensors::SensorData;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    // A sensor thread that dies half way through an update, with a motor speeding up
+    fn poisoned_plant() -> Arc<Mutex<SensorData>> {
+        let sensor_data = Arc::new(Mutex::new(SensorData::from_config(&Config::default())));
+        let shared = Arc::clone(&sensor_data);
+        let crashed = thread::spawn(move || {
+            let mut data = shared.lock().unwrap();
+            data.motors[0].speed = 3000;
+            panic!("sensor thread failed mid-update");
+        }).join();
+        assert!(crashed.is_err());
+        assert!(sensor_data.is_poisoned());
+        sensor_data
+    }
+
+    #[test]
+    fn a_poisoned_lock_is_reported() {
+        let sensor_data = poisoned_plant();
+        assert_eq!(lock(&sensor_data, "sensor readings").err(), Some(FisError::LockPoisoned("sensor readings")));
+        assert_eq!(
+            FisError::LockPoisoned("sensor readings").to_string(),
+            "the sensor readings can't be trusted: a thread failed while updating them"
+        );
+    }
+
+    #[test]
+    fn emergency_shutdown_goes_ahead_on_a_poisoned_lock() {
+        let sensor_data = poisoned_plant();
+        let (mut data, poisoned) = lock_or_recover(&sensor_data);
+        assert!(poisoned);
+        assert_eq!(data.motors[0].speed, 3000, "what the thread left");
+        data.trip_emergency_shutdown();
+        assert!(data.emergency_shutdown);
+        assert!(data.motors.iter().all(|motor| !motor.running && motor.speed == 0 && motor.setpoint == 0));
+        drop(data);
+
+        // Still poisoned until the sensor thread is restarted, but the shutdown holds
+        let (data, poisoned) = lock_or_recover(&sensor_data);
+        assert!(poisoned && data.emergency_shutdown);
+    }
+
+    #[test]
+    fn a_renewed_lock_keeps_the_contents() {
+        let mut sensor_data = poisoned_plant();
+        lock_or_recover(&sensor_data).0.trip_emergency_shutdown();
+        let old = Arc::clone(&sensor_data);
+        assert!(renew(&mut sensor_data));
+        let data = lock(&sensor_data, "sensor readings").unwrap();
+        assert!(data.emergency_shutdown);
+        drop(data);
+        assert!(old.is_poisoned(), "the old lock is left as it was");
+        let renewed = Arc::clone(&sensor_data);
+        assert!(!renew(&mut sensor_data), "only a poisoned lock is replaced");
+        assert!(Arc::ptr_eq(&renewed, &sensor_data));
+    }
+
+    #[test]
+    fn a_healthy_lock_is_not_reported() {
+        let sensor_data = Mutex::new(SensorData::from_config(&Config::default()));
+        assert!(lock(&sensor_data, "sensor readings").is_ok());
+        assert!(!lock_or_recover(&sensor_data).1);
+    }
+}
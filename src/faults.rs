@@ -0,0 +1,159 @@
+// Simulated instrument faults for operator training. A fault changes what the
+// console reads from a channel while the process behind it keeps evolving, so
+// clearing the fault shows where the plant has drifted to in the meantime.
+use crate::rand::Rng;
+use crate::sensors::ChannelKind;
+
+use std::fmt;
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultTarget {
+    Temperature(usize),
+    Pressure(usize),
+    Motor(usize),
+    // The link to the legacy PLC; while it's down no channel updates
+    PlcLink,
+}
+
+impl FaultTarget {
+    // Channels are numbered from 1 as on the plant floor
+    pub fn parse(kind: &str, channel: Option<&str>) -> Result<FaultTarget, String> {
+        let index = || match channel.map(str::parse::<usize>) {
+            Some(Ok(number)) if number > 0 => Ok(number - 1),
+            _ => Err(format!("'{}' needs a channel number", kind)),
+        };
+        match kind {
+            "temp" => Ok(FaultTarget::Temperature(index()?)),
+            "pressure" => Ok(FaultTarget::Pressure(index()?)),
+            "motor" => Ok(FaultTarget::Motor(index()?)),
+            "plc" if channel.is_none() => Ok(FaultTarget::PlcLink),
+            "plc" => Err("the PLC link has no channel number".to_string()),
+            _ => Err(format!("unknown channel type '{}' (use temp, pressure, motor or plc)", kind)),
+        }
+    }
+
+    pub fn channel(kind: ChannelKind, index: usize) -> FaultTarget {
+        match kind {
+            ChannelKind::Temperature => FaultTarget::Temperature(index),
+            ChannelKind::Pressure => FaultTarget::Pressure(index),
+        }
+    }
+
+    // The kind of analog channel targeted; None for motors and the PLC link
+    pub fn channel_kind(&self) -> Option<ChannelKind> {
+        match self {
+            FaultTarget::Temperature(_) => Some(ChannelKind::Temperature),
+            FaultTarget::Pressure(_) => Some(ChannelKind::Pressure),
+            FaultTarget::Motor(_) | FaultTarget::PlcLink => None,
+        }
+    }
+
+    // Size of the random error during a noise burst, in the channel's units
+    fn noise_amplitude(&self) -> f32 {
+        match self {
+            FaultTarget::Temperature(_) => 3.0,
+            FaultTarget::Pressure(_) => 8.0,
+            FaultTarget::Motor(_) => 300.0,
+            FaultTarget::PlcLink => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for FaultTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultTarget::Temperature(index) => write!(f, "Temperature Sensor {}", index + 1),
+            FaultTarget::Pressure(index) => write!(f, "Pressure Gauge {}", index + 1),
+            FaultTarget::Motor(index) => write!(f, "Motor {}", index + 1),
+            FaultTarget::PlcLink => write!(f, "PLC link"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    // The reading freezes at its value when the fault was applied
+    Stuck,
+    Offset(f32),
+    // A large random error on every update
+    NoiseBurst,
+    // Broken wiring: analog channels read NaN, motor tachometers read 0 and the PLC link drops
+    OpenCircuit,
+    // A jammed load: the motor draws well over its full-load amps while running. Only
+    // the current is affected, so its overload relay is what gives it away.
+    Overload,
+    // The PLC link stops answering mid-poll and never returns, hanging the sensor thread
+    // until acquisition is restarted
+    Hang,
+    // A motor bearing wearing out: its vibration grows until the fault is cleared. The
+    // speed reading is unaffected.
+    BearingWear,
+}
+
+impl FaultKind {
+    pub fn parse(words: &[&str]) -> Result<FaultKind, String> {
+        match words {
+            ["stuck"] => Ok(FaultKind::Stuck),
+            ["offset", value] => value.parse()
+                .ok()
+                .filter(|value: &f32| value.is_finite())
+                .map(FaultKind::Offset)
+                .ok_or_else(|| format!("invalid offset '{}'", value)),
+            ["noise"] => Ok(FaultKind::NoiseBurst),
+            ["open"] | ["drop"] => Ok(FaultKind::OpenCircuit),
+            ["overload"] => Ok(FaultKind::Overload),
+            ["hang"] => Ok(FaultKind::Hang),
+            ["bearing"] => Ok(FaultKind::BearingWear),
+            _ => Err("fault type must be stuck, offset VALUE, noise, open, overload, bearing or hang".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultKind::Stuck => write!(f, "STUCK"),
+            FaultKind::Offset(offset) => write!(f, "OFFSET {:+.1}", offset),
+            FaultKind::NoiseBurst => write!(f, "NOISE BURST"),
+            FaultKind::OpenCircuit => write!(f, "OPEN CIRCUIT"),
+            FaultKind::Overload => write!(f, "OVERLOAD"),
+            FaultKind::Hang => write!(f, "HUNG"),
+            FaultKind::BearingWear => write!(f, "BEARING WEAR"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InjectedFault {
+    pub target: FaultTarget,
+    pub kind: FaultKind,
+    // The process value hidden behind the faulted reading
+    pub true_value: f32,
+    held_value: f32,
+    // When the console clears it by itself; None lasts until it is cleared
+    pub until: Option<Instant>,
+}
+
+impl InjectedFault {
+    pub fn new(target: FaultTarget, kind: FaultKind, true_value: f32, reading: f32) -> Self {
+        InjectedFault { target, kind, true_value, held_value: reading, until: None }
+    }
+
+    // What the console reads from the channel; noise is the random draw for a noise burst, in [-1, 1)
+    pub fn reading(&self, noise: f32) -> f32 {
+        match (self.kind, self.target) {
+            (FaultKind::Stuck, _) => self.held_value,
+            (FaultKind::Offset(offset), _) => self.true_value + offset,
+            (FaultKind::NoiseBurst, target) => self.true_value + noise * target.noise_amplitude(),
+            (FaultKind::OpenCircuit, FaultTarget::Motor(_)) => 0.0,
+            (FaultKind::OpenCircuit, _) => f32::NAN,
+            (FaultKind::Overload | FaultKind::Hang | FaultKind::BearingWear, _) => self.true_value,
+        }
+    }
+
+    pub fn next_reading(&self, rng: &mut Rng) -> f32 {
+        let noise = if self.kind == FaultKind::NoiseBurst { rng.random_f32() * 2.0 - 1.0 } else { 0.0 };
+        self.reading(noise)
+    }
+}
@@ -0,0 +1,58 @@
+// Radix-2 fast Fourier transform, for the vibration spectrum. In place, iterative,
+// Cooley-Tukey; small and plain rather than fast, as the frames are a thousand samples.
+use std::f32::consts::PI;
+
+// Transforms `re` and `im` in place. Their length must be a power of two.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n, "FFT length must be a power of two");
+
+    // Bit-reversed order, so the butterflies can work in place
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let angle = -2.0 * PI / size as f32;
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size <<= 1;
+    }
+}
+
+// Amplitude of each frequency bin up to half the sampling rate, from a Hann-windowed
+// frame, scaled so a sinusoid of amplitude A that falls on a bin reads A
+pub fn amplitude_spectrum(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let window: Vec<f32> = (0..n).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos()).collect();
+    let gain: f32 = window.iter().sum();
+    let mut re: Vec<f32> = samples.iter().zip(&window).map(|(sample, weight)| sample * weight).collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    (0..n / 2).map(|k| {
+        let magnitude = (re[k] * re[k] + im[k] * im[k]).sqrt() / gain;
+        // Every bin but DC has a mirror image above half the sampling rate
+        if k == 0 { magnitude } else { 2.0 * magnitude }
+    }).collect()
+}
@@ -0,0 +1,201 @@
+// Health of the link to the data source, judged from recent polls rather than the last
+// one alone: the round trip of each, how many were lost, and how many failed in a row.
+// The sensor thread records every poll; the console reports the link moving between
+// connected, degraded and offline.
+use crate::json::Value;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Polls the loss and latency figures cover
+pub const WINDOW: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkThresholds {
+    // Average round trip above which the link is degraded
+    pub degraded_latency: Duration,
+    // Share of the window's polls lost, in percent, at which it is degraded
+    pub degraded_loss_percent: u32,
+    // Failed polls in a row before it is offline
+    pub offline_failures: u32,
+}
+
+impl Default for LinkThresholds {
+    fn default() -> Self {
+        LinkThresholds { degraded_latency: Duration::from_millis(500), degraded_loss_percent: 10, offline_failures: 3 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkHealth {
+    Connected,
+    Degraded,
+    Offline,
+}
+
+impl LinkHealth {
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkHealth::Connected => "connected",
+            LinkHealth::Degraded => "degraded",
+            LinkHealth::Offline => "offline",
+        }
+    }
+}
+
+impl fmt::Display for LinkHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name().to_uppercase())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkStats {
+    pub thresholds: LinkThresholds,
+    // Round trip of each recent poll, oldest first; None for one that failed
+    recent: VecDeque<Option<Duration>>,
+    pub polls: u64,
+    pub failures: u64,
+    pub consecutive_successes: u32,
+    pub consecutive_failures: u32,
+    // Times the link has gone offline, and how long it was down each time: from the first
+    // failed poll to the next answer
+    pub disconnects: u64,
+    downtime: Duration,
+    failing_since: Option<Instant>,
+}
+
+impl LinkStats {
+    pub fn new(thresholds: LinkThresholds) -> Self {
+        LinkStats {
+            thresholds,
+            recent: VecDeque::with_capacity(WINDOW),
+            polls: 0,
+            failures: 0,
+            consecutive_successes: 0,
+            consecutive_failures: 0,
+            disconnects: 0,
+            downtime: Duration::ZERO,
+            failing_since: None,
+        }
+    }
+
+    pub fn record_success(&mut self, latency: Duration) {
+        self.push(Some(latency));
+        if let Some(since) = self.failing_since.take() {
+            if self.health() == LinkHealth::Offline {
+                self.downtime += since.elapsed();
+            }
+        }
+        self.consecutive_successes = self.consecutive_successes.saturating_add(1);
+        self.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.push(None);
+        self.failures += 1;
+        self.failing_since.get_or_insert_with(Instant::now);
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures == self.thresholds.offline_failures {
+            self.disconnects += 1;
+        }
+        self.consecutive_successes = 0;
+    }
+
+    fn push(&mut self, sample: Option<Duration>) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(sample);
+        self.polls += 1;
+    }
+
+    // The round trip of the latest poll that got an answer
+    pub fn last_latency(&self) -> Option<Duration> {
+        self.recent.iter().rev().find_map(|sample| *sample)
+    }
+
+    pub fn average_latency(&self) -> Option<Duration> {
+        let answered: Vec<Duration> = self.recent.iter().filter_map(|sample| *sample).collect();
+        if answered.is_empty() {
+            return None;
+        }
+        Some(answered.iter().sum::<Duration>() / answered.len() as u32)
+    }
+
+    pub fn max_latency(&self) -> Option<Duration> {
+        self.recent.iter().filter_map(|sample| *sample).max()
+    }
+
+    // Summed over every disconnect, including one still going on
+    pub fn downtime(&self) -> Duration {
+        match self.failing_since {
+            Some(since) if self.health() == LinkHealth::Offline => self.downtime + since.elapsed(),
+            _ => self.downtime,
+        }
+    }
+
+    // Over the window; 0 before the first poll
+    pub fn loss_percent(&self) -> f32 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        let lost = self.recent.iter().filter(|sample| sample.is_none()).count();
+        lost as f32 * 100.0 / self.recent.len() as f32
+    }
+
+    // Connected until the first poll has been made
+    pub fn health(&self) -> LinkHealth {
+        if self.consecutive_failures >= self.thresholds.offline_failures {
+            LinkHealth::Offline
+        } else if self.degraded_reason().is_some() {
+            LinkHealth::Degraded
+        } else {
+            LinkHealth::Connected
+        }
+    }
+
+    // What puts the link below connected, worst first
+    pub fn degraded_reason(&self) -> Option<String> {
+        if self.consecutive_failures > 0 {
+            return Some(format!("{} poll(s) in a row failed", self.consecutive_failures));
+        }
+        let loss = self.loss_percent();
+        if loss >= self.thresholds.degraded_loss_percent as f32 {
+            return Some(format!("{:.0}% of the last {} polls lost", loss, self.recent.len()));
+        }
+        match self.average_latency() {
+            Some(average) if average > self.thresholds.degraded_latency => {
+                Some(format!("average round trip {} ms (over {} ms)", average.as_millis(), self.thresholds.degraded_latency.as_millis()))
+            }
+            _ => None,
+        }
+    }
+
+    // The window's round trips as bars scaled to the slowest, oldest first; x marks a lost poll
+    pub fn sparkline(&self) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let slowest = self.max_latency().unwrap_or_default().as_secs_f32();
+        self.recent.iter().map(|sample| match sample {
+            None => 'x',
+            Some(_) if slowest <= 0.0 => BARS[0],
+            Some(latency) => BARS[((latency.as_secs_f32() / slowest * (BARS.len() - 1) as f32).round() as usize).min(BARS.len() - 1)],
+        }).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let millis = |latency: Option<Duration>| latency.map_or(Value::Null, |latency| (latency.as_secs_f64() * 1000.0).into());
+        Value::Object(vec![
+            ("health".to_string(), self.health().name().into()),
+            ("latency_ms".to_string(), millis(self.last_latency())),
+            ("average_latency_ms".to_string(), millis(self.average_latency())),
+            ("loss_percent".to_string(), self.loss_percent().into()),
+            ("polls".to_string(), self.polls.into()),
+            ("failures".to_string(), self.failures.into()),
+            ("consecutive_failures".to_string(), (self.consecutive_failures as u64).into()),
+            ("disconnects".to_string(), self.disconnects.into()),
+            ("downtime_s".to_string(), self.downtime().as_secs_f64().into()),
+        ])
+    }
+}
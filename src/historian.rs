@@ -0,0 +1,721 @@
+// Long-term historian: every sensor sample (with the measured value behind each forced
+// one), alarm, operator action and operator note appended to one CSV file per table per
+// UTC day, so a week of data can be charted after the in-memory history has rolled
+// over. Writes happen on a background thread and are flushed about once a second; old
+// days are deleted once they pass the retention.
+// With change-of-value recording, a sample field left empty holds the value above it,
+// and the readers fill it back in.
+use crate::alarms::AlarmLevel;
+use crate::clock::{format_datetime, parse_datetime};
+use crate::csv;
+use crate::history::{self, HistoryRecord, Sample};
+use crate::notes::Note;
+use crate::sensors::{ChannelKind, SensorData};
+use crate::units::{Unit, Units};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+// Samples that may queue up while the disk is slow; more than that are dropped
+const SAMPLE_QUEUE: usize = 256;
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DAY: Duration = Duration::from_secs(86_400);
+pub const MAX_HEARTBEAT: Duration = Duration::from_secs(60);
+// After an alarm transition every sample is written in full for this long
+const EXACT_AFTER: Duration = Duration::from_secs(10);
+// Gaps between rows up to a heartbeat, and a late sample at the slowest update interval,
+// hold the value above; a longer one is the console not running
+const HOLD_LIMIT: Duration = Duration::from_secs(65);
+// Held values are charted at the default update rate, so they weigh as much as recorded ones
+const HOLD_STEP: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistorianConfig {
+    pub directory: PathBuf,
+    pub retention_days: u64,
+    // None writes every sample in full
+    pub change_of_value: Option<ChangeOfValue>,
+}
+
+// Change-of-value recording: a reading is written once it has moved more than its
+// channel's dead-band from the value last written for it, the motors and interlocks when
+// they change, and a row where nothing did is left out. Every column is still written once
+// a heartbeat, and every sample in full around an alarm transition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeOfValue {
+    // °C and kPa, for the channels of a kind without one of their own; None writes every
+    // sample of them
+    pub temperature: Option<f32>,
+    pub pressure: Option<f32>,
+    // Per channel of each kind, in order
+    pub temperatures: Vec<f32>,
+    pub pressures: Vec<f32>,
+    // RPM; None writes every change
+    pub speed: Option<f32>,
+    pub heartbeat: Duration,
+}
+
+impl ChangeOfValue {
+    fn deadband(&self, kind: ChannelKind, index: usize) -> Option<f32> {
+        match kind {
+            ChannelKind::Temperature => self.temperatures.get(index).copied().or(self.temperature),
+            ChannelKind::Pressure => self.pressures.get(index).copied().or(self.pressure),
+        }
+    }
+
+    // How each field of history::csv_row is written
+    fn rules(&self, record: &HistoryRecord) -> Vec<Rule> {
+        let band = |deadband: Option<f32>| deadband.map_or(Rule::Always, Rule::Deadband);
+        let mut rules = vec![Rule::Always];
+        for (i, (kind, _)) in record.channels.iter().enumerate() {
+            let index = record.channels[..i].iter().filter(|(earlier, _)| earlier == kind).count();
+            rules.push(band(self.deadband(*kind, index)));
+        }
+        rules.extend(record.motor_speeds.iter().map(|_| self.speed.map_or(Rule::Change, Rule::Deadband)));
+        // The motors' states, then the interlocks
+        rules.extend((0..=record.motor_states.len()).map(|_| Rule::Change));
+        if record.ambient.is_some() {
+            rules.extend([band(self.temperature), band(self.pressure)]);
+        }
+        rules
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rule {
+    Always,
+    Change,
+    // Written once it has moved more than this
+    Deadband(f32),
+}
+
+impl Rule {
+    fn due(self, written: &str, field: &str) -> bool {
+        match self {
+            Rule::Always => true,
+            Rule::Deadband(deadband) => match (written.parse::<f32>(), field.parse::<f32>()) {
+                (Ok(written), Ok(value)) if written.is_finite() && value.is_finite() => (value - written).abs() > deadband,
+                // An open circuit is written as soon as it appears and clears
+                _ => written != field,
+            },
+            Rule::Change => written != field,
+        }
+    }
+}
+
+// Each channel's alarm and rate-of-change levels, and whether the system is shut down
+type AlarmStates = (Vec<(AlarmLevel, AlarmLevel)>, bool);
+
+fn alarm_states(data: &SensorData) -> AlarmStates {
+    let levels = data.channels.iter().map(|channel| (channel.alarm.level(), channel.rate.level())).collect();
+    (levels, data.emergency_shutdown || data.auto_trip.is_some())
+}
+
+// What change-of-value recording has written
+struct Recorder {
+    config: ChangeOfValue,
+    // Each field last written to the open samples file; empty until its first row, which
+    // is written in full
+    written: Vec<String>,
+    // When a row was last written in full
+    full_at: Option<SystemTime>,
+    exact_until: Option<SystemTime>,
+    // The latest sample, held back until the next arrives so that it can be written in
+    // full if the next is an alarm transition
+    pending: Option<(HistoryRecord, AlarmStates)>,
+}
+
+impl Recorder {
+    // Empties the fields of `row` that needn't be written; false if none is left
+    fn filter(&mut self, record: &HistoryRecord, row: &mut [String], exact: bool) -> bool {
+        let heartbeat = self.full_at.map_or(true, |at| record.time.duration_since(at).map_or(true, |since| since >= self.config.heartbeat));
+        let exact = exact || self.exact_until.is_some_and(|until| record.time <= until);
+        if exact || heartbeat || self.written.len() != row.len() {
+            self.written = row.to_vec();
+            self.full_at = Some(record.time);
+            return true;
+        }
+        let mut changed = false;
+        // The timestamp is always written
+        for ((field, written), rule) in row.iter_mut().zip(&mut self.written).zip(self.config.rules(record)).skip(1) {
+            if rule.due(written, field) {
+                written.clone_from(field);
+                changed = true;
+            } else {
+                field.clear();
+            }
+        }
+        changed
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Table {
+    Samples,
+    Alarms,
+    Actions,
+    Forces,
+    Notes,
+}
+
+impl Table {
+    const ALL: [Table; 5] = [Table::Samples, Table::Alarms, Table::Actions, Table::Forces, Table::Notes];
+
+    fn file_name(self, day: &str) -> String {
+        let name = match self {
+            Table::Samples => "samples",
+            Table::Alarms => "alarms",
+            Table::Actions => "actions",
+            Table::Forces => "forces",
+            Table::Notes => "notes",
+        };
+        format!("{}-{}.csv", day, name)
+    }
+}
+
+// Owns the writer thread. Dropping it writes out everything still queued.
+pub struct Historian {
+    directory: PathBuf,
+    retention_days: u64,
+    // With change-of-value recording
+    heartbeat: Option<Duration>,
+    // Whole rows, timestamp first
+    events: Sender<(Table, SystemTime, Vec<String>)>,
+    progress: Arc<Progress>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// How far behind the writer thread is, for the performance screen
+#[derive(Default)]
+struct Progress {
+    // Rows taken in but not yet flushed to disk; samples still queued for the writer
+    // aren't known until it takes them
+    backlog: AtomicUsize,
+    // How old the newest sample was when it was written, in milliseconds
+    lag_ms: AtomicU64,
+}
+
+impl Historian {
+    // Fails if the directory can't be created or written to. Later write failures are
+    // reported once through `reports` instead.
+    pub fn start(config: HistorianConfig, reports: Sender<Result<String, String>>) -> io::Result<(Historian, SyncSender<SensorData>)> {
+        let recorder = config.change_of_value.clone().map(|config| Recorder { config, written: Vec::new(), full_at: None, exact_until: None, pending: None });
+        let mut store = Store { directory: config.directory.clone(), retention_days: config.retention_days, day: String::new(), files: Vec::new(), recorder };
+        store.open_day(SystemTime::now())?;
+
+        let (updates, samples) = mpsc::sync_channel(SAMPLE_QUEUE);
+        let (events, queued) = mpsc::channel();
+        let progress = Arc::new(Progress::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_progress = Arc::clone(&progress);
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || run_writer(store, samples, queued, reports, thread_progress, thread_stop));
+
+        let heartbeat = config.change_of_value.map(|change_of_value| change_of_value.heartbeat);
+        let historian = Historian { directory: config.directory, retention_days: config.retention_days, heartbeat, events, progress, stop, thread: Some(thread) };
+        Ok((historian, updates))
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn retention_days(&self) -> u64 {
+        self.retention_days
+    }
+
+    // None while every sample is written in full
+    pub fn heartbeat(&self) -> Option<Duration> {
+        self.heartbeat
+    }
+
+    pub fn record_alarm(&self, message: &str) {
+        self.progress.backlog.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now();
+        let _ = self.events.send((Table::Alarms, now, vec![format_datetime(now), message.to_string()]));
+    }
+
+    pub fn record_action(&self, action: &str) {
+        self.progress.backlog.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now();
+        let _ = self.events.send((Table::Actions, now, vec![format_datetime(now), action.to_string()]));
+    }
+
+    pub fn record_note(&self, note: &Note) {
+        self.progress.backlog.fetch_add(1, Ordering::Relaxed);
+        let _ = self.events.send((Table::Notes, note.time, note.to_row()));
+    }
+
+    // Rows waiting to be written out
+    pub fn backlog(&self) -> usize {
+        self.progress.backlog.load(Ordering::Relaxed)
+    }
+
+    // How long after it was taken the newest sample was written
+    pub fn lag(&self) -> Duration {
+        Duration::from_millis(self.progress.lag_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Historian {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_writer(
+    mut store: Store,
+    samples: Receiver<SensorData>,
+    events: Receiver<(Table, SystemTime, Vec<String>)>,
+    reports: Sender<Result<String, String>>,
+    progress: Arc<Progress>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut last_flush = Instant::now();
+    let mut failing = false;
+    // Rows written to the buffers since the last flush
+    let mut unflushed = 0;
+    loop {
+        let stopping = stop.load(Ordering::Relaxed);
+        let mut result = Ok(());
+        match samples.recv_timeout(Duration::from_millis(200)) {
+            Ok(data) => {
+                progress.backlog.fetch_add(1, Ordering::Relaxed);
+                let record = HistoryRecord::capture(&data, data.last_update.unwrap_or_else(SystemTime::now));
+                result = store.append_sample(&record, alarm_states(&data));
+                unflushed += 1;
+                // The samples table has what the logic saw; this keeps what the instrument said
+                for channel in data.channels.iter().filter(|channel| channel.forced.is_some()) {
+                    result = result.and(store.append_force(record.time, &channel.name, channel.measured(), channel.value));
+                    unflushed += 1;
+                }
+                let lag = SystemTime::now().duration_since(record.time).unwrap_or_default();
+                progress.lag_ms.store(lag.as_millis() as u64, Ordering::Relaxed);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) if stopping => {}
+            // The sensor thread is gone, but alarms and actions can still arrive
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(200)),
+        }
+        for (table, time, row) in events.try_iter() {
+            result = result.and(store.append_event(table, time, &row));
+            unflushed += 1;
+        }
+        if stopping {
+            result = result.and(store.finish());
+        }
+        if stopping || last_flush.elapsed() >= FLUSH_INTERVAL {
+            result = result.and(store.flush());
+            last_flush = Instant::now();
+            progress.backlog.fetch_sub(std::mem::take(&mut unflushed), Ordering::Relaxed);
+        }
+
+        // Reopened on the next write, in case the directory was removed or the disk replaced;
+        // what was buffered is lost
+        if result.is_err() {
+            store.files.clear();
+            progress.backlog.fetch_sub(std::mem::take(&mut unflushed), Ordering::Relaxed);
+        }
+        // Reported once when writing starts failing and once when it recovers
+        match result {
+            Err(e) if !failing => {
+                failing = true;
+                let _ = reports.send(Err(format!("Historian write to {} failed: {} - history is not being recorded", store.directory.display(), e)));
+            }
+            Ok(()) if failing => {
+                failing = false;
+                let _ = reports.send(Ok(format!("Historian writing to {} again", store.directory.display())));
+            }
+            _ => {}
+        }
+        if stopping {
+            return;
+        }
+    }
+}
+
+// The open files for the current UTC day
+struct Store {
+    directory: PathBuf,
+    retention_days: u64,
+    day: String,
+    files: Vec<(Table, BufWriter<File>)>,
+    recorder: Option<Recorder>,
+}
+
+impl Store {
+    // Switches to the files for `time`'s day, creating them with a header if needed
+    fn open_day(&mut self, time: SystemTime) -> io::Result<()> {
+        let day = day_of(time);
+        if day == self.day && !self.files.is_empty() {
+            return Ok(());
+        }
+        self.flush()?;
+        self.files.clear();
+        fs::create_dir_all(&self.directory)?;
+        for table in [Table::Alarms, Table::Actions] {
+            let file = self.open(table, &day, &["timestamp".to_string(), "message".to_string()])?;
+            self.files.push((table, file));
+        }
+        self.day = day;
+        self.prune();
+        Ok(())
+    }
+
+    fn open(&self, table: Table, day: &str, header: &[String]) -> io::Result<BufWriter<File>> {
+        let path = self.directory.join(table.file_name(day));
+        let is_new = fs::metadata(&path).map_or(true, |metadata| metadata.len() == 0);
+        let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+        if is_new {
+            csv::write_row(&mut file, header)?;
+        }
+        Ok(file)
+    }
+
+    fn append_sample(&mut self, record: &HistoryRecord, alarms: AlarmStates) -> io::Result<()> {
+        let recorder = match &mut self.recorder {
+            Some(recorder) => recorder,
+            None => return self.write_sample(record, true),
+        };
+        // An alarm transition is written in full, with the sample before it and those after
+        let transition = recorder.pending.as_ref().is_some_and(|(_, before)| *before != alarms);
+        if transition {
+            recorder.exact_until = Some(record.time + EXACT_AFTER);
+        }
+        match recorder.pending.replace((record.clone(), alarms)) {
+            Some((previous, _)) => self.write_sample(&previous, transition),
+            None => Ok(()),
+        }
+    }
+
+    // Writes the sample change-of-value recording is holding back
+    fn finish(&mut self) -> io::Result<()> {
+        match self.recorder.as_mut().and_then(|recorder| recorder.pending.take()) {
+            Some((record, _)) => self.write_sample(&record, false),
+            None => Ok(()),
+        }
+    }
+
+    fn write_sample(&mut self, record: &HistoryRecord, exact: bool) -> io::Result<()> {
+        self.open_day(record.time)?;
+        // The column count depends on the configuration, so the header waits for the first sample.
+        // History is stored in SI whatever the operator is viewing.
+        if !self.files.iter().any(|(table, _)| *table == Table::Samples) {
+            let file = self.open(Table::Samples, &self.day, &history::csv_header(Some(record), Units::default()))?;
+            self.files.push((Table::Samples, file));
+            // A new day's file, or one reopened after a failed write, starts in full
+            if let Some(recorder) = &mut self.recorder {
+                recorder.written.clear();
+            }
+        }
+        let mut row = history::csv_row(record, Units::default());
+        if let Some(recorder) = &mut self.recorder {
+            if !recorder.filter(record, &mut row, exact) {
+                return Ok(());
+            }
+        }
+        self.append(Table::Samples, record.time, &row)
+    }
+
+    // Created with the first forced channel, so most days have no forces file
+    fn append_force(&mut self, time: SystemTime, channel: &str, measured: f32, forced: f32) -> io::Result<()> {
+        self.open_day(time)?;
+        if !self.files.iter().any(|(table, _)| *table == Table::Forces) {
+            let header = ["timestamp", "channel", "measured", "forced"].map(String::from);
+            let file = self.open(Table::Forces, &self.day, &header)?;
+            self.files.push((Table::Forces, file));
+        }
+        self.append(Table::Forces, time, &[format_datetime(time), channel.to_string(), measured.to_string(), forced.to_string()])
+    }
+
+    // Alarms and actions go in files opened with the day; notes in one created with the
+    // first note, so most days have no notes file
+    fn append_event(&mut self, table: Table, time: SystemTime, row: &[String]) -> io::Result<()> {
+        self.open_day(time)?;
+        if table == Table::Notes && !self.files.iter().any(|(open, _)| *open == Table::Notes) {
+            let file = self.open(Table::Notes, &self.day, &Note::HEADER.map(String::from))?;
+            self.files.push((Table::Notes, file));
+        }
+        self.append(table, time, row)
+    }
+
+    fn append(&mut self, table: Table, time: SystemTime, row: &[String]) -> io::Result<()> {
+        self.open_day(time)?;
+        match self.files.iter_mut().find(|(open, _)| *open == table) {
+            Some((_, file)) => csv::write_row(file, row),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.files.iter_mut().try_for_each(|(_, file)| file.flush())
+    }
+
+    // Deletes whole days older than the retention period
+    fn prune(&self) {
+        let cutoff = day_of(SystemTime::now() - DAY * self.retention_days as u32);
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let expired = Table::ALL.iter().any(|table| name.get(..10).is_some_and(|day| table.file_name(day) == name && day < cutoff.as_str()));
+            if expired {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+// YYYY-MM-DD in UTC; also sorts chronologically as text
+fn day_of(time: SystemTime) -> String {
+    format_datetime(time)[..10].to_string()
+}
+
+// Reads one channel's samples between `since` and `until` back from the daily files. A
+// value holds until the next row, so gaps left by change-of-value recording between two
+// stored rows are filled with it. Nothing is filled in after the last stored row: past
+// that the value isn't known.
+pub fn query(directory: &Path, kind: ChannelKind, index: usize, since: SystemTime, until: SystemTime) -> io::Result<Vec<Sample>> {
+    let column_name = history::csv_column(kind, index, Unit::si(kind));
+    let mut samples = Vec::new();
+    scan_samples(directory, since.checked_sub(HOLD_LIMIT).unwrap_or(since), until, |header, time, fields| {
+        // Open-circuit readings were written as NaN and stay gaps in the chart
+        let value = header.iter().position(|name| *name == column_name).and_then(|column| fields[column].parse().ok());
+        if let Some(value) = value {
+            hold(&mut samples, time);
+            samples.push(Sample { time, value });
+        }
+    })?;
+    samples.retain(|sample| sample.time >= since);
+    Ok(samples)
+}
+
+// Repeats the last sample every HOLD_STEP up to the row at `until`, unless the gap is too
+// long to be held
+fn hold(samples: &mut Vec<Sample>, until: SystemTime) {
+    let last = match samples.last() {
+        Some(last) => *last,
+        None => return,
+    };
+    if until.duration_since(last.time).map_or(true, |gap| gap > HOLD_LIMIT) {
+        return;
+    }
+    let mut time = last.time + HOLD_STEP;
+    while time + HOLD_STEP / 2 < until {
+        samples.push(Sample { time, value: last.value });
+        time += HOLD_STEP;
+    }
+}
+
+// Calls `visit` with the header, time and fields of every sample row between `since` and
+// `until`, oldest first. Readings are in SI, under the names history::csv_header gives;
+// empty fields have the value above them filled in.
+pub fn scan_samples(directory: &Path, since: SystemTime, until: SystemTime, mut visit: impl FnMut(&[String], SystemTime, &[&str])) -> io::Result<()> {
+    let mut day = since;
+    while day_of(day) <= day_of(until) {
+        let path = directory.join(Table::Samples.file_name(&day_of(day)));
+        day += DAY;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut lines = BufReader::new(file).lines();
+        let header: Vec<String> = match lines.next() {
+            Some(line) => line?.split(',').map(str::to_string).collect(),
+            None => continue,
+        };
+        // Each field's latest value in the file, since each file starts with a full row
+        let mut held = vec![String::new(); header.len()];
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split(',').collect();
+            // Rows written under a different channel layout don't line up with the header
+            if fields.len() != header.len() {
+                continue;
+            }
+            for (field, value) in fields.iter().zip(&mut held).filter(|(field, _)| !field.is_empty()) {
+                *value = field.to_string();
+            }
+            let fields: Vec<&str> = held.iter().map(String::as_str).collect();
+            match parse_datetime(fields[0]) {
+                Some(time) if time >= since && time <= until => visit(&header, time, &fields),
+                _ => continue,
+            }
+        }
+    }
+    Ok(())
+}
+
+// Reads the alarm lines logged between `since` and `until` back from the daily files,
+// oldest first
+pub fn query_alarms(directory: &Path, since: SystemTime, until: SystemTime) -> io::Result<Vec<(SystemTime, String)>> {
+    let mut alarms = Vec::new();
+    let mut day = since;
+    while day_of(day) <= day_of(until) {
+        let path = directory.join(Table::Alarms.file_name(&day_of(day)));
+        day += DAY;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        // The first line is the header
+        for line in BufReader::new(file).lines().skip(1) {
+            let fields = csv::parse_row(&line?);
+            let time = match fields.first().and_then(|time| parse_datetime(time)) {
+                Some(time) if time >= since && time <= until => time,
+                _ => continue,
+            };
+            if let Some(message) = fields.get(1) {
+                alarms.push((time, message.clone()));
+            }
+        }
+    }
+    Ok(alarms)
+}
+
+// Reads the notes written between `since` and `until` back from the daily files, oldest
+// first
+pub fn query_notes(directory: &Path, since: SystemTime, until: SystemTime) -> io::Result<Vec<Note>> {
+    let mut notes = Vec::new();
+    let mut day = since;
+    while day_of(day) <= day_of(until) {
+        let path = directory.join(Table::Notes.file_name(&day_of(day)));
+        day += DAY;
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for line in BufReader::new(file).lines().skip(1) {
+            if let Some(note) = Note::from_row(&csv::parse_row(&line?)).filter(|note| note.time >= since && note.time <= until) {
+                notes.push(note);
+            }
+        }
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    const DEADBAND: f32 = 0.5;
+    // 2023-11-14T22:13:20Z, an hour clear of midnight
+    const START: u64 = 1_700_000_000;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("forlenza-historian-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn store(directory: &Path, change_of_value: Option<ChangeOfValue>) -> Store {
+        let recorder = change_of_value.map(|config| Recorder { config, written: Vec::new(), full_at: None, exact_until: None, pending: None });
+        Store { directory: directory.to_path_buf(), retention_days: 36_500, day: String::new(), files: Vec::new(), recorder }
+    }
+
+    // An hour at one sample a second: a slow temperature swing with a little noise, a
+    // steady pressure and a motor running at speed
+    fn recording() -> Vec<HistoryRecord> {
+        let mut noise: u32 = 12345;
+        (0..3600u64).map(|second| {
+            noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let jitter = (noise >> 16) as f32 / 65536.0 * 0.2 - 0.1;
+            let temperature = 40.0 + 5.0 * (second as f32 / 600.0 * std::f32::consts::TAU).sin() + jitter;
+            HistoryRecord {
+                time: UNIX_EPOCH + Duration::from_secs(START + second),
+                channels: vec![(ChannelKind::Temperature, temperature), (ChannelKind::Pressure, 101_325.0 + jitter)],
+                motor_speeds: vec![1500],
+                motor_states: vec![true],
+                motor_power: vec![5.5],
+                safety_interlocks: true,
+                ambient: None,
+            }
+        }).collect()
+    }
+
+    fn record(directory: &Path, change_of_value: Option<ChangeOfValue>, records: &[HistoryRecord]) -> u64 {
+        let mut store = store(directory, change_of_value);
+        let alarms: AlarmStates = (vec![(AlarmLevel::Normal, AlarmLevel::Normal); 2], false);
+        for record in records {
+            store.append_sample(record, alarms.clone()).unwrap();
+        }
+        store.finish().unwrap();
+        store.flush().unwrap();
+        fs::metadata(directory.join(Table::Samples.file_name(&day_of(records[0].time)))).unwrap().len()
+    }
+
+    fn last_row_time(directory: &Path, day: &str) -> SystemTime {
+        let text = fs::read_to_string(directory.join(Table::Samples.file_name(day))).unwrap();
+        parse_datetime(text.lines().last().unwrap().split(',').next().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn change_of_value_is_smaller_and_reconstructs_within_the_deadband() {
+        let records = recording();
+        let (full, reduced) = (temp_dir("full"), temp_dir("reduced"));
+        let change_of_value = ChangeOfValue {
+            temperature: Some(DEADBAND),
+            pressure: Some(100.0),
+            temperatures: Vec::new(),
+            pressures: Vec::new(),
+            speed: Some(5.0),
+            heartbeat: Duration::from_secs(30),
+        };
+        let full_size = record(&full, None, &records);
+        let reduced_size = record(&reduced, Some(change_of_value), &records);
+        assert!(reduced_size * 4 < full_size, "{} bytes against {} at full rate", reduced_size, full_size);
+
+        let (since, until) = (records[0].time, records[records.len() - 1].time);
+        let exact = query(&full, ChannelKind::Temperature, 0, since, until).unwrap();
+        let held = query(&reduced, ChannelKind::Temperature, 0, since, until).unwrap();
+        assert_eq!(exact.len(), records.len());
+        // Every second up to the last stored row, each within the dead-band (and the
+        // rounding of the file) of what was measured
+        let last = last_row_time(&reduced, &day_of(since));
+        assert_eq!(held.last().map(|sample| sample.time), Some(last));
+        let covered: Vec<&Sample> = exact.iter().filter(|sample| sample.time <= last).collect();
+        assert_eq!(held.len(), covered.len());
+        for (held, exact) in held.iter().zip(covered) {
+            assert_eq!(held.time, exact.time);
+            assert!((held.value - exact.value).abs() <= DEADBAND + 0.01, "{} held against {} measured", held.value, exact.value);
+        }
+
+        let _ = fs::remove_dir_all(&full);
+        let _ = fs::remove_dir_all(&reduced);
+    }
+
+    #[test]
+    fn nothing_is_held_past_the_last_row() {
+        let records = recording();
+        let directory = temp_dir("tail");
+        record(&directory, None, &records[..10]);
+        let since = records[0].time;
+        let samples = query(&directory, ChannelKind::Temperature, 0, since, since + Duration::from_secs(600)).unwrap();
+        assert_eq!(samples.len(), 10);
+        assert_eq!(samples.last().map(|sample| sample.time), Some(records[9].time));
+        // A window starting after the recording has nothing in it
+        assert!(query(&directory, ChannelKind::Temperature, 0, records[9].time + Duration::from_secs(1), since + Duration::from_secs(600)).unwrap().is_empty());
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,311 @@
+// Fixed-capacity sample history so memory stays bounded however long the system runs.
+use crate::clock::format_datetime;
+use crate::csv;
+use crate::sensors::{ChannelKind, SensorData};
+use crate::units::{Unit, Units};
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::SystemTime;
+
+// 15 minutes at the default 1 Hz update rate
+pub const HISTORY_CAPACITY: usize = 900;
+
+// Records used to measure the effective update rate
+const RATE_WINDOW: usize = 10;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub value: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryRecord {
+    pub time: SystemTime,
+    // Every analog channel's kind and reading, in the sensor data's order
+    pub channels: Vec<(ChannelKind, f32)>,
+    pub motor_speeds: Vec<u16>,
+    pub motor_states: Vec<bool>,
+    // kW
+    pub motor_power: Vec<f32>,
+    pub safety_interlocks: bool,
+    // Air temperature and barometric pressure, while the simulation has them
+    pub ambient: Option<(f32, f32)>,
+}
+
+impl HistoryRecord {
+    pub fn capture(data: &SensorData, time: SystemTime) -> Self {
+        HistoryRecord {
+            time,
+            channels: data.channels.iter().map(|channel| (channel.kind, channel.value)).collect(),
+            motor_speeds: data.motors.iter().map(|motor| motor.speed).collect(),
+            motor_states: data.motors.iter().map(|motor| motor.running).collect(),
+            motor_power: data.motors.iter().map(|motor| data.supply.power(motor.current)).collect(),
+            safety_interlocks: data.interlocks_satisfied(),
+            ambient: data.ambient.as_ref().map(|ambient| (ambient.temperature, ambient.pressure)),
+        }
+    }
+
+    // The reading of the index-th channel of a kind
+    pub fn value(&self, kind: ChannelKind, index: usize) -> Option<f32> {
+        self.channels.iter().filter(|(channel_kind, _)| *channel_kind == kind).nth(index).map(|(_, value)| *value)
+    }
+}
+
+#[derive(Clone)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer { items: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(item);
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct SensorHistory {
+    records: RingBuffer<HistoryRecord>,
+}
+
+impl SensorHistory {
+    pub fn new() -> Self {
+        SensorHistory { records: RingBuffer::new(HISTORY_CAPACITY) }
+    }
+
+    pub fn record(&mut self, record: HistoryRecord) {
+        self.records.push(record);
+    }
+
+    // Copies out only the samples newer than `since` so callers hold the lock briefly
+    pub fn window(&self, kind: ChannelKind, index: usize, since: SystemTime) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = self.records.iter().rev()
+            .take_while(|record| record.time >= since)
+            .filter_map(|record| record.value(kind, index).map(|value| Sample { time: record.time, value }))
+            .collect();
+        samples.reverse();
+        samples
+    }
+
+    // A motor's power draw in kW, as `window` gives a channel's readings
+    pub fn motor_power(&self, motor: usize, since: SystemTime) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = self.records.iter().rev()
+            .take_while(|record| record.time >= since)
+            .filter_map(|record| record.motor_power.get(motor).map(|value| Sample { time: record.time, value: *value }))
+            .collect();
+        samples.reverse();
+        samples
+    }
+
+    // Updates per second actually achieved over the most recent records
+    pub fn update_rate(&self) -> Option<f32> {
+        let times: Vec<SystemTime> = self.records.iter().rev().take(RATE_WINDOW).map(|record| record.time).collect();
+        let span = times.first()?.duration_since(*times.last()?).ok()?.as_secs_f32();
+        if span > 0.0 {
+            Some((times.len() - 1) as f32 / span)
+        } else {
+            None
+        }
+    }
+
+    pub fn records(&self) -> Vec<HistoryRecord> {
+        self.records.iter().cloned().collect()
+    }
+}
+
+impl Default for SensorHistory {
+    fn default() -> Self {
+        SensorHistory::new()
+    }
+}
+
+// The valid sample closest in time to `time`, for reading a chart at a cursor
+// The samples from `since` to `until` as `width` bars scaled between their lowest and
+// highest, oldest on the left. Each bar averages its slice of the window; x marks a slice
+// with no valid reading and a space one with no samples at all.
+pub fn sparkline(samples: &[Sample], since: SystemTime, until: SystemTime, width: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let span = until.duration_since(since).unwrap_or_default().as_secs_f32();
+    let mut slices = vec![(0.0, 0, 0); width];
+    for sample in samples {
+        let offset = sample.time.duration_since(since).map_or(-1.0, |offset| offset.as_secs_f32());
+        if span <= 0.0 || !(0.0..=span).contains(&offset) {
+            continue;
+        }
+        let slice = &mut slices[((offset / span * width as f32) as usize).min(width - 1)];
+        slice.2 += 1;
+        if sample.value.is_finite() {
+            slice.0 += sample.value;
+            slice.1 += 1;
+        }
+    }
+    let averages: Vec<Option<f32>> = slices.iter().map(|(sum, valid, _)| (*valid > 0).then(|| sum / *valid as f32)).collect();
+    let low = averages.iter().flatten().copied().fold(f32::INFINITY, f32::min);
+    let high = averages.iter().flatten().copied().fold(f32::NEG_INFINITY, f32::max);
+    slices.iter().zip(averages).map(|((_, _, count), average)| match average {
+        None if *count == 0 => ' ',
+        None => 'x',
+        Some(_) if high <= low => BARS[BARS.len() / 2],
+        Some(average) => BARS[(((average - low) / (high - low) * (BARS.len() - 1) as f32).round() as usize).min(BARS.len() - 1)],
+    }).collect()
+}
+
+pub fn nearest(samples: &[Sample], time: SystemTime) -> Option<Sample> {
+    let distance = |sample: &Sample| sample.time.duration_since(time).unwrap_or_else(|e| e.duration());
+    samples.iter().filter(|sample| sample.value.is_finite()).min_by_key(|sample| distance(sample)).copied()
+}
+
+// One row per record: timestamp, then every channel in a fixed column order,
+// converted to `units`; the column names say which, followed by the tag and name in
+// brackets from `labels` (channels, then motors), e.g. "temperature_1_C (TT-101 Reactor
+// jacket)". Replay reads the column name and ignores the rest.
+pub fn write_csv(path: &str, records: &[HistoryRecord], units: Units, labels: &[String]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let header = csv_header(records.first(), units);
+    let (channels, motors) = records.first().map_or((0, 0), |r| (r.channels.len(), r.motor_speeds.len()));
+    let header: Vec<String> = header.iter().enumerate().map(|(i, column)| {
+        // The timestamp comes first; each motor has a speed and a running column
+        let label = match i {
+            0 => None,
+            i if i <= channels => labels.get(i - 1),
+            i if i <= channels + 2 * motors => labels.get(channels + (i - channels - 1) % motors),
+            _ => None,
+        };
+        label.map_or_else(|| column.clone(), |label| format!("{} ({})", column, label))
+    }).collect();
+    csv::write_row(&mut writer, &header)?;
+    for record in records {
+        csv::write_row(&mut writer, &csv_row(record, units))?;
+    }
+    writer.flush()
+}
+
+// Column names for records shaped like `first`
+pub fn csv_header(first: Option<&HistoryRecord>, units: Units) -> Vec<String> {
+    let mut header = vec!["timestamp".to_string()];
+    let channels = first.map_or(&[][..], |r| &r.channels);
+    for (i, (kind, _)) in channels.iter().enumerate() {
+        let index = channels[..i].iter().filter(|(earlier, _)| earlier == kind).count();
+        header.push(csv_column(*kind, index, units.get(*kind)));
+    }
+    header.extend((1..=first.map_or(0, |r| r.motor_speeds.len())).map(|i| format!("motor_{}_rpm", i)));
+    header.extend((1..=first.map_or(0, |r| r.motor_states.len())).map(|i| format!("motor_{}_running", i)));
+    header.push("safety_interlocks".to_string());
+    if first.is_some_and(|r| r.ambient.is_some()) {
+        header.extend(ChannelKind::ALL.map(|kind| ambient_column(kind, units.get(kind))));
+    }
+    header
+}
+
+// One place more than the display so exports don't lose resolution
+pub fn csv_row(record: &HistoryRecord, units: Units) -> Vec<String> {
+    let mut row = vec![format_datetime(record.time)];
+    row.extend(record.channels.iter().map(|(kind, value)| {
+        let unit = units.get(*kind);
+        format!("{:.*}", unit.decimals() + 1, unit.from_si(*value))
+    }));
+    row.extend(record.motor_speeds.iter().map(|value| value.to_string()));
+    row.extend(record.motor_states.iter().map(|value| value.to_string()));
+    row.push(record.safety_interlocks.to_string());
+    if let Some((temperature, pressure)) = record.ambient {
+        for (kind, value) in [(ChannelKind::Temperature, temperature), (ChannelKind::Pressure, pressure)] {
+            let unit = units.get(kind);
+            row.push(format!("{:.*}", unit.decimals() + 1, unit.from_si(value)));
+        }
+    }
+    row
+}
+
+// Column name csv_header gives the index-th channel of a kind, e.g. pressure_2_psi
+pub fn csv_column(kind: ChannelKind, index: usize, unit: Unit) -> String {
+    format!("{}_{}_{}", kind, index + 1, unit.column_suffix())
+}
+
+// Column name of the air temperature or barometric pressure, e.g. ambient_pressure_kPa
+pub fn ambient_column(kind: ChannelKind, unit: Unit) -> String {
+    format!("ambient_{}_{}", kind, unit.column_suffix())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::parse_datetime;
+
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn record(second: u64, temperature: f32, running: bool) -> HistoryRecord {
+        HistoryRecord {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000 + second),
+            channels: vec![(ChannelKind::Temperature, temperature), (ChannelKind::Temperature, 22.0), (ChannelKind::Pressure, 101.3)],
+            motor_speeds: vec![if running { 1750 } else { 0 }],
+            motor_states: vec![running],
+            motor_power: vec![0.0],
+            safety_interlocks: true,
+            ambient: None,
+        }
+    }
+
+    #[test]
+    fn csv_export_reads_back() {
+        let path = std::env::temp_dir().join(format!("forlenza-history-{}.csv", std::process::id()));
+        let records = [record(0, 23.5, true), record(1, 23.55, true), record(2, 23.6, false)];
+        // Labels with a comma and a quote have to be quoted
+        let labels = ["TT-101 Reactor jacket, north".to_string(), "TT-102 \"B\" side".to_string(), "PT-201".to_string(), "M-1".to_string()];
+        let units = Units { temperature: Unit::Fahrenheit, pressure: Unit::Psi };
+        write_csv(&path.to_string_lossy(), &records, units, &labels).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert!(text.ends_with("\r\n") && !text.replace("\r\n", "").contains('\n'), "rows end in CRLF");
+        let rows: Vec<Vec<String>> = text.lines().map(csv::parse_row).collect();
+        assert_eq!(rows[0], [
+            "timestamp", "temperature_1_f (TT-101 Reactor jacket, north)", "temperature_2_f (TT-102 \"B\" side)", "pressure_1_psi (PT-201)",
+            "motor_1_rpm (M-1)", "motor_1_running (M-1)", "safety_interlocks",
+        ]);
+        assert_eq!(rows.len(), records.len() + 1);
+        for (row, record) in rows[1..].iter().zip(&records) {
+            assert_eq!(parse_datetime(&row[0]), Some(record.time));
+            let fahrenheit: f32 = row[1].parse().unwrap();
+            assert!((fahrenheit - (record.channels[0].1 * 9.0 / 5.0 + 32.0)).abs() < 0.01, "{}", row[1]);
+            let psi: f32 = row[3].parse().unwrap();
+            assert!((psi - 14.69).abs() < 0.01, "{}", row[3]);
+            assert_eq!(row[4], record.motor_speeds[0].to_string());
+            assert_eq!(row[5], record.motor_states[0].to_string());
+            assert_eq!(row[6], "true");
+        }
+    }
+
+    #[test]
+    fn ring_buffer_drops_the_oldest() {
+        let mut buffer = RingBuffer::new(3);
+        assert!(buffer.is_empty());
+        for line in 1..=5 {
+            buffer.push(line);
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.iter().copied().collect::<Vec<_>>(), [3, 4, 5]);
+    }
+}
@@ -0,0 +1,164 @@
+// Console language. User-facing text is looked up by key in a table per language,
+// embedded from locales/<code>.txt, and numbers are shown with that language's decimal
+// separator. Records kept for other programs - the audit trail, the historian, exports,
+// telemetry and the log file's tags - stay as they are whatever the language.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    English,
+    Spanish,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::English, Language::Spanish];
+
+    pub fn parse(code: &str) -> Result<Language, String> {
+        Language::ALL.into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(code) || language.name().eq_ignore_ascii_case(code))
+            .ok_or_else(|| format!("Unknown language '{}' (use en or es)", code))
+    }
+
+    // As typed and stored, e.g. "es"
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+        }
+    }
+
+    // In the language itself
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+        }
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Language::English => include_str!("../locales/en.txt"),
+            Language::Spanish => include_str!("../locales/es.txt"),
+        }
+    }
+
+    fn decimal_comma(self) -> bool {
+        self == Language::Spanish
+    }
+
+    // "key = text" lines; blank lines and lines starting with # are skipped
+    fn table(self) -> &'static HashMap<&'static str, String> {
+        static TABLES: [OnceLock<HashMap<&'static str, String>>; 2] = [OnceLock::new(), OnceLock::new()];
+        let index = Language::ALL.iter().position(|language| *language == self).unwrap_or(0);
+        TABLES[index].get_or_init(|| {
+            self.source()
+                .lines()
+                .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+                .filter_map(|line| line.split_once(" = "))
+                .map(|(key, text)| (key.trim(), text.replace("\\n", "\n")))
+                .collect()
+        })
+    }
+
+    // English keys with no text in this language, for 'language'
+    pub fn missing_keys(self) -> Vec<&'static str> {
+        let table = self.table();
+        let mut missing: Vec<&'static str> = Language::English.table().keys().copied().filter(|key| !table.contains_key(key)).collect();
+        missing.sort_unstable();
+        missing
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+// Text is looked up from everywhere, so the language is process-wide
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+// Keys already warned about, and warnings not yet logged by the console
+static MISSING: Mutex<(Vec<String>, Vec<String>)> = Mutex::new((Vec::new(), Vec::new()));
+
+pub fn set_language(language: Language) {
+    let index = Language::ALL.iter().position(|candidate| *candidate == language).unwrap_or(0);
+    LANGUAGE.store(index as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    Language::ALL.get(LANGUAGE.load(Ordering::Relaxed) as usize).copied().unwrap_or(Language::English)
+}
+
+// The text for a key in the current language. A key it lacks falls back to English,
+// with a warning the first time; one English lacks too is shown as the key itself.
+pub fn tr(key: &'static str) -> &'static str {
+    let language = language();
+    if let Some(text) = language.table().get(key) {
+        return text.as_str();
+    }
+    if let Ok(mut missing) = MISSING.lock() {
+        let tag = format!("{}:{}", language.code(), key);
+        if !missing.0.contains(&tag) {
+            missing.1.push(format!("No {} text for '{}' - shown in English", language.name(), key));
+            missing.0.push(tag);
+        }
+    }
+    Language::English.table().get(key).map_or(key, String::as_str)
+}
+
+// tr with each {} filled in turn from `args`
+pub fn tr_args(key: &'static str, args: &[&dyn fmt::Display]) -> String {
+    let mut parts = tr(key).split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    let mut args = args.iter();
+    for part in parts {
+        if let Some(arg) = args.next() {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+// Warnings about missing text since the last call, for the console to log
+pub fn take_missing_warnings() -> Vec<String> {
+    MISSING.lock().map(|mut missing| std::mem::take(&mut missing.1)).unwrap_or_default()
+}
+
+// A number formatted for display, with the language's decimal separator
+pub fn decimal(value: f32, places: usize) -> String {
+    let text = format!("{:.*}", places, value);
+    if language().decimal_comma() {
+        text.replace('.', ",")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_english_key_has_a_spanish_text() {
+        assert_eq!(Language::Spanish.missing_keys(), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn translations_fill_the_same_number_of_values() {
+        let spanish = Language::Spanish.table();
+        for (key, text) in Language::English.table() {
+            let translated = spanish.get(key).map_or(0, |text| text.matches("{}").count());
+            assert_eq!(translated, text.matches("{}").count(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn values_are_filled_in_turn() {
+        assert_eq!(tr_args("status.device", &[&2, &3]), "Device 2 of 3 ('devices' lists them)");
+        assert_eq!(tr("no.such.key"), "no.such.key");
+    }
+}
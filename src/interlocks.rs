@@ -0,0 +1,260 @@
+// Safety interlocks: conditions on channel readings and digital I/O that must hold
+// for motors to run. The sensor thread evaluates them on every sample; one dropping
+// out stops the motors it protects in a controlled way (they coast down, unlike the
+// hard stop of an estop) and keeps them from starting until it is satisfied again.
+// A supervisor can bypass one for up to MAX_BYPASS, e.g. to test a motor with a guard
+// open; it is re-armed when the time is up, and an emergency shutdown re-arms them all.
+use crate::sensors::{ChannelKind, SensorData};
+use crate::units::Unit;
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+pub const MAX_BYPASS: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Below,
+    AtMost,
+    Above,
+    AtLeast,
+}
+
+impl Comparison {
+    // Longest first so "<=" isn't taken for "<"
+    const ALL: [(&'static str, Comparison); 4] = [
+        ("<=", Comparison::AtMost),
+        (">=", Comparison::AtLeast),
+        ("<", Comparison::Below),
+        (">", Comparison::Above),
+    ];
+
+    fn symbol(self) -> &'static str {
+        Comparison::ALL.iter().find(|(_, comparison)| *comparison == self).map_or("?", |(symbol, _)| symbol)
+    }
+
+    // An open circuit (NaN) never satisfies a comparison, so a lost sensor drops the interlock
+    fn holds(self, value: f32, limit: f32) -> bool {
+        match self {
+            Comparison::Below => value < limit,
+            Comparison::AtMost => value <= limit,
+            Comparison::Above => value > limit,
+            Comparison::AtLeast => value >= limit,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    // A channel reading against a limit, kept in SI; the unit is the one it was written in,
+    // or None for a bare number in the channel's SI unit until `resolve` fills it in
+    Reading { channel: String, comparison: Comparison, limit: f32, unit: Option<Unit> },
+    // A digital input or output that must be in the given state
+    Point { name: String, state: bool },
+}
+
+impl Condition {
+    // "Pressure Gauge 1 < 104 kPa", "Reactor inlet >= 21" (in °C or kPa when no unit is
+    // given), "Guard door closed" or "not Guard door closed"
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let text = text.trim();
+        let found = Comparison::ALL.iter().filter_map(|(symbol, comparison)| text.find(symbol).map(|at| (at, *symbol, *comparison))).min_by_key(|(at, _, _)| *at);
+        let (at, symbol, comparison) = match found {
+            Some(found) => found,
+            None => {
+                let (name, state) = match text.strip_prefix("not ") {
+                    Some(name) => (name.trim(), false),
+                    None => (text, true),
+                };
+                if name.is_empty() {
+                    return Err("empty condition".to_string());
+                }
+                return Ok(Condition::Point { name: name.to_string(), state });
+            }
+        };
+
+        let channel = text[..at].trim();
+        if channel.is_empty() {
+            return Err(format!("'{}' needs a channel name before {}", text, symbol));
+        }
+        let mut words = text[at + symbol.len()..].split_whitespace();
+        let limit: f32 = words.next()
+            .and_then(|number| number.parse().ok())
+            .filter(|limit: &f32| limit.is_finite())
+            .ok_or_else(|| format!("'{}' needs a number after {}", text, symbol))?;
+        let unit = words.next().map(Unit::parse).transpose()?;
+        if words.next().is_some() {
+            return Err(format!("unexpected text after the limit in '{}'", text));
+        }
+        let limit = unit.map_or(limit, |unit| unit.to_si(limit));
+        Ok(Condition::Reading { channel: channel.to_string(), comparison, limit, unit })
+    }
+
+    // Checks that what the condition refers to is configured, and settles the unit of a bare limit
+    pub fn resolve(&mut self, channels: &[(String, ChannelKind)], points: &[&String]) -> Result<(), String> {
+        match self {
+            Condition::Reading { channel, unit, .. } => {
+                let kind = channels.iter().find(|(name, _)| name == channel).map(|(_, kind)| *kind).ok_or_else(|| format!("no channel named '{}'", channel))?;
+                match unit {
+                    Some(unit) if unit.kind() != kind => Err(format!("{} is a {} channel but the limit is in {}", channel, kind, unit)),
+                    Some(_) => Ok(()),
+                    None => {
+                        *unit = Some(Unit::si(kind));
+                        Ok(())
+                    }
+                }
+            }
+            Condition::Point { name, .. } if !points.contains(&&*name) => Err(format!("no channel or digital input or output named '{}'", name)),
+            Condition::Point { .. } => Ok(()),
+        }
+    }
+
+    // Unknown channels and inputs count as not satisfied
+    pub fn evaluate(&self, data: &SensorData) -> bool {
+        match self {
+            Condition::Reading { channel, comparison, limit, .. } => data.channels.iter()
+                .find(|reading| reading.name == *channel)
+                .is_some_and(|reading| comparison.holds(reading.value, *limit)),
+            Condition::Point { name, state } => data.find_digital_point(name).is_some_and(|point| point.state == *state),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Reading { channel, comparison, limit, unit: Some(unit) } => {
+                write!(f, "{} {} {}{}", channel, comparison.symbol(), unit.number(*limit), unit.symbol())
+            }
+            Condition::Reading { channel, comparison, limit, unit: None } => write!(f, "{} {} {}", channel, comparison.symbol(), limit),
+            Condition::Point { name, state: true } => write!(f, "{}", name),
+            Condition::Point { name, state: false } => write!(f, "not {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interlock {
+    pub name: String,
+    pub condition: Condition,
+    // Motors (0-based) it stops; empty protects them all
+    pub motors: Vec<usize>,
+    pub satisfied: bool,
+    // Motors that were running when it last dropped out
+    pub stopped: Vec<usize>,
+    pub bypass: Option<Bypass>,
+}
+
+// While bypassed an interlock is still evaluated, and its dropping out still raised, but
+// it neither stops nor holds its motors
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bypass {
+    pub until: SystemTime,
+    pub user: String,
+    pub reason: String,
+}
+
+impl Bypass {
+    // Zero once it has run out
+    pub fn remaining(&self, now: SystemTime) -> Duration {
+        self.until.duration_since(now).unwrap_or_default()
+    }
+}
+
+impl Interlock {
+    // Starts out satisfied so the first evaluation reports a condition that doesn't hold
+    pub fn new(name: &str, condition: Condition, motors: Vec<usize>) -> Self {
+        Interlock { name: name.to_string(), condition, motors, satisfied: true, stopped: Vec::new(), bypass: None }
+    }
+
+    // Whether it lets its motors run: satisfied or bypassed
+    pub fn permits(&self) -> bool {
+        self.satisfied || self.bypass.is_some()
+    }
+
+    pub fn protects(&self, motor: usize) -> bool {
+        self.motors.is_empty() || self.motors.contains(&motor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn reading(channel: &str, comparison: Comparison, limit: f32, unit: Option<Unit>) -> Condition {
+        Condition::Reading { channel: channel.to_string(), comparison, limit, unit }
+    }
+
+    // The default plant with a guard door input, and `conditions` as interlocks on every motor
+    fn plant(conditions: &[&str]) -> SensorData {
+        let channels: Vec<(String, ChannelKind)> = SensorData::from_config(&Config::default()).channels.iter().map(|channel| (channel.name.clone(), channel.kind)).collect();
+        let door = "Guard door closed".to_string();
+        let interlocks = conditions.iter().enumerate().map(|(i, text)| {
+            let mut condition = Condition::parse(text).unwrap();
+            condition.resolve(&channels, &[&door]).unwrap();
+            Interlock::new(&format!("IL-{}", i + 1), condition, Vec::new())
+        }).collect();
+        SensorData::from_config(&Config { inputs: vec![door.clone()], input_states: vec![true], interlocks, ..Config::default() })
+    }
+
+    #[test]
+    fn conditions_parse() {
+        assert_eq!(Condition::parse("Pressure Gauge 1 < 104 kPa"), Ok(reading("Pressure Gauge 1", Comparison::Below, 104.0, Some(Unit::Kilopascal))));
+        assert_eq!(Condition::parse(" Reactor inlet<=21 "), Ok(reading("Reactor inlet", Comparison::AtMost, 21.0, None)));
+        assert_eq!(Condition::parse("Line >= 14.5 psi"), Ok(reading("Line", Comparison::AtLeast, 14.5 * 6.894_757, Some(Unit::Psi))));
+        assert_eq!(Condition::parse("Guard door closed"), Ok(Condition::Point { name: "Guard door closed".to_string(), state: true }));
+        assert_eq!(Condition::parse("not Guard door closed"), Ok(Condition::Point { name: "Guard door closed".to_string(), state: false }));
+        for bad in ["", "< 104", "Gauge > high", "Gauge > 5 kPa now", "Gauge > 5 furlongs", "Gauge > inf"] {
+            assert!(Condition::parse(bad).is_err(), "'{}' parsed", bad);
+        }
+    }
+
+    #[test]
+    fn conditions_resolve_against_the_configuration() {
+        let channels = [("Temperature Sensor 1".to_string(), ChannelKind::Temperature)];
+        let door = "Guard door closed".to_string();
+        let mut bare = Condition::parse("Temperature Sensor 1 < 28").unwrap();
+        bare.resolve(&channels, &[&door]).unwrap();
+        assert_eq!(bare.to_string(), "Temperature Sensor 1 < 28.0°C");
+        assert!(Condition::parse("Temperature Sensor 1 < 28 kPa").unwrap().resolve(&channels, &[]).is_err(), "wrong kind of unit");
+        assert!(Condition::parse("Temperature Sensor 9 < 28").unwrap().resolve(&channels, &[]).is_err());
+        assert!(Condition::parse("Back door closed").unwrap().resolve(&channels, &[&door]).is_err());
+    }
+
+    #[test]
+    fn conditions_evaluate_against_readings_and_points() {
+        let mut data = plant(&[]);
+        let below = Condition::parse("Pressure Gauge 1 < 104").unwrap();
+        let at_least = Condition::parse("Pressure Gauge 1 >= 101.3").unwrap();
+        assert!(below.evaluate(&data) && at_least.evaluate(&data));
+        data.channel_mut(ChannelKind::Pressure, 0).unwrap().value = 104.0;
+        assert!(!below.evaluate(&data) && at_least.evaluate(&data));
+        // An open circuit satisfies nothing
+        data.channel_mut(ChannelKind::Pressure, 0).unwrap().value = f32::NAN;
+        assert!(!below.evaluate(&data) && !at_least.evaluate(&data));
+
+        assert!(Condition::parse("Guard door closed").unwrap().evaluate(&data));
+        assert!(!Condition::parse("not Guard door closed").unwrap().evaluate(&data));
+        assert!(!Condition::parse("Nowhere < 5").unwrap().evaluate(&data), "unknown channels don't hold");
+    }
+
+    #[test]
+    fn a_dropped_interlock_stops_the_running_motors_once() {
+        let mut data = plant(&["Pressure Gauge 1 < 104", "Guard door closed"]);
+        assert!(data.update_interlocks().is_empty());
+        assert!(data.interlocks_satisfied());
+
+        data.set_digital_point("Guard door closed", false).unwrap();
+        assert_eq!(data.update_interlocks(), [0, 1, 3], "the running motors");
+        assert!(data.motors.iter().all(|motor| !motor.running));
+        assert!(!data.interlocks_satisfied());
+        assert!(data.update_interlocks().is_empty(), "only when it drops out");
+        assert!(data.set_motor_state(1, true).is_err(), "held stopped");
+
+        data.set_digital_point("Guard door closed", true).unwrap();
+        data.update_interlocks();
+        assert!(data.interlocks_satisfied());
+        assert_eq!(data.set_motor_state(1, true), Ok(()));
+    }
+}
@@ -0,0 +1,431 @@
+// Small JSON document model with a parser and pretty printer, enough for
+// snapshots and other files this application reads and writes.
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Arrays and objects inside one another, at most; the parser recurses once per level, so
+// a body of nothing but '[' would otherwise run it out of stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn parse(text: &str) -> Result<Value, String> {
+        let mut parser = Parser { chars: text.char_indices().peekable(), text, depth: 0 };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some((offset, _)) => Err(format!("unexpected trailing data at offset {}", offset)),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // Looks up a required member, describing what was wrong if it's missing
+    pub fn field(&self, key: &str) -> Result<&Value, String> {
+        self.get(key).ok_or_else(|| format!("missing field '{}'", key))
+    }
+
+    pub fn f64_field(&self, key: &str) -> Result<f64, String> {
+        self.field(key)?.as_f64().ok_or_else(|| format!("field '{}' must be a number", key))
+    }
+
+    pub fn str_field(&self, key: &str) -> Result<&str, String> {
+        self.field(key)?.as_str().ok_or_else(|| format!("field '{}' must be a string", key))
+    }
+
+    pub fn bool_field(&self, key: &str) -> Result<bool, String> {
+        self.field(key)?.as_bool().ok_or_else(|| format!("field '{}' must be true or false", key))
+    }
+
+    pub fn array_field<T>(&self, key: &str, convert: impl Fn(&Value) -> Option<T>) -> Result<Vec<T>, String> {
+        let items = self.field(key)?.as_array().ok_or_else(|| format!("field '{}' must be an array", key))?;
+        items.iter()
+            .map(|item| convert(item).ok_or_else(|| format!("field '{}' contains an invalid element", key)))
+            .collect()
+    }
+
+    // Written to a temporary file first so a crash mid-write can't leave a half-written file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.to_pretty_string())?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn to_pretty_string(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                // Arrays of scalars stay on one line to keep snapshots readable
+                if items.iter().all(|item| !matches!(item, Value::Array(_) | Value::Object(_))) {
+                    let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                    let _ = write!(out, "[{}]", items.join(", "));
+                    return;
+                }
+                out.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    item.write_pretty(out, indent + 1);
+                    out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Value::Object(members) if !members.is_empty() => {
+                out.push_str("{\n");
+                for (i, (name, value)) in members.iter().enumerate() {
+                    out.push_str(&"  ".repeat(indent + 1));
+                    write_string(out, name);
+                    out.push_str(": ");
+                    value.write_pretty(out, indent + 1);
+                    out.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+            _ => {
+                let _ = write!(out, "{}", self);
+            }
+        }
+    }
+}
+
+// Compact single-line form
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            // JSON has no NaN or infinity
+            Value::Number(number) if !number.is_finite() => write!(f, "null"),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(value) => {
+                let mut out = String::new();
+                write_string(&mut out, value);
+                f.write_str(&out)
+            }
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    let mut key = String::new();
+                    write_string(&mut key, name);
+                    write!(f, "{}:{}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        // Round-trip through the decimal form so 23.5f32 doesn't serialize as 23.5000000001
+        Value::Number(value.to_string().parse().unwrap_or(value as f64))
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value> + Clone> From<&[T]> for Value {
+    fn from(items: &[T]) -> Self {
+        Value::Array(items.iter().cloned().map(Into::into).collect())
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    // Arrays and objects open around the current value
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some((_, ' ' | '\t' | '\n' | '\r')) = self.chars.peek() {
+            self.chars.next();
+        }
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |(offset, _)| *offset)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((offset, c)) => Err(format!("expected '{}' but found '{}' at offset {}", expected, c, offset)),
+            None => Err(format!("expected '{}' but reached end of input", expected)),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let offset = self.offset();
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some(c @ ('{' | '[')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err("nesting too deep".to_string());
+                }
+                self.depth += 1;
+                let value = if c == '{' { self.parse_object() } else { self.parse_array() };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => self.parse_string().map(Value::String),
+            Some('t') => self.parse_literal("true", Value::Bool(true)),
+            Some('f') => self.parse_literal("false", Value::Bool(false)),
+            Some('n') => self.parse_literal("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!("unexpected character '{}' at offset {}", c, offset)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, String> {
+        for expected in literal.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.offset();
+        while let Some((_, c)) = self.chars.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = self.offset();
+        let text = &self.text[start..end];
+        text.parse().map(Value::Number).map_err(|_| format!("invalid number '{}' at offset {}", text, start))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((offset, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '/')) => value.push('/'),
+                    Some((_, 'b')) => value.push('\u{8}'),
+                    Some((_, 'f')) => value.push('\u{c}'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, 'u')) => {
+                        let mut code = 0u32;
+                        for _ in 0..4 {
+                            let digit = self.chars.next().and_then(|(_, c)| c.to_digit(16));
+                            code = code * 16 + digit.ok_or_else(|| format!("invalid \\u escape at offset {}", offset))?;
+                        }
+                        value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err(format!("invalid escape sequence at offset {}", offset)),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if let Some((_, ']')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Value::Array(items)),
+                Some((offset, c)) => return Err(format!("expected ',' or ']' but found '{}' at offset {}", c, offset)),
+                None => return Err("unterminated array".to_string()),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if let Some((_, '}')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((name, value));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Value::Object(members)),
+                Some((offset, c)) => return Err(format!("expected ',' or '}}' but found '{}' at offset {}", c, offset)),
+                None => return Err("unterminated object".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"name":"line \"2\"","rpm":[1500,0,-2.5e3],"on":true,"note":null,"nested":{"empty":[],"none":{}}}"#;
+        let value = Value::parse(text).unwrap();
+        assert_eq!(value.str_field("name"), Ok("line \"2\""));
+        assert_eq!(value.array_field("rpm", Value::as_f64), Ok(vec![1500.0, 0.0, -2500.0]));
+        assert_eq!(Value::parse(&value.to_string()), Ok(value.clone()));
+        assert_eq!(Value::parse(&value.to_pretty_string()), Ok(value));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(Value::parse(&nested(MAX_DEPTH + 1)), Err("nesting too deep".to_string()));
+        // Far deeper than the stack would take, unbalanced as an attacker would send it
+        assert_eq!(Value::parse(&"[".repeat(30_000)), Err("nesting too deep".to_string()));
+        assert_eq!(Value::parse(&r#"{"a":"#.repeat(30_000)), Err("nesting too deep".to_string()));
+        // Depth is what is open at once, not how many there are
+        let wide = format!("[{}]", vec![nested(MAX_DEPTH - 1); 100].join(","));
+        assert!(Value::parse(&wide).is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Value::parse("").is_err());
+        assert!(Value::parse("[1,]").is_err());
+        assert!(Value::parse(r#"{"a" 1}"#).is_err());
+        assert!(Value::parse(r#""open"#).is_err());
+        assert_eq!(Value::parse("1 2"), Err("unexpected trailing data at offset 2".to_string()));
+    }
+}
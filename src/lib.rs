@@ -0,0 +1,62 @@
+// Forlenza Industrial Control System. The console binary in main.rs is a thin
+// wrapper; everything else is here so it can be driven without a terminal.
+pub mod alarms;
+pub mod ambient;
+pub mod annunciator;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod bundle;
+pub mod calibration;
+pub mod clock;
+pub mod commands;
+pub mod compare;
+pub mod config;
+pub mod csv;
+pub mod devices;
+pub mod diagnostics;
+pub mod energy;
+pub mod error;
+pub mod faults;
+pub mod fft;
+pub mod heartbeat;
+pub mod historian;
+pub mod history;
+pub mod i18n;
+pub mod interlocks;
+pub mod json;
+pub mod logfile;
+pub mod maintenance;
+pub mod modbus;
+pub mod mode;
+pub mod mqtt;
+pub mod notes;
+pub mod notify;
+pub mod pens;
+pub mod pid;
+pub mod platform;
+pub mod prometheus;
+pub mod rand;
+pub mod recipes;
+pub mod replay;
+pub mod report;
+pub mod scan;
+pub mod scenario;
+pub mod schema;
+pub mod sensors;
+pub mod serial;
+pub mod settings;
+pub mod sha1;
+pub mod shift;
+pub mod simulation;
+pub mod source;
+pub mod stats;
+pub mod timeline;
+pub mod toasts;
+pub mod toml;
+pub mod trips;
+pub mod ui;
+pub mod undo;
+pub mod units;
+pub mod vibration;
+pub mod websocket;
@@ -0,0 +1,190 @@
+// Rolling log file: every line the console logs, and every alarm, written with its
+// time and level to forlenza.log in the log directory. When the file passes its size
+// limit it becomes forlenza.1.log, older files move up one number and the oldest is
+// deleted. Writes happen on a background thread, as for the audit trail.
+use crate::clock::format_datetime;
+use crate::platform::LogLevel;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+
+const FILE_NAME: &str = "forlenza";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    pub max_bytes: u64,
+    // Rotated files kept besides the current one
+    pub keep_files: usize,
+}
+
+fn numbered_path(directory: &Path, number: usize) -> PathBuf {
+    match number {
+        0 => directory.join(format!("{}.log", FILE_NAME)),
+        number => directory.join(format!("{}.{}.log", FILE_NAME, number)),
+    }
+}
+
+// Owns the writer thread. Dropping it writes out everything still queued.
+pub struct LogFile {
+    path: PathBuf,
+    writer: Option<(Sender<String>, JoinHandle<()>)>,
+}
+
+impl LogFile {
+    // Later write failures are reported once through `reports`, like the audit trail's
+    pub fn open(config: &LogFileConfig, reports: Sender<Result<String, String>>) -> io::Result<LogFile> {
+        fs::create_dir_all(&config.directory)?;
+        let path = numbered_path(&config.directory, 0);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let (lines, queued) = mpsc::channel();
+        let config = config.clone();
+        let thread = thread::spawn(move || run_writer(file, size, &config, queued, reports));
+        Ok(LogFile { path, writer: Some((lines, thread)) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&self, level: LogLevel, message: &str) {
+        if let Some((lines, _)) = &self.writer {
+            let _ = lines.send(format!("{} {:<5} {}", format_datetime(SystemTime::now()), level.tag(), message));
+        }
+    }
+
+    // Closing the channel lets the writer drain the queue and flush before it exits
+    pub fn close(&mut self) {
+        if let Some((lines, thread)) = self.writer.take() {
+            drop(lines);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// Renames forlenza.log to forlenza.1.log, shifting the older ones up and dropping the
+// last, then starts a new empty file
+fn rotate(config: &LogFileConfig) -> io::Result<File> {
+    let _ = fs::remove_file(numbered_path(&config.directory, config.keep_files));
+    for number in (0..config.keep_files).rev() {
+        let from = numbered_path(&config.directory, number);
+        if from.exists() {
+            fs::rename(&from, numbered_path(&config.directory, number + 1))?;
+        }
+    }
+    File::create(numbered_path(&config.directory, 0))
+}
+
+fn run_writer(file: File, mut size: u64, config: &LogFileConfig, lines: Receiver<String>, reports: Sender<Result<String, String>>) {
+    let path = numbered_path(&config.directory, 0);
+    let mut file = BufWriter::new(file);
+    let mut failing = false;
+    // Ends when the log is closed and the queue is empty
+    while let Ok(line) = lines.recv() {
+        let mut result = Ok(());
+        for line in std::iter::once(line).chain(lines.try_iter()) {
+            // A line is never split across files, so a file can end up one line over the limit
+            if size > 0 && size + line.len() as u64 + 1 > config.max_bytes {
+                result = result.and_then(|()| file.flush()).and_then(|()| rotate(config)).map(|rotated| {
+                    file = BufWriter::new(rotated);
+                    size = 0;
+                });
+            }
+            result = result.and_then(|()| writeln!(file, "{}", line));
+            size += line.len() as u64 + 1;
+        }
+        result = result.and_then(|()| file.flush());
+
+        // Reported once when writing starts failing and once when it recovers
+        match result {
+            Err(e) if !failing => {
+                failing = true;
+                let _ = reports.send(Err(format!("Log file write to {} failed: {}", path.display(), e)));
+            }
+            Ok(()) if failing => {
+                failing = false;
+                let _ = reports.send(Ok(format!("Log file writing to {} again", path.display())));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str) -> LogFileConfig {
+        let directory = std::env::temp_dir().join(format!("forlenza-logfile-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        LogFileConfig { directory, max_bytes: 1000, keep_files: 2 }
+    }
+
+    // Writes `count` numbered lines and closes the file so they are all on disk
+    fn write_lines(config: &LogFileConfig, first: usize, count: usize) {
+        let (reports, failures) = mpsc::channel();
+        let mut log = LogFile::open(config, reports).unwrap();
+        for number in first..first + count {
+            log.write(LogLevel::Info, &format!("line {:04}", number));
+        }
+        log.close();
+        assert!(failures.try_recv().is_err());
+    }
+
+    fn numbers(config: &LogFileConfig, file: usize) -> Vec<usize> {
+        fs::read_to_string(numbered_path(&config.directory, file)).unwrap()
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn files_rotate_by_size_and_the_oldest_is_dropped() {
+        let config = config("rotate");
+        // Each line is "<date time> INFO  line NNNN" and a newline
+        let line = format!("{} INFO  line 0000\n", format_datetime(SystemTime::now())).len() as u64;
+        let per_file = (config.max_bytes / line) as usize;
+        write_lines(&config, 0, per_file * 3 + 5);
+
+        let current = numbers(&config, 0);
+        let previous = numbers(&config, 1);
+        let oldest = numbers(&config, 2);
+        assert_eq!(current, (per_file * 3..per_file * 3 + 5).collect::<Vec<_>>());
+        assert_eq!(previous, (per_file * 2..per_file * 3).collect::<Vec<_>>());
+        assert_eq!(oldest, (per_file..per_file * 2).collect::<Vec<_>>(), "the first file was dropped");
+        assert!(!numbered_path(&config.directory, 3).exists());
+        for file in 0..3 {
+            assert!(fs::metadata(numbered_path(&config.directory, file)).unwrap().len() <= config.max_bytes);
+        }
+        let _ = fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn reopening_appends_and_counts_what_is_there() {
+        let config = config("reopen");
+        write_lines(&config, 0, 10);
+        write_lines(&config, 10, 10);
+        assert_eq!(numbers(&config, 0), (0..20).collect::<Vec<_>>());
+        assert!(!numbered_path(&config.directory, 1).exists());
+
+        // The limit counts the lines already written, so the first file fills up to it
+        write_lines(&config, 20, 30);
+        let first = numbers(&config, 2);
+        let line = fs::metadata(numbered_path(&config.directory, 0)).unwrap().len() / numbers(&config, 0).len() as u64;
+        assert_eq!(first.len() as u64, config.max_bytes / line);
+        let all: Vec<usize> = (0..3).rev().flat_map(|file| numbers(&config, file)).collect();
+        assert_eq!(all, (0..50).collect::<Vec<_>>());
+        let _ = fs::remove_dir_all(&config.directory);
+    }
+}
@@ -0,0 +1,130 @@
+// Motor runtime and start counters for maintenance planning. The sensor thread adds
+// up running time sample by sample; the counters are kept across restarts in a small
+// JSON state file, matched to motors by name.
+use crate::clock::{format_datetime, parse_datetime};
+use crate::json::Value;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MotorCounters {
+    pub name: String,
+    pub runtime: Duration,
+    // Running time since maintenance was last recorded
+    pub service_runtime: Duration,
+    pub starts: u64,
+    pub last_service: Option<SystemTime>,
+    // State at the previous sample; None until the first, so startup isn't counted as a start
+    running: Option<bool>,
+}
+
+impl MotorCounters {
+    fn new(name: &str) -> Self {
+        MotorCounters { name: name.to_string(), runtime: Duration::ZERO, service_runtime: Duration::ZERO, starts: 0, last_service: None, running: None }
+    }
+}
+
+#[derive(Clone)]
+pub struct Maintenance {
+    motors: Vec<MotorCounters>,
+    // Running time between services before a motor is flagged
+    service_interval: Duration,
+}
+
+impl Maintenance {
+    pub fn new(names: &[String], service_interval: Duration) -> Self {
+        Maintenance { motors: names.iter().map(|name| MotorCounters::new(name)).collect(), service_interval }
+    }
+
+    pub fn motors(&self) -> &[MotorCounters] {
+        &self.motors
+    }
+
+    pub fn service_interval(&self) -> Duration {
+        self.service_interval
+    }
+
+    pub fn is_due(&self, motor: usize) -> bool {
+        self.motors.get(motor).is_some_and(|counters| counters.service_runtime >= self.service_interval)
+    }
+
+    // Called by the sensor thread with each sample's motor states and the time it covers
+    pub fn record(&mut self, running: &[bool], dt: Duration) {
+        for (counters, running) in self.motors.iter_mut().zip(running) {
+            if *running {
+                counters.runtime += dt;
+                counters.service_runtime += dt;
+                if counters.running == Some(false) {
+                    counters.starts += 1;
+                }
+            }
+            counters.running = Some(*running);
+        }
+    }
+
+    // Motors are numbered from 1 as on the plant floor
+    pub fn record_service(&mut self, motor: usize, time: SystemTime) -> Result<&MotorCounters, String> {
+        let count = self.motors.len();
+        let counters = motor.checked_sub(1)
+            .and_then(|index| self.motors.get_mut(index))
+            .ok_or_else(|| format!("no motor {} (valid motors are 1-{})", motor, count))?;
+        counters.service_runtime = Duration::ZERO;
+        counters.last_service = Some(time);
+        Ok(counters)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let motors = self.motors.iter().map(|counters| Value::Object(vec![
+            ("name".to_string(), counters.name.as_str().into()),
+            ("runtime_s".to_string(), counters.runtime.as_secs_f64().into()),
+            ("service_runtime_s".to_string(), counters.service_runtime.as_secs_f64().into()),
+            ("starts".to_string(), counters.starts.into()),
+            ("last_service".to_string(), counters.last_service.map_or(Value::Null, |time| format_datetime(time).into())),
+        ])).collect();
+        Value::Object(vec![
+            ("format".to_string(), "forlenza-maintenance".into()),
+            ("version".to_string(), 1u16.into()),
+            ("motors".to_string(), Value::Array(motors)),
+        ])
+    }
+
+    // Takes the saved counters of every motor still configured under the same name and
+    // returns how many there were; a missing file leaves everything at zero.
+    pub fn load(&mut self, path: &Path) -> Result<usize, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.to_string()),
+        };
+        let state = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+        if state.get("format").and_then(Value::as_str) != Some("forlenza-maintenance") {
+            return Err("not a Forlenza maintenance file".to_string());
+        }
+        let saved = state.array_field("motors", |item| {
+            let seconds = |key: &str| item.f64_field(key).ok().filter(|secs| secs.is_finite() && *secs >= 0.0).map(Duration::from_secs_f64);
+            let last_service = match item.field("last_service").ok()? {
+                Value::Null => None,
+                time => Some(parse_datetime(time.as_str()?)?),
+            };
+            Some(MotorCounters {
+                runtime: seconds("runtime_s")?,
+                service_runtime: seconds("service_runtime_s")?,
+                starts: item.f64_field("starts").ok().filter(|starts| starts.fract() == 0.0 && *starts >= 0.0)? as u64,
+                last_service,
+                ..MotorCounters::new(item.str_field("name").ok()?)
+            })
+        })?;
+
+        let mut matched = 0;
+        for counters in &mut self.motors {
+            if let Some(found) = saved.iter().find(|found| found.name == counters.name) {
+                *counters = found.clone();
+                matched += 1;
+            }
+        }
+        Ok(matched)
+    }
+}
@@ -0,0 +1,516 @@
+// Modbus client for reading live values from the plant PLC, over TCP or RTU on a
+// serial line. Only the function codes the register map needs are implemented: read
+// coils (0x01), read holding registers (0x03), write single coil (0x05) and write
+// single register (0x06).
+use crate::sensors::{ChannelKind, SensorData};
+use crate::serial::{Parity, SerialPort};
+use crate::source::{DataSource, SourceCommand, SourceError};
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const READ_COILS: u8 = 0x01;
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+const WRITE_SINGLE_COIL: u8 = 0x05;
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+
+// Protocol limits on a single read request
+const MAX_REGISTERS_PER_READ: usize = 125;
+const MAX_COILS_PER_READ: usize = 2000;
+
+// Where each channel lives in the PLC's address space
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterMap {
+    pub temperature_registers: Vec<u16>,
+    // Engineering units per count; registers are signed 16-bit
+    pub temperature_scale: f32,
+    pub pressure_registers: Vec<u16>,
+    pub pressure_scale: f32,
+    pub motor_speed_registers: Vec<u16>,
+    pub motor_coils: Vec<u16>,
+    // Optional; without them setpoint changes stay local
+    pub motor_setpoint_registers: Vec<u16>,
+}
+
+impl RegisterMap {
+    pub fn validate(&self, temperatures: usize, pressures: usize, motors: usize) -> Result<(), String> {
+        let lists = [
+            ("temperature_registers", &self.temperature_registers, temperatures, MAX_REGISTERS_PER_READ),
+            ("pressure_registers", &self.pressure_registers, pressures, MAX_REGISTERS_PER_READ),
+            ("motor_speed_registers", &self.motor_speed_registers, motors, MAX_REGISTERS_PER_READ),
+            ("motor_coils", &self.motor_coils, motors, MAX_COILS_PER_READ),
+        ];
+        for (name, addresses, channels, max_span) in lists {
+            if addresses.len() != channels {
+                return Err(format!("{} has {} entries but {} channels are configured", name, addresses.len(), channels));
+            }
+            // Each list is fetched with one request
+            if span(addresses) > max_span {
+                return Err(format!("{} must fall within a block of {} addresses", name, max_span));
+            }
+        }
+        if !self.motor_setpoint_registers.is_empty() && self.motor_setpoint_registers.len() != motors {
+            return Err(format!("motor_setpoint_registers has {} entries but {} motors are configured", self.motor_setpoint_registers.len(), motors));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModbusTcpConfig {
+    pub host: String,
+    pub port: u16,
+    pub unit_id: u8,
+    pub timeout_ms: u64,
+    pub map: RegisterMap,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModbusRtuConfig {
+    // COM3 on Windows, /dev/ttyUSB0 on Linux
+    pub serial_port: String,
+    pub baud_rate: u32,
+    pub parity: Parity,
+    // Slave address, 1-247
+    pub unit_id: u8,
+    pub timeout_ms: u64,
+    pub map: RegisterMap,
+}
+
+// One request/response exchange with a device, independent of the framing
+trait Transport {
+    fn transact(&mut self, unit_id: u8, pdu: &[u8]) -> Result<Vec<u8>, SourceError>;
+}
+
+pub struct ModbusTcpSource {
+    config: ModbusTcpConfig,
+    connection: Option<TcpConnection>,
+}
+
+impl ModbusTcpSource {
+    pub fn new(config: ModbusTcpConfig) -> Self {
+        ModbusTcpSource { config, connection: None }
+    }
+
+    fn connection(&mut self) -> Result<&mut TcpConnection, SourceError> {
+        if self.connection.is_none() {
+            self.connection = Some(TcpConnection::open(&self.config)?);
+        }
+        self.connection.as_mut().ok_or_else(|| SourceError::Connection("not connected".to_string()))
+    }
+}
+
+impl DataSource for ModbusTcpSource {
+    fn describe(&self) -> String {
+        format!("Modbus TCP {}:{} (unit {})", self.config.host, self.config.port, self.config.unit_id)
+    }
+
+    fn poll(&mut self, sensor_data: &Mutex<SensorData>, _dt: Duration) -> Result<(), SourceError> {
+        let (unit_id, map) = (self.config.unit_id, self.config.map.clone());
+        let readings = self.connection().and_then(|connection| Readings::read(connection, unit_id, &map));
+        // Start from a fresh connection on the next attempt. After a response that didn't
+        // match its request the stream can't be trusted to be in step, so any failure counts.
+        if readings.is_err() {
+            self.connection = None;
+        }
+        readings?.store(sensor_data)
+    }
+
+    fn write(&mut self, command: &SourceCommand) -> Result<(), SourceError> {
+        let (unit_id, map) = (self.config.unit_id, self.config.map.clone());
+        let result = self.connection().and_then(|connection| write_command(connection, unit_id, &map, command));
+        if result.is_err() {
+            self.connection = None;
+        }
+        result
+    }
+}
+
+pub struct ModbusRtuSource {
+    config: ModbusRtuConfig,
+    connection: Option<RtuConnection>,
+}
+
+impl ModbusRtuSource {
+    pub fn new(config: ModbusRtuConfig) -> Self {
+        ModbusRtuSource { config, connection: None }
+    }
+
+    fn connection(&mut self) -> Result<&mut RtuConnection, SourceError> {
+        if self.connection.is_none() {
+            self.connection = Some(RtuConnection::open(&self.config)?);
+        }
+        self.connection.as_mut().ok_or_else(|| SourceError::Connection("not connected".to_string()))
+    }
+}
+
+impl DataSource for ModbusRtuSource {
+    fn describe(&self) -> String {
+        format!(
+            "Modbus RTU {} {} baud {} (slave {})",
+            self.config.serial_port, self.config.baud_rate, self.config.parity, self.config.unit_id
+        )
+    }
+
+    fn poll(&mut self, sensor_data: &Mutex<SensorData>, _dt: Duration) -> Result<(), SourceError> {
+        let (unit_id, map) = (self.config.unit_id, self.config.map.clone());
+        let readings = self.connection().and_then(|connection| Readings::read(connection, unit_id, &map));
+        // Reopened on the next attempt in case the USB adapter was unplugged and replugged
+        if matches!(readings, Err(SourceError::Connection(_))) {
+            self.connection = None;
+        }
+        readings?.store(sensor_data)
+    }
+
+    fn write(&mut self, command: &SourceCommand) -> Result<(), SourceError> {
+        let (unit_id, map) = (self.config.unit_id, self.config.map.clone());
+        let result = self.connection().and_then(|connection| write_command(connection, unit_id, &map, command));
+        if matches!(result, Err(SourceError::Connection(_))) {
+            self.connection = None;
+        }
+        result
+    }
+}
+
+struct Readings {
+    temperatures: Vec<f32>,
+    pressures: Vec<f32>,
+    motor_speeds: Vec<u16>,
+    motor_states: Vec<bool>,
+}
+
+impl Readings {
+    fn read(transport: &mut dyn Transport, unit_id: u8, map: &RegisterMap) -> Result<Readings, SourceError> {
+        let signed = |values: Vec<u16>, scale: f32| values.into_iter().map(|value| value as i16 as f32 * scale).collect();
+        let temperatures = read_list(transport, unit_id, READ_HOLDING_REGISTERS, &map.temperature_registers)?;
+        let pressures = read_list(transport, unit_id, READ_HOLDING_REGISTERS, &map.pressure_registers)?;
+        let motor_speeds = read_list(transport, unit_id, READ_HOLDING_REGISTERS, &map.motor_speed_registers)?;
+        let motor_states = read_list(transport, unit_id, READ_COILS, &map.motor_coils)?;
+        Ok(Readings {
+            temperatures: signed(temperatures, map.temperature_scale),
+            pressures: signed(pressures, map.pressure_scale),
+            motor_speeds,
+            motor_states: motor_states.into_iter().map(|coil| coil != 0).collect(),
+        })
+    }
+
+    // All device I/O is done by now, so the lock is only held for the copy
+    fn store(self, sensor_data: &Mutex<SensorData>) -> Result<(), SourceError> {
+        let mut data = sensor_data.lock().map_err(|_| SourceError::Protocol("sensor data is unavailable".to_string()))?;
+        if self.temperatures.len() != data.channels_of(ChannelKind::Temperature).count()
+            || self.pressures.len() != data.channels_of(ChannelKind::Pressure).count()
+            || self.motor_speeds.len() != data.motors.len()
+        {
+            return Err(SourceError::Protocol("register map doesn't match the displayed channels".to_string()));
+        }
+        let mut temperatures = self.temperatures.into_iter();
+        let mut pressures = self.pressures.into_iter();
+        for channel in &mut data.channels {
+            let value = match channel.kind {
+                ChannelKind::Temperature => temperatures.next(),
+                ChannelKind::Pressure => pressures.next(),
+            };
+            channel.set_raw(value.unwrap_or(f32::NAN));
+        }
+        for (motor, (speed, running)) in data.motors.iter_mut().zip(self.motor_speeds.into_iter().zip(self.motor_states)) {
+            motor.speed = speed;
+            motor.running = running;
+            // Estimated as in the simulation; overload protection is left to the plant's own relays
+            motor.current = motor.load_current(speed as f32);
+        }
+        Ok(())
+    }
+}
+
+fn write_command(transport: &mut dyn Transport, unit_id: u8, map: &RegisterMap, command: &SourceCommand) -> Result<(), SourceError> {
+    let (function, address, value) = match *command {
+        SourceCommand::MotorRunning { motor, running } => {
+            let coil = map.motor_coils.get(motor).ok_or_else(|| SourceError::Protocol(format!("no coil mapped for motor {}", motor + 1)))?;
+            (WRITE_SINGLE_COIL, *coil, if running { 0xFF00 } else { 0x0000 })
+        }
+        SourceCommand::MotorSetpoint { motor, rpm } => {
+            let register = map.motor_setpoint_registers
+                .get(motor)
+                .ok_or_else(|| SourceError::Protocol(format!("no setpoint register mapped for motor {}", motor + 1)))?;
+            (WRITE_SINGLE_REGISTER, *register, rpm)
+        }
+    };
+
+    let mut request = vec![function];
+    request.extend_from_slice(&address.to_be_bytes());
+    request.extend_from_slice(&value.to_be_bytes());
+    let response = transport.transact(unit_id, &request)?;
+    check_exception(function, &response)?;
+    // A successful single write echoes the request
+    if response != request {
+        return Err(SourceError::Protocol(format!("unexpected response to function 0x{:02X}", function)));
+    }
+    Ok(())
+}
+
+struct TcpConnection {
+    stream: TcpStream,
+    transaction_id: u16,
+}
+
+impl TcpConnection {
+    fn open(config: &ModbusTcpConfig) -> Result<TcpConnection, SourceError> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| SourceError::Connection(format!("can't resolve {}", config.host)))?;
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(TcpConnection { stream, transaction_id: 0 })
+    }
+}
+
+impl Transport for TcpConnection {
+    // Wraps the request PDU in an MBAP header and returns the response PDU
+    fn transact(&mut self, unit_id: u8, pdu: &[u8]) -> Result<Vec<u8>, SourceError> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let mut frame = Vec::with_capacity(7 + pdu.len());
+        frame.extend_from_slice(&self.transaction_id.to_be_bytes());
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+        frame.push(unit_id);
+        frame.extend_from_slice(pdu);
+        self.stream.write_all(&frame)?;
+
+        let mut header = [0u8; 7];
+        self.stream.read_exact(&mut header)?;
+        let transaction_id = u16::from_be_bytes([header[0], header[1]]);
+        let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if transaction_id != self.transaction_id {
+            return Err(SourceError::Protocol(format!("response to transaction {} instead of {}", transaction_id, self.transaction_id)));
+        }
+        if protocol_id != 0 || !(2..=254).contains(&length) {
+            return Err(SourceError::Protocol("malformed response header".to_string()));
+        }
+        if header[6] != unit_id {
+            return Err(SourceError::Protocol(format!("response from unit {} instead of {}", header[6], unit_id)));
+        }
+        let mut response = vec![0u8; length - 1];
+        self.stream.read_exact(&mut response)?;
+        Ok(response)
+    }
+}
+
+struct RtuConnection {
+    port: SerialPort,
+    // Silence that marks the end of a frame: 3.5 character times, fixed above 19200 baud
+    frame_gap: Duration,
+}
+
+impl RtuConnection {
+    fn open(config: &ModbusRtuConfig) -> Result<RtuConnection, SourceError> {
+        let port = SerialPort::open(&config.serial_port, config.baud_rate, config.parity, Duration::from_millis(config.timeout_ms))
+            .map_err(|e| SourceError::Connection(format!("{}: {}", config.serial_port, e)))?;
+        // 11 bits per character with start, parity or second stop, and stop bit
+        let frame_gap = if config.baud_rate > 19200 {
+            Duration::from_micros(1750)
+        } else {
+            Duration::from_micros(11 * 3_500_000 / config.baud_rate as u64)
+        };
+        Ok(RtuConnection { port, frame_gap })
+    }
+
+    fn read_frame(&mut self, unit_id: u8, function: u8) -> Result<Vec<u8>, SourceError> {
+        // Address and function code decide how much more follows
+        let mut frame = vec![0u8; 2];
+        self.port.read_exact(&mut frame)?;
+        let remaining = if frame[1] & 0x80 != 0 {
+            1
+        } else if function == READ_COILS || function == READ_HOLDING_REGISTERS {
+            let mut byte_count = [0u8; 1];
+            self.port.read_exact(&mut byte_count)?;
+            frame.push(byte_count[0]);
+            byte_count[0] as usize
+        } else {
+            4
+        };
+        let start = frame.len();
+        frame.resize(start + remaining + 2, 0);
+        self.port.read_exact(&mut frame[start..])?;
+
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        if crc16(body).to_le_bytes() != crc {
+            return Err(SourceError::Protocol("CRC mismatch in response".to_string()));
+        }
+        if body[0] != unit_id {
+            return Err(SourceError::Protocol(format!("response from slave {} instead of {}", body[0], unit_id)));
+        }
+        Ok(body[1..].to_vec())
+    }
+}
+
+impl Transport for RtuConnection {
+    // Frames the PDU with the slave address and CRC and waits out the inter-frame gap first
+    fn transact(&mut self, unit_id: u8, pdu: &[u8]) -> Result<Vec<u8>, SourceError> {
+        let mut frame = Vec::with_capacity(pdu.len() + 3);
+        frame.push(unit_id);
+        frame.extend_from_slice(pdu);
+        frame.extend_from_slice(&crc16(&frame).to_le_bytes());
+
+        thread::sleep(self.frame_gap);
+        self.port.discard_input()?;
+        self.port.write_all(&frame)?;
+        self.read_frame(unit_id, pdu[0])
+    }
+}
+
+// CRC-16/MODBUS, transmitted low byte first
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 })
+    })
+}
+
+fn span(addresses: &[u16]) -> usize {
+    match (addresses.iter().min(), addresses.iter().max()) {
+        (Some(first), Some(last)) => (last - first) as usize + 1,
+        _ => 0,
+    }
+}
+
+// Reads the block covering every address in the list with one request and picks
+// out the listed values. Coils come back as 0 or 1.
+fn read_list(transport: &mut dyn Transport, unit_id: u8, function: u8, addresses: &[u16]) -> Result<Vec<u16>, SourceError> {
+    let first = match addresses.iter().min() {
+        Some(first) => *first,
+        None => return Ok(Vec::new()),
+    };
+    let count = span(addresses) as u16;
+
+    let mut request = vec![function];
+    request.extend_from_slice(&first.to_be_bytes());
+    request.extend_from_slice(&count.to_be_bytes());
+    let response = transport.transact(unit_id, &request)?;
+    let block = decode_read_response(function, &response, count as usize)?;
+    Ok(addresses.iter().map(|address| block[(address - first) as usize]).collect())
+}
+
+fn check_exception(function: u8, pdu: &[u8]) -> Result<(), SourceError> {
+    match pdu {
+        [code, exception, ..] if *code == function | 0x80 => {
+            Err(SourceError::Protocol(format!("device returned exception {} ({})", exception, exception_name(*exception))))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn decode_read_response(function: u8, pdu: &[u8], count: usize) -> Result<Vec<u16>, SourceError> {
+    check_exception(function, pdu)?;
+    if let [code, byte_count, data @ ..] = pdu {
+        if *code == function && *byte_count as usize == data.len() {
+            if function == READ_COILS && data.len() == (count + 7) / 8 {
+                return Ok((0..count).map(|i| ((data[i / 8] >> (i % 8)) & 1) as u16).collect());
+            }
+            if function == READ_HOLDING_REGISTERS && data.len() == count * 2 {
+                return Ok(data.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect());
+            }
+        }
+    }
+    Err(SourceError::Protocol(format!("unexpected response to function 0x{:02X}", function)))
+}
+
+fn exception_name(code: u8) -> &'static str {
+    match code {
+        1 => "illegal function",
+        2 => "illegal data address",
+        3 => "illegal data value",
+        4 => "server device failure",
+        6 => "server device busy",
+        10 => "gateway path unavailable",
+        11 => "gateway target failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    // A device that echoes single writes back; on its first connection it answers with the
+    // wrong transaction ID, and on its third from the wrong unit
+    fn echo_device() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let mut header = [0u8; 7];
+                while stream.read_exact(&mut header).is_ok() {
+                    let mut pdu = vec![0u8; u16::from_be_bytes([header[4], header[5]]) as usize - 1];
+                    if stream.read_exact(&mut pdu).is_err() {
+                        break;
+                    }
+                    match connection {
+                        0 => header[1] = header[1].wrapping_add(1),
+                        2 => header[6] += 1,
+                        _ => {}
+                    }
+                    if stream.write_all(&header).and_then(|()| stream.write_all(&pdu)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        port
+    }
+
+    fn source(port: u16) -> ModbusTcpSource {
+        let map = RegisterMap {
+            temperature_registers: Vec::new(),
+            temperature_scale: 0.1,
+            pressure_registers: Vec::new(),
+            pressure_scale: 1.0,
+            motor_speed_registers: Vec::new(),
+            motor_coils: vec![0],
+            motor_setpoint_registers: vec![100],
+        };
+        ModbusTcpSource::new(ModbusTcpConfig { host: "127.0.0.1".to_string(), port, unit_id: 1, timeout_ms: 2000, map })
+    }
+
+    #[test]
+    fn out_of_step_responses_drop_the_connection() {
+        let mut source = source(echo_device());
+        let setpoint = SourceCommand::MotorSetpoint { motor: 0, rpm: 1500 };
+
+        let error = source.write(&setpoint).unwrap_err();
+        assert_eq!(error, SourceError::Protocol("response to transaction 2 instead of 1".to_string()));
+        assert!(source.connection.is_none());
+        // A fresh connection, in step again
+        assert_eq!(source.write(&setpoint), Ok(()));
+        assert_eq!(source.write(&SourceCommand::MotorRunning { motor: 0, running: true }), Ok(()));
+        assert!(source.connection.is_some());
+        // The third connection answers from the wrong unit
+        source.connection = None;
+        let error = source.write(&setpoint).unwrap_err();
+        assert_eq!(error, SourceError::Protocol("response from unit 2 instead of 1".to_string()));
+        assert!(source.connection.is_none());
+    }
+
+    #[test]
+    fn exceptions_and_read_responses_are_decoded() {
+        assert_eq!(decode_read_response(READ_HOLDING_REGISTERS, &[0x03, 4, 0x01, 0x02, 0xFF, 0xFE], 2), Ok(vec![0x0102, 0xFFFE]));
+        assert_eq!(decode_read_response(READ_COILS, &[0x01, 1, 0b101], 3), Ok(vec![1, 0, 1]));
+        assert!(decode_read_response(READ_HOLDING_REGISTERS, &[0x03, 4, 0x01, 0x02], 2).is_err());
+        let error = decode_read_response(READ_HOLDING_REGISTERS, &[0x83, 2], 2).unwrap_err();
+        assert_eq!(error, SourceError::Protocol("device returned exception 2 (illegal data address)".to_string()));
+        // The example from the Modbus over serial line specification
+        assert_eq!(crc16(&[0x02, 0x07]), 0x1241);
+    }
+}
@@ -0,0 +1,172 @@
+// Operating mode of a device and the only ways between modes. The console moves a device
+// through this table alone, so what each mode allows is decided here rather than by
+// combinations of flags:
+//
+//   Startup      until the startup diagnostic has run and the first readings are in
+//   Running      normal operation
+//   Degraded     the link to the data source is failing or the readings are stale
+//   EStop        after an emergency shutdown or auto-trip; left only by 'reset'
+//   Maintenance  entered by a supervisor: auto-trip is off and motors can't be started
+use crate::i18n::tr;
+use crate::platform::StatusColor;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemMode {
+    Startup,
+    Running,
+    Degraded,
+    EStop,
+    Maintenance,
+}
+
+// What can move a device from one mode to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModeEvent {
+    StartupComplete,
+    LinkLost,
+    LinkRestored,
+    Shutdown,
+    Reset,
+    EnterMaintenance,
+    LeaveMaintenance,
+}
+
+impl SystemMode {
+    // The mode `event` leads to, or why it can't happen now. An emergency shutdown is
+    // never refused.
+    pub fn next(self, event: ModeEvent) -> Result<SystemMode, String> {
+        use ModeEvent::*;
+        use SystemMode::*;
+        match (self, event) {
+            (_, Shutdown) => Ok(EStop),
+            (Startup, StartupComplete) => Ok(Running),
+            (Startup | Running, LinkLost) => Ok(Degraded),
+            (Degraded, LinkRestored) => Ok(Running),
+            (EStop, Reset) => Ok(Running),
+            (Running | Degraded, EnterMaintenance) => Ok(Maintenance),
+            (Maintenance, LeaveMaintenance) => Ok(Running),
+            (EStop, _) => Err("the emergency shutdown must be cleared with 'reset' first".to_string()),
+            (mode, Reset) => Err(format!("there is no emergency shutdown to reset ({})", mode)),
+            (Startup, EnterMaintenance) => Err("the startup checks haven't finished".to_string()),
+            (Maintenance, EnterMaintenance) => Err("already in maintenance".to_string()),
+            (mode, LeaveMaintenance) => Err(format!("not in maintenance ({})", mode)),
+            (mode, event) => Err(format!("{:?} doesn't apply in {}", event, mode)),
+        }
+    }
+
+    pub fn motor_starts_allowed(self) -> Result<(), String> {
+        match self {
+            SystemMode::Maintenance => Err("motors can't be started in maintenance mode ('mode run' leaves it)".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn auto_trip_enabled(self) -> bool {
+        self != SystemMode::Maintenance
+    }
+
+    pub fn color(self) -> StatusColor {
+        match self {
+            SystemMode::Startup => StatusColor::Normal,
+            SystemMode::Running => StatusColor::Green,
+            SystemMode::Degraded => StatusColor::Yellow,
+            SystemMode::EStop => StatusColor::Red,
+            SystemMode::Maintenance => StatusColor::Blue,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemMode::Startup => "STARTUP",
+            SystemMode::Running => "RUNNING",
+            SystemMode::Degraded => "DEGRADED",
+            SystemMode::EStop => "E-STOP",
+            SystemMode::Maintenance => "MAINTENANCE",
+        }
+    }
+
+    // The name as shown on the console, in its language
+    pub fn label(self) -> &'static str {
+        match self {
+            SystemMode::Startup => tr("mode.startup"),
+            SystemMode::Running => tr("mode.running"),
+            SystemMode::Degraded => tr("mode.degraded"),
+            SystemMode::EStop => tr("mode.estop"),
+            SystemMode::Maintenance => tr("mode.maintenance"),
+        }
+    }
+}
+
+impl fmt::Display for SystemMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [SystemMode; 5] = [SystemMode::Startup, SystemMode::Running, SystemMode::Degraded, SystemMode::EStop, SystemMode::Maintenance];
+    const EVENTS: [ModeEvent; 7] = [
+        ModeEvent::StartupComplete,
+        ModeEvent::LinkLost,
+        ModeEvent::LinkRestored,
+        ModeEvent::Shutdown,
+        ModeEvent::Reset,
+        ModeEvent::EnterMaintenance,
+        ModeEvent::LeaveMaintenance,
+    ];
+
+    #[test]
+    fn transitions_follow_the_table() {
+        use ModeEvent::*;
+        use SystemMode::*;
+        // Each mode's row, in the order of EVENTS; None is refused
+        let table = [
+            (Startup, [Some(Running), Some(Degraded), None, Some(EStop), None, None, None]),
+            (Running, [None, Some(Degraded), None, Some(EStop), None, Some(Maintenance), None]),
+            (Degraded, [None, None, Some(Running), Some(EStop), None, Some(Maintenance), None]),
+            (EStop, [None, None, None, Some(EStop), Some(Running), None, None]),
+            (Maintenance, [None, None, None, Some(EStop), None, None, Some(Running)]),
+        ];
+        assert_eq!(table.map(|(mode, _)| mode), MODES);
+        for (mode, row) in table {
+            for (event, expected) in EVENTS.into_iter().zip(row) {
+                match (mode.next(event), expected) {
+                    (Ok(next), Some(expected)) => assert_eq!(next, expected, "{} on {:?}", mode, event),
+                    (Err(reason), None) => assert!(!reason.is_empty()),
+                    (result, expected) => panic!("{} on {:?} gave {:?}, expected {:?}", mode, event, result, expected),
+                }
+            }
+        }
+        assert_eq!(Maintenance.next(LinkLost), Err("LinkLost doesn't apply in MAINTENANCE".to_string()));
+    }
+
+    #[test]
+    fn an_emergency_shutdown_is_left_only_by_reset() {
+        for event in EVENTS.into_iter().filter(|event| !matches!(event, ModeEvent::Reset | ModeEvent::Shutdown)) {
+            assert_eq!(SystemMode::EStop.next(event), Err("the emergency shutdown must be cleared with 'reset' first".to_string()), "{:?}", event);
+        }
+        for mode in MODES.into_iter().filter(|mode| *mode != SystemMode::EStop) {
+            assert!(mode.next(ModeEvent::Reset).is_err(), "reset from {}", mode);
+        }
+    }
+
+    #[test]
+    fn maintenance_bars_motor_starts_and_auto_trip() {
+        for mode in MODES {
+            let maintenance = mode == SystemMode::Maintenance;
+            assert_eq!(mode.motor_starts_allowed().is_err(), maintenance, "{}", mode);
+            assert_eq!(mode.auto_trip_enabled(), !maintenance, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn names_pad_for_the_header() {
+        assert_eq!(format!("[{:<8}]", SystemMode::EStop), "[E-STOP  ]");
+        assert_eq!(SystemMode::Maintenance.to_string(), "MAINTENANCE");
+    }
+}
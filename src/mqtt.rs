@@ -0,0 +1,308 @@
+// MQTT 3.1.1 telemetry publisher. Runs on its own thread and is fed the latest
+// sensor data over a one-slot channel, so a slow or missing broker only ever costs
+// skipped telemetry, never a stalled simulation or console.
+use crate::sensors::SensorData;
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub telemetry_topic: String,
+    // Retained "online"/"offline"; also the last will
+    pub status_topic: String,
+    // 0 or 1
+    pub qos: u8,
+    pub publish_interval_ms: u64,
+}
+
+impl MqttConfig {
+    // Accepts mqtt://host:port, host:port or a bare host
+    pub fn parse_broker(url: &str) -> Result<(String, u16), String> {
+        if url.starts_with("mqtts://") {
+            return Err("TLS brokers (mqtts://) are not supported".to_string());
+        }
+        let address = url.strip_prefix("mqtt://").unwrap_or(url).trim_end_matches('/');
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid broker port '{}'", port))?),
+            None => (address, 1883),
+        };
+        if host.is_empty() {
+            return Err(format!("invalid broker address '{}'", url));
+        }
+        Ok((host.to_string(), port))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MqttStatus {
+    Connecting,
+    Connected,
+    Disconnected { reason: String },
+}
+
+// Owns the publisher thread. Dropping it publishes the offline status and disconnects.
+pub struct MqttPublisher {
+    broker: String,
+    status: Arc<Mutex<MqttStatus>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MqttPublisher {
+    // Returns the publisher and the sender the sensor thread feeds it through
+    pub fn start(config: MqttConfig, system_id: String) -> (MqttPublisher, SyncSender<SensorData>) {
+        let (updates, latest) = mpsc::sync_channel(1);
+        let status = Arc::new(Mutex::new(MqttStatus::Connecting));
+        let stop = Arc::new(AtomicBool::new(false));
+        let broker = format!("{}:{}", config.host, config.port);
+
+        let thread_status = Arc::clone(&status);
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::spawn(move || run_publisher(config, system_id, latest, thread_status, thread_stop));
+
+        (MqttPublisher { broker, status, stop, thread: Some(thread) }, updates)
+    }
+
+    pub fn broker(&self) -> &str {
+        &self.broker
+    }
+
+    pub fn status(&self) -> MqttStatus {
+        self.status.lock().map_or(MqttStatus::Connecting, |status| status.clone())
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_publisher(config: MqttConfig, system_id: String, latest: Receiver<SensorData>, status: Arc<Mutex<MqttStatus>>, stop: Arc<AtomicBool>) {
+    let set_status = |new_status: MqttStatus| {
+        if let Ok(mut status) = status.lock() {
+            *status = new_status;
+        }
+    };
+    let mut failures: u32 = 0;
+    while !stop.load(Ordering::Relaxed) {
+        let result = Session::connect(&config).and_then(|mut session| {
+            set_status(MqttStatus::Connected);
+            failures = 0;
+            session.publish_loop(&config, &system_id, &latest, &stop)
+        });
+        // The session only ends cleanly on shutdown
+        if let Err(e) = result {
+            set_status(MqttStatus::Disconnected { reason: e.to_string() });
+            failures += 1;
+        } else {
+            return;
+        }
+
+        // Same backoff as the PLC link: 1, 2, 4, 8, 16, then every 30 s
+        let retry_at = Instant::now() + Duration::from_secs(1 << failures.saturating_sub(1).min(5)).min(Duration::from_secs(30));
+        while !stop.load(Ordering::Relaxed) && Instant::now() < retry_at {
+            // Keep the channel drained so the latest data is fresh on reconnect
+            if let Err(RecvTimeoutError::Disconnected) = latest.recv_timeout(Duration::from_millis(100)) {
+                return;
+            }
+        }
+    }
+}
+
+struct Session {
+    stream: TcpStream,
+    packet_id: u16,
+    last_sent: Instant,
+}
+
+impl Session {
+    fn connect(config: &MqttConfig) -> io::Result<Session> {
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("can't resolve {}", config.host)))?;
+        let stream = TcpStream::connect_timeout(&address, IO_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut session = Session { stream, packet_id: 0, last_sent: Instant::now() };
+
+        // Clean session, with a retained "offline" will at the telemetry QoS
+        let mut flags = 0x02 | 0x04 | 0x20 | (config.qos << 3);
+        let mut body = Vec::new();
+        put_string(&mut body, "MQTT");
+        body.push(4);
+        if config.username.is_some() {
+            flags |= 0x80;
+        }
+        if config.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+        put_string(&mut body, &config.client_id);
+        put_string(&mut body, &config.status_topic);
+        put_string(&mut body, "offline");
+        for credential in [&config.username, &config.password].into_iter().flatten() {
+            put_string(&mut body, credential);
+        }
+        session.send(CONNECT, &body)?;
+
+        let (packet_type, body) = session.receive()?;
+        match (packet_type & 0xF0, body.as_slice()) {
+            (CONNACK, [_, 0]) => {}
+            (CONNACK, [_, code]) => return Err(io::Error::new(io::ErrorKind::ConnectionRefused, connack_reason(*code))),
+            _ => return Err(invalid_data("expected CONNACK")),
+        }
+
+        session.publish(&config.status_topic, b"online", config.qos, true)?;
+        Ok(session)
+    }
+
+    // Publishes the newest data at most once per interval until stopped, then says goodbye
+    fn publish_loop(&mut self, config: &MqttConfig, system_id: &str, latest: &Receiver<SensorData>, stop: &AtomicBool) -> io::Result<()> {
+        let interval = Duration::from_millis(config.publish_interval_ms);
+        let mut last_publish: Option<Instant> = None;
+        while !stop.load(Ordering::Relaxed) {
+            match latest.recv_timeout(Duration::from_millis(100)) {
+                Ok(data) => {
+                    // Updates arrive with some jitter, so a publish interval equal to the update
+                    // interval would otherwise skip every other one
+                    let now = Instant::now();
+                    let due = match last_publish {
+                        Some(at) => now.duration_since(at) >= interval - interval / 10,
+                        None => true,
+                    };
+                    if due {
+                        last_publish = Some(now);
+                        let payload = data.to_telemetry(system_id).to_string();
+                        self.publish(&config.telemetry_topic, payload.as_bytes(), config.qos, false)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if self.last_sent.elapsed() >= KEEP_ALIVE / 2 {
+                self.send(PINGREQ, &[])?;
+                self.expect(PINGRESP)?;
+            }
+        }
+
+        // A clean DISCONNECT suppresses the will, so the offline status is published explicitly
+        self.publish(&config.status_topic, b"offline", config.qos, true)?;
+        self.send(DISCONNECT, &[])
+    }
+
+    fn publish(&mut self, topic: &str, payload: &[u8], qos: u8, retain: bool) -> io::Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+        put_string(&mut body, topic);
+        if qos > 0 {
+            self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+            body.extend_from_slice(&self.packet_id.to_be_bytes());
+        }
+        body.extend_from_slice(payload);
+        self.send(PUBLISH | (qos << 1) | retain as u8, &body)?;
+        if qos > 0 {
+            let ack = self.expect(PUBACK)?;
+            if ack != self.packet_id.to_be_bytes() {
+                return Err(invalid_data("PUBACK for the wrong packet"));
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, header: u8, body: &[u8]) -> io::Result<()> {
+        let mut packet = vec![header];
+        // Remaining length, 7 bits per byte with a continuation bit
+        let mut length = body.len();
+        loop {
+            let byte = (length % 128) as u8;
+            length /= 128;
+            packet.push(if length > 0 { byte | 0x80 } else { byte });
+            if length == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        self.stream.read_exact(&mut header)?;
+        let mut length = 0;
+        for shift in (0..28).step_by(7) {
+            let mut byte = [0u8; 1];
+            self.stream.read_exact(&mut byte)?;
+            length |= ((byte[0] & 0x7F) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                let mut body = vec![0u8; length];
+                self.stream.read_exact(&mut body)?;
+                return Ok((header[0], body));
+            }
+        }
+        Err(invalid_data("malformed remaining length"))
+    }
+
+    // Nothing is subscribed to, so anything else from the broker is skipped
+    fn expect(&mut self, packet_type: u8) -> io::Result<Vec<u8>> {
+        loop {
+            let (header, body) = self.receive()?;
+            if header & 0xF0 == packet_type {
+                return Ok(body);
+            }
+        }
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, text: &str) {
+    out.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn connack_reason(code: u8) -> String {
+    let reason = match code {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    };
+    format!("broker refused connection: {}", reason)
+}
@@ -0,0 +1,72 @@
+// Operator notes: the console's logbook. Each note is a timestamped line of free text
+// with the user who wrote it, optionally about a channel or an alarm, kept in the
+// historian's daily files so it turns up on the timeline and in the shift summary.
+// Notes are never edited or removed: a correction is a new note naming the one it
+// corrects, so the record shows what was written first as well as what replaced it.
+use crate::clock::{format_datetime, parse_datetime};
+
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    // Numbered across sessions, from 1
+    pub id: u64,
+    pub time: SystemTime,
+    pub user: String,
+    // The channel or alarm it is about, as it was labelled when written; empty if neither
+    pub subject: String,
+    // The note this one corrects
+    pub corrects: Option<u64>,
+    pub text: String,
+}
+
+impl Note {
+    pub const HEADER: [&'static str; 6] = ["timestamp", "id", "user", "subject", "corrects", "text"];
+
+    pub fn to_row(&self) -> Vec<String> {
+        vec![
+            format_datetime(self.time),
+            self.id.to_string(),
+            self.user.clone(),
+            self.subject.clone(),
+            self.corrects.map_or(String::new(), |id| id.to_string()),
+            self.text.clone(),
+        ]
+    }
+
+    // None for a row that doesn't hold a note, e.g. one cut short when the disk filled
+    pub fn from_row(fields: &[String]) -> Option<Note> {
+        if fields.len() != Note::HEADER.len() {
+            return None;
+        }
+        let corrects = match fields[4].as_str() {
+            "" => None,
+            id => Some(id.parse().ok()?),
+        };
+        Some(Note {
+            time: parse_datetime(&fields[0])?,
+            id: fields[1].parse().ok()?,
+            user: fields[2].clone(),
+            subject: fields[3].clone(),
+            corrects,
+            text: fields[5].clone(),
+        })
+    }
+
+    // One line: what it is about, what it corrects and the text
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Note #{}", self.id);
+        if let Some(id) = self.corrects {
+            summary.push_str(&format!(" (corrects #{})", id));
+        }
+        if !self.subject.is_empty() {
+            summary.push_str(&format!(" on {}", self.subject));
+        }
+        format!("{}: {}", summary, self.text)
+    }
+}
+
+// The note that replaced `note`, if a later one corrects it
+pub fn corrected_by(notes: &[Note], note: &Note) -> Option<u64> {
+    notes.iter().filter(|later| later.corrects == Some(note.id)).map(|later| later.id).max()
+}
@@ -0,0 +1,364 @@
+// Outbound notifications for unattended operation: alarms, automatic trips and
+// emergency shutdowns are sent to every configured sink, an HTTP webhook and/or SMTP
+// email. Delivery runs on its own thread with retries, and each attempt's outcome
+// comes back to the console for the audit trail. Plain TCP only; TLS is not supported.
+use crate::clock::format_datetime;
+use crate::json::Value;
+use crate::websocket::base64;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+// Doubled after each failed attempt
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebhookConfig {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl WebhookConfig {
+    // Accepts http://host[:port][/path]
+    pub fn parse_url(url: &str) -> Result<WebhookConfig, String> {
+        if url.starts_with("https://") {
+            return Err("HTTPS webhooks are not supported".to_string());
+        }
+        let rest = url.strip_prefix("http://").ok_or_else(|| format!("webhook URL '{}' must start with http://", url))?;
+        let (address, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| format!("invalid webhook port '{}'", port))?),
+            None => (address, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("invalid webhook URL '{}'", url));
+        }
+        Ok(WebhookConfig { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: u16,
+    pub from: String,
+    pub to: Vec<String>,
+    // AUTH LOGIN is used when both are set
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotifyConfig {
+    pub webhook: Option<WebhookConfig>,
+    pub email: Option<EmailConfig>,
+    // At most one notification per alarm source in this time
+    pub repeat_interval: Duration,
+    // Attempts after the first before a notification is given up on
+    pub retries: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotificationKind {
+    Alarm,
+    AutoTrip,
+    EmergencyShutdown,
+    Test,
+}
+
+impl NotificationKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            NotificationKind::Alarm => "alarm",
+            NotificationKind::AutoTrip => "auto-trip",
+            NotificationKind::EmergencyShutdown => "estop",
+            NotificationKind::Test => "test",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            NotificationKind::Alarm => "ALARM",
+            NotificationKind::AutoTrip => "AUTO-TRIP",
+            NotificationKind::EmergencyShutdown => "EMERGENCY SHUTDOWN",
+            NotificationKind::Test => "Test notification",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub system_id: String,
+    // What the rate limit is kept per, e.g. the alarm source
+    pub source: String,
+    pub message: String,
+    pub time: SystemTime,
+}
+
+impl Notification {
+    pub fn new(kind: NotificationKind, system_id: &str, source: &str, message: &str) -> Self {
+        Notification { kind, system_id: system_id.to_string(), source: source.to_string(), message: message.to_string(), time: SystemTime::now() }
+    }
+
+    pub fn subject(&self) -> String {
+        format!("[{}] {}: {}", self.system_id, self.kind.title(), self.message)
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("system_id".to_string(), self.system_id.as_str().into()),
+            ("kind".to_string(), self.kind.name().into()),
+            ("source".to_string(), self.source.as_str().into()),
+            ("message".to_string(), self.message.as_str().into()),
+            ("time".to_string(), format_datetime(self.time).into()),
+        ])
+    }
+}
+
+// Somewhere notifications are delivered to
+pub trait NotificationSink: Send {
+    fn describe(&self) -> String;
+
+    fn deliver(&mut self, notification: &Notification) -> Result<(), String>;
+}
+
+fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let address = (host, port).to_socket_addrs()?.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address not found"))?;
+    let stream = TcpStream::connect_timeout(&address, IO_TIMEOUT)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok(stream)
+}
+
+pub struct Webhook {
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Self {
+        Webhook { config }
+    }
+}
+
+impl NotificationSink for Webhook {
+    fn describe(&self) -> String {
+        format!("webhook http://{}:{}{}", self.config.host, self.config.port, self.config.path)
+    }
+
+    // Any 2xx response counts as delivered
+    fn deliver(&mut self, notification: &Notification) -> Result<(), String> {
+        let body = notification.to_json().to_string();
+        let mut stream = connect(&self.config.host, self.config.port).map_err(|e| e.to_string())?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.config.path, self.config.host, body.len(), body
+        );
+        stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+        let mut status_line = String::new();
+        BufReader::new(stream.take(1024)).read_line(&mut status_line).map_err(|e| e.to_string())?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            Some(_) => Err(format!("server answered {}", status_line.trim())),
+            None => Err("no HTTP response".to_string()),
+        }
+    }
+}
+
+pub struct Email {
+    config: EmailConfig,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig) -> Self {
+        Email { config }
+    }
+}
+
+// Reads one SMTP reply, following continuation lines, and checks its code class
+fn smtp_reply(reader: &mut BufReader<TcpStream>, expected: char) -> Result<(), String> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("server closed the connection".to_string());
+        }
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match line.chars().next() {
+            Some(class) if class == expected => Ok(()),
+            _ => Err(format!("server answered {}", line.trim())),
+        };
+    }
+}
+
+impl NotificationSink for Email {
+    fn describe(&self) -> String {
+        format!("email to {} via {}:{}", self.config.to.join(", "), self.config.host, self.config.port)
+    }
+
+    fn deliver(&mut self, notification: &Notification) -> Result<(), String> {
+        let stream = connect(&self.config.host, self.config.port).map_err(|e| e.to_string())?;
+        let mut writer = stream.try_clone().map_err(|e| e.to_string())?;
+        let mut reader = BufReader::new(stream);
+        let mut command = |reader: &mut BufReader<TcpStream>, line: &str, expected: char| -> Result<(), String> {
+            write!(writer, "{}\r\n", line).map_err(|e| e.to_string())?;
+            smtp_reply(reader, expected)
+        };
+
+        smtp_reply(&mut reader, '2')?;
+        command(&mut reader, "EHLO forlenza", '2')?;
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            command(&mut reader, "AUTH LOGIN", '3')?;
+            command(&mut reader, &base64(username.as_bytes()), '3')?;
+            command(&mut reader, &base64(password.as_bytes()), '2')?;
+        }
+        command(&mut reader, &format!("MAIL FROM:<{}>", self.config.from), '2')?;
+        for to in &self.config.to {
+            command(&mut reader, &format!("RCPT TO:<{}>", to), '2')?;
+        }
+        command(&mut reader, "DATA", '3')?;
+        // A line starting with a dot has it doubled so it isn't taken for the end of the message
+        let body: Vec<String> = format!("{}\nSource: {}\nTime: {}", notification.message, notification.source, format_datetime(notification.time))
+            .lines()
+            .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+            .collect();
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.",
+            self.config.from, self.config.to.join(", "), notification.subject(), body.join("\r\n")
+        );
+        command(&mut reader, &message, '2')?;
+        let _ = command(&mut reader, "QUIT", '2');
+        Ok(())
+    }
+}
+
+// Owns the delivery thread. Dropping it gives up on anything still waiting for a retry.
+pub struct Notifier {
+    sinks: Vec<String>,
+    repeat_interval: Duration,
+    // When each source was last notified
+    last_sent: Vec<(String, Instant)>,
+    queue: Option<Sender<Notification>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Notifier {
+    // Each attempt's outcome is sent through `outcomes`, failures as errors
+    pub fn start(config: &NotifyConfig, outcomes: Sender<Result<String, String>>) -> Notifier {
+        let mut sinks: Vec<Box<dyn NotificationSink>> = Vec::new();
+        if let Some(webhook) = &config.webhook {
+            sinks.push(Box::new(Webhook::new(webhook.clone())));
+        }
+        if let Some(email) = &config.email {
+            sinks.push(Box::new(Email::new(email.clone())));
+        }
+        let names = sinks.iter().map(|sink| sink.describe()).collect();
+        let (queue, queued) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let retries = config.retries;
+        let thread = thread::spawn(move || run_delivery(sinks, retries, queued, outcomes, &thread_stop));
+        Notifier { sinks: names, repeat_interval: config.repeat_interval, last_sent: Vec::new(), queue: Some(queue), stop, thread: Some(thread) }
+    }
+
+    pub fn sinks(&self) -> &[String] {
+        &self.sinks
+    }
+
+    pub fn repeat_interval(&self) -> Duration {
+        self.repeat_interval
+    }
+
+    // False if the source was notified too recently; a test is never held back
+    pub fn send(&mut self, notification: Notification) -> bool {
+        let now = Instant::now();
+        if notification.kind != NotificationKind::Test {
+            self.last_sent.retain(|(_, sent)| now.duration_since(*sent) < self.repeat_interval);
+            if self.last_sent.iter().any(|(source, _)| *source == notification.source) {
+                return false;
+            }
+            self.last_sent.push((notification.source.clone(), now));
+        }
+        if let Some(queue) = &self.queue {
+            let _ = queue.send(notification);
+        }
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+        true
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.queue = None;
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_delivery(mut sinks: Vec<Box<dyn NotificationSink>>, retries: u32, queue: Receiver<Notification>, outcomes: Sender<Result<String, String>>, stop: &AtomicBool) {
+    // Deliveries waiting for a retry: sink, notification, attempts made and when to try again
+    let mut pending: VecDeque<(usize, Notification, u32, Instant)> = VecDeque::new();
+    while !stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        pending.retain(|(sink, notification, attempts, retry_at)| {
+            if *retry_at <= now {
+                due.push((*sink, notification.clone(), *attempts));
+                false
+            } else {
+                true
+            }
+        });
+        for notification in queue.try_iter() {
+            due.extend((0..sinks.len()).map(|sink| (sink, notification.clone(), 0)));
+        }
+
+        for (sink, notification, attempts) in due {
+            let attempt = attempts + 1;
+            let sink_name = sinks[sink].describe();
+            match sinks[sink].deliver(&notification) {
+                Ok(()) => {
+                    let _ = outcomes.send(Ok(format!("Notification '{}' sent via {}", notification.subject(), sink_name)));
+                }
+                Err(e) if attempt <= retries => {
+                    let delay = FIRST_RETRY_DELAY * 2u32.saturating_pow(attempts);
+                    let _ = outcomes.send(Err(format!(
+                        "Notification '{}' via {} failed (attempt {} of {}): {} - retrying in {} s",
+                        notification.subject(), sink_name, attempt, retries + 1, e, delay.as_secs()
+                    )));
+                    pending.push_back((sink, notification, attempt, Instant::now() + delay));
+                }
+                Err(e) => {
+                    let _ = outcomes.send(Err(format!("Notification '{}' via {} failed after {} attempt(s), giving up: {}", notification.subject(), sink_name, attempt, e)));
+                }
+            }
+        }
+
+        // Woken by stop() and by each new notification
+        let next_retry = pending.iter().map(|(_, _, _, retry_at)| *retry_at).min();
+        let wait = next_retry.map_or(Duration::from_secs(1), |retry_at| retry_at.saturating_duration_since(Instant::now()).min(Duration::from_secs(1)));
+        thread::park_timeout(wait);
+    }
+}
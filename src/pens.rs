@@ -0,0 +1,172 @@
+// Pens of the multi-channel trend chart ('trend all'): which channels are drawn, the
+// character each is plotted with and the axis it is scaled against, plus a fixed range
+// for either axis. Remembered in settings.json; a channel without a stored pen is shown,
+// with temperatures on the left axis and pressures on the right.
+use crate::i18n::tr;
+use crate::json::Value;
+use crate::sensors::ChannelKind;
+
+use std::fmt;
+
+// Given out in channel order; '-' and '=' are left out, being the limit lines of 'trend'
+pub const MARKS: [char; 8] = ['*', 'o', '+', 'x', '#', '@', '%', '&'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Left,
+    Right,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 2] = [Axis::Left, Axis::Right];
+
+    pub fn parse(name: &str) -> Result<Axis, String> {
+        match name {
+            "left" => Ok(Axis::Left),
+            "right" => Ok(Axis::Right),
+            _ => Err(format!("unknown axis '{}' (use left or right)", name)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Axis::Left => "left",
+            Axis::Right => "right",
+        }
+    }
+
+    // The axis as shown on the console, in its language
+    pub fn label(self) -> &'static str {
+        match self {
+            Axis::Left => tr("trend.axis_left"),
+            Axis::Right => tr("trend.axis_right"),
+        }
+    }
+
+    fn default_for(kind: ChannelKind) -> Axis {
+        match kind {
+            ChannelKind::Temperature => Axis::Left,
+            ChannelKind::Pressure => Axis::Right,
+        }
+    }
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pen {
+    pub visible: bool,
+    pub mark: char,
+    pub axis: Axis,
+}
+
+impl Pen {
+    // A printable character other than the chart's own
+    pub fn check_mark(mark: char) -> Result<(), String> {
+        if mark.is_ascii_graphic() && !matches!(mark, '-' | '=' | '|') {
+            Ok(())
+        } else {
+            Err(format!("'{}' can't be used as a pen mark (use a single printable character other than - = |)", mark))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PenSet {
+    // Pens changed from their defaults, by channel name (prefixed like stored limits)
+    pens: Vec<(String, Pen)>,
+    // Fixed low and high of each axis in the units the chart is shown in; None scales to the data
+    left: Option<(f32, f32)>,
+    right: Option<(f32, f32)>,
+}
+
+impl PenSet {
+    // The pen stored for a channel, if it has been changed from the default
+    pub fn stored(&self, name: &str) -> Option<Pen> {
+        self.pens.iter().find(|(stored, _)| stored == name).map(|(_, pen)| *pen)
+    }
+
+    // `position` is the channel's place among all of the device's channels
+    pub fn pen(&self, name: &str, kind: ChannelKind, position: usize) -> Pen {
+        self.pens.iter()
+            .find(|(stored, _)| stored == name)
+            .map_or(Pen { visible: true, mark: MARKS[position % MARKS.len()], axis: Axis::default_for(kind) }, |(_, pen)| *pen)
+    }
+
+    pub fn set_pen(&mut self, name: &str, pen: Pen) {
+        match self.pens.iter_mut().find(|(stored, _)| stored == name) {
+            Some((_, stored)) => *stored = pen,
+            None => self.pens.push((name.to_string(), pen)),
+        }
+    }
+
+    pub fn range(&self, axis: Axis) -> Option<(f32, f32)> {
+        match axis {
+            Axis::Left => self.left,
+            Axis::Right => self.right,
+        }
+    }
+
+    pub fn set_range(&mut self, axis: Axis, range: Option<(f32, f32)>) -> Result<(), String> {
+        if let Some((low, high)) = range {
+            if !(low.is_finite() && high.is_finite() && low < high) {
+                return Err(format!("the {} axis range must run from a lower to a higher number (got {} to {})", axis, low, high));
+            }
+        }
+        match axis {
+            Axis::Left => self.left = range,
+            Axis::Right => self.right = range,
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let range = |range: Option<(f32, f32)>| match range {
+            Some((low, high)) => Value::Array(vec![low.into(), high.into()]),
+            None => Value::Null,
+        };
+        let pens = self.pens.iter().map(|(name, pen)| Value::Object(vec![
+            ("channel".to_string(), name.as_str().into()),
+            ("visible".to_string(), pen.visible.into()),
+            ("mark".to_string(), pen.mark.to_string().into()),
+            ("axis".to_string(), pen.axis.name().into()),
+        ])).collect();
+        Value::Object(vec![
+            ("pens".to_string(), Value::Array(pens)),
+            ("left".to_string(), range(self.left)),
+            ("right".to_string(), range(self.right)),
+        ])
+    }
+
+    // Entries that don't make sense are dropped, leaving those pens at their defaults
+    pub fn from_json(value: &Value) -> PenSet {
+        let pens = value.get("pens")
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|item| {
+                let mut mark = item.str_field("mark").ok()?.chars();
+                let pen = Pen {
+                    visible: item.get("visible")?.as_bool()?,
+                    mark: mark.next().filter(|mark| Pen::check_mark(*mark).is_ok())?,
+                    axis: Axis::parse(item.str_field("axis").ok()?).ok()?,
+                };
+                let channel = item.str_field("channel").ok()?.to_string();
+                mark.next().is_none().then_some((channel, pen))
+            })
+            .collect();
+        let mut pen_set = PenSet { pens, left: None, right: None };
+        for axis in Axis::ALL {
+            let range = value.get(axis.name()).and_then(Value::as_array).and_then(|range| match range {
+                [low, high] => Some((low.as_f64()? as f32, high.as_f64()? as f32)),
+                _ => None,
+            });
+            let _ = pen_set.set_range(axis, range);
+        }
+        pen_set
+    }
+}
@@ -0,0 +1,267 @@
+// PID control for the closed-loop demo: a cooling fan motor holding a temperature.
+// The controller is stepped by the simulation with each sample. In manual, or while
+// the motor can't run, it tracks the output it is given so that going back to auto
+// carries on from there instead of jumping.
+use crate::json::Value;
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Auto,
+    Manual,
+}
+
+impl LoopMode {
+    // As typed in commands and the config file
+    pub fn parse(name: &str) -> Result<LoopMode, String> {
+        match name {
+            "auto" => Ok(LoopMode::Auto),
+            "manual" => Ok(LoopMode::Manual),
+            _ => Err(format!("unknown loop mode '{}' (use auto or manual)", name)),
+        }
+    }
+}
+
+impl fmt::Display for LoopMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoopMode::Auto => write!(f, "AUTO"),
+            LoopMode::Manual => write!(f, "MANUAL"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PidController {
+    // Output per unit of error, per unit of error-second and per unit of error per second
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    pub setpoint: f32,
+    pub output_limits: (f32, f32),
+    // Direct acting: the output rises with the process value, as for cooling
+    pub direct: bool,
+    pub mode: LoopMode,
+    pub output: f32,
+    integral: f32,
+    last_pv: Option<f32>,
+    last_error: f32,
+}
+
+impl PidController {
+    pub fn new(kp: f32, ki: f32, kd: f32, setpoint: f32, output_limits: (f32, f32)) -> Self {
+        PidController {
+            kp,
+            ki,
+            kd,
+            setpoint,
+            output_limits,
+            direct: false,
+            mode: LoopMode::Auto,
+            output: output_limits.0,
+            integral: output_limits.0,
+            last_pv: None,
+            last_error: 0.0,
+        }
+    }
+
+    pub fn error(&self, pv: f32) -> f32 {
+        if self.direct { pv - self.setpoint } else { self.setpoint - pv }
+    }
+
+    // Advances the loop by dt in auto and returns the new output. The integral is held
+    // where it would saturate the output (anti-windup), and the derivative acts on the
+    // process value so setpoint changes don't kick the output. A reading that can't be
+    // trusted holds the output where it is.
+    pub fn update(&mut self, pv: f32, dt: Duration) -> f32 {
+        if self.mode == LoopMode::Manual || !pv.is_finite() || dt.is_zero() {
+            return self.output;
+        }
+        let dt = dt.as_secs_f32();
+        let error = self.error(pv);
+        let proportional = self.kp * error;
+        let rate = self.last_pv.map_or(0.0, |last| (pv - last) / dt);
+        let derivative = self.kd * if self.direct { rate } else { -rate };
+        let (low, high) = self.output_limits;
+        self.integral = (self.integral + self.ki * error * dt).clamp(low - proportional - derivative, high - proportional - derivative);
+        self.output = (proportional + self.integral + derivative).clamp(low, high);
+        self.last_pv = Some(pv);
+        self.last_error = error;
+        self.output
+    }
+
+    // Follows an output set from outside the loop: by hand in manual, or a motor held
+    // stopped in auto. The integral is backed out so the next auto step starts there.
+    pub fn track(&mut self, pv: f32, output: f32) {
+        self.output = output.clamp(self.output_limits.0, self.output_limits.1);
+        if pv.is_finite() {
+            self.last_error = self.error(pv);
+            self.last_pv = Some(pv);
+        }
+        self.integral = self.output - self.kp * self.last_error;
+    }
+
+    // A new proportional gain moves the integral to keep the output where it is
+    pub fn set_gains(&mut self, kp: f32, ki: f32, kd: f32) {
+        self.integral += (self.kp - kp) * self.last_error;
+        self.kp = kp;
+        self.ki = ki;
+        self.kd = kd;
+    }
+
+    // Output as a share of its range, for the faceplate
+    pub fn output_percent(&self) -> f32 {
+        let (low, high) = self.output_limits;
+        (self.output - low) / (high - low) * 100.0
+    }
+}
+
+// Temperature sensor and fan motor (both 0-based) under PID control. Only the built-in
+// simulation models how the fan cools the sensor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlLoop {
+    pub temperature: usize,
+    pub motor: usize,
+    pub pid: PidController,
+}
+
+impl ControlLoop {
+    // Compact form for telemetry; the setpoint and process value are in °C
+    pub fn to_json(&self, pv: f32) -> Value {
+        Value::Object(vec![
+            ("temperature".to_string(), (self.temperature as u64 + 1).into()),
+            ("motor".to_string(), (self.motor as u64 + 1).into()),
+            ("mode".to_string(), self.pid.mode.to_string().to_lowercase().into()),
+            ("setpoint".to_string(), self.pid.setpoint.into()),
+            ("process_value".to_string(), pv.into()),
+            ("output".to_string(), self.pid.output.into()),
+            ("kp".to_string(), self.pid.kp.into()),
+            ("ki".to_string(), self.pid.ki.into()),
+            ("kd".to_string(), self.pid.kd.into()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: Duration = Duration::from_millis(100);
+
+    // A heater warming a first-order process: 0.5°C per unit of output above 20°C ambient,
+    // with a 10 s time constant
+    struct Process {
+        pv: f32,
+    }
+
+    impl Process {
+        fn step(&mut self, output: f32) -> f32 {
+            self.pv += (20.0 + 0.5 * output - self.pv) / 10.0 * DT.as_secs_f32();
+            self.pv
+        }
+    }
+
+    fn run(pid: &mut PidController, process: &mut Process, seconds: u32) {
+        for _ in 0..seconds * 10 {
+            let output = pid.update(process.pv, DT);
+            process.step(output);
+        }
+    }
+
+    #[test]
+    fn settles_on_the_setpoint_of_a_first_order_process() {
+        let mut pid = PidController::new(2.0, 0.5, 0.0, 50.0, (0.0, 100.0));
+        let mut process = Process { pv: 20.0 };
+        run(&mut pid, &mut process, 120);
+        assert!((process.pv - 50.0).abs() < 0.05, "settled at {}", process.pv);
+        assert!((pid.output - 60.0).abs() < 0.1, "holding {}", pid.output);
+        assert!((pid.output_percent() - 60.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn cooling_is_direct_acting() {
+        let mut pid = PidController::new(2.0, 0.0, 0.0, 25.0, (0.0, 100.0));
+        pid.direct = true;
+        assert_eq!(pid.error(30.0), 5.0);
+        assert_eq!(pid.update(30.0, DT), 10.0);
+        assert_eq!(pid.update(20.0, DT), 0.0, "clamped");
+    }
+
+    #[test]
+    fn the_integral_does_not_wind_up_at_the_limit() {
+        // 100°C is out of reach: the heater saturates at 70°C
+        let mut pid = PidController::new(2.0, 0.5, 0.0, 100.0, (0.0, 100.0));
+        let mut process = Process { pv: 20.0 };
+        run(&mut pid, &mut process, 600);
+        assert_eq!(pid.output, 100.0);
+
+        pid.setpoint = 50.0;
+        assert!(pid.update(process.pv, DT) < 10.0, "comes off the limit at once");
+        // A wound-up integral would hold the heater on for minutes
+        run(&mut pid, &mut process, 30);
+        assert!(process.pv < 52.0, "still at {} after 30 s", process.pv);
+        let mut lowest = process.pv;
+        for _ in 0..900 {
+            let output = pid.update(process.pv, DT);
+            lowest = lowest.min(process.step(output));
+        }
+        assert!(lowest > 40.0, "undershot to {}", lowest);
+        assert!((process.pv - 50.0).abs() < 0.05, "settled at {}", process.pv);
+    }
+
+    #[test]
+    fn derivative_acts_on_the_process_value_only() {
+        let mut pid = PidController::new(2.0, 0.0, 1.0, 50.0, (0.0, 1000.0));
+        pid.update(40.0, DT);
+        let before = pid.update(40.0, DT);
+        pid.setpoint = 60.0;
+        assert!((pid.update(40.0, DT) - before - 20.0).abs() < 1e-3, "no derivative kick");
+        // 1°C in 0.1 s against kd 1 takes 10 off
+        assert!((pid.update(41.0, DT) - (2.0 * 19.0 - 10.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn manual_and_bad_readings_hold_the_output() {
+        let mut pid = PidController::new(2.0, 0.5, 0.0, 50.0, (0.0, 100.0));
+        let output = pid.update(40.0, DT);
+        assert_eq!(pid.update(f32::NAN, DT), output);
+        assert_eq!(pid.update(40.0, Duration::ZERO), output);
+        pid.mode = LoopMode::Manual;
+        assert_eq!(pid.update(0.0, DT), output);
+    }
+
+    #[test]
+    fn returning_to_auto_is_bumpless() {
+        let mut pid = PidController::new(2.0, 0.5, 0.0, 50.0, (0.0, 100.0));
+        let mut process = Process { pv: 20.0 };
+        run(&mut pid, &mut process, 120);
+
+        // Hand the heater to the operator at 30 and let the process fall
+        pid.mode = LoopMode::Manual;
+        for _ in 0..300 {
+            pid.track(process.pv, 30.0);
+            process.step(pid.output);
+        }
+        assert_eq!(pid.output, 30.0);
+        pid.mode = LoopMode::Auto;
+        let first = pid.update(process.pv, DT);
+        assert!((first - 30.0).abs() < 1.0, "bumped to {}", first);
+        run(&mut pid, &mut process, 120);
+        assert!((process.pv - 50.0).abs() < 0.05);
+
+        // Retuning doesn't bump either
+        let before = pid.output;
+        pid.set_gains(4.0, 0.5, 0.0);
+        assert!((pid.update(process.pv, DT) - before).abs() < 0.1);
+    }
+
+    #[test]
+    fn modes_parse() {
+        assert_eq!(LoopMode::parse("auto"), Ok(LoopMode::Auto));
+        assert_eq!(LoopMode::parse("manual"), Ok(LoopMode::Manual));
+        assert!(LoopMode::parse("cascade").is_err());
+        assert_eq!(LoopMode::Manual.to_string(), "MANUAL");
+    }
+}
@@ -0,0 +1,469 @@
+// Operating system detection and console output. Everything that talks to the
+// Windows API directly lives here; other platforms get portable fallbacks.
+use crate::i18n::tr_args;
+
+use std::env;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Duration;
+
+// Windows API declarations for legacy functions
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetVersion() -> u32;
+    fn GetStdHandle(nStdHandle: u32) -> *mut std::ffi::c_void;
+    fn SetConsoleTextAttribute(hConsoleOutput: *mut std::ffi::c_void, wAttributes: u16) -> i32;
+    fn SetConsoleTitleW(lpConsoleTitle: *const u16) -> i32;
+    fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    fn Beep(dwFreq: u32, dwDuration: u32) -> i32;
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+// RtlGetVersion reports the real kernel version; GetVersionExW is shimmed on
+// Windows 8.1+ and lies depending on the executable manifest.
+#[cfg(windows)]
+#[link(name = "ntdll")]
+extern "system" {
+    fn RtlGetVersion(lpVersionInformation: *mut OSVERSIONINFOEXW) -> i32;
+}
+
+#[cfg(windows)]
+#[repr(C)]
+#[allow(non_snake_case)]
+struct OSVERSIONINFOEXW {
+    dwOSVersionInfoSize: u32,
+    dwMajorVersion: u32,
+    dwMinorVersion: u32,
+    dwBuildNumber: u32,
+    dwPlatformId: u32,
+    szCSDVersion: [u16; 128],
+    wServicePackMajor: u16,
+    wServicePackMinor: u16,
+    wSuiteMask: u16,
+    wProductType: u8,
+    wReserved: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+    pub service_pack: u16,
+}
+
+impl OsVersion {
+    #[cfg(windows)]
+    pub fn detect() -> Option<OsVersion> {
+        unsafe {
+            let mut version_info = OSVERSIONINFOEXW {
+                dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOEXW>() as u32,
+                dwMajorVersion: 0,
+                dwMinorVersion: 0,
+                dwBuildNumber: 0,
+                dwPlatformId: 0,
+                szCSDVersion: [0; 128],
+                wServicePackMajor: 0,
+                wServicePackMinor: 0,
+                wSuiteMask: 0,
+                wProductType: 0,
+                wReserved: 0,
+            };
+
+            // STATUS_SUCCESS is 0
+            if RtlGetVersion(&mut version_info) == 0 {
+                Some(OsVersion {
+                    major: version_info.dwMajorVersion,
+                    minor: version_info.dwMinorVersion,
+                    build: version_info.dwBuildNumber,
+                    service_pack: version_info.wServicePackMajor,
+                })
+            } else {
+                // Fallback to deprecated GetVersion (removed in Windows 8.1+)
+                let version = GetVersion();
+                Some(OsVersion {
+                    major: version & 0xFF,
+                    minor: (version >> 8) & 0xFF,
+                    build: if version < 0x8000_0000 { version >> 16 } else { 0 },
+                    service_pack: 0,
+                })
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub fn detect() -> Option<OsVersion> {
+        None
+    }
+
+    pub fn is_windows_7(&self) -> bool {
+        // Windows 7 is version 6.1 (SP0 build 7600, SP1 build 7601)
+        self.major == 6 && self.minor == 1
+    }
+
+    pub fn describe(version: Option<OsVersion>) -> String {
+        match version {
+            Some(version) => version.to_string(),
+            None => format!("{} ({})", env::consts::OS, env::consts::ARCH),
+        }
+    }
+}
+
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match (self.major, self.minor) {
+            (6, 1) => "Windows 7",
+            (6, 2) => "Windows 8",
+            (6, 3) => "Windows 8.1",
+            (10, 0) if self.build >= 22000 => "Windows 11",
+            (10, 0) => "Windows 10",
+            _ => "Windows",
+        };
+        write!(f, "{} ({}.{}.{}", name, self.major, self.minor, self.build)?;
+        if self.service_pack > 0 {
+            write!(f, " SP{}", self.service_pack)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Checks the Windows 7 requirement, unless the operator forced compatibility.
+// --simulate-incompatible always wins so the legacy-failure demo stays reachable.
+// Returns whether the check was bypassed.
+pub fn check_compatibility(version: Option<OsVersion>, force_compatible: bool, simulate_incompatible: bool) -> Result<bool, String> {
+    let is_windows_7 = version.is_some_and(|version| version.is_windows_7());
+    let compatible = !simulate_incompatible && (force_compatible || is_windows_7);
+    if !compatible {
+        return Err(tr_args("startup.requires_windows_7", &[&OsVersion::describe(version)]));
+    }
+    Ok(!is_windows_7)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StatusColor {
+    Normal,
+    Green,
+    Yellow,
+    Red,
+    // Readings that are no longer current
+    Gray,
+    // Values forced by hand for commissioning
+    Blue,
+}
+
+// Severity of a log line, taken from its color. Ordered so that a line is shown when its
+// level is at or below the one chosen with --log-level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Result<LogLevel, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            _ => Err(format!("unknown log level '{}' (use error, warn or info)", name)),
+        }
+    }
+
+    // As typed on the command line, in commands and in forlenza.toml
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+        }
+    }
+
+    pub fn tag(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+        }
+    }
+}
+
+impl From<StatusColor> for LogLevel {
+    fn from(color: StatusColor) -> Self {
+        match color {
+            StatusColor::Red => LogLevel::Error,
+            StatusColor::Yellow => LogLevel::Warn,
+            _ => LogLevel::Info,
+        }
+    }
+}
+
+// How the status colors are drawn. Dark suits the usual console; Light uses darker
+// shades for a white background; High Contrast puts statuses on solid color blocks;
+// Colorblind shows normal status in blue so it can't be confused with an alarm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+    HighContrast,
+    Colorblind,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 4] = [Theme::Dark, Theme::Light, Theme::HighContrast, Theme::Colorblind];
+
+    // As typed in commands and forlenza.toml
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::HighContrast => "high-contrast",
+            Theme::Colorblind => "colorblind",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Theme, String> {
+        Theme::ALL.into_iter().find(|theme| theme.name() == name.to_ascii_lowercase()).ok_or_else(|| {
+            let names: Vec<&str> = Theme::ALL.iter().map(|theme| theme.name()).collect();
+            format!("unknown theme '{}' (use {})", name, names.join(", "))
+        })
+    }
+
+    // Console text attribute: background in the high nibble, foreground in the low
+    #[cfg(windows)]
+    fn attribute(self, color: StatusColor) -> u16 {
+        match (self, color) {
+            (_, StatusColor::Normal) => 0x07,
+            (Theme::Dark, StatusColor::Green) => 0x0A,
+            (Theme::Dark | Theme::Colorblind, StatusColor::Yellow) => 0x0E,
+            (Theme::Dark | Theme::Colorblind, StatusColor::Red) => 0x0C,
+            (Theme::Dark | Theme::Colorblind, StatusColor::Gray) => 0x08,
+            (Theme::Light, StatusColor::Green) => 0x02,
+            (Theme::Light, StatusColor::Yellow) => 0x06,
+            (Theme::Light, StatusColor::Red) => 0x04,
+            (Theme::Light, StatusColor::Gray) => 0x08,
+            (Theme::HighContrast, StatusColor::Green) => 0xA0,
+            (Theme::HighContrast, StatusColor::Yellow) => 0xE0,
+            (Theme::HighContrast, StatusColor::Red) => 0xCF,
+            (Theme::HighContrast, StatusColor::Gray) => 0x70,
+            (Theme::Colorblind, StatusColor::Green) => 0x09,
+            (Theme::Dark, StatusColor::Blue) => 0x09,
+            (Theme::Light, StatusColor::Blue) => 0x01,
+            // A solid block, as normal status is already blue text for colorblind users
+            (Theme::HighContrast | Theme::Colorblind, StatusColor::Blue) => 0x1F,
+        }
+    }
+
+    // SGR parameters; None leaves the terminal's own colors
+    #[cfg(not(windows))]
+    fn ansi_code(self, color: StatusColor) -> Option<&'static str> {
+        let code = match (self, color) {
+            (_, StatusColor::Normal) => return None,
+            (Theme::Dark, StatusColor::Green) => "32",
+            (Theme::Dark, StatusColor::Yellow) => "33",
+            (Theme::Dark, StatusColor::Red) => "31",
+            (Theme::Dark | Theme::Light | Theme::Colorblind, StatusColor::Gray) => "90",
+            (Theme::Light, StatusColor::Green) => "32",
+            // Yellow text is unreadable on white, so warnings are dark orange
+            (Theme::Light, StatusColor::Yellow) => "38;5;130",
+            (Theme::Light, StatusColor::Red) => "31",
+            (Theme::HighContrast, StatusColor::Green) => "1;30;102",
+            (Theme::HighContrast, StatusColor::Yellow) => "1;30;103",
+            (Theme::HighContrast, StatusColor::Red) => "1;97;101",
+            (Theme::HighContrast, StatusColor::Gray) => "30;47",
+            (Theme::Colorblind, StatusColor::Green) => "94",
+            (Theme::Colorblind, StatusColor::Yellow) => "93",
+            (Theme::Colorblind, StatusColor::Red) => "91",
+            (Theme::Dark, StatusColor::Blue) => "1;94",
+            (Theme::Light, StatusColor::Blue) => "1;34",
+            // A solid block, as normal status is already blue text for colorblind users
+            (Theme::HighContrast | Theme::Colorblind, StatusColor::Blue) => "1;97;44",
+        };
+        Some(code)
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+// print_colored is called from everywhere, so the theme is process-wide
+static THEME: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_theme(theme: Theme) {
+    let index = Theme::ALL.iter().position(|candidate| *candidate == theme).unwrap_or(0);
+    THEME.store(index as u8, Ordering::Relaxed);
+}
+
+pub fn theme() -> Theme {
+    Theme::ALL.get(THEME.load(Ordering::Relaxed) as usize).copied().unwrap_or(Theme::Dark)
+}
+
+// Headless output goes to a log collector rather than a person: plain lines, each with
+// its severity, flushed as they are written
+static PLAIN_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_plain_output(plain: bool) {
+    PLAIN_OUTPUT.store(plain, Ordering::Relaxed);
+}
+
+fn print_plain(color: StatusColor, text: &str) {
+    use std::io::Write;
+
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{:<5} {}", LogLevel::from(color).tag(), text.trim_start_matches('\n'));
+    let _ = stdout.flush();
+}
+
+// Windows 7 consoles don't understand ANSI escapes, so color goes through the console API there
+#[cfg(windows)]
+pub fn print_colored(color: StatusColor, text: &str) {
+    use std::io::Write;
+
+    if PLAIN_OUTPUT.load(Ordering::Relaxed) {
+        return print_plain(color, text);
+    }
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    let _ = io::stdout().flush();
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        SetConsoleTextAttribute(handle, theme().attribute(color));
+        println!("{}", text);
+        let _ = io::stdout().flush();
+        SetConsoleTextAttribute(handle, 0x07);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn print_colored(color: StatusColor, text: &str) {
+    if PLAIN_OUTPUT.load(Ordering::Relaxed) {
+        return print_plain(color, text);
+    }
+    match theme().ansi_code(color) {
+        Some(code) => println!("\x1b[{}m{}\x1b[0m", code, text),
+        None => println!("{}", text),
+    }
+}
+
+#[cfg(windows)]
+pub fn set_console_title(title: &str) {
+    let wide: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        SetConsoleTitleW(wide.as_ptr());
+    }
+}
+
+#[cfg(not(windows))]
+pub fn set_console_title(title: &str) {
+    use std::io::IsTerminal;
+
+    if io::stdout().is_terminal() {
+        print!("\x1b]0;{}\x07", title);
+    }
+}
+
+// Blocks for the length of the tone. False when there is no sound device to play it on.
+#[cfg(windows)]
+pub fn beep(frequency: u32, duration: Duration) -> bool {
+    unsafe { Beep(frequency.clamp(37, 32767), duration.as_millis() as u32) != 0 }
+}
+
+// Elsewhere the terminal bell stands in, at its own pitch; a console that isn't a
+// terminal, e.g. a service's log, has nothing to ring
+#[cfg(not(windows))]
+pub fn beep(_frequency: u32, duration: Duration) -> bool {
+    use std::io::{IsTerminal, Write};
+
+    if !io::stdout().is_terminal() {
+        return false;
+    }
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "\x07");
+    let _ = stdout.flush();
+    drop(stdout);
+    std::thread::sleep(duration);
+    true
+}
+
+// Set by Ctrl-C, or SIGINT/SIGTERM, once the shutdown handler is installed
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+// The handlers only set a flag; the main loop notices it and shuts down in order
+#[cfg(windows)]
+pub fn install_shutdown_handler() -> bool {
+    unsafe extern "system" fn handler(_event: u32) -> i32 {
+        SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+        1
+    }
+    unsafe { SetConsoleCtrlHandler(Some(handler), 1) != 0 }
+}
+
+#[cfg(unix)]
+pub fn install_shutdown_handler() -> bool {
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" fn handler(_signal: i32) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+    }
+    unsafe { signal(SIGINT, handler) != SIG_ERR && signal(SIGTERM, handler) != SIG_ERR }
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn install_shutdown_handler() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u32, minor: u32, build: u32, service_pack: u16) -> Option<OsVersion> {
+        Some(OsVersion { major, minor, build, service_pack })
+    }
+
+    #[test]
+    fn only_windows_7_passes_the_check() {
+        assert_eq!(check_compatibility(version(6, 1, 7600, 0), false, false), Ok(false));
+        assert_eq!(check_compatibility(version(6, 1, 7601, 1), false, false), Ok(false));
+        for other in [version(6, 0, 6002, 2), version(6, 2, 9200, 0), version(6, 3, 9600, 0), version(10, 0, 19045, 0), version(10, 0, 22631, 0), None] {
+            assert!(check_compatibility(other, false, false).is_err(), "{:?}", other);
+        }
+    }
+
+    #[test]
+    fn forcing_bypasses_the_check_and_says_so() {
+        assert_eq!(check_compatibility(version(10, 0, 19045, 0), true, false), Ok(true));
+        assert_eq!(check_compatibility(None, true, false), Ok(true));
+        // Forcing on Windows 7 bypasses nothing
+        assert_eq!(check_compatibility(version(6, 1, 7601, 1), true, false), Ok(false));
+    }
+
+    #[test]
+    fn simulating_incompatible_always_fails() {
+        let error = check_compatibility(version(6, 1, 7601, 1), true, true).unwrap_err();
+        assert!(error.contains("Windows 7 (6.1.7601 SP1)"), "{}", error);
+    }
+
+    #[test]
+    fn versions_are_named_from_their_numbers() {
+        assert_eq!(version(6, 1, 7601, 1).unwrap().to_string(), "Windows 7 (6.1.7601 SP1)");
+        assert_eq!(version(6, 3, 9600, 0).unwrap().to_string(), "Windows 8.1 (6.3.9600)");
+        assert_eq!(version(10, 0, 19045, 0).unwrap().to_string(), "Windows 10 (10.0.19045)");
+        assert_eq!(version(10, 0, 22631, 0).unwrap().to_string(), "Windows 11 (10.0.22631)");
+        assert_eq!(version(5, 1, 2600, 3).unwrap().to_string(), "Windows (5.1.2600 SP3)");
+    }
+}
@@ -0,0 +1,428 @@
+// Prometheus text exposition of the readings and the console's health, served at
+// /metrics by the embedded HTTP server for plants that scrape everything. The names,
+// types and labels below are what dashboards and alert rules are built on: add new
+// metrics freely, but don't rename or relabel existing ones.
+//
+//   fis_temperature_celsius{system,sensor}            gauge    calibrated reading; NaN without one
+//   fis_pressure_kilopascals{system,sensor}           gauge    calibrated reading; NaN without one
+//   fis_channel_alarm_level{system,sensor}            gauge    0 normal, 1 warning, 2 alarm
+//   fis_channels_in_alarm{system,severity}            gauge    channels at "warning" or "alarm"
+//   fis_channel_forced{system,sensor}                 gauge    0 or 1; the reading above is then the forced value
+//   fis_motor_speed_rpm{system,motor}                 gauge
+//   fis_motor_setpoint_rpm{system,motor}              gauge
+//   fis_motor_running{system,motor}                   gauge    0 or 1
+//   fis_motor_current_amperes{system,motor}           gauge
+//   fis_motor_power_kilowatts{system,motor}           gauge    from the current, supply voltage and power factor
+//   fis_motor_overloaded{system,motor}                gauge    0 or 1, until the relay is reset
+//   fis_interlock_satisfied{system,interlock}         gauge    0 or 1
+//   fis_interlock_bypassed{system,interlock}          gauge    0 or 1
+//   fis_emergency_shutdown{system}                    gauge    0 or 1
+//   fis_alarms_active                                 gauge    alarms in the list not yet cleared
+//   fis_alarms_unacknowledged                         gauge
+//   fis_polls_total{system}                           counter  polls of the data source
+//   fis_poll_errors_total{system}                     counter  polls that failed
+//   fis_link_round_trip_seconds{system}               gauge    last successful poll; absent before one
+//   fis_link_disconnects_total{system}                counter  times the link went offline
+//   fis_link_downtime_seconds_total{system}           counter  time offline, including now
+//   fis_scan_cycle_seconds{system}                    gauge    time between the last two polls
+//   fis_scan_target_seconds{system}                   gauge    the update interval
+//   fis_scan_cycles_total{system}                     counter
+//   fis_scan_overruns_total{system}                   counter  cycles over 1.5 times the target
+//   fis_last_update_timestamp_seconds{system}         gauge    Unix time of the last good poll
+//
+// `sensor` and `motor` are the channel's or motor's tag, or its name when it has none;
+// `system` is the device's system ID. Counters restart from 0 with the console.
+use crate::alarms::{AlarmLevel, AlarmList};
+use crate::sensors::{ChannelKind, Motor, SensorData};
+
+use std::fmt::{Display, Write};
+use std::time::UNIX_EPOCH;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// One device's readings, copied out of its sensor data
+pub struct Target<'a> {
+    pub system_id: &'a str,
+    pub data: &'a SensorData,
+}
+
+pub fn render(targets: &[Target], alarms: &AlarmList) -> String {
+    let mut out = Exposition::default();
+    for (kind, name, help) in [
+        (ChannelKind::Temperature, "fis_temperature_celsius", "Calibrated temperature reading in degrees Celsius."),
+        (ChannelKind::Pressure, "fis_pressure_kilopascals", "Calibrated pressure reading in kilopascals."),
+    ] {
+        out.family(name, "gauge", help);
+        for target in targets {
+            for channel in target.data.channels_of(kind) {
+                out.sample(name, &[("system", target.system_id), ("sensor", identity(&channel.tagging.tag, &channel.name))], float(channel.value));
+            }
+        }
+    }
+
+    out.family("fis_channel_alarm_level", "gauge", "Alarm level of an analog channel: 0 normal, 1 warning, 2 alarm.");
+    for target in targets {
+        for channel in &target.data.channels {
+            out.sample("fis_channel_alarm_level", &[("system", target.system_id), ("sensor", identity(&channel.tagging.tag, &channel.name))], level(channel.alarm.level()));
+        }
+    }
+    out.family("fis_channel_forced", "gauge", "1 while an analog channel is forced to a value by hand.");
+    for target in targets {
+        for channel in &target.data.channels {
+            out.sample("fis_channel_forced", &[("system", target.system_id), ("sensor", identity(&channel.tagging.tag, &channel.name))], flag(channel.forced.is_some()));
+        }
+    }
+    out.family("fis_channels_in_alarm", "gauge", "Analog channels currently at each alarm severity.");
+    for target in targets {
+        for (severity, at) in [("warning", AlarmLevel::Warning), ("alarm", AlarmLevel::Alarm)] {
+            let count = target.data.channels.iter().filter(|channel| channel.alarm.level() == at).count();
+            out.sample("fis_channels_in_alarm", &[("system", target.system_id), ("severity", severity)], count);
+        }
+    }
+
+    type MotorValue = fn(&Motor) -> String;
+    let motor_families: [(&str, &str, MotorValue); 5] = [
+        ("fis_motor_speed_rpm", "Motor speed in revolutions per minute.", |motor| motor.speed.to_string()),
+        ("fis_motor_setpoint_rpm", "Motor speed setpoint in revolutions per minute.", |motor| motor.setpoint.to_string()),
+        ("fis_motor_running", "1 while the motor is commanded to run.", |motor| flag(motor.running)),
+        ("fis_motor_current_amperes", "Motor current in amperes.", |motor| float(motor.current)),
+        ("fis_motor_overloaded", "1 while the motor's overload relay has tripped.", |motor| flag(motor.overloaded)),
+    ];
+    for (name, help, value) in motor_families {
+        out.family(name, "gauge", help);
+        for target in targets {
+            for motor in &target.data.motors {
+                out.sample(name, &[("system", target.system_id), ("motor", identity(&motor.tagging.tag, &motor.name))], value(motor));
+            }
+        }
+    }
+    out.family("fis_motor_power_kilowatts", "gauge", "Motor power in kilowatts, from its current and the supply.");
+    for target in targets {
+        for motor in &target.data.motors {
+            out.sample("fis_motor_power_kilowatts", &[("system", target.system_id), ("motor", identity(&motor.tagging.tag, &motor.name))], float(target.data.supply.power(motor.current)));
+        }
+    }
+
+    out.family("fis_interlock_satisfied", "gauge", "1 while the interlock's condition holds.");
+    for target in targets {
+        for interlock in &target.data.interlocks {
+            out.sample("fis_interlock_satisfied", &[("system", target.system_id), ("interlock", &interlock.name)], flag(interlock.satisfied));
+        }
+    }
+    out.family("fis_interlock_bypassed", "gauge", "1 while the interlock is bypassed by a supervisor.");
+    for target in targets {
+        for interlock in &target.data.interlocks {
+            out.sample("fis_interlock_bypassed", &[("system", target.system_id), ("interlock", &interlock.name)], flag(interlock.bypass.is_some()));
+        }
+    }
+    out.family("fis_emergency_shutdown", "gauge", "1 while the emergency shutdown is active.");
+    for target in targets {
+        out.sample("fis_emergency_shutdown", &[("system", target.system_id)], flag(target.data.emergency_shutdown));
+    }
+
+    out.family("fis_alarms_active", "gauge", "Alarms in the alarm list that have not cleared.");
+    out.sample("fis_alarms_active", &[], alarms.iter().filter(|alarm| alarm.is_active()).count());
+    out.family("fis_alarms_unacknowledged", "gauge", "Alarms in the alarm list awaiting acknowledgment.");
+    out.sample("fis_alarms_unacknowledged", &[], alarms.unacknowledged());
+
+    out.family("fis_polls_total", "counter", "Polls of the data source.");
+    for target in targets {
+        out.sample("fis_polls_total", &[("system", target.system_id)], target.data.link.polls);
+    }
+    out.family("fis_poll_errors_total", "counter", "Polls of the data source that failed.");
+    for target in targets {
+        out.sample("fis_poll_errors_total", &[("system", target.system_id)], target.data.link.failures);
+    }
+    out.family("fis_link_round_trip_seconds", "gauge", "Round trip of the last successful poll in seconds.");
+    for target in targets {
+        if let Some(latency) = target.data.link.last_latency() {
+            out.sample("fis_link_round_trip_seconds", &[("system", target.system_id)], latency.as_secs_f64());
+        }
+    }
+
+    out.family("fis_link_disconnects_total", "counter", "Times the link to the data source went offline.");
+    for target in targets {
+        out.sample("fis_link_disconnects_total", &[("system", target.system_id)], target.data.link.disconnects);
+    }
+    out.family("fis_link_downtime_seconds_total", "counter", "Time the link to the data source has been offline in seconds.");
+    for target in targets {
+        out.sample("fis_link_downtime_seconds_total", &[("system", target.system_id)], target.data.link.downtime().as_secs_f64());
+    }
+
+    let last_cycle = |target: &Target| target.data.scan.last();
+    out.family("fis_scan_cycle_seconds", "gauge", "Time between the last two polls in seconds.");
+    for target in targets {
+        if let Some(cycle) = last_cycle(target) {
+            out.sample("fis_scan_cycle_seconds", &[("system", target.system_id)], cycle.period.as_secs_f64());
+        }
+    }
+    out.family("fis_scan_target_seconds", "gauge", "Target time between polls (the update interval) in seconds.");
+    for target in targets {
+        if let Some(cycle) = last_cycle(target) {
+            out.sample("fis_scan_target_seconds", &[("system", target.system_id)], cycle.target.as_secs_f64());
+        }
+    }
+    out.family("fis_scan_cycles_total", "counter", "Scan cycles timed.");
+    for target in targets {
+        out.sample("fis_scan_cycles_total", &[("system", target.system_id)], target.data.scan.cycles);
+    }
+    out.family("fis_scan_overruns_total", "counter", "Scan cycles that took over 1.5 times their target.");
+    for target in targets {
+        out.sample("fis_scan_overruns_total", &[("system", target.system_id)], target.data.scan.overruns);
+    }
+    out.family("fis_last_update_timestamp_seconds", "gauge", "Unix time of the last good poll.");
+    for target in targets {
+        if let Some(time) = target.data.last_update.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+            out.sample("fis_last_update_timestamp_seconds", &[("system", target.system_id)], time.as_secs_f64());
+        }
+    }
+    out.text
+}
+
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter().map(|(label, text)| format!("{}=\"{}\"", label, escape(text))).collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+}
+
+fn identity<'a>(tag: &'a str, name: &'a str) -> &'a str {
+    if tag.is_empty() { name } else { tag }
+}
+
+// Label values escape backslashes, quotes and line breaks
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Shortest form of the reading, with Prometheus' spelling of the special values
+fn float(value: impl Into<f64> + Display + Copy) -> String {
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        "NaN".to_string()
+    } else if wide.is_infinite() {
+        if wide > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn flag(on: bool) -> String {
+    if on { "1" } else { "0" }.to_string()
+}
+
+fn level(level: AlarmLevel) -> u8 {
+    match level {
+        AlarmLevel::Normal => 0,
+        AlarmLevel::Warning => 1,
+        AlarmLevel::Alarm => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alarms::AlarmState;
+    use crate::api::{ApiConfig, ApiRequest, ApiServer};
+    use crate::config::Config;
+
+    use std::collections::HashSet;
+    use std::io::{Read, Write as _};
+    use std::net::TcpStream;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    type Labels = Vec<(String, String)>;
+
+    #[derive(Debug)]
+    struct Sample {
+        name: String,
+        labels: Labels,
+        value: f64,
+    }
+
+    fn is_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    // Reads `text` by the rules of the text exposition format, version 0.0.4: every sample
+    // under a family declared before it with a known type, valid names, escaped label
+    // values, a value Prometheus accepts and no series twice
+    fn parse(text: &str) -> Result<Vec<Sample>, String> {
+        let mut types = Vec::new();
+        let mut samples = Vec::new();
+        let mut series = HashSet::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.splitn(3, ' ');
+                match (words.next(), words.next(), words.next()) {
+                    (Some("TYPE"), Some(name), Some(kind)) if is_name(name) => {
+                        if !["counter", "gauge", "histogram", "summary", "untyped"].contains(&kind) {
+                            return Err(format!("unknown type in '{}'", line));
+                        }
+                        if types.iter().any(|(declared, _)| declared == name) {
+                            return Err(format!("{} declared twice", name));
+                        }
+                        types.push((name.to_string(), kind.to_string()));
+                    }
+                    (Some("HELP"), Some(name), Some(_)) if is_name(name) => {}
+                    _ => return Err(format!("bad comment '{}'", line)),
+                }
+                continue;
+            }
+            let end = line.find(['{', ' ']).ok_or_else(|| format!("no value in '{}'", line))?;
+            let name = &line[..end];
+            if !is_name(name) {
+                return Err(format!("bad metric name '{}'", name));
+            }
+            match types.last() {
+                Some((family, kind)) if family == name => {
+                    if kind == "counter" && !name.ends_with("_total") {
+                        return Err(format!("counter {} doesn't end in _total", name));
+                    }
+                }
+                _ => return Err(format!("{} isn't under its TYPE line", name)),
+            }
+            let (labels, rest) = match line[end..].strip_prefix('{') {
+                Some(inside) => parse_labels(inside).map_err(|e| format!("{} in '{}'", e, line))?,
+                None => (Vec::new(), &line[end..]),
+            };
+            let value = match rest.strip_prefix(' ').ok_or_else(|| format!("no space before the value in '{}'", line))? {
+                "NaN" => f64::NAN,
+                "+Inf" => f64::INFINITY,
+                "-Inf" => f64::NEG_INFINITY,
+                number => number.parse().map_err(|_| format!("bad value in '{}'", line))?,
+            };
+            if !series.insert(format!("{}{:?}", name, labels)) {
+                return Err(format!("{}{:?} appears twice", name, labels));
+            }
+            samples.push(Sample { name: name.to_string(), labels, value });
+        }
+        Ok(samples)
+    }
+
+    // The labels after the opening brace, and what follows the closing one
+    fn parse_labels(mut text: &str) -> Result<(Labels, &str), String> {
+        let mut labels = Vec::new();
+        loop {
+            if let Some(rest) = text.strip_prefix('}') {
+                return Ok((labels, rest));
+            }
+            let (label, rest) = text.split_once("=\"").ok_or("label without a quoted value")?;
+            if !is_name(label) {
+                return Err(format!("bad label name '{}'", label));
+            }
+            let mut value = String::new();
+            let mut chars = rest.char_indices();
+            text = loop {
+                match chars.next().ok_or("unterminated label value")? {
+                    (at, '"') => break &rest[at + 1..],
+                    (_, '\\') => match chars.next().ok_or("unterminated escape")?.1 {
+                        '\\' => value.push('\\'),
+                        '"' => value.push('"'),
+                        'n' => value.push('\n'),
+                        other => return Err(format!("bad escape \\{}", other)),
+                    },
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.push((label.to_string(), value));
+            text = text.strip_prefix(',').unwrap_or(text);
+        }
+    }
+
+    fn find<'a>(samples: &'a [Sample], name: &str, labels: &[(&str, &str)]) -> Option<&'a Sample> {
+        samples.iter().find(|sample| {
+            sample.name == name && labels.iter().all(|(label, value)| sample.labels.iter().any(|(l, v)| l == label && v == value))
+        })
+    }
+
+    #[test]
+    fn the_parser_rejects_what_prometheus_would() {
+        for bad in [
+            "fis_up 1",
+            "# TYPE fis_up gauge\nfis_up one",
+            "# TYPE fis_up gauge\nfis_up{a=\"1\"} 1\nfis_up{a=\"1\"} 2",
+            "# TYPE fis_up gauge\nfis_up{a=\"\\x\"} 1",
+            "# TYPE fis_polls counter\nfis_polls 1",
+            "# TYPE 1fis gauge",
+        ] {
+            assert!(parse(bad).is_err(), "accepted {:?}", bad);
+        }
+        let samples = parse("# HELP fis_up Up.\n# TYPE fis_up gauge\nfis_up{a=\"q\\\"\\\\\",b=\"x\"} NaN\n").unwrap();
+        assert_eq!(samples[0].labels, [("a".to_string(), "q\"\\".to_string()), ("b".to_string(), "x".to_string())]);
+        assert!(samples[0].value.is_nan());
+    }
+
+    #[test]
+    fn scraping_metrics_gives_valid_exposition_text() {
+        let mut data = SensorData::from_config(&Config::default());
+        data.channels[0].tagging.tag = "TT-101 \"inlet\"".to_string();
+        // An open circuit, as the sensor thread would have classified it
+        data.channels[1].set_raw(f32::NAN);
+        data.channels[1].alarm = AlarmState::settled(AlarmLevel::Alarm);
+        let sensor_data = Arc::new(Mutex::new(data));
+        let config = ApiConfig { bind: "127.0.0.1:0".to_string(), token: None, clients: Vec::new() };
+        let (mut server, _updates) = ApiServer::start(config, Arc::clone(&sensor_data), "plant-a".to_string(), Arc::new(AtomicU64::new(0))).unwrap();
+
+        let address = server.address();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        // Answered as the console's main loop does
+        let mut alarms = AlarmList::default();
+        alarms.raise("plant-a TT-101", "high temperature".to_string());
+        let started = Instant::now();
+        while !client.is_finished() && started.elapsed() < Duration::from_secs(5) {
+            for request in server.pending_requests() {
+                if let ApiRequest::Scrape { reply } = request {
+                    let data = sensor_data.lock().unwrap().clone();
+                    let _ = reply.send(render(&[Target { system_id: "plant-a", data: &data }], &alarms));
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let response = client.join().unwrap();
+        server.stop();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"), "{}", head);
+        assert!(head.contains(&format!("Content-Type: {}", CONTENT_TYPE)), "{}", head);
+        let samples = parse(body).unwrap_or_else(|e| panic!("{}\n{}", e, body));
+
+        let channels = sensor_data.lock().unwrap().channels.len();
+        assert_eq!(samples.iter().filter(|sample| sample.name == "fis_channel_alarm_level").count(), channels);
+        let inlet = find(&samples, "fis_temperature_celsius", &[("system", "plant-a"), ("sensor", "TT-101 \"inlet\"")]).unwrap();
+        assert!((20.0..30.0).contains(&inlet.value));
+        assert!(samples.iter().filter(|sample| sample.name == "fis_temperature_celsius").any(|sample| sample.value.is_nan()), "no reading is NaN");
+        assert_eq!(find(&samples, "fis_motor_running", &[("motor", "Motor 1")]).map(|sample| sample.value), Some(1.0));
+        assert_eq!(find(&samples, "fis_motor_running", &[("motor", "Motor 3")]).map(|sample| sample.value), Some(0.0));
+        assert_eq!(find(&samples, "fis_emergency_shutdown", &[("system", "plant-a")]).map(|sample| sample.value), Some(0.0));
+        assert_eq!(find(&samples, "fis_alarms_active", &[]).map(|sample| sample.value), Some(1.0));
+        assert_eq!(find(&samples, "fis_alarms_unacknowledged", &[]).map(|sample| sample.value), Some(1.0));
+        assert_eq!(find(&samples, "fis_channels_in_alarm", &[("severity", "alarm")]).map(|sample| sample.value), Some(1.0));
+        assert!(find(&samples, "fis_polls_total", &[("system", "plant-a")]).is_some());
+    }
+}
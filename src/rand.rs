@@ -0,0 +1,75 @@
+// Small seedable PRNG (SplitMix64) so the simulation doesn't need external crates.
+// Output is reproducible for a given seed, which matters for classroom demos.
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn random_f32(&mut self) -> f32 {
+        // 24 random bits fill an f32 mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    // Uniform in [lo, hi); returns lo when the range is empty
+    pub fn random_u16_range(&mut self, lo: u16, hi: u16) -> u16 {
+        if hi <= lo {
+            return lo;
+        }
+        let span = (hi - lo) as u64;
+        lo + (self.next_u64() % span) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_are_in_the_unit_interval_and_centered() {
+        let mut rng = Rng::new(7);
+        let samples: Vec<f32> = (0..100_000).map(|_| rng.random_f32()).collect();
+        assert!(samples.iter().all(|sample| (0.0..1.0).contains(sample)));
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!((mean - 0.5).abs() < 0.01, "mean {}", mean);
+        // Both ends of the interval are reached
+        assert!(samples.iter().any(|sample| *sample < 0.001) && samples.iter().any(|sample| *sample > 0.999));
+    }
+
+    #[test]
+    fn ranges_are_half_open() {
+        let mut rng = Rng::new(7);
+        let samples: Vec<u16> = (0..10_000).map(|_| rng.random_u16_range(10, 15)).collect();
+        assert!(samples.iter().all(|sample| (10..15).contains(sample)));
+        for value in 10..15 {
+            assert!(samples.contains(&value), "{} never drawn", value);
+        }
+        assert_eq!(rng.random_u16_range(5, 5), 5);
+        assert_eq!(rng.random_u16_range(9, 3), 9);
+    }
+
+    #[test]
+    fn reseeding_repeats_the_sequence() {
+        let draw = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..100).map(|_| (rng.random_f32(), rng.random_u16_range(0, 1000))).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(42), draw(42));
+        assert_ne!(draw(42), draw(43));
+    }
+}
@@ -0,0 +1,147 @@
+// Production recipes: named sets of motor setpoints and alarm limits for a product,
+// kept in a small JSON file and loaded in one action. A recipe is applied completely
+// or not at all.
+use crate::alarms::Limits;
+use crate::json::Value;
+use crate::pid::LoopMode;
+use crate::sensors::{SensorData, MAX_MOTOR_SETPOINT};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recipe {
+    pub name: String,
+    // One per configured motor, in order
+    pub setpoints: Vec<u16>,
+    // By channel name, in SI; channels not listed keep their limits
+    pub limits: Vec<(String, Limits)>,
+}
+
+impl Recipe {
+    // The running plant's setpoints and every channel's limits
+    pub fn capture(name: &str, data: &SensorData) -> Recipe {
+        Recipe {
+            name: name.to_string(),
+            setpoints: data.motors.iter().map(|motor| motor.setpoint).collect(),
+            limits: data.channels.iter().map(|channel| (channel.name.clone(), channel.limits)).collect(),
+        }
+    }
+
+    // Checks everything against the plant before changing anything. Returns the motors
+    // (0-based) given new setpoints; the fan of a control loop in auto is left to the loop.
+    pub fn apply(&self, data: &mut SensorData) -> Result<Vec<(usize, u16)>, String> {
+        if data.emergency_shutdown {
+            return Err("emergency shutdown is active".to_string());
+        }
+        if self.setpoints.len() != data.motors.len() {
+            return Err(format!("it has setpoints for {} motors but {} are configured", self.setpoints.len(), data.motors.len()));
+        }
+        if let Some(rpm) = self.setpoints.iter().find(|rpm| **rpm > MAX_MOTOR_SETPOINT) {
+            return Err(format!("setpoint {} RPM is above {} RPM", rpm, MAX_MOTOR_SETPOINT));
+        }
+        let mut channels = Vec::new();
+        for (name, limits) in &self.limits {
+            let index = data.channels.iter().position(|channel| channel.name == *name).ok_or_else(|| format!("no channel named '{}'", name))?;
+            limits.validate().map_err(|e| format!("{}: {}", name, e))?;
+            channels.push((index, *limits));
+        }
+
+        let loop_motor = data.control_loop.as_ref().filter(|control| control.pid.mode == LoopMode::Auto).map(|control| control.motor);
+        let setpoints: Vec<(usize, u16)> = self.setpoints.iter().copied().enumerate().filter(|(motor, _)| Some(*motor) != loop_motor).collect();
+        for (motor, rpm) in &setpoints {
+            data.motors[*motor].setpoint = *rpm;
+        }
+        for (index, limits) in channels {
+            data.channels[index].limits = limits;
+        }
+        Ok(setpoints)
+    }
+
+    fn to_json(&self) -> Value {
+        let limits = self.limits.iter().map(|(channel, limits)| Value::Object(vec![
+            ("channel".to_string(), channel.as_str().into()),
+            ("limits".to_string(), limits.to_json()),
+        ])).collect();
+        Value::Object(vec![
+            ("name".to_string(), self.name.as_str().into()),
+            ("setpoints".to_string(), Value::Array(self.setpoints.iter().map(|rpm| (*rpm).into()).collect())),
+            ("limits".to_string(), Value::Array(limits)),
+        ])
+    }
+
+    fn from_json(item: &Value) -> Option<Recipe> {
+        Some(Recipe {
+            name: item.str_field("name").ok()?.to_string(),
+            setpoints: item.array_field("setpoints", |rpm| rpm.as_f64().filter(|rpm| rpm.fract() == 0.0 && (0.0..=MAX_MOTOR_SETPOINT as f64).contains(rpm)).map(|rpm| rpm as u16)).ok()?,
+            limits: item.array_field("limits", |entry| Some((entry.str_field("channel").ok()?.to_string(), Limits::from_json(entry.field("limits").ok()?)?))).ok()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    // A missing file is an empty book; a damaged one is an error rather than being
+    // silently replaced at the next save
+    pub fn load(path: &Path) -> Result<RecipeBook, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(RecipeBook::default()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let state = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+        RecipeBook::from_json(&state)
+    }
+
+    pub fn from_json(state: &Value) -> Result<RecipeBook, String> {
+        if state.get("format").and_then(Value::as_str) != Some("forlenza-recipes") {
+            return Err("not a Forlenza recipes file".to_string());
+        }
+        Ok(RecipeBook { recipes: state.array_field("recipes", Recipe::from_json)? })
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("format".to_string(), "forlenza-recipes".into()),
+            ("version".to_string(), 1u16.into()),
+            ("recipes".to_string(), Value::Array(self.recipes.iter().map(Recipe::to_json).collect())),
+        ])
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.to_json().save(path)
+    }
+
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+
+    // Names are matched without regard to case, as operators type them
+    pub fn find(&self, name: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.name.eq_ignore_ascii_case(name))
+    }
+
+    // Replaces a recipe of the same name; returns whether there was one
+    pub fn store(&mut self, recipe: Recipe) -> bool {
+        match self.recipes.iter_mut().find(|stored| stored.name.eq_ignore_ascii_case(&recipe.name)) {
+            Some(stored) => {
+                *stored = recipe;
+                true
+            }
+            None => {
+                self.recipes.push(recipe);
+                false
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Recipe> {
+        let index = self.recipes.iter().position(|recipe| recipe.name.eq_ignore_ascii_case(name))?;
+        Some(self.recipes.remove(index))
+    }
+}
@@ -0,0 +1,422 @@
+// Replay of recorded readings: a CSV file written by 'export' or the historian, or JSON
+// telemetry as published over MQTT (one message per line, or an array of them), played
+// back through the sensor thread in place of the simulation or a PLC. The transport is
+// shared with the console, which plays, pauses, seeks and changes the speed.
+use crate::clock::parse_datetime;
+use crate::csv;
+use crate::json::Value;
+use crate::scenario::format_elapsed;
+use crate::sensors::{ChannelKind, SensorData};
+use crate::source::{DataSource, SourceError};
+use crate::units::Unit;
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+pub const MAX_SPEED_MULTIPLIER: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    // Recorded time passes this many times faster than real time
+    Times(u32),
+    // One recorded row per update
+    Max,
+}
+
+impl ReplaySpeed {
+    // 1x, 10x or max
+    pub fn parse(text: &str) -> Result<ReplaySpeed, String> {
+        if text == "max" {
+            return Ok(ReplaySpeed::Max);
+        }
+        text.strip_suffix('x')
+            .unwrap_or(text)
+            .parse()
+            .ok()
+            .filter(|times| (1..=MAX_SPEED_MULTIPLIER).contains(times))
+            .map(ReplaySpeed::Times)
+            .ok_or_else(|| format!("invalid replay speed '{}' (use e.g. 1x or 10x, up to {}x, or max)", text, MAX_SPEED_MULTIPLIER))
+    }
+}
+
+impl fmt::Display for ReplaySpeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplaySpeed::Times(times) => write!(f, "{}x", times),
+            ReplaySpeed::Max => write!(f, "max"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayConfig {
+    pub file: PathBuf,
+    pub speed: ReplaySpeed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayState {
+    // The file hasn't been read yet, or couldn't be
+    Loading,
+    Playing,
+    Paused,
+    Complete,
+}
+
+// One recorded update. Readings are in °C and kPa, and were calibrated when recorded, so
+// they are stored as they are; anything not recorded keeps its value.
+#[derive(Clone, Debug, PartialEq)]
+struct ReplayRow {
+    time: SystemTime,
+    // From the first row
+    offset: Duration,
+    // Kind, index within the kind, reading
+    channels: Vec<(ChannelKind, usize, f32)>,
+    motor_speeds: Vec<(usize, u16)>,
+    motor_states: Vec<(usize, bool)>,
+}
+
+impl ReplayRow {
+    fn store(&self, sensor_data: &Mutex<SensorData>) -> Result<(), SourceError> {
+        let mut data = sensor_data.lock().map_err(|_| SourceError::Protocol("sensor data is unavailable".to_string()))?;
+        for (kind, index, value) in &self.channels {
+            match data.channel_mut(*kind, *index) {
+                Some(channel) => channel.set_calibrated(*value),
+                None => return Err(SourceError::Protocol(format!("the recording has {} channel {}, which is not configured", kind, index + 1))),
+            }
+        }
+        for (index, speed) in &self.motor_speeds {
+            let motor = data.motors.get_mut(*index).ok_or_else(|| SourceError::Protocol(format!("the recording has motor {}, which is not configured", index + 1)))?;
+            motor.speed = *speed;
+            motor.current = motor.load_current(*speed as f32);
+        }
+        for (index, running) in &self.motor_states {
+            let motor = data.motors.get_mut(*index).ok_or_else(|| SourceError::Protocol(format!("the recording has motor {}, which is not configured", index + 1)))?;
+            motor.running = *running;
+        }
+        Ok(())
+    }
+}
+
+struct Recording {
+    rows: Vec<ReplayRow>,
+    // Rows that couldn't be read, or were out of time order
+    skipped: usize,
+}
+
+impl Recording {
+    fn load(path: &Path) -> Result<Recording, SourceError> {
+        let text = fs::read_to_string(path).map_err(|e| SourceError::Connection(format!("can't read {}: {}", path.display(), e)))?;
+        let json = matches!(path.extension().and_then(|extension| extension.to_str()), Some("json" | "jsonl"));
+        let rows = if json { read_json(&text) } else { read_csv(&text) }.map_err(SourceError::Protocol)?;
+
+        let mut recording = Recording { rows: Vec::new(), skipped: 0 };
+        for row in rows {
+            match (row, recording.rows.last()) {
+                (Some(row), Some(last)) if row.time < last.time => recording.skipped += 1,
+                (Some(row), _) => recording.rows.push(row),
+                (None, _) => recording.skipped += 1,
+            }
+        }
+        let start = match recording.rows.first() {
+            Some(first) => first.time,
+            None => return Err(SourceError::Protocol(format!("{} has no readable rows", path.display()))),
+        };
+        for row in &mut recording.rows {
+            row.offset = row.time.duration_since(start).unwrap_or_default();
+        }
+        Ok(recording)
+    }
+
+    fn duration(&self) -> Duration {
+        self.rows.last().map_or(Duration::ZERO, |row| row.offset)
+    }
+}
+
+enum Column {
+    Timestamp,
+    Channel(ChannelKind, usize, Unit),
+    MotorSpeed(usize),
+    MotorRunning(usize),
+    // e.g. safety_interlocks, which follows from the readings
+    Ignored,
+}
+
+impl Column {
+    // The names history::csv_header gives, e.g. pressure_2_psi or motor_1_rpm; an export
+    // follows them with the channel's tag and name in brackets
+    fn parse(name: &str) -> Column {
+        let name = name.split(" (").next().unwrap_or(name);
+        let number = |text: &str| text.parse::<usize>().ok().filter(|number| *number > 0).map(|number| number - 1);
+        match name.split('_').collect::<Vec<_>>().as_slice() {
+            ["timestamp"] => Column::Timestamp,
+            ["motor", motor, "rpm"] => number(motor).map_or(Column::Ignored, Column::MotorSpeed),
+            ["motor", motor, "running"] => number(motor).map_or(Column::Ignored, Column::MotorRunning),
+            [kind, channel, unit] => {
+                let kind = ChannelKind::ALL.into_iter().find(|known| known.to_string() == *kind);
+                let unit = Unit::parse(unit).ok();
+                match (kind, number(channel), unit) {
+                    (Some(kind), Some(index), Some(unit)) if unit.kind() == kind => Column::Channel(kind, index, unit),
+                    _ => Column::Ignored,
+                }
+            }
+            _ => Column::Ignored,
+        }
+    }
+}
+
+// None for each row that can't be read
+fn read_csv(text: &str) -> Result<Vec<Option<ReplayRow>>, String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().ok_or("the file is empty")?;
+    let columns: Vec<Column> = csv::parse_row(header).iter().map(|name| Column::parse(name.trim())).collect();
+    if !columns.iter().any(|column| matches!(column, Column::Timestamp)) {
+        return Err("no timestamp column".to_string());
+    }
+    Ok(lines.map(|line| {
+        let fields = csv::parse_row(line);
+        if fields.len() != columns.len() {
+            return None;
+        }
+        let mut row = ReplayRow { time: SystemTime::UNIX_EPOCH, offset: Duration::ZERO, channels: Vec::new(), motor_speeds: Vec::new(), motor_states: Vec::new() };
+        for (column, field) in columns.iter().zip(&fields) {
+            match column {
+                Column::Timestamp => row.time = parse_datetime(field)?,
+                // Left empty by the historian's change-of-value recording: unchanged
+                _ if field.is_empty() => {}
+                Column::Channel(kind, index, unit) => row.channels.push((*kind, *index, unit.to_si(field.parse().ok()?))),
+                Column::MotorSpeed(index) => row.motor_speeds.push((*index, field.parse().ok()?)),
+                Column::MotorRunning(index) => row.motor_states.push((*index, field.parse().ok()?)),
+                Column::Ignored => {}
+            }
+        }
+        Some(row)
+    }).collect())
+}
+
+// Telemetry messages, one per line or all in one array
+fn read_json(text: &str) -> Result<Vec<Option<ReplayRow>>, String> {
+    if text.trim_start().starts_with('[') {
+        let messages = Value::parse(text)?;
+        let messages = messages.as_array().ok_or("expected an array of telemetry messages")?;
+        return Ok(messages.iter().map(telemetry_row).collect());
+    }
+    Ok(text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Value::parse(line).ok().as_ref().and_then(telemetry_row))
+        .collect())
+}
+
+// The fields of SensorData::to_telemetry that describe the plant
+fn telemetry_row(message: &Value) -> Option<ReplayRow> {
+    let mut row = ReplayRow {
+        time: parse_datetime(message.get("timestamp")?.as_str()?)?,
+        offset: Duration::ZERO,
+        channels: Vec::new(),
+        motor_speeds: Vec::new(),
+        motor_states: Vec::new(),
+    };
+    for (key, kind) in [("temperatures", ChannelKind::Temperature), ("pressures", ChannelKind::Pressure)] {
+        for (index, value) in message.get(key)?.as_array()?.iter().enumerate() {
+            // An open circuit is published as null
+            let value = match value {
+                Value::Null => f32::NAN,
+                value => value.as_f64()? as f32,
+            };
+            row.channels.push((kind, index, value));
+        }
+    }
+    for (index, motor) in message.get("motors")?.as_array()?.iter().enumerate() {
+        row.motor_speeds.push((index, motor.get("speed")?.as_f64().filter(|speed| (0.0..=u16::MAX as f64).contains(speed))? as u16));
+        row.motor_states.push((index, motor.get("running")?.as_bool()?));
+    }
+    Some(row)
+}
+
+// Where a replay is up to; shared between the sensor thread and the console
+pub struct Replay {
+    file: PathBuf,
+    speed: ReplaySpeed,
+    paused: bool,
+    recording: Option<Recording>,
+    // Recorded time reached, from the first row
+    position: Duration,
+    // The first row not yet shown
+    next: usize,
+    // Set by a seek, so the row there is shown even while paused
+    seeked: bool,
+}
+
+impl Replay {
+    pub fn new(config: ReplayConfig) -> Replay {
+        Replay { file: config.file, speed: config.speed, paused: false, recording: None, position: Duration::ZERO, next: 0, seeked: false }
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    pub fn speed(&self) -> ReplaySpeed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: ReplaySpeed) {
+        self.speed = speed;
+    }
+
+    pub fn state(&self) -> ReplayState {
+        match &self.recording {
+            None => ReplayState::Loading,
+            Some(recording) if self.next >= recording.rows.len() => ReplayState::Complete,
+            Some(_) if self.paused => ReplayState::Paused,
+            Some(_) => ReplayState::Playing,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.recording.as_ref().map_or(Duration::ZERO, Recording::duration)
+    }
+
+    // When the reading now shown was recorded
+    pub fn recorded_time(&self) -> Option<SystemTime> {
+        let rows = &self.recording.as_ref()?.rows;
+        rows.get(self.next.checked_sub(1)?).map(|row| row.time)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.recording.as_ref().map_or(0, |recording| recording.rows.len())
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.recording.as_ref().map_or(0, |recording| recording.skipped)
+    }
+
+    // A complete replay starts again from the beginning
+    pub fn play(&mut self) -> Result<(), String> {
+        match self.state() {
+            ReplayState::Loading => return Err(format!("{} is not loaded yet", self.file.display())),
+            ReplayState::Playing => return Err("the replay is already playing".to_string()),
+            ReplayState::Complete => self.seek(Duration::ZERO)?,
+            ReplayState::Paused => {}
+        }
+        self.paused = false;
+        Ok(())
+    }
+
+    pub fn pause(&mut self) -> Result<(), String> {
+        match self.state() {
+            ReplayState::Playing | ReplayState::Loading => {
+                self.paused = true;
+                Ok(())
+            }
+            ReplayState::Paused => Err("the replay is already paused".to_string()),
+            ReplayState::Complete => Err("the replay is complete".to_string()),
+        }
+    }
+
+    // The next update shows the last row recorded at or before `position`
+    pub fn seek(&mut self, position: Duration) -> Result<(), String> {
+        let recording = self.recording.as_ref().ok_or_else(|| format!("{} is not loaded yet", self.file.display()))?;
+        if position > recording.duration() {
+            return Err(format!("the recording is only {} long", format_elapsed(recording.duration())));
+        }
+        self.position = position;
+        self.next = recording.rows.partition_point(|row| row.offset <= position).saturating_sub(1);
+        self.seeked = true;
+        Ok(())
+    }
+
+    // Moves the replay on by `dt` of real time and returns the row to show, if it changed
+    fn advance(&mut self, dt: Duration) -> Option<ReplayRow> {
+        let recording = self.recording.as_ref()?;
+        if self.paused && !self.seeked {
+            return None;
+        }
+        if !self.paused {
+            match self.speed {
+                ReplaySpeed::Times(times) => self.position = (self.position + dt * times).min(recording.duration()),
+                ReplaySpeed::Max => {
+                    if let Some(row) = recording.rows.get(self.next) {
+                        self.position = row.offset;
+                    }
+                }
+            }
+        }
+        self.seeked = false;
+        let mut shown = None;
+        while let Some(row) = recording.rows.get(self.next) {
+            if row.offset > self.position {
+                break;
+            }
+            shown = Some(row);
+            self.next += 1;
+        }
+        shown.cloned()
+    }
+}
+
+pub struct ReplaySource {
+    replay: Arc<Mutex<Replay>>,
+    file: PathBuf,
+    // Loading and the end of the recording are reported here
+    reports: Sender<Result<String, String>>,
+}
+
+impl ReplaySource {
+    pub fn new(replay: Arc<Mutex<Replay>>, reports: Sender<Result<String, String>>) -> Self {
+        let file = replay.lock().map(|replay| replay.file.clone()).unwrap_or_default();
+        ReplaySource { replay, file, reports }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Replay>, SourceError> {
+        self.replay.lock().map_err(|_| SourceError::Protocol("replay state is unavailable".to_string()))
+    }
+}
+
+impl DataSource for ReplaySource {
+    fn describe(&self) -> String {
+        format!("Replay of {}", self.file.display())
+    }
+
+    // The file is read on the first poll, so a missing one is retried like a PLC that
+    // doesn't answer. A sensor thread restarted by the watchdog carries on where it was.
+    fn poll(&mut self, sensor_data: &Mutex<SensorData>, dt: Duration) -> Result<(), SourceError> {
+        if self.lock()?.recording.is_none() {
+            let recording = Recording::load(&self.file)?;
+            let skipped = match recording.skipped {
+                0 => String::new(),
+                skipped => format!(", {} malformed row(s) skipped", skipped),
+            };
+            let _ = self.reports.send(Ok(format!(
+                "Replay of {} loaded: {} rows over {}{}",
+                self.file.display(),
+                recording.rows.len(),
+                format_elapsed(recording.duration()),
+                skipped
+            )));
+            self.lock()?.recording = Some(recording);
+        }
+
+        let (row, completed) = {
+            let mut replay = self.lock()?;
+            let was_complete = replay.state() == ReplayState::Complete;
+            let row = replay.advance(dt);
+            (row, !was_complete && replay.state() == ReplayState::Complete)
+        };
+        if let Some(row) = row {
+            row.store(sensor_data)?;
+        }
+        if completed {
+            let _ = self.reports.send(Ok(format!("Replay complete: end of {} reached - readings are held at the last row", self.file.display())));
+        }
+        Ok(())
+    }
+}
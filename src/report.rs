@@ -0,0 +1,245 @@
+// Incident report: the panel as it stood - readings, motors, active alarms, the last
+// diagnostic run and recent trends - in one self-contained HTML file. The trends are
+// drawn as inline SVG, so the file needs nothing else to open and can be attached to a
+// ticket or printed to PDF from any browser. The console gathers the data; rendering and
+// writing happen on a background thread.
+use crate::alarms::{AlarmLevel, Limits};
+use crate::clock::format_datetime;
+use crate::diagnostics::{CheckResult, DiagnosticRun};
+use crate::history::Sample;
+use crate::units::Unit;
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const CHART_WIDTH: f32 = 560.0;
+const CHART_HEIGHT: f32 = 160.0;
+
+#[derive(Clone, Debug)]
+pub struct ReportChannel {
+    // Tag and name
+    pub name: String,
+    pub description: String,
+    pub unit: Unit,
+    // SI, like the limits and samples
+    pub value: f32,
+    pub level: AlarmLevel,
+    pub limits: Limits,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReportMotor {
+    pub name: String,
+    pub description: String,
+    pub status: String,
+    pub speed: u16,
+    pub setpoint: u16,
+    pub current: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub title: String,
+    pub system_id: String,
+    pub source: String,
+    pub user: String,
+    pub generated: SystemTime,
+    // Emergency shutdown, trips, dropped interlocks and the like, worst first
+    pub conditions: Vec<String>,
+    pub channels: Vec<ReportChannel>,
+    pub motors: Vec<ReportMotor>,
+    // ID, state, raised, message
+    pub alarms: Vec<(u32, String, SystemTime, String)>,
+    pub diagnostic: Option<DiagnosticRun>,
+    // How far back the trends go
+    pub window: Duration,
+}
+
+impl Report {
+    // report_YYYYMMDD_HHMMSS.html in `directory`, which is created if need be
+    pub fn write(&self, directory: &Path, stamp: &str) -> Result<PathBuf, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("{} could not be created: {}", directory.display(), e))?;
+        let path = directory.join(format!("report_{}.html", stamp));
+        fs::write(&path, self.to_html()).map_err(|e| format!("{} could not be written: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{} - {}</title>\n<style>{}</style></head><body>\n",
+            escape(&self.system_id),
+            format_datetime(self.generated),
+            STYLE
+        );
+        let _ = writeln!(html, "<h1>{}</h1>", escape(&self.title));
+        let _ = writeln!(
+            html,
+            "<p>System <b>{}</b> &middot; {} &middot; generated {} by {}</p>",
+            escape(&self.system_id),
+            escape(&self.source),
+            format_datetime(self.generated),
+            escape(&self.user)
+        );
+        for condition in &self.conditions {
+            let _ = writeln!(html, "<p class=\"alarm\">{}</p>", escape(condition));
+        }
+
+        html.push_str("<h2>Readings</h2>\n<table><tr><th>Channel</th><th>Reading</th><th>Status</th><th>Warning</th><th>Alarm</th></tr>\n");
+        for channel in &self.channels {
+            let (unit, limits) = (channel.unit, &channel.limits);
+            let _ = writeln!(
+                html,
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{} - {}</td><td>{} - {}</td></tr>",
+                level_class(channel.level),
+                described(&channel.name, &channel.description),
+                escape(&unit.format(channel.value)),
+                level_name(channel.level),
+                unit.number(limits.low_warning),
+                unit.number(limits.high_warning),
+                unit.number(limits.low_alarm),
+                unit.number(limits.high_alarm)
+            );
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Motors</h2>\n<table><tr><th>Motor</th><th>Status</th><th>Speed</th><th>Setpoint</th><th>Current</th></tr>\n");
+        for motor in &self.motors {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{} RPM</td><td>{} RPM</td><td>{:.1} A</td></tr>",
+                described(&motor.name, &motor.description),
+                escape(&motor.status),
+                motor.speed,
+                motor.setpoint,
+                motor.current
+            );
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Active and unacknowledged alarms</h2>\n");
+        if self.alarms.is_empty() {
+            html.push_str("<p>None</p>\n");
+        } else {
+            html.push_str("<table><tr><th>#</th><th>State</th><th>Raised</th><th>Alarm</th></tr>\n");
+            for (id, state, raised, message) in &self.alarms {
+                let _ = writeln!(html, "<tr class=\"alarm\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>", id, escape(state), format_datetime(*raised), escape(message));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("<h2>Last diagnostic</h2>\n");
+        match &self.diagnostic {
+            Some(run) => {
+                let outcome = match &run.result {
+                    Ok(summary) if summary.failures > 0 => format!("{} failure(s), {} warning(s)", summary.failures, summary.warnings),
+                    Ok(summary) if summary.warnings > 0 => format!("{} warning(s)", summary.warnings),
+                    Ok(_) => "all checks passed".to_string(),
+                    Err(e) => format!("aborted: {}", e),
+                };
+                let _ = writeln!(html, "<p>{} run at {}, {} ms: {}</p>", run.trigger, format_datetime(run.started), run.elapsed.as_millis(), escape(&outcome));
+                html.push_str("<table><tr><th>Check</th><th>Result</th></tr>\n");
+                for (step, result) in &run.steps {
+                    let (class, text) = match result {
+                        CheckResult::Pass => ("normal", "OK".to_string()),
+                        CheckResult::Warn(reason) => ("warning", format!("WARNING: {}", reason)),
+                        CheckResult::Fail(reason) => ("alarm", format!("FAILED: {}", reason)),
+                    };
+                    let _ = writeln!(html, "<tr class=\"{}\"><td>{}</td><td>{}</td></tr>", class, escape(step), escape(&text));
+                }
+                html.push_str("</table>\n");
+            }
+            None => html.push_str("<p>No diagnostic has run this session.</p>\n"),
+        }
+
+        let _ = writeln!(html, "<h2>Trends (last {} min)</h2>", self.window.as_secs() / 60);
+        for channel in &self.channels {
+            let _ = writeln!(html, "<h3>{}</h3>", escape(&channel.name));
+            html.push_str(&self.chart(channel));
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+
+    // The samples as a line over the window, with the warning and alarm limits as dashed
+    // lines, in the display unit
+    fn chart(&self, channel: &ReportChannel) -> String {
+        let unit = channel.unit;
+        let limits = unit.limits_from_si(&channel.limits);
+        let values: Vec<(f32, f32)> = channel.samples.iter()
+            .filter(|sample| sample.value.is_finite())
+            .map(|sample| {
+                let age = self.generated.duration_since(sample.time).unwrap_or_default().as_secs_f32();
+                (1.0 - age / self.window.as_secs_f32().max(1.0), unit.from_si(sample.value))
+            })
+            .collect();
+        if values.is_empty() {
+            return "<p>No samples in the window.</p>\n".to_string();
+        }
+        // Room for the limits and the readings, with a margin
+        let bounds = values.iter().map(|(_, value)| *value).chain([limits.low_alarm, limits.high_alarm]);
+        let (low, high) = bounds.fold((f32::MAX, f32::MIN), |(low, high), value| (low.min(value), high.max(value)));
+        let margin = ((high - low) * 0.1).max(0.5);
+        let (low, high) = (low - margin, high + margin);
+        let y = |value: f32| CHART_HEIGHT - (value - low) / (high - low) * CHART_HEIGHT;
+
+        let mut svg = String::new();
+        let _ = writeln!(svg, "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" xmlns=\"http://www.w3.org/2000/svg\">", w = CHART_WIDTH + 60.0, h = CHART_HEIGHT);
+        let _ = writeln!(svg, "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"#fafafa\" stroke=\"#ccc\"/>", CHART_WIDTH, CHART_HEIGHT);
+        for (limit, color) in [(limits.low_alarm, "#c00"), (limits.low_warning, "#d90"), (limits.high_warning, "#d90"), (limits.high_alarm, "#c00")] {
+            let _ = writeln!(
+                svg,
+                "<line x1=\"0\" y1=\"{y:.1}\" x2=\"{w}\" y2=\"{y:.1}\" stroke=\"{color}\" stroke-dasharray=\"4 3\"/><text x=\"{tx}\" y=\"{ty:.1}\" fill=\"{color}\">{label:.*}</text>",
+                unit.decimals(),
+                y = y(limit),
+                w = CHART_WIDTH,
+                color = color,
+                tx = CHART_WIDTH + 4.0,
+                ty = y(limit) + 4.0,
+                label = limit
+            );
+        }
+        let points: Vec<String> = values.iter().map(|(x, value)| format!("{:.1},{:.1}", x.clamp(0.0, 1.0) * CHART_WIDTH, y(*value))).collect();
+        let _ = writeln!(svg, "<polyline fill=\"none\" stroke=\"#036\" stroke-width=\"1.5\" points=\"{}\"/>", points.join(" "));
+        svg.push_str("</svg>\n");
+        let _ = writeln!(svg, "<p class=\"note\">{} min ago at the left to now at the right, in {}</p>", self.window.as_secs() / 60, unit);
+        svg
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}table{border-collapse:collapse;margin-bottom:1em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}th{background:#eee}\
+.warning{background:#fff4cc}.alarm{background:#fdd}.note{color:#666;font-size:smaller}";
+
+fn level_class(level: AlarmLevel) -> &'static str {
+    match level {
+        AlarmLevel::Normal => "normal",
+        AlarmLevel::Warning => "warning",
+        AlarmLevel::Alarm => "alarm",
+    }
+}
+
+fn level_name(level: AlarmLevel) -> &'static str {
+    match level {
+        AlarmLevel::Normal => "Normal",
+        AlarmLevel::Warning => "Warning",
+        AlarmLevel::Alarm => "ALARM",
+    }
+}
+
+// A table cell's name with its description in smaller type underneath, if it has one
+fn described(name: &str, description: &str) -> String {
+    if description.is_empty() {
+        escape(name)
+    } else {
+        format!("{}<br><span class=\"note\">{}</span>", escape(name), escape(description))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,139 @@
+// Timing of the sensor thread's cycles, the way a PLC reports its scan: how far apart
+// polls really were against the update interval, how long each poll took and how long
+// the thread then waited for the sensor data lock. The sensor thread records every
+// cycle; the console warns once the cycle has overrun its target for OVERRUN_CYCLES in a row.
+use crate::json::Value;
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Cycles the averages, maxima and histogram cover
+pub const WINDOW: usize = 120;
+// A cycle this much longer than its target is an overrun
+pub const OVERRUN_FACTOR: f64 = 1.5;
+pub const OVERRUN_CYCLES: u32 = 10;
+
+// Upper bounds of the histogram's bins of deviation from the target, in milliseconds;
+// the last bin takes everything above
+const JITTER_BINS: [(f64, &str); 7] = [
+    (-10.0, "< -10 ms"),
+    (-2.0, "-10 .. -2 ms"),
+    (2.0, "-2 .. +2 ms"),
+    (10.0, "+2 .. +10 ms"),
+    (50.0, "+10 .. +50 ms"),
+    (250.0, "+50 .. +250 ms"),
+    (f64::INFINITY, "> +250 ms"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cycle {
+    // The update interval at the time
+    pub target: Duration,
+    // Since the previous poll began
+    pub period: Duration,
+    pub poll: Duration,
+    pub lock_wait: Duration,
+}
+
+impl Cycle {
+    pub fn is_overrun(&self) -> bool {
+        self.period.as_secs_f64() > self.target.as_secs_f64() * OVERRUN_FACTOR
+    }
+
+    // Positive when late
+    fn deviation_ms(&self) -> f64 {
+        (self.period.as_secs_f64() - self.target.as_secs_f64()) * 1000.0
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanStats {
+    // Oldest first
+    recent: VecDeque<Cycle>,
+    pub cycles: u64,
+    pub overruns: u64,
+    pub consecutive_overruns: u32,
+}
+
+impl ScanStats {
+    pub fn record(&mut self, cycle: Cycle) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(cycle);
+        self.cycles += 1;
+        if cycle.is_overrun() {
+            self.overruns += 1;
+            self.consecutive_overruns = self.consecutive_overruns.saturating_add(1);
+        } else {
+            self.consecutive_overruns = 0;
+        }
+    }
+
+    pub fn last(&self) -> Option<Cycle> {
+        self.recent.back().copied()
+    }
+
+    // Cycles in the window
+    pub fn len(&self) -> usize {
+        self.recent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+
+    pub fn average(&self, measure: impl Fn(&Cycle) -> Duration) -> Option<Duration> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        Some(self.recent.iter().map(measure).sum::<Duration>() / self.recent.len() as u32)
+    }
+
+    pub fn max(&self, measure: impl Fn(&Cycle) -> Duration) -> Option<Duration> {
+        self.recent.iter().map(measure).max()
+    }
+
+    // Average distance of the period from the target, early or late, in milliseconds
+    pub fn jitter_ms(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        Some(self.recent.iter().map(|cycle| cycle.deviation_ms().abs()).sum::<f64>() / self.recent.len() as f64)
+    }
+
+    // How many of the window's cycles fell in each bin of deviation from the target
+    pub fn histogram(&self) -> Vec<(&'static str, usize)> {
+        let mut counts = vec![0; JITTER_BINS.len()];
+        for cycle in &self.recent {
+            let deviation = cycle.deviation_ms();
+            if let Some(bin) = JITTER_BINS.iter().position(|(upper, _)| deviation < *upper) {
+                counts[bin] += 1;
+            }
+        }
+        JITTER_BINS.iter().map(|(_, label)| *label).zip(counts).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let millis = |duration: Option<Duration>| duration.map_or(Value::Null, |duration| (duration.as_secs_f64() * 1000.0).into());
+        let histogram = self.histogram().into_iter().map(|(label, count)| Value::Object(vec![
+            ("deviation".to_string(), label.into()),
+            ("cycles".to_string(), (count as u64).into()),
+        ])).collect();
+        Value::Object(vec![
+            ("target_ms".to_string(), millis(self.last().map(|cycle| cycle.target))),
+            ("cycle_ms".to_string(), millis(self.last().map(|cycle| cycle.period))),
+            ("average_cycle_ms".to_string(), millis(self.average(|cycle| cycle.period))),
+            ("max_cycle_ms".to_string(), millis(self.max(|cycle| cycle.period))),
+            ("jitter_ms".to_string(), self.jitter_ms().map_or(Value::Null, Value::from)),
+            ("jitter_histogram".to_string(), Value::Array(histogram)),
+            ("average_poll_ms".to_string(), millis(self.average(|cycle| cycle.poll))),
+            ("max_poll_ms".to_string(), millis(self.max(|cycle| cycle.poll))),
+            ("average_lock_wait_ms".to_string(), millis(self.average(|cycle| cycle.lock_wait))),
+            ("max_lock_wait_ms".to_string(), millis(self.max(|cycle| cycle.lock_wait))),
+            ("cycles".to_string(), self.cycles.into()),
+            ("overruns".to_string(), self.overruns.into()),
+            ("consecutive_overruns".to_string(), (self.consecutive_overruns as u64).into()),
+        ])
+    }
+}
@@ -0,0 +1,414 @@
+// Scripted training scenarios: a timed list of process overrides, faults, motor
+// commands and digital I/O changes read from a TOML file and played back by the
+// simulation thread. Motor commands are left for the console to carry out through
+// `execute`, like any other command.
+use crate::commands::Command;
+use crate::faults::{FaultKind, FaultTarget};
+use crate::json::Value;
+use crate::sensors::{SensorData, MAX_MOTOR_SETPOINT};
+use crate::toml;
+
+use std::fs;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScenarioAction {
+    Ramp { target: FaultTarget, to: f32, over: Duration },
+    Release(FaultTarget),
+    // Cleared again after `lasting`, if given
+    Fault { target: FaultTarget, kind: FaultKind, lasting: Option<Duration> },
+    ClearFault(FaultTarget),
+    Trip(usize),
+    Setpoint { motor: usize, rpm: u16 },
+    // A digital input or output by name, e.g. a guard door opening
+    Digital { point: String, state: bool },
+    Message(String),
+}
+
+impl ScenarioAction {
+    fn parse(event: &Value) -> Result<ScenarioAction, String> {
+        let target = || {
+            let channel = event.str_field("channel")?;
+            let words: Vec<&str> = channel.split_whitespace().collect();
+            match words.as_slice() {
+                [kind] => FaultTarget::parse(kind, None),
+                [kind, number] => FaultTarget::parse(kind, Some(number)),
+                _ => Err(format!("invalid channel '{}'", channel)),
+            }
+        };
+        let motor = || match target()? {
+            FaultTarget::Motor(index) => Ok(index),
+            other => Err(format!("{} is not a motor", other)),
+        };
+
+        let action = event.str_field("action")?;
+        match action {
+            "ramp" => {
+                let target = target()?;
+                if !matches!(target, FaultTarget::Temperature(_) | FaultTarget::Pressure(_)) {
+                    return Err(format!("{} can't be ramped (use setpoint for motors)", target));
+                }
+                let over = match event.get("over") {
+                    Some(over) => over.as_f64().filter(|over| *over >= 0.0).ok_or("field 'over' must be a number of seconds")?,
+                    None => 0.0,
+                };
+                Ok(ScenarioAction::Ramp { target, to: event.f64_field("to")? as f32, over: Duration::from_secs_f64(over) })
+            }
+            "release" => Ok(ScenarioAction::Release(target()?)),
+            "fault" => {
+                let fault = event.str_field("fault")?;
+                let kind = match (fault, event.get("offset")) {
+                    ("offset", Some(offset)) => FaultKind::Offset(offset.as_f64().ok_or("field 'offset' must be a number")? as f32),
+                    ("offset", None) => return Err("offset faults need an 'offset' field".to_string()),
+                    (fault, _) => FaultKind::parse(&[fault])?,
+                };
+                let target = target()?;
+                if target == FaultTarget::PlcLink && !matches!(kind, FaultKind::OpenCircuit | FaultKind::NoiseBurst | FaultKind::Hang) {
+                    return Err("the PLC link can only be dropped, made noisy or hung".to_string());
+                }
+                if kind == FaultKind::Hang && target != FaultTarget::PlcLink {
+                    return Err("only the PLC link can hang".to_string());
+                }
+                if kind == FaultKind::Overload && !matches!(target, FaultTarget::Motor(_)) {
+                    return Err("only motors can be overloaded".to_string());
+                }
+                if kind == FaultKind::BearingWear && !matches!(target, FaultTarget::Motor(_)) {
+                    return Err("only motors have bearings".to_string());
+                }
+                let lasting = match event.get("duration") {
+                    Some(duration) => Some(duration.as_f64().filter(|duration| *duration > 0.0).ok_or("field 'duration' must be a positive number of seconds")?),
+                    None => None,
+                };
+                Ok(ScenarioAction::Fault { target, kind, lasting: lasting.map(Duration::from_secs_f64) })
+            }
+            "clear-fault" => Ok(ScenarioAction::ClearFault(target()?)),
+            "trip" => Ok(ScenarioAction::Trip(motor()?)),
+            "setpoint" => {
+                let rpm = event.f64_field("rpm")?;
+                if !(0.0..=MAX_MOTOR_SETPOINT as f64).contains(&rpm) {
+                    return Err(format!("setpoint must be between 0 and {} RPM", MAX_MOTOR_SETPOINT));
+                }
+                Ok(ScenarioAction::Setpoint { motor: motor()?, rpm: rpm as u16 })
+            }
+            "set" => Ok(ScenarioAction::Digital { point: event.str_field("point")?.to_string(), state: event.bool_field("state")? }),
+            "message" => Ok(ScenarioAction::Message(event.str_field("text")?.to_string())),
+            _ => Err(format!("unknown action '{}'", action)),
+        }
+    }
+
+    fn target(&self) -> Option<FaultTarget> {
+        match self {
+            ScenarioAction::Ramp { target, .. }
+            | ScenarioAction::Release(target)
+            | ScenarioAction::Fault { target, .. }
+            | ScenarioAction::ClearFault(target) => Some(*target),
+            ScenarioAction::Trip(motor) | ScenarioAction::Setpoint { motor, .. } => Some(FaultTarget::Motor(*motor)),
+            ScenarioAction::Digital { .. } | ScenarioAction::Message(_) => None,
+        }
+    }
+
+    // The control command a motor event stands for; None for what the simulation does itself
+    pub fn command(&self, device: usize) -> Option<Command> {
+        match self {
+            ScenarioAction::Trip(motor) => Some(Command::StopMotor { device, motor: motor + 1 }),
+            ScenarioAction::Setpoint { motor, rpm } => Some(Command::SetSetpoint { device, motor: motor + 1, rpm: *rpm }),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, data: &mut SensorData) -> Result<(), String> {
+        match self {
+            ScenarioAction::Ramp { target, to, over } => data.set_override(*target, *to, *over),
+            ScenarioAction::Release(target) => {
+                data.release_override(*target);
+            }
+            ScenarioAction::Fault { target, kind, lasting } => data.inject_fault(*target, *kind, *lasting)?,
+            ScenarioAction::ClearFault(target) => {
+                data.clear_fault(*target);
+            }
+            ScenarioAction::Digital { point, state } => data.set_digital_point(point, *state)?,
+            // Carried out by the console as commands
+            ScenarioAction::Trip(_) | ScenarioAction::Setpoint { .. } => {}
+            ScenarioAction::Message(_) => {}
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            ScenarioAction::Ramp { target, to, over } if over.is_zero() => format!("{} steps to {:.1}", target, to),
+            ScenarioAction::Ramp { target, to, over } => format!("{} ramps to {:.1} over {} s", target, to, over.as_secs_f32()),
+            ScenarioAction::Release(target) => format!("{} released to normal simulation", target),
+            ScenarioAction::Fault { target, kind, lasting: Some(lasting) } => format!("fault injected on {} for {} s: {}", target, lasting.as_secs_f32(), kind),
+            ScenarioAction::Fault { target, kind, lasting: None } => format!("fault injected on {}: {}", target, kind),
+            ScenarioAction::ClearFault(target) => format!("fault cleared on {}", target),
+            ScenarioAction::Trip(motor) => format!("Motor {} trips", motor + 1),
+            ScenarioAction::Setpoint { motor, rpm } => format!("Motor {} setpoint changed to {} RPM", motor + 1, rpm),
+            ScenarioAction::Digital { point, state } => format!("{} set {}", point, if *state { "ON" } else { "OFF" }),
+            ScenarioAction::Message(text) => text.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioEvent {
+    pub at: Duration,
+    pub action: ScenarioAction,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub description: String,
+    // Sorted by time
+    pub events: Vec<ScenarioEvent>,
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Scenario, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Scenario::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Scenario, String> {
+        let document = toml::parse(text)?;
+        let name = document.str_field("name")?.to_string();
+        let description = document.get("description").and_then(Value::as_str).unwrap_or_default().to_string();
+
+        let mut events = Vec::new();
+        let items = document.field("events")?.as_array().ok_or("'events' must be an array of tables")?;
+        for (i, item) in items.iter().enumerate() {
+            let event = || -> Result<ScenarioEvent, String> {
+                let at = item.f64_field("at")?;
+                if !(0.0..=86_400.0).contains(&at) {
+                    return Err("'at' must be between 0 and 86400 seconds".to_string());
+                }
+                Ok(ScenarioEvent { at: Duration::from_secs_f64(at), action: ScenarioAction::parse(item)? })
+            };
+            events.push(event().map_err(|e| format!("event {}: {}", i + 1, e))?);
+        }
+        if events.is_empty() {
+            return Err("scenario has no events".to_string());
+        }
+        // Stable, so events at the same time fire in file order
+        events.sort_by_key(|event| event.at);
+
+        Ok(Scenario { name, description, events })
+    }
+
+    // Rejects scenarios that refer to channels or digital points this system doesn't have
+    pub fn check_channels(&self, data: &SensorData) -> Result<(), String> {
+        for event in &self.events {
+            match (&event.action, event.action.target()) {
+                (_, Some(target)) if !data.has_channel(target) => return Err(format!("no {} on this system", target)),
+                (ScenarioAction::Digital { point, .. }, _) if data.find_digital_point(point).is_none() => {
+                    return Err(format!("no digital input or output named '{}' on this system", point));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.events.last().map_or(Duration::ZERO, |event| event.at)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackState {
+    Ready,
+    Running,
+    Paused,
+    Finished,
+}
+
+// Scenario time advances with the simulation steps rather than the wall clock, so
+// pausing the scenario or the simulation holds it, and playback can be driven headlessly.
+#[derive(Clone)]
+pub struct ScenarioPlayer {
+    scenario: Option<Scenario>,
+    state: PlaybackState,
+    elapsed: Duration,
+    next_event: usize,
+    messages: Vec<String>,
+    // Motor events waiting for the console
+    commands: Vec<ScenarioAction>,
+}
+
+impl Default for ScenarioPlayer {
+    fn default() -> Self {
+        ScenarioPlayer { scenario: None, state: PlaybackState::Ready, elapsed: Duration::ZERO, next_event: 0, messages: Vec::new(), commands: Vec::new() }
+    }
+}
+
+impl ScenarioPlayer {
+    pub fn load(&mut self, scenario: Scenario) {
+        *self = ScenarioPlayer { scenario: Some(scenario), ..ScenarioPlayer::default() };
+    }
+
+    pub fn scenario(&self) -> Option<&Scenario> {
+        self.scenario.as_ref()
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn events_fired(&self) -> usize {
+        self.next_event
+    }
+
+    // Starts or resumes playback
+    pub fn start(&mut self) -> Result<(), String> {
+        match (&self.scenario, self.state) {
+            (None, _) => Err("no scenario loaded".to_string()),
+            (Some(_), PlaybackState::Running) => Err("scenario is already running".to_string()),
+            (Some(_), PlaybackState::Finished) => Err("scenario has finished (reset it to play again)".to_string()),
+            (Some(_), _) => {
+                self.state = PlaybackState::Running;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn pause(&mut self) -> Result<(), String> {
+        if self.state != PlaybackState::Running {
+            return Err("scenario is not running".to_string());
+        }
+        self.state = PlaybackState::Paused;
+        Ok(())
+    }
+
+    // Rewinds to the start; the caller restores the plant
+    pub fn reset(&mut self) {
+        self.state = PlaybackState::Ready;
+        self.elapsed = Duration::ZERO;
+        self.next_event = 0;
+        self.commands.clear();
+    }
+
+    // Advances scenario time by one simulation step and returns the actions that came due;
+    // motor events are kept for `take_commands` instead. Each event fires exactly once,
+    // however playback is paused and resumed.
+    pub fn advance(&mut self, dt: Duration) -> Vec<ScenarioAction> {
+        let scenario = match &self.scenario {
+            Some(scenario) if self.state == PlaybackState::Running => scenario,
+            _ => return Vec::new(),
+        };
+
+        self.elapsed += dt;
+        let mut due = Vec::new();
+        while let Some(event) = scenario.events.get(self.next_event).filter(|event| event.at <= self.elapsed) {
+            self.messages.push(format!("Scenario t={}: {}", format_elapsed(event.at), event.action.describe()));
+            if event.action.command(0).is_some() {
+                self.commands.push(event.action.clone());
+            } else {
+                due.push(event.action.clone());
+            }
+            self.next_event += 1;
+        }
+        if self.next_event == scenario.events.len() {
+            self.state = PlaybackState::Finished;
+            self.messages.push(format!("Scenario '{}' finished at t={}", scenario.name, format_elapsed(self.elapsed)));
+        }
+        due
+    }
+
+    // Log lines for events fired since the last call
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+
+    // Motor events fired since the last call, for the console to carry out
+    pub fn take_commands(&mut self) -> Vec<ScenarioAction> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::rand::Rng;
+    use crate::sensors::ChannelKind;
+    use crate::simulation::{self, ProcessModel, SimulationModel};
+
+    const EXAMPLE: &str = include_str!("../scenarios/pressure_excursion.toml");
+
+    // Steps a default plant a second at a time as the sensor thread and the console
+    // would, returning Pressure Gauge 2 and motor 1's speed after each second
+    fn play(player: &mut ScenarioPlayer, data: &mut SensorData, rng: &mut Rng, seconds: usize) -> Vec<(f32, u16)> {
+        let model = SimulationModel::Process(ProcessModel::default());
+        (0..seconds)
+            .map(|_| {
+                for action in player.advance(Duration::from_secs(1)) {
+                    action.apply(data).unwrap();
+                }
+                for action in player.take_commands() {
+                    if let ScenarioAction::Trip(motor) = action {
+                        data.set_motor_state(motor + 1, false).unwrap();
+                    }
+                }
+                simulation::simulate_step(data, rng, Duration::from_secs(1), &model);
+                (data.channel(ChannelKind::Pressure, 1).unwrap().value, data.motors[0].speed)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_example_scenario_plays_its_trajectory() {
+        let scenario = Scenario::parse(EXAMPLE).unwrap();
+        let mut data = SensorData::from_config(&Config::default());
+        scenario.check_channels(&data).unwrap();
+        let mut player = ScenarioPlayer::default();
+        player.load(scenario);
+        player.start().unwrap();
+        let trace = play(&mut player, &mut data, &mut Rng::new(3), 180);
+        // trace[n] is the plant after n + 1 seconds
+        let at = |second: usize| trace[second - 1];
+
+        assert!(trace[..29].iter().all(|(pressure, _)| (98.0..103.0).contains(pressure)), "steady before the ramp");
+        assert!(at(40).0 > at(35).0 && at(45).0 > at(40).0, "climbing");
+        assert!((at(55).0 - 110.0).abs() < 0.01, "held at 110 kPa: {}", at(55).0);
+        assert!(at(59).1 > 1500 && at(70).1 == 0, "motor 1 trips at 60 s");
+        assert!(!data.motors[0].running);
+        // Stuck at 75 s while the process ramps down underneath
+        assert!(trace[75..119].iter().all(|(pressure, _)| (pressure - at(75).0).abs() < 0.01), "stuck");
+        let steady = trace[10..29].iter().map(|(pressure, _)| pressure).sum::<f32>() / 19.0;
+        assert!((at(180).0 - steady).abs() < 0.5, "released back to the model: {} against {}", at(180).0, steady);
+
+        assert_eq!(player.state(), PlaybackState::Finished);
+        assert_eq!(player.events_fired(), 8);
+        let messages = player.take_messages();
+        assert!(messages.last().unwrap().starts_with("Scenario 'Pressure excursion with motor trip' finished"), "{:?}", messages);
+    }
+
+    #[test]
+    fn events_fire_once_across_pause_and_resume() {
+        let mut data = SensorData::from_config(&Config::default());
+        let mut rng = Rng::new(3);
+        let mut player = ScenarioPlayer::default();
+        player.load(Scenario::parse(EXAMPLE).unwrap());
+        player.start().unwrap();
+        play(&mut player, &mut data, &mut rng, 65);
+        player.pause().unwrap();
+        play(&mut player, &mut data, &mut rng, 30);
+        assert_eq!(player.elapsed(), Duration::from_secs(65), "paused scenario time stands still");
+        assert_eq!(player.events_fired(), 3);
+        player.start().unwrap();
+        play(&mut player, &mut data, &mut rng, 70);
+        let messages = player.take_messages();
+        assert_eq!(messages.iter().filter(|message| message.contains("Motor 1 trips")).count(), 1);
+        assert_eq!(messages.iter().filter(|message| message.starts_with("Scenario t=")).count(), 8);
+    }
+}
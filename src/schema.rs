@@ -0,0 +1,131 @@
+// Versions of the forlenza.toml layout. A file names the version it was written for in a
+// top-level schema_version; a file without one is version 1, the layout from before
+// versions were numbered. An older file is brought up to CURRENT_VERSION as it is loaded,
+// one version at a time, and a file newer than this console is refused rather than read
+// with settings it doesn't know about.
+//
+// The steps only rework the parsed document, so the loader decides what happens to the
+// file itself. To change the layout, raise CURRENT_VERSION, add the step from the old
+// version to the new one at the end of STEPS, and update forlenza.example.toml.
+use crate::json::Value;
+
+pub const CURRENT_VERSION: u64 = 1;
+const KEY: &str = "schema_version";
+
+type Step = fn(Value) -> Result<Value, String>;
+// STEPS[n] turns a version n + 1 document into version n + 2
+const STEPS: [Step; CURRENT_VERSION as usize - 1] = [];
+
+pub fn version(document: &Value) -> Result<u64, String> {
+    match document.get(KEY) {
+        None => Ok(1),
+        Some(Value::Number(version)) if version.fract() == 0.0 && *version >= 1.0 => Ok(*version as u64),
+        Some(_) => Err(format!("{} must be a whole number from 1", KEY)),
+    }
+}
+
+// Brings a document up to CURRENT_VERSION, and returns it with the version it was written for
+pub fn migrate(document: Value) -> Result<(Value, u64), String> {
+    migrate_through(document, &STEPS)
+}
+
+// `migrate` with the given steps, the last of which makes the current version
+fn migrate_through(document: Value, steps: &[Step]) -> Result<(Value, u64), String> {
+    let current = steps.len() as u64 + 1;
+    let from = version(&document)?;
+    if from > current {
+        return Err(format!(
+            "it is for schema version {} but this console reads versions up to {} - it was written for a newer release, so upgrade the console or use a file written for this one",
+            from, current,
+        ));
+    }
+    let mut document = document;
+    for (step, to) in steps[from as usize - 1..].iter().zip(from + 1..) {
+        document = step(document).map_err(|e| format!("upgrading it to schema version {}: {}", to, e))?;
+    }
+    if let Value::Object(members) = &mut document {
+        members.retain(|(key, _)| key != KEY);
+        members.insert(0, (KEY.to_string(), current.into()));
+    }
+    Ok((document, from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::toml;
+
+    // Version 2 renamed simulation.update_interval_ms to interval_ms; version 3 moved
+    // system.title to a [display] table
+    fn rename_interval(mut document: Value) -> Result<Value, String> {
+        if let Value::Object(members) = &mut document {
+            for (_, simulation) in members.iter_mut().filter(|(name, _)| name == "simulation") {
+                if let Value::Object(settings) = simulation {
+                    for (key, _) in settings.iter_mut().filter(|(key, _)| key == "update_interval_ms") {
+                        *key = "interval_ms".to_string();
+                    }
+                }
+            }
+        }
+        Ok(document)
+    }
+
+    fn move_title(mut document: Value) -> Result<Value, String> {
+        let title = document.get("system").and_then(|system| system.get("title")).cloned();
+        if let (Value::Object(members), Some(title)) = (&mut document, title) {
+            if !title.as_str().is_some_and(|title| !title.is_empty()) {
+                return Err("system.title must not be empty".to_string());
+            }
+            for (_, system) in members.iter_mut().filter(|(name, _)| name == "system") {
+                if let Value::Object(settings) = system {
+                    settings.retain(|(key, _)| key != "title");
+                }
+            }
+            members.push(("display".to_string(), Value::Object(vec![("title".to_string(), title)])));
+        }
+        Ok(document)
+    }
+
+    const SYNTHETIC: [Step; 2] = [rename_interval, move_title];
+
+    // One fixture per historical version, each with the same settings
+    const VERSION_1: &str = "[system]\ntitle = \"Line 2\"\n\n[simulation]\nupdate_interval_ms = 500\n";
+    const VERSION_2: &str = "schema_version = 2\n\n[system]\ntitle = \"Line 2\"\n\n[simulation]\ninterval_ms = 500\n";
+    const VERSION_3: &str = "schema_version = 3\n\n[system]\n\n[simulation]\ninterval_ms = 500\n\n[display]\ntitle = \"Line 2\"\n";
+
+    #[test]
+    fn every_version_arrives_at_the_current_layout() {
+        let current = toml::parse(VERSION_3).unwrap();
+        for (fixture, version) in [(VERSION_1, 1), (VERSION_2, 2), (VERSION_3, 3)] {
+            let (document, from) = migrate_through(toml::parse(fixture).unwrap(), &SYNTHETIC).unwrap();
+            assert_eq!(from, version);
+            assert_eq!(document, current, "from version {}", version);
+        }
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let error = migrate_through(toml::parse("schema_version = 4\n").unwrap(), &SYNTHETIC).unwrap_err();
+        assert!(error.contains("schema version 4") && error.contains("up to 3"), "{}", error);
+        let error = migrate(toml::parse(&format!("schema_version = {}\n", CURRENT_VERSION + 1)).unwrap()).unwrap_err();
+        assert!(error.contains("newer release"), "{}", error);
+    }
+
+    #[test]
+    fn failed_steps_and_bad_versions_are_reported() {
+        let error = migrate_through(toml::parse("[system]\ntitle = \"\"\n").unwrap(), &SYNTHETIC).unwrap_err();
+        assert_eq!(error, "upgrading it to schema version 3: system.title must not be empty");
+        for bad in ["schema_version = 0\n", "schema_version = 1.5\n", "schema_version = \"2\"\n"] {
+            assert!(migrate(toml::parse(bad).unwrap()).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn the_example_file_is_current() {
+        let example = toml::parse(include_str!("../forlenza.example.toml")).unwrap();
+        assert_eq!(version(&example), Ok(CURRENT_VERSION));
+        let (migrated, from) = migrate(example.clone()).unwrap();
+        assert_eq!((migrated, from), (example, CURRENT_VERSION));
+    }
+}
@@ -0,0 +1,1374 @@
+// Live process values for the plant and the operations that change them.
+use crate::alarms::{AlarmFilter, AlarmLevel, AlarmState, AutoTrip, Limits, RateLimit, RateState};
+use crate::ambient::Ambient;
+use crate::calibration::Calibration;
+use crate::clock::format_datetime;
+use crate::config::Config;
+use crate::energy::Supply;
+use crate::faults::{FaultKind, FaultTarget, InjectedFault};
+use crate::heartbeat::{LinkStats, LinkThresholds};
+use crate::i18n::{tr, tr_args};
+use crate::interlocks::{Bypass, Interlock};
+use crate::json::Value;
+use crate::pid::{ControlLoop, LoopMode};
+use crate::rand::Rng;
+use crate::scan::ScanStats;
+use crate::source::ConnectionStatus;
+use crate::stats::RunningStats;
+use crate::units::Units;
+
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const MAX_MOTOR_SETPOINT: u16 = 3600;
+pub const MOTOR_SPEED_TOLERANCE: u16 = 20;
+// A motor draws its full-load amps at this speed with a load factor of 1
+pub const RATED_MOTOR_SPEED: u16 = 1800;
+pub const DEFAULT_FULL_LOAD_AMPS: f32 = 10.0;
+pub const DEFAULT_LOAD_FACTOR: f32 = 0.75;
+pub const DEFAULT_OVERLOAD_TIME: Duration = Duration::from_secs(10);
+// Update intervals that may pass without new data before the readings count as stale
+pub const STALE_INTERVALS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelKind {
+    Temperature,
+    Pressure,
+}
+
+impl ChannelKind {
+    // Display order in the sensor panel, exports and telemetry
+    pub const ALL: [ChannelKind; 2] = [ChannelKind::Temperature, ChannelKind::Pressure];
+
+    // As typed in commands and scenario files
+    pub fn parse(name: &str) -> Result<ChannelKind, String> {
+        match name {
+            "temp" => Ok(ChannelKind::Temperature),
+            "pressure" => Ok(ChannelKind::Pressure),
+            _ => Err(tr_args("channels.unknown_kind", &[&name])),
+        }
+    }
+
+    // Appended straight to a reading, hence the space before kPa
+    pub fn unit(self) -> &'static str {
+        match self {
+            ChannelKind::Temperature => "°C",
+            ChannelKind::Pressure => " kPa",
+        }
+    }
+
+    // Heading for the channels of this kind in the sensor panel
+    pub fn group_name(self) -> &'static str {
+        match self {
+            ChannelKind::Temperature => tr("channels.temperature"),
+            ChannelKind::Pressure => tr("channels.pressure"),
+        }
+    }
+
+    // The kind as shown on the console, in its language
+    pub fn label(self) -> &'static str {
+        match self {
+            ChannelKind::Temperature => tr("channels.kind_temperature"),
+            ChannelKind::Pressure => tr("channels.kind_pressure"),
+        }
+    }
+
+    // Channels without a configured name are called e.g. "Pressure Gauge 2"
+    pub fn default_name(self, index: usize) -> String {
+        match self {
+            ChannelKind::Temperature => format!("Temperature Sensor {}", index + 1),
+            ChannelKind::Pressure => format!("Pressure Gauge {}", index + 1),
+        }
+    }
+}
+
+// How a channel or motor is known on the plant floor besides its name: a short
+// instrument tag such as "TT-101", a line of description, and the asset ID on the bar
+// code maintenance scans. Any may be empty.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tagging {
+    pub tag: String,
+    pub description: String,
+    pub asset: String,
+}
+
+impl Tagging {
+    pub const MAX_TAG: usize = 16;
+    pub const MAX_DESCRIPTION: usize = 80;
+    pub const MAX_ASSET: usize = 32;
+
+    // Tags are compared ignoring case, so "tt-101" and "TT-101" clash
+    pub fn check_tag(tag: &str) -> Result<(), String> {
+        if tag.chars().count() > Tagging::MAX_TAG {
+            Err(format!("tag '{}' is longer than {} characters", tag, Tagging::MAX_TAG))
+        } else if tag.contains(char::is_whitespace) {
+            Err(format!("tag '{}' contains a space", tag))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Asset IDs are compared ignoring case too, as scanners differ
+    pub fn check_asset(asset: &str) -> Result<(), String> {
+        if asset.chars().count() > Tagging::MAX_ASSET {
+            Err(format!("asset ID '{}' is longer than {} characters", asset, Tagging::MAX_ASSET))
+        } else if asset.contains(|c: char| c.is_whitespace() || c.is_control()) {
+            Err(format!("asset ID '{}' contains a space", asset))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Whether `id`, as typed or scanned, is this one's asset ID
+    pub fn has_asset(&self, id: &str) -> bool {
+        !self.asset.is_empty() && self.asset.eq_ignore_ascii_case(id)
+    }
+
+    pub fn check_description(description: &str) -> Result<(), String> {
+        if description.chars().count() > Tagging::MAX_DESCRIPTION {
+            Err(format!("description is longer than {} characters", Tagging::MAX_DESCRIPTION))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Tag and name as alarms, panels, exports and the audit trail show them, e.g.
+    // "TT-101 Reactor jacket"; the name alone without a tag
+    pub fn label(&self, name: &str) -> String {
+        if self.tag.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} {}", self.tag, name)
+        }
+    }
+}
+
+// Each tag used by more than one of the (tag, name) pairs, with the names using it, e.g.
+// "TT-101 (Reactor inlet, Reactor outlet)"; empty when every tag is unique
+pub fn duplicate_tags<'a>(tagged: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<String> {
+    let mut groups: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (tag, name) in tagged.into_iter().filter(|(tag, _)| !tag.is_empty()) {
+        match groups.iter_mut().find(|(seen, _)| seen.eq_ignore_ascii_case(tag)) {
+            Some((_, names)) => names.push(name),
+            None => groups.push((tag, vec![name])),
+        }
+    }
+    groups.into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(tag, names)| format!("{} ({})", tag, names.join(", ")))
+        .collect()
+}
+
+impl fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelKind::Temperature => write!(f, "temperature"),
+            ChannelKind::Pressure => write!(f, "pressure"),
+        }
+    }
+}
+
+// An analog input with its latest reading and alarm limits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub tagging: Tagging,
+    pub kind: ChannelKind,
+    pub unit: &'static str,
+    // Calibrated; what everything downstream of acquisition sees
+    pub value: f32,
+    // As acquired, before calibration
+    pub raw: f32,
+    // Calibrated value put in place of the reading for commissioning. Alarms, interlocks,
+    // the control loop and the history all see it; it is never saved.
+    pub forced: Option<f32>,
+    pub calibration: Calibration,
+    pub limits: Limits,
+    pub filter: AlarmFilter,
+    pub alarm: AlarmState,
+    pub rate_limit: RateLimit,
+    pub rate: RateState,
+    pub stats: RunningStats,
+}
+
+impl Channel {
+    fn new(name: String, kind: ChannelKind, value: f32, limits: Limits, filter: AlarmFilter) -> Self {
+        Channel {
+            name,
+            tagging: Tagging::default(),
+            kind,
+            unit: kind.unit(),
+            value,
+            raw: value,
+            forced: None,
+            calibration: Calibration::default(),
+            limits,
+            filter,
+            alarm: AlarmState::settled(limits.classify(value)),
+            rate_limit: RateLimit::default(),
+            rate: RateState::default(),
+            stats: RunningStats::new(SystemTime::now()),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        self.tagging.label(&self.name)
+    }
+
+    // Stores a reading from the data source, calibrating it
+    pub fn set_raw(&mut self, raw: f32) {
+        self.raw = raw;
+        self.value = self.forced.unwrap_or(self.measured());
+    }
+
+    // Stores a reading that is already calibrated, e.g. a recorded one
+    pub fn set_calibrated(&mut self, value: f32) {
+        self.raw = self.calibration.raw_for(value);
+        self.value = self.forced.unwrap_or(value);
+    }
+
+    // Takes effect on the current reading at once
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+        self.value = self.forced.unwrap_or(self.measured());
+    }
+
+    // The calibrated reading from the data source, forced or not
+    pub fn measured(&self) -> f32 {
+        self.calibration.apply(self.raw)
+    }
+
+    // None puts the measured value back
+    pub fn force(&mut self, forced: Option<f32>) {
+        self.forced = forced;
+        self.value = forced.unwrap_or(self.measured());
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Motor {
+    pub name: String,
+    pub tagging: Tagging,
+    pub speed: u16,
+    pub setpoint: u16,
+    pub running: bool,
+    // Amps, derived from speed and load
+    pub current: f32,
+    pub full_load_amps: f32,
+    pub load_factor: f32,
+    // Seconds the thermal overload relay has spent heating, less the time it has had to cool
+    pub overload_heat: f32,
+    // Tripped by the overload relay; the motor can't be started until it is reset
+    pub overloaded: bool,
+}
+
+impl Motor {
+    fn new(name: String, speed: u16, setpoint: u16, running: bool) -> Self {
+        Motor {
+            name,
+            tagging: Tagging::default(),
+            speed,
+            setpoint,
+            running,
+            current: 0.0,
+            full_load_amps: DEFAULT_FULL_LOAD_AMPS,
+            load_factor: DEFAULT_LOAD_FACTOR,
+            overload_heat: 0.0,
+            overloaded: false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        self.tagging.label(&self.name)
+    }
+
+    // What the motor draws at `speed`: its share of full load grows in step with speed
+    pub fn load_current(&self, speed: f32) -> f32 {
+        self.full_load_amps * self.load_factor * speed / RATED_MOTOR_SPEED as f32
+    }
+
+    // Advances the thermal overload relay by dt. It heats while the draw is above full-load
+    // amps and cools at the same rate otherwise, and trips once it has heated for
+    // `trip_after`. Returns whether it tripped just now; stopping the motor is up to the caller.
+    pub fn heat_overload(&mut self, dt: Duration, trip_after: Duration) -> bool {
+        if self.current > self.full_load_amps {
+            self.overload_heat += dt.as_secs_f32();
+        } else {
+            self.overload_heat = (self.overload_heat - dt.as_secs_f32()).max(0.0);
+        }
+        if self.overloaded || self.overload_heat < trip_after.as_secs_f32() {
+            return false;
+        }
+        self.overloaded = true;
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoKind {
+    Input,
+    Output,
+}
+
+impl IoKind {
+    // As typed in commands
+    pub fn parse(name: &str) -> Result<IoKind, String> {
+        match name {
+            "input" => Ok(IoKind::Input),
+            "output" => Ok(IoKind::Output),
+            _ => Err(tr_args("io.unknown_kind", &[&name])),
+        }
+    }
+}
+
+impl fmt::Display for IoKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IoKind::Input => write!(f, "input"),
+            IoKind::Output => write!(f, "output"),
+        }
+    }
+}
+
+// A discrete input such as a guard door switch, or an output such as a valve solenoid.
+// On means the contact is closed or the output energized.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DigitalPoint {
+    pub name: String,
+    pub state: bool,
+    // Held at `state` for training, whatever the simulation or the operator does
+    pub forced: bool,
+    // The state without the force, which the point goes back to when it is released
+    pub live: bool,
+}
+
+impl DigitalPoint {
+    pub fn new(name: &str, state: bool) -> Self {
+        DigitalPoint { name: name.to_string(), state, forced: false, live: state }
+    }
+
+    // A forced point takes the new state once released
+    pub fn set(&mut self, state: bool) {
+        self.live = state;
+        if !self.forced {
+            self.state = state;
+        }
+    }
+
+    pub fn force(&mut self, state: bool) {
+        self.forced = true;
+        self.state = state;
+    }
+
+    // Returns whether the point was forced
+    pub fn release(&mut self) -> bool {
+        self.state = self.live;
+        std::mem::replace(&mut self.forced, false)
+    }
+
+    fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("name".to_string(), self.name.as_str().into()),
+            ("state".to_string(), self.state.into()),
+            ("forced".to_string(), self.forced.into()),
+        ])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensorData {
+    // Grouped by kind, in ChannelKind::ALL order
+    pub channels: Vec<Channel>,
+    pub motors: Vec<Motor>,
+    pub motor_ramp_rate: u16,
+    // Time above full-load amps before a motor's overload relay trips
+    pub overload_time: Duration,
+    // What the motors' power is worked out from
+    pub supply: Supply,
+    // Conditions that must hold for motors to run, re-evaluated on every sample
+    pub interlocks: Vec<Interlock>,
+    pub digital_inputs: Vec<DigitalPoint>,
+    pub digital_outputs: Vec<DigitalPoint>,
+    // A fan motor holding a temperature, stepped with the simulation
+    pub control_loop: Option<ControlLoop>,
+    pub emergency_shutdown: bool,
+    // Why the system shut itself down, until reset
+    pub auto_trip: Option<AutoTrip>,
+    // Set by the console in maintenance mode; the sensor thread then doesn't check the trip limits
+    pub trips_inhibited: bool,
+    // Shutdown cleared, but the motors haven't been restarted yet
+    pub estop_cleared: bool,
+    // Motors (0-based) that were running when the shutdown tripped, with their setpoints
+    pub restart_motors: Vec<(usize, u16)>,
+    pub faults: Vec<InjectedFault>,
+    pub overrides: Vec<ProcessOverride>,
+    // Set while a loaded snapshot is displayed so the simulation doesn't overwrite it
+    pub frozen: bool,
+    pub last_update: Option<SystemTime>,
+    // State of the link to the data source, maintained by the sensor thread
+    pub connection: ConnectionStatus,
+    // Round trips and lost polls behind the link's health
+    pub link: LinkStats,
+    // Timing of the sensor thread's cycles
+    pub scan: ScanStats,
+    // Weather around the plant, while the process model is simulating it
+    pub ambient: Option<Ambient>,
+}
+
+// Drives a channel's process value toward a target instead of letting it drift,
+// e.g. a scripted pressure excursion. Held at the target until released.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessOverride {
+    pub target: FaultTarget,
+    pub value: f32,
+    pub setpoint: f32,
+    // Units per second; infinite for an immediate step
+    pub rate: f32,
+}
+
+impl SensorData {
+    pub fn from_config(config: &Config) -> Self {
+        let channels = |kind: ChannelKind, values: &[f32], names: &[String], tagging: &[Tagging], limits: Limits, filter: AlarmFilter| -> Vec<Channel> {
+            let (rate_limit, rate_limits) = match kind {
+                ChannelKind::Temperature => (config.temperature_rate, &config.temperature_rate_limits),
+                ChannelKind::Pressure => (config.pressure_rate, &config.pressure_rate_limits),
+            };
+            values.iter().enumerate().map(|(i, value)| {
+                let mut channel = Channel::new(names.get(i).cloned().unwrap_or_else(|| kind.default_name(i)), kind, *value, limits, filter);
+                channel.tagging = tagging.get(i).cloned().unwrap_or_default();
+                // Channels past the end of the list take the limit for the type
+                channel.rate_limit = RateLimit { limit: rate_limits.get(i).copied().unwrap_or(rate_limit.limit), ..rate_limit };
+                channel
+            }).collect()
+        };
+        let mut all_channels = channels(ChannelKind::Temperature, &config.temperatures, &config.temperature_names, &config.temperature_tagging, config.temperature_limits, config.temperature_filter);
+        all_channels.extend(channels(ChannelKind::Pressure, &config.pressures, &config.pressure_names, &config.pressure_tagging, config.pressure_limits, config.pressure_filter));
+        // Motors configured as running start out at their setpoint
+        let motors = config.motor_setpoints.iter().zip(&config.motor_running).enumerate()
+            .map(|(i, (setpoint, running))| {
+                let name = config.motor_names.get(i).cloned().unwrap_or_else(|| format!("Motor {}", i + 1));
+                let mut motor = Motor::new(name, if *running { *setpoint } else { 0 }, *setpoint, *running);
+                motor.tagging = config.motor_tagging.get(i).cloned().unwrap_or_default();
+                motor.full_load_amps = config.motor_full_load_amps.get(i).copied().unwrap_or(DEFAULT_FULL_LOAD_AMPS);
+                motor.load_factor = config.motor_load_factors.get(i).copied().unwrap_or(DEFAULT_LOAD_FACTOR);
+                motor.current = motor.load_current(motor.speed as f32);
+                motor
+            })
+            .collect();
+
+        let mut data = SensorData {
+            channels: all_channels,
+            motors,
+            motor_ramp_rate: config.motor_ramp_rate,
+            overload_time: config.motor_overload_time,
+            supply: config.supply,
+            interlocks: config.interlocks.clone(),
+            // Points past the end of their list of states start off
+            digital_inputs: config.inputs.iter().enumerate()
+                .map(|(i, name)| DigitalPoint::new(name, config.input_states.get(i).copied().unwrap_or(false)))
+                .collect(),
+            digital_outputs: config.outputs.iter().enumerate()
+                .map(|(i, name)| DigitalPoint::new(name, config.output_states.get(i).copied().unwrap_or(false)))
+                .collect(),
+            control_loop: config.control_loop.clone(),
+            emergency_shutdown: false,
+            auto_trip: None,
+            trips_inhibited: false,
+            estop_cleared: false,
+            restart_motors: Vec::new(),
+            faults: Vec::new(),
+            overrides: Vec::new(),
+            frozen: false,
+            last_update: None,
+            connection: ConnectionStatus::Connecting,
+            link: LinkStats::new(config.link_thresholds),
+            scan: ScanStats::default(),
+            ambient: None,
+        };
+        // The loop takes over from the fan's configured setpoint
+        if let Some(mut control) = data.control_loop.take() {
+            control.pid.track(data.loop_process_value(&control), data.motors[control.motor].setpoint as f32);
+            data.control_loop = Some(control);
+        }
+        data
+    }
+
+    // Stops every motor and latches the shutdown until reset
+    pub fn trip_emergency_shutdown(&mut self) {
+        // A second trip mustn't forget what was running before the first
+        if !self.emergency_shutdown {
+            self.restart_motors = self.motors.iter().enumerate()
+                .filter(|(_, motor)| motor.running)
+                .map(|(i, motor)| (i, motor.setpoint))
+                .collect();
+        }
+        self.estop_cleared = false;
+        for motor in &mut self.motors {
+            motor.speed = 0;
+            motor.setpoint = 0;
+            motor.running = false;
+        }
+        for interlock in &mut self.interlocks {
+            interlock.bypass = None;
+        }
+        self.emergency_shutdown = true;
+    }
+
+    // First step of recovery: returns whether a shutdown was active. Every interlock must
+    // be satisfied and every reading back inside its alarm limits; after an automatic trip
+    // the channel that caused it is named, in the operator's units. Motors stay stopped
+    // until restarted.
+    pub fn reset_emergency_shutdown(&mut self, units: Units) -> Result<bool, String> {
+        if !self.emergency_shutdown {
+            return Ok(false);
+        }
+        if let Some(trip) = &self.auto_trip {
+            let reading = self.channels.iter().find(|channel| channel.name == trip.channel);
+            if let Some(reading) = reading.filter(|reading| reading.limits.trip_limit(reading.value).is_some()) {
+                return Err(format!("{} is still at {} (trip limit {})", reading.label(), units.format(reading.kind, reading.value), units.format(trip.kind, trip.limit)));
+            }
+        }
+        if let Some(interlock) = self.interlocks.iter().find(|interlock| !interlock.satisfied) {
+            return Err(format!("interlock {} is not satisfied ({})", interlock.name, interlock.condition));
+        }
+        let in_alarm: Vec<String> = self.channels.iter()
+            .filter(|reading| reading.limits.classify(reading.value) == AlarmLevel::Alarm)
+            .map(|reading| format!("{} at {}", reading.label(), units.format(reading.kind, reading.value)))
+            .collect();
+        if !in_alarm.is_empty() {
+            return Err(format!("outside alarm limits: {}", in_alarm.join(", ")));
+        }
+        self.auto_trip = None;
+        self.emergency_shutdown = false;
+        self.estop_cleared = true;
+        Ok(true)
+    }
+
+    // Second step of recovery: the motors to bring back, with their setpoints
+    pub fn take_restart_plan(&mut self) -> Result<Vec<(usize, u16)>, String> {
+        if self.emergency_shutdown {
+            Err("clear the emergency shutdown with 'reset' first".to_string())
+        } else if !self.estop_cleared {
+            Err("no cleared emergency shutdown to restart from".to_string())
+        } else {
+            self.estop_cleared = false;
+            Ok(std::mem::take(&mut self.restart_motors))
+        }
+    }
+
+    // Gives a channel of `kind`, or a motor with None, a new tag, description and asset
+    // ID; a tag or asset ID another channel or motor has is refused. Channels and motors
+    // are numbered from 1. Returns the name and what it had before.
+    pub fn set_tagging(&mut self, kind: Option<ChannelKind>, number: usize, tagging: Tagging) -> Result<(String, Tagging), String> {
+        Tagging::check_tag(&tagging.tag)?;
+        Tagging::check_description(&tagging.description)?;
+        Tagging::check_asset(&tagging.asset)?;
+        let index = number.wrapping_sub(1);
+        let name = match kind {
+            Some(kind) => match self.channel(kind, index) {
+                Some(channel) => channel.name.clone(),
+                None => return Err(format!("no {} channel {} (valid channels are 1-{})", kind, number, self.channels_of(kind).count())),
+            },
+            None => match self.motors.get(index) {
+                Some(motor) => motor.name.clone(),
+                None => return Err(format!("no motor {} (valid motors are 1-{})", number, self.motors.len())),
+            },
+        };
+        let other = self.channels.iter().map(|channel| (&channel.name, &channel.tagging.tag))
+            .chain(self.motors.iter().map(|motor| (&motor.name, &motor.tagging.tag)))
+            .find(|(other, tag)| **other != name && !tagging.tag.is_empty() && tag.eq_ignore_ascii_case(&tagging.tag));
+        if let Some((other, _)) = other {
+            return Err(format!("tag {} is already used by {}", tagging.tag, other));
+        }
+        let other = self.channels.iter().map(|channel| (&channel.name, &channel.tagging))
+            .chain(self.motors.iter().map(|motor| (&motor.name, &motor.tagging)))
+            .find(|(other, theirs)| **other != name && theirs.has_asset(&tagging.asset));
+        if let Some((other, _)) = other {
+            return Err(format!("asset ID {} is already used by {}", tagging.asset, other));
+        }
+        let target = match kind {
+            Some(kind) => self.channel_mut(kind, index).map(|channel| &mut channel.tagging),
+            None => self.motors.get_mut(index).map(|motor| &mut motor.tagging),
+        };
+        match target {
+            Some(target) => Ok((name, std::mem::replace(target, tagging))),
+            None => Err(format!("no channel {}", number)),
+        }
+    }
+
+    // Tag and name of motor N (from 1), for messages about it; "Motor N" if there is none
+    pub fn motor_label(&self, motor: usize) -> String {
+        match motor.checked_sub(1).and_then(|index| self.motors.get(index)) {
+            Some(found) => found.label(),
+            None => format!("Motor {}", motor),
+        }
+    }
+
+    // Motors are numbered from 1 as on the plant floor
+    pub fn set_motor_state(&mut self, motor: usize, running: bool) -> Result<(), String> {
+        if motor == 0 || motor > self.motors.len() {
+            Err(format!("no motor {} (valid motors are 1-{})", motor, self.motors.len()))
+        } else if running && self.emergency_shutdown {
+            Err("emergency shutdown is active".to_string())
+        } else if running && self.motors[motor - 1].overloaded {
+            Err(format!("tripped on overload - 'reset motor {}' once it has cooled", motor))
+        } else if let Some(interlock) = self.blocking_interlock(motor - 1).filter(|_| running) {
+            Err(format!("interlock {} is not satisfied ({})", interlock.name, interlock.condition))
+        } else {
+            // Starting a motor by hand takes the place of restarting them all
+            if running {
+                self.estop_cleared = false;
+                self.restart_motors.clear();
+            }
+            self.motors[motor - 1].running = running;
+            Ok(())
+        }
+    }
+
+    // Re-arms a motor's overload relay once it has cooled and whatever overloaded the motor
+    // is gone. The motor stays stopped until started.
+    pub fn reset_overload(&mut self, motor: usize) -> Result<(), String> {
+        if motor == 0 || motor > self.motors.len() {
+            return Err(format!("no motor {} (valid motors are 1-{})", motor, self.motors.len()));
+        }
+        let jammed = self.fault_on(FaultTarget::Motor(motor - 1)).is_some_and(|fault| fault.kind == FaultKind::Overload);
+        let state = &mut self.motors[motor - 1];
+        if !state.overloaded {
+            Err("it has not tripped on overload".to_string())
+        } else if jammed {
+            Err("the overload condition is still present".to_string())
+        } else if state.overload_heat > 0.0 {
+            Err(format!("the overload relay is still cooling ({:.0} s left)", state.overload_heat.ceil()))
+        } else {
+            state.overloaded = false;
+            Ok(())
+        }
+    }
+
+    // Returns whether the motor is running, i.e. whether the new setpoint applies immediately
+    pub fn set_motor_setpoint(&mut self, motor: usize, rpm: u16) -> Result<bool, String> {
+        if motor == 0 || motor > self.motors.len() {
+            Err(format!("no motor {} (valid motors are 1-{})", motor, self.motors.len()))
+        } else if rpm > MAX_MOTOR_SETPOINT {
+            Err(format!("setpoint must be between 0 and {} RPM", MAX_MOTOR_SETPOINT))
+        } else if self.control_loop.as_ref().is_some_and(|control| control.motor == motor - 1 && control.pid.mode == LoopMode::Auto) {
+            Err("the temperature loop sets its speed - 'loop manual' first".to_string())
+        } else {
+            self.motors[motor - 1].setpoint = rpm;
+            Ok(self.motors[motor - 1].running)
+        }
+    }
+
+    pub fn has_channel(&self, target: FaultTarget) -> bool {
+        match target {
+            FaultTarget::Temperature(index) | FaultTarget::Pressure(index) | FaultTarget::Motor(index) => index < self.channel_count(target),
+            FaultTarget::PlcLink => true,
+        }
+    }
+
+    // The channels of one kind, numbered from 0 in the order they were configured
+    pub fn channels_of(&self, kind: ChannelKind) -> impl Iterator<Item = &Channel> {
+        self.channels.iter().filter(move |channel| channel.kind == kind)
+    }
+
+    pub fn channel(&self, kind: ChannelKind, index: usize) -> Option<&Channel> {
+        self.channels_of(kind).nth(index)
+    }
+
+    pub fn channel_mut(&mut self, kind: ChannelKind, index: usize) -> Option<&mut Channel> {
+        self.channels.iter_mut().filter(|channel| channel.kind == kind).nth(index)
+    }
+
+    // Forces channel N (from 1) of `kind` to a calibrated value, or with None puts its
+    // measured value back. Returns the label and what it was forced to before.
+    pub fn force(&mut self, kind: ChannelKind, number: usize, forced: Option<f32>) -> Result<(String, Option<f32>), String> {
+        let count = self.channels_of(kind).count();
+        match self.channel_mut(kind, number.wrapping_sub(1)) {
+            Some(channel) => {
+                let before = channel.forced;
+                channel.force(forced);
+                Ok((channel.label(), before))
+            }
+            None => Err(format!("no {} channel {} (valid channels are 1-{})", kind, number, count)),
+        }
+    }
+
+    pub fn forced_count(&self) -> usize {
+        self.channels.iter().filter(|channel| channel.forced.is_some()).count()
+    }
+
+    fn channel_count(&self, target: FaultTarget) -> usize {
+        match target.channel_kind() {
+            Some(kind) => self.channels_of(kind).count(),
+            None if matches!(target, FaultTarget::Motor(_)) => self.motors.len(),
+            None => 1,
+        }
+    }
+
+    fn reading(&self, target: FaultTarget) -> f32 {
+        match (target, target.channel_kind()) {
+            (FaultTarget::Temperature(index) | FaultTarget::Pressure(index), Some(kind)) => self.channel(kind, index).map_or(f32::NAN, |channel| channel.raw),
+            (FaultTarget::Motor(index), _) => self.motors[index].speed as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set_reading(&mut self, target: FaultTarget, value: f32) {
+        match (target, target.channel_kind()) {
+            (FaultTarget::Temperature(index) | FaultTarget::Pressure(index), Some(kind)) => {
+                if let Some(channel) = self.channel_mut(kind, index) {
+                    channel.set_raw(value);
+                }
+            }
+            (FaultTarget::Motor(index), _) => self.motors[index].speed = value.round() as u16,
+            _ => {}
+        }
+    }
+
+    // True when every interlock holds, including when none are configured
+    pub fn interlocks_satisfied(&self) -> bool {
+        self.interlocks.iter().all(|interlock| interlock.satisfied)
+    }
+
+    // The first unsatisfied interlock protecting the motor (0-based), bypassed ones aside
+    pub fn blocking_interlock(&self, motor: usize) -> Option<&Interlock> {
+        self.interlocks.iter().find(|interlock| !interlock.permits() && interlock.protects(motor))
+    }
+
+    // Interlocks are numbered from 1 in the order configured. Returns the interlock's name.
+    pub fn bypass_interlock(&mut self, number: usize, bypass: Bypass) -> Result<String, String> {
+        let count = self.interlocks.len();
+        let interlock = number.checked_sub(1)
+            .and_then(|index| self.interlocks.get_mut(index))
+            .ok_or_else(|| format!("no interlock {} (valid interlocks are 1-{})", number, count))?;
+        if self.emergency_shutdown {
+            return Err("emergency shutdown is active".to_string());
+        }
+        interlock.bypass = Some(bypass);
+        Ok(interlock.name.clone())
+    }
+
+    // Ends an interlock's bypass. If its condition doesn't hold, the running motors it
+    // protects are stopped as though it had just dropped out; returns its name and those
+    // motors (0-based).
+    pub fn rearm_interlock(&mut self, number: usize) -> Result<(String, Vec<usize>), String> {
+        let count = self.interlocks.len();
+        let index = number.checked_sub(1).filter(|index| *index < count).ok_or_else(|| format!("no interlock {} (valid interlocks are 1-{})", number, count))?;
+        if self.interlocks[index].bypass.take().is_none() {
+            return Err(format!("interlock {} is not bypassed", self.interlocks[index].name));
+        }
+        let mut stopped = Vec::new();
+        if !self.interlocks[index].satisfied {
+            stopped = (0..self.motors.len())
+                .filter(|motor| self.motors[*motor].running && self.interlocks[index].protects(*motor))
+                .collect();
+            for motor in &stopped {
+                self.motors[*motor].running = false;
+            }
+            self.interlocks[index].stopped = stopped.clone();
+        }
+        Ok((self.interlocks[index].name.clone(), stopped))
+    }
+
+    // Re-arms the interlocks whose bypass has run out, as rearm_interlock
+    pub fn expire_bypasses(&mut self, now: SystemTime) -> Vec<(String, Vec<usize>)> {
+        let expired: Vec<usize> = self.interlocks.iter()
+            .enumerate()
+            .filter(|(_, interlock)| interlock.bypass.as_ref().is_some_and(|bypass| bypass.until <= now))
+            .map(|(index, _)| index + 1)
+            .collect();
+        expired.into_iter().filter_map(|number| self.rearm_interlock(number).ok()).collect()
+    }
+
+    // Called by the sensor thread on each sample, so the alarm debounce counts samples and
+    // an alarm's raise time is that of the sample that raised it
+    // The rate of change is only taken from readings that can be trusted: not an open
+    // circuit, a forced value or an instrument with a fault injected
+    pub fn update_alarm_states(&mut self, now: SystemTime) {
+        for i in 0..self.channels.len() {
+            let channel = &self.channels[i];
+            let index = self.channels[..i].iter().filter(|earlier| earlier.kind == channel.kind).count();
+            let faulted = self.fault_on(FaultTarget::channel(channel.kind, index)).is_some();
+            let channel = &mut self.channels[i];
+            channel.alarm.update(&channel.limits, &channel.filter, channel.value, now);
+            let reading = Some(channel.value).filter(|value| value.is_finite() && channel.forced.is_none() && !faulted);
+            channel.rate.update(&channel.rate_limit, &channel.filter, reading, now);
+        }
+    }
+
+    // Called by the sensor thread on each sample. Faulted channels and invalid readings are
+    // counted but left out.
+    pub fn update_statistics(&mut self, now: SystemTime) {
+        for i in 0..self.channels.len() {
+            let channel = &self.channels[i];
+            let index = self.channels[..i].iter().filter(|earlier| earlier.kind == channel.kind).count();
+            let faulted = self.fault_on(FaultTarget::channel(channel.kind, index)).is_some();
+            let channel = &mut self.channels[i];
+            if faulted {
+                channel.stats.ignore();
+            } else {
+                channel.stats.record(channel.value, now);
+            }
+        }
+    }
+
+    pub fn reset_statistics(&mut self, now: SystemTime) {
+        for channel in &mut self.channels {
+            channel.stats = RunningStats::new(now);
+        }
+    }
+
+    // Re-evaluates every interlock and returns those (by index) that have just dropped out
+    pub fn evaluate_interlocks(&mut self) -> Vec<usize> {
+        let states: Vec<bool> = self.interlocks.iter().map(|interlock| interlock.condition.evaluate(self)).collect();
+        let mut dropped = Vec::new();
+        for (i, (interlock, satisfied)) in self.interlocks.iter_mut().zip(states).enumerate() {
+            if interlock.satisfied && !satisfied {
+                dropped.push(i);
+            }
+            interlock.satisfied = satisfied;
+        }
+        dropped
+    }
+
+    // Called by the sensor thread on each sample. Stops the running motors protected by an
+    // interlock that has just dropped out, unless it is bypassed, and returns them (0-based)
+    // so a PLC can be told. They coast down at the ramp rate rather than stopping dead as
+    // in an estop.
+    pub fn update_interlocks(&mut self) -> Vec<usize> {
+        let mut stopped = Vec::new();
+        for index in self.evaluate_interlocks() {
+            let bypassed = self.interlocks[index].bypass.is_some();
+            let protected: Vec<usize> = (0..self.motors.len())
+                .filter(|motor| !bypassed && self.motors[*motor].running && self.interlocks[index].protects(*motor))
+                .collect();
+            // A motor stopped for one interlock isn't listed again for the next
+            for motor in &protected {
+                self.motors[*motor].running = false;
+            }
+            stopped.extend(&protected);
+            self.interlocks[index].stopped = protected;
+        }
+        stopped
+    }
+
+    pub fn digital_points(&self, kind: IoKind) -> &[DigitalPoint] {
+        match kind {
+            IoKind::Input => &self.digital_inputs,
+            IoKind::Output => &self.digital_outputs,
+        }
+    }
+
+    // Points are numbered from 1 as on the plant floor
+    pub fn digital_point_mut(&mut self, kind: IoKind, number: usize) -> Result<&mut DigitalPoint, String> {
+        let points = match kind {
+            IoKind::Input => &mut self.digital_inputs,
+            IoKind::Output => &mut self.digital_outputs,
+        };
+        let count = points.len();
+        number.checked_sub(1)
+            .and_then(|index| points.get_mut(index))
+            .ok_or_else(|| match count {
+                0 => format!("no digital {}s are configured", kind),
+                _ => format!("no {} {} (valid {}s are 1-{})", kind, number, kind, count),
+            })
+    }
+
+    // Inputs and outputs share one namespace, so interlocks and scenarios can name either
+    pub fn find_digital_point(&self, name: &str) -> Option<&DigitalPoint> {
+        self.digital_inputs.iter().chain(&self.digital_outputs).find(|point| point.name == name)
+    }
+
+    // What an operator toggling an output does; a forced output keeps its forced state
+    pub fn set_output(&mut self, output: usize, state: bool) -> Result<&DigitalPoint, String> {
+        let point = self.digital_point_mut(IoKind::Output, output)?;
+        if point.forced {
+            return Err(format!("{} is forced - 'fault clear output {}' first", point.name, output));
+        }
+        point.set(state);
+        Ok(point)
+    }
+
+    // How a scenario drives a point: the simulated state, which a force still overrides
+    pub fn set_digital_point(&mut self, name: &str, state: bool) -> Result<(), String> {
+        let point = self.digital_inputs.iter_mut()
+            .chain(&mut self.digital_outputs)
+            .find(|point| point.name == name)
+            .ok_or_else(|| format!("no digital input or output named '{}'", name))?;
+        point.set(state);
+        Ok(())
+    }
+
+    pub fn release_digital_points(&mut self) -> usize {
+        self.digital_inputs.iter_mut().chain(&mut self.digital_outputs).map(DigitalPoint::release).filter(|released| *released).count()
+    }
+
+    // What the loop controls on: the reading, so a faulty sensor misleads it as it would a real one
+    pub fn loop_process_value(&self, control: &ControlLoop) -> f32 {
+        self.channel(ChannelKind::Temperature, control.temperature).map_or(f32::NAN, |channel| channel.value)
+    }
+
+    pub fn fault_on(&self, target: FaultTarget) -> Option<&InjectedFault> {
+        self.faults.iter().find(|fault| fault.target == target)
+    }
+
+    // How old the readings are once nothing has arrived for STALE_INTERVALS update intervals.
+    // None while they're fresh, before the first update and while a snapshot is shown.
+    pub fn staleness(&self, now: SystemTime, interval: Duration) -> Option<Duration> {
+        let age = now.duration_since(self.last_update?).unwrap_or_default();
+        (!self.frozen && age > interval * STALE_INTERVALS).then_some(age)
+    }
+
+    pub fn plc_link_down(&self) -> bool {
+        self.fault_on(FaultTarget::PlcLink).is_some_and(|fault| fault.kind == FaultKind::OpenCircuit)
+    }
+
+    // Slow, jittery round trips and the odd lost poll, for practising on a degraded link
+    pub fn plc_link_noisy(&self) -> bool {
+        self.fault_on(FaultTarget::PlcLink).is_some_and(|fault| fault.kind == FaultKind::NoiseBurst)
+    }
+
+    // The process value behind a channel, which differs from its reading while a fault is injected
+    pub fn process_value(&self, target: FaultTarget) -> f32 {
+        match self.fault_on(target) {
+            Some(fault) => fault.true_value,
+            None => self.reading(target),
+        }
+    }
+
+    // Stores a new process value, passing it through any fault on the channel
+    pub fn update_channel(&mut self, target: FaultTarget, value: f32, rng: &mut Rng) {
+        let reading = match self.faults.iter_mut().find(|fault| fault.target == target) {
+            Some(fault) => {
+                fault.true_value = value;
+                fault.next_reading(rng)
+            }
+            None => value,
+        };
+        self.set_reading(target, reading);
+    }
+
+    // Replaces any fault already on the channel; the reading changes immediately. A fault
+    // lasting a while is cleared by expire_faults() once that is up.
+    pub fn inject_fault(&mut self, target: FaultTarget, kind: FaultKind, lasting: Option<Duration>) -> Result<(), String> {
+        let count = self.channel_count(target);
+        let index = match target {
+            FaultTarget::Temperature(_) | FaultTarget::Pressure(_) if kind == FaultKind::Overload => return Err("only motors can be overloaded".to_string()),
+            FaultTarget::Temperature(_) | FaultTarget::Pressure(_) if kind == FaultKind::BearingWear => return Err("only motors have bearings".to_string()),
+            FaultTarget::Temperature(_) | FaultTarget::Pressure(_) | FaultTarget::Motor(_) if kind == FaultKind::Hang => return Err("only the PLC link can hang".to_string()),
+            FaultTarget::Temperature(index) | FaultTarget::Pressure(index) | FaultTarget::Motor(index) => index,
+            FaultTarget::PlcLink if !matches!(kind, FaultKind::OpenCircuit | FaultKind::NoiseBurst | FaultKind::Hang) => return Err("the PLC link can only be dropped, made noisy or hung".to_string()),
+            FaultTarget::PlcLink => 0,
+        };
+        if index >= count {
+            return Err(format!("no {} (valid channels are 1-{})", target, count));
+        }
+
+        let true_value = self.process_value(target);
+        let reading = self.reading(target);
+        self.faults.retain(|fault| fault.target != target);
+        let mut fault = InjectedFault::new(target, kind, true_value, reading);
+        fault.until = lasting.map(|lasting| Instant::now() + lasting);
+        self.set_reading(target, fault.reading(0.0));
+        self.faults.push(fault);
+        Ok(())
+    }
+
+    // Puts the process value back on the channel; returns whether a fault was active
+    pub fn clear_fault(&mut self, target: FaultTarget) -> bool {
+        let index = match self.faults.iter().position(|fault| fault.target == target) {
+            Some(index) => index,
+            None => return false,
+        };
+        let fault = self.faults.remove(index);
+        self.set_reading(target, fault.true_value);
+        true
+    }
+
+    // Clears the faults whose time is up, returning what they were on
+    pub fn expire_faults(&mut self, now: Instant) -> Vec<FaultTarget> {
+        let expired: Vec<FaultTarget> = self.faults.iter().filter(|fault| fault.until.is_some_and(|until| now >= until)).map(|fault| fault.target).collect();
+        for target in &expired {
+            self.clear_fault(*target);
+        }
+        expired
+    }
+
+    pub fn clear_faults(&mut self) -> usize {
+        let targets: Vec<FaultTarget> = self.faults.iter().map(|fault| fault.target).collect();
+        for target in &targets {
+            self.clear_fault(*target);
+        }
+        targets.len()
+    }
+
+    // Moves the channel's process value to setpoint over the given time, replacing any override already on it
+    pub fn set_override(&mut self, target: FaultTarget, setpoint: f32, over: Duration) {
+        let value = self.process_value(target);
+        let rate = if over.is_zero() { f32::INFINITY } else { (setpoint - value).abs() / over.as_secs_f32() };
+        self.overrides.retain(|process_override| process_override.target != target);
+        self.overrides.push(ProcessOverride { target, value, setpoint, rate });
+    }
+
+    // Returns whether the channel was overridden; it drifts normally from where it was left
+    pub fn release_override(&mut self, target: FaultTarget) -> bool {
+        let count = self.overrides.len();
+        self.overrides.retain(|process_override| process_override.target != target);
+        self.overrides.len() < count
+    }
+
+    // Steps an overridden channel toward its setpoint and returns the new process value
+    pub fn advance_override(&mut self, target: FaultTarget, dt: Duration) -> Option<f32> {
+        let process_override = self.overrides.iter_mut().find(|process_override| process_override.target == target)?;
+        let step = process_override.rate * dt.as_secs_f32();
+        let remaining = process_override.setpoint - process_override.value;
+        process_override.value = if remaining.abs() <= step {
+            process_override.setpoint
+        } else {
+            process_override.value + step.copysign(remaining)
+        };
+        Some(process_override.value)
+    }
+
+    // Compact live view for remote monitoring; readings from an open circuit are null
+    pub fn to_telemetry(&self, system_id: &str) -> Value {
+        let values = |kind| Value::Array(self.channels_of(kind).map(|channel| channel.value.into()).collect());
+        let channels = self.channels.iter().map(|channel| Value::Object(vec![
+            ("name".to_string(), channel.name.as_str().into()),
+            ("tag".to_string(), channel.tagging.tag.as_str().into()),
+            ("kind".to_string(), channel.kind.to_string().into()),
+            ("unit".to_string(), channel.unit.trim().into()),
+            ("value".to_string(), channel.value.into()),
+            ("forced".to_string(), channel.forced.is_some().into()),
+        ])).collect();
+        let motors = self.motors.iter().map(|motor| Value::Object(vec![
+            ("name".to_string(), motor.name.as_str().into()),
+            ("tag".to_string(), motor.tagging.tag.as_str().into()),
+            ("speed".to_string(), motor.speed.into()),
+            ("setpoint".to_string(), motor.setpoint.into()),
+            ("running".to_string(), motor.running.into()),
+            ("current".to_string(), motor.current.into()),
+            ("power_kw".to_string(), self.supply.power(motor.current).into()),
+            ("overloaded".to_string(), motor.overloaded.into()),
+        ])).collect();
+        let interlocks = self.interlocks.iter().map(|interlock| Value::Object(vec![
+            ("name".to_string(), interlock.name.as_str().into()),
+            ("condition".to_string(), interlock.condition.to_string().into()),
+            ("satisfied".to_string(), interlock.satisfied.into()),
+            ("bypassed_until".to_string(), interlock.bypass.as_ref().map_or(Value::Null, |bypass| format_datetime(bypass.until).into())),
+        ])).collect();
+
+        Value::Object(vec![
+            ("system_id".to_string(), system_id.into()),
+            ("timestamp".to_string(), self.last_update.map_or(Value::Null, |time| format_datetime(time).into())),
+            ("online".to_string(), (self.connection == ConnectionStatus::Online).into()),
+            ("link".to_string(), self.link.to_json()),
+            // Kept alongside `channels` for dashboards written against the fixed layout
+            ("temperatures".to_string(), values(ChannelKind::Temperature)),
+            ("pressures".to_string(), values(ChannelKind::Pressure)),
+            ("channels".to_string(), Value::Array(channels)),
+            ("motors".to_string(), Value::Array(motors)),
+            ("safety_interlocks".to_string(), self.interlocks_satisfied().into()),
+            ("interlocks".to_string(), Value::Array(interlocks)),
+            ("inputs".to_string(), Value::Array(self.digital_inputs.iter().map(DigitalPoint::to_json).collect())),
+            ("outputs".to_string(), Value::Array(self.digital_outputs.iter().map(DigitalPoint::to_json).collect())),
+            ("control_loop".to_string(), self.control_loop.as_ref().map_or(Value::Null, |control| control.to_json(self.loop_process_value(control)))),
+            ("ambient".to_string(), self.ambient.as_ref().map_or(Value::Null, Ambient::to_json)),
+            ("emergency_shutdown".to_string(), self.emergency_shutdown.into()),
+            ("auto_trip".to_string(), self.auto_trip.as_ref().map_or(Value::Null, |trip| trip.to_string().into())),
+        ])
+    }
+
+    pub fn to_snapshot(&self, system_id: &str, diagnostic_log: &[String]) -> Value {
+        let channels = self.channels.iter().map(|channel| Value::Object(vec![
+            ("name".to_string(), channel.name.as_str().into()),
+            ("tag".to_string(), channel.tagging.tag.as_str().into()),
+            ("description".to_string(), channel.tagging.description.as_str().into()),
+            ("asset".to_string(), channel.tagging.asset.as_str().into()),
+            ("kind".to_string(), channel.kind.to_string().into()),
+            ("value".to_string(), channel.value.into()),
+            ("limits".to_string(), channel.limits.to_json()),
+        ])).collect();
+        let motors = self.motors.iter().map(|motor| Value::Object(vec![
+            ("name".to_string(), motor.name.as_str().into()),
+            ("tag".to_string(), motor.tagging.tag.as_str().into()),
+            ("description".to_string(), motor.tagging.description.as_str().into()),
+            ("asset".to_string(), motor.tagging.asset.as_str().into()),
+            ("speed".to_string(), motor.speed.into()),
+            ("setpoint".to_string(), motor.setpoint.into()),
+            ("running".to_string(), motor.running.into()),
+            ("current".to_string(), motor.current.into()),
+            ("full_load_amps".to_string(), motor.full_load_amps.into()),
+            ("load_factor".to_string(), motor.load_factor.into()),
+            ("overloaded".to_string(), motor.overloaded.into()),
+        ])).collect();
+
+        Value::Object(vec![
+            ("format".to_string(), "forlenza-snapshot".into()),
+            ("version".to_string(), 2u16.into()),
+            ("system_id".to_string(), system_id.into()),
+            ("captured_at".to_string(), format_datetime(SystemTime::now()).into()),
+            ("channels".to_string(), Value::Array(channels)),
+            ("motors".to_string(), Value::Array(motors)),
+            ("motor_ramp_rate".to_string(), self.motor_ramp_rate.into()),
+            ("overload_time_s".to_string(), self.overload_time.as_secs_f64().into()),
+            ("safety_interlocks".to_string(), self.interlocks_satisfied().into()),
+            ("inputs".to_string(), Value::Array(self.digital_inputs.iter().map(DigitalPoint::to_json).collect())),
+            ("outputs".to_string(), Value::Array(self.digital_outputs.iter().map(DigitalPoint::to_json).collect())),
+            ("emergency_shutdown".to_string(), self.emergency_shutdown.into()),
+            ("diagnostic_log".to_string(), diagnostic_log.into()),
+        ])
+    }
+
+    // Rebuilds sensor data and the diagnostic log from a snapshot, rejecting
+    // anything inconsistent rather than loading half of it. Interlocks aren't
+    // saved, nor are the control loop and alarm filters; they come from forlenza.toml.
+    pub fn from_snapshot(snapshot: &Value) -> Result<(SensorData, Vec<String>), String> {
+        if snapshot.get("format").and_then(Value::as_str) != Some("forlenza-snapshot") {
+            return Err("not a Forlenza snapshot file".to_string());
+        }
+
+        let (mut channels, motors) = if snapshot.get("version").and_then(Value::as_f64) == Some(1.0) {
+            snapshot_v1_channels(snapshot)?
+        } else {
+            snapshot_channels(snapshot)?
+        };
+        for channel in &channels {
+            channel.limits.validate().map_err(|e| format!("{}: {}", channel.name, e))?;
+        }
+        // Kept grouped by kind, as the rest of the system expects
+        channels.sort_by_key(|channel| ChannelKind::ALL.iter().position(|kind| *kind == channel.kind));
+
+        let data = SensorData {
+            channels,
+            motors,
+            motor_ramp_rate: snapshot_u16(snapshot.field("motor_ramp_rate")?).ok_or("field 'motor_ramp_rate' is out of range")?,
+            // Snapshots from before motor current was modelled keep the defaults
+            overload_time: match snapshot.get("overload_time_s") {
+                Some(seconds) => seconds.as_f64().filter(|seconds| *seconds > 0.0 && seconds.is_finite()).map(Duration::from_secs_f64).ok_or("field 'overload_time_s' is out of range")?,
+                None => DEFAULT_OVERLOAD_TIME,
+            },
+            // From forlenza.toml, like the interlocks
+            supply: Supply::default(),
+            interlocks: Vec::new(),
+            digital_inputs: snapshot_points(snapshot, "inputs")?,
+            digital_outputs: snapshot_points(snapshot, "outputs")?,
+            control_loop: None,
+            emergency_shutdown: snapshot.bool_field("emergency_shutdown")?,
+            auto_trip: None,
+            trips_inhibited: false,
+            estop_cleared: false,
+            restart_motors: Vec::new(),
+            faults: Vec::new(),
+            overrides: Vec::new(),
+            frozen: true,
+            last_update: Some(SystemTime::now()),
+            connection: ConnectionStatus::Connecting,
+            link: LinkStats::new(LinkThresholds::default()),
+            scan: ScanStats::default(),
+            ambient: None,
+        };
+        let diagnostic_log = snapshot.array_field("diagnostic_log", |item| item.as_str().map(str::to_string))?;
+        Ok((data, diagnostic_log))
+    }
+}
+
+fn snapshot_u16(value: &Value) -> Option<u16> {
+    value.as_f64().filter(|value| (0.0..=u16::MAX as f64).contains(value)).map(|value| value as u16)
+}
+
+// Not in snapshots from before digital I/O. Forces aren't restored.
+fn snapshot_points(snapshot: &Value, key: &str) -> Result<Vec<DigitalPoint>, String> {
+    match snapshot.get(key) {
+        Some(_) => snapshot.array_field(key, |item| Some(DigitalPoint::new(item.str_field("name").ok()?, item.bool_field("state").ok()?))),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Open-circuit readings are saved as null
+fn snapshot_reading(value: &Value) -> Option<f32> {
+    match value {
+        Value::Null => Some(f32::NAN),
+        value => value.as_f64().map(|value| value as f32),
+    }
+}
+
+// Version 2 onwards: one object per channel and motor
+fn snapshot_channels(snapshot: &Value) -> Result<(Vec<Channel>, Vec<Motor>), String> {
+    let channels = snapshot.array_field("channels", |item| {
+        let kind = match item.str_field("kind").ok()? {
+            "temperature" => ChannelKind::Temperature,
+            "pressure" => ChannelKind::Pressure,
+            _ => return None,
+        };
+        let mut channel = Channel::new(
+            item.str_field("name").ok()?.to_string(),
+            kind,
+            snapshot_reading(item.field("value").ok()?)?,
+            Limits::from_json(item.field("limits").ok()?)?,
+            AlarmFilter::default(),
+        );
+        channel.tagging = snapshot_tagging(item)?;
+        Some(channel)
+    })?;
+    let motors = snapshot.array_field("motors", |item| {
+        let mut motor = Motor::new(
+            item.str_field("name").ok()?.to_string(),
+            snapshot_u16(item.field("speed").ok()?)?,
+            snapshot_u16(item.field("setpoint").ok()?)?,
+            item.bool_field("running").ok()?,
+        );
+        // Not in snapshots from before motor current was modelled
+        let number = |key: &str, default: f32| match item.get(key) {
+            Some(value) => value.as_f64().filter(|value| *value >= 0.0 && value.is_finite()).map(|value| value as f32),
+            None => Some(default),
+        };
+        motor.full_load_amps = number("full_load_amps", DEFAULT_FULL_LOAD_AMPS)?;
+        motor.load_factor = number("load_factor", DEFAULT_LOAD_FACTOR)?;
+        motor.current = number("current", motor.load_current(motor.speed as f32))?;
+        motor.overloaded = match item.get("overloaded") {
+            Some(overloaded) => overloaded.as_bool()?,
+            None => false,
+        };
+        motor.tagging = snapshot_tagging(item)?;
+        Some(motor)
+    })?;
+    Ok((channels, motors))
+}
+
+// Not in snapshots from before tags, or before asset IDs
+fn snapshot_tagging(item: &Value) -> Option<Tagging> {
+    let text = |key: &str| match item.get(key) {
+        Some(value) => value.as_str().map(str::to_string),
+        None => Some(String::new()),
+    };
+    Some(Tagging { tag: text("tag")?, description: text("description")?, asset: text("asset")? })
+}
+
+// Version 1 snapshots kept each kind of reading in its own array, with numbered names
+fn snapshot_v1_channels(snapshot: &Value) -> Result<(Vec<Channel>, Vec<Motor>), String> {
+    let mut channels = Vec::new();
+    for (kind, values_key, limits_key) in [
+        (ChannelKind::Temperature, "temperature_sensors", "temperature_limits"),
+        (ChannelKind::Pressure, "pressure_gauges", "pressure_limits"),
+    ] {
+        let values = snapshot.array_field(values_key, snapshot_reading)?;
+        let limits = snapshot.array_field(limits_key, Limits::from_json)?;
+        if limits.len() != values.len() {
+            return Err("alarm limits don't match the number of sensors".to_string());
+        }
+        channels.extend(values.into_iter().zip(limits).enumerate().map(|(i, (value, limits))| {
+            Channel::new(kind.default_name(i), kind, value, limits, AlarmFilter::default())
+        }));
+    }
+
+    let speeds = snapshot.array_field("motor_speeds", snapshot_u16)?;
+    let states = snapshot.array_field("motor_states", Value::as_bool)?;
+    let setpoints = snapshot.array_field("motor_setpoints", snapshot_u16)?;
+    if states.len() != speeds.len() || setpoints.len() != speeds.len() {
+        return Err("motor arrays have different lengths".to_string());
+    }
+    let motors = speeds.into_iter().zip(states).zip(setpoints).enumerate()
+        .map(|(i, ((speed, running), setpoint))| {
+            let mut motor = Motor::new(format!("Motor {}", i + 1), speed, setpoint, running);
+            motor.current = motor.load_current(speed as f32);
+            motor
+        })
+        .collect();
+    Ok((channels, motors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{self, HistoryRecord};
+    use crate::simulation::{self, ProcessModel, SimulationModel};
+
+    #[test]
+    fn emergency_shutdown_stops_every_motor_until_reset() {
+        let mut data = SensorData::from_config(&Config::default());
+        data.trip_emergency_shutdown();
+        assert!(data.emergency_shutdown);
+        assert!(data.motors.iter().all(|motor| !motor.running && motor.speed == 0 && motor.setpoint == 0));
+        assert_eq!(data.set_motor_state(1, true), Err("emergency shutdown is active".to_string()));
+        assert!(data.take_restart_plan().is_err());
+
+        // A second shutdown keeps what was running before the first
+        data.trip_emergency_shutdown();
+        assert_eq!(data.reset_emergency_shutdown(Units::default()), Ok(true));
+        assert!(!data.emergency_shutdown);
+        assert!(data.motors.iter().all(|motor| !motor.running), "motors stay stopped until restarted");
+        assert_eq!(data.take_restart_plan(), Ok(vec![(0, 1750), (1, 1800), (3, 2200)]));
+        assert!(data.take_restart_plan().is_err(), "the plan is used once");
+        assert_eq!(data.reset_emergency_shutdown(Units::default()), Ok(false));
+    }
+
+    #[test]
+    fn reset_is_refused_while_a_reading_is_in_alarm() {
+        let mut data = SensorData::from_config(&Config::default());
+        data.trip_emergency_shutdown();
+        data.channel_mut(ChannelKind::Pressure, 0).unwrap().value = 104.5;
+        let refusal = data.reset_emergency_shutdown(Units::default()).unwrap_err();
+        assert!(refusal.starts_with("outside alarm limits"), "{}", refusal);
+        assert!(data.emergency_shutdown);
+        data.channel_mut(ChannelKind::Pressure, 0).unwrap().value = 101.3;
+        assert_eq!(data.reset_emergency_shutdown(Units::default()), Ok(true));
+    }
+
+    #[test]
+    fn readings_go_stale_after_three_missed_updates() {
+        let mut data = SensorData::from_config(&Config::default());
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let interval = Duration::from_millis(500);
+        assert_eq!(data.staleness(start, interval), None, "nothing to be stale before the first update");
+
+        data.last_update = Some(start);
+        assert_eq!(data.staleness(start + Duration::from_millis(1500), interval), None, "three intervals is still fresh");
+        assert_eq!(data.staleness(start + Duration::from_millis(1501), interval), Some(Duration::from_millis(1501)));
+        // A clock stepped backwards is not staleness
+        assert_eq!(data.staleness(start - Duration::from_secs(60), interval), None);
+        // Slower updates allow longer gaps
+        assert_eq!(data.staleness(start + Duration::from_secs(2), Duration::from_secs(1)), None);
+
+        // A fresh update clears it
+        data.last_update = Some(start + Duration::from_secs(10));
+        assert_eq!(data.staleness(start + Duration::from_millis(10_200), interval), None);
+
+        // A loaded snapshot is never stale
+        data.frozen = true;
+        assert_eq!(data.staleness(start + Duration::from_secs(3600), interval), None);
+    }
+
+    #[test]
+    fn thirty_two_channels_are_laid_out_and_stepped_by_kind() {
+        let config = Config {
+            temperatures: vec![24.0; 20],
+            pressures: vec![100.0; 12],
+            motor_setpoints: vec![1500; 8],
+            motor_running: vec![true; 8],
+            ..Config::default()
+        };
+        let mut data = SensorData::from_config(&config);
+        assert_eq!(data.channels.len(), 32);
+        assert_eq!(data.channels_of(ChannelKind::Temperature).count(), 20);
+        assert_eq!(data.channels_of(ChannelKind::Pressure).last().unwrap().name, "Pressure Gauge 12");
+        assert_eq!(data.channel(ChannelKind::Temperature, 19).unwrap().name, "Temperature Sensor 20");
+        assert_eq!(data.motors.len(), 8);
+
+        let mut rng = Rng::new(1);
+        let model = SimulationModel::Process(ProcessModel::default());
+        let started = Instant::now();
+        for _ in 0..1000 {
+            simulation::simulate_step(&mut data, &mut rng, Duration::from_secs(1), &model);
+        }
+        // Well under a millisecond a step even unoptimized
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?} for 1000 steps", started.elapsed());
+        assert!(data.channels.iter().all(|channel| channel.value.is_finite()));
+
+        let header = history::csv_header(Some(&HistoryRecord::capture(&data, SystemTime::now())), Units::default());
+        assert_eq!(header.len(), 1 + 32 + 2 * 8 + 1 + 2);
+        assert_eq!(header[20], "temperature_20_c");
+        assert_eq!(header[32], "pressure_12_kpa");
+        assert_eq!(header[33], "motor_1_rpm");
+    }
+}
@@ -0,0 +1,400 @@
+// Raw serial ports for RS-485 links to the PLC. Opened 8 data bits with the given
+// parity and no flow control; reads give up after the configured timeout so a pulled
+// cable shows up as an error instead of a hung thread.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+impl Parity {
+    pub fn parse(text: &str) -> Result<Parity, String> {
+        match text {
+            "none" => Ok(Parity::None),
+            "even" => Ok(Parity::Even),
+            "odd" => Ok(Parity::Odd),
+            _ => Err(format!("unknown parity '{}' (use none, even or odd)", text)),
+        }
+    }
+}
+
+impl fmt::Display for Parity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parity::None => write!(f, "8N2"),
+            Parity::Even => write!(f, "8E1"),
+            Parity::Odd => write!(f, "8O1"),
+        }
+    }
+}
+
+pub const BAUD_RATES: [u32; 8] = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+pub struct SerialPort {
+    #[cfg(windows)]
+    handle: windows::Handle,
+    #[cfg(target_os = "linux")]
+    file: std::fs::File,
+}
+
+impl SerialPort {
+    // Modbus RTU uses two stop bits when there is no parity bit, one otherwise
+    pub fn open(path: &str, baud_rate: u32, parity: Parity, timeout: Duration) -> io::Result<SerialPort> {
+        if !BAUD_RATES.contains(&baud_rate) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported baud rate {}", baud_rate)));
+        }
+        Self::open_port(path, baud_rate, parity, timeout)
+    }
+
+    #[cfg(windows)]
+    fn open_port(path: &str, baud_rate: u32, parity: Parity, timeout: Duration) -> io::Result<SerialPort> {
+        windows::open(path, baud_rate, parity, timeout).map(|handle| SerialPort { handle })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn open_port(path: &str, baud_rate: u32, parity: Parity, timeout: Duration) -> io::Result<SerialPort> {
+        linux::open(path, baud_rate, parity, timeout).map(|file| SerialPort { file })
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    fn open_port(_path: &str, _baud_rate: u32, _parity: Parity, _timeout: Duration) -> io::Result<SerialPort> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "serial ports are not supported on this platform"))
+    }
+
+    // Drops anything left over from an earlier, abandoned exchange
+    pub fn discard_input(&mut self) -> io::Result<()> {
+        #[cfg(windows)]
+        return windows::purge(&self.handle);
+        #[cfg(target_os = "linux")]
+        return linux::flush_input(&self.file);
+        #[cfg(not(any(windows, target_os = "linux")))]
+        Ok(())
+    }
+}
+
+impl Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(windows)]
+        let read = windows::read(&self.handle, buf)?;
+        #[cfg(target_os = "linux")]
+        let read = self.file.read(buf)?;
+        #[cfg(not(any(windows, target_os = "linux")))]
+        let read = { let _ = buf; 0 };
+        // Both platforms return zero bytes when the timeout expires
+        if read == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no response from device"));
+        }
+        Ok(read)
+    }
+}
+
+impl Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(windows)]
+        return windows::write(&self.handle, buf);
+        #[cfg(target_os = "linux")]
+        return self.file.write(buf);
+        #[cfg(not(any(windows, target_os = "linux")))]
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Serial ports present on this machine, for the 'ports' command
+#[cfg(windows)]
+pub fn available_ports() -> Vec<String> {
+    windows::available_ports()
+}
+
+#[cfg(target_os = "linux")]
+pub fn available_ports() -> Vec<String> {
+    linux::available_ports()
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn available_ports() -> Vec<String> {
+    Vec::new()
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::Parity;
+
+    use std::ffi::c_void;
+    use std::io;
+    use std::ptr;
+    use std::time::Duration;
+
+    const GENERIC_READ: u32 = 0x8000_0000;
+    const GENERIC_WRITE: u32 = 0x4000_0000;
+    const OPEN_EXISTING: u32 = 3;
+    const PURGE_RXCLEAR: u32 = 0x0008;
+    const INVALID_HANDLE_VALUE: isize = -1;
+    // fBinary | fParity in the DCB flag bits; every flow control bit stays clear
+    const DCB_BINARY: u32 = 0x0001;
+    const DCB_PARITY: u32 = 0x0002;
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    struct DCB {
+        DCBlength: u32,
+        BaudRate: u32,
+        flags: u32,
+        wReserved: u16,
+        XonLim: u16,
+        XoffLim: u16,
+        ByteSize: u8,
+        Parity: u8,
+        StopBits: u8,
+        XonChar: i8,
+        XoffChar: i8,
+        ErrorChar: i8,
+        EofChar: i8,
+        EvtChar: i8,
+        wReserved1: u16,
+    }
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    struct COMMTIMEOUTS {
+        ReadIntervalTimeout: u32,
+        ReadTotalTimeoutMultiplier: u32,
+        ReadTotalTimeoutConstant: u32,
+        WriteTotalTimeoutMultiplier: u32,
+        WriteTotalTimeoutConstant: u32,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateFileW(
+            lpFileName: *const u16,
+            dwDesiredAccess: u32,
+            dwShareMode: u32,
+            lpSecurityAttributes: *mut c_void,
+            dwCreationDisposition: u32,
+            dwFlagsAndAttributes: u32,
+            hTemplateFile: *mut c_void,
+        ) -> *mut c_void;
+        fn CloseHandle(hObject: *mut c_void) -> i32;
+        fn GetCommState(hFile: *mut c_void, lpDCB: *mut DCB) -> i32;
+        fn SetCommState(hFile: *mut c_void, lpDCB: *const DCB) -> i32;
+        fn SetCommTimeouts(hFile: *mut c_void, lpCommTimeouts: *const COMMTIMEOUTS) -> i32;
+        fn PurgeComm(hFile: *mut c_void, dwFlags: u32) -> i32;
+        fn ReadFile(hFile: *mut c_void, lpBuffer: *mut u8, nNumberOfBytesToRead: u32, lpNumberOfBytesRead: *mut u32, lpOverlapped: *mut c_void) -> i32;
+        fn WriteFile(hFile: *mut c_void, lpBuffer: *const u8, nNumberOfBytesToWrite: u32, lpNumberOfBytesWritten: *mut u32, lpOverlapped: *mut c_void) -> i32;
+        fn QueryDosDeviceW(lpDeviceName: *const u16, lpTargetPath: *mut u16, ucchMax: u32) -> u32;
+    }
+
+    pub struct Handle(*mut c_void);
+
+    // The handle is only ever used by the thread that owns the port
+    unsafe impl Send for Handle {}
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn check(result: i32) -> io::Result<()> {
+        if result != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    pub fn open(path: &str, baud_rate: u32, parity: Parity, timeout: Duration) -> io::Result<Handle> {
+        // COM10 and up are only reachable through the device namespace
+        let path = if path.starts_with(r"\\.\") { path.to_string() } else { format!(r"\\.\{}", path) };
+        let name = wide(&path);
+        unsafe {
+            let raw = CreateFileW(name.as_ptr(), GENERIC_READ | GENERIC_WRITE, 0, ptr::null_mut(), OPEN_EXISTING, 0, ptr::null_mut());
+            if raw as isize == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            let handle = Handle(raw);
+
+            let mut dcb: DCB = std::mem::zeroed();
+            dcb.DCBlength = std::mem::size_of::<DCB>() as u32;
+            check(GetCommState(handle.0, &mut dcb))?;
+            dcb.BaudRate = baud_rate;
+            dcb.ByteSize = 8;
+            let (flags, parity, stop_bits) = match parity {
+                // ONESTOPBIT is 0 and TWOSTOPBITS is 2
+                Parity::None => (DCB_BINARY, 0, 2),
+                Parity::Odd => (DCB_BINARY | DCB_PARITY, 1, 0),
+                Parity::Even => (DCB_BINARY | DCB_PARITY, 2, 0),
+            };
+            dcb.flags = flags;
+            dcb.Parity = parity;
+            dcb.StopBits = stop_bits;
+            check(SetCommState(handle.0, &dcb))?;
+
+            let timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+            let timeouts = COMMTIMEOUTS {
+                ReadIntervalTimeout: 0,
+                ReadTotalTimeoutMultiplier: 0,
+                ReadTotalTimeoutConstant: timeout_ms,
+                WriteTotalTimeoutMultiplier: 0,
+                WriteTotalTimeoutConstant: timeout_ms,
+            };
+            check(SetCommTimeouts(handle.0, &timeouts))?;
+            Ok(handle)
+        }
+    }
+
+    pub fn purge(handle: &Handle) -> io::Result<()> {
+        unsafe { check(PurgeComm(handle.0, PURGE_RXCLEAR)) }
+    }
+
+    pub fn read(handle: &Handle, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        unsafe { check(ReadFile(handle.0, buf.as_mut_ptr(), buf.len() as u32, &mut read, ptr::null_mut()))? };
+        Ok(read as usize)
+    }
+
+    pub fn write(handle: &Handle, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        unsafe { check(WriteFile(handle.0, buf.as_ptr(), buf.len() as u32, &mut written, ptr::null_mut()))? };
+        if written == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "serial write timed out"));
+        }
+        Ok(written as usize)
+    }
+
+    pub fn available_ports() -> Vec<String> {
+        let mut target = [0u16; 256];
+        (1..=256)
+            .map(|number| format!("COM{}", number))
+            .filter(|name| unsafe { QueryDosDeviceW(wide(name).as_ptr(), target.as_mut_ptr(), target.len() as u32) != 0 })
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Parity;
+
+    use std::fs::{self, File, OpenOptions};
+    use std::io;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::time::Duration;
+
+    // glibc's struct termios
+    #[repr(C)]
+    struct Termios {
+        c_iflag: u32,
+        c_oflag: u32,
+        c_cflag: u32,
+        c_lflag: u32,
+        c_line: u8,
+        c_cc: [u8; 32],
+        c_ispeed: u32,
+        c_ospeed: u32,
+    }
+
+    const O_NOCTTY: i32 = 0o400;
+    const TCSANOW: i32 = 0;
+    const TCIFLUSH: i32 = 0;
+    const CSTOPB: u32 = 0o100;
+    const PARENB: u32 = 0o400;
+    const PARODD: u32 = 0o1000;
+    const CREAD: u32 = 0o200;
+    const CLOCAL: u32 = 0o4000;
+    const CRTSCTS: u32 = 0o20000000000;
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+
+    extern "C" {
+        fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+        fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32;
+        fn tcflush(fd: i32, queue_selector: i32) -> i32;
+        fn cfmakeraw(termios: *mut Termios);
+        fn cfsetspeed(termios: *mut Termios, speed: u32) -> i32;
+    }
+
+    fn check(result: i32) -> io::Result<()> {
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    fn speed(baud_rate: u32) -> u32 {
+        match baud_rate {
+            1200 => 0o11,
+            2400 => 0o13,
+            4800 => 0o14,
+            9600 => 0o15,
+            19200 => 0o16,
+            38400 => 0o17,
+            57600 => 0o10001,
+            _ => 0o10002,
+        }
+    }
+
+    pub fn open(path: &str, baud_rate: u32, parity: Parity, timeout: Duration) -> io::Result<File> {
+        let file = OpenOptions::new().read(true).write(true).custom_flags(O_NOCTTY).open(path)?;
+        let fd = file.as_raw_fd();
+        unsafe {
+            let mut termios: Termios = std::mem::zeroed();
+            check(tcgetattr(fd, &mut termios))?;
+            cfmakeraw(&mut termios);
+            check(cfsetspeed(&mut termios, speed(baud_rate)))?;
+            termios.c_cflag &= !(CSTOPB | PARENB | PARODD | CRTSCTS);
+            termios.c_cflag |= CREAD | CLOCAL;
+            termios.c_cflag |= match parity {
+                Parity::None => CSTOPB,
+                Parity::Even => PARENB,
+                Parity::Odd => PARENB | PARODD,
+            };
+            // Non-blocking with a timer: a read returns what has arrived, or nothing once
+            // the timeout (in tenths of a second, at most 25.5 s) passes with no data
+            termios.c_cc[VMIN] = 0;
+            termios.c_cc[VTIME] = ((timeout.as_millis() + 99) / 100).clamp(1, 255) as u8;
+            check(tcsetattr(fd, TCSANOW, &termios))?;
+        }
+        Ok(file)
+    }
+
+    pub fn flush_input(file: &File) -> io::Result<()> {
+        unsafe { check(tcflush(file.as_raw_fd(), TCIFLUSH)) }
+    }
+
+    // USB adapters appear on demand; the fixed ttyS ports only count when backed by hardware
+    pub fn available_ports() -> Vec<String> {
+        let mut ports: Vec<String> = fs::read_dir("/dev")
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| {
+                        name.starts_with("ttyUSB")
+                            || name.starts_with("ttyACM")
+                            || (name.starts_with("ttyS") && Path::new("/sys/class/tty").join(name).join("device/driver").exists())
+                    })
+                    .map(|name| format!("/dev/{}", name))
+                    .collect()
+            })
+            .unwrap_or_default();
+        ports.sort();
+        ports
+    }
+}
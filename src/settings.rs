@@ -0,0 +1,215 @@
+// Operator preferences changed at the console and remembered across restarts: display
+// units, language, color theme and reading view, the log level, the update interval,
+// edited alarm limits, channel tags and trend pens. They are kept in a small JSON file
+// and take the place of the forlenza.toml values until cleared with 'settings reset'.
+use crate::alarms::Limits;
+use crate::i18n::Language;
+use crate::json::Value;
+use crate::pens::PenSet;
+use crate::platform::{LogLevel, Theme};
+use crate::sensors::{ChannelKind, SensorData, Tagging};
+use crate::simulation;
+use crate::units::{Unit, Units};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub units: Option<Units>,
+    pub theme: Option<Theme>,
+    pub language: Option<Language>,
+    // Status readings drawn as bar gauges rather than a list
+    pub gauges: Option<bool>,
+    pub log_level: Option<LogLevel>,
+    pub update_interval_ms: Option<u64>,
+    // Edited alarm limits by channel name, in SI
+    pub limits: Vec<(String, Limits)>,
+    // Edited tags and descriptions by channel or motor name
+    pub tagging: Vec<(String, Tagging)>,
+    pub pens: PenSet,
+}
+
+impl Settings {
+    // A missing or unreadable file, or any entry that no longer makes sense, just means
+    // the forlenza.toml value stays in effect.
+    pub fn load(path: &Path) -> Settings {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| Value::parse(&text).ok())
+            .filter(|state| state.get("format").and_then(Value::as_str) == Some("forlenza-settings"))
+            .map_or_else(Settings::default, |state| Settings::from_json(&state))
+    }
+
+    // As load, but something that isn't a settings document at all is an error
+    pub fn parse(state: &Value) -> Result<Settings, String> {
+        match state.get("format").and_then(Value::as_str) {
+            Some("forlenza-settings") => Ok(Settings::from_json(state)),
+            _ => Err("not a Forlenza settings document".to_string()),
+        }
+    }
+
+    fn from_json(state: &Value) -> Settings {
+        let unit = |key: &str| state.get("units")?.get(key)?.as_str().and_then(|name| Unit::parse(name).ok());
+        let units = match (unit("temperature"), unit("pressure")) {
+            (Some(temperature), Some(pressure)) if temperature.kind() == ChannelKind::Temperature && pressure.kind() == ChannelKind::Pressure => Some(Units { temperature, pressure }),
+            _ => None,
+        };
+        let theme = state.get("theme").and_then(Value::as_str).and_then(|name| Theme::parse(name).ok());
+        let language = state.get("language").and_then(Value::as_str).and_then(|code| Language::parse(code).ok());
+        let gauges = match state.get("view").and_then(Value::as_str) {
+            Some("gauges") => Some(true),
+            Some("list") => Some(false),
+            _ => None,
+        };
+        let log_level = state.get("log_level").and_then(Value::as_str).and_then(|name| LogLevel::parse(name).ok());
+        let update_interval_ms = state.get("update_interval_ms")
+            .and_then(Value::as_f64)
+            .filter(|interval_ms| interval_ms.fract() == 0.0 && *interval_ms >= 0.0)
+            .map(|interval_ms| interval_ms as u64)
+            .filter(|interval_ms| simulation::check_update_interval(*interval_ms).is_ok());
+        let limits = state.get("limits")
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|item| Some((item.str_field("channel").ok()?.to_string(), Limits::from_json(item.field("limits").ok()?)?)))
+            .filter(|(_, limits)| limits.validate().is_ok())
+            .collect();
+        let tagging = state.get("tagging")
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|item| {
+                // No asset ID in files from before them
+                let asset = item.get("asset").map_or(Some(""), Value::as_str)?.to_string();
+                let tagging = Tagging { tag: item.str_field("tag").ok()?.to_string(), description: item.str_field("description").ok()?.to_string(), asset };
+                Some((item.str_field("name").ok()?.to_string(), tagging))
+            })
+            .filter(|(_, tagging)| Tagging::check_tag(&tagging.tag).is_ok() && Tagging::check_description(&tagging.description).is_ok() && Tagging::check_asset(&tagging.asset).is_ok())
+            .collect();
+        let pens = state.get("trend_pens").map_or_else(PenSet::default, PenSet::from_json);
+        Settings { units, theme, language, gauges, log_level, update_interval_ms, limits, tagging, pens }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut members = vec![
+            ("format".to_string(), "forlenza-settings".into()),
+            ("version".to_string(), 1u16.into()),
+        ];
+        if let Some(units) = self.units {
+            members.push(("units".to_string(), Value::Object(vec![
+                ("temperature".to_string(), units.temperature.column_suffix().into()),
+                ("pressure".to_string(), units.pressure.column_suffix().into()),
+            ])));
+        }
+        if let Some(theme) = self.theme {
+            members.push(("theme".to_string(), theme.name().into()));
+        }
+        if let Some(language) = self.language {
+            members.push(("language".to_string(), language.code().into()));
+        }
+        if let Some(gauges) = self.gauges {
+            members.push(("view".to_string(), if gauges { "gauges" } else { "list" }.into()));
+        }
+        if let Some(level) = self.log_level {
+            members.push(("log_level".to_string(), level.name().into()));
+        }
+        if let Some(interval_ms) = self.update_interval_ms {
+            members.push(("update_interval_ms".to_string(), interval_ms.into()));
+        }
+        let limits = self.limits.iter().map(|(channel, limits)| Value::Object(vec![
+            ("channel".to_string(), channel.as_str().into()),
+            ("limits".to_string(), limits.to_json()),
+        ])).collect();
+        members.push(("limits".to_string(), Value::Array(limits)));
+        let tagging = self.tagging.iter().map(|(name, tagging)| Value::Object(vec![
+            ("name".to_string(), name.as_str().into()),
+            ("tag".to_string(), tagging.tag.as_str().into()),
+            ("description".to_string(), tagging.description.as_str().into()),
+            ("asset".to_string(), tagging.asset.as_str().into()),
+        ])).collect();
+        members.push(("tagging".to_string(), Value::Array(tagging)));
+        if self.pens != PenSet::default() {
+            members.push(("trend_pens".to_string(), self.pens.to_json()));
+        }
+        Value::Object(members)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Settings::default()
+    }
+
+    pub fn set_limits(&mut self, channel: &str, limits: Limits) {
+        match self.limits.iter_mut().find(|(name, _)| name == channel) {
+            Some((_, stored)) => *stored = limits,
+            None => self.limits.push((channel.to_string(), limits)),
+        }
+    }
+
+    // Puts the stored limits on the channels of the same name; returns how many matched.
+    // Channels of devices after the first are stored as "DEVICE/channel", with that prefix.
+    pub fn apply_limits(&self, data: &mut SensorData, prefix: &str) -> usize {
+        let mut applied = 0;
+        for (name, limits) in &self.limits {
+            let name = match name.strip_prefix(prefix) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Some(channel) = data.channels.iter_mut().find(|channel| channel.name == name) {
+                channel.limits = *limits;
+                applied += 1;
+            }
+        }
+        applied
+    }
+
+    pub fn set_tagging(&mut self, name: &str, tagging: Tagging) {
+        match self.tagging.iter_mut().find(|(stored, _)| stored == name) {
+            Some((_, stored)) => *stored = tagging,
+            None => self.tagging.push((name.to_string(), tagging)),
+        }
+    }
+
+    // Puts the stored tags, descriptions and asset IDs on the channels and motors of the
+    // same name, with the same prefix as the limits; a tag or asset ID another channel or
+    // motor now has is skipped. Returns how many matched.
+    pub fn apply_tagging(&self, data: &mut SensorData, prefix: &str) -> usize {
+        let mut applied = 0;
+        for (name, tagging) in &self.tagging {
+            let name = match name.strip_prefix(prefix) {
+                Some(name) => name,
+                None => continue,
+            };
+            let taken = data.channels.iter().map(|channel| (&channel.name, &channel.tagging))
+                .chain(data.motors.iter().map(|motor| (&motor.name, &motor.tagging)))
+                .any(|(other, theirs)| other != name
+                    && ((!theirs.tag.is_empty() && theirs.tag.eq_ignore_ascii_case(&tagging.tag)) || theirs.has_asset(&tagging.asset)));
+            if taken {
+                continue;
+            }
+            let target = match data.channels.iter_mut().find(|channel| channel.name == name) {
+                Some(channel) => Some(&mut channel.tagging),
+                None => data.motors.iter_mut().find(|motor| motor.name == name).map(|motor| &mut motor.tagging),
+            };
+            if let Some(target) = target {
+                *target = tagging.clone();
+                applied += 1;
+            }
+        }
+        applied
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.to_json().save(path)
+    }
+
+    // Forgets everything; a file that is already gone is fine
+    pub fn clear(&mut self, path: &Path) -> io::Result<()> {
+        *self = Settings::default();
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
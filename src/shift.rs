@@ -0,0 +1,224 @@
+// End-of-shift summary: for a shift just closed, each channel's minimum, maximum and
+// mean, each motor's running time, the alarms and warnings raised, emergency shutdowns,
+// the operator actions taken and the notes written. Readings, alarms and notes are read
+// back from the historian's daily files and actions from the audit trail, so a summary
+// covers the whole shift even if the console was restarted during it. Generated on a
+// background thread.
+use crate::audit::{self, AuditAction};
+use crate::clock::format_datetime;
+use crate::csv;
+use crate::historian;
+use crate::history;
+use crate::notes::Note;
+use crate::sensors::ChannelKind;
+use crate::units::{Unit, Units};
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// A longer gap between samples, e.g. while the console was not running, doesn't count
+// towards a motor's running time
+const MAX_SAMPLE_GAP: Duration = Duration::from_secs(60);
+
+// What the console knows when the shift closes, for the thread that reads the files
+#[derive(Clone, Debug)]
+pub struct ShiftRequest {
+    pub system_id: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    // "schedule", or who closed the shift
+    pub closed_by: String,
+    // Each channel's kind, number within its kind (from 0) and tag and name
+    pub channels: Vec<(ChannelKind, usize, String)>,
+    pub motors: Vec<String>,
+    pub historian: PathBuf,
+    pub audit: PathBuf,
+}
+
+// One channel's valid readings over the shift
+#[derive(Clone, Copy, Debug)]
+struct Totals {
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: usize,
+    // Each reading times how long it held, as the historian's change-of-value recording
+    // leaves out rows where nothing moved
+    weighted: f64,
+    seconds: f64,
+    last: Option<(SystemTime, f32)>,
+}
+
+impl Default for Totals {
+    fn default() -> Self {
+        Totals { min: f32::INFINITY, max: f32::NEG_INFINITY, sum: 0.0, count: 0, weighted: 0.0, seconds: 0.0, last: None }
+    }
+}
+
+impl Totals {
+    // None for an invalid reading, which ends the one before
+    fn add(&mut self, time: SystemTime, value: Option<f32>) {
+        if let Some((previous, held)) = self.last.take() {
+            let seconds = time.duration_since(previous).unwrap_or_default().min(MAX_SAMPLE_GAP).as_secs_f64();
+            self.weighted += held as f64 * seconds;
+            self.seconds += seconds;
+        }
+        if let Some(value) = value {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.sum += value as f64;
+            self.count += 1;
+            self.last = Some((time, value));
+        }
+    }
+
+    // Weighed by time; a single reading is its own mean
+    fn mean(&self) -> Option<f32> {
+        match self.count {
+            0 => None,
+            _ if self.seconds > 0.0 => Some((self.weighted / self.seconds) as f32),
+            count => Some((self.sum / count as f64) as f32),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChannelSummary {
+    pub label: String,
+    pub kind: ChannelKind,
+    // SI; None without a valid sample in the shift
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub mean: Option<f32>,
+    pub samples: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShiftSummary {
+    pub system_id: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub closed_by: String,
+    pub channels: Vec<ChannelSummary>,
+    // Tag and name, with the time the motor ran in the shift
+    pub motors: Vec<(String, Duration)>,
+    pub alarms: usize,
+    pub warnings: usize,
+    pub emergency_shutdowns: usize,
+    // Operator actions by kind, most frequent first
+    pub actions: Vec<(AuditAction, usize)>,
+    // Oldest first, corrections included
+    pub notes: Vec<Note>,
+    pub samples: usize,
+}
+
+impl ShiftSummary {
+    pub fn generate(request: &ShiftRequest) -> Result<ShiftSummary, String> {
+        let (start, end) = (request.start, request.end);
+        let columns: Vec<String> = request.channels.iter().map(|(kind, index, _)| history::csv_column(*kind, *index, Unit::si(*kind))).collect();
+        let mut totals = vec![Totals::default(); columns.len()];
+        let mut runtimes = vec![Duration::ZERO; request.motors.len()];
+        // When each motor was last seen running
+        let mut running: Vec<Option<SystemTime>> = vec![None; request.motors.len()];
+        let mut samples = 0;
+        historian::scan_samples(&request.historian, start, end, |header, time, fields| {
+            samples += 1;
+            let field = |name: &str| header.iter().position(|column| column == name).map(|column| fields[column]);
+            for (column, totals) in columns.iter().zip(&mut totals) {
+                totals.add(time, field(column).and_then(|text| text.parse::<f32>().ok()).filter(|value| value.is_finite()));
+            }
+            for (motor, (runtime, since)) in runtimes.iter_mut().zip(&mut running).enumerate() {
+                if let Some(previous) = since.take() {
+                    *runtime += time.duration_since(previous).unwrap_or_default().min(MAX_SAMPLE_GAP);
+                }
+                if field(&format!("motor_{}_running", motor + 1)) == Some("true") {
+                    *since = Some(time);
+                }
+            }
+        })
+        .map_err(|e| format!("the historian files in {} could not be read: {}", request.historian.display(), e))?;
+
+        let alarms = historian::query_alarms(&request.historian, start, end)
+            .map_err(|e| format!("the historian files in {} could not be read: {}", request.historian.display(), e))?;
+        let notes = historian::query_notes(&request.historian, start, end)
+            .map_err(|e| format!("the historian files in {} could not be read: {}", request.historian.display(), e))?;
+        let events = audit::query(&request.audit, start, end).map_err(|e| format!("{} could not be read: {}", request.audit.display(), e))?;
+        let mut actions: Vec<(AuditAction, usize)> = AuditAction::ALL.into_iter()
+            .map(|action| (action, events.iter().filter(|event| event.action == action).count()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        actions.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        Ok(ShiftSummary {
+            system_id: request.system_id.clone(),
+            start,
+            end,
+            closed_by: request.closed_by.clone(),
+            channels: request.channels.iter().zip(totals).map(|((kind, _, label), totals)| ChannelSummary {
+                label: label.clone(),
+                kind: *kind,
+                min: (totals.count > 0).then_some(totals.min),
+                max: (totals.count > 0).then_some(totals.max),
+                mean: totals.mean(),
+                samples: totals.count,
+            }).collect(),
+            motors: request.motors.iter().cloned().zip(runtimes).collect(),
+            alarms: alarms.iter().filter(|(_, message)| message.starts_with("ALARM")).count(),
+            warnings: alarms.iter().filter(|(_, message)| message.starts_with("WARNING")).count(),
+            emergency_shutdowns: events.iter().filter(|event| event.action == AuditAction::EmergencyShutdown).count(),
+            actions,
+            notes,
+            samples,
+        })
+    }
+
+    // shift_<stamp>.csv in `directory`, in the display units; one place more than the
+    // display, like the history export
+    pub fn write_csv(&self, directory: &Path, stamp: &str, units: Units) -> Result<PathBuf, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("{} could not be created: {}", directory.display(), e))?;
+        let path = directory.join(format!("shift_{}.csv", stamp));
+        self.write_rows(&path, units).map_err(|e| format!("{} could not be written: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    fn write_rows(&self, path: &Path, units: Units) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let row = |section: &str, item: &str, field: &str, value: String, unit: &str| -> Vec<String> {
+            vec![section.to_string(), item.to_string(), field.to_string(), value, unit.to_string()]
+        };
+        let mut rows = vec![
+            row("section", "item", "field", "value".to_string(), "unit"),
+            row("shift", &self.system_id, "start", format_datetime(self.start), ""),
+            row("shift", &self.system_id, "end", format_datetime(self.end), ""),
+            row("shift", &self.system_id, "closed by", self.closed_by.clone(), ""),
+            row("shift", &self.system_id, "samples", self.samples.to_string(), ""),
+        ];
+        for channel in &self.channels {
+            let unit = units.get(channel.kind);
+            let number = |value: Option<f32>| value.map_or(String::new(), |value| format!("{:.*}", unit.decimals() + 1, unit.from_si(value)));
+            let symbol = unit.symbol().trim();
+            rows.push(row("channel", &channel.label, "min", number(channel.min), symbol));
+            rows.push(row("channel", &channel.label, "max", number(channel.max), symbol));
+            rows.push(row("channel", &channel.label, "mean", number(channel.mean), symbol));
+            rows.push(row("channel", &channel.label, "samples", channel.samples.to_string(), ""));
+        }
+        for (motor, runtime) in &self.motors {
+            rows.push(row("motor", motor, "runtime", format!("{:.2}", runtime.as_secs_f64() / 3600.0), "h"));
+        }
+        rows.push(row("alarms", "alarm", "raised", self.alarms.to_string(), ""));
+        rows.push(row("alarms", "warning", "raised", self.warnings.to_string(), ""));
+        rows.push(row("estops", "emergency shutdown", "count", self.emergency_shutdowns.to_string(), ""));
+        for (action, count) in &self.actions {
+            rows.push(row("actions", action.name(), "count", count.to_string(), ""));
+        }
+        for note in &self.notes {
+            rows.push(row("notes", &format_datetime(note.time), &note.user, note.summary(), ""));
+        }
+        for row in rows {
+            csv::write_row(&mut writer, &row)?;
+        }
+        writer.flush()
+    }
+}
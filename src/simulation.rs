@@ -0,0 +1,784 @@
+// Background simulation of the legacy sensors and the thread that polls the configured
+// data source. The step function is pure so the same model can be driven by the thread
+// or stepped directly. By default the plant is a simple physical process - motors heat
+// the sensors near them and a pump sets the line pressure - so that stopping a motor
+// shows up in the readings; the older random walk is kept for comparison.
+use crate::alarms::TripMonitor;
+use crate::ambient::{Ambient, Weather};
+use crate::config::Config;
+use crate::energy::EnergyMeter;
+use crate::faults::{FaultKind, FaultTarget};
+use crate::history::{HistoryRecord, SensorHistory};
+use crate::maintenance::Maintenance;
+use crate::pid::LoopMode;
+use crate::rand::Rng;
+use crate::scan::Cycle;
+use crate::scenario::ScenarioPlayer;
+use crate::sensors::{ChannelKind, SensorData, MOTOR_SPEED_TOLERANCE, RATED_MOTOR_SPEED};
+use crate::source::{ConnectionStatus, DataSource, SourceCommand, SourceError};
+use crate::vibration::{Analysis, Vibration, VibrationModel};
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Draw of a jammed motor, as a multiple of its full-load amps
+const JAMMED_CURRENT: f32 = 1.5;
+// The control loop's sensor settles at LOOP_HEAT_TEMPERATURE with its fan stopped, and
+// LOOP_COOLING lower for every RPM of fan speed, with a first-order lag. It is less noisy
+// than the other sensors so the loop's response can be seen.
+const LOOP_HEAT_TEMPERATURE: f32 = 30.0;
+const LOOP_COOLING: f32 = 0.005;
+const LOOP_TIME_CONSTANT: f32 = 30.0;
+const LOOP_DRIFT: f32 = 0.05;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimulationModel {
+    Process(ProcessModel),
+    // Each reading wanders at random within its range, unrelated to anything else
+    RandomWalk,
+}
+
+impl SimulationModel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SimulationModel::Process(_) => "process",
+            SimulationModel::RandomWalk => "random",
+        }
+    }
+}
+
+// Each temperature settles, with a first-order lag, at the air temperature plus heat from
+// its motor in proportion to the motor's current. Each pressure settles at the static
+// pressure plus the pump's head, which goes with the square of its speed as on a
+// centrifugal pump curve, less a line loss for each gauge further from the pump, and
+// moves with the barometer. Noise is added to every reading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessModel {
+    // °C, the air temperature the weather's day/night swing is around
+    pub ambient: f32,
+    pub weather: Weather,
+    // °C above ambient with the motor at full-load amps
+    pub heat_rise: f32,
+    // Heating motor by temperature sensor, None for none; sensors past the end of the
+    // list are heated by the motor of the same number, if there is one
+    pub heat_motors: Vec<Option<usize>>,
+    pub temperature_time_constant: Duration,
+    // °C peak to peak
+    pub temperature_noise: f32,
+    // None for the fourth motor, or the last if there are fewer
+    pub pump_motor: Option<usize>,
+    // kPa with the pump stopped
+    pub static_pressure: f32,
+    // kPa added at rated speed
+    pub pump_head: f32,
+    // kPa lost between one gauge and the next
+    pub line_loss: f32,
+    pub pressure_time_constant: Duration,
+    // kPa peak to peak
+    pub pressure_noise: f32,
+}
+
+impl Default for ProcessModel {
+    // Close to the random walk's readings with the default motors running, and low
+    // enough with the pump stopped to bring up a pressure warning
+    fn default() -> Self {
+        ProcessModel {
+            ambient: 21.5,
+            weather: Weather::default(),
+            heat_rise: 4.0,
+            heat_motors: Vec::new(),
+            temperature_time_constant: Duration::from_secs(60),
+            temperature_noise: 0.1,
+            pump_motor: None,
+            static_pressure: 96.5,
+            pump_head: 3.5,
+            line_loss: 0.5,
+            pressure_time_constant: Duration::from_secs(5),
+            pressure_noise: 0.3,
+        }
+    }
+}
+
+impl ProcessModel {
+    fn heat_motor(&self, sensor: usize, motors: usize) -> Option<usize> {
+        self.heat_motors.get(sensor).copied().unwrap_or(Some(sensor)).filter(|motor| *motor < motors)
+    }
+
+    fn pump(&self, motors: usize) -> Option<usize> {
+        Some(self.pump_motor.unwrap_or(3).min(motors.checked_sub(1)?))
+    }
+
+    // Where a reading settles with the motors and the weather as they are now
+    fn settled(&self, data: &SensorData, kind: ChannelKind, index: usize) -> f32 {
+        match kind {
+            ChannelKind::Temperature => {
+                let load = self.heat_motor(index, data.motors.len())
+                    .map_or(0.0, |motor| data.motors[motor].current / data.motors[motor].full_load_amps);
+                self.ambient + self.ambient_bias(data, kind) + self.heat_rise * load
+            }
+            ChannelKind::Pressure => {
+                let speed = self.pump(data.motors.len()).map_or(0.0, |pump| data.process_value(FaultTarget::Motor(pump)));
+                let head = self.pump_head * (speed / RATED_MOTOR_SPEED as f32).powi(2);
+                self.static_pressure + self.ambient_bias(data, kind) + head - self.line_loss * index as f32
+            }
+        }
+    }
+
+    // How far the weather has moved a reading from where it settles in average weather
+    fn ambient_bias(&self, data: &SensorData, kind: ChannelKind) -> f32 {
+        match (&data.ambient, kind) {
+            (Some(ambient), ChannelKind::Temperature) => ambient.temperature - self.ambient,
+            (Some(ambient), ChannelKind::Pressure) => ambient.pressure - self.weather.barometer,
+            (None, _) => 0.0,
+        }
+    }
+
+    fn time_constant(&self, kind: ChannelKind) -> Duration {
+        match kind {
+            ChannelKind::Temperature => self.temperature_time_constant,
+            ChannelKind::Pressure => self.pressure_time_constant,
+        }
+    }
+
+    fn noise(&self, kind: ChannelKind) -> f32 {
+        match kind {
+            ChannelKind::Temperature => self.temperature_noise,
+            ChannelKind::Pressure => self.pressure_noise,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    pub seed: u64,
+    pub update_interval_ms: u64,
+    pub trip_samples: u32,
+    // Automatic trips are held off for this long after the thread starts, so the jump in
+    // readings when the data source is switched doesn't shut the plant down
+    pub trip_grace: Duration,
+    pub model: SimulationModel,
+}
+
+impl SimulationConfig {
+    // A --seed on the command line wins over the config file
+    pub fn new(config: &Config, seed: Option<u64>) -> Self {
+        // Without an explicit seed every run gets a fresh trace
+        let seed = seed.or(config.seed).unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        });
+        SimulationConfig { seed, update_interval_ms: config.update_interval_ms, trip_samples: config.trip_samples, trip_grace: Duration::ZERO, model: config.simulation_model.clone() }
+    }
+}
+
+// Advances the model by dt using nothing but its arguments, so it can be stepped at any
+// rate; the caller stamps last_update. In the random walk, legacy sensors drift slightly
+// between reads and readings stay within their physical ranges.
+pub fn simulate_step(data: &mut SensorData, rng: &mut Rng, dt: Duration, model: &SimulationModel) {
+    if let SimulationModel::Process(process) = model {
+        data.ambient.get_or_insert_with(|| Ambient::new(process.ambient, &process.weather)).advance(process.ambient, &process.weather, dt);
+    }
+    // Injected faults only affect readings, so the model works on the process values behind them.
+    // Scripted overrides take the place of the model on their channels.
+    for kind in ChannelKind::ALL {
+        let (drift, range) = match kind {
+            ChannelKind::Temperature => (0.4, 20.0..=30.0),
+            ChannelKind::Pressure => (1.0, 95.0..=105.0),
+        };
+        for i in 0..data.channels_of(kind).count() {
+            let channel = FaultTarget::channel(kind, i);
+            let mut value = data.process_value(channel);
+            // The loop's sensor is cooled by its fan, whichever the model, and warmed by the weather in the process model
+            let value = if let Some(control) = data.control_loop.as_ref().filter(|control| kind == ChannelKind::Temperature && control.temperature == i) {
+                let weather = match model {
+                    SimulationModel::Process(process) => process.ambient_bias(data, kind),
+                    SimulationModel::RandomWalk => 0.0,
+                };
+                let settled = LOOP_HEAT_TEMPERATURE + weather - LOOP_COOLING * data.process_value(FaultTarget::Motor(control.motor));
+                value += (settled - value) * (dt.as_secs_f32() / LOOP_TIME_CONSTANT).min(1.0);
+                (value + (rng.random_f32() - 0.5) * LOOP_DRIFT).clamp(*range.start(), *range.end())
+            } else {
+                match model {
+                    SimulationModel::Process(process) => {
+                        let settled = process.settled(data, kind, i);
+                        value += (settled - value) * (dt.as_secs_f32() / process.time_constant(kind).as_secs_f32()).min(1.0);
+                        value + (rng.random_f32() - 0.5) * process.noise(kind)
+                    }
+                    SimulationModel::RandomWalk => (value + (rng.random_f32() - 0.5) * drift).clamp(*range.start(), *range.end()),
+                }
+            };
+            let value = data.advance_override(channel, dt).unwrap_or(value);
+            data.update_channel(channel, value, rng);
+        }
+    }
+    step_control_loop(data, dt);
+
+    // Motors move toward their setpoint no faster than the ramp rate, and coast down when stopped
+    let max_step = ((data.motor_ramp_rate as f32 * dt.as_secs_f32()) as u16).max(1);
+    for i in 0..data.motors.len() {
+        let channel = FaultTarget::Motor(i);
+        let target = if data.motors[i].running { data.motors[i].setpoint } else { 0 };
+        let speed = data.process_value(channel) as u16;
+        let speed = if speed.abs_diff(target) <= MOTOR_SPEED_TOLERANCE {
+            if target == 0 {
+                0
+            } else {
+                (target + rng.random_u16_range(0, 2 * MOTOR_SPEED_TOLERANCE + 1)).saturating_sub(MOTOR_SPEED_TOLERANCE)
+            }
+        } else if speed < target {
+            (speed + max_step).min(target)
+        } else {
+            speed.saturating_sub(max_step).max(target)
+        };
+        data.update_channel(channel, speed as f32, rng);
+
+        // A tripped overload relay stops the motor on the spot rather than letting it coast down
+        let jammed = data.fault_on(channel).is_some_and(|fault| fault.kind == FaultKind::Overload);
+        let overload_time = data.overload_time;
+        let motor = &mut data.motors[i];
+        motor.current = if jammed && motor.running { motor.full_load_amps * JAMMED_CURRENT } else { motor.load_current(speed as f32) };
+        if motor.heat_overload(dt, overload_time) {
+            motor.running = false;
+            motor.current = 0.0;
+            data.update_channel(channel, 0.0, rng);
+        }
+    }
+}
+
+// In auto the loop sets its fan's speed. In manual, or while the fan is stopped, it
+// follows the fan's setpoint so that it picks up from there.
+fn step_control_loop(data: &mut SensorData, dt: Duration) {
+    let mut control = match data.control_loop.take() {
+        Some(control) => control,
+        None => return,
+    };
+    let pv = data.loop_process_value(&control);
+    let fan = &mut data.motors[control.motor];
+    if control.pid.mode == LoopMode::Auto && fan.running {
+        fan.setpoint = control.pid.update(pv, dt).round() as u16;
+    } else {
+        control.pid.track(pv, fan.setpoint as f32);
+    }
+    data.control_loop = Some(control);
+}
+
+// Faster updates would mostly contend for the sensor lock
+pub const MIN_UPDATE_INTERVAL_MS: u64 = 100;
+pub const MAX_UPDATE_INTERVAL_MS: u64 = 5000;
+
+pub fn check_update_interval(interval_ms: u64) -> Result<(), String> {
+    if (MIN_UPDATE_INTERVAL_MS..=MAX_UPDATE_INTERVAL_MS).contains(&interval_ms) {
+        Ok(())
+    } else {
+        Err(format!("update interval must be between {} and {} ms", MIN_UPDATE_INTERVAL_MS, MAX_UPDATE_INTERVAL_MS))
+    }
+}
+
+// Owns the sensor thread. Dropping the handle stops and joins the thread.
+pub struct SimulationHandle {
+    stop: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
+    interval_ms: Arc<AtomicU64>,
+    // When the thread last went round its loop, in milliseconds since the Unix epoch
+    heartbeat: Arc<AtomicU64>,
+    commands: Sender<SourceCommand>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SimulationHandle {
+    // False once the thread has exited, whether stopped or crashed
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // A paused thread keeps running but leaves the sensor data untouched
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn update_interval_ms(&self) -> u64 {
+        self.interval_ms.load(Ordering::Relaxed)
+    }
+
+    // Takes effect from the next tick without restarting the thread
+    pub fn set_update_interval_ms(&self, interval_ms: u64) -> Result<(), String> {
+        check_update_interval(interval_ms)?;
+        self.interval_ms.store(interval_ms, Ordering::Relaxed);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+        Ok(())
+    }
+
+    // Queued for the data source; sent before the next poll
+    pub fn send_command(&self, command: SourceCommand) {
+        let _ = self.commands.send(command);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+
+    // Time since the thread last went round its loop; it does at least once per update
+    // interval while paused or backing off, so only a hung thread gets far behind
+    pub fn heartbeat_age(&self) -> Duration {
+        Duration::from_millis(unix_millis().saturating_sub(self.heartbeat.load(Ordering::Relaxed)))
+    }
+
+    // Signals the thread and waits for it to finish its current tick
+    pub fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+
+    // Signals the thread to stop but only waits for one that has already finished; a hung
+    // thread is left to exit by itself if it ever comes back
+    pub fn abandon(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            if thread.is_finished() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for SimulationHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// The built-in plant model, used when no PLC is configured
+pub struct SimulatedSource {
+    seed: u64,
+    model: SimulationModel,
+    rng: Rng,
+    // Kept apart from the plant's generator so a seed gives the same readings as before
+    link_rng: Rng,
+    link_noisy: bool,
+    vibration: VibrationModel,
+}
+
+impl SimulatedSource {
+    pub fn new(seed: u64, model: SimulationModel) -> Self {
+        SimulatedSource { seed, model, rng: Rng::new(seed), link_rng: Rng::new(seed ^ LINK_SEED), link_noisy: false, vibration: VibrationModel::new(seed ^ VIBRATION_SEED) }
+    }
+}
+
+const LINK_SEED: u64 = 0x4C49_4E4B;
+const VIBRATION_SEED: u64 = 0x5649_4252;
+// Share of polls a noisy link loses
+const NOISY_LINK_LOSS: f32 = 0.2;
+
+impl DataSource for SimulatedSource {
+    fn describe(&self) -> String {
+        format!("Simulated PLC (seed {}, {} model)", self.seed, self.model.name())
+    }
+
+    fn poll(&mut self, sensor_data: &Mutex<SensorData>, dt: Duration) -> Result<(), SourceError> {
+        let mut data = sensor_data.lock().map_err(|_| SourceError::Protocol("sensor data is unavailable".to_string()))?;
+        // The fault is used up so a restarted thread polls normally. The lock is released
+        // first: a real hung driver leaves the console responsive, just without new readings.
+        if data.fault_on(FaultTarget::PlcLink).is_some_and(|fault| fault.kind == FaultKind::Hang) {
+            data.clear_fault(FaultTarget::PlcLink);
+            drop(data);
+            loop {
+                thread::sleep(Duration::from_secs(3600));
+            }
+        }
+        self.link_noisy = data.plc_link_noisy();
+        if self.link_noisy && self.link_rng.random_f32() < NOISY_LINK_LOSS {
+            return Err(SourceError::Connection("no response from PLC (poll lost on a noisy link)".to_string()));
+        }
+        simulate_step(&mut data, &mut self.rng, dt, &self.model);
+        Ok(())
+    }
+
+    // A few milliseconds of jitter on a healthy link, up to a second on a noisy one
+    fn simulated_latency(&mut self) -> Option<Duration> {
+        let millis = if self.link_noisy { self.link_rng.random_u16_range(300, 1200) } else { self.link_rng.random_u16_range(4, 16) };
+        Some(Duration::from_millis(millis as u64))
+    }
+
+    fn vibration(&mut self, motors: &[(f32, bool)], dt: Duration) -> Option<Vec<Vec<f32>>> {
+        Some(self.vibration.generate(motors, dt))
+    }
+
+    // The simulated link comes back as soon as the fault is cleared
+    fn retry_delay(&self, _failures: u32) -> Duration {
+        Duration::ZERO
+    }
+}
+
+// What the sensor thread keeps up to date for the console. Each is locked on its own.
+pub struct SharedState {
+    pub sensor_data: Arc<Mutex<SensorData>>,
+    pub history: Arc<Mutex<SensorHistory>>,
+    pub maintenance: Arc<Mutex<Maintenance>>,
+    pub energy: Arc<Mutex<EnergyMeter>>,
+    pub vibration: Arc<Mutex<Vibration>>,
+    pub scenario: Arc<Mutex<ScenarioPlayer>>,
+}
+
+pub fn start_sensor_thread(
+    mut source: Box<dyn DataSource>,
+    shared: SharedState,
+    config: SimulationConfig,
+    // Failed writes are reported here for the diagnostic log
+    reports: Sender<Result<String, String>>,
+    // Telemetry consumers get each update, or miss it if they haven't taken the last one
+    listeners: Vec<SyncSender<SensorData>>,
+) -> SimulationHandle {
+    let SharedState { sensor_data, history, maintenance, energy, vibration, scenario } = shared;
+    let stop = Arc::new(AtomicBool::new(false));
+    let paused = Arc::new(AtomicBool::new(false));
+    let interval_ms = Arc::new(AtomicU64::new(config.update_interval_ms));
+    let thread_stop = Arc::clone(&stop);
+    let thread_paused = Arc::clone(&paused);
+    let thread_interval_ms = Arc::clone(&interval_ms);
+    let heartbeat = Arc::new(AtomicU64::new(unix_millis()));
+    let thread_heartbeat = Arc::clone(&heartbeat);
+    let (commands, pending_commands) = mpsc::channel();
+
+    let crash_reports = reports.clone();
+    let thread = thread::spawn(move || {
+        // A panic ends the thread, leaving the watchdog to notice; its message goes to the diagnostic log
+        let result = panic::catch_unwind(AssertUnwindSafe(move || {
+            let mut last_tick = Instant::now();
+            // When the last poll of an unbroken run began; a pause, a loaded snapshot or a
+            // backoff starts a new run rather than counting as one long cycle
+            let mut last_poll: Option<Instant> = None;
+            let mut failures = 0;
+            let mut retry_at = last_tick;
+            let mut trips = TripMonitor::new(config.trip_samples);
+            let trips_from = Instant::now() + config.trip_grace;
+            while !thread_stop.load(Ordering::Relaxed) {
+                thread_heartbeat.store(unix_millis(), Ordering::Relaxed);
+                // Commands go out as soon as they arrive rather than waiting for the next tick. A
+                // failed write leaves the connection to the next poll, which reports it offline.
+                for command in pending_commands.try_iter() {
+                    let result = if Instant::now() < retry_at {
+                        Err("the PLC is offline".to_string())
+                    } else {
+                        source.write(&command).map_err(|e| e.to_string())
+                    };
+                    if let Err(e) = result {
+                        let _ = reports.send(Err(format!("{} not sent to PLC: {}", command, e)));
+                    }
+                }
+
+                // Parked rather than slept so stop() and interval changes don't wait out the old interval
+                let interval = Duration::from_millis(thread_interval_ms.load(Ordering::Relaxed));
+                let now = Instant::now();
+                if now < last_tick + interval {
+                    thread::park_timeout(last_tick + interval - now);
+                    continue;
+                }
+                last_tick = now;
+                if thread_paused.load(Ordering::Relaxed) {
+                    last_poll = None;
+                    continue;
+                }
+
+                // Scenario time follows the simulation, so it only advances while the plant is live.
+                // Each lock is released before the next is taken.
+                let link_dropped = match sensor_data.lock() {
+                    Ok(data) if !data.frozen => data.plc_link_down(),
+                    _ => {
+                        last_poll = None;
+                        continue;
+                    }
+                };
+                let actions = scenario.lock().map(|mut player| player.advance(interval)).unwrap_or_default();
+                if !actions.is_empty() {
+                    if let Ok(mut data) = sensor_data.lock() {
+                        for action in &actions {
+                            // Channels were checked when the scenario was loaded
+                            let _ = action.apply(&mut data);
+                        }
+                    }
+                }
+
+                // Backing off after a failure; the readings stay as they were
+                if now < retry_at {
+                    last_poll = None;
+                    continue;
+                }
+                let started = Instant::now();
+                let period = last_poll.replace(started).map(|last_poll| started - last_poll);
+                // A dropped link leaves the last readings in place and last_update going stale,
+                // whatever the source
+                let result = if link_dropped {
+                    Err(SourceError::Connection("no response from PLC (link dropped)".to_string()))
+                } else {
+                    source.poll(&sensor_data, interval)
+                };
+                let polled = started.elapsed();
+                let latency = source.simulated_latency().unwrap_or(polled);
+
+                let waiting = Instant::now();
+                let (readings, update, stopped, motion) = match sensor_data.lock() {
+                    // A snapshot loaded while the source was polled wins
+                    Ok(data) if data.frozen => continue,
+                    Ok(mut data) => match result {
+                        Ok(()) => {
+                            failures = 0;
+                            if let Some(period) = period {
+                                data.scan.record(Cycle { target: interval, period, poll: polled, lock_wait: waiting.elapsed() });
+                            }
+                            let now = SystemTime::now();
+                            data.connection = ConnectionStatus::Online;
+                            data.link.record_success(latency);
+                            data.last_update = Some(now);
+                            // Checked on every sample so the debounce counts samples, not console ticks.
+                            // The console sees the trip and performs the shutdown.
+                            data.update_alarm_states(now);
+                            data.update_statistics(now);
+                            if Instant::now() >= trips_from && !data.trips_inhibited {
+                                let trip = trips.check(&data.channels);
+                                if !data.emergency_shutdown && data.auto_trip.is_none() {
+                                    data.auto_trip = trip;
+                                }
+                            }
+                            let stopped = data.update_interlocks();
+                            // What the vibration channels follow: each motor's true speed and bearing
+                            let motion = (0..data.motors.len()).map(|i| {
+                                let motor = FaultTarget::Motor(i);
+                                (data.process_value(motor), data.fault_on(motor).is_some_and(|fault| fault.kind == FaultKind::BearingWear))
+                            }).collect::<Vec<_>>();
+                            (HistoryRecord::capture(&data, now), (!listeners.is_empty()).then(|| data.clone()), stopped, motion)
+                        }
+                        Err(e) => {
+                            failures += 1;
+                            retry_at = Instant::now() + source.retry_delay(failures);
+                            data.connection = ConnectionStatus::Offline { reason: e.to_string(), retry_at };
+                            data.link.record_failure();
+                            last_poll = None;
+                            continue;
+                        }
+                    },
+                    Err(_) => continue,
+                };
+                // A PLC has to be told about motors an interlock has stopped; the console reports why
+                for motor in stopped {
+                    let command = SourceCommand::MotorRunning { motor, running: false };
+                    if let Err(e) = source.write(&command) {
+                        let _ = reports.send(Err(format!("{} not sent to PLC: {}", command, e)));
+                    }
+                }
+                // Recorded after releasing the sensor lock so no two locks are ever held together.
+                // Runtime counts whole intervals, like the simulation step.
+                if let Ok(mut maintenance) = maintenance.lock() {
+                    maintenance.record(&readings.motor_states, interval);
+                }
+                if let Ok(mut energy) = energy.lock() {
+                    energy.record(&readings.motor_power, interval, readings.time);
+                }
+                if let Ok(mut history) = history.lock() {
+                    history.record(readings);
+                }
+                if let Some(samples) = source.vibration(&motion, interval) {
+                    let frames = vibration.lock().map(|mut vibration| vibration.record(samples)).unwrap_or_default();
+                    // The FFTs are worked out here, without the lock, so the console never waits on them
+                    let analyses = frames.iter().map(|frame| frame.as_deref().map(Analysis::of)).collect();
+                    if let Ok(mut vibration) = vibration.lock() {
+                        vibration.analysed(analyses);
+                    }
+                }
+                if let Some(update) = update {
+                    for listener in &listeners {
+                        let _ = listener.try_send(update.clone());
+                    }
+                }
+            }
+        }));
+        if let Err(payload) = result {
+            let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_string());
+            let _ = crash_reports.send(Err(format!("Sensor thread crashed: {}", message)));
+        }
+    });
+
+    SimulationHandle { stop, paused, interval_ms, heartbeat, commands, thread: Some(thread) }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::energy::EnergyMeter;
+
+    // Temperatures after each of `ticks` polls of a fresh default plant
+    fn temperature_trace(seed: u64, ticks: usize) -> Vec<Vec<f32>> {
+        let data = Mutex::new(SensorData::from_config(&Config::default()));
+        let mut source = SimulatedSource::new(seed, SimulationModel::Process(ProcessModel::default()));
+        (0..ticks)
+            .map(|_| {
+                source.poll(&data, Duration::from_secs(1)).unwrap();
+                data.lock().unwrap().channels_of(ChannelKind::Temperature).map(|channel| channel.value).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_readings() {
+        assert_eq!(temperature_trace(42, 50), temperature_trace(42, 50));
+        assert_ne!(temperature_trace(42, 50), temperature_trace(43, 50));
+    }
+
+    #[test]
+    fn snapshots_stay_quick_beside_a_10_ms_sensor_thread() {
+        let config = Config::default();
+        let sensor_data = Arc::new(Mutex::new(SensorData::from_config(&config)));
+        let history = Arc::new(Mutex::new(SensorHistory::new()));
+        let names: Vec<String> = sensor_data.lock().unwrap().motors.iter().map(|motor| motor.name.clone()).collect();
+        let shared = SharedState {
+            sensor_data: Arc::clone(&sensor_data),
+            history: Arc::clone(&history),
+            maintenance: Arc::new(Mutex::new(Maintenance::new(&names, config.service_interval))),
+            energy: Arc::new(Mutex::new(EnergyMeter::new(&names, SystemTime::now()))),
+            vibration: Arc::new(Mutex::new(Vibration::new(names.len(), config.vibration_limit))),
+            scenario: Arc::new(Mutex::new(ScenarioPlayer::default())),
+        };
+        let model = SimulationModel::Process(ProcessModel::default());
+        let simulation = SimulationConfig { seed: 1, update_interval_ms: 10, trip_samples: 3, trip_grace: Duration::ZERO, model: model.clone() };
+        let (reports, _) = mpsc::channel();
+        let mut thread = start_sensor_thread(Box::new(SimulatedSource::new(1, model)), shared, simulation, reports, Vec::new());
+
+        // Each frame copies the readings under a short lock, as the console does, and
+        // works from the copy
+        let mut frames = Vec::new();
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(2) {
+            let frame = Instant::now();
+            let snapshot = sensor_data.lock().unwrap().clone();
+            frames.push(frame.elapsed());
+            assert!(snapshot.channels.iter().all(|channel| channel.value.is_finite()));
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread.stop();
+
+        let updates = history.lock().unwrap().records().len();
+        assert!(updates > 50, "only {} updates in 2 s", updates);
+        frames.sort_unstable();
+        let p99 = frames[frames.len() * 99 / 100];
+        assert!(p99 < Duration::from_millis(10), "99th percentile frame {:?}, longest {:?}", p99, frames.last());
+    }
+
+    #[test]
+    fn a_step_ramps_a_started_motor_to_its_setpoint() {
+        let mut data = SensorData::from_config(&Config::default());
+        let mut rng = Rng::new(1);
+        let model = SimulationModel::Process(ProcessModel::default());
+        data.set_motor_state(3, true).unwrap();
+        simulate_step(&mut data, &mut rng, Duration::from_secs(1), &model);
+        assert_eq!(data.motors[2].speed, data.motor_ramp_rate);
+        for _ in 0..10 {
+            simulate_step(&mut data, &mut rng, Duration::from_secs(1), &model);
+        }
+        assert!(data.motors[2].speed.abs_diff(1500) <= MOTOR_SPEED_TOLERANCE, "{} RPM", data.motors[2].speed);
+        assert!(data.motors[2].current > 0.0);
+    }
+
+    #[test]
+    fn the_random_walk_stays_within_its_ranges() {
+        let mut data = SensorData::from_config(&Config::default());
+        let mut rng = Rng::new(5);
+        for step in 0..10_000 {
+            // Stopped half way through, the first motor coasts down to a standstill
+            if step == 5_000 {
+                data.set_motor_state(1, false).unwrap();
+            }
+            simulate_step(&mut data, &mut rng, Duration::from_millis(100), &SimulationModel::RandomWalk);
+            assert!(data.channels_of(ChannelKind::Temperature).all(|channel| (20.0..=30.0).contains(&channel.value)), "step {}", step);
+            assert!(data.channels_of(ChannelKind::Pressure).all(|channel| (95.0..=105.0).contains(&channel.value)), "step {}", step);
+            assert_eq!(data.motors[2].speed, 0, "a motor never started stays still");
+            if step > 6_000 {
+                assert_eq!(data.motors[0].speed, 0, "step {}", step);
+            }
+        }
+    }
+
+    // The default plant without noise, run at `dt` until everything has settled
+    fn settled_plant(dt: Duration) -> (SensorData, Rng, SimulationModel) {
+        let model = SimulationModel::Process(ProcessModel { temperature_noise: 0.0, pressure_noise: 0.0, ..ProcessModel::default() });
+        let mut data = SensorData::from_config(&Config::default());
+        let mut rng = Rng::new(9);
+        for _ in 0..(600.0 / dt.as_secs_f32()) as usize {
+            simulate_step(&mut data, &mut rng, dt, &model);
+        }
+        (data, rng, model)
+    }
+
+    // The share of a step still left after `seconds`
+    fn step_left(kind: ChannelKind, index: usize, step: f32, seconds: f32, dt: Duration) -> f32 {
+        let (mut data, mut rng, model) = settled_plant(dt);
+        let settled = data.channels_of(kind).nth(index).unwrap().value;
+        data.channel_mut(kind, index).unwrap().set_raw(settled + step);
+        for _ in 0..(seconds / dt.as_secs_f32()).round() as usize {
+            simulate_step(&mut data, &mut rng, dt, &model);
+        }
+        let value = data.channels_of(kind).nth(index).unwrap().value;
+        (value - settled) / step
+    }
+
+    #[test]
+    fn steps_decay_with_their_time_constants() {
+        // e^-1 of the step is left after one time constant, and e^-3 after three
+        let second = Duration::from_secs(1);
+        let left = step_left(ChannelKind::Temperature, 1, 10.0, 60.0, second);
+        assert!((0.35..0.39).contains(&left), "{} of the temperature step left after 60 s", left);
+        let left = step_left(ChannelKind::Temperature, 1, 10.0, 180.0, second);
+        assert!((0.04..0.06).contains(&left), "{} of the temperature step left after 180 s", left);
+
+        let tick = Duration::from_millis(100);
+        let left = step_left(ChannelKind::Pressure, 0, -5.0, 5.0, tick);
+        assert!((0.35..0.39).contains(&left), "{} of the pressure step left after 5 s", left);
+        let left = step_left(ChannelKind::Pressure, 0, -5.0, 15.0, tick);
+        assert!((0.04..0.06).contains(&left), "{} of the pressure step left after 15 s", left);
+    }
+
+    #[test]
+    fn stopping_a_motor_lets_its_readings_decay() {
+        let dt = Duration::from_millis(100);
+        let (mut data, mut rng, model) = settled_plant(dt);
+        let pressure = |data: &SensorData| data.channels_of(ChannelKind::Pressure).next().unwrap().value;
+        let temperature = |data: &SensorData| data.channels_of(ChannelKind::Temperature).next().unwrap().value;
+        // The pump (motor 4) adds its head with the square of its speed
+        let head = 3.5 * (data.motors[3].speed as f32 / 1800.0).powi(2);
+        assert!((pressure(&data) - (96.5 + head)).abs() < 0.1, "{} kPa", pressure(&data));
+        assert!(temperature(&data) > 22.5, "motor 1 warms its sensor");
+
+        data.set_motor_state(4, false).unwrap();
+        data.set_motor_state(1, false).unwrap();
+        let mut last = pressure(&data);
+        for _ in 0..600 {
+            simulate_step(&mut data, &mut rng, dt, &model);
+            assert!(pressure(&data) <= last + 1e-4, "pressure falls steadily");
+            last = pressure(&data);
+        }
+        assert!((pressure(&data) - 96.5).abs() < 0.05, "{} kPa a minute after the pump stopped", pressure(&data));
+
+        for _ in 0..3000 {
+            simulate_step(&mut data, &mut rng, dt, &model);
+        }
+        assert!((temperature(&data) - 21.5).abs() < 0.1, "{}°C six minutes after motor 1 stopped", temperature(&data));
+    }
+}
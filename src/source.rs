@@ -0,0 +1,89 @@
+// Where live readings come from: the built-in simulator or a real PLC. The sensor
+// thread polls the configured source once per update interval and backs off while
+// the source is unreachable.
+use crate::sensors::SensorData;
+
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceError {
+    // The device couldn't be reached; worth retrying
+    Connection(String),
+    // The device answered with something unusable
+    Protocol(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceError::Connection(reason) => write!(f, "{}", reason),
+            SourceError::Protocol(reason) => write!(f, "protocol error: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for SourceError {
+    fn from(error: io::Error) -> Self {
+        SourceError::Connection(error.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Online,
+    Offline { reason: String, retry_at: Instant },
+}
+
+// Operator commands forwarded to a real PLC. Motors are numbered from 0 here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceCommand {
+    MotorRunning { motor: usize, running: bool },
+    MotorSetpoint { motor: usize, rpm: u16 },
+}
+
+impl fmt::Display for SourceCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceCommand::MotorRunning { motor, running } => write!(f, "Motor {} {}", motor + 1, if *running { "start" } else { "stop" }),
+            SourceCommand::MotorSetpoint { motor, rpm } => write!(f, "Motor {} setpoint {} RPM", motor + 1, rpm),
+        }
+    }
+}
+
+pub trait DataSource: Send {
+    fn describe(&self) -> String;
+
+    // Brings the shared sensor data up to date. Implementations must not hold the lock
+    // across device I/O so operator commands and the display never wait on the network.
+    fn poll(&mut self, sensor_data: &Mutex<SensorData>, dt: Duration) -> Result<(), SourceError>;
+
+    // The console has already applied the command to the sensor data; sources backed by
+    // hardware pass it on so the next poll reads it back from the device.
+    fn write(&mut self, _command: &SourceCommand) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    // Sources that model vibration return each motor's samples since the last poll, at
+    // vibration::SAMPLE_RATE, for its speed (RPM) and whether its bearing is wearing.
+    // Others have no vibration channels.
+    fn vibration(&mut self, _motors: &[(f32, bool)], _dt: Duration) -> Option<Vec<Vec<f32>>> {
+        None
+    }
+
+    // Sources without a real link report a modelled round trip for the last poll in
+    // place of the measured one
+    fn simulated_latency(&mut self) -> Option<Duration> {
+        None
+    }
+
+    // Wait before the next attempt after the given number of consecutive failures
+    fn retry_delay(&self, failures: u32) -> Duration {
+        // 1, 2, 4, 8, 16, then every 30 s
+        let exponent = failures.saturating_sub(1).min(5);
+        Duration::from_secs(1 << exponent).min(Duration::from_secs(30))
+    }
+}
@@ -0,0 +1,70 @@
+// Running statistics for an analog channel since the last reset, for questions like "what
+// was the peak pressure this shift?". Updated on every sample with Welford's algorithm,
+// so nothing is kept but the running totals.
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunningStats {
+    pub since: SystemTime,
+    count: u64,
+    mean: f64,
+    // Sum of squared differences from the mean
+    m2: f64,
+    min: Option<(f32, SystemTime)>,
+    max: Option<(f32, SystemTime)>,
+    // Samples left out because the channel was faulted or had no valid reading
+    ignored: u64,
+}
+
+impl RunningStats {
+    pub fn new(since: SystemTime) -> Self {
+        RunningStats { since, count: 0, mean: 0.0, m2: 0.0, min: None, max: None, ignored: 0 }
+    }
+
+    pub fn record(&mut self, value: f32, time: SystemTime) {
+        if !value.is_finite() {
+            self.ignore();
+            return;
+        }
+        self.count += 1;
+        let delta = value as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value as f64 - self.mean);
+        if !matches!(self.min, Some((min, _)) if min <= value) {
+            self.min = Some((value, time));
+        }
+        if !matches!(self.max, Some((max, _)) if max >= value) {
+            self.max = Some((value, time));
+        }
+    }
+
+    pub fn ignore(&mut self) {
+        self.ignored += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn ignored(&self) -> u64 {
+        self.ignored
+    }
+
+    // The lowest and highest readings, with when they were taken
+    pub fn min(&self) -> Option<(f32, SystemTime)> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<(f32, SystemTime)> {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.mean as f32)
+    }
+
+    // Sample standard deviation; needs two samples
+    pub fn std_dev(&self) -> Option<f32> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt() as f32)
+    }
+}
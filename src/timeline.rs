@@ -0,0 +1,128 @@
+// Event timeline: alarms, emergency shutdowns, diagnostic runs, operator actions and
+// operator notes on one time axis, so what led up to a trip can be seen at a glance.
+// The console gathers the events in the window being shown from the historian and the
+// audit trail; this lays them out, one lane per kind of event.
+use crate::clock::format_time;
+use crate::platform::StatusColor;
+
+use std::time::SystemTime;
+
+// Columns across the time axis
+pub const WIDTH: usize = 60;
+// Columns between time labels
+const TICK: usize = 15;
+// Shown over any other marker in its column
+const FIRST_OUT_MARKER: char = '1';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    Alarms,
+    Shutdowns,
+    Diagnostics,
+    Actions,
+    Notes,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 5] = [Lane::Alarms, Lane::Shutdowns, Lane::Diagnostics, Lane::Actions, Lane::Notes];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lane::Alarms => "Alarms",
+            Lane::Shutdowns => "E-stops",
+            Lane::Diagnostics => "Diagnostics",
+            Lane::Actions => "Actions",
+            Lane::Notes => "Notes",
+        }
+    }
+}
+
+// Worst last; a column holding several events shows the worst
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn color(self) -> StatusColor {
+        match self {
+            Severity::Info => StatusColor::Normal,
+            Severity::Normal => StatusColor::Green,
+            Severity::Warning => StatusColor::Yellow,
+            Severity::Critical => StatusColor::Red,
+        }
+    }
+
+    fn marker(self) -> char {
+        match self {
+            Severity::Info => 'o',
+            Severity::Normal => '+',
+            Severity::Warning => '~',
+            Severity::Critical => '!',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineEvent {
+    pub time: SystemTime,
+    pub lane: Lane,
+    pub severity: Severity,
+    pub summary: String,
+    // Who or what it came from, for the expanded view
+    pub origin: String,
+    // The first alarm before a trip, marked apart from the others
+    pub first_out: bool,
+}
+
+// The column an instant falls in, if it is inside the window
+pub fn column(time: SystemTime, since: SystemTime, until: SystemTime) -> Option<usize> {
+    let span = until.duration_since(since).unwrap_or_default().as_secs_f64();
+    let offset = time.duration_since(since).ok()?.as_secs_f64();
+    if span <= 0.0 || offset > span {
+        return None;
+    }
+    Some(((offset / span * WIDTH as f64) as usize).min(WIDTH - 1))
+}
+
+// The time axis, then a line per lane with a marker wherever an event fell, each in
+// the color of the worst event in it
+pub fn render(events: &[TimelineEvent], since: SystemTime, until: SystemTime) -> Vec<(StatusColor, String)> {
+    let label_width = Lane::ALL.iter().map(|lane| lane.name().len()).max().unwrap_or(0);
+    let step = until.duration_since(since).unwrap_or_default() / WIDTH as u32;
+    // HH:MM at each tick, the last one ending over the right edge
+    let mut labels = String::new();
+    for tick in (0..WIDTH).step_by(TICK) {
+        labels.push_str(&format!("{:<TICK$}", clock_label(since + step * tick as u32)));
+    }
+    labels.truncate(WIDTH - 4);
+    labels.push_str(&clock_label(until));
+    let axis: String = (0..WIDTH).map(|column| if column % TICK == 0 { '+' } else { '-' }).collect();
+    let mut lines = vec![
+        (StatusColor::Normal, format!("{:<label_width$}  {}", "", labels)),
+        (StatusColor::Normal, format!("{:<label_width$} |{}|", "", axis)),
+    ];
+    for lane in Lane::ALL {
+        let mut columns: Vec<Option<Severity>> = vec![None; WIDTH];
+        let mut first_outs = [false; WIDTH];
+        for event in events.iter().filter(|event| event.lane == lane) {
+            if let Some(column) = column(event.time, since, until) {
+                columns[column] = columns[column].max(Some(event.severity));
+                first_outs[column] |= event.first_out;
+            }
+        }
+        let row: String = columns.iter().zip(first_outs)
+            .map(|(severity, first_out)| if first_out { FIRST_OUT_MARKER } else { severity.map_or(' ', Severity::marker) })
+            .collect();
+        let color = columns.iter().flatten().max().map_or(StatusColor::Normal, |severity| severity.color());
+        lines.push((color, format!("{:<label_width$} |{}|", lane.name(), row)));
+    }
+    lines
+}
+
+fn clock_label(time: SystemTime) -> String {
+    format_time(time).chars().take(5).collect()
+}
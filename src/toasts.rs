@@ -0,0 +1,94 @@
+// Toasts: short notices of how something the operator started turned out - an export
+// written, a recipe loaded, a notification sent. Each is logged as it arrives and kept
+// at the top of the status screen for a few seconds; errors stay until dismissed, so a
+// failure is not missed while the operator was looking at something else. Nothing here
+// waits for the operator.
+use crate::platform::StatusColor;
+
+use std::time::{Duration, Instant};
+
+// How long information and warnings stay up
+pub const INFO_DURATION: Duration = Duration::from_secs(5);
+pub const WARNING_DURATION: Duration = Duration::from_secs(10);
+// Beyond this the oldest is dropped, even an error not yet dismissed
+const MAX_TOASTS: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn color(self) -> StatusColor {
+        match self {
+            Severity::Info => StatusColor::Green,
+            Severity::Warning => StatusColor::Yellow,
+            Severity::Error => StatusColor::Red,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Toast {
+    pub id: u32,
+    pub severity: Severity,
+    pub message: String,
+    // None until dismissed, for errors
+    pub expires_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+    last_id: u32,
+}
+
+impl Toasts {
+    pub fn push(&mut self, severity: Severity, message: &str, now: Instant) -> u32 {
+        let expires_at = match severity {
+            Severity::Info => Some(now + INFO_DURATION),
+            Severity::Warning => Some(now + WARNING_DURATION),
+            Severity::Error => None,
+        };
+        self.last_id += 1;
+        self.toasts.push(Toast { id: self.last_id, severity, message: message.to_string(), expires_at });
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.last_id
+    }
+
+    // Drops the toasts whose time is up
+    pub fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|toast| toast.expires_at.map_or(true, |expires_at| expires_at > now));
+    }
+
+    // False if there is no toast with this number
+    pub fn dismiss(&mut self, id: u32) -> bool {
+        let before = self.toasts.len();
+        self.toasts.retain(|toast| toast.id != id);
+        self.toasts.len() < before
+    }
+
+    // Returns how many were dismissed
+    pub fn dismiss_all(&mut self) -> usize {
+        let count = self.toasts.len();
+        self.toasts.clear();
+        count
+    }
+
+    // Oldest first
+    pub fn visible(&self) -> &[Toast] {
+        &self.toasts
+    }
+
+    pub fn is_showing(&self, message: &str) -> bool {
+        self.toasts.iter().any(|toast| toast.message == message)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toasts.is_empty()
+    }
+}
@@ -0,0 +1,264 @@
+// Parser for the subset of TOML used by forlenza.toml and scenario files: [tables],
+// [dotted.tables] and [[arrays.of.tables]], key = value pairs, strings, numbers,
+// booleans and (possibly multi-line) arrays.
+// Documents are returned as JSON values so config code can share the JSON accessors;
+// `write` turns one back into TOML for a configuration file upgraded to a newer schema.
+use crate::json::Value;
+
+use std::fmt::Write;
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut root = Value::Object(Vec::new());
+    let mut table: Vec<String> = Vec::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with("[[") {
+            if !line.ends_with("]]") {
+                return Err(format!("line {}: invalid table header", line_number));
+            }
+            table = table_name(&line[2..line.len() - 2]).ok_or_else(|| format!("line {}: invalid table name", line_number))?;
+            append_table(&mut root, &table).map_err(|e| format!("line {}: {}", line_number, e))?;
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(format!("line {}: invalid table header", line_number));
+            }
+            table = table_name(&line[1..line.len() - 1]).ok_or_else(|| format!("line {}: invalid table name", line_number))?;
+            table_mut(&mut root, &table).map_err(|e| format!("line {}: {}", line_number, e))?;
+            continue;
+        }
+
+        // Arrays may continue over several lines until the brackets balance
+        while bracket_depth(&line) > 0 {
+            match lines.next() {
+                Some((_, next)) => {
+                    line.push(' ');
+                    line.push_str(strip_comment(next).trim());
+                }
+                None => return Err(format!("line {}: unterminated array", line_number)),
+            }
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected key = value", line_number))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(format!("line {}: invalid key '{}'", line_number, key));
+        }
+        let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", line_number, e))?;
+
+        let members = match table_mut(&mut root, &table) {
+            Ok(Value::Object(members)) => members,
+            _ => return Err(format!("line {}: invalid table", line_number)),
+        };
+        if members.iter().any(|(name, _)| name == key) {
+            return Err(format!("line {}: duplicate key '{}'", line_number, key));
+        }
+        members.push((key.to_string(), value));
+    }
+
+    Ok(root)
+}
+
+// Writes a document out as TOML that parse reads back the same: each table's keys, then
+// its tables and arrays of tables under their dotted names. Comments and layout aren't
+// kept, and nulls, which TOML has no way to write, are left out.
+pub fn write(document: &Value) -> String {
+    let mut out = String::new();
+    write_table(&mut out, &[], document, false);
+    out
+}
+
+fn write_table(out: &mut String, path: &[String], table: &Value, element: bool) {
+    let members = match table {
+        Value::Object(members) => members,
+        _ => return,
+    };
+    if !path.is_empty() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let _ = match element {
+            true => writeln!(out, "[[{}]]", path.join(".")),
+            false => writeln!(out, "[{}]", path.join(".")),
+        };
+    }
+    let nested = |value: &Value| match value {
+        Value::Object(_) => true,
+        Value::Array(items) => !items.is_empty() && items.iter().all(|item| matches!(item, Value::Object(_))),
+        _ => false,
+    };
+    // Strings, numbers, booleans and arrays of them are written as JSON writes them
+    for (key, value) in members.iter().filter(|(_, value)| !nested(value) && *value != Value::Null) {
+        let _ = writeln!(out, "{} = {}", key, value);
+    }
+    for (key, value) in members.iter().filter(|(_, value)| nested(value)) {
+        let path = [path, std::slice::from_ref(key)].concat();
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    write_table(out, &path, item, true);
+                }
+            }
+            table => write_table(out, &path, table, false),
+        }
+    }
+}
+
+fn table_name(header: &str) -> Option<Vec<String>> {
+    let name: Vec<String> = header.split('.').map(|part| part.trim().to_string()).collect();
+    if name.iter().all(|part| is_bare_key(part)) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Removes a trailing # comment, ignoring # inside strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn bracket_depth(line: &str) -> i32 {
+    let value = match line.split_once('=') {
+        Some((_, value)) => value,
+        None => return 0,
+    };
+    let mut depth = 0;
+    let mut in_string = false;
+    for c in value.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+fn table_mut<'a>(root: &'a mut Value, path: &[String]) -> Result<&'a mut Value, String> {
+    let mut current = root;
+    for part in path {
+        let members = match current {
+            Value::Object(members) => members,
+            _ => return Err(format!("'{}' is not a table", part)),
+        };
+        let index = match members.iter().position(|(name, _)| name == part) {
+            Some(index) => index,
+            None => {
+                members.push((part.clone(), Value::Object(Vec::new())));
+                members.len() - 1
+            }
+        };
+        // Headers below an array of tables refer to its most recent element
+        current = match &mut members[index].1 {
+            Value::Array(items) => items.last_mut().ok_or_else(|| format!("'{}' is not a table", part))?,
+            value => value,
+        };
+    }
+    match current {
+        Value::Object(_) => Ok(current),
+        _ => Err("key is already defined as a value".to_string()),
+    }
+}
+
+// Starts a new element of the array of tables at path
+fn append_table(root: &mut Value, path: &[String]) -> Result<(), String> {
+    let (name, parent) = match path.split_last() {
+        Some(split) => split,
+        None => return Err("invalid table name".to_string()),
+    };
+    let members = match table_mut(root, parent)? {
+        Value::Object(members) => members,
+        _ => return Err("invalid table".to_string()),
+    };
+    match members.iter_mut().find(|(key, _)| key == name) {
+        Some((_, Value::Array(items))) if items.iter().all(|item| matches!(item, Value::Object(_))) => {
+            items.push(Value::Object(Vec::new()));
+        }
+        Some(_) => return Err(format!("'{}' is already defined as a value", name)),
+        None => members.push((name.clone(), Value::Array(vec![Value::Object(Vec::new())]))),
+    }
+    Ok(())
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    if text.is_empty() {
+        return Err("missing value".to_string());
+    }
+    if text.starts_with('"') {
+        // TOML basic strings share JSON's escape rules for everything this config uses
+        return match Value::parse(text) {
+            Ok(value @ Value::String(_)) => Ok(value),
+            _ => Err(format!("invalid string {}", text)),
+        };
+    }
+    if text.starts_with('[') {
+        return parse_array(text);
+    }
+    match text {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    text.replace('_', "")
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .map(Value::Number)
+        .ok_or_else(|| format!("invalid value '{}'", text))
+}
+
+fn parse_array(text: &str) -> Result<Value, String> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| format!("invalid array {}", text))?;
+
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                items.push(parse_value(inner[start..i].trim())?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    // A trailing comma is allowed
+    let last = inner[start..].trim();
+    if !last.is_empty() {
+        items.push(parse_value(last)?);
+    }
+    Ok(Value::Array(items))
+}
@@ -0,0 +1,167 @@
+// Trip records: every channel's readings from the minute before an emergency shutdown or
+// automatic trip to half a minute after it, with what the console knew at the moment -
+// the reason, the first-out, the alarms, who was logged in and the last diagnostic. A
+// capture waits out the half minute on its own thread, then copies the readings out of
+// the device's history buffer and writes trip_YYYYMMDD_HHMMSS.json (read back by
+// 'trips') and a .csv of the same readings. Nothing the console does in the meantime,
+// a reset included, stops it.
+use crate::alarms::Limits;
+use crate::clock::{format_datetime, parse_datetime};
+use crate::history::{self, HistoryRecord, Sample, SensorHistory};
+use crate::json::Value;
+use crate::sensors::ChannelKind;
+use crate::units::Units;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub const BEFORE: Duration = Duration::from_secs(60);
+pub const AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct TripChannel {
+    // Tag and name
+    pub label: String,
+    pub kind: ChannelKind,
+    // SI, like the samples
+    pub limits: Limits,
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TripRecord {
+    pub system_id: String,
+    pub time: SystemTime,
+    pub reason: String,
+    // The channel beyond its trip limit; None for a shutdown by hand
+    pub auto_trip: Option<String>,
+    pub first_out: Option<String>,
+    pub user: String,
+    // The device's alarms in the list at the moment
+    pub alarms: Vec<String>,
+    pub diagnostic: String,
+    pub channels: Vec<TripChannel>,
+}
+
+impl TripRecord {
+    pub fn to_json(&self) -> Value {
+        let text = |text: &Option<String>| text.as_ref().map_or(Value::Null, |text| text.as_str().into());
+        let channels = self.channels.iter().map(|channel| Value::Object(vec![
+            ("name".to_string(), channel.label.as_str().into()),
+            ("kind".to_string(), channel.kind.to_string().into()),
+            ("limits".to_string(), channel.limits.to_json()),
+            ("samples".to_string(), Value::Array(channel.samples.iter().map(|sample| {
+                Value::Array(vec![format_datetime(sample.time).into(), sample.value.into()])
+            }).collect())),
+        ])).collect();
+        Value::Object(vec![
+            ("system_id".to_string(), self.system_id.as_str().into()),
+            ("time".to_string(), format_datetime(self.time).into()),
+            ("reason".to_string(), self.reason.as_str().into()),
+            ("auto_trip".to_string(), text(&self.auto_trip)),
+            ("first_out".to_string(), text(&self.first_out)),
+            ("user".to_string(), self.user.as_str().into()),
+            ("alarms".to_string(), Value::Array(self.alarms.iter().map(|alarm| alarm.as_str().into()).collect())),
+            ("diagnostic".to_string(), self.diagnostic.as_str().into()),
+            ("before_s".to_string(), BEFORE.as_secs().into()),
+            ("after_s".to_string(), AFTER.as_secs().into()),
+            ("channels".to_string(), Value::Array(channels)),
+        ])
+    }
+
+    pub fn from_json(value: &Value) -> Result<TripRecord, String> {
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        let channels = value.array_field("channels", |item| {
+            let kind = match item.str_field("kind").ok()? {
+                "temperature" => ChannelKind::Temperature,
+                "pressure" => ChannelKind::Pressure,
+                _ => return None,
+            };
+            let samples = item.array_field("samples", |sample| match sample.as_array()? {
+                // A missing reading is written as null
+                [time, value] => Some(Sample { time: parse_datetime(time.as_str()?)?, value: value.as_f64().map_or(f32::NAN, |value| value as f32) }),
+                _ => None,
+            }).ok()?;
+            Some(TripChannel { label: item.str_field("name").ok()?.to_string(), kind, limits: Limits::from_json(item.field("limits").ok()?)?, samples })
+        })?;
+        Ok(TripRecord {
+            system_id: value.str_field("system_id")?.to_string(),
+            time: parse_datetime(value.str_field("time")?).ok_or("time is not a timestamp")?,
+            reason: value.str_field("reason")?.to_string(),
+            auto_trip: text("auto_trip"),
+            first_out: text("first_out"),
+            user: value.str_field("user")?.to_string(),
+            alarms: value.array_field("alarms", |alarm| alarm.as_str().map(str::to_string))?,
+            diagnostic: value.str_field("diagnostic")?.to_string(),
+            channels,
+        })
+    }
+}
+
+// Starts capturing a trip that happened at `record.time`; `record` has everything but
+// the readings. The outcome is sent to `done` once the files are written.
+pub fn capture(mut record: TripRecord, history: Arc<Mutex<SensorHistory>>, directory: PathBuf, done: Sender<Result<String, String>>) {
+    thread::spawn(move || {
+        let until = record.time + AFTER;
+        // Slept in steps in case the clock is set back
+        while let Ok(remaining) = until.duration_since(SystemTime::now()) {
+            thread::sleep(remaining.min(Duration::from_secs(1)));
+        }
+        let since = record.time.checked_sub(BEFORE).unwrap_or(record.time);
+        let records: Vec<HistoryRecord> = match history.lock() {
+            Ok(history) => history.records().into_iter().filter(|sampled| sampled.time >= since && sampled.time <= until).collect(),
+            Err(_) => {
+                let _ = done.send(Err(format!("Trip record of {} not written: the history buffer was lost", format_datetime(record.time))));
+                return;
+            }
+        };
+        for (position, channel) in record.channels.iter_mut().enumerate() {
+            channel.samples = records.iter()
+                .filter_map(|sampled| sampled.channels.get(position).map(|(_, value)| Sample { time: sampled.time, value: *value }))
+                .collect();
+        }
+        let labels: Vec<String> = record.channels.iter().map(|channel| channel.label.clone()).collect();
+        let result = write(&record, &records, &labels, &directory)
+            .map(|path| format!("Trip record written to {} ({} samples, and a .csv beside it)", path.display(), records.len()))
+            .map_err(|e| format!("Trip record of {} not written: {}", format_datetime(record.time), e));
+        let _ = done.send(result);
+    });
+}
+
+fn write(record: &TripRecord, records: &[HistoryRecord], labels: &[String], directory: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(directory).map_err(|e| format!("{} could not be created: {}", directory.display(), e))?;
+    let datetime = format_datetime(record.time);
+    let stamp = format!("{}_{}", datetime[..10].replace('-', ""), datetime[11..19].replace(':', ""));
+    let path = directory.join(format!("trip_{}.json", stamp));
+    record.to_json().save(&path).map_err(|e| format!("{} could not be written: {}", path.display(), e))?;
+    // In SI, like the historian
+    let csv = path.with_extension("csv");
+    history::write_csv(&csv.to_string_lossy(), records, Units::default(), labels).map_err(|e| format!("{} could not be written: {}", csv.display(), e))?;
+    Ok(path)
+}
+
+// The trip records in `directory`, oldest first; files that don't read back are skipped.
+// No directory yet means no trips.
+pub fn list(directory: &Path) -> Vec<(PathBuf, TripRecord)> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut trips: Vec<(PathBuf, TripRecord)> = entries.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json") && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("trip_")))
+        .filter_map(|path| load(&path).ok().map(|record| (path, record)))
+        .collect();
+    trips.sort_by_key(|(_, record)| record.time);
+    trips
+}
+
+pub fn load(path: &Path) -> Result<TripRecord, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let value = Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e))?;
+    TripRecord::from_json(&value)
+}
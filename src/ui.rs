@@ -0,0 +1,6785 @@
+// Operator console: the controller that owns the application state, the command
+// interpreter and the text rendering of status, alarms and trends.
+use crate::alarms::{self, AlarmLevel, AlarmList, Limits};
+use crate::annunciator::{Annunciator, Tone};
+use crate::api::{ApiRequest, ApiServer, WriteCommand, WriteOutcome};
+use crate::audit::{self, AuditAction, AuditLog};
+use crate::auth::{self, Account, Role, Session};
+use crate::bundle::Bundle;
+use crate::calibration::{Calibration, CalibrationBook, SANE_GAIN};
+use crate::clock::{format_datetime, format_time, format_time_precise, timestamp};
+use crate::commands::{self, Actor, Command, CommandError};
+use crate::compare::{Baseline, Comparison, Deviation, Tolerances};
+use crate::config::{parse_time_of_day, Config};
+use crate::devices::{self, Device, SourceChoice};
+use crate::error::{self, FisError};
+use crate::diagnostics::{progress_bar, CheckResult, DiagnosticContext, DiagnosticRunner, DiagnosticSummary, DiagnosticTrigger, DiagnosticUpdate, DIAGNOSTIC_STEPS};
+use crate::history::{self, RingBuffer, Sample};
+use crate::i18n::{self, decimal, tr, tr_args, Language};
+use crate::interlocks::{self, Bypass};
+use crate::json::Value;
+use crate::logfile::LogFile;
+use crate::mode::{ModeEvent, SystemMode};
+use crate::mqtt::{MqttPublisher, MqttStatus};
+use crate::notes::{self, Note};
+use crate::notify::{Notification, NotificationKind, Notifier};
+use crate::pens::{Axis, Pen, PenSet};
+use crate::pid::LoopMode;
+use crate::prometheus;
+use crate::recipes::{Recipe, RecipeBook};
+use crate::replay::{ReplayConfig, ReplaySpeed, ReplayState};
+use crate::report::{Report, ReportChannel, ReportMotor};
+use crate::platform::{self, print_colored, set_console_title, LogLevel, OsVersion, StatusColor, Theme};
+use crate::faults::{FaultKind, FaultTarget};
+use crate::historian::{self, Historian};
+use crate::heartbeat::{LinkHealth, LinkStats, WINDOW};
+use crate::scan::{self, ScanStats};
+use crate::sensors::{Channel, ChannelKind, DigitalPoint, IoKind, Motor, SensorData, Tagging, MAX_MOTOR_SETPOINT, MOTOR_SPEED_TOLERANCE, RATED_MOTOR_SPEED};
+use crate::scenario::{format_elapsed, PlaybackState, Scenario};
+use crate::simulation::{SimulationConfig, SimulationModel};
+use crate::serial;
+use crate::sha1::sha1;
+use crate::settings::Settings;
+use crate::shift::{ShiftRequest, ShiftSummary};
+use crate::source::{ConnectionStatus, SourceCommand};
+use crate::timeline::{self, Lane, Severity, TimelineEvent};
+use crate::toasts::{self, Toasts};
+use crate::trips::{self, TripChannel, TripRecord};
+use crate::undo::{self, Change, Previous, UndoStack};
+use crate::units::{Unit, Units};
+use crate::vibration::{self, Analysis};
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Command-line options win over forlenza.toml, which wins over the built-in defaults
+#[derive(Default)]
+pub struct StartupOptions {
+    pub force_compatible: bool,
+    pub simulate_incompatible: bool,
+    pub seed: Option<u64>,
+    // No console: services run until interrupted, and output is plain log lines
+    pub headless: bool,
+    // In place of forlenza.toml next to the executable
+    pub config: Option<PathBuf>,
+    // In place of source.kind: simulation, modbus-tcp or modbus-rtu
+    pub source: Option<String>,
+    pub log_level: Option<LogLevel>,
+    // Account name and PIN to print a config hash for, instead of starting
+    pub hash_pin: Option<(String, String)>,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl StartupOptions {
+    pub fn from_args() -> Result<Self, String> {
+        let mut options = StartupOptions::default();
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--force-compatible" => options.force_compatible = true,
+                "--simulate-incompatible" => options.simulate_incompatible = true,
+                "--headless" => options.headless = true,
+                "--seed" => {
+                    let value = args.next().ok_or("--seed requires a value")?;
+                    let seed = value.parse().map_err(|_| format!("Invalid seed: {}", value))?;
+                    options.seed = Some(seed);
+                }
+                "--config" => options.config = Some(PathBuf::from(args.next().ok_or("--config requires a file path")?)),
+                "--source" => {
+                    let value = args.next().ok_or("--source requires a value")?;
+                    let kind = match value.as_str() {
+                        "sim" | "simulation" => "simulation",
+                        "modbus-tcp" | "modbus-rtu" | "replay" => value.as_str(),
+                        _ => return Err(format!("Invalid source: {} (use sim, modbus-tcp, modbus-rtu or replay)", value)),
+                    };
+                    options.source = Some(kind.to_string());
+                }
+                "--log-level" => {
+                    let value = args.next().ok_or("--log-level requires a value")?;
+                    options.log_level = Some(LogLevel::parse(&value)?);
+                }
+                "--hash-pin" => {
+                    let (name, pin) = args.next().zip(args.next()).ok_or("--hash-pin requires a user name and a PIN")?;
+                    auth::check_pin(&pin)?;
+                    options.hash_pin = Some((name, pin));
+                }
+                "--help" | "-h" => options.help = true,
+                "--version" | "-V" => options.version = true,
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+
+        // Caught here so nothing has started when the combination is refused
+        if options.seed.is_some() && options.source.as_deref().is_some_and(|kind| kind != "simulation") {
+            return Err("--seed only applies to the simulation source".to_string());
+        }
+        Ok(options)
+    }
+}
+
+impl From<AlarmLevel> for StatusColor {
+    fn from(level: AlarmLevel) -> Self {
+        match level {
+            AlarmLevel::Normal => StatusColor::Green,
+            AlarmLevel::Warning => StatusColor::Yellow,
+            AlarmLevel::Alarm => StatusColor::Red,
+        }
+    }
+}
+
+pub struct IndustrialController {
+    // Never empty; commands act on the selected device, and all of them are watched
+    devices: Vec<Device>,
+    selected: usize,
+    mqtt: Option<MqttPublisher>,
+    mqtt_status: MqttStatus,
+    api: Option<ApiServer>,
+    // Counts changes to setpoints, limits, motor states and the like, for REST API
+    // clients to name in If-Match
+    revision: Arc<AtomicU64>,
+    historian: Option<Historian>,
+    // As configured, for the diagnostic to check even if the historian couldn't start
+    historian_directory: Option<PathBuf>,
+    // None when the alarm horn is turned off
+    annunciator: Option<Annunciator>,
+    // The newest alarm the horn has sounded for
+    annunciated_alarm: u32,
+    // None unless notifications are enabled
+    notifier: Option<Notifier>,
+    // The newest alarm sent out as a notification
+    notified_alarm: u32,
+    // How each delivery attempt went, for the audit trail
+    notify_outcomes: Receiver<Result<String, String>>,
+    audit: AuditLog,
+    // Who control actions are attributed to
+    user: String,
+    // No accounts means no login; otherwise controls need a session with the right role
+    accounts: Vec<Account>,
+    session: Option<Session>,
+    session_timeout: Duration,
+    // Account waiting for its PIN to be typed
+    pending_login: Option<String>,
+    failed_logins: u32,
+    login_locked_until: Instant,
+    detected_os: String,
+    compatibility_override: bool,
+    headless: bool,
+    log_level: LogLevel,
+    log_file: Option<LogFile>,
+    config_path: PathBuf,
+    diagnostics: DiagnosticRunner,
+    diagnostic_log: RingBuffer<String>,
+    // Whether a failed scheduled run's alarm is still active
+    diagnostic_alarm: bool,
+    // Automatic runs: how often, and when the next is due (held back while another run is in progress)
+    diagnostic_interval: Option<Duration>,
+    next_scheduled_diagnostic: Option<Instant>,
+    // Times of day (UTC) at which channel statistics are reset, and when that was last checked
+    shift_changes: Vec<Duration>,
+    shift_checked: SystemTime,
+    // When the current shift began, and the summary of the last one closed this session
+    shift_started: SystemTime,
+    shift_summary: Option<ShiftSummary>,
+    shift_tx: Sender<Result<(ShiftSummary, PathBuf), String>>,
+    shift_rx: Receiver<Result<(ShiftSummary, PathBuf), String>>,
+    alarm_log: Vec<String>,
+    alarms: AlarmList,
+    // Events the last 'timeline' listed, for 'timeline event N'
+    timeline: Vec<TimelineEvent>,
+    // Notes written this session, which the historian may not have written out yet, and
+    // the number the next one gets
+    notes: Vec<Note>,
+    next_note: u64,
+    // Instants 'trend' and 'history' read values at, kept while the span changes; at most two
+    cursors: Vec<SystemTime>,
+    // What readings, limits, charts and exports are shown in; storage stays SI
+    units: Units,
+    // Whether status draws analog readings as bar gauges instead of a list
+    gauges: bool,
+    // What the operator has changed at the console, and the forlenza.toml values it replaced
+    settings: Settings,
+    settings_file: PathBuf,
+    defaults: Settings,
+    recipes: RecipeBook,
+    recipes_file: PathBuf,
+    calibrations: CalibrationBook,
+    calibration_file: PathBuf,
+    // Heading and destination of incident reports
+    title: String,
+    reports_directory: PathBuf,
+    trips_directory: PathBuf,
+    // Calibration offsets beyond which a warning is given, in °C and kPa
+    temperature_max_offset: f32,
+    pressure_max_offset: f32,
+    // Cost of a kWh for the energy screen; 0 shows no cost
+    energy_price: f64,
+    energy_currency: String,
+    job_tx: Sender<Result<String, String>>,
+    job_rx: Receiver<Result<String, String>>,
+    // Outcomes shown at the top of the status screen
+    toasts: Toasts,
+    // How long each recent pass of the main loop took to service everything, not counting
+    // the wait for a command
+    loop_times: RingBuffer<Duration>,
+    // Snapshot 'compare' measures the selected device against
+    baseline: Option<Baseline>,
+    compare_tolerances: Tolerances,
+    // Device (None for all of them) and reason of an estop waiting for the operator to confirm
+    pending_estop: Option<(Option<usize>, String)>,
+    // Channel detail redrawn every second until the next line of input
+    watching: Option<ChannelWatch>,
+    // Configuration bundle read by 'config import', with where it came from, waiting for
+    // the operator to confirm
+    pending_import: Option<(String, Bundle)>,
+    // Ctrl+F or 'find' is waiting for an ID to be typed or scanned
+    pending_find: bool,
+    // An ID 'find' didn't know, waiting for the text of a note about it
+    pending_asset_note: Option<String>,
+    // Configuration changes made at the console this session, and whether 'undo' is
+    // waiting for the operator to confirm reverting the last one
+    undo: UndoStack,
+    pending_undo: bool,
+}
+
+impl IndustrialController {
+    pub fn new(options: &StartupOptions) -> Result<Self, String> {
+        platform::set_plain_output(options.headless);
+        let os_version = OsVersion::detect();
+        let detected_os = OsVersion::describe(os_version);
+
+        // Read first so even the compatibility error is in the operator's language.
+        // Preferences changed at the console last time win over forlenza.toml.
+        let config_path = options.config.clone().unwrap_or_else(Config::default_path);
+        let (config, config_warning) = Config::load(&config_path, options.source.as_deref());
+        let settings = Settings::load(&config.settings_file);
+        i18n::set_language(settings.language.unwrap_or(config.language));
+        println!("{}", tr_args("startup.detected_os", &[&detected_os]));
+
+        let compatibility_override = platform::check_compatibility(os_version, options.force_compatible, options.simulate_incompatible)?;
+        if compatibility_override {
+            println!("{}", tr("startup.compatibility_bypassed"));
+        }
+        
+        println!("{}", config.title);
+        set_console_title(&config.title);
+        println!("{}", tr("startup.initializing_hardware"));
+        
+        platform::set_theme(settings.theme.unwrap_or(config.theme));
+        let mut simulation = SimulationConfig::new(&config, options.seed);
+        if let Some(interval_ms) = settings.update_interval_ms {
+            simulation.update_interval_ms = interval_ms;
+        }
+        println!("{}", tr_args("startup.simulation_seed", &[&simulation.seed, &simulation.update_interval_ms]));
+        
+        let mut initial_data = SensorData::from_config(&config);
+        let mut defaults = Settings {
+            units: Some(config.units),
+            theme: Some(config.theme),
+            language: Some(config.language),
+            gauges: Some(false),
+            log_level: Some(config.log_level),
+            update_interval_ms: Some(config.update_interval_ms),
+            limits: initial_data.channels.iter().map(|channel| (channel.name.clone(), channel.limits)).collect(),
+            tagging: initial_data.channels.iter().map(|channel| (channel.name.clone(), channel.tagging.clone()))
+                .chain(initial_data.motors.iter().map(|motor| (motor.name.clone(), motor.tagging.clone())))
+                .collect(),
+            pens: Default::default(),
+        };
+        settings.apply_limits(&mut initial_data, "");
+        settings.apply_tagging(&mut initial_data, "");
+        let (calibrations, calibration_warning) = match CalibrationBook::load(&config.calibration_file) {
+            Ok(calibrations) => (calibrations, None),
+            Err(e) => (CalibrationBook::default(), Some(tr_args("startup.calibrations_not_loaded", &[&config.calibration_file.display(), &e]))),
+        };
+        calibrations.apply(&mut initial_data, "");
+        let sensor_data = Arc::new(Mutex::new(initial_data));
+        let (recipes, recipes_warning) = match RecipeBook::load(&config.recipes_file) {
+            Ok(recipes) => (recipes, None),
+            Err(e) => (RecipeBook::default(), Some(tr_args("startup.recipes_not_loaded", &[&config.recipes_file.display(), &e]))),
+        };
+        let (job_tx, job_rx) = mpsc::channel();
+        let mut listeners = Vec::new();
+        let mqtt = config.mqtt.clone().map(|mqtt| {
+            println!("{}", tr_args("startup.mqtt_telemetry", &[&mqtt.host, &mqtt.port, &mqtt.telemetry_topic]));
+            let (publisher, updates) = MqttPublisher::start(mqtt, config.system_id.clone());
+            listeners.push(updates);
+            publisher
+        });
+        
+        // A port already in use shouldn't keep the panel from starting
+        let mut api_warning = None;
+        let revision = Arc::new(AtomicU64::new(1));
+        let api = config.api.clone().and_then(|api| {
+            let bind = api.bind.clone();
+            match ApiServer::start(api, Arc::clone(&sensor_data), config.system_id.clone(), Arc::clone(&revision)) {
+                Ok((server, updates)) => {
+                    println!("{}", tr_args("startup.rest_api", &[&server.address()]));
+                    listeners.push(updates);
+                    Some(server)
+                }
+                Err(e) => {
+                    api_warning = Some(tr_args("startup.api_disabled", &[&bind, &e]));
+                    None
+                }
+            }
+        });
+        
+        // Neither should a historian directory that can't be written
+        let mut historian_warning = None;
+        let historian_directory = config.historian.as_ref().map(|historian| historian.directory.clone());
+        let historian = config.historian.clone().and_then(|historian| {
+            let directory = historian.directory.clone();
+            match Historian::start(historian, job_tx.clone()) {
+                Ok((historian, updates)) => {
+                    println!("{}", tr_args("startup.historian_recording", &[&directory.display(), &historian.retention_days()]));
+                    listeners.push(updates);
+                    Some(historian)
+                }
+                Err(e) => {
+                    historian_warning = Some(tr_args("startup.historian_disabled", &[&directory.display(), &e]));
+                    None
+                }
+            }
+        });
+        
+        // Notes are numbered on from the newest the historian still keeps
+        let next_note = historian.as_ref().map_or(1, |historian| {
+            let since = SystemTime::now().checked_sub(Duration::from_secs(86_400) * (historian.retention_days() as u32 + 1)).unwrap_or(UNIX_EPOCH);
+            historian::query_notes(historian.directory(), since, SystemTime::now() + Duration::from_secs(86_400))
+                .map_or(1, |notes| notes.iter().map(|note| note.id).max().unwrap_or(0) + 1)
+        });
+
+        // Actions are still recorded for the session if the file can't be opened
+        let audit_file = config.audit_file.clone();
+        let (audit, audit_warning) = match AuditLog::open(audit_file.clone(), job_tx.clone()) {
+            Ok(audit) => (audit, None),
+            Err(e) => (
+                AuditLog::in_memory(audit_file.clone()),
+                Some(tr_args("startup.audit_not_saved", &[&audit_file.display(), &e])),
+            ),
+        };
+        let mut log_file_warning = None;
+        let log_file = config.log_file.as_ref().and_then(|log_file| match LogFile::open(log_file, job_tx.clone()) {
+            Ok(log_file) => Some(log_file),
+            Err(e) => {
+                log_file_warning = Some(tr_args("startup.log_file_disabled", &[&log_file.directory.display(), &e]));
+                None
+            }
+        });
+        
+        let (device, warnings) = Device::start(&config, simulation, sensor_data, config.maintenance_file.clone(), config.energy_file.clone(), job_tx.clone(), listeners);
+        println!("{}", tr_args("source.current", &[&device.source_name]));
+        let mut devices = vec![device];
+
+        // A device that can't be set up is left out rather than keeping the console from starting
+        let mut device_warnings: Vec<Option<String>> = warnings.into_iter().map(Some).collect();
+        let mut schema_upgrades = vec![config.schema_upgrade.clone()];
+        for (i, path) in config.device_files.iter().enumerate() {
+            let (device_config, warning) = Config::load(path, None);
+            let problem = match warning {
+                Some(error) => Some(error.to_string()),
+                None if !path.exists() => Some(tr_args("startup.device_file_missing", &[&path.display()])),
+                None if devices.iter().any(|device| device.name == device_config.system_id) => {
+                    Some(tr_args("startup.device_duplicate", &[&path.display(), &device_config.system_id]))
+                }
+                None => None,
+            };
+            if let Some(problem) = problem {
+                device_warnings.push(Some(tr_args("startup.device_not_started", &[&problem])));
+                continue;
+            }
+            schema_upgrades.push(device_config.schema_upgrade.clone());
+            // Counters are kept per device unless its file names its own
+            let maintenance_file = if device_config.maintenance_file == config.maintenance_file {
+                config.maintenance_file.with_file_name(format!("maintenance-{}.json", device_config.system_id))
+            } else {
+                device_config.maintenance_file.clone()
+            };
+            let energy_file = if device_config.energy_file == config.energy_file {
+                config.energy_file.with_file_name(format!("energy-{}.json", device_config.system_id))
+            } else {
+                device_config.energy_file.clone()
+            };
+            let mut simulation = SimulationConfig::new(&device_config, options.seed.map(|seed| seed.wrapping_add(i as u64 + 1)));
+            if let Some(interval_ms) = settings.update_interval_ms {
+                simulation.update_interval_ms = interval_ms;
+            }
+            let mut data = SensorData::from_config(&device_config);
+            let prefix = format!("{}/", device_config.system_id);
+            defaults.limits.extend(data.channels.iter().map(|channel| (format!("{}{}", prefix, channel.name), channel.limits)));
+            defaults.tagging.extend(data.channels.iter().map(|channel| (format!("{}{}", prefix, channel.name), channel.tagging.clone())));
+            defaults.tagging.extend(data.motors.iter().map(|motor| (format!("{}{}", prefix, motor.name), motor.tagging.clone())));
+            settings.apply_limits(&mut data, &prefix);
+            settings.apply_tagging(&mut data, &prefix);
+            calibrations.apply(&mut data, &prefix);
+            let (device, warnings) = Device::start(&device_config, simulation, Arc::new(Mutex::new(data)), maintenance_file, energy_file, job_tx.clone(), Vec::new());
+            println!("{}", tr_args("startup.device", &[&device.name, &device.source_name]));
+            device_warnings.extend(warnings.into_iter().map(|warning| Some(format!("{}: {}", device.name, warning))));
+            devices.push(device);
+        }
+        
+        let annunciator = config.annunciator.map(|horn| Annunciator::start(horn, job_tx.clone()));
+        let (notify_tx, notify_outcomes) = mpsc::channel();
+        let (shift_tx, shift_rx) = mpsc::channel();
+        let notifier = config.notifications.as_ref().map(|notifications| {
+            let notifier = Notifier::start(notifications, notify_tx);
+            println!("{}", tr_args("startup.notifications", &[&notifier.sinks().join(", ")]));
+            notifier
+        });
+        let mut controller = IndustrialController {
+            devices,
+            selected: 0,
+            mqtt,
+            mqtt_status: MqttStatus::Connecting,
+            api,
+            revision,
+            historian,
+            historian_directory,
+            annunciator,
+            annunciated_alarm: 0,
+            notifier,
+            notified_alarm: 0,
+            notify_outcomes,
+            audit,
+            user: audit::os_user(),
+            accounts: config.accounts.clone(),
+            session: None,
+            session_timeout: config.session_timeout,
+            pending_login: None,
+            failed_logins: 0,
+            login_locked_until: Instant::now(),
+            detected_os,
+            compatibility_override,
+            headless: options.headless,
+            log_level: options.log_level.or(settings.log_level).unwrap_or(config.log_level),
+            log_file,
+            config_path: config_path.clone(),
+            diagnostics: DiagnosticRunner::default(),
+            diagnostic_log: RingBuffer::new(DIAGNOSTIC_LOG_LINES),
+            diagnostic_alarm: false,
+            diagnostic_interval: config.diagnostic_interval,
+            shift_changes: config.shift_changes.clone(),
+            shift_checked: SystemTime::now(),
+            shift_started: previous_shift_change(&config.shift_changes, SystemTime::now()),
+            shift_summary: None,
+            shift_tx,
+            shift_rx,
+            next_scheduled_diagnostic: config.diagnostic_interval.map(|interval| Instant::now() + interval),
+            alarm_log: Vec::new(),
+            timeline: Vec::new(),
+            notes: Vec::new(),
+            next_note,
+            cursors: Vec::new(),
+            alarms: AlarmList::default(),
+            units: settings.units.unwrap_or(config.units),
+            gauges: settings.gauges.unwrap_or(false),
+            settings,
+            settings_file: config.settings_file.clone(),
+            defaults,
+            recipes,
+            recipes_file: config.recipes_file.clone(),
+            calibrations,
+            calibration_file: config.calibration_file.clone(),
+            title: config.title.clone(),
+            reports_directory: config.reports_directory.clone(),
+            trips_directory: config.trips_directory.clone(),
+            temperature_max_offset: config.temperature_max_offset,
+            pressure_max_offset: config.pressure_max_offset,
+            energy_price: config.energy_price,
+            energy_currency: config.energy_currency.clone(),
+            job_tx,
+            job_rx,
+            toasts: Toasts::default(),
+            loop_times: RingBuffer::new(scan::WINDOW),
+            baseline: None,
+            compare_tolerances: config.compare_tolerances,
+            pending_estop: None,
+            watching: None,
+            pending_import: None,
+            pending_find: false,
+            pending_asset_note: None,
+            undo: UndoStack::default(),
+            pending_undo: false,
+        };
+        
+        match config_warning {
+            Some(error) => controller.report_error(0, &error),
+            None if config_path.exists() => controller.log(&tr_args("startup.config_loaded", &[&config_path.display()])),
+            None => controller.log(&tr_args("startup.config_missing", &[&config_path.display()])),
+        }
+        for upgrade in schema_upgrades.into_iter().flatten() {
+            controller.log_colored(StatusColor::Yellow, &upgrade);
+        }
+        // Shift totals saved during an earlier shift start again from zero
+        for device in &controller.devices {
+            if let Ok(mut energy) = device.energy.lock() {
+                energy.begin_shift(controller.shift_started);
+            }
+        }
+        if !controller.settings.is_empty() {
+            controller.log(&tr_args("startup.settings_restored", &[&controller.settings_file.display()]));
+        }
+        for warning in [api_warning, historian_warning, audit_warning, log_file_warning, recipes_warning, calibration_warning].into_iter().chain(device_warnings).flatten() {
+            controller.log_colored(StatusColor::Red, &warning);
+        }
+        if controller.devices.len() > 1 {
+            let names: Vec<&str> = controller.devices.iter().map(|device| device.name.as_str()).collect();
+            controller.log(&tr_args("startup.devices", &[&names.len(), &names.join(", ")]));
+        }
+        if !controller.accounts.is_empty() {
+            controller.log(&tr_args("startup.accounts", &[&controller.accounts.len()]));
+        }
+        Ok(controller)
+    }
+
+    // Services background work between operator commands until quit or end of input
+    pub fn run(&mut self) {
+        // Headless there is no console to read commands from; it runs until interrupted
+        let input = if self.headless {
+            if platform::install_shutdown_handler() {
+                self.log(tr("startup.headless"));
+            } else {
+                self.log_colored(StatusColor::Yellow, tr("startup.headless_unclean"));
+            }
+            None
+        } else {
+            println!("{}", tr("startup.help_hint"));
+            Some(spawn_input_reader())
+        };
+        self.run_diagnostic(DiagnosticTrigger::Startup);
+        
+        loop {
+            let started = Instant::now();
+            self.poll_diagnostic();
+            for index in 0..self.devices.len() {
+                self.poll_source_swap(index);
+                self.poll_alarms(index);
+                self.poll_trip(index);
+                self.poll_restart(index);
+                self.poll_maintenance(index);
+                self.poll_scenario(index);
+                self.poll_faults(index);
+                self.poll_locks(index);
+                self.poll_bypasses(index);
+                self.poll_watchdog(index);
+                self.poll_connection(index);
+                self.poll_staleness(index);
+                self.poll_scan(index);
+                self.poll_mode(index);
+            }
+            self.poll_watch();
+            self.poll_shift_change();
+            self.poll_shift_summaries();
+            self.poll_horn();
+            self.poll_notifications();
+            self.poll_jobs();
+            self.poll_toasts();
+            self.poll_translations();
+            self.poll_mqtt();
+            self.poll_api();
+            self.poll_session();
+            self.loop_times.push(started.elapsed());
+            
+            let command = match &input {
+                Some(input) => match input.recv_timeout(Duration::from_millis(100)) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None if platform::shutdown_requested() => {
+                    self.log(tr("startup.shutdown_requested"));
+                    break;
+                }
+                None => {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            };
+            
+            if !self.handle_command(&command) {
+                break;
+            }
+        }
+        
+        for index in 0..self.devices.len() {
+            // Waiting on a hung thread would hang the shutdown too
+            if self.devices[index].acquisition_halted.is_some() {
+                self.devices[index].simulation_thread.abandon();
+            } else {
+                self.devices[index].simulation_thread.stop();
+            }
+            self.save_maintenance(index);
+            self.save_energy(index);
+        }
+        if let Some(mqtt) = &mut self.mqtt {
+            mqtt.stop();
+        }
+        if let Some(api) = &mut self.api {
+            api.stop();
+        }
+        if let Some(historian) = &mut self.historian {
+            historian.stop();
+        }
+        if let Some(annunciator) = &mut self.annunciator {
+            annunciator.stop();
+        }
+        if let Some(notifier) = &mut self.notifier {
+            notifier.stop();
+        }
+        self.audit.close();
+        if self.headless {
+            self.log(tr("startup.stopped_cleanly"));
+        }
+        if let Some(log_file) = &mut self.log_file {
+            log_file.close();
+        }
+    }
+
+    fn log(&mut self, message: &str) {
+        self.log_colored(StatusColor::Normal, message);
+    }
+
+    // Lines below the log level are still kept for the diagnostic log export
+    fn log_colored(&mut self, color: StatusColor, message: &str) {
+        let entry = format!("[{}] {}", timestamp(), message);
+        self.write_log_file(color, message);
+        if LogLevel::from(color) <= self.log_level {
+            print_colored(color, &entry);
+        }
+        self.diagnostic_log.push(entry);
+    }
+
+    // Logged, and shown as a toast on the status screen for a few seconds
+    fn notify_info(&mut self, message: &str) {
+        self.log_colored(StatusColor::Green, message);
+        self.toast(toasts::Severity::Info, message);
+    }
+
+    fn notify_warn(&mut self, message: &str) {
+        self.log_colored(StatusColor::Yellow, message);
+        self.toast(toasts::Severity::Warning, message);
+    }
+
+    // Shown until dismissed
+    fn notify_error(&mut self, message: &str) {
+        self.log_colored(StatusColor::Red, message);
+        self.toast(toasts::Severity::Error, message);
+    }
+
+    // A failure on one device, logged and shown until dismissed. While the same one is
+    // still on the status screen it isn't repeated.
+    fn report_error(&mut self, index: usize, error: &FisError) {
+        let message = format!("{}{}", self.device_label(index), sentence(&error.to_string()));
+        if !self.toasts.is_showing(&message) {
+            self.notify_error(&message);
+        }
+    }
+
+    // A toast for something already logged, e.g. as an audited action
+    fn toast(&mut self, severity: toasts::Severity, message: &str) {
+        self.toasts.push(severity, message, Instant::now());
+    }
+
+    fn write_log_file(&self, color: StatusColor, message: &str) {
+        let level = LogLevel::from(color);
+        if let Some(log_file) = self.log_file.as_ref().filter(|_| level <= self.log_level) {
+            log_file.write(level, message);
+        }
+    }
+
+    // Alarms raised and cleared go to the console, the alarm log, the log file and the
+    // historian alike; a raised one also brings up the banner
+    fn report_alarm(&mut self, color: StatusColor, message: &str) {
+        if let Some(historian) = &self.historian {
+            historian.record_alarm(message);
+        }
+        self.write_log_file(color, message);
+        let entry = format!("[{}] {}", timestamp(), message);
+        print_colored(color, &entry);
+        self.alarm_log.push(entry);
+        if color == StatusColor::Red {
+            self.poll_horn();
+            self.print_alarm_banner();
+        }
+    }
+
+    fn set_log_level(&mut self, level: LogLevel) {
+        self.log_level = level;
+        self.log(&tr_args("log.level_changed", &[&level.name()]));
+        self.print_log_level();
+        self.settings.log_level = Some(level);
+        self.save_settings();
+    }
+
+    fn print_log_level(&self) {
+        let written = self.log_file.as_ref().map_or(String::new(), |log_file| tr_args("log.written_to", &[&log_file.path().display()]));
+        println!("{}", tr_args("log.level", &[&self.log_level.name(), &written]));
+    }
+
+    // Logs an operator control action and records it in the audit trail and historian.
+    // An emergency shutdown is logged as an error, everything else as information. The
+    // console shows `shown`, in its language; the records get `recorded`, in English.
+    fn log_action_shown(&mut self, action: AuditAction, shown: &str, recorded: &str) {
+        let color = if action == AuditAction::EmergencyShutdown { StatusColor::Red } else { StatusColor::Normal };
+        self.log_colored(color, shown);
+        self.audit.record(&self.user, action, recorded);
+        if action.is_change() {
+            self.revision.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(historian) = &self.historian {
+            historian.record_action(recorded);
+        }
+    }
+
+    // Records a control action on one device, naming it when there are several
+    fn log_device_action_shown(&mut self, index: usize, action: AuditAction, shown: &str, recorded: &str) {
+        let label = self.device_label(index);
+        self.log_action_shown(action, &format!("{}{}", label, shown), &format!("{}{}", label, recorded));
+    }
+
+    // The device operator commands act on
+    fn device(&self) -> &Device {
+        &self.devices[self.selected]
+    }
+
+    fn device_mut(&mut self) -> &mut Device {
+        &mut self.devices[self.selected]
+    }
+
+    // Put in front of alarm and log lines, so a line says which device it is about
+    fn device_label(&self, index: usize) -> String {
+        if self.devices.len() > 1 {
+            format!("[{}] ", self.devices[index].name)
+        } else {
+            String::new()
+        }
+    }
+
+    // Name limits are stored under in settings.json; the first device's channels keep
+    // their plain names, so a settings file from a single-device setup still applies
+    fn settings_key(&self, index: usize, channel: &str) -> String {
+        match index {
+            0 => channel.to_string(),
+            _ => format!("{}/{}", self.devices[index].name, channel),
+        }
+    }
+
+    // Locks the shared readings for an operator command. A poisoned lock means the sensor
+    // thread panicked mid-update; the command is refused with the error panel rather than
+    // silently doing nothing.
+    fn lock_sensors(&self) -> Option<MutexGuard<'_, SensorData>> {
+        self.lock_device(self.selected)
+    }
+
+    fn lock_device(&self, index: usize) -> Option<MutexGuard<'_, SensorData>> {
+        match self.devices[index].lock_data() {
+            Ok(data) => Some(data),
+            Err(_) => {
+                print_sensor_data_lost();
+                None
+            }
+        }
+    }
+
+    // A copy to render from, so the lock is held only for the clone
+    fn read_sensors(&self) -> Option<SensorData> {
+        self.lock_sensors().map(|data| data.clone())
+    }
+
+    fn print_status(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+
+        let device = self.device();
+        println!("\n{}", tr_args("status.title", &[&device.name, &device.simulation.seed]));
+        if self.devices.len() > 1 {
+            println!("{}", tr_args("status.device", &[&(self.selected + 1), &self.devices.len()]));
+        }
+        print_colored(device.mode.color(), &tr_args("status.mode", &[&device.mode.label()]));
+        match &self.session {
+            Some(session) => println!("{}", tr_args("status.user", &[&session.name, &session.role, &format_span(self.session_timeout)])),
+            None if !self.accounts.is_empty() => print_colored(StatusColor::Yellow, tr("status.view_only")),
+            None => {}
+        }
+        for toast in self.toasts.visible() {
+            print_toast(toast);
+        }
+        let last_update = data.last_update.map_or(tr("status.never").to_string(), |time| {
+            let age = SystemTime::now().duration_since(time).unwrap_or_default();
+            tr_args("status.age", &[&format_time(time), &decimal(age.as_secs_f32(), 1)])
+        });
+        let rate = device.history.lock().ok().and_then(|history| history.update_rate());
+        let rate = rate.map_or("--".to_string(), |rate| format!("{} Hz", decimal(rate, 1)));
+        println!("{}", tr_args("status.last_update", &[&last_update, &rate, &device.simulation_thread.update_interval_ms()]));
+        let stale = data.staleness(SystemTime::now(), device.update_interval());
+        // The health comes from recent polls; the connection says why the last one failed
+        let (failure, retry) = match &data.connection {
+            ConnectionStatus::Offline { reason, retry_at } => {
+                let retry_in = retry_at.saturating_duration_since(Instant::now());
+                let retry = if retry_in.is_zero() { String::new() } else { format!(" ({})", tr_args("status.retrying", &[&retry_in.as_secs_f32().ceil()])) };
+                (Some(reason.as_str()), retry)
+            }
+            _ => (None, String::new()),
+        };
+        match (&data.connection, data.link.health()) {
+            (ConnectionStatus::Connecting, _) => print_colored(StatusColor::Yellow, &tr_args("status.connecting", &[&device.source_name])),
+            (_, LinkHealth::Offline) => {
+                print_colored(StatusColor::Red, &tr_args("status.plc_offline", &[&retry, &failure.unwrap_or(tr("status.no_response"))]));
+            }
+            (_, LinkHealth::Degraded) => {
+                let reason = data.link.degraded_reason().unwrap_or_default();
+                let last = failure.map_or(String::new(), |failure| tr_args("status.last_poll", &[&failure, &retry]));
+                print_colored(StatusColor::Yellow, &tr_args("status.degraded", &[&device.source_name, &reason, &last]));
+            }
+            (_, LinkHealth::Connected) if stale.is_some() => print_colored(StatusColor::Yellow, &tr_args("status.not_updating", &[&device.source_name])),
+            (_, LinkHealth::Connected) => print_colored(StatusColor::Green, &tr_args("status.online", &[&device.source_name])),
+        }
+        if data.link.polls > 0 {
+            println!("{}", tr_args("status.link", &[&link_summary(&data.link), &data.link.sparkline()]));
+        }
+        if let Some(mqtt) = &self.mqtt {
+            match mqtt.status() {
+                MqttStatus::Connecting => print_colored(StatusColor::Yellow, &tr_args("status.mqtt_connecting", &[&mqtt.broker()])),
+                MqttStatus::Connected => print_colored(StatusColor::Green, &tr_args("status.mqtt_connected", &[&mqtt.broker()])),
+                MqttStatus::Disconnected { reason } => print_colored(StatusColor::Red, &tr_args("status.mqtt_disconnected", &[&mqtt.broker(), &reason])),
+            }
+        }
+        if let Some(api) = &self.api {
+            println!("{}", tr_args("status.api", &[&api.address(), &api.websocket_clients()]));
+        }
+        if let Some(historian) = &self.historian {
+            match historian.heartbeat() {
+                Some(heartbeat) => println!("{}", tr_args("status.historian_heartbeat", &[&historian.directory().display(), &historian.retention_days(), &heartbeat.as_secs()])),
+                None => println!("{}", tr_args("status.historian", &[&historian.directory().display(), &historian.retention_days()])),
+            }
+        }
+        if let Some(line) = self.scenario_status() {
+            println!("{}", line);
+        }
+        if let Some(line) = self.replay_status() {
+            println!("{}", line);
+        }
+        self.print_alarm_banner();
+        let forces = self.forces().len();
+        if forces > 0 {
+            print_colored(StatusColor::Blue, &tr_args("status.forces", &[&forces]));
+        }
+        if let Some(age) = stale {
+            print_colored(StatusColor::Yellow, &tr_args("status.stale", &[&decimal(age.as_secs_f32(), 0)]));
+        }
+        // Faulted channels are flagged so trainees can tell a bad instrument from a bad process
+        let fault_tag = |target| data.fault_on(target).map_or(String::new(), |fault| format!("  ⚠ {}", fault.kind));
+        let width = data.channels.iter().map(Channel::label).chain(data.motors.iter().map(Motor::label)).map(|label| label.chars().count()).max().unwrap_or(0);
+        for kind in ChannelKind::ALL {
+            if data.channels_of(kind).next().is_none() {
+                continue;
+            }
+            println!("{}:", kind.group_name());
+            for (i, channel) in data.channels_of(kind).enumerate() {
+                // A forced value stands out whatever else is going on
+                let color = match (channel.forced, stale) {
+                    (Some(_), _) => StatusColor::Blue,
+                    (None, Some(_)) => StatusColor::Gray,
+                    (None, None) => channel.alarm.level().into(),
+                };
+                let forced_tag = match channel.forced {
+                    Some(_) => format!("  [{}]", tr_args("status.forced", &[&self.units.format(kind, channel.measured())])),
+                    None => String::new(),
+                };
+                let reading = self.units.format(kind, channel.value);
+                let line = if self.gauges {
+                    let gauge = render_gauge(channel.value, &channel.limits, self.units.get(kind));
+                    format!("  {:<width$} {:>11} {}", channel.label(), reading, gauge)
+                } else {
+                    format!("  {}: {}", channel.label(), reading)
+                };
+                print_colored(color, &format!("{}{}{}", line, forced_tag, fault_tag(FaultTarget::channel(kind, i))));
+            }
+        }
+        if self.gauges {
+            println!("{}", tr_args("status.gauge_legend", &[&GAUGE_MARKER]));
+        }
+        println!("{}", tr("status.motors"));
+        let due: Vec<bool> = match device.maintenance.lock() {
+            Ok(maintenance) => (0..data.motors.len()).map(|i| maintenance.is_due(i)).collect(),
+            Err(_) => Vec::new(),
+        };
+        for (i, motor) in data.motors.iter().enumerate() {
+            let line = if self.gauges {
+                format!(
+                    "  {:<width$} {:>7} RPM {} {} ({}){}",
+                    motor.label(),
+                    motor.speed,
+                    render_speed_gauge(motor),
+                    motor_status(motor),
+                    tr_args("status.motor_load", &[&decimal(motor.current, 1), &decimal(data.supply.power(motor.current), 1), &motor.setpoint]),
+                    fault_tag(FaultTarget::Motor(i))
+                )
+            } else {
+                format!(
+                    "  {}: {} ({} RPM, {}){}",
+                    motor.label(),
+                    motor_status(motor),
+                    motor.speed,
+                    tr_args("status.motor_load", &[&decimal(motor.current, 1), &decimal(data.supply.power(motor.current), 1), &motor.setpoint]),
+                    fault_tag(FaultTarget::Motor(i))
+                )
+            };
+            if motor.overloaded {
+                print_colored(StatusColor::Red, &tr_args("status.overloaded", &[&line, &(i + 1)]));
+            } else if due.get(i) == Some(&true) {
+                print_colored(StatusColor::Yellow, &format!("{}  🔧 {}", line, tr("status.service_due")));
+            } else {
+                println!("{}", line);
+            }
+        }
+        if self.gauges {
+            println!("{}", tr_args("status.speed_gauge_legend", &[&GAUGE_MARKER, &RATED_MOTOR_SPEED]));
+        }
+        let (power, today) = self.device_power(self.selected);
+        println!("{}", tr_args("status.power", &[&decimal(power, 1), &decimal(today as f32, 1)]));
+        if self.devices.len() > 1 {
+            let (power, today) = (0..self.devices.len()).map(|index| self.device_power(index)).fold((0.0, 0.0), |(power, today), device| (power + device.0, today + device.1));
+            println!("{}", tr_args("status.facility_power", &[&decimal(power, 1), &decimal(today as f32, 1), &self.devices.len()]));
+        }
+        if let Some(control) = &data.control_loop {
+            println!("{}", tr_args("status.loop", &[
+                &control.pid.mode,
+                &self.units.temperature.format(control.pid.setpoint),
+                &self.units.temperature.format(data.loop_process_value(control)),
+                &decimal(control.pid.output, 0),
+                &data.motors[control.motor].label(),
+            ]));
+        }
+        self.print_interlocks(&data);
+        if let Some(ambient) = &data.ambient {
+            let held = |text: String, held: bool| if held { tr_args("status.held", &[&text]) } else { text };
+            println!("{}", tr_args("status.ambient", &[
+                &held(self.units.temperature.format(ambient.temperature), ambient.temperature_held),
+                &held(self.units.pressure.format(ambient.pressure), ambient.pressure_held),
+            ]));
+        }
+        if device.simulation_thread.is_paused() {
+            print_colored(StatusColor::Yellow, tr("status.paused"));
+        }
+        if data.frozen {
+            print_colored(StatusColor::Yellow, tr("status.frozen"));
+        }
+        if data.emergency_shutdown {
+            print_colored(StatusColor::Red, tr("status.estop_active"));
+        } else if data.estop_cleared {
+            print_colored(StatusColor::Yellow, tr("status.estop_cleared"));
+        } else if !device.restart_queue.is_empty() {
+            print_colored(StatusColor::Yellow, &tr_args("status.restarting", &[&device.restart_queue.len()]));
+        }
+    }
+
+    // Records each analog channel's transitions into and out of the alarm band, after the
+    // sensor thread's hysteresis and debounce; called once per tick of the main loop for each device.
+    // Alarms are raised under the device label, so equal channel names on two devices stay apart.
+    fn poll_alarms(&mut self, index: usize) {
+        // Held off after a source switch; what is still in alarm afterwards is raised then
+        if self.devices[index].alarm_grace_until.is_some() {
+            return;
+        }
+        let (readings, motors, overload_time, interlocks) = match self.devices[index].sensor_data.lock() {
+            Ok(data) => (data.channels.clone(), data.motors.clone(), data.overload_time, data.interlocks.clone()),
+            Err(_) => return,
+        };
+        // Whether each motor is over the limit, after the sensor thread's deadband, with its RMS
+        let (vibration, vibration_limit) = match self.devices[index].vibration.lock() {
+            Ok(vibration) => ((0..motors.len()).map(|i| (vibration.in_alarm(i), vibration.analysis(i).map_or(0.0, |analysis| analysis.rms))).collect(), vibration.limit()),
+            Err(_) => (Vec::new(), 0.0),
+        };
+        let label = self.device_label(index);
+        let device = &mut self.devices[index];
+        device.alarm_levels.resize(readings.len(), AlarmLevel::Normal);
+        device.rate_levels.resize(readings.len(), AlarmLevel::Normal);
+        device.motor_overloads.resize(motors.len(), false);
+        device.vibration_alarms.resize(motors.len(), false);
+        device.interlock_states.resize(interlocks.len(), true);
+
+        for (i, reading) in readings.iter().enumerate() {
+            let level = reading.alarm.level();
+            let previous = std::mem::replace(&mut self.devices[index].alarm_levels[i], level);
+            if level == previous {
+                continue;
+            }
+            if level == AlarmLevel::Warning && previous == AlarmLevel::Normal {
+                if let Some(annunciator) = &mut self.annunciator {
+                    annunciator.sound(Tone::Warning);
+                }
+                // Not shown on the console, but counted in the shift summary
+                if let Some(historian) = &self.historian {
+                    historian.record_alarm(&format!("WARNING {}{}: {}", label, reading.label(), self.units.format(reading.kind, reading.value)));
+                }
+            }
+
+            let message = if level == AlarmLevel::Alarm {
+                let limit = if reading.value <= reading.limits.low_alarm { reading.limits.low_alarm } else { reading.limits.high_alarm };
+                let source = format!("{}{}", label, reading.label());
+                let message = if reading.value.is_nan() {
+                    tr_args("alarm.no_reading", &[&source])
+                } else {
+                    tr_args("alarm.limit", &[&source, &self.units.format(reading.kind, reading.value), &self.units.format(reading.kind, limit)])
+                };
+                // Raised at the sample that crossed the limit, for working out the first-out
+                let raised_at = reading.alarm.changed_at().unwrap_or_else(SystemTime::now);
+                let id = self.alarms.raise_at(&format!("{}{}", label, reading.name), message.clone(), raised_at);
+                format!("ALARM #{} {}", id, message)
+            } else if previous == AlarmLevel::Alarm {
+                self.alarms.clear(&format!("{}{}", label, reading.name));
+                format!("CLEARED {}{}: {}", label, reading.label(), self.units.format(reading.kind, reading.value))
+            } else {
+                continue;
+            };
+
+            self.report_alarm(level.into(), &message);
+        }
+
+        // Rate-of-change alarms are listed apart from the channel's limit alarm; warnings are only logged
+        for (i, reading) in readings.iter().enumerate() {
+            let level = reading.rate.level();
+            let previous = std::mem::replace(&mut self.devices[index].rate_levels[i], level);
+            if level == previous {
+                continue;
+            }
+            let source = format!("{}{} rate", label, reading.name);
+            let unit = self.units.get(reading.kind);
+            let threshold = reading.rate_limit.limit.map_or("--".to_string(), |limit| format_rate(unit, limit));
+            let message = match (level, reading.rate.rate()) {
+                (AlarmLevel::Normal, _) => {
+                    self.alarms.clear(&source);
+                    format!("CLEARED {}{}: {}", label, reading.label(), tr_args("alarm.rate_cleared", &[&threshold]))
+                }
+                (level, rate) => {
+                    let rate = rate.unwrap_or_default();
+                    let key = if rate >= 0.0 { "alarm.rate_rising" } else { "alarm.rate_falling" };
+                    let message = tr_args(key, &[&format!("{}{}", label, reading.label()), &format_rate(unit, rate.abs()), &threshold]);
+                    if level == AlarmLevel::Warning {
+                        if let Some(annunciator) = &mut self.annunciator {
+                            annunciator.sound(Tone::Warning);
+                        }
+                        format!("WARNING {}", message)
+                    } else {
+                        let raised_at = reading.rate.changed_at().unwrap_or_else(SystemTime::now);
+                        let id = self.alarms.raise_at(&source, message.clone(), raised_at);
+                        format!("ALARM #{} {}", id, message)
+                    }
+                }
+            };
+            self.report_alarm(level.into(), &message);
+        }
+
+        // A motor tripped by its overload relay stays in alarm until the relay is reset
+        for (i, motor) in motors.iter().enumerate() {
+            if motor.overloaded == std::mem::replace(&mut self.devices[index].motor_overloads[i], motor.overloaded) {
+                continue;
+            }
+            let message = if motor.overloaded {
+                let message = tr_args("alarm.overload", &[&format!("{}{}", label, motor.label()), &decimal(motor.full_load_amps, 1), &overload_time.as_secs()]);
+                let id = self.alarms.raise(&format!("{}{}", label, motor.name), message.clone());
+                format!("ALARM #{} {} {}", id, message, tr_args("alarm.overload_hint", &[&(i + 1)]))
+            } else {
+                self.alarms.clear(&format!("{}{}", label, motor.name));
+                format!("CLEARED {}{}: {}", label, motor.label(), tr("alarm.overload_reset"))
+            };
+
+            self.report_alarm(if motor.overloaded { StatusColor::Red } else { StatusColor::Green }, &message);
+        }
+
+        for (i, (alarm, rms)) in vibration.into_iter().enumerate() {
+            if alarm == std::mem::replace(&mut self.devices[index].vibration_alarms[i], alarm) {
+                continue;
+            }
+            let source = format!("{}{} vibration", label, motors[i].name);
+            let message = if alarm {
+                let message = tr_args("alarm.vibration", &[&format!("{}{}", label, motors[i].label()), &decimal(rms, 2), &decimal(vibration_limit, 1)]);
+                let id = self.alarms.raise(&source, message.clone());
+                format!("ALARM #{} {}", id, message)
+            } else {
+                self.alarms.clear(&source);
+                format!("CLEARED {}{}: {}", label, motors[i].label(), tr_args("alarm.vibration_cleared", &[&decimal(rms, 1)]))
+            };
+            self.report_alarm(if alarm { StatusColor::Red } else { StatusColor::Green }, &message);
+        }
+
+        // The sensor thread has already stopped the motors a dropped interlock protects
+        for (i, interlock) in interlocks.iter().enumerate() {
+            if interlock.satisfied == std::mem::replace(&mut self.devices[index].interlock_states[i], interlock.satisfied) {
+                continue;
+            }
+            let message = if interlock.satisfied {
+                self.alarms.clear(&format!("{}{}", label, interlock.name));
+                format!("CLEARED {}{}: {}", label, interlock.name, tr_args("alarm.interlock_satisfied", &[&interlock.condition]))
+            } else if interlock.bypass.is_some() {
+                let message = tr_args("alarm.interlock_bypassed", &[&format!("{}{}", label, interlock.name), &interlock.condition]);
+                let id = self.alarms.raise(&format!("{}{}", label, interlock.name), message.clone());
+                format!("ALARM #{} {}", id, message)
+            } else {
+                let stopped: Vec<String> = interlock.stopped.iter().filter_map(|motor| motors.get(*motor)).map(Motor::label).collect();
+                let stopped = if stopped.is_empty() { tr("alarm.no_motors_running").to_string() } else { tr_args("alarm.motors_stopped", &[&stopped.join(", ")]) };
+                let message = tr_args("alarm.interlock_dropped", &[&format!("{}{}", label, interlock.name), &interlock.condition, &stopped]);
+                let id = self.alarms.raise(&format!("{}{}", label, interlock.name), message.clone());
+                format!("ALARM #{} {}", id, message)
+            };
+
+            self.report_alarm(if interlock.satisfied { StatusColor::Green } else { StatusColor::Red }, &message);
+        }
+    }
+
+    // Shuts the device down once its sensor thread reports a channel held beyond its trip
+    // limit; called once per tick of the main loop for each device.
+    fn poll_trip(&mut self, index: usize) {
+        if !self.devices[index].mode.auto_trip_enabled() {
+            return;
+        }
+        let trip = match self.devices[index].sensor_data.lock() {
+            Ok(data) if !data.emergency_shutdown => data.auto_trip.clone(),
+            _ => return,
+        };
+        if let Some(trip) = trip {
+            let label = self.device_label(index);
+            self.log_colored(StatusColor::Red, &tr_args("trip.auto", &[&label, &trip.shown(self.units), &self.units.format(trip.kind, trip.value)]));
+            // The reason goes into the audit trail and the trip record, so it stays in English
+            let _ = self.execute(Command::EmergencyShutdown { device: index, reason: format!("automatic trip, {}", trip.describe(self.units)) }, &Actor::System);
+        }
+    }
+
+    // Headless there is nobody to acknowledge alarms, so the alarm lines say it all
+    fn print_alarm_banner(&self) {
+        if self.headless {
+            return;
+        }
+        for (index, device) in self.devices.iter().enumerate() {
+            if let Some(reason) = &device.acquisition_halted {
+                print_colored(StatusColor::Red, &tr_args("banner.halted", &[&self.device_label(index), reason]));
+            }
+            if let Some(trip) = device.sensor_data.lock().ok().and_then(|data| data.auto_trip.clone()) {
+                print_colored(StatusColor::Red, &tr_args("banner.trip", &[&self.device_label(index), &trip.shown(self.units)]));
+            }
+            if let Some(alarm) = &device.first_out {
+                print_colored(StatusColor::Red, &tr_args("banner.first_out", &[&self.device_label(index), &alarm.id, &alarm.message, &format_time_precise(alarm.raised_at)]));
+            }
+        }
+        let unacked = self.alarms.unacknowledged();
+        if unacked > 0 {
+            print_colored(StatusColor::Red, &tr_args("banner.unacknowledged", &[&unacked]));
+        }
+        if self.annunciator.as_ref().is_some_and(Annunciator::is_sounding) {
+            print_colored(StatusColor::Red, tr("banner.horn"));
+        }
+    }
+
+    fn print_alarms(&self) {
+        println!("\n{}", tr("alarms.title"));
+        let mut empty = true;
+        for alarm in self.alarms.iter() {
+            empty = false;
+            let color = if alarm.is_active() { StatusColor::Red } else { StatusColor::Yellow };
+            let cleared = alarm.cleared_at.map_or(String::new(), |cleared| tr_args("alarms.cleared_at", &[&format_time(cleared)]));
+            let first_out = if alarm.first_out { format!("  ▶ {}", tr("alarms.first_out")) } else { String::new() };
+            print_colored(color, &format!("  #{:<4} {:<17} {}{}  {}{}", alarm.id, alarm.state(), tr_args("alarms.raised", &[&format_time(alarm.raised_at)]), cleared, alarm.message, first_out));
+        }
+        if empty {
+            println!("  {}", tr("alarms.none"));
+        }
+    }
+
+    fn acknowledge_alarm(&mut self, target: &str) {
+        if target == "all" {
+            let count = self.alarms.acknowledge_all();
+            self.log_action_shown(AuditAction::AlarmAck, &tr_args("alarms.acknowledged_all", &[&count]), &format!("Acknowledged {} alarm(s)", count));
+            return;
+        }
+
+        match target.trim_start_matches('#').parse() {
+            Ok(id) if self.alarms.acknowledge(id) => {
+                self.log_action_shown(AuditAction::AlarmAck, &tr_args("alarms.acknowledged", &[&id]), &format!("Alarm #{} acknowledged", id));
+            }
+            Ok(id) => println!("{}", tr_args("alarms.not_awaiting_ack", &[&id])),
+            Err(_) => println!("{}", tr("alarms.usage_ack")),
+        }
+    }
+
+    fn print_alarm_log(&self) {
+        println!("\n{}", tr_args("alarms.log_title", &[&self.alarm_log.len()]));
+        for entry in &self.alarm_log {
+            println!("{}", entry);
+        }
+    }
+
+    fn print_limits(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+
+        println!("\n{}", tr("limits.title"));
+        let width = data.channels.iter().map(|channel| channel.label().chars().count()).max().unwrap_or(0).max(22);
+        println!(
+            "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+            tr("column.channel"), tr("column.low_trip"), tr("column.low_alarm"), tr("column.low_warning"),
+            tr("column.high_warning"), tr("column.high_alarm"), tr("column.high_trip"), tr("column.rate"), tr("column.unit")
+        );
+        for channel in &data.channels {
+            let unit = self.units.get(channel.kind);
+            let trip = |limit: Option<f32>| limit.map_or("--".to_string(), |limit| unit.number(limit));
+            let rate = channel.rate_limit.limit.map_or("--".to_string(), |limit| decimal(unit.from_si(limit) - unit.from_si(0.0), unit.decimals()));
+            let limits = channel.limits;
+            println!(
+                "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+                channel.label(), trip(limits.low_trip), unit.number(limits.low_alarm), unit.number(limits.low_warning),
+                unit.number(limits.high_warning), unit.number(limits.high_alarm), trip(limits.high_trip), rate, unit
+            );
+        }
+        for kind in ChannelKind::ALL {
+            if let Some(channel) = data.channels_of(kind).next() {
+                let unit = self.units.get(kind);
+                let filter = channel.filter;
+                let deadband = unit.from_si(filter.deadband) - unit.from_si(0.0);
+                println!("{}", tr_args("limits.debounce", &[
+                    &kind.label(),
+                    &filter.raise_samples,
+                    &filter.clear_samples,
+                    &format!("{}{}", decimal(deadband, unit.decimals()), unit),
+                ]));
+                if data.channels_of(kind).any(|channel| channel.rate_limit.limit.is_some()) {
+                    let rate = channel.rate_limit;
+                    let severity = if rate.severity == AlarmLevel::Warning { tr("limits.a_warning") } else { tr("limits.an_alarm") };
+                    println!("{}", tr_args("limits.rate_change", &[&kind.label(), &rate.window.as_secs_f32(), &severity]));
+                }
+            }
+        }
+    }
+
+    // Min, max, mean and standard deviation of each channel since the last reset
+    fn print_statistics(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let since = data.channels.iter().map(|channel| channel.stats.since).min().unwrap_or_else(SystemTime::now);
+        println!("\n{}", tr_args("stats.title", &[&format_datetime(since)]));
+        let width = data.channels.iter().map(|channel| channel.label().chars().count()).max().unwrap_or(0).max(22);
+        println!(
+            "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>8} {:>8}  {}",
+            tr("column.channel"), tr("column.min"), tr("column.max"), tr("column.mean"), tr("column.std_dev"), tr("column.samples"), tr("column.ignored"), tr("column.unit")
+        );
+        for channel in &data.channels {
+            let unit = self.units.get(channel.kind);
+            let stats = &channel.stats;
+            let number = |value: Option<f32>| value.map_or("--".to_string(), |value| unit.number(value));
+            println!(
+                "{:<width$} {:>10} {:>10} {:>10} {:>10} {:>8} {:>8}  {}",
+                channel.label(),
+                number(stats.min().map(|(min, _)| min)),
+                number(stats.max().map(|(max, _)| max)),
+                number(stats.mean()),
+                stats.std_dev().map_or("--".to_string(), |std_dev| format!("{:.*}", unit.decimals() + 1, unit.from_si(std_dev) - unit.from_si(0.0))),
+                stats.count(),
+                stats.ignored(),
+                unit
+            );
+        }
+        println!("{}", tr("stats.ignored_note"));
+        match self.next_shift_change(SystemTime::now()) {
+            Some(next) => println!("{}", tr_args("stats.next_reset", &[&&format_time(next)[..5]])),
+            None => println!("{}", tr("stats.no_reset")),
+        }
+    }
+
+    // Everything about one channel of the selected device; with `watch` it is redrawn
+    // every second until the next line of input
+    fn show_channel_detail(&mut self, kind: &str, number: usize, watch: bool) {
+        let kind = match ChannelKind::parse(kind) {
+            Ok(kind) => kind,
+            Err(reason) => return println!("{}", reason),
+        };
+        if !self.print_channel_detail(self.selected, kind, number) {
+            return;
+        }
+        if watch {
+            println!("{}", tr("channel.watching"));
+            self.watching = Some(ChannelWatch { device: self.selected, kind, number, next: Instant::now() + Duration::from_secs(1) });
+        }
+    }
+
+    fn poll_watch(&mut self) {
+        let (device, kind, number) = match &mut self.watching {
+            Some(watch) if Instant::now() >= watch.next => {
+                watch.next += Duration::from_secs(1);
+                (watch.device, watch.kind, watch.number)
+            }
+            _ => return,
+        };
+        if !self.print_channel_detail(device, kind, number) {
+            self.watching = None;
+        }
+    }
+
+    // The detail view of channel `number` (from 1); false when there is no such channel
+    fn print_channel_detail(&self, index: usize, kind: ChannelKind, number: usize) -> bool {
+        let channel = match self.lock_device(index).and_then(|data| data.channel(kind, number.wrapping_sub(1)).cloned()) {
+            Some(channel) => channel,
+            None => {
+                println!("{}", tr_args("channel.no_channel", &[&kind.label(), &number]));
+                return false;
+            }
+        };
+        let now = SystemTime::now();
+        let since = now.checked_sub(CHANNEL_DETAIL_WINDOW).unwrap_or(UNIX_EPOCH);
+        let samples = match error::lock(&self.devices[index].history, devices::HISTORY) {
+            Ok(history) => history.window(kind, number - 1, since),
+            Err(_) => Vec::new(),
+        };
+        let unit = self.units.get(kind);
+        let label = self.device_label(index);
+        let limits = &channel.limits;
+        let level = channel.alarm.level();
+
+        println!("\n{}", tr_args("channel.title", &[&label, &channel.label(), &kind, &number, &format_time(now)]));
+        if !channel.tagging.tag.is_empty() {
+            println!("{}", tr_args("channel.tag", &[&channel.tagging.tag]));
+        }
+        println!("{}", tr_args("channel.name", &[&channel.name]));
+        if !channel.tagging.description.is_empty() {
+            println!("{}", tr_args("channel.description", &[&channel.tagging.description]));
+        }
+        if !channel.tagging.asset.is_empty() {
+            println!("{}", tr_args("channel.asset_id", &[&channel.tagging.asset]));
+        }
+        let state = match level {
+            AlarmLevel::Normal => tr("channel.state_normal"),
+            AlarmLevel::Warning => tr("channel.state_warning"),
+            AlarmLevel::Alarm => tr("channel.state_alarm"),
+        };
+        print_colored(level.into(), &tr_args("channel.value", &[&unit.format(channel.value), &state]));
+        if let Some(forced) = channel.forced {
+            print_colored(StatusColor::Blue, &tr_args("channel.forced_measured", &[&unit.format(forced), &unit.format(channel.measured())]));
+        }
+        match channel.calibration.is_identity() {
+            true => println!("{}", tr_args("channel.raw_calibration", &[&unit.format(channel.raw)])),
+            false => println!("{}", tr_args("channel.raw_offset", &[&unit.format(channel.raw), &format_offset(unit, channel.calibration.offset), &decimal(channel.calibration.gain, 3)])),
+        }
+        println!("{}", tr_args("channel.alarm_limits", &[&unit.format(limits.low_alarm), &unit.format(limits.high_alarm)]));
+        println!("{}", tr_args("channel.warnings", &[&unit.format(limits.low_warning), &unit.format(limits.high_warning)]));
+        let trip = |trip: Option<f32>| trip.map_or(tr("channel.trip_none").to_string(), |trip| unit.format(trip));
+        println!("{}", tr_args("channel.trips", &[&trip(limits.low_trip), &trip(limits.high_trip)]));
+        println!("{}", tr_args("channel.hysteresis", &[
+            &format_offset(unit, channel.filter.deadband).trim_start_matches('+'),
+            &channel.filter.raise_samples,
+            &channel.filter.clear_samples,
+        ]));
+        let rate = channel.rate.rate().map_or("--".to_string(), |rate| format_rate(unit, rate));
+        match channel.rate_limit.limit {
+            Some(limit) => println!("{}", tr_args("channel.rate", &[&rate, &channel.rate_limit.window.as_secs_f32(), &format_rate(unit, limit)])),
+            None => println!("{}", tr_args("channel.rate_no_alarm", &[&rate, &channel.rate_limit.window.as_secs_f32()])),
+        }
+        let source = format!("{}{}", label, channel.name);
+        match self.alarms.iter().filter(|alarm| alarm.source == source).last() {
+            Some(alarm) => println!("{}", tr_args("channel.alarm_raised", &[&alarm.id, &alarm.state(), &alarm.message, &format_time(alarm.raised_at)])),
+            None => println!("{}", tr("channel.alarm_none")),
+        }
+        let stats = &channel.stats;
+        let number_of = |value: Option<f32>| value.map_or("--".to_string(), |value| unit.format(value));
+        println!("{}", tr_args("channel.since", &[
+            &format_time(stats.since),
+            &number_of(stats.min().map(|(min, _)| min)),
+            &number_of(stats.max().map(|(max, _)| max)),
+            &number_of(stats.mean()),
+            &stats.count(),
+        ]));
+        let values: Vec<f32> = samples.iter().map(|sample| sample.value).filter(|value| value.is_finite()).collect();
+        let range = match (values.iter().copied().reduce(f32::min), values.iter().copied().reduce(f32::max)) {
+            (Some(low), Some(high)) => format!("{} .. {}", unit.format(low), unit.format(high)),
+            _ => tr("channel.no_readings").to_string(),
+        };
+        println!("{}", tr_args("channel.last_min", &[&(CHANNEL_DETAIL_WINDOW.as_secs() / 60), &history::sparkline(&samples, since, now, TREND_WIDTH), &range]));
+        true
+    }
+
+    fn print_channel_statistics(&self, kind: &str, channel: usize) {
+        let (kind, _, channel) = match self.trend_channel(kind, channel) {
+            Some(found) => found,
+            None => return,
+        };
+        let unit = self.units.get(kind);
+        let stats = &channel.stats;
+        let extreme = |extreme: Option<(f32, SystemTime)>| {
+            extreme.map_or("--".to_string(), |(value, time)| tr_args("stats.extreme", &[&unit.format(value), &format_datetime(time)]))
+        };
+        println!("\n{}", tr_args("stats.channel_title", &[&channel.label()]));
+        println!("{}", tr_args("stats.since", &[&format_datetime(stats.since)]));
+        println!("{}", tr_args("stats.current", &[&unit.format(channel.value)]));
+        if !channel.calibration.is_identity() {
+            println!("{}", tr_args("stats.raw_before", &[&unit.format(channel.raw)]));
+        }
+        println!("{}", tr_args("stats.minimum", &[&extreme(stats.min())]));
+        println!("{}", tr_args("stats.maximum", &[&extreme(stats.max())]));
+        println!("{}", tr_args("stats.mean", &[&stats.mean().map_or("--".to_string(), |mean| unit.format(mean))]));
+        println!("{}", tr_args("stats.std_dev", &[
+            &stats.std_dev().map_or("--".to_string(), |std_dev| format!("{} {}", decimal(unit.from_si(std_dev) - unit.from_si(0.0), unit.decimals() + 1), unit)),
+        ]));
+        println!("{}", tr_args("stats.samples_ignored", &[&stats.count(), &stats.ignored()]));
+    }
+
+    fn reset_statistics(&mut self) {
+        let now = SystemTime::now();
+        if let Some(mut data) = self.lock_sensors() {
+            data.reset_statistics(now);
+        } else {
+            return;
+        }
+        self.log_device_action_shown(self.selected, AuditAction::StatisticsReset, tr("stats.reset"), "Channel statistics reset");
+    }
+
+    // Offset, gain and last calibration of every channel, with raw and calibrated readings
+    fn print_calibrations(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        println!("\n{}", tr_args("calibration.title", &[&self.calibration_file.display()]));
+        let width = data.channels.iter().map(|channel| channel.label().chars().count()).max().unwrap_or(0).max(22);
+        println!(
+            "{:<width$} {:>10} {:>10} {:>9} {:>7}  {:<10} {:<4} {}",
+            tr("column.channel"), tr("column.raw"), tr("column.calibrated"), tr("column.offset"), tr("column.gain"), tr("column.date"), tr("column.by"), tr("column.unit")
+        );
+        for channel in &data.channels {
+            let unit = self.units.get(channel.kind);
+            let calibration = &channel.calibration;
+            let line = format!(
+                "{:<width$} {:>10} {:>10} {:>9} {:>7.3}  {:<10} {:<4} {}",
+                channel.label(),
+                unit.number(channel.raw),
+                unit.number(channel.value),
+                format_offset(unit, calibration.offset),
+                calibration.gain,
+                calibration.calibrated.map_or(tr("status.never").to_string(), |calibrated| format_datetime(calibrated)[..10].to_string()),
+                calibration.technician,
+                unit
+            );
+            match self.calibration_warnings(channel.kind, calibration).is_empty() {
+                true => println!("{}", line),
+                false => print_colored(StatusColor::Yellow, &line),
+            }
+        }
+        println!("{}", tr("calibration.hint"));
+    }
+
+    fn print_channel_calibration(&self, kind: &str, channel: usize) {
+        let (kind, _, channel) = match self.trend_channel(kind, channel) {
+            Some(found) => found,
+            None => return,
+        };
+        let unit = self.units.get(kind);
+        let calibration = &channel.calibration;
+        println!("\n{}", tr_args("calibration.channel_title", &[&channel.label()]));
+        println!("{}", tr_args("calibration.raw", &[&unit.format(channel.raw)]));
+        println!("{}", tr_args("calibration.calibrated", &[&unit.format(channel.value)]));
+        println!("{}", tr_args("calibration.offset", &[&format_offset(unit, calibration.offset)]));
+        println!("{}", tr_args("calibration.gain", &[&decimal(calibration.gain, 3)]));
+        match &calibration.calibrated {
+            Some(calibrated) => println!("{}", tr_args("calibration.last_done", &[&format_datetime(*calibrated), &calibration.technician])),
+            None => println!("{}", tr("calibration.last_done_never")),
+        }
+        for warning in self.calibration_warnings(kind, calibration) {
+            print_colored(StatusColor::Yellow, &format!("  ⚠ {}", warning));
+        }
+    }
+
+    // What looks implausible about a calibration; it is applied all the same
+    fn calibration_warnings(&self, kind: ChannelKind, calibration: &Calibration) -> Vec<String> {
+        let unit = self.units.get(kind);
+        let max_offset = match kind {
+            ChannelKind::Temperature => self.temperature_max_offset,
+            ChannelKind::Pressure => self.pressure_max_offset,
+        };
+        let mut warnings = Vec::new();
+        if calibration.implausible_gain() {
+            warnings.push(tr_args("calibration.gain_outside", &[
+                &decimal(calibration.gain, 3),
+                &decimal(*SANE_GAIN.start(), 1),
+                &decimal(*SANE_GAIN.end(), 1),
+            ]));
+        }
+        if calibration.offset.abs() > max_offset {
+            warnings.push(tr_args("calibration.offset_beyond", &[
+                &format_offset(unit, calibration.offset),
+                &format_offset(unit, max_offset).trim_start_matches('+'),
+            ]));
+        }
+        warnings
+    }
+
+    // Anything not given keeps its current value. Applied to the current reading at once
+    // and stored under the channel's name.
+    fn calibrate_channel(&mut self, kind: &str, channel: usize, args: &[&str]) {
+        let kind = match ChannelKind::parse(kind) {
+            Ok(kind) => kind,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        let unit = self.units.get(kind);
+        let (offset, gain, technician) = match parse_calibration(args) {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                println!("{}", reason);
+                println!("{}", tr("calibration.usage_calibrate"));
+                return;
+            }
+        };
+        let result = match self.lock_sensors() {
+            Some(mut data) => {
+                let count = data.channels_of(kind).count();
+                match data.channel_mut(kind, channel.wrapping_sub(1)) {
+                    Some(found) => {
+                        let calibration = Calibration {
+                            // Typed in the display unit, as a difference
+                            offset: offset.map_or(found.calibration.offset, |offset| unit.to_si(offset) - unit.to_si(0.0)),
+                            gain: gain.unwrap_or(found.calibration.gain),
+                            calibrated: Some(SystemTime::now()),
+                            technician,
+                        };
+                        let previous = found.calibration.clone();
+                        calibration.validate().map(|()| {
+                            found.set_calibration(calibration.clone());
+                            (found.name.clone(), found.label(), previous, calibration)
+                        })
+                    }
+                    None => Err(tr_args("channel.unknown", &[&kind.label(), &channel, &count])),
+                }
+            }
+            None => return,
+        };
+
+        match result {
+            Ok((name, label, previous, calibration)) => {
+                let shown = tr_args("calibration.done", &[
+                    &label,
+                    &calibration.technician,
+                    &format_offset(unit, calibration.offset),
+                    &decimal(calibration.gain, 3),
+                    &format_offset(unit, previous.offset),
+                    &decimal(previous.gain, 3),
+                ]);
+                let recorded = format!(
+                    "{} calibrated by {}: offset {}, gain {:.3} (was offset {}, gain {:.3})",
+                    label, calibration.technician, format_offset(unit, calibration.offset), calibration.gain, format_offset(unit, previous.offset), previous.gain
+                );
+                self.log_device_action_shown(self.selected, AuditAction::Calibration, &shown, &recorded);
+                for warning in self.calibration_warnings(kind, &calibration) {
+                    self.log_colored(StatusColor::Yellow, &tr_args("calibration.check", &[&label, &warning]));
+                }
+                let key = self.settings_key(self.selected, &name);
+                self.calibrations.set(&key, calibration);
+                if let Err(e) = self.calibrations.save(&self.calibration_file) {
+                    self.log_colored(StatusColor::Red, &tr_args("calibration.not_saved", &[&self.calibration_file.display(), &e]));
+                }
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("calibration.rejected", &[&reason])),
+        }
+    }
+
+    // Forced channels of every device: device index, label, kind, forced and measured value
+    fn forces(&self) -> Vec<(usize, String, ChannelKind, f32, f32)> {
+        let mut forces = Vec::new();
+        for index in 0..self.devices.len() {
+            if let Some(data) = self.lock_device(index) {
+                forces.extend(data.channels.iter().filter_map(|channel| {
+                    channel.forced.map(|forced| (index, channel.label(), channel.kind, forced, channel.measured()))
+                }));
+            }
+        }
+        forces
+    }
+
+    fn print_forces(&self) {
+        let forces = self.forces();
+        if forces.is_empty() {
+            println!("{}", tr("force.none_hint"));
+            return;
+        }
+        println!("\n{}", tr_args("force.title", &[&forces.len()]));
+        for (index, label, kind, forced, measured) in &forces {
+            print_colored(StatusColor::Blue, &tr_args("force.forced_measured", &[&self.device_label(*index), &label, &self.units.format(*kind, *forced), &self.units.format(*kind, *measured)]));
+        }
+        println!("{}", tr("force.hint"));
+    }
+
+    // Puts `value` (in the display unit) in place of the channel's reading, or with None
+    // gives the reading back. Alarms, interlocks and the history see the forced value.
+    fn force_channel(&mut self, kind: &str, channel: usize, value: Option<f32>) {
+        let kind = match ChannelKind::parse(kind) {
+            Ok(kind) => kind,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        let forced = value.map(|value| self.units.get(kind).to_si(value));
+        if forced.is_some_and(|forced| !forced.is_finite()) {
+            println!("{}", tr("force.number_only"));
+            return;
+        }
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.force(kind, channel, forced).map(|(label, before)| {
+                let measured = data.channel(kind, channel - 1).map_or(f32::NAN, |channel| channel.measured());
+                (label, before, measured)
+            }),
+            None => return,
+        };
+        match result {
+            Ok((label, before, measured)) => {
+                let measured = self.units.format(kind, measured);
+                let (shown, recorded) = match (forced, before) {
+                    (Some(forced), _) => {
+                        let forced = self.units.format(kind, forced);
+                        (tr_args("force.forced", &[&label, &forced, &measured]), format!("{} FORCED to {} (measured {})", label, forced, measured))
+                    }
+                    (None, Some(before)) => {
+                        let before = self.units.format(kind, before);
+                        (tr_args("force.removed", &[&label, &before, &measured]), format!("Force removed from {} (was forced to {}, measured {})", label, before, measured))
+                    }
+                    (None, None) => return println!("{}", tr_args("force.not_forced", &[&label])),
+                };
+                self.log_device_action_shown(self.selected, AuditAction::Force, &shown, &recorded);
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("force.rejected", &[&reason])),
+        }
+    }
+
+    // Every device's forces, each removal audited on its own
+    fn clear_all_forces(&mut self) {
+        let mut cleared = Vec::new();
+        for index in 0..self.devices.len() {
+            if let Some(mut data) = self.lock_device(index) {
+                for channel in data.channels.iter_mut().filter(|channel| channel.forced.is_some()) {
+                    let before = channel.forced.unwrap_or(f32::NAN);
+                    channel.force(None);
+                    cleared.push((index, channel.label(), channel.kind, before, channel.measured()));
+                }
+            }
+        }
+        if cleared.is_empty() {
+            println!("{}", tr("force.none"));
+            return;
+        }
+        for (index, label, kind, before, measured) in cleared {
+            let (before, measured) = (self.units.format(kind, before), self.units.format(kind, measured));
+            let shown = tr_args("force.removed", &[&label, &before, &measured]);
+            let recorded = format!("Force removed from {} (was forced to {}, measured {})", label, before, measured);
+            self.log_device_action_shown(index, AuditAction::Force, &shown, &recorded);
+        }
+    }
+
+    fn print_bypasses(&self) {
+        let data = match self.lock_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        if data.interlocks.is_empty() {
+            println!("{}", tr("interlocks.none"));
+            return;
+        }
+        let now = SystemTime::now();
+        for (number, interlock) in data.interlocks.iter().enumerate() {
+            let state = if interlock.satisfied { tr("bypass.satisfied") } else { tr("bypass.not_satisfied") };
+            match &interlock.bypass {
+                Some(bypass) => print_colored(StatusColor::Yellow, &tr_args("bypass.bypassed", &[
+                    &(number + 1),
+                    &interlock.name,
+                    &interlock.condition,
+                    &state,
+                    &format_elapsed(bypass.remaining(now)),
+                    &bypass.user,
+                    &bypass.reason,
+                ])),
+                None => println!("{}", tr_args("bypass.armed", &[&(number + 1), &interlock.name, &interlock.condition, &state])),
+            }
+        }
+        println!("{}", tr_args("bypass.hint", &[&(interlocks::MAX_BYPASS.as_secs() / 60)]));
+    }
+
+    // Lets the selected device's motors run whatever interlock `number`'s condition, for
+    // `minutes`
+    fn bypass_interlock(&mut self, number: usize, minutes: &str, reason: &[&str]) {
+        let minutes = match minutes.parse::<u64>() {
+            Ok(minutes) if minutes > 0 && minutes * 60 <= interlocks::MAX_BYPASS.as_secs() => minutes,
+            _ => return println!("{}", tr_args("bypass.duration", &[&(interlocks::MAX_BYPASS.as_secs() / 60)])),
+        };
+        if reason.is_empty() {
+            println!("{}", tr_args("bypass.give_reason", &[&number, &minutes]));
+            return;
+        }
+        let reason = reason.join(" ");
+        let bypass = Bypass { until: SystemTime::now() + Duration::from_secs(minutes * 60), user: self.user.clone(), reason: reason.clone() };
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.bypass_interlock(number, bypass),
+            None => return,
+        };
+        match result {
+            Ok(name) => {
+                let shown = tr_args("bypass.done", &[&name, &minutes, &reason]);
+                let recorded = format!("Interlock {} BYPASSED for {} min - reason: {}", name, minutes, reason);
+                self.log_device_action_shown(self.selected, AuditAction::Bypass, &shown, &recorded);
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("bypass.refused", &[&reason])),
+        }
+    }
+
+    // Ends bypasses by hand: one interlock's, or with None all of the selected device's
+    fn rearm_interlocks(&mut self, number: Option<usize>) {
+        let index = self.selected;
+        let rearmed = match (self.lock_sensors(), number) {
+            (Some(mut data), Some(number)) => data.rearm_interlock(number).map(|rearmed| vec![rearmed]),
+            (Some(mut data), None) => {
+                let bypassed: Vec<usize> = (1..=data.interlocks.len()).filter(|number| data.interlocks[number - 1].bypass.is_some()).collect();
+                Ok(bypassed.into_iter().filter_map(|number| data.rearm_interlock(number).ok()).collect())
+            }
+            (None, _) => return,
+        };
+        match rearmed {
+            Ok(rearmed) if rearmed.is_empty() => println!("{}", tr("bypass.none")),
+            Ok(rearmed) => self.report_rearmed(index, rearmed, false),
+            Err(reason) => println!("{}", reason),
+        }
+    }
+
+    // Re-arms interlocks whose bypass has run out; called once per tick of the main loop
+    // for each device
+    fn poll_bypasses(&mut self, index: usize) {
+        let rearmed = match self.lock_device(index) {
+            Some(mut data) if data.interlocks.iter().any(|interlock| interlock.bypass.is_some()) => data.expire_bypasses(SystemTime::now()),
+            _ => return,
+        };
+        if !rearmed.is_empty() {
+            self.report_rearmed(index, rearmed, true);
+        }
+    }
+
+    // Tells the data source about the motors a re-armed interlock stopped, and audits it;
+    // `expired` when the bypass ran out rather than being cleared by hand
+    fn report_rearmed(&mut self, index: usize, rearmed: Vec<(String, Vec<usize>)>, expired: bool) {
+        let (shown_cause, cause) = match expired {
+            true => (tr("bypass.expired"), "bypass expired"),
+            false => (tr("bypass.cleared"), "bypass cleared by hand"),
+        };
+        for (name, stopped) in rearmed {
+            let mut shown = tr_args("bypass.rearmed", &[&name, &shown_cause]);
+            let mut recorded = format!("Interlock {} re-armed ({})", name, cause);
+            if !stopped.is_empty() {
+                for motor in &stopped {
+                    self.devices[index].simulation_thread.send_command(SourceCommand::MotorRunning { motor: *motor, running: false });
+                }
+                let labels: Vec<String> = match self.lock_device(index) {
+                    Some(data) => stopped.iter().map(|motor| data.motor_label(motor + 1)).collect(),
+                    None => Vec::new(),
+                };
+                shown.push_str(&tr_args("bypass.rearmed_stopped", &[&labels.join(", ")]));
+                recorded.push_str(&format!(" - not satisfied, stopped {}", labels.join(", ")));
+            }
+            self.log_device_action_shown(index, AuditAction::Bypass, &shown, &recorded);
+        }
+    }
+
+    // Logs each key missing from the current language once, when it is first shown
+    fn poll_translations(&mut self) {
+        for warning in i18n::take_missing_warnings() {
+            self.log_colored(StatusColor::Yellow, &warning);
+        }
+    }
+
+    // The next configured shift change after `now`
+    fn next_shift_change(&self, now: SystemTime) -> Option<SystemTime> {
+        let midnight = UNIX_EPOCH + Duration::from_secs(now.duration_since(UNIX_EPOCH).ok()?.as_secs() / DAY_SECONDS * DAY_SECONDS);
+        self.shift_changes.iter()
+            .map(|change| midnight + *change)
+            .map(|change| if change <= now { change + Duration::from_secs(DAY_SECONDS) } else { change })
+            .min()
+    }
+
+    // Resets every device's channel statistics and closes the shift at each configured
+    // shift change; called once per tick of the main loop
+    fn poll_shift_change(&mut self) {
+        let now = SystemTime::now();
+        let due = match self.next_shift_change(self.shift_checked) {
+            Some(change) if change <= now => change,
+            _ => {
+                self.shift_checked = now;
+                return;
+            }
+        };
+        self.shift_checked = now;
+        for index in 0..self.devices.len() {
+            if let Some(mut data) = self.lock_device(index) {
+                data.reset_statistics(now);
+            }
+        }
+        self.log(&tr_args("stats.shift_change", &[&&format_time(due)[..5]]));
+        self.close_shift(due, "schedule");
+    }
+
+    // 'shift close': ends the shift now instead of at the next shift change, e.g. when a
+    // batch finishes early
+    fn close_shift_now(&mut self) {
+        let now = SystemTime::now();
+        for index in 0..self.devices.len() {
+            if let Some(mut data) = self.lock_device(index) {
+                data.reset_statistics(now);
+            }
+        }
+        let started = format_datetime(self.shift_started);
+        let shown = tr_args("shift.closed_now", &[&started]);
+        self.log_action_shown(AuditAction::ShiftClose, &shown, &format!("Shift closed (started {}); channel statistics reset", started));
+        let closed_by = self.user.clone();
+        self.close_shift(now, &closed_by);
+    }
+
+    // Starts the next shift at `end` and summarises the one that ended from the historian
+    // and audit trail in the background; the outcome arrives through poll_shift_summaries
+    fn close_shift(&mut self, end: SystemTime, closed_by: &str) {
+        let start = std::mem::replace(&mut self.shift_started, end);
+        for device in &self.devices {
+            if let Ok(mut energy) = device.energy.lock() {
+                energy.begin_shift(end);
+            }
+        }
+        let directory = match &self.historian {
+            Some(historian) => historian.directory().to_path_buf(),
+            None => {
+                self.notify_warn(tr("shift.no_historian"));
+                return;
+            }
+        };
+        // The historian records the first device
+        let (channels, motors) = match self.lock_device(0) {
+            Some(data) => {
+                let channels = ChannelKind::ALL.into_iter()
+                    .flat_map(|kind| data.channels_of(kind).enumerate().map(move |(index, channel)| (kind, index, channel.label())).collect::<Vec<_>>())
+                    .collect();
+                (channels, data.motors.iter().map(Motor::label).collect())
+            }
+            None => return,
+        };
+        let request = ShiftRequest {
+            system_id: self.devices[0].name.clone(),
+            start,
+            end,
+            closed_by: closed_by.to_string(),
+            channels,
+            motors,
+            historian: directory,
+            audit: self.audit.path().to_path_buf(),
+        };
+        let reports = self.reports_directory.clone();
+        let units = self.units;
+        let stamp = file_stamp(end);
+        let tx = self.shift_tx.clone();
+        thread::spawn(move || {
+            // Lets the historian write out the shift's last samples first
+            thread::sleep(historian::FLUSH_INTERVAL * 2);
+            let result = ShiftSummary::generate(&request)
+                .and_then(|summary| summary.write_csv(&reports, &stamp, units).map(|path| (summary, path)))
+                .map_err(|e| tr_args("shift.not_written", &[&e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    fn poll_shift_summaries(&mut self) {
+        while let Ok(result) = self.shift_rx.try_recv() {
+            match result {
+                Ok((summary, path)) => {
+                    self.notify_info(&tr_args("shift.written", &[&path.display()]));
+                    self.shift_summary = Some(summary);
+                }
+                Err(message) => self.notify_error(&message),
+            }
+        }
+    }
+
+    fn print_shift_summary(&self) {
+        let summary = match &self.shift_summary {
+            Some(summary) => summary,
+            None => {
+                println!("{}", tr_args("shift.none_closed", &[&format_datetime(self.shift_started)]));
+                match self.next_shift_change(SystemTime::now()) {
+                    Some(next) => println!("{}", tr_args("shift.closes_at", &[&&format_time(next)[..5]])),
+                    None => println!("{}", tr("shift.no_changes")),
+                }
+                return;
+            }
+        };
+        println!("\n{}", tr_args("shift.title", &[&summary.system_id]));
+        println!("{}", tr_args("shift.closed", &[
+            &format_datetime(summary.start),
+            &format_datetime(summary.end),
+            &format_hours(summary.end.duration_since(summary.start).unwrap_or_default()),
+            &summary.closed_by,
+        ]));
+        if summary.samples == 0 {
+            println!("{}", tr("shift.no_samples"));
+        }
+        println!("\n{:<32} {:>12} {:>12} {:>12}", tr("column.channel"), tr("column.min"), tr("column.max"), tr("column.mean"));
+        for channel in &summary.channels {
+            let format = |value: Option<f32>| value.map_or("--".to_string(), |value| self.units.format(channel.kind, value));
+            println!("{:<32} {:>12} {:>12} {:>12}", channel.label, format(channel.min), format(channel.max), format(channel.mean));
+        }
+        println!();
+        for (motor, runtime) in &summary.motors {
+            println!("{}", tr_args("shift.ran", &[&format!("{:<32}", motor), &format_hours(*runtime)]));
+        }
+        println!("\n{}", tr_args("shift.alarm_counts", &[&summary.alarms, &summary.warnings, &summary.emergency_shutdowns]));
+        if summary.actions.is_empty() {
+            println!("{}", tr("shift.no_actions"));
+        } else {
+            let actions: Vec<String> = summary.actions.iter().map(|(action, count)| format!("{} {}", action.name(), count)).collect();
+            println!("{}", tr_args("shift.actions", &[&actions.join(", ")]));
+        }
+        if !summary.notes.is_empty() {
+            println!("\n{}", tr("shift.notes"));
+            for note in &summary.notes {
+                println!("  {}  {:<12} {}", format_datetime(note.time), note.user, note.summary());
+            }
+        }
+    }
+
+    // 'note': a logbook entry from the logged-in user, about a channel of the selected
+    // device or an alarm in the list if one is named, or correcting an earlier note.
+    // Kept in the historian; nothing written can be changed.
+    fn add_note(&mut self, subject: NoteSubject, text: &str) {
+        let historian = match &self.historian {
+            Some(historian) => historian,
+            None => {
+                println!("{}", tr("note.no_historian"));
+                return;
+            }
+        };
+        let (subject, corrects) = match subject {
+            NoteSubject::None => (String::new(), None),
+            NoteSubject::Channel(kind, number) => {
+                let kind = match ChannelKind::parse(kind) {
+                    Ok(kind) => kind,
+                    Err(reason) => {
+                        println!("{}", reason);
+                        return;
+                    }
+                };
+                let label = match self.lock_sensors() {
+                    Some(data) => {
+                        let count = data.channels_of(kind).count();
+                        match data.channels_of(kind).nth(number.wrapping_sub(1)) {
+                            Some(channel) => channel.label(),
+                            None => {
+                                println!("{}", tr_args("note.no_channel", &[&kind.label(), &number, &count]));
+                                return;
+                            }
+                        }
+                    }
+                    None => return,
+                };
+                (format!("{}{}", self.device_label(self.selected), label), None)
+            }
+            NoteSubject::Alarm(id) => match self.alarms.iter().find(|alarm| alarm.id == id) {
+                Some(alarm) => (format!("alarm #{} ({})", id, alarm.message), None),
+                None => {
+                    println!("{}", tr_args("note.no_alarm", &[&id]));
+                    return;
+                }
+            },
+            NoteSubject::Asset(id) => (format!("unknown asset {}", id), None),
+            NoteSubject::Correction(id) => {
+                let since = SystemTime::now().checked_sub(Duration::from_secs(86_400) * (historian.retention_days() as u32 + 1)).unwrap_or(UNIX_EPOCH);
+                let notes = match self.notes_between(since, SystemTime::now()) {
+                    Ok(notes) => notes,
+                    Err(e) => {
+                        println!("{}", e);
+                        return;
+                    }
+                };
+                match notes.iter().find(|note| note.id == id) {
+                    // A correction is about what the note it corrects was about
+                    Some(note) => (note.subject.clone(), Some(id)),
+                    None => {
+                        println!("{}", tr_args("note.no_note", &[&id]));
+                        return;
+                    }
+                }
+            }
+        };
+        let note = Note { id: self.next_note, time: SystemTime::now(), user: self.user.clone(), subject, corrects, text: text.to_string() };
+        historian.record_note(&note);
+        self.next_note += 1;
+        self.log(&tr_args("note.by", &[&note.summary(), &note.user]));
+        self.notes.push(note);
+    }
+
+    // Notes from the historian's files, with this session's that aren't written out yet,
+    // oldest first
+    fn notes_between(&self, since: SystemTime, until: SystemTime) -> Result<Vec<Note>, String> {
+        let mut notes = match &self.historian {
+            Some(historian) => historian::query_notes(historian.directory(), since, until)
+                .map_err(|e| tr_args("note.unreadable", &[&historian.directory().display(), &e]))?,
+            None => Vec::new(),
+        };
+        for note in self.notes.iter().filter(|note| note.time >= since && note.time <= until) {
+            if !notes.iter().any(|written| written.id == note.id) {
+                notes.push(note.clone());
+            }
+        }
+        notes.sort_by_key(|note| (note.time, note.id));
+        Ok(notes)
+    }
+
+    // This shift's notes, or those over a span such as 7d
+    fn print_notes(&self, window: Option<Duration>) {
+        if self.historian.is_none() {
+            println!("{}", tr("note.no_historian"));
+            return;
+        }
+        let now = SystemTime::now();
+        let since = match window {
+            Some(window) => now.checked_sub(window).unwrap_or(UNIX_EPOCH),
+            None => self.shift_started,
+        };
+        let notes = match self.notes_between(since, now) {
+            Ok(notes) => notes,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        println!("\n{}", tr_args("note.title", &[&format_datetime(since), &notes.len()]));
+        for note in &notes {
+            let corrected = notes::corrected_by(&notes, note).map_or(String::new(), |id| tr_args("note.corrected_by", &[&id]));
+            let color = if corrected.is_empty() { StatusColor::Normal } else { StatusColor::Yellow };
+            print_colored(color, &format!("  {}  {:<12} {}{}", format_datetime(note.time), note.user, note.summary(), corrected));
+        }
+        if notes.is_empty() {
+            println!("{}", tr("note.none"));
+        }
+    }
+
+    // Returns why the change was refused, if it was, having said so
+    fn set_alarm_limit(&mut self, index: usize, kind: ChannelKind, channel: usize, limit: &str, value: f32) -> Result<(), String> {
+        // Typed in the display unit; checked in it too so a rejection quotes the numbers the operator sees
+        let unit = self.units.get(kind);
+        let result = match self.lock_device(index) {
+            Some(mut data) => {
+                let count = data.channels_of(kind).count();
+                match data.channel_mut(kind, channel.wrapping_sub(1)) {
+                    Some(found) => unit.limits_from_si(&found.limits).set(limit, value)
+                        .and_then(|()| found.limits.set(limit, unit.to_si(value)))
+                        .map(|()| (found.name.clone(), found.label(), found.limits)),
+                    None => Err(format!("no {} channel {} (valid channels are 1-{})", kind, channel, count)),
+                }
+            }
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+
+        match result {
+            Ok((name, label, limits)) => {
+                let shown = tr_args("limits.set", &[&label, &limit, &format!("{}{}", decimal(value, unit.decimals()), unit.symbol())]);
+                let recorded = format!("{} {} limit set to {:.*}{}", label, limit, unit.decimals(), value, unit.symbol());
+                self.log_device_action_shown(index, AuditAction::LimitChange, &shown, &recorded);
+                let key = self.settings_key(index, &name);
+                self.settings.set_limits(&key, limits);
+                self.save_settings();
+                Ok(())
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("limits.rejected", &[&self.device_label(index), &reason]));
+                Err(reason)
+            }
+        }
+    }
+
+    // Puts back a channel's whole set of limits, in SI, for undo
+    fn restore_limits(&mut self, index: usize, kind: ChannelKind, channel: usize, limits: Limits) -> Result<(), String> {
+        limits.validate()?;
+        let found = match self.lock_device(index) {
+            Some(mut data) => data.channel_mut(kind, channel.wrapping_sub(1)).map(|found| {
+                found.limits = limits;
+                (found.name.clone(), found.label())
+            }),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+        let (name, label) = found.ok_or_else(|| format!("no {} channel {}", kind, channel))?;
+        let unit = self.units.get(kind);
+        let shown = unit.limits_from_si(&limits);
+        let places = unit.decimals();
+        let message = tr_args("limits.restored", &[
+            &label,
+            &decimal(shown.low_alarm, places),
+            &format!("{}{}", decimal(shown.high_alarm, places), unit.symbol()),
+            &decimal(shown.low_warning, places),
+            &format!("{}{}", decimal(shown.high_warning, places), unit.symbol()),
+        ]);
+        let recorded = format!("{} limits restored: alarm {:.*} to {:.*}{}, warning {:.*} to {:.*}{}", label,
+            places, shown.low_alarm, places, shown.high_alarm, unit.symbol(),
+            places, shown.low_warning, places, shown.high_warning, unit.symbol());
+        self.log_device_action_shown(index, AuditAction::LimitChange, &message, &recorded);
+        let key = self.settings_key(index, &name);
+        self.settings.set_limits(&key, limits);
+        self.save_settings();
+        Ok(())
+    }
+
+    // Tag, name, asset ID and description of every channel and motor
+    fn print_channel_setup(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        println!("\n{}", tr("channel.setup_title"));
+        let rows: Vec<(String, &str, &Tagging)> = ChannelKind::ALL.into_iter()
+            .flat_map(|kind| data.channels_of(kind).enumerate().map(move |(i, channel)| (format!("{} {}", kind_word(kind), i + 1), channel)))
+            .map(|(number, channel)| (number, channel.name.as_str(), &channel.tagging))
+            .chain(data.motors.iter().enumerate().map(|(i, motor)| (format!("motor {}", i + 1), motor.name.as_str(), &motor.tagging)))
+            .collect();
+        let width = rows.iter().map(|(_, name, _)| name.chars().count()).max().unwrap_or(0).max(22);
+        let shown = |text: &str| if text.is_empty() { "--".to_string() } else { text.to_string() };
+        println!("{:<11} {:<16} {:<width$}  {:<16} {}", tr("column.channel"), tr("column.tag"), tr("column.name"), tr("column.asset"), tr("column.description"));
+        for (number, name, tagging) in rows {
+            println!("{:<11} {:<16} {:<width$}  {:<16} {}", number, shown(&tagging.tag), name, shown(&tagging.asset), tagging.description);
+        }
+        println!("{}", tr("channel.setup_hint"));
+    }
+
+    // 'find ID' or Ctrl+F: the channel or motor with that asset ID or tag, on whichever
+    // device has it, is selected and shown. Looked up in the channels as they are now, so
+    // a tag or asset ID changed at the console or by a configuration import is found at
+    // once. An unknown ID can be noted for maintenance to follow up.
+    fn find_asset(&mut self, id: &str) {
+        let matches = |tagging: &Tagging| tagging.has_asset(id) || (!tagging.tag.is_empty() && tagging.tag.eq_ignore_ascii_case(id));
+        let mut found = None;
+        for index in 0..self.devices.len() {
+            let data = match self.lock_device(index) {
+                Some(data) => data,
+                None => continue,
+            };
+            // A channel kind and number from 1, or a motor with no kind
+            let channel = ChannelKind::ALL.into_iter().find_map(|kind| {
+                data.channels_of(kind).enumerate().find(|(_, channel)| matches(&channel.tagging)).map(|(i, channel)| (Some(kind), i + 1, channel.label()))
+            });
+            let motor = data.motors.iter().enumerate().find(|(_, motor)| matches(&motor.tagging)).map(|(i, motor)| (None, i + 1, motor.label()));
+            if let Some((kind, number, label)) = channel.or(motor) {
+                found = Some((index, kind, number, label));
+                break;
+            }
+        }
+
+        match found {
+            Some((index, kind, number, label)) => {
+                if index != self.selected {
+                    self.selected = index;
+                    self.log(&tr_args("devices.selected", &[&self.devices[index].name, &(index + 1), &self.devices.len()]));
+                }
+                let what = kind.map_or(format!("motor {}", number), |kind| format!("{} {}", kind_word(kind), number));
+                print_colored(StatusColor::Green, &tr_args("find.found", &[&id, &self.device_label(index), &label, &what]));
+                match kind {
+                    Some(kind) => {
+                        self.print_channel_detail(index, kind, number);
+                    }
+                    None => self.print_motor(number),
+                }
+            }
+            None if self.historian.is_some() => {
+                print_colored(StatusColor::Yellow, &tr_args("find.not_found", &[&id]));
+                println!("{}", tr("find.note_prompt"));
+                self.pending_asset_note = Some(id.to_string());
+            }
+            None => print_colored(StatusColor::Yellow, &tr_args("find.not_found_hint", &[&id])),
+        }
+    }
+
+    // `tag`, `description` or `asset` of one channel or motor; "-" clears it
+    fn set_channel_tagging(&mut self, kind: &str, number: &str, field: &str, value: &str) {
+        let kind = match kind {
+            "motor" => None,
+            kind => match ChannelKind::parse(kind) {
+                Ok(kind) => Some(kind),
+                Err(reason) => {
+                    println!("{}", reason);
+                    return;
+                }
+            },
+        };
+        let number = match number.parse::<usize>() {
+            Ok(number) => number,
+            Err(_) => {
+                println!("{}", tr("channel.usage_channel"));
+                return;
+            }
+        };
+        let value = if value == "-" { "" } else { value };
+        let result = match self.lock_sensors() {
+            Some(mut data) => {
+                let current = match kind {
+                    Some(kind) => data.channel(kind, number.wrapping_sub(1)).map(|channel| channel.tagging.clone()),
+                    None => data.motors.get(number.wrapping_sub(1)).map(|motor| motor.tagging.clone()),
+                };
+                let mut tagging = current.unwrap_or_default();
+                match field {
+                    "tag" => tagging.tag = value.to_string(),
+                    "asset" => tagging.asset = value.to_string(),
+                    _ => tagging.description = value.to_string(),
+                }
+                data.set_tagging(kind, number, tagging.clone()).map(|(name, previous)| (name, previous, tagging))
+            }
+            None => return,
+        };
+
+        match result {
+            Ok((name, previous, tagging)) => {
+                let change = Previous::Tagging { device: self.selected, kind, number, tagging: previous.clone() };
+                let (before, after) = match field {
+                    "tag" => (previous.tag, tagging.tag.clone()),
+                    "asset" => (previous.asset, tagging.asset.clone()),
+                    _ => (previous.description, tagging.description.clone()),
+                };
+                let quoted = |text: &str, none: &str| if text.is_empty() { none.to_string() } else { format!("'{}'", text) };
+                let shown = tr_args("channel.tagging_changed", &[
+                    &name,
+                    &field,
+                    &quoted(&after, tr("channel.tagging_none")),
+                    &quoted(&before, tr("channel.tagging_none")),
+                ]);
+                let detail = format!("{}{} {} changed to {} (was {})", self.device_label(self.selected), name, field, quoted(&after, "none"), quoted(&before, "none"));
+                self.log_action_shown(AuditAction::ChannelSetup, &format!("{}{}", self.device_label(self.selected), shown), &detail);
+                self.record_change(change, detail);
+                let key = self.settings_key(self.selected, &name);
+                self.settings.set_tagging(&key, tagging);
+                self.save_settings();
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("channel.setup_rejected", &[&reason])),
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save(&self.settings_file) {
+            self.log_colored(StatusColor::Red, &tr_args("settings.not_saved", &[&self.settings_file.display(), &e]));
+        }
+    }
+
+    fn print_settings(&self) {
+        if self.settings.is_empty() {
+            println!("{}", tr("settings.none"));
+            return;
+        }
+        println!("\n{}", tr_args("settings.title", &[&self.settings_file.display()]));
+        if let Some(units) = self.settings.units {
+            println!("{}", tr_args("settings.units", &[&units]));
+        }
+        if let Some(theme) = self.settings.theme {
+            println!("{}", tr_args("settings.theme", &[&theme]));
+        }
+        if let Some(language) = self.settings.language {
+            println!("{}", tr_args("settings.language", &[&language]));
+        }
+        if let Some(gauges) = self.settings.gauges {
+            println!("{}", tr_args("settings.readings_shown", &[&if gauges { tr("settings.gauges") } else { tr("settings.list") }]));
+        }
+        if let Some(level) = self.settings.log_level {
+            println!("{}", tr_args("settings.log_level", &[&level.name()]));
+        }
+        if let Some(interval_ms) = self.settings.update_interval_ms {
+            println!("{}", tr_args("settings.update_interval", &[&interval_ms]));
+        }
+        if !self.settings.limits.is_empty() {
+            let names: Vec<&str> = self.settings.limits.iter().map(|(name, _)| name.as_str()).collect();
+            println!("{}", tr_args("settings.alarm_limits", &[&names.join(", ")]));
+        }
+        if !self.settings.tagging.is_empty() {
+            let names: Vec<&str> = self.settings.tagging.iter().map(|(name, _)| name.as_str()).collect();
+            println!("{}", tr_args("settings.tags", &[&names.join(", ")]));
+        }
+        if self.settings.pens != Default::default() {
+            println!("{}", tr("settings.pens"));
+        }
+        println!("{}", tr("settings.reset_hint"));
+    }
+
+    fn reset_settings(&mut self) {
+        let cleared = self.settings.clear(&self.settings_file);
+        self.units = self.defaults.units.unwrap_or_default();
+        platform::set_theme(self.defaults.theme.unwrap_or(Theme::Dark));
+        i18n::set_language(self.defaults.language.unwrap_or(Language::English));
+        self.gauges = self.defaults.gauges.unwrap_or(false);
+        self.log_level = self.defaults.log_level.unwrap_or(LogLevel::Info);
+        for index in 0..self.devices.len() {
+            if let Some(interval_ms) = self.defaults.update_interval_ms {
+                let _ = self.devices[index].simulation_thread.set_update_interval_ms(interval_ms);
+            }
+            let prefix = match index {
+                0 => String::new(),
+                _ => format!("{}/", self.devices[index].name),
+            };
+            if let Some(mut data) = self.lock_device(index) {
+                self.defaults.apply_limits(&mut data, &prefix);
+                self.defaults.apply_tagging(&mut data, &prefix);
+            }
+        }
+        self.log_action_shown(
+            AuditAction::LimitChange,
+            tr("settings.reset"),
+            "Settings reset: units, language, theme, view, log level, update interval, alarm limits, channel tags and trend pens back to the forlenza.toml values",
+        );
+        if let Err(e) = cleared {
+            self.log_colored(StatusColor::Red, &tr_args("settings.not_removed", &[&self.settings_file.display(), &e]));
+        }
+    }
+
+    // forlenza.toml, the stored settings and the recipes in one bundle for another station
+    fn export_configuration(&mut self, path: &str) {
+        let config_text = match std::fs::read_to_string(&self.config_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return self.log_colored(StatusColor::Red, &tr_args("config.unreadable", &[&self.config_path.display(), &e])),
+        };
+        let bundle = Bundle::new(&self.devices[0].name, config_text, self.settings.clone(), self.recipes.clone());
+        match bundle.to_json().save(Path::new(path)) {
+            Ok(()) => {
+                let recipes = self.recipes.recipes().len();
+                let shown = tr_args("config.exported", &[&path, &bundle.schema_version, &recipes]);
+                let recorded = format!("Configuration exported to {} (schema version {}): forlenza.toml, stored settings and {} recipe(s)", path, bundle.schema_version, recipes);
+                self.log_action_shown(AuditAction::Configuration, &shown, &recorded);
+            }
+            Err(e) => self.log_colored(StatusColor::Red, &tr_args("config.export_failed", &[&path, &e])),
+        }
+    }
+
+    // Checks a bundle and lists what it would change; nothing is written until confirmed
+    fn import_configuration(&mut self, path: &str) {
+        let bundle = match Bundle::load(Path::new(path)) {
+            Ok(bundle) => bundle,
+            Err(reason) => return self.log_colored(StatusColor::Red, &tr_args("config.import_refused", &[&path, &reason])),
+        };
+        let config_text = std::fs::read_to_string(&self.config_path).unwrap_or_default();
+        let changes = bundle.changes(&config_text, &self.settings, &self.recipes);
+        println!("\n{}", tr_args("config.title", &[&path]));
+        println!("{}", tr_args("config.exported_from", &[&bundle.system_id, &bundle.exported_at, &bundle.schema_version]));
+        if changes.is_empty() {
+            println!("{}", tr("config.unchanged"));
+            return;
+        }
+        println!("{}", tr("config.changes"));
+        for change in &changes {
+            println!("  {}", change);
+        }
+        let config = bundle.config();
+        println!("{}", tr_args("config.files_replaced", &[&self.config_path.display(), &config.settings_file.display(), &config.recipes_file.display()]));
+        print_colored(StatusColor::Yellow, tr("import.confirm"));
+        self.pending_import = Some((path.to_string(), bundle));
+    }
+
+    fn apply_import(&mut self, path: &str, bundle: &Bundle) {
+        match bundle.apply(&self.config_path) {
+            Ok(written) => {
+                let config = bundle.config();
+                // Later changes at the console build on what was imported rather than overwriting it
+                self.settings = bundle.settings.clone();
+                self.settings_file = config.settings_file;
+                self.recipes = bundle.recipes.clone();
+                self.recipes_file = config.recipes_file;
+                let written: Vec<String> = written.iter().map(|path| path.display().to_string()).collect();
+                let written = written.join(", ");
+                let shown = tr_args("config.imported", &[&path, &bundle.system_id, &bundle.exported_at, &written]);
+                let recorded = format!("Configuration imported from {} (exported from {} at {}): {} written", path, bundle.system_id, bundle.exported_at, written);
+                self.log_action_shown(AuditAction::Configuration, &shown, &recorded);
+                self.notify_warn(tr("config.restart_needed"));
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("config.import_failed", &[&path, &reason])),
+        }
+    }
+
+    fn print_recipes(&self) {
+        if self.recipes.recipes().is_empty() {
+            println!("{}", tr("recipe.none"));
+            return;
+        }
+        println!("\n{}", tr_args("recipe.list_title", &[&self.recipes_file.display()]));
+        for recipe in self.recipes.recipes() {
+            let setpoints: Vec<String> = recipe.setpoints.iter().map(|rpm| rpm.to_string()).collect();
+            println!("{}", tr_args("recipe.summary", &[&format!("{:<24}", recipe.name), &setpoints.join("/"), &recipe.limits.len()]));
+        }
+        println!("{}", tr("recipe.hint"));
+    }
+
+    fn print_recipe(&self, name: &str) {
+        let recipe = match self.recipes.find(name) {
+            Some(recipe) => recipe,
+            None => {
+                println!("{}", tr_args("recipe.unknown", &[&name]));
+                return;
+            }
+        };
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        println!("\n{}", tr_args("recipe.title", &[&recipe.name]));
+        println!("{}", tr("recipe.motor_setpoints"));
+        for (i, rpm) in recipe.setpoints.iter().enumerate() {
+            let name = data.motors.get(i).map_or_else(|| tr_args("recipe.motor_not_configured", &[&(i + 1)]), |motor| motor.name.clone());
+            println!("{}", tr_args("recipe.rpm", &[&name, &rpm]));
+        }
+        if !recipe.limits.is_empty() {
+            println!("{}", tr("recipe.alarm_limits"));
+        }
+        for (channel, limits) in &recipe.limits {
+            // A channel that is no longer configured can't be shown in a display unit
+            let unit = match data.channels.iter().find(|found| found.name == *channel) {
+                Some(found) => self.units.get(found.kind),
+                None => {
+                    println!("{}", tr_args("recipe.no_channel", &[&channel]));
+                    continue;
+                }
+            };
+            println!(
+                "  {}: {} / {} / {} / {}{}",
+                channel, unit.number(limits.low_alarm), unit.number(limits.low_warning), unit.number(limits.high_warning), unit.number(limits.high_alarm), unit.symbol()
+            );
+        }
+    }
+
+    fn save_recipe(&mut self, name: &str) {
+        if name.chars().count() > 32 {
+            println!("{}", tr("recipe.name_too_long"));
+            return;
+        }
+        let recipe = match self.read_sensors() {
+            Some(data) => Recipe::capture(name, &data),
+            None => return,
+        };
+        let replaced = self.recipes.store(recipe);
+        match self.recipes.save(&self.recipes_file) {
+            Ok(()) if replaced => {
+                let shown = tr_args("recipe.updated", &[&name]);
+                self.log_action_shown(AuditAction::Recipe, &shown, &format!("Recipe {} updated from the current setpoints and alarm limits", name));
+            }
+            Ok(()) => self.log_action_shown(AuditAction::Recipe, &tr_args("recipe.saved", &[&name]), &format!("Recipe {} saved from the current setpoints and alarm limits", name)),
+            Err(e) => self.log_colored(StatusColor::Red, &tr_args("recipe.not_saved", &[&name, &self.recipes_file.display(), &e])),
+        }
+    }
+
+    fn delete_recipe(&mut self, name: &str) {
+        let recipe = match self.recipes.remove(name) {
+            Some(recipe) => recipe,
+            None => {
+                println!("{}", tr_args("recipe.unknown", &[&name]));
+                return;
+            }
+        };
+        match self.recipes.save(&self.recipes_file) {
+            Ok(()) => self.log_action_shown(AuditAction::Recipe, &tr_args("recipe.deleted", &[&recipe.name]), &format!("Recipe {} deleted", recipe.name)),
+            Err(e) => self.log_colored(StatusColor::Red, &tr_args("recipe.deleted_not_saved", &[&recipe.name, &self.recipes_file.display(), &e])),
+        }
+    }
+
+    fn load_recipe(&mut self, name: &str) {
+        let recipe = match self.recipes.find(name) {
+            Some(recipe) => recipe.clone(),
+            None => {
+                println!("{}", tr_args("recipe.unknown", &[&name]));
+                return;
+            }
+        };
+        // Refusals are reported by execute
+        let _ = self.execute(Command::LoadRecipe { device: self.selected, recipe }, &Actor::Console);
+    }
+
+    // All setpoints and limits change under one lock, so nothing sees half a recipe
+    fn apply_recipe(&mut self, index: usize, recipe: Recipe) -> Result<(), String> {
+        let result = match self.lock_device(index) {
+            Some(mut data) => recipe.apply(&mut data).map(|setpoints| {
+                let limits: Vec<(String, Limits)> = data.channels.iter()
+                    .filter(|channel| recipe.limits.iter().any(|(name, _)| *name == channel.name))
+                    .map(|channel| (channel.name.clone(), channel.limits))
+                    .collect();
+                (setpoints, limits)
+            }),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+
+        match result {
+            Ok((setpoints, limits)) => {
+                for (motor, rpm) in &setpoints {
+                    self.devices[index].simulation_thread.send_command(SourceCommand::MotorSetpoint { motor: *motor, rpm: *rpm });
+                }
+                let left = recipe.setpoints.len() - setpoints.len();
+                let (shown_note, note) = match left {
+                    0 => ("", ""),
+                    _ => (tr("recipe.fan_left"), " (the control loop's fan is left to the loop)"),
+                };
+                let shown = tr_args("recipe.loaded", &[&recipe.name, &setpoints.len(), &limits.len(), &shown_note]);
+                let recorded = format!("Recipe {} loaded: {} motor setpoint(s) and alarm limits for {} channel(s) applied{}", recipe.name, setpoints.len(), limits.len(), note);
+                self.log_device_action_shown(index, AuditAction::Recipe, &shown, &recorded);
+                self.toast(toasts::Severity::Info, &shown);
+                // Kept like limits edited by hand
+                for (name, limits) in limits {
+                    let key = self.settings_key(index, &name);
+                    self.settings.set_limits(&key, limits);
+                }
+                self.save_settings();
+                Ok(())
+            }
+            Err(reason) => {
+                self.notify_error(&tr_args("recipe.not_loaded", &[&recipe.name, &reason]));
+                Err(reason)
+            }
+        }
+    }
+
+    // The kind, index within the kind, and current state of the channel a trend command names
+    fn trend_channel(&self, kind: &str, channel: usize) -> Option<(ChannelKind, usize, Channel)> {
+        let kind = match ChannelKind::parse(kind) {
+            Ok(kind) => kind,
+            Err(reason) => {
+                println!("{}", reason);
+                return None;
+            }
+        };
+        let index = channel.wrapping_sub(1);
+        match self.read_sensors()?.channel(kind, index) {
+            Some(found) => Some((kind, index, found.clone())),
+            None => {
+                println!("{}", tr_args("channel.no_channel", &[&kind.label(), &channel]));
+                None
+            }
+        }
+    }
+
+    fn print_trend(&self, kind: &str, channel: usize, minutes: u64) {
+        let (kind, index, channel) = match self.trend_channel(kind, channel) {
+            Some(found) => found,
+            None => return,
+        };
+
+        let window = Duration::from_secs(minutes * 60);
+        let now = SystemTime::now();
+        let since = now.checked_sub(window).unwrap_or(UNIX_EPOCH);
+        let samples = match error::lock(&self.device().history, devices::HISTORY) {
+            Ok(history) => history.window(kind, index, since),
+            Err(e) => return print_colored(StatusColor::Red, &tr_args("trend.unavailable", &[&e])),
+        };
+
+        println!("\n{}", tr_args("trend.title", &[&channel.label(), &minutes, &samples.len()]));
+        let cursors = self.snap_cursors(&samples, since, now);
+        for line in render_trend(&samples, &channel.limits, self.units.get(kind), window, now, &cursor_markers(&cursors)) {
+            println!("{}", line);
+        }
+        println!("{}", tr("trend.legend"));
+        print_cursor_readout(&cursors, self.units.get(kind));
+    }
+
+    // Same chart as `trend`, but read back from the historian's files
+    fn print_history(&self, kind: &str, channel: usize, window: Duration) {
+        let historian = match &self.historian {
+            Some(historian) => historian,
+            None => {
+                println!("{}", tr("history.no_historian"));
+                return;
+            }
+        };
+        if self.selected != 0 {
+            println!("{}", tr_args("history.first_device_only", &[&self.devices[0].name]));
+            return;
+        }
+        let (kind, index, channel) = match self.trend_channel(kind, channel) {
+            Some(found) => found,
+            None => return,
+        };
+
+        let now = SystemTime::now();
+        let since = now.checked_sub(window).unwrap_or(UNIX_EPOCH);
+        let samples = match historian::query(historian.directory(), kind, index, since, now) {
+            Ok(samples) => samples,
+            Err(e) => {
+                println!("{}", tr_args("history.unreadable", &[&historian.directory().display(), &e]));
+                return;
+            }
+        };
+
+        println!("\n{}", tr_args("history.title", &[&channel.label(), &format_span(window), &samples.len()]));
+        let cursors = self.snap_cursors(&samples, since, now);
+        for line in render_trend(&samples, &channel.limits, self.units.get(kind), window, now, &cursor_markers(&cursors)) {
+            println!("{}", line);
+        }
+        println!("{}", tr("history.legend"));
+        print_cursor_readout(&cursors, self.units.get(kind));
+    }
+
+    // The selected device's channels with their pens
+    fn pens(&self) -> Option<Vec<PenChannel>> {
+        let data = self.read_sensors()?;
+        Some(data.channels.iter().enumerate().map(|(position, channel)| {
+            let key = self.settings_key(self.selected, &channel.name);
+            PenChannel {
+                pen: self.settings.pens.pen(&key, channel.kind, position),
+                key,
+                label: channel.label(),
+                kind: channel.kind,
+                index: data.channels[..position].iter().filter(|other| other.kind == channel.kind).count(),
+            }
+        }).collect())
+    }
+
+    fn print_pens(&self) {
+        let pens = match self.pens() {
+            Some(pens) => pens,
+            None => return,
+        };
+        println!("\n{}", tr("trend.pens_title"));
+        for PenChannel { label, kind, pen, .. } in &pens {
+            let shown = if pen.visible { tr("trend.pen_shown") } else { tr("trend.pen_hidden") };
+            println!("  {}  {:<32} {:<7} {}  {}", pen.mark, label, shown, tr_args("trend.pen_axis", &[&format!("{:<9}", pen.axis.label())]), self.units.get(*kind));
+        }
+        for axis in Axis::ALL {
+            match self.settings.pens.range(axis) {
+                Some((low, high)) => println!("{}", tr_args("trend.axis", &[&axis.label(), &low, &high])),
+                None => println!("{}", tr_args("trend.axis_scaled", &[&axis.label()])),
+            }
+        }
+        println!("{}", tr("trend.pens_hint"));
+    }
+
+    fn set_pen(&mut self, kind: &str, channel: usize, change: &[&str]) {
+        let kind = match ChannelKind::parse(kind) {
+            Ok(kind) => kind,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        let pens = match self.pens() {
+            Some(pens) => pens,
+            None => return,
+        };
+        let count = pens.iter().filter(|found| found.kind == kind).count();
+        let PenChannel { key, label, pen, .. } = match pens.into_iter().find(|found| found.kind == kind && found.index == channel.wrapping_sub(1)) {
+            Some(found) => found,
+            None => {
+                println!("{}", tr_args("note.no_channel", &[&kind.label(), &channel, &count]));
+                return;
+            }
+        };
+        let result = match change {
+            ["show" | "hide"] => Ok(Pen { visible: change[0] == "show", ..pen }),
+            ["axis", axis] => Axis::parse(axis).map(|axis| Pen { axis, ..pen }),
+            ["mark", mark] => match mark.chars().collect::<Vec<char>>()[..] {
+                [mark] => Pen::check_mark(mark).map(|()| Pen { mark, ..pen }),
+                _ => Err(tr_args("trend.not_one_character", &[&mark])),
+            },
+            _ => Err(tr("trend.usage_pen").to_string()),
+        };
+        let previous = Previous::Pen { key: key.clone(), label: label.clone(), pen };
+        let pen = match result {
+            Ok(pen) => pen,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        self.settings.pens.set_pen(&key, pen);
+        self.save_settings();
+        let shown = if pen.visible { tr("trend.pen_shown") } else { tr("trend.pen_hidden") };
+        self.log(&tr_args("trend.pen_changed", &[&label, &shown, &pen.mark, &pen.axis.label()]));
+        let detail = format!("{} pen: {}, mark {}, {} axis", label, if pen.visible { "shown" } else { "hidden" }, pen.mark, pen.axis);
+        self.record_change(previous, detail);
+    }
+
+    fn set_pen_axis(&mut self, axis: &str, range: &[&str]) {
+        let axis = match Axis::parse(axis) {
+            Ok(axis) => axis,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        let range = match range {
+            ["auto"] => None,
+            [low, high] => match (low.parse(), high.parse()) {
+                (Ok(low), Ok(high)) => Some((low, high)),
+                _ => return println!("{}", tr("trend.usage_axis")),
+            },
+            _ => return println!("{}", tr("trend.usage_axis")),
+        };
+        let previous = Previous::PenRange { axis, range: self.settings.pens.range(axis) };
+        if let Err(reason) = self.settings.pens.set_range(axis, range) {
+            println!("{}", reason);
+            return;
+        }
+        self.save_settings();
+        let (shown, detail) = match range {
+            Some((low, high)) => (tr_args("trend.axis_fixed", &[&axis.label(), &low, &high]), format!("Trend {} axis fixed at {} to {}", axis, low, high)),
+            None => (tr_args("trend.axis_to_data", &[&axis.label()]), format!("Trend {} axis scaled to the data", axis)),
+        };
+        self.log(&shown);
+        self.record_change(previous, detail);
+    }
+
+    // Every shown pen of the selected device on one chart. Only the shown channels are
+    // copied out of the history buffer; the buffer and the historian keep recording all.
+    fn print_pen_trend(&self, minutes: u64) {
+        let pens: Vec<PenChannel> = match self.pens() {
+            Some(pens) => pens.into_iter().filter(|found| found.pen.visible).collect(),
+            None => return,
+        };
+        if pens.is_empty() {
+            println!("{}", tr("trend.all_hidden"));
+            return;
+        }
+        let window = Duration::from_secs(minutes * 60);
+        let now = SystemTime::now();
+        let since = now.checked_sub(window).unwrap_or(UNIX_EPOCH);
+        let series: Vec<(Vec<Sample>, Pen, Unit)> = match error::lock(&self.device().history, devices::HISTORY) {
+            Ok(history) => pens.iter().map(|found| (history.window(found.kind, found.index, since), found.pen, self.units.get(found.kind))).collect(),
+            Err(e) => return print_colored(StatusColor::Red, &tr_args("trend.unavailable", &[&e])),
+        };
+
+        println!("\n{}", tr_args("trend.pens_chart_title", &[&pens.len(), &minutes]));
+        for line in render_pens(&series, [self.settings.pens.range(Axis::Left), self.settings.pens.range(Axis::Right)], window, now, &[]) {
+            println!("{}", line);
+        }
+        for PenChannel { label, kind, pen, .. } in &pens {
+            println!("{}", tr_args("trend.pen", &[&pen.mark, &label, &self.units.get(*kind), &pen.axis.label()]));
+        }
+    }
+
+    // Each cursor moved onto the closest sample; None for one outside the chart's window
+    // or with no valid sample to read
+    fn snap_cursors(&self, samples: &[Sample], since: SystemTime, until: SystemTime) -> Vec<(SystemTime, Option<Sample>)> {
+        self.cursors.iter()
+            .map(|cursor| (*cursor, Some(*cursor).filter(|cursor| *cursor >= since && *cursor <= until).and_then(|cursor| history::nearest(samples, cursor))))
+            .collect()
+    }
+
+    fn print_cursors(&self) {
+        if self.cursors.is_empty() {
+            println!("{}", tr("trend.no_cursors"));
+            return;
+        }
+        for (cursor, name) in self.cursors.iter().zip(CURSOR_NAMES) {
+            println!("{}", tr_args("trend.cursor", &[&name, &format_datetime(*cursor)]));
+        }
+        println!("{}", tr("trend.cursor_hint"));
+    }
+
+    fn set_cursors(&mut self, cursors: Vec<SystemTime>) {
+        self.cursors = cursors;
+        self.print_cursors();
+    }
+
+    // Alarms from the historian, actions and emergency shutdowns from the audit trail, and
+    // this session's diagnostic runs, oldest first; with a note for each source that
+    // couldn't be read
+    fn timeline_events(&self, since: SystemTime, until: SystemTime) -> (Vec<TimelineEvent>, Vec<String>) {
+        let mut events = Vec::new();
+        let mut notes = Vec::new();
+        match &self.historian {
+            Some(historian) => match historian::query_alarms(historian.directory(), since, until) {
+                Ok(alarms) => events.extend(alarms.into_iter().map(|(time, message)| TimelineEvent {
+                    time,
+                    lane: Lane::Alarms,
+                    first_out: message.starts_with("FIRST-OUT"),
+                    severity: if message.starts_with("CLEARED") {
+                        Severity::Normal
+                    } else if message.starts_with("WARNING") {
+                        Severity::Warning
+                    } else {
+                        Severity::Critical
+                    },
+                    summary: message,
+                    origin: "historian".to_string(),
+                })),
+                Err(e) => notes.push(tr_args("timeline.alarms_unreadable", &[&historian.directory().display(), &e])),
+            },
+            None => notes.push(tr("timeline.alarms_no_historian").to_string()),
+        }
+        match self.notes_between(since, until) {
+            Ok(written) => events.extend(written.into_iter().map(|note| TimelineEvent {
+                time: note.time,
+                lane: Lane::Notes,
+                first_out: false,
+                severity: Severity::Info,
+                summary: note.summary(),
+                origin: note.user,
+            })),
+            Err(e) => notes.push(tr_args("timeline.notes_unreadable", &[&e])),
+        }
+
+        // The file also holds earlier sessions
+        let actions = if self.audit.is_writing() {
+            audit::query(self.audit.path(), since, until).unwrap_or_else(|e| {
+                notes.push(tr_args("timeline.actions_unreadable", &[&self.audit.path().display(), &e]));
+                self.audit.events().iter().filter(|event| event.timestamp >= since && event.timestamp <= until).cloned().collect()
+            })
+        } else {
+            self.audit.events().iter().filter(|event| event.timestamp >= since && event.timestamp <= until).cloned().collect()
+        };
+        for event in actions {
+            let (lane, severity) = match event.action {
+                AuditAction::EmergencyShutdown => (Lane::Shutdowns, Severity::Critical),
+                AuditAction::Reset | AuditAction::Restart => (Lane::Shutdowns, Severity::Normal),
+                AuditAction::Diagnostic => (Lane::Diagnostics, Severity::Info),
+                _ => (Lane::Actions, Severity::Info),
+            };
+            events.push(TimelineEvent {
+                time: event.timestamp,
+                first_out: false,
+                lane,
+                severity,
+                summary: event.detail,
+                origin: format!("{} ({})", event.user, event.action.name()),
+            });
+        }
+
+        for run in self.diagnostics.history() {
+            let finished = run.started + run.elapsed;
+            if finished < since || finished > until {
+                continue;
+            }
+            let (color, outcome) = diagnostic_outcome(&run.result);
+            let severity = match color {
+                StatusColor::Red => Severity::Critical,
+                StatusColor::Yellow => Severity::Warning,
+                _ => Severity::Normal,
+            };
+            events.push(TimelineEvent {
+                time: finished,
+                first_out: false,
+                lane: Lane::Diagnostics,
+                severity,
+                summary: tr_args("timeline.diagnostic_finished", &[&run.trigger.label(), &outcome]),
+                origin: tr_args("timeline.diagnostic_origin", &[&run.elapsed.as_millis()]),
+            });
+        }
+
+        events.sort_by_key(|event| event.time);
+        (events, notes)
+    }
+
+    // The window ends now, or is centered on `at`
+    fn print_timeline(&mut self, window: Duration, at: Option<SystemTime>) {
+        let (since, until) = match at {
+            Some(center) => (center.checked_sub(window / 2).unwrap_or(UNIX_EPOCH), center + window / 2),
+            None => {
+                let now = SystemTime::now();
+                (now.checked_sub(window).unwrap_or(UNIX_EPOCH), now)
+            }
+        };
+        let (events, notes) = self.timeline_events(since, until);
+
+        println!("\n{}", tr_args("timeline.title", &[&format_datetime(since), &format_datetime(until), &events.len()]));
+        for (color, line) in timeline::render(&events, since, until) {
+            print_colored(color, &line);
+        }
+        println!("{}", tr("timeline.legend"));
+        for note in &notes {
+            println!("{}", note);
+        }
+        let first = events.len().saturating_sub(TIMELINE_LIST);
+        if first > 0 {
+            println!("{}", tr_args("timeline.earlier", &[&first]));
+        }
+        for (number, event) in events.iter().enumerate().skip(first) {
+            let icon = if event.first_out { "▶ " } else { "" };
+            print_colored(event.severity.color(), &format!("{:>3}  {}  {:<11}  {}{}", number + 1, format_datetime(event.time), event.lane.name(), icon, event.summary));
+        }
+        if events.is_empty() {
+            println!("{}", tr("timeline.nothing_recorded"));
+        } else {
+            println!("{}", tr("timeline.event_hint"));
+        }
+        self.timeline = events;
+    }
+
+    // One event from the last timeline, with the historian's chart of the channel it
+    // names around it
+    fn print_timeline_event(&self, number: usize) {
+        let event = match self.timeline.get(number.wrapping_sub(1)) {
+            Some(event) => event,
+            None => {
+                println!("{}", tr_args("timeline.no_event", &[&number]));
+                return;
+            }
+        };
+        println!("\n{}", tr_args("timeline.event_title", &[&number]));
+        println!("{}", tr_args("timeline.time", &[&format_datetime(event.time)]));
+        println!("{}", tr_args("timeline.kind", &[&event.lane.name()]));
+        println!("{}", tr_args("timeline.source", &[&event.origin]));
+        print_colored(event.severity.color(), &tr_args("timeline.detail", &[&event.summary]));
+
+        let historian = match &self.historian {
+            Some(historian) => historian,
+            None => return,
+        };
+        // The historian records the first device only
+        if (1..self.devices.len()).any(|index| event.summary.contains(&self.device_label(index))) {
+            return;
+        }
+        let channel = match self.lock_device(0) {
+            Some(data) => ChannelKind::ALL.iter()
+                .flat_map(|kind| data.channels_of(*kind).enumerate().map(|(index, channel)| (*kind, index, channel.clone())))
+                .filter(|(_, _, channel)| event.summary.contains(&channel.name))
+                .max_by_key(|(_, _, channel)| channel.name.len()),
+            None => return,
+        };
+        let (kind, index, channel) = match channel {
+            Some(found) => found,
+            None => return,
+        };
+        let since = event.time.checked_sub(TIMELINE_CHART / 2).unwrap_or(UNIX_EPOCH);
+        let until = event.time + TIMELINE_CHART / 2;
+        let samples = match historian::query(historian.directory(), kind, index, since, until) {
+            Ok(samples) => samples,
+            Err(e) => {
+                println!("{}", tr_args("history.unreadable", &[&historian.directory().display(), &e]));
+                return;
+            }
+        };
+        println!("\n{}", tr_args("timeline.around_event", &[&channel.label(), &samples.len()]));
+        for line in render_trend(&samples, &channel.limits, self.units.get(kind), TIMELINE_CHART, until, &[(event.time, '|')]) {
+            println!("{}", line);
+        }
+        println!("{}", tr("timeline.chart_legend"));
+    }
+
+    // Reports the outcome of background file jobs (exports etc.); called once per tick of the main loop.
+    fn poll_jobs(&mut self) {
+        while let Ok(result) = self.job_rx.try_recv() {
+            match result {
+                Ok(message) => self.notify_info(&message),
+                Err(message) => self.notify_error(&message),
+            }
+        }
+    }
+
+    fn poll_toasts(&mut self) {
+        self.toasts.expire(Instant::now());
+    }
+
+    fn print_toasts(&self) {
+        if self.toasts.is_empty() {
+            println!("{}", tr_args("notices.none", &[&toasts::INFO_DURATION.as_secs(), &toasts::WARNING_DURATION.as_secs()]));
+            return;
+        }
+        for toast in self.toasts.visible() {
+            print_toast(toast);
+        }
+    }
+
+    fn dismiss_toast(&mut self, which: &str) {
+        if which == "all" {
+            let count = self.toasts.dismiss_all();
+            println!("{}", tr_args("notices.dismissed_all", &[&count]));
+            return;
+        }
+        match which.parse() {
+            Ok(id) if self.toasts.dismiss(id) => println!("{}", tr_args("notices.dismissed", &[&id])),
+            _ => println!("{}", tr_args("notices.unknown", &[&which])),
+        }
+    }
+
+    fn export_csv(&mut self, path: &str) {
+        let records = match error::lock(&self.device().history, devices::HISTORY).map(|history| history.records()) {
+            Ok(records) => records,
+            Err(e) => return self.report_error(self.selected, &e),
+        };
+        let labels: Vec<String> = match self.lock_sensors() {
+            Some(data) => data.channels.iter().map(Channel::label).chain(data.motors.iter().map(Motor::label)).collect(),
+            None => return,
+        };
+        self.log(&tr_args("export.exporting", &[&records.len(), &path, &self.units]));
+
+        let path = path.to_string();
+        let units = self.units;
+        let tx = self.job_tx.clone();
+        thread::spawn(move || {
+            let result = history::write_csv(&path, &records, units, &labels)
+                .map(|()| tr_args("export.complete", &[&records.len(), &path]))
+                .map_err(|e| tr_args("export.failed", &[&path, &e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    fn load_baseline(&mut self, path: &str) {
+        match Baseline::load(path) {
+            Ok(baseline) => {
+                self.baseline = Some(baseline);
+                self.print_comparison();
+            }
+            Err(e) => println!("{}", tr_args("compare.not_loaded", &[&path, &e])),
+        }
+    }
+
+    // The loaded snapshot against the selected device as it is now
+    fn comparison(&self) -> Option<(&Baseline, Comparison)> {
+        let baseline = match &self.baseline {
+            Some(baseline) => baseline,
+            None => {
+                println!("{}", tr("compare.no_snapshot"));
+                return None;
+            }
+        };
+        let live = self.read_sensors()?;
+        Some((baseline, Comparison::new(&baseline.data, &live)))
+    }
+
+    fn print_comparison(&self) {
+        let (baseline, comparison) = match self.comparison() {
+            Some(found) => found,
+            None => return,
+        };
+        let tolerances = &self.compare_tolerances;
+        let tolerance = |kind: ChannelKind| format_offset(self.units.get(kind), tolerances.channel(kind)).replace('+', "±");
+        println!("\n{}", tr_args("compare.title", &[&baseline.path]));
+        println!("{}", tr_args("compare.taken", &[&baseline.system_id, &baseline.captured_at, &self.device().name]));
+        println!("{}", tr_args("compare.tolerance", &[
+            &tolerance(ChannelKind::Temperature),
+            &tolerance(ChannelKind::Pressure),
+            &tolerances.setpoint,
+        ]));
+        let width = comparison.channels.iter().map(|channel| channel.label.chars().count())
+            .chain(comparison.motors.iter().map(|motor| motor.label.chars().count()))
+            .max()
+            .unwrap_or(0)
+            .max(22);
+        println!(
+            "{:<width$} {:>12} {:>12} {:>11}  {}",
+            tr("column.channel"), tr("column.snapshot"), tr("column.live"), tr("column.difference"), tr("column.limits")
+        );
+        for channel in &comparison.channels {
+            let unit = self.units.get(channel.kind);
+            let changed: Vec<String> = channel.limits().into_iter()
+                .filter(|(_, snapshot, live)| snapshot != live)
+                .map(|(name, snapshot, live)| {
+                    let limit = |value: Option<f32>| value.map_or(tr("compare.none").to_string(), |value| unit.number(value));
+                    format!("{} {} -> {}", name, limit(snapshot), limit(live))
+                })
+                .collect();
+            let difference = if channel.snapshot.is_finite() && channel.live.is_finite() { format_offset(unit, channel.live - channel.snapshot) } else { "--".to_string() };
+            let line = format!(
+                "{:<width$} {:>12} {:>12} {:>11}  {}",
+                channel.label,
+                unit.format(channel.snapshot),
+                unit.format(channel.live),
+                difference,
+                if changed.is_empty() { tr("compare.same").to_string() } else { changed.join(", ") }
+            );
+            print_deviation(channel.deviation(tolerances).max(channel.limits_deviation(tolerances)), &line);
+        }
+        for motor in &comparison.motors {
+            let state = |setpoint: u16, running: bool| format!("{} RPM{}", setpoint, if running { "" } else { tr("compare.off") });
+            let line = format!(
+                "{:<width$} {:>12} {:>12} {:>11}",
+                motor.label,
+                state(motor.snapshot_setpoint, motor.snapshot_running),
+                state(motor.live_setpoint, motor.live_running),
+                format!("{:+} RPM", motor.live_setpoint as i32 - motor.snapshot_setpoint as i32)
+            );
+            print_deviation(motor.setpoint_deviation(tolerances).max(motor.running_deviation()), &line);
+        }
+        if !comparison.only_in_snapshot.is_empty() {
+            print_colored(StatusColor::Yellow, &tr_args("compare.only_snapshot", &[&comparison.only_in_snapshot.join(", ")]));
+        }
+        if !comparison.only_live.is_empty() {
+            print_colored(StatusColor::Yellow, &tr_args("compare.only_live", &[&comparison.only_live.join(", ")]));
+        }
+        match comparison.flagged(tolerances) {
+            0 => print_colored(StatusColor::Green, tr("compare.all_within")),
+            flagged => print_colored(StatusColor::Yellow, &tr_args("compare.beyond", &[&flagged])),
+        }
+        println!("{}", tr("compare.hint"));
+    }
+
+    fn export_comparison(&mut self, path: &str) {
+        let comparison = match self.comparison() {
+            Some((_, comparison)) => comparison,
+            None => return,
+        };
+        self.log(&tr_args("compare.exporting", &[&self.baseline.as_ref().map_or("", |baseline| baseline.path.as_str()), &path, &self.units]));
+        let path = path.to_string();
+        let units = self.units;
+        let tolerances = self.compare_tolerances;
+        let tx = self.job_tx.clone();
+        thread::spawn(move || {
+            let result = comparison.write_csv(std::path::Path::new(&path), units, &tolerances)
+                .map(|()| tr_args("compare.written", &[&path]))
+                .map_err(|e| tr_args("compare.export_failed", &[&path, &e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    // Captures the selected device's panel here, so the report shows this moment, and
+    // renders and writes it in the background; the outcome is logged when it is done
+    fn generate_report(&mut self) {
+        let now = SystemTime::now();
+        let since = now - REPORT_WINDOW;
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let samples: Vec<Vec<Sample>> = match error::lock(&self.device().history, devices::HISTORY) {
+            Ok(history) => ChannelKind::ALL.into_iter()
+                .flat_map(|kind| (0..data.channels_of(kind).count()).map(move |index| (kind, index)))
+                .map(|(kind, index)| history.window(kind, index, since))
+                .collect(),
+            // The report still has the readings; the trends are left out
+            Err(_) => Vec::new(),
+        };
+        let channels = ChannelKind::ALL.into_iter()
+            .flat_map(|kind| data.channels_of(kind))
+            .zip(samples.into_iter().chain(std::iter::repeat_with(Vec::new)))
+            .map(|(channel, samples)| ReportChannel {
+                name: channel.label(),
+                description: channel.tagging.description.clone(),
+                unit: self.units.get(channel.kind),
+                value: channel.value,
+                level: channel.alarm.level(),
+                limits: channel.limits,
+                samples,
+            })
+            .collect();
+        let motors = data.motors.iter().map(|motor| ReportMotor {
+            name: motor.label(),
+            description: motor.tagging.description.clone(),
+            status: motor_status(motor).to_string(),
+            speed: motor.speed,
+            setpoint: motor.setpoint,
+            current: motor.current,
+        }).collect();
+
+        let device = self.device();
+        let mut conditions = Vec::new();
+        if data.emergency_shutdown {
+            conditions.push("Emergency shutdown active".to_string());
+        }
+        if let Some(trip) = &data.auto_trip {
+            conditions.push(format!("Automatic trip: {}", trip.describe(self.units)));
+        }
+        if let Some(alarm) = &device.first_out {
+            conditions.push(format!("First-out alarm: #{} {} (raised {})", alarm.id, alarm.message, format_time_precise(alarm.raised_at)));
+        }
+        if let Some(reason) = &device.acquisition_halted {
+            conditions.push(format!("Data acquisition halted: {}", reason));
+        }
+        for interlock in data.interlocks.iter().filter(|interlock| !interlock.satisfied) {
+            conditions.push(format!("Interlock {} dropped out: {} no longer holds", interlock.name, interlock.condition));
+        }
+        for interlock in &data.interlocks {
+            if let Some(bypass) = &interlock.bypass {
+                conditions.push(format!("Interlock {} bypassed by {} until {}: {}", interlock.name, bypass.user, format_time(bypass.until), bypass.reason));
+            }
+        }
+        if let Some(age) = data.staleness(now, device.update_interval()) {
+            conditions.push(format!("Readings stale: last update {:.0} s ago", age.as_secs_f32()));
+        }
+        let report = Report {
+            title: self.title.clone(),
+            system_id: device.name.clone(),
+            source: device.source_name.clone(),
+            user: self.user.clone(),
+            generated: now,
+            conditions,
+            channels,
+            motors,
+            alarms: self.alarms.iter().map(|alarm| (alarm.id, alarm.state().to_string(), alarm.raised_at, alarm.message.clone())).collect(),
+            diagnostic: self.diagnostics.history().back().cloned(),
+            window: REPORT_WINDOW,
+        };
+        drop(data);
+
+        self.log(tr("report.generating"));
+        let directory = self.reports_directory.clone();
+        let tx = self.job_tx.clone();
+        thread::spawn(move || {
+            let result = report.write(&directory, &file_stamp(now))
+                .map(|path| tr_args("report.written", &[&path.display()]))
+                .map_err(|e| tr_args("report.not_written", &[&e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    // Writes the diagnostic log with a header identifying the installation, for support calls
+    fn save_log(&mut self, path: Option<&str>) {
+        let now = SystemTime::now();
+        let path = match path {
+            Some(path) => path.to_string(),
+            None => format!("diagnostic_{}.txt", file_stamp(now)),
+        };
+        let mut header = vec![
+            format!("Forlenza Industrial Control System {} - diagnostic log", env!("CARGO_PKG_VERSION")),
+            format!("Saved: {}", format_datetime(now)),
+            format!("System ID: {}", self.devices[0].name),
+            format!("Operating system: {}", self.detected_os),
+            format!("Compatibility override: {}", if self.compatibility_override { "active" } else { "not needed" }),
+        ];
+        for (index, device) in self.devices.iter().enumerate() {
+            match index {
+                0 => header.push(format!("Data source: {}", device.source_name)),
+                _ => header.push(format!("Device {}: {}", device.name, device.source_name)),
+            }
+        }
+        let lines: Vec<String> = self.diagnostic_log.iter().cloned().collect();
+        self.log(&tr_args("log.saving", &[&lines.len(), &path]));
+
+        let tx = self.job_tx.clone();
+        let config_path = self.config_path.clone();
+        thread::spawn(move || {
+            // Hashed so support can tell whether two sites run the same configuration
+            header.push(match std::fs::read(&config_path) {
+                Ok(contents) => {
+                    let hash: String = sha1(&contents).iter().map(|byte| format!("{:02x}", byte)).collect();
+                    format!("Configuration: {} (SHA-1 {})", config_path.display(), hash)
+                }
+                Err(_) => format!("Configuration: {} not found - built-in defaults", config_path.display()),
+            });
+            let text = format!("{}\n\n{}\n", header.join("\n"), lines.join("\n"));
+            let result = std::fs::write(&path, text)
+                .map(|()| tr_args("log.saved", &[&path]))
+                .map_err(|e| tr_args("log.save_failed", &[&path, &e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    fn save_snapshot(&mut self, path: &str) {
+        let snapshot = match self.read_sensors() {
+            Some(data) => data.to_snapshot(&self.device().name, &self.diagnostic_log.iter().cloned().collect::<Vec<_>>()),
+            None => return,
+        };
+
+        let path = path.to_string();
+        let tx = self.job_tx.clone();
+        thread::spawn(move || {
+            let result = std::fs::write(&path, snapshot.to_pretty_string())
+                .map(|()| tr_args("snapshot.saved", &[&path]))
+                .map_err(|e| tr_args("snapshot.save_failed", &[&path, &e]));
+            let _ = tx.send(result);
+        });
+    }
+
+    fn load_snapshot(&mut self, path: &str) {
+        let loaded = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Value::parse(&text).map_err(|e| format!("invalid JSON: {}", e)))
+            .and_then(|snapshot| SensorData::from_snapshot(&snapshot));
+
+        match loaded {
+            Ok((mut data, diagnostic_log)) => {
+                if let Some(mut current) = self.lock_sensors() {
+                    // The link to the data source is unaffected by what's displayed
+                    data.connection = current.connection.clone();
+                    data.link = current.link.clone();
+                    data.trips_inhibited = current.trips_inhibited;
+                    // Alarm filters and calibrations are configured, not saved, and the shift's statistics carry on
+                    for channel in &mut data.channels {
+                        if let Some(configured) = current.channels.iter().find(|configured| configured.name == channel.name) {
+                            channel.filter = configured.filter;
+                            channel.stats = configured.stats.clone();
+                            channel.calibration = configured.calibration.clone();
+                            // Forces stay until they are cleared
+                            channel.forced = configured.forced;
+                            channel.set_calibrated(channel.value);
+                        }
+                    }
+                    // Interlocks and digital points are configured, not saved; the points take their saved states
+                    let saved: Vec<DigitalPoint> = data.digital_inputs.drain(..).chain(data.digital_outputs.drain(..)).collect();
+                    data.digital_inputs = current.digital_inputs.clone();
+                    data.digital_outputs = current.digital_outputs.clone();
+                    for point in data.digital_inputs.iter_mut().chain(&mut data.digital_outputs) {
+                        if let Some(saved) = saved.iter().find(|saved| saved.name == point.name) {
+                            point.set(saved.state);
+                        }
+                    }
+                    data.interlocks = current.interlocks.clone();
+                    data.evaluate_interlocks();
+                    data.control_loop = current.control_loop.clone();
+                    data.supply = current.supply;
+                    *current = data;
+                }
+                // The alarm state machine restarts from the loaded values
+                self.device_mut().reset_alarm_tracking();
+                self.diagnostic_log = RingBuffer::new(DIAGNOSTIC_LOG_LINES);
+                for line in diagnostic_log {
+                    self.diagnostic_log.push(line);
+                }
+                self.notify_warn(&tr_args("snapshot.loaded", &[&path]));
+            }
+            Err(e) => self.notify_error(&tr_args("snapshot.load_failed", &[&path, &e])),
+        }
+    }
+
+    fn print_faults(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+
+        println!("\n{}", tr("fault.title"));
+        for fault in &data.faults {
+            let remaining = fault.until.map_or(String::new(), |until| tr_args("fault.remaining", &[&until.saturating_duration_since(Instant::now()).as_secs()]));
+            print_colored(StatusColor::Yellow, &format!("  ⚠ {:<22} {}{}", fault.target.to_string(), fault.kind, remaining));
+        }
+        let forced: Vec<(IoKind, usize, &DigitalPoint)> = [IoKind::Input, IoKind::Output].into_iter()
+            .flat_map(|kind| data.digital_points(kind).iter().enumerate().map(move |(i, point)| (kind, i + 1, point)))
+            .filter(|(_, _, point)| point.forced)
+            .collect();
+        for (kind, number, point) in &forced {
+            print_colored(StatusColor::Yellow, &format!("  ⚠ {:<22} {}", format!("{} {}", kind, number), tr_args("fault.forced_point", &[&on_off(point.state), &point.name])));
+        }
+        if data.faults.is_empty() && forced.is_empty() {
+            println!("{}", tr("fault.none"));
+        }
+    }
+
+    fn inject_fault(&mut self, target: FaultTarget, kind: FaultKind, lasting: Option<Duration>) {
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.inject_fault(target, kind, lasting),
+            None => return,
+        };
+        match (result, lasting) {
+            (Ok(()), Some(lasting)) => self.log_colored(StatusColor::Yellow, &tr_args("fault.injected_for", &[&target, &kind, &lasting.as_secs()])),
+            (Ok(()), None) => self.log_colored(StatusColor::Yellow, &tr_args("fault.injected", &[&target, &kind])),
+            (Err(reason), _) => self.log_colored(StatusColor::Red, &tr_args("fault.rejected", &[&reason])),
+        }
+    }
+
+    // Clears faults injected for a while once their time is up; called once per tick of
+    // the main loop for each device.
+    fn poll_faults(&mut self, index: usize) {
+        let expired = match self.devices[index].sensor_data.lock() {
+            Ok(mut data) => data.expire_faults(Instant::now()),
+            Err(_) => return,
+        };
+        let label = self.device_label(index);
+        for target in expired {
+            self.log(&tr_args("fault.expired", &[&label, &target]));
+        }
+    }
+
+    fn clear_fault(&mut self, target: Option<FaultTarget>) {
+        let (cleared, released) = match self.lock_sensors() {
+            Some(mut data) => match target {
+                Some(target) => (usize::from(data.clear_fault(target)), 0),
+                None => (data.clear_faults(), data.release_digital_points()),
+            },
+            None => return,
+        };
+        match target {
+            Some(target) if cleared == 0 => println!("{}", tr_args("fault.not_injected", &[&target])),
+            Some(target) => self.log(&tr_args("fault.cleared", &[&target])),
+            None => self.log(&tr_args("fault.cleared_all", &[&cleared, &released])),
+        }
+    }
+
+    // Training control: holds a digital point in a state, e.g. an open guard door
+    fn force_point(&mut self, kind: IoKind, number: usize, state: bool) {
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.digital_point_mut(kind, number).map(|point| {
+                point.force(state);
+                point.name.clone()
+            }),
+            None => return,
+        };
+        match result {
+            Ok(name) => self.log_colored(StatusColor::Yellow, &tr_args("fault.point_forced", &[&kind, &number, &name, &on_off(state)])),
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("fault.rejected", &[&reason])),
+        }
+    }
+
+    fn release_point(&mut self, kind: IoKind, number: usize) {
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.digital_point_mut(kind, number).map(|point| (point.name.clone(), point.release(), point.state)),
+            None => return,
+        };
+        match result {
+            Ok((name, false, _)) => println!("{}", tr_args("fault.point_not_forced", &[&kind, &number, &name])),
+            Ok((name, true, state)) => self.log(&tr_args("fault.point_released", &[&kind, &number, &name, &on_off(state)])),
+            Err(reason) => println!("{}", reason),
+        }
+    }
+
+    fn set_output(&mut self, output: usize, state: bool) {
+        let result = match self.lock_sensors() {
+            Some(mut data) => data.set_output(output, state).map(|point| point.name.clone()),
+            None => return,
+        };
+        match result {
+            Ok(name) => {
+                let shown = tr_args("io.output_switched", &[&output, &name, &on_off(state)]);
+                let recorded = format!("Output {} ({}) switched {}", output, name, on_off(state));
+                self.log_device_action_shown(self.selected, AuditAction::Output, &shown, &recorded);
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("io.output_refused", &[&output, &reason])),
+        }
+    }
+
+    // Every device polls at the same interval
+    fn set_update_interval(&mut self, interval_ms: u64) {
+        let result = self.devices.iter().try_for_each(|device| device.simulation_thread.set_update_interval_ms(interval_ms));
+        match result {
+            Ok(()) => {
+                self.log(&tr_args("simulation.interval_set", &[&interval_ms]));
+                self.settings.update_interval_ms = Some(interval_ms);
+                self.save_settings();
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("simulation.interval_rejected", &[&reason])),
+        }
+    }
+
+    fn scenario_status(&self) -> Option<String> {
+        let player = self.device().scenario.lock().ok()?;
+        let scenario = player.scenario()?;
+        let state = match player.state() {
+            PlaybackState::Ready => tr("scenario.ready"),
+            PlaybackState::Running => tr("scenario.running"),
+            PlaybackState::Paused => tr("scenario.paused"),
+            PlaybackState::Finished => tr("scenario.finished"),
+        };
+        Some(tr_args("scenario.status", &[
+            &scenario.name,
+            &state,
+            &format_elapsed(player.elapsed()),
+            &format_elapsed(scenario.duration()),
+            &player.events_fired(),
+            &scenario.events.len(),
+        ]))
+    }
+
+    fn replay_status(&self) -> Option<String> {
+        let replay = self.device().replay.as_ref()?.lock().ok()?;
+        let state = match replay.state() {
+            ReplayState::Loading => return Some(tr_args("replay.loading", &[&replay.file().display()])),
+            ReplayState::Playing => tr("replay.playing"),
+            ReplayState::Paused => tr("replay.paused"),
+            ReplayState::Complete => tr("replay.complete"),
+        };
+        let recorded = replay.recorded_time().map_or(String::new(), |time| tr_args("replay.recorded", &[&format_datetime(time)]));
+        let skipped = match replay.skipped() {
+            0 => String::new(),
+            skipped => tr_args("replay.skipped", &[&skipped]),
+        };
+        Some(tr_args("replay.status", &[
+            &replay.file().display(),
+            &state,
+            &format_elapsed(replay.position()),
+            &format_elapsed(replay.duration()),
+            &replay.speed(),
+            &replay.rows(),
+            &recorded,
+            &skipped,
+        ]))
+    }
+
+    fn load_replay(&mut self, path: &str) {
+        let file = PathBuf::from(path);
+        if !file.is_file() {
+            self.log_colored(StatusColor::Red, &tr_args("replay.no_file", &[&path]));
+            return;
+        }
+        let label = self.device_label(self.selected);
+        let index = self.selected;
+        self.devices[index].start_replay(ReplayConfig { file, speed: ReplaySpeed::Times(1) });
+        self.log(&tr_args("replay.started", &[&label, &path]));
+    }
+
+    fn end_replay(&mut self) {
+        if self.device().replay.is_none() {
+            println!("{}", tr("replay.not_running"));
+            return;
+        }
+        let index = self.selected;
+        self.devices[index].end_replay();
+        let label = self.device_label(index);
+        self.log(&tr_args("replay.ended", &[&label, &self.devices[index].source_name]));
+    }
+
+    fn control_replay(&mut self, words: &[&str]) {
+        let replay = match &self.device().replay {
+            Some(replay) => Arc::clone(replay),
+            None => {
+                println!("{}", tr("replay.not_running_hint"));
+                return;
+            }
+        };
+        let result = match replay.lock() {
+            Ok(mut replay) => match words {
+                ["play"] => replay.play().map(|()| tr_args("replay.playing_from", &[&format_elapsed(replay.position())])),
+                ["pause"] => replay.pause().map(|()| tr_args("replay.paused_at", &[&format_elapsed(replay.position())])),
+                ["speed", speed] => ReplaySpeed::parse(speed).map(|speed| {
+                    replay.set_speed(speed);
+                    tr_args("replay.speed_set", &[&speed])
+                }),
+                ["seek", position] => match parse_replay_position(position, replay.duration()) {
+                    Some(position) => replay.seek(position).map(|()| tr_args("replay.moved", &[&format_elapsed(position), &format_elapsed(replay.duration())])),
+                    None => Err(tr("replay.usage_seek").to_string()),
+                },
+                _ => Err(tr("replay.usage").to_string()),
+            },
+            Err(_) => return,
+        };
+        match result {
+            Ok(message) => self.log(&message),
+            Err(reason) => println!("{}", tr_args("replay.rejected", &[&words.first().unwrap_or(&""), &reason])),
+        }
+    }
+
+    // Reports each lock a sensor thread panicked while holding, once; called once per tick
+    // of the main loop for each device. Nothing recovers from that short of a restart.
+    fn poll_locks(&mut self, index: usize) {
+        let device = &mut self.devices[index];
+        let newly: Vec<&'static str> = device.poisoned_locks().into_iter().filter(|name| !device.poisoned.contains(name)).collect();
+        device.poisoned.extend(&newly);
+        for name in newly {
+            self.report_error(index, &FisError::LockPoisoned(name));
+            if name == devices::SENSOR_DATA {
+                print_sensor_data_lost();
+            }
+        }
+    }
+
+    // Raises an alarm once a device's sensor thread has exited or stopped going round its
+    // loop for longer than the watchdog limit; called once per tick of the main loop for
+    // each device. Acquisition stays halted until 'restart acquisition'.
+    fn poll_watchdog(&mut self, index: usize) {
+        let device = &self.devices[index];
+        if device.acquisition_halted.is_some() {
+            return;
+        }
+        let age = device.simulation_thread.heartbeat_age();
+        let reason = if !device.simulation_thread.is_running() {
+            FisError::ThreadStopped
+        } else if age > device.watchdog_limit() {
+            FisError::SourceTimeout(age)
+        } else {
+            return;
+        };
+        self.devices[index].acquisition_halted = Some(reason.clone());
+
+        let source = format!("{}{}", self.device_label(index), WATCHDOG_ALARM_SOURCE);
+        let message = tr_args("alarm.halted", &[&source, &reason]);
+        let id = self.alarms.raise(&source, message.clone());
+        self.report_alarm(StatusColor::Red, &format!("ALARM #{} {}", id, message));
+    }
+
+    fn print_source(&self) {
+        let device = self.device();
+        println!("{}", tr_args("source.current", &[&device.source_name]));
+        match device.source_choice() {
+            Some(SourceChoice::Configured) => println!("{}", tr("source.configured")),
+            Some(SourceChoice::Simulation) => println!("{}", tr("source.simulation")),
+            None => println!("{}", tr("source.replay")),
+        }
+        if let Some(name) = device.swap_pending() {
+            println!("{}", tr_args("source.waiting", &[&name]));
+        }
+        if let Some(until) = device.alarm_grace_until {
+            println!("{}", tr_args("source.alarms_held", &[&until.saturating_duration_since(Instant::now()).as_secs()]));
+        }
+        println!("{}", tr("source.hint"));
+    }
+
+    fn swap_source(&mut self, choice: &str) {
+        let choice = match SourceChoice::parse(choice) {
+            Ok(choice) => choice,
+            Err(reason) => {
+                println!("{}", reason);
+                return;
+            }
+        };
+        let index = self.selected;
+        match self.devices[index].begin_swap(choice) {
+            Ok(name) => self.log(&tr_args("source.switching", &[&self.device_label(index), &name])),
+            Err(reason) => println!("{}", tr_args("source.not_switched", &[&reason])),
+        }
+    }
+
+    // Hands a device over to a new data source once it has answered, or reports why the
+    // switch was given up, and says when alarms are checked again; called once per tick of
+    // the main loop for each device.
+    fn poll_source_swap(&mut self, index: usize) {
+        let label = self.device_label(index);
+        match self.devices[index].finish_swap() {
+            Some(Ok(previous)) => {
+                let device = &self.devices[index];
+                let shown = tr_args("source.switched", &[&previous, &device.source_name, &device.swap_grace.as_secs()]);
+                let recorded = format!(
+                    "Data source switched from {} to {}; readings are stale until it answers and alarms are held off for {} s",
+                    previous, device.source_name, device.swap_grace.as_secs()
+                );
+                self.log_device_action_shown(index, AuditAction::SourceSwap, &shown, &recorded);
+            }
+            Some(Err(reason)) => {
+                let current = self.devices[index].source_name.clone();
+                self.notify_error(&tr_args("source.switch_failed", &[&label, &reason, &current]));
+            }
+            None => {}
+        }
+        if self.devices[index].alarm_grace_until.is_some_and(|until| Instant::now() >= until) {
+            self.devices[index].alarm_grace_until = None;
+            self.log(&tr_args("source.alarms_resumed", &[&label]));
+        }
+    }
+
+    fn restart_acquisition(&mut self) {
+        let index = self.selected;
+        let halted = self.devices[index].acquisition_halted.is_some();
+        self.devices[index].restart_acquisition();
+        // The API serves the first device's readings
+        if let (0, Some(api)) = (index, &self.api) {
+            api.follow(Arc::clone(&self.devices[0].sensor_data));
+        }
+        let source = format!("{}{}", self.device_label(index), WATCHDOG_ALARM_SOURCE);
+        let shown = tr_args("source.restarted", &[&self.devices[index].source_name]);
+        let recorded = format!("Data acquisition restarted on {}", self.devices[index].source_name);
+        self.log_device_action_shown(index, AuditAction::Restart, &shown, &recorded);
+        if halted {
+            self.alarms.clear(&source);
+            self.report_alarm(StatusColor::Green, &format!("CLEARED {}: {}", source, tr("alarm.thread_restarted")));
+        }
+    }
+
+    // Warns when readings stop arriving, whatever the cause (paused, link down, source hung),
+    // and clears the warning on the next fresh update; called once per tick of the main loop
+    // for each device.
+    fn poll_staleness(&mut self, index: usize) {
+        let device = &self.devices[index];
+        let (stale, waiting) = match device.sensor_data.lock() {
+            Ok(data) => (data.staleness(SystemTime::now(), device.update_interval()), data.last_update.is_none()),
+            Err(_) => return,
+        };
+        // After a source switch the readings count as stale until the new source's first update
+        if stale.is_some() == device.data_stale || (device.data_stale && waiting) {
+            return;
+        }
+        self.devices[index].data_stale = stale.is_some();
+        let label = self.device_label(index);
+        match stale {
+            Some(age) => self.log_colored(StatusColor::Yellow, &tr_args("stale.raised", &[&label, &self.devices[index].source_name, &decimal(age.as_secs_f32(), 0)])),
+            None => self.log_colored(StatusColor::Green, &tr_args("stale.cleared", &[&label])),
+        }
+    }
+
+    // Moves the device between modes as its startup checks finish, its link fails and
+    // recovers, and shutdowns happen other than through the console (e.g. a loaded
+    // snapshot); called once per tick of the main loop for each device, after the
+    // connection and staleness checks.
+    fn poll_mode(&mut self, index: usize) {
+        let device = &self.devices[index];
+        let (shut_down, online, healthy) = match device.sensor_data.lock() {
+            Ok(data) => (
+                data.emergency_shutdown,
+                data.last_update.is_some(),
+                data.link.health() == LinkHealth::Connected && !device.data_stale && device.acquisition_halted.is_none(),
+            ),
+            Err(_) => return,
+        };
+        let (event, shown, cause) = match (device.mode, shut_down) {
+            (SystemMode::EStop, false) => (ModeEvent::Reset, tr("mode.cause_cleared"), "the emergency shutdown was cleared"),
+            (SystemMode::EStop, true) => return,
+            (_, true) => (ModeEvent::Shutdown, tr("mode.cause_shutdown"), "emergency shutdown active"),
+            // The startup diagnostic has its own say before the device is called running
+            (SystemMode::Startup, _) if self.diagnostics.is_running() || !online => return,
+            (SystemMode::Startup, _) if healthy => (ModeEvent::StartupComplete, tr("mode.cause_started"), "startup checks done"),
+            (SystemMode::Startup | SystemMode::Running, _) if !healthy => {
+                (ModeEvent::LinkLost, tr("mode.cause_link_lost"), "link to the data source failing or readings stale")
+            }
+            (SystemMode::Degraded, _) if healthy => (ModeEvent::LinkRestored, tr("mode.cause_link_restored"), "link to the data source healthy"),
+            _ => return,
+        };
+        let _ = self.transition(index, event, shown, cause);
+    }
+
+    // The one way a device changes mode: checked against the transition table, applied
+    // and audited, `shown_cause` on the console and `cause` in the audit trail. Returns the
+    // mode it is now in.
+    fn transition(&mut self, index: usize, event: ModeEvent, shown_cause: &str, cause: &str) -> Result<SystemMode, String> {
+        let from = self.devices[index].mode;
+        let to = from.next(event)?;
+        if to == from {
+            return Ok(to);
+        }
+        self.devices[index].mode = to;
+        if let Ok(mut data) = self.devices[index].sensor_data.lock() {
+            data.trips_inhibited = !to.auto_trip_enabled();
+        }
+        let shown = tr_args("mode.changed", &[&self.device_label(index), &from.label(), &to.label(), &shown_cause]);
+        let recorded = format!("{}Mode {} -> {} ({})", self.device_label(index), from, to, cause);
+        self.log_action_shown(AuditAction::ModeChange, &shown, &recorded);
+        Ok(to)
+    }
+
+    fn print_mode(&self) {
+        for (index, device) in self.devices.iter().enumerate() {
+            print_colored(device.mode.color(), &tr_args("mode.line", &[&self.device_label(index), &device.mode.label()]));
+        }
+        let mode = self.device().mode;
+        let trips = if mode.auto_trip_enabled() { tr("mode.armed") } else { tr("mode.off") };
+        let starts = if mode.motor_starts_allowed().is_ok() { tr("mode.allowed") } else { tr("mode.barred") };
+        println!("{}", tr_args("mode.summary", &[&trips, &starts]));
+    }
+
+    fn change_mode(&mut self, to: &str) {
+        let (event, shown, cause) = match to {
+            "maintenance" => (ModeEvent::EnterMaintenance, tr("mode.cause_maintenance"), "entered by hand: auto-trip off, motor starts barred"),
+            "run" | "running" => (ModeEvent::LeaveMaintenance, tr("mode.cause_maintenance_done"), "maintenance finished"),
+            _ => return println!("{}", tr("mode.usage")),
+        };
+        if let Err(reason) = self.transition(self.selected, event, shown, cause) {
+            self.log_colored(StatusColor::Red, &tr_args("mode.refused", &[&self.device_label(self.selected), &reason]));
+        }
+    }
+
+    // Warns once a device's scan cycle has overrun its target for scan::OVERRUN_CYCLES in a
+    // row, and again when it is back; called once per tick of the main loop for each device.
+    fn poll_scan(&mut self, index: usize) {
+        let (streak, last) = match self.devices[index].sensor_data.lock() {
+            Ok(data) => (data.scan.consecutive_overruns, data.scan.last()),
+            Err(_) => return,
+        };
+        let overrun = streak >= scan::OVERRUN_CYCLES;
+        if overrun == self.devices[index].scan_overrun || (!overrun && streak > 0) {
+            return;
+        }
+        self.devices[index].scan_overrun = overrun;
+        let label = self.device_label(index);
+        match last {
+            Some(cycle) if overrun => self.notify_warn(&tr_args("perf.overrun", &[
+                &label,
+                &streak,
+                &decimal(scan::OVERRUN_FACTOR as f32 * 100.0, 0),
+                &cycle.target.as_millis(),
+                &cycle.period.as_millis(),
+            ])),
+            _ => self.log_colored(StatusColor::Green, &tr_args("perf.overrun_cleared", &[&label])),
+        }
+    }
+
+    fn print_performance(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let scan = &data.scan;
+        let millis = |duration: Option<Duration>| duration.map_or("--".to_string(), |duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0));
+        println!("\n{}", tr_args("perf.title", &[&self.device().name]));
+        if scan.is_empty() {
+            println!("{}", tr("perf.no_cycles"));
+        } else {
+            let last = scan.last();
+            println!("{}", tr_args("perf.cycle", &[
+                &millis(last.map(|cycle| cycle.target)),
+                &millis(last.map(|cycle| cycle.period)),
+                &millis(scan.average(|cycle| cycle.period)),
+                &millis(scan.max(|cycle| cycle.period)),
+                &scan.len(),
+            ]));
+            let color = if scan.consecutive_overruns >= scan::OVERRUN_CYCLES { StatusColor::Yellow } else { StatusColor::Normal };
+            print_colored(color, &tr_args("perf.overruns", &[
+                &decimal(scan::OVERRUN_FACTOR as f32 * 100.0, 0),
+                &scan.overruns,
+                &scan.cycles,
+                &scan.consecutive_overruns,
+                &scan::OVERRUN_CYCLES,
+            ]));
+            println!("{}", tr_args("perf.poll_time", &[&millis(scan.average(|cycle| cycle.poll)), &millis(scan.max(|cycle| cycle.poll))]));
+            println!("{}", tr_args("perf.sensor_lock", &[&millis(scan.average(|cycle| cycle.lock_wait)), &millis(scan.max(|cycle| cycle.lock_wait))]));
+            println!("{}", tr_args("perf.jitter", &[&decimal(scan.jitter_ms().unwrap_or_default() as f32, 1)]));
+            print_jitter_histogram(scan);
+        }
+        let loop_times: Vec<Duration> = self.loop_times.iter().copied().collect();
+        let average = (!loop_times.is_empty()).then(|| loop_times.iter().sum::<Duration>() / loop_times.len() as u32);
+        println!("{}", tr_args("perf.console_loop", &[&millis(average), &millis(loop_times.iter().max().copied()), &loop_times.len()]));
+        match &self.historian {
+            Some(historian) => println!("{}", tr_args("perf.historian", &[&historian.backlog(), &millis(Some(historian.lag()))])),
+            None => println!("{}", tr("perf.historian_stopped")),
+        }
+    }
+
+    // Prometheus text for GET /metrics, covering every device; devices whose sensor data
+    // can't be read are left out
+    fn scrape(&self) -> String {
+        let readings: Vec<(&str, SensorData)> = self.devices.iter()
+            .filter_map(|device| device.sensor_data.lock().ok().map(|data| (device.name.as_str(), data.clone())))
+            .collect();
+        let targets: Vec<prometheus::Target> = readings.iter().map(|(system_id, data)| prometheus::Target { system_id, data }).collect();
+        prometheus::render(&targets, &self.alarms)
+    }
+
+    // For GET /api/v1/metrics; the scan figures are the first device's, like the rest of the API
+    fn metrics_json(&self) -> Value {
+        let millis = |duration: Option<Duration>| duration.map_or(Value::Null, |duration| (duration.as_secs_f64() * 1000.0).into());
+        let scan = self.lock_device(0).map_or(Value::Null, |data| data.scan.to_json());
+        let loop_times: Vec<Duration> = self.loop_times.iter().copied().collect();
+        let average = (!loop_times.is_empty()).then(|| loop_times.iter().sum::<Duration>() / loop_times.len() as u32);
+        let historian = self.historian.as_ref().map_or(Value::Null, |historian| Value::Object(vec![
+            ("backlog_rows".to_string(), (historian.backlog() as u64).into()),
+            ("lag_ms".to_string(), millis(Some(historian.lag()))),
+        ]));
+        Value::Object(vec![
+            ("system_id".to_string(), self.devices[0].name.as_str().into()),
+            ("scan".to_string(), scan),
+            ("console_loop".to_string(), Value::Object(vec![
+                ("average_ms".to_string(), millis(average)),
+                ("max_ms".to_string(), millis(loop_times.iter().max().copied())),
+            ])),
+            ("historian".to_string(), historian),
+        ])
+    }
+
+    // Logs the link to a data source moving between connected, degraded and offline, and
+    // raises an alarm while it is offline; called once per tick of the main loop for each device.
+    fn poll_connection(&mut self, index: usize) {
+        let (connection, link) = match self.devices[index].sensor_data.lock() {
+            Ok(data) => (data.connection.clone(), data.link.clone()),
+            Err(_) => return,
+        };
+        if connection == ConnectionStatus::Connecting {
+            return;
+        }
+        let health = link.health();
+        let previous = match self.devices[index].link_health.replace(health) {
+            Some(previous) if previous == health => return,
+            previous => previous,
+        };
+
+        let label = self.device_label(index);
+        let source = format!("{}{}", label, LINK_ALARM_SOURCE);
+        let source_name = self.devices[index].source_name.clone();
+        if previous == Some(LinkHealth::Offline) {
+            self.alarms.clear(&source);
+            self.report_alarm(StatusColor::Green, &format!("CLEARED {}: {}", source, tr_args("alarm.link_restored", &[&source_name, &link_summary(&link)])));
+        }
+        match health {
+            LinkHealth::Offline => {
+                let reason = match connection {
+                    ConnectionStatus::Offline { reason, .. } => reason,
+                    _ => tr("status.no_response").to_string(),
+                };
+                let message = tr_args("alarm.link_offline", &[&source, &source_name, &link.consecutive_failures, &reason]);
+                let id = self.alarms.raise(&source, message.clone());
+                self.report_alarm(StatusColor::Red, &format!("ALARM #{} {}", id, message));
+            }
+            LinkHealth::Degraded => {
+                let reason = link.degraded_reason().unwrap_or_default();
+                self.log_colored(StatusColor::Yellow, &tr_args("alarm.link_degraded", &[&label, &source_name, &reason]));
+            }
+            LinkHealth::Connected if previous == Some(LinkHealth::Offline) => {}
+            LinkHealth::Connected => self.log_colored(StatusColor::Green, &tr_args("alarm.link_connected", &[&label, &source_name, &link_summary(&link)])),
+        }
+    }
+
+    fn print_link(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let link = &data.link;
+        let thresholds = link.thresholds;
+        println!("\n{}", tr_args("link.title", &[&self.device().source_name]));
+        if link.polls == 0 {
+            println!("{}", tr("link.no_polls"));
+            return;
+        }
+        let health = link.health();
+        let reason = link.degraded_reason().map_or(String::new(), |reason| format!(" - {}", reason));
+        let color = match health {
+            LinkHealth::Connected => StatusColor::Green,
+            LinkHealth::Degraded => StatusColor::Yellow,
+            LinkHealth::Offline => StatusColor::Red,
+        };
+        print_colored(color, &tr_args("link.health", &[&health, &reason]));
+        let millis = |latency: Option<Duration>| latency.map_or("--".to_string(), |latency| format!("{} ms", latency.as_millis()));
+        println!("{}", tr_args("link.round_trip", &[
+            &millis(link.last_latency()),
+            &millis(link.average_latency()),
+            &millis(link.max_latency()),
+            &thresholds.degraded_latency.as_millis(),
+        ]));
+        println!("{}", tr_args("link.loss", &[&decimal(link.loss_percent(), 0), &link.polls.min(WINDOW as u64), &thresholds.degraded_loss_percent]));
+        println!("{}", tr_args("link.polls", &[&link.polls, &link.failures]));
+        println!("{}", tr_args("link.disconnects", &[&link.disconnects, &thresholds.offline_failures, &format_elapsed(link.downtime())]));
+        if link.consecutive_failures > 0 {
+            println!("{}", tr_args("link.failed_row", &[&link.consecutive_failures, &thresholds.offline_failures]));
+        } else {
+            println!("{}", tr_args("link.answered_row", &[&link.consecutive_successes]));
+        }
+        println!("{}", tr_args("link.recent", &[&link.sparkline()]));
+        println!("{}", tr("link.recent_legend"));
+    }
+
+    // Logs the MQTT publisher connecting and disconnecting; called once per tick of the main loop.
+    fn poll_mqtt(&mut self) {
+        let (status, broker) = match &self.mqtt {
+            Some(mqtt) => (mqtt.status(), mqtt.broker().to_string()),
+            None => return,
+        };
+        if status == self.mqtt_status {
+            return;
+        }
+        // Repeated failures with a new reason aren't worth a log line each
+        let was_disconnected = matches!(self.mqtt_status, MqttStatus::Disconnected { .. });
+        self.mqtt_status = status.clone();
+
+        match status {
+            MqttStatus::Connecting => {}
+            MqttStatus::Connected => self.log_colored(StatusColor::Green, &tr_args("mqtt.connected", &[&broker])),
+            MqttStatus::Disconnected { .. } if was_disconnected => {}
+            MqttStatus::Disconnected { reason } => self.log_colored(StatusColor::Red, &tr_args("mqtt.disconnected", &[&broker, &reason])),
+        }
+    }
+
+    // Answers REST API requests that need the console's state; called once per tick of the main loop.
+    fn poll_api(&mut self) {
+        let requests: Vec<ApiRequest> = match &self.api {
+            Some(api) => api.pending_requests(),
+            None => return,
+        };
+        for request in requests {
+            match request {
+                ApiRequest::Alarms { reply } => {
+                    let _ = reply.send(Value::Array(self.alarms.iter().map(|alarm| alarm.to_json()).collect()));
+                }
+                ApiRequest::Metrics { reply } => {
+                    let _ = reply.send(self.metrics_json());
+                }
+                ApiRequest::Scrape { reply } => {
+                    let _ = reply.send(self.scrape());
+                }
+                // Every device is shut down; the API has no way to name one
+                ApiRequest::EmergencyShutdown { client, reply } => {
+                    self.log_colored(StatusColor::Red, &tr_args("api.estop", &[&client.ip()]));
+                    // The emergency shutdown needs no role; the client is named by its address
+                    let actor = Actor::Api { client: client.ip().to_string(), role: Role::Operator };
+                    for index in 0..self.devices.len() {
+                        let _ = self.execute(Command::EmergencyShutdown { device: index, reason: format!("REST API request from {}", client.ip()) }, &actor);
+                    }
+                    let _ = reply.send(());
+                }
+                ApiRequest::Write { client, role, revision, command, reply } => {
+                    let outcome = self.api_write(&client, role, revision, command);
+                    let _ = reply.send(outcome);
+                }
+            }
+        }
+    }
+
+    // A REST API write, carried out on the first device like any other command, for the
+    // client with the role it claimed
+    fn api_write(&mut self, client: &str, role: Role, revision: u64, command: WriteCommand) -> WriteOutcome {
+        if revision != self.revision.load(Ordering::Relaxed) {
+            return WriteOutcome::Conflict(revision);
+        }
+        let resolved = match self.lock_device(0) {
+            Some(data) => match &command {
+                WriteCommand::Setpoint { motor, rpm } => find_motor(&data, motor).map(|motor| Command::SetSetpoint { device: 0, motor, rpm: *rpm }),
+                WriteCommand::MotorRunning { motor, running: true } => find_motor(&data, motor).map(|motor| Command::StartMotor { device: 0, motor }),
+                WriteCommand::MotorRunning { motor, running: false } => find_motor(&data, motor).map(|motor| Command::StopMotor { device: 0, motor }),
+                WriteCommand::Limit { channel, limit, value } => find_channel(&data, channel).map(|(kind, channel)| {
+                    // Given in °C or kPa, like the readings the API serves
+                    Command::SetLimit { device: 0, kind, channel, limit: limit.clone(), value: self.units.get(kind).from_si(*value) }
+                }),
+            },
+            None => return WriteOutcome::Rejected("sensor data is unavailable".to_string()),
+        };
+        let resolved = match resolved {
+            Some(resolved) => resolved,
+            None => return WriteOutcome::NotFound(match &command {
+                WriteCommand::Limit { channel, .. } => format!("no channel with the name, tag or asset ID '{}'", channel),
+                WriteCommand::Setpoint { motor, .. } | WriteCommand::MotorRunning { motor, .. } => format!("no motor with the number, name, tag or asset ID '{}'", motor),
+            }),
+        };
+        self.log(&tr_args("api.client_write", &[&client, &role, &resolved]));
+        match self.execute(resolved, &Actor::Api { client: client.to_string(), role }) {
+            Ok(()) => WriteOutcome::Done(self.revision.load(Ordering::Relaxed)),
+            Err(CommandError::Forbidden(reason)) => WriteOutcome::Forbidden(reason),
+            Err(CommandError::EmergencyShutdown) => WriteOutcome::Locked(CommandError::EmergencyShutdown.to_string()),
+            Err(CommandError::Mode(reason) | CommandError::Refused(reason)) => WriteOutcome::Rejected(reason),
+        }
+    }
+
+    // Logs scenario events fired by a simulation thread and carries out its motor events;
+    // called once per tick of the main loop for each device.
+    fn poll_scenario(&mut self, index: usize) {
+        let (messages, actions) = match self.devices[index].scenario.lock() {
+            Ok(mut player) => (player.take_messages(), player.take_commands()),
+            Err(_) => return,
+        };
+        let label = self.device_label(index);
+        for message in messages {
+            self.log_colored(StatusColor::Yellow, &format!("{}{}", label, message));
+        }
+        // Refusals are logged by execute
+        for command in actions.iter().filter_map(|action| action.command(index)) {
+            let _ = self.execute(command, &Actor::System);
+        }
+    }
+
+    fn load_scenario(&mut self, path: &str) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let scenario = match Scenario::load(path).and_then(|scenario| scenario.check_channels(&data).map(|()| scenario)) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                self.log_colored(StatusColor::Red, &tr_args("scenario.load_failed", &[&path, &e]));
+                return;
+            }
+        };
+
+        let summary = tr_args("scenario.loaded", &[&scenario.name, &path, &scenario.events.len(), &format_elapsed(scenario.duration())]);
+        let description = scenario.description.clone();
+        // Overrides left by a previous scenario would otherwise hold their channels forever
+        if let Some(mut data) = self.lock_sensors() {
+            data.overrides.clear();
+        }
+        if let Err(e) = error::lock(&self.device().scenario, devices::SCENARIO).map(|mut player| player.load(scenario)) {
+            return self.report_error(self.selected, &e);
+        }
+        self.log(&summary);
+        if !description.is_empty() {
+            println!("  {}", description);
+        }
+    }
+
+    fn control_scenario(&mut self, action: &str) {
+        let result = match error::lock(&self.device().scenario, devices::SCENARIO).map(|mut player| match action {
+                "start" => player.start().map(|()| tr_args("scenario.started", &[&format_elapsed(player.elapsed())])),
+                "pause" => player.pause().map(|()| tr_args("scenario.paused_at", &[&format_elapsed(player.elapsed())])),
+                _ => {
+                    player.reset();
+                    Ok(tr("scenario.reset").to_string())
+                }
+            }) {
+            Ok(result) => result,
+            Err(e) => return self.report_error(self.selected, &e),
+        };
+
+        match result {
+            Ok(message) => {
+                if action == "reset" {
+                    if let Some(mut data) = self.lock_sensors() {
+                        data.overrides.clear();
+                        data.clear_faults();
+                    }
+                }
+                self.log(&message);
+            }
+            Err(reason) => println!("{}", tr_args("scenario.rejected", &[&action, &reason])),
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        let paused = !self.device().simulation_thread.is_paused();
+        self.device().simulation_thread.set_paused(paused);
+        if paused {
+            self.log_colored(StatusColor::Yellow, tr("simulation.paused"));
+        } else {
+            self.log(tr("simulation.unpaused"));
+        }
+    }
+
+    fn resume_simulation(&mut self) {
+        let was_frozen = match self.lock_sensors() {
+            Some(mut data) => std::mem::replace(&mut data.frozen, false),
+            None => return,
+        };
+        if was_frozen {
+            self.log(tr("simulation.resumed"));
+        } else {
+            println!("{}", tr("simulation.not_frozen"));
+        }
+    }
+
+    fn print_ambient(&self) {
+        let ambient = match self.read_sensors() {
+            Some(data) => data.ambient,
+            None => return,
+        };
+        let (ambient, process) = match (ambient, &self.device().simulation.model) {
+            (Some(ambient), SimulationModel::Process(process)) => (ambient, process),
+            _ => {
+                println!("{}", tr_args("ambient.none", &[&self.device().source_name]));
+                return;
+            }
+        };
+        let weather = &process.weather;
+        let held = |held: bool| if held { tr("ambient.held") } else { "" };
+        println!("\n{}", tr_args("ambient.title", &[&self.device().name]));
+        println!("{}", tr_args("ambient.air", &[&self.units.temperature.format(ambient.temperature), &held(ambient.temperature_held)]));
+        println!("{}", tr_args("ambient.air_swing", &[&decimal(weather.daily_swing, 1), &decimal(process.ambient, 1), &format_span(weather.day)]));
+        println!("{}", tr_args("ambient.barometer", &[&self.units.pressure.format(ambient.pressure), &held(ambient.pressure_held)]));
+        println!("{}", tr_args("ambient.barometer_swing", &[&decimal(weather.barometer_swing, 1), &decimal(weather.barometer, 1), &decimal(weather.barometer, 1)]));
+    }
+
+    // Training control: holds the air temperature or the barometer at a value in the display units
+    fn set_ambient(&mut self, kind: ChannelKind, value: f32) {
+        let si = self.units.get(kind).to_si(value);
+        let label = self.device_label(self.selected);
+        let set = match self.lock_sensors() {
+            Some(mut data) => match &mut data.ambient {
+                Some(ambient) => {
+                    match kind {
+                        ChannelKind::Temperature => (ambient.temperature, ambient.temperature_held) = (si, true),
+                        ChannelKind::Pressure => (ambient.pressure, ambient.pressure_held) = (si, true),
+                    }
+                    true
+                }
+                None => false,
+            },
+            None => return,
+        };
+        match (set, kind) {
+            (true, ChannelKind::Temperature) => self.log(&tr_args("ambient.air_held", &[&label, &self.units.format(kind, si)])),
+            (true, ChannelKind::Pressure) => self.log(&tr_args("ambient.barometer_held", &[&label, &self.units.format(kind, si)])),
+            (false, _) => println!("{}", tr("ambient.none_to_set")),
+        }
+    }
+
+    fn release_ambient(&mut self) {
+        let label = self.device_label(self.selected);
+        let released = match self.lock_sensors() {
+            Some(mut data) => match &mut data.ambient {
+                Some(ambient) if ambient.temperature_held || ambient.pressure_held => {
+                    ambient.release();
+                    true
+                }
+                _ => false,
+            },
+            None => return,
+        };
+        if released {
+            self.log(&tr_args("ambient.released", &[&label]));
+        } else {
+            println!("{}", tr("ambient.not_held"));
+        }
+    }
+
+    fn run_diagnostic(&mut self, trigger: DiagnosticTrigger) {
+        if let Some(running) = self.diagnostics.running() {
+            self.log(&tr_args("diag.busy", &[&running.label()]));
+            return;
+        }
+
+        let device = self.device();
+        self.diagnostics.start(DiagnosticContext {
+            sensor_data: Arc::clone(&device.sensor_data),
+            thread_running: device.simulation_thread.is_running(),
+            paused: device.simulation_thread.is_paused(),
+            update_interval: device.update_interval(),
+            historian: self.historian_directory.clone(),
+            units: self.units,
+        }, trigger);
+        println!("\n{}", tr("diag.title"));
+        // Only a run someone asked for is a control action
+        match trigger {
+            DiagnosticTrigger::Scheduled => self.log(&tr_args("diag.scheduled_started", &[&self.device().name, &self.detected_os])),
+            _ => {
+                let shown = tr_args("diag.started", &[&self.device().name, &self.detected_os]);
+                let recorded = format!("Diagnostic started on {} ({})", self.device().name, self.detected_os);
+                self.log_action_shown(AuditAction::Diagnostic, &shown, &recorded);
+            }
+        }
+        if self.compatibility_override {
+            self.log(tr("diag.compatibility_override"));
+        }
+    }
+
+    // Reports progress from the diagnostic worker; called once per tick of the main loop.
+    fn poll_diagnostic(&mut self) {
+        for update in self.diagnostics.poll() {
+            match update {
+                DiagnosticUpdate::Step { event, done } => {
+                    let progress = progress_bar(done, DIAGNOSTIC_STEPS.len());
+                    let elapsed = event.elapsed.as_millis();
+                    match &event.result {
+                        CheckResult::Pass => {
+                            self.log_colored(StatusColor::Green, &tr_args("diag.step_ok", &[&progress, &event.step, &elapsed]));
+                        }
+                        CheckResult::Warn(reason) => {
+                            self.log_colored(StatusColor::Yellow, &tr_args("diag.step_warning", &[&progress, &event.step, &elapsed, &reason]));
+                        }
+                        CheckResult::Fail(reason) => {
+                            self.log_colored(StatusColor::Red, &tr_args("diag.step_failed", &[&progress, &event.step, &elapsed, &reason]));
+                        }
+                    }
+                    for line in event.details {
+                        println!("{}", line);
+                        self.diagnostic_log.push(line);
+                    }
+                }
+                DiagnosticUpdate::Finished { trigger, result, elapsed, skipped } => {
+                    match &result {
+                        Ok(_) => {
+                            let (color, outcome) = diagnostic_outcome(&result);
+                            self.log_colored(color, &tr_args("diag.complete", &[&outcome]));
+                            self.log(&tr_args("diag.completed_in", &[&elapsed.as_millis()]));
+                        }
+                        Err(e) => {
+                            self.log_colored(StatusColor::Red, &tr_args("diag.aborted", &[&elapsed.as_millis(), &e, &skipped]));
+                        }
+                    }
+                    self.update_diagnostic_alarm(trigger, &result);
+                }
+            }
+        }
+
+        // A run that comes due while another is in progress waits for it to finish
+        let due = self.next_scheduled_diagnostic.is_some_and(|due| Instant::now() >= due);
+        if due && !self.diagnostics.is_running() {
+            self.next_scheduled_diagnostic = self.diagnostic_interval.map(|interval| Instant::now() + interval);
+            self.run_diagnostic(DiagnosticTrigger::Scheduled);
+        }
+    }
+
+    // Nobody is watching a scheduled run, so a failed one raises an alarm. Any later run
+    // without failures clears it.
+    fn update_diagnostic_alarm(&mut self, trigger: DiagnosticTrigger, result: &Result<DiagnosticSummary, String>) {
+        let failed = !result.as_ref().is_ok_and(|summary| summary.failures == 0);
+        let message = if failed && trigger == DiagnosticTrigger::Scheduled {
+            let (_, outcome) = diagnostic_outcome(result);
+            let message = tr_args("alarm.diagnostic_failed", &[&DIAGNOSTIC_ALARM_SOURCE, &outcome]);
+            self.alarms.clear(DIAGNOSTIC_ALARM_SOURCE);
+            let id = self.alarms.raise(DIAGNOSTIC_ALARM_SOURCE, message.clone());
+            self.diagnostic_alarm = true;
+            format!("ALARM #{} {}", id, message)
+        } else if !failed && std::mem::replace(&mut self.diagnostic_alarm, false) {
+            self.alarms.clear(DIAGNOSTIC_ALARM_SOURCE);
+            format!("CLEARED {}: {}", DIAGNOSTIC_ALARM_SOURCE, tr_args("alarm.diagnostic_passed", &[&trigger.label()]))
+        } else {
+            return;
+        };
+
+        self.report_alarm(if failed { StatusColor::Red } else { StatusColor::Green }, &message);
+    }
+
+    // A misplaced estop shouldn't halt the plant, so the operator has to confirm it.
+    // With no device given, every device is shut down.
+    fn request_emergency_shutdown(&mut self, device: Option<usize>, reason: String) {
+        let target = match device {
+            Some(index) if self.devices.len() > 1 => tr_args("estop.of_device", &[&self.devices[index].name]),
+            Some(_) => tr("estop.of_motors").to_string(),
+            None => tr_args("estop.of_all", &[&self.devices.len()]),
+        };
+        print_colored(StatusColor::Red, &tr_args("estop.confirm", &[&target]));
+        self.pending_estop = Some((device, reason));
+    }
+
+    fn emergency_shutdown(&mut self, index: usize, reason: &str) {
+        let label = self.device_label(index);
+        println!("\n{}", tr_args("estop.initiated", &[&label]));
+        // Shutting down is the safe action even if the sensor thread died mid-update
+        let device = &mut self.devices[index];
+        let (mut data, poisoned) = error::lock_or_recover(&device.sensor_data);
+        let auto_trip = data.auto_trip.as_ref().map(|trip| trip.describe(self.units));
+        let already_shut_down = data.emergency_shutdown;
+        let bypassed: Vec<String> = data.interlocks.iter().filter(|interlock| interlock.bypass.is_some()).map(|interlock| interlock.name.clone()).collect();
+        data.trip_emergency_shutdown();
+        for motor in 0..data.motors.len() {
+            device.simulation_thread.send_command(SourceCommand::MotorRunning { motor, running: false });
+        }
+        drop(data);
+        println!("{}", tr("estop.stopped"));
+        if !device.restart_queue.is_empty() {
+            device.restart_queue.clear();
+            self.log_colored(StatusColor::Red, &tr_args("estop.restart_abandoned", &[&label]));
+        }
+        if poisoned {
+            self.report_error(index, &FisError::LockPoisoned(devices::SENSOR_DATA));
+            self.log_colored(StatusColor::Red, &tr_args("estop.stopped_anyway", &[&label]));
+        }
+        let (shown_reason, reason) = match reason {
+            "" => (tr("estop.no_reason"), "none given"),
+            reason => (reason, reason),
+        };
+        // A second shutdown keeps the first one's first-out and trip record
+        let first_out = match already_shut_down {
+            true => None,
+            false => self.record_first_out(index),
+        };
+        let shown_note = first_out.as_ref().map_or(String::new(), |first_out| tr_args("estop.first_out_note", &[first_out]));
+        let first_out_note = first_out.as_ref().map_or(String::new(), |first_out| format!(" - first-out: {}", first_out));
+        let shown = tr_args("estop.executed", &[&shown_reason, &shown_note]);
+        self.log_device_action_shown(index, AuditAction::EmergencyShutdown, &shown, &format!("Emergency shutdown executed - reason: {}{}", reason, first_out_note));
+        if !bypassed.is_empty() {
+            let bypassed = bypassed.join(", ");
+            let shown = tr_args("estop.bypasses_cleared", &[&bypassed]);
+            self.log_device_action_shown(index, AuditAction::Bypass, &shown, &format!("Interlock bypasses cleared by the emergency shutdown: {}", bypassed));
+        }
+        if !already_shut_down {
+            self.capture_trip(index, reason, auto_trip.clone(), first_out);
+        }
+        let _ = self.transition(index, ModeEvent::Shutdown, shown_reason, reason);
+        let kind = if auto_trip.is_some() { NotificationKind::AutoTrip } else { NotificationKind::EmergencyShutdown };
+        self.notify(kind, &format!("{}E-stop", label), &format!("{}Emergency shutdown - reason: {}", label, reason));
+    }
+
+    // Picks the device's first alarm of the lead-up to a shutdown and marks it in the alarm
+    // list, the banner and the historian. Returns it as the shutdown's audit entry gives it.
+    fn record_first_out(&mut self, index: usize) -> Option<String> {
+        let since = SystemTime::now().checked_sub(alarms::FIRST_OUT_WINDOW).unwrap_or(UNIX_EPOCH);
+        let alarm = self.alarms.first_out(&self.device_label(index), since).cloned();
+        self.devices[index].first_out = alarm.clone();
+        let alarm = alarm?;
+        self.alarms.mark_first_out(alarm.id);
+        let first_out = format!("#{} {} (raised {})", alarm.id, alarm.message, format_time_precise(alarm.raised_at));
+        self.log_colored(StatusColor::Red, &tr_args("estop.first_out", &[&first_out]));
+        if let Some(historian) = &self.historian {
+            historian.record_alarm(&format!("FIRST-OUT {}", first_out));
+        }
+        Some(first_out)
+    }
+
+    // Starts capturing the readings around a shutdown that just happened; the record is
+    // written half a minute later whatever happens at the console meanwhile
+    fn capture_trip(&mut self, index: usize, reason: &str, auto_trip: Option<String>, first_out: Option<String>) {
+        let channels = match self.lock_device(index) {
+            Some(data) => data.channels.iter().map(|channel| TripChannel { label: channel.label(), kind: channel.kind, limits: channel.limits, samples: Vec::new() }).collect(),
+            None => return,
+        };
+        let label = self.device_label(index);
+        let diagnostic = match (self.diagnostics.running(), self.diagnostics.history().back()) {
+            (Some(trigger), _) => format!("{} run in progress", trigger),
+            (None, Some(run)) => format!("{} run at {}: {}", run.trigger, format_datetime(run.started), recorded_outcome(&run.result)),
+            (None, None) => "none run this session".to_string(),
+        };
+        let record = TripRecord {
+            system_id: self.devices[index].name.clone(),
+            time: SystemTime::now(),
+            reason: reason.to_string(),
+            auto_trip,
+            first_out,
+            user: self.user.clone(),
+            alarms: self.alarms.iter().filter(|alarm| alarm.source.starts_with(&label)).map(|alarm| format!("#{} {} ({})", alarm.id, alarm.message, alarm.state())).collect(),
+            diagnostic,
+            channels,
+        };
+        trips::capture(record, Arc::clone(&self.devices[index].history), self.trips_directory.clone(), self.job_tx.clone());
+        self.log(&tr_args("trip.capturing", &[&label, &trips::BEFORE.as_secs(), &trips::AFTER.as_secs()]));
+    }
+
+    fn print_trips(&self) {
+        let trips = trips::list(&self.trips_directory);
+        if trips.is_empty() {
+            println!("{}", tr_args("trip.none", &[&self.trips_directory.display()]));
+            return;
+        }
+        println!("\n{}", tr_args("trip.list_title", &[&self.trips_directory.display()]));
+        for (number, (_, record)) in trips.iter().enumerate() {
+            let first_out = record.first_out.as_ref().map_or(String::new(), |first_out| tr_args("trip.first_out_note", &[first_out]));
+            println!("  {:>3}  {}  {}  {}{}", number + 1, format_datetime(record.time), record.system_id, record.reason, first_out);
+        }
+        println!("{}", tr("trip.hint"));
+    }
+
+    // A stored trip, read-only: what was recorded, then every channel's trend around it
+    fn print_trip(&self, number: usize, channel: Option<(&str, usize)>) {
+        let (path, record) = match trips::list(&self.trips_directory).into_iter().nth(number.wrapping_sub(1)) {
+            Some(found) => found,
+            None => return println!("{}", tr_args("trip.unknown", &[&number])),
+        };
+        let window = trips::BEFORE + trips::AFTER;
+        let until = record.time + trips::AFTER;
+        if let Some((kind, channel)) = channel {
+            let kind = match ChannelKind::parse(kind) {
+                Ok(kind) => kind,
+                Err(reason) => return println!("{}", reason),
+            };
+            let count = record.channels.iter().filter(|trip_channel| trip_channel.kind == kind).count();
+            let trip_channel = match record.channels.iter().filter(|trip_channel| trip_channel.kind == kind).nth(channel.wrapping_sub(1)) {
+                Some(trip_channel) => trip_channel,
+                None => return println!("{}", tr_args("trip.no_channel", &[&kind.label(), &channel, &count])),
+            };
+            println!("\n{}", tr_args("trip.chart_title", &[&number, &trip_channel.label, &trip_channel.samples.len()]));
+            for line in render_trend(&trip_channel.samples, &trip_channel.limits, self.units.get(kind), window, until, &[(record.time, '|')]) {
+                println!("{}", line);
+            }
+            println!("{}", tr("trip.legend"));
+            return;
+        }
+
+        println!("\n{}", tr_args("trip.title", &[&number, &path.display()]));
+        println!("{}", tr_args("trip.time", &[&format_datetime(record.time)]));
+        println!("{}", tr_args("trip.system", &[&record.system_id]));
+        println!("{}", tr_args("trip.reason", &[&record.reason]));
+        if let Some(auto_trip) = &record.auto_trip {
+            println!("{}", tr_args("trip.auto_trip", &[&auto_trip]));
+        }
+        println!("{}", tr_args("trip.first_out", &[&record.first_out.as_deref().unwrap_or(tr("trip.first_out_none"))]));
+        println!("{}", tr_args("trip.user", &[&record.user]));
+        println!("{}", tr_args("trip.diagnostic", &[&record.diagnostic]));
+        match record.alarms.as_slice() {
+            [] => println!("{}", tr("trip.alarms_none")),
+            alarms => {
+                println!("{}", tr("trip.alarms"));
+                for alarm in alarms {
+                    println!("  {}", alarm);
+                }
+            }
+        }
+        let pens = PenSet::default();
+        let series: Vec<(Vec<Sample>, Pen, Unit)> = record.channels.iter().enumerate()
+            .map(|(position, trip_channel)| (trip_channel.samples.clone(), pens.pen(&trip_channel.label, trip_channel.kind, position), self.units.get(trip_channel.kind)))
+            .collect();
+        println!("\n{}", tr_args("trip.readings", &[&trips::BEFORE.as_secs(), &trips::AFTER.as_secs()]));
+        for line in render_pens(&series, [None, None], window, until, &[(record.time, '|')]) {
+            println!("{}", line);
+        }
+        println!("{}", tr("trip.marker_legend"));
+        for ((_, pen, unit), trip_channel) in series.iter().zip(&record.channels) {
+            println!("{}", tr_args("trend.pen", &[&pen.mark, &trip_channel.label, &unit, &pen.axis.label()]));
+        }
+    }
+
+    // Carries out a control command for `actor`: the role it needs, then what the device's
+    // mode allows, then the command itself, which checks the interlocks and the rest under
+    // the lock and writes the audit entry. A refusal is logged where it is made and returned.
+    fn execute(&mut self, command: Command, actor: &Actor) -> Result<(), CommandError> {
+        let allowed = match (actor, command.required_role()) {
+            (_, None) | (Actor::System, _) => Ok(()),
+            (Actor::Console | Actor::Undo, Some(role)) => self.check_role(role).map_err(|reason| {
+                let hint = if self.session.is_none() { tr("command.login_hint") } else { "" };
+                print_colored(StatusColor::Yellow, &tr_args("command.locked", &[&command.word(), &reason, &hint]));
+                reason
+            }),
+            (Actor::Api { role: claimed, .. }, Some(role)) if *claimed >= role => Ok(()),
+            (Actor::Api { client, role: claimed }, Some(role)) => {
+                let reason = format!("needs {} access (client {} claimed {})", role, client, claimed);
+                self.log_colored(StatusColor::Yellow, &tr_args("command.api_refused", &[&command, &reason]));
+                Err(reason)
+            }
+        };
+        allowed.map_err(CommandError::Forbidden)?;
+
+        let index = command.device();
+        // Read even from a poisoned lock: the emergency shutdown has to go ahead regardless
+        let shut_down = error::lock_or_recover(&self.devices[index].sensor_data).0.emergency_shutdown;
+        if let Err(e) = commands::permitted(&command, self.devices[index].mode, shut_down) {
+            self.log_colored(StatusColor::Red, &tr_args("command.refused", &[&self.device_label(index), &command, &e]));
+            return Err(e);
+        }
+
+        // Only what is changed at the console can be undone there
+        let previous = match actor {
+            Actor::Console => self.previous_value(&command),
+            Actor::Api { .. } | Actor::System | Actor::Undo => None,
+        };
+        // Audited under the client's name
+        let console_user = match actor {
+            Actor::Api { client, .. } => Some(std::mem::replace(&mut self.user, format!("api:{}", client))),
+            Actor::Console | Actor::System | Actor::Undo => None,
+        };
+        let result = match command {
+            Command::StartMotor { device, motor } => self.set_motor_state(device, motor, true),
+            Command::StopMotor { device, motor } => self.set_motor_state(device, motor, false),
+            Command::SetSetpoint { device, motor, rpm } => self.set_motor_setpoint(device, motor, rpm),
+            Command::SetLimit { device, kind, channel, limit, value } => self.set_alarm_limit(device, kind, channel, &limit, value),
+            Command::RestoreLimits { device, kind, channel, limits } => self.restore_limits(device, kind, channel, limits),
+            Command::LoadRecipe { device, recipe } => self.apply_recipe(device, recipe),
+            Command::ResetOverload { device, motor } => self.reset_motor_overload(device, motor),
+            Command::EmergencyShutdown { device, reason } => {
+                self.emergency_shutdown(device, &reason);
+                Ok(())
+            }
+            Command::Reset { device } => self.reset_system(device),
+            Command::Restart { device } => self.restart_motors(device),
+        };
+        if let Some(user) = console_user {
+            self.user = user;
+        }
+        if let (Ok(()), Some(previous)) = (&result, previous) {
+            // The entry the command just wrote
+            let detail = self.audit.events().last().map(|event| event.detail.clone()).unwrap_or_default();
+            self.record_change(previous, detail);
+        }
+        result.map_err(CommandError::Refused)
+    }
+
+    // What a setpoint or limit change is about to replace, to be put back by 'undo'
+    fn previous_value(&self, command: &Command) -> Option<Previous> {
+        match command {
+            Command::SetSetpoint { device, motor, .. } => {
+                let data = error::lock(&self.devices[*device].sensor_data, devices::SENSOR_DATA).ok()?;
+                let rpm = data.motors.get(motor.wrapping_sub(1))?.setpoint;
+                Some(Previous::Setpoint { device: *device, motor: *motor, rpm })
+            }
+            Command::SetLimit { device, kind, channel, .. } => {
+                let data = error::lock(&self.devices[*device].sensor_data, devices::SENSOR_DATA).ok()?;
+                let limits = data.channel(*kind, channel.wrapping_sub(1))?.limits;
+                Some(Previous::Limits { device: *device, kind: *kind, channel: *channel, limits })
+            }
+            _ => None,
+        }
+    }
+
+    // The live value of what `previous` describes, in the same form; None if it can't be read
+    fn current_value(&self, previous: &Previous) -> Option<Previous> {
+        match previous {
+            Previous::Setpoint { device, motor, .. } => {
+                let data = error::lock(&self.devices[*device].sensor_data, devices::SENSOR_DATA).ok()?;
+                let rpm = data.motors.get(motor.wrapping_sub(1))?.setpoint;
+                Some(Previous::Setpoint { device: *device, motor: *motor, rpm })
+            }
+            Previous::Limits { device, kind, channel, .. } => {
+                let data = error::lock(&self.devices[*device].sensor_data, devices::SENSOR_DATA).ok()?;
+                let limits = data.channel(*kind, channel.wrapping_sub(1))?.limits;
+                Some(Previous::Limits { device: *device, kind: *kind, channel: *channel, limits })
+            }
+            Previous::Tagging { device, kind, number, .. } => {
+                let data = error::lock(&self.devices[*device].sensor_data, devices::SENSOR_DATA).ok()?;
+                let tagging = match kind {
+                    Some(kind) => data.channel(*kind, number.wrapping_sub(1))?.tagging.clone(),
+                    None => data.motors.get(number.wrapping_sub(1))?.tagging.clone(),
+                };
+                Some(Previous::Tagging { device: *device, kind: *kind, number: *number, tagging })
+            }
+            Previous::Pen { key, label, .. } => {
+                let pen = self.settings.pens.stored(key)?;
+                Some(Previous::Pen { key: key.clone(), label: label.clone(), pen })
+            }
+            Previous::PenRange { axis, .. } => Some(Previous::PenRange { axis: *axis, range: self.settings.pens.range(*axis) }),
+        }
+    }
+
+    // Called just after the change, so what it set can be read back
+    fn record_change(&mut self, previous: Previous, detail: String) {
+        let applied = match self.current_value(&previous) {
+            Some(applied) => applied,
+            None => return,
+        };
+        self.undo.push(Change { time: SystemTime::now(), user: self.user.clone(), detail, previous, applied });
+    }
+
+    // 'undo': shows the change that would be reverted and waits for a yes
+    fn request_undo(&mut self) {
+        let change = match self.undo.last() {
+            Some(change) => change,
+            None => {
+                println!("{}", tr("undo.nothing"));
+                return;
+            }
+        };
+        print_colored(StatusColor::Yellow, &tr_args("undo.confirm", &[&change.detail, &format_time(change.time), &change.user]));
+        println!("{}", tr("undo.confirm_hint"));
+        self.pending_undo = true;
+    }
+
+    // Puts back exactly what the last change replaced, and audits that as a change of its own
+    fn undo_last(&mut self) {
+        let change = match self.undo.last() {
+            Some(change) => change.clone(),
+            None => return,
+        };
+        if let Some(role) = change.previous.required_role() {
+            if let Err(reason) = self.check_role(role) {
+                print_colored(StatusColor::Yellow, &tr_args("undo.locked", &[&reason]));
+                return;
+            }
+        }
+        // Changed again since, e.g. over the REST API or by a recipe: that change stands
+        match self.current_value(&change.previous) {
+            Some(current) if current == change.applied => {}
+            Some(_) => {
+                self.undo.pop();
+                self.log_colored(StatusColor::Red, &tr_args("undo.changed_since", &[&change.detail]));
+                return;
+            }
+            None => {
+                self.log_colored(StatusColor::Red, &tr_args("undo.unreadable", &[&change.detail]));
+                return;
+            }
+        }
+        match self.revert(&change.previous) {
+            Ok((shown, restored)) => {
+                self.undo.pop();
+                let shown = tr_args("undo.done", &[&change.detail, &format_time(change.time), &change.user, &shown]);
+                self.log_action_shown(AuditAction::Undo, &shown, &format!("Undid '{}' ({} by {}): {}", change.detail, format_time(change.time), change.user, restored));
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("undo.refused", &[&reason, &change.detail])),
+        }
+    }
+
+    // Returns what was put back, as shown on the console and as audited
+    fn revert(&mut self, previous: &Previous) -> Result<(String, String), String> {
+        if let Some(command) = previous.command() {
+            self.execute(command, &Actor::Undo).map_err(|e| e.to_string())?;
+        }
+        match previous {
+            Previous::Setpoint { device, motor, rpm } => {
+                let name = self.lock_device(*device).map(|data| data.motor_label(*motor)).unwrap_or_default();
+                let label = format!("{}{}", self.device_label(*device), name);
+                Ok((tr_args("undo.setpoint", &[&label, &rpm]), format!("{} setpoint back to {} RPM", label, rpm)))
+            }
+            Previous::Limits { device, kind, channel, .. } => {
+                let label = self.lock_device(*device).and_then(|data| data.channel(*kind, channel - 1).map(Channel::label)).unwrap_or_default();
+                let label = format!("{}{}", self.device_label(*device), label);
+                Ok((tr_args("undo.limits", &[&label]), format!("{} alarm limits back to what they were", label)))
+            }
+            Previous::Tagging { device, kind, number, tagging } => {
+                let result = match self.lock_device(*device) {
+                    Some(mut data) => data.set_tagging(*kind, *number, tagging.clone()),
+                    None => return Err("sensor data is unavailable".to_string()),
+                };
+                let (name, _) = result?;
+                let key = self.settings_key(*device, &name);
+                self.settings.set_tagging(&key, tagging.clone());
+                self.save_settings();
+                let label = format!("{}{}", self.device_label(*device), name);
+                Ok((tr_args("undo.tagging", &[&label]), format!("{} tag, description and asset ID back to what they were", label)))
+            }
+            Previous::Pen { key, label, pen } => {
+                self.settings.pens.set_pen(key, *pen);
+                self.save_settings();
+                let shown = if pen.visible { tr("trend.pen_shown") } else { tr("trend.pen_hidden") };
+                Ok((
+                    tr_args("undo.pen", &[&label, &shown, &pen.mark, &pen.axis.label()]),
+                    format!("{} pen back to {}, mark {}, {} axis", label, if pen.visible { "shown" } else { "hidden" }, pen.mark, pen.axis),
+                ))
+            }
+            Previous::PenRange { axis, range } => {
+                self.settings.pens.set_range(*axis, *range)?;
+                self.save_settings();
+                Ok(match range {
+                    Some((low, high)) => (tr_args("undo.axis", &[&axis.label(), low, high]), format!("trend {} axis back to {} to {}", axis, low, high)),
+                    None => (tr_args("undo.axis_to_data", &[&axis.label()]), format!("trend {} axis back to scaling to the data", axis)),
+                })
+            }
+        }
+    }
+
+    fn print_undo(&self) {
+        println!("\n{}", tr_args("undo.title", &[&self.undo.len(), &undo::DEPTH]));
+        for (i, change) in self.undo.iter().enumerate() {
+            let next = if i == 0 { tr("undo.next") } else { "" };
+            println!("  {}  {:<12} {}{}", format_time(change.time), change.user, change.detail, next);
+        }
+        if self.undo.is_empty() {
+            println!("{}", tr("undo.none"));
+        }
+    }
+
+    fn reset_system(&mut self, index: usize) -> Result<(), String> {
+        let result = match self.lock_device(index) {
+            Some(mut data) => data.reset_emergency_shutdown(self.units),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+        match result {
+            Ok(true) => {
+                self.devices[index].first_out = None;
+                self.log_device_action_shown(index, AuditAction::Reset, tr("control.estop_cleared"), "E-stop cleared - motors remain stopped until 'restart'");
+                if let Err(reason) = self.transition(index, ModeEvent::Reset, tr("mode.cause_reset"), "reset") {
+                    self.log_colored(StatusColor::Red, &tr_args("mode.unchanged", &[&reason]));
+                }
+                Ok(())
+            }
+            // The mode still said E-STOP
+            Ok(false) => {
+                let _ = self.transition(index, ModeEvent::Reset, tr("mode.cause_reset"), "reset");
+                Ok(())
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("control.reset_refused", &[&self.device_label(index), &reason]));
+                Err(reason)
+            }
+        }
+    }
+
+    fn reset_motor_overload(&mut self, index: usize, motor: usize) -> Result<(), String> {
+        let (result, name) = match self.lock_device(index) {
+            Some(mut data) => (data.reset_overload(motor), data.motor_label(motor)),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+        match result {
+            Ok(()) => {
+                let shown = tr_args("control.overload_reset", &[&name]);
+                self.log_device_action_shown(index, AuditAction::Reset, &shown, &format!("{} overload reset - motor remains stopped until started", name));
+                Ok(())
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("control.overload_reset_refused", &[&self.device_label(index), &name, &reason]));
+                Err(reason)
+            }
+        }
+    }
+
+    fn restart_motors(&mut self, index: usize) -> Result<(), String> {
+        let plan = match self.lock_device(index) {
+            Some(mut data) => data.take_restart_plan(),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+        match plan {
+            Ok(plan) if plan.is_empty() => {
+                self.log_device_action_shown(index, AuditAction::Restart, tr("control.restart_nothing"), "Restart: no motors were running before the shutdown");
+            }
+            Ok(plan) => {
+                let shown = tr_args("control.restarting", &[&plan.len(), &RESTART_STAGGER.as_secs()]);
+                let recorded = format!("Restarting {} motor(s), one every {} s", plan.len(), RESTART_STAGGER.as_secs());
+                self.log_device_action_shown(index, AuditAction::Restart, &shown, &recorded);
+                let device = &mut self.devices[index];
+                device.restart_queue = plan.into();
+                device.next_restart = Instant::now();
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("control.restart_refused", &[&self.device_label(index), &reason]));
+                return Err(reason);
+            }
+        }
+        Ok(())
+    }
+
+    // Starts the next queued motor once the previous one has had time to spin up;
+    // called once per tick of the main loop for each device.
+    fn poll_restart(&mut self, index: usize) {
+        let device = &mut self.devices[index];
+        if Instant::now() < device.next_restart {
+            return;
+        }
+        if let Some((motor, rpm)) = device.restart_queue.pop_front() {
+            device.next_restart = Instant::now() + RESTART_STAGGER;
+            let _ = self.execute(Command::SetSetpoint { device: index, motor: motor + 1, rpm }, &Actor::System);
+            let _ = self.execute(Command::StartMotor { device: index, motor: motor + 1 }, &Actor::System);
+        }
+    }
+
+    // Returns why the command was refused, if it was, having said so
+    fn set_motor_state(&mut self, index: usize, motor: usize, running: bool) -> Result<(), String> {
+        let (result, name) = match self.lock_device(index) {
+            Some(mut data) => (data.set_motor_state(motor, running), data.motor_label(motor)),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+
+        let action = if running { "start" } else { "stop" };
+        match result {
+            Ok(()) => {
+                self.devices[index].simulation_thread.send_command(SourceCommand::MotorRunning { motor: motor - 1, running });
+                let audit_action = if running { AuditAction::MotorStart } else { AuditAction::MotorStop };
+                let shown = tr_args("control.command_accepted", &[&name, &action]);
+                self.log_device_action_shown(index, audit_action, &shown, &format!("{} {} command accepted", name, action));
+                Ok(())
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("control.motor_refused", &[&self.device_label(index), &name, &action, &reason]));
+                Err(reason)
+            }
+        }
+    }
+
+    fn set_motor_setpoint(&mut self, index: usize, motor: usize, rpm: u16) -> Result<(), String> {
+        let (result, name) = match self.lock_device(index) {
+            Some(mut data) => (data.set_motor_setpoint(motor, rpm), data.motor_label(motor)),
+            None => return Err("sensor data is unavailable".to_string()),
+        };
+
+        if result.is_ok() {
+            self.devices[index].simulation_thread.send_command(SourceCommand::MotorSetpoint { motor: motor - 1, rpm });
+        }
+        match result {
+            Ok(true) => {
+                let shown = tr_args("control.setpoint_changed", &[&name, &rpm]);
+                self.log_device_action_shown(index, AuditAction::Setpoint, &shown, &format!("{} setpoint changed to {} RPM", name, rpm));
+            }
+            Ok(false) => {
+                let shown = tr_args("control.setpoint_stored", &[&name, &rpm]);
+                self.log_device_action_shown(index, AuditAction::Setpoint, &shown, &format!("{} setpoint stored as {} RPM (applies when started)", name, rpm));
+            }
+            Err(reason) => {
+                self.log_colored(StatusColor::Red, &tr_args("control.setpoint_rejected", &[&self.device_label(index), &name, &reason]));
+                return Err(reason);
+            }
+        }
+        Ok(())
+    }
+
+    // Loop tuning faceplate: setpoint, process value and output with the gains
+    fn print_loop(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let control = match &data.control_loop {
+            Some(control) => control,
+            None => {
+                println!("{}", tr("loop.none"));
+                return;
+            }
+        };
+        let (sensor, fan) = match data.channel(ChannelKind::Temperature, control.temperature) {
+            Some(sensor) => (sensor, &data.motors[control.motor]),
+            None => return,
+        };
+        let unit = self.units.temperature;
+        let pv = data.loop_process_value(control);
+
+        println!("\n{}", tr_args("loop.title", &[&sensor.label(), &fan.label()]));
+        match control.pid.mode {
+            LoopMode::Auto if !fan.running => print_colored(StatusColor::Yellow, &tr_args("loop.auto_held", &[&fan.name])),
+            LoopMode::Auto => print_colored(StatusColor::Green, tr("loop.auto")),
+            LoopMode::Manual => print_colored(StatusColor::Yellow, &tr_args("loop.manual", &[&(control.motor + 1)])),
+        }
+        println!("{}", tr_args("loop.sp", &[&format!("{:>11}", unit.format(control.pid.setpoint))]));
+        print_colored(sensor.limits.classify(pv).into(), &tr_args("loop.pv_error", &[&format!("{:>11}", unit.format(pv)), &render_gauge(pv, &sensor.limits, unit), &format_error(unit, control.pid.error(pv))]));
+        println!("{}", tr_args("loop.out", &[&format!("{:>7}", decimal(control.pid.output, 0)), &render_speed_gauge(fan), &decimal(control.pid.output_percent(), 0), &fan.name, &fan.speed]));
+        println!("{}", tr_args("loop.tuning", &[&control.pid.kp, &control.pid.ki, &control.pid.kd]));
+        println!("{}", tr_args("loop.output_limits", &[&decimal(control.pid.output_limits.0, 0), &decimal(control.pid.output_limits.1, 0)]));
+        println!("{}", tr_args("loop.trend_hint", &[&(control.temperature + 1)]));
+    }
+
+    fn set_loop_mode(&mut self, mode: LoopMode) {
+        let result = match self.lock_sensors() {
+            Some(mut data) => match data.control_loop.as_mut() {
+                Some(control) => Ok(std::mem::replace(&mut control.pid.mode, mode)),
+                None => Err(tr("loop.none_configured").to_string()),
+            },
+            None => return,
+        };
+        match result {
+            Ok(previous) if previous == mode => println!("{}", tr_args("loop.already", &[&mode])),
+            Ok(_) => {
+                let shown = tr_args("loop.switched", &[&mode]);
+                self.log_device_action_shown(self.selected, AuditAction::LoopChange, &shown, &format!("Temperature loop switched to {}", mode));
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("loop.rejected", &[&reason])),
+        }
+    }
+
+    // The setpoint is typed in the display unit; gains are always per °C
+    fn set_loop_parameter(&mut self, parameter: &str, value: f32) {
+        let unit = self.units.temperature;
+        let result = match self.lock_sensors() {
+            Some(mut data) => match data.control_loop.as_mut() {
+                Some(_) if !value.is_finite() || (parameter != "sp" && value < 0.0) => Err(tr_args("loop.non_negative", &[&parameter])),
+                Some(control) => {
+                    let pid = &mut control.pid;
+                    match parameter {
+                        "sp" => pid.setpoint = unit.to_si(value),
+                        "kp" => pid.set_gains(value, pid.ki, pid.kd),
+                        "ki" => pid.set_gains(pid.kp, value, pid.kd),
+                        _ => pid.set_gains(pid.kp, pid.ki, value),
+                    }
+                    Ok(())
+                }
+                None => Err(tr("loop.none_configured").to_string()),
+            },
+            None => return,
+        };
+        match result {
+            Ok(()) if parameter == "sp" => {
+                let setpoint = unit.format(unit.to_si(value));
+                let shown = tr_args("loop.setpoint_changed", &[&setpoint]);
+                self.log_device_action_shown(self.selected, AuditAction::LoopChange, &shown, &format!("Temperature loop setpoint changed to {}", setpoint));
+            }
+            Ok(()) => {
+                let shown = tr_args("loop.gain_changed", &[&parameter.to_uppercase(), &value]);
+                self.log_device_action_shown(self.selected, AuditAction::LoopChange, &shown, &format!("Temperature loop {} changed to {}", parameter.to_uppercase(), value));
+            }
+            Err(reason) => self.log_colored(StatusColor::Red, &tr_args("loop.rejected", &[&reason])),
+        }
+    }
+
+    fn print_motor(&self, motor: usize) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+        let (counters, due, service_interval) = match self.device().maintenance.lock() {
+            Ok(maintenance) => match motor.checked_sub(1).and_then(|index| maintenance.motors().get(index)) {
+                Some(counters) => (counters.clone(), maintenance.is_due(motor - 1), maintenance.service_interval()),
+                None => {
+                    println!("{}", tr_args("motor.unknown", &[&motor, &data.motors.len()]));
+                    return;
+                }
+            },
+            Err(_) => return,
+        };
+        let state = &data.motors[motor - 1];
+
+        println!("\n{}", tr_args("motor.title", &[&motor, &state.label()]));
+        if !state.tagging.description.is_empty() {
+            println!("{}", tr_args("motor.description", &[&state.tagging.description]));
+        }
+        if !state.tagging.asset.is_empty() {
+            println!("{}", tr_args("motor.asset_id", &[&state.tagging.asset]));
+        }
+        println!("{}", tr_args("motor.status", &[&motor_status(state), &state.speed, &state.setpoint]));
+        println!("{}", tr_args("motor.current", &[&decimal(state.current, 1), &decimal(state.full_load_amps, 1), &decimal(state.load_factor, 2)]));
+        if state.overloaded {
+            let cooled = match state.overload_heat > 0.0 {
+                true => tr_args("motor.cooling", &[&decimal(state.overload_heat.ceil(), 0)]),
+                false => tr("motor.cooled").to_string(),
+            };
+            print_colored(StatusColor::Red, &tr_args("motor.overload_tripped", &[&cooled, &motor]));
+        } else if state.overload_heat > 0.0 {
+            print_colored(StatusColor::Yellow, &tr_args("motor.overload_heating", &[&decimal(state.overload_heat, 0), &data.overload_time.as_secs()]));
+        }
+        println!("{}", tr_args("motor.runtime", &[&format_hours(counters.runtime), &counters.starts]));
+        let last_service = counters.last_service.map_or(tr("motor.never_serviced").to_string(), format_datetime);
+        println!("{}", tr_args("motor.since_service", &[&format_hours(counters.service_runtime), &format_hours(service_interval), &last_service]));
+        if due {
+            print_colored(StatusColor::Yellow, &tr_args("motor.maintenance_due", &[&motor]));
+        }
+    }
+
+    fn record_maintenance(&mut self, motor: usize) {
+        let result = match error::lock(&self.device().maintenance, devices::MAINTENANCE)
+            .map(|mut maintenance| maintenance.record_service(motor, SystemTime::now()).map(|counters| (counters.name.clone(), counters.runtime)))
+        {
+            Ok(result) => result,
+            Err(e) => return self.report_error(self.selected, &e),
+        };
+        match result {
+            Ok((name, runtime)) => {
+                if let Some(due) = self.device_mut().maintenance_due.get_mut(motor - 1) {
+                    *due = false;
+                }
+                let shown = tr_args("motor.serviced", &[&name, &format_hours(runtime)]);
+                let recorded = format!("Maintenance recorded for {} at {} total runtime - service counter reset", name, format_hours(runtime));
+                self.log_device_action_shown(self.selected, AuditAction::Maintenance, &shown, &recorded);
+                self.save_maintenance(self.selected);
+            }
+            Err(reason) => println!("{}", reason),
+        }
+    }
+
+    // Reports motors as they come due for maintenance and saves the counters every
+    // MAINTENANCE_SAVE_INTERVAL; called once per tick of the main loop for each device.
+    fn poll_maintenance(&mut self, index: usize) {
+        let motors: Vec<(String, bool)> = match self.devices[index].maintenance.lock() {
+            Ok(maintenance) => maintenance.motors().iter().enumerate().map(|(i, counters)| (counters.name.clone(), maintenance.is_due(i))).collect(),
+            Err(_) => return,
+        };
+        let label = self.device_label(index);
+        self.devices[index].maintenance_due.resize(motors.len(), false);
+        for (i, (name, due)) in motors.into_iter().enumerate() {
+            if due && !self.devices[index].maintenance_due[i] {
+                self.log_colored(StatusColor::Yellow, &tr_args("maintenance.due", &[&label, &name, &(i + 1)]));
+            }
+            self.devices[index].maintenance_due[i] = due;
+        }
+        if self.devices[index].maintenance_saved.elapsed() >= MAINTENANCE_SAVE_INTERVAL {
+            self.save_maintenance(index);
+            self.save_energy(index);
+        }
+    }
+
+    fn save_maintenance(&mut self, index: usize) {
+        let device = &mut self.devices[index];
+        device.maintenance_saved = Instant::now();
+        let state = match device.maintenance.lock() {
+            Ok(maintenance) => maintenance.to_json(),
+            Err(_) => return,
+        };
+        // Reported once when saving starts failing and once when it recovers
+        let file = device.maintenance_file.clone();
+        match state.save(&file) {
+            Err(e) if !device.maintenance_save_failing => {
+                device.maintenance_save_failing = true;
+                self.log_colored(StatusColor::Red, &tr_args("maintenance.not_saved", &[&file.display(), &e]));
+            }
+            Ok(()) if device.maintenance_save_failing => {
+                device.maintenance_save_failing = false;
+                self.log_colored(StatusColor::Green, &tr_args("maintenance.saving_again", &[&file.display()]));
+            }
+            _ => {}
+        }
+    }
+
+    // Saved with the maintenance counters; the totals carry the time they were last added
+    // to, so a restart picks up from there
+    fn save_energy(&mut self, index: usize) {
+        let device = &mut self.devices[index];
+        let state = match device.energy.lock() {
+            Ok(energy) => energy.to_json(),
+            Err(_) => return,
+        };
+        let file = device.energy_file.clone();
+        match state.save(&file) {
+            Err(e) if !device.energy_save_failing => {
+                device.energy_save_failing = true;
+                self.log_colored(StatusColor::Red, &tr_args("energy.not_saved", &[&file.display(), &e]));
+            }
+            Ok(()) if device.energy_save_failing => {
+                device.energy_save_failing = false;
+                self.log_colored(StatusColor::Green, &tr_args("energy.saving_again", &[&file.display()]));
+            }
+            _ => {}
+        }
+    }
+
+    // A device's motors' power draw now (kW) and energy today (kWh); zero for whatever
+    // can't be read
+    fn device_power(&self, index: usize) -> (f32, f64) {
+        let power = match self.devices[index].lock_data() {
+            Ok(data) => data.motors.iter().map(|motor| data.supply.power(motor.current)).sum(),
+            Err(_) => 0.0,
+        };
+        let today = self.devices[index].energy.lock().map_or(0.0, |energy| energy.total().today);
+        (power, today)
+    }
+
+    fn print_energy(&self, minutes: u64) {
+        let (supply, motors) = match self.lock_sensors() {
+            Some(data) => (data.supply, data.motors.iter().map(|motor| (motor.label(), data.supply.power(motor.current))).collect::<Vec<_>>()),
+            None => return,
+        };
+        let device = self.device();
+        let (totals, total, shift_started, reset_at) = match error::lock(&device.energy, devices::ENERGY) {
+            Ok(energy) => (energy.motors().to_vec(), energy.total(), energy.shift_started(), energy.reset_at()),
+            Err(e) => return print_colored(StatusColor::Red, &tr_args("energy.no_totals", &[&e])),
+        };
+        let window = Duration::from_secs(minutes * 60);
+        let now = SystemTime::now();
+        let since = now.checked_sub(window).unwrap_or(UNIX_EPOCH);
+        let series: Vec<Vec<Sample>> = match error::lock(&device.history, devices::HISTORY) {
+            Ok(history) => (0..motors.len()).map(|motor| history.motor_power(motor, since)).collect(),
+            Err(e) => return print_colored(StatusColor::Red, &tr_args("energy.no_chart", &[&e])),
+        };
+
+        println!("\n{}", tr_args("energy.title", &[&device.name, &minutes]));
+        let price = if self.energy_price > 0.0 { format!(", {}{}/kWh", self.energy_currency, self.energy_price) } else { String::new() };
+        println!("{}", tr_args("energy.supply", &[&supply.voltage, &supply.power_factor, &price]));
+        let width = motors.iter().map(|(label, _)| label.chars().count()).max().unwrap_or_default().max(5);
+        println!(
+            "     {:<width$} {:>8} {:>10} {:>10} {:>12}",
+            tr("column.motor"), tr("column.now_kw"), tr("column.today_kwh"), tr("column.shift_kwh"), tr("column.reset_kwh")
+        );
+        for (i, (label, power)) in motors.iter().enumerate() {
+            let energy = totals.get(i).map(|motor| motor.totals).unwrap_or_default();
+            println!("  {}  {:<width$} {:>8.1} {:>10.2} {:>10.2} {:>12.2}", stack_mark(i), label, power, energy.today, energy.shift, energy.since_reset);
+        }
+        let power: f32 = motors.iter().map(|(_, power)| power).sum();
+        println!("     {:<width$} {:>8.1} {:>10.2} {:>10.2} {:>12.2}", tr("column.total"), power, total.today, total.shift, total.since_reset);
+        if self.energy_price > 0.0 {
+            let cost = |kwh: f64| format!("{}{:.2}", self.energy_currency, kwh * self.energy_price);
+            println!("{}", tr_args("energy.cost", &[&cost(total.today), &cost(total.shift), &cost(total.since_reset)]));
+        }
+        println!("{}", tr_args("energy.shift_began", &[&format_datetime(shift_started), &format_datetime(reset_at)]));
+        if self.devices.len() > 1 {
+            let (power, today) = (0..self.devices.len()).map(|index| self.device_power(index)).fold((0.0, 0.0), |(power, today), device| (power + device.0, today + device.1));
+            println!("{}", tr_args("status.facility_power", &[&decimal(power, 1), &decimal(today as f32, 1), &self.devices.len()]));
+        }
+        println!();
+        for line in render_stacked(&series, window, now) {
+            println!("{}", line);
+        }
+        println!("{}", tr("energy.legend"));
+    }
+
+    // Overall vibration of each motor against its limit, and the spectrum of one
+    fn print_vibration(&self, motor: usize) {
+        let motors: Vec<(String, u16)> = match self.lock_sensors() {
+            Some(data) => data.motors.iter().map(|motor| (motor.label(), motor.speed)).collect(),
+            None => return,
+        };
+        let device = self.device();
+        let (limit, analyses, alarms): (f32, Vec<Option<Analysis>>, Vec<bool>) = match error::lock(&device.vibration, devices::VIBRATION) {
+            Ok(vibration) => (vibration.limit(), (0..motors.len()).map(|i| vibration.analysis(i).cloned()).collect(), (0..motors.len()).map(|i| vibration.in_alarm(i)).collect()),
+            Err(e) => return print_colored(StatusColor::Red, &tr_args("vibration.unavailable", &[&e])),
+        };
+        if motor == 0 || motor > motors.len() {
+            println!("{}", tr_args("motor.unknown", &[&motor, &motors.len()]));
+            return;
+        }
+
+        println!("\n{}", tr_args("vibration.title", &[&device.name]));
+        if analyses.iter().all(Option::is_none) {
+            println!("{}", tr_args("vibration.no_channels", &[&device.source_name]));
+            return;
+        }
+        let width = motors.iter().map(|(label, _)| label.chars().count()).max().unwrap_or_default().max(5);
+        println!("  {:<width$} {:>9}  {:>13}", tr("column.motor"), tr("column.speed"), tr("column.overall"));
+        for (i, ((label, speed), analysis)) in motors.iter().zip(&analyses).enumerate() {
+            let rms = analysis.as_ref().map_or("--".to_string(), |analysis| format!("{:.2} mm/s", analysis.rms));
+            let line = format!("  {:<width$} {:>5} RPM  {:>13}", label, speed, rms);
+            match alarms[i] {
+                true => print_colored(StatusColor::Red, &tr_args("vibration.high", &[&line])),
+                false => println!("{}", line),
+            }
+        }
+        println!("{}", tr_args("vibration.limit", &[&decimal(limit, 1)]));
+
+        let (label, speed) = &motors[motor - 1];
+        let analysis = match &analyses[motor - 1] {
+            Some(analysis) => analysis,
+            None => return println!("\n{}", tr_args("vibration.too_few_samples", &[&label])),
+        };
+        let running = *speed as f32 / 60.0;
+        println!("\n{}", tr_args("vibration.spectrum", &[&label, &vibration::FRAME, &vibration::SAMPLE_RATE, &vibration::BIN_HZ]));
+        for line in render_spectrum(&analysis.spectrum, running) {
+            println!("{}", line);
+        }
+        if running > 0.0 {
+            println!("{}", tr_args("vibration.legend", &[&decimal(running, 1)]));
+        } else {
+            println!("{}", tr("vibration.motor_stopped"));
+        }
+        if motors.len() > 1 {
+            println!("{}", tr("vibration.hint"));
+        }
+    }
+
+    fn set_vibration_limit(&mut self, limit: f32) {
+        let result = error::lock(&self.device().vibration, devices::VIBRATION).map(|mut vibration| {
+            let previous = vibration.limit();
+            vibration.set_limit(limit).map(|()| previous)
+        });
+        match result {
+            Ok(Ok(previous)) => {
+                let shown = tr_args("vibration.limit_set", &[&decimal(limit, 1), &decimal(previous, 1)]);
+                let recorded = format!("Vibration alarm limit set to {:.1} mm/s RMS (was {:.1})", limit, previous);
+                self.log_device_action_shown(self.selected, AuditAction::LimitChange, &shown, &recorded);
+            }
+            Ok(Err(reason)) => self.log_colored(StatusColor::Red, &tr_args("vibration.limit_rejected", &[&reason])),
+            Err(e) => self.report_error(self.selected, &e),
+        }
+    }
+
+    fn reset_energy(&mut self) {
+        let now = SystemTime::now();
+        let result = error::lock(&self.device().energy, devices::ENERGY).map(|mut energy| {
+            let counted = (energy.total().since_reset, energy.reset_at());
+            energy.reset(now);
+            counted
+        });
+        let (kwh, since) = match result {
+            Ok(counted) => counted,
+            Err(e) => return self.report_error(self.selected, &e),
+        };
+        let shown = tr_args("energy.reset", &[&decimal(kwh as f32, 2), &format_datetime(since)]);
+        let recorded = format!("Energy totals reset ({:.2} kWh counted since {})", kwh, format_datetime(since));
+        self.log_device_action_shown(self.selected, AuditAction::EnergyReset, &shown, &recorded);
+        self.save_energy(self.selected);
+    }
+
+    // Why the current user can't use controls that need `role`, if they can't
+    fn check_role(&self, role: Role) -> Result<(), String> {
+        match &self.session {
+            _ if self.accounts.is_empty() => Ok(()),
+            Some(session) if session.role >= role => Ok(()),
+            Some(session) => Err(tr_args("login.needs_role", &[&role, &session.name, &session.role])),
+            None => Err(tr_args("login.needs_login", &[&role])),
+        }
+    }
+
+    fn begin_login(&mut self, name: &str) {
+        if self.accounts.is_empty() {
+            println!("{}", tr("login.not_configured"));
+            return;
+        }
+        let locked_for = self.login_locked_until.saturating_duration_since(Instant::now());
+        if !locked_for.is_zero() {
+            print_colored(StatusColor::Red, &tr_args("login.too_many", &[&(locked_for.as_secs() + 1)]));
+            return;
+        }
+        println!("{}", tr_args("login.pin", &[&name]));
+        self.pending_login = Some(name.to_string());
+    }
+
+    fn complete_login(&mut self, name: &str, pin: &str) {
+        let account = self.accounts.iter().find(|account| account.name == name && account.verify(pin)).cloned();
+        let account = match account {
+            Some(account) => account,
+            None => {
+                // Doesn't say which was wrong, so names can't be probed
+                self.audit.record(name, AuditAction::Login, "Login failed");
+                self.log_colored(StatusColor::Red, &tr_args("login.failed", &[&name]));
+                self.failed_logins += 1;
+                if self.failed_logins >= MAX_LOGIN_ATTEMPTS {
+                    self.failed_logins = 0;
+                    self.login_locked_until = Instant::now() + LOGIN_LOCKOUT;
+                    self.log_colored(StatusColor::Red, &tr_args("login.locked", &[&LOGIN_LOCKOUT.as_secs(), &MAX_LOGIN_ATTEMPTS]));
+                }
+                return;
+            }
+        };
+
+        if let Some(previous) = self.session.take() {
+            let shown = tr_args("login.replaced", &[&previous.name, &account.name]);
+            self.log_action_shown(AuditAction::Logout, &shown, &format!("{} logged out ({} logged in)", previous.name, account.name));
+        }
+        self.failed_logins = 0;
+        // Changes are only undone by whoever made them
+        self.undo.clear();
+        self.user = account.name.clone();
+        self.session = Some(Session { name: account.name.clone(), role: account.role, last_activity: Instant::now() });
+        let shown = tr_args("login.logged_in", &[&account.name, &account.role]);
+        self.log_action_shown(AuditAction::Login, &shown, &format!("{} logged in as {}", account.name, account.role));
+    }
+
+    fn logout(&mut self, shown: &str, recorded: &str) {
+        self.log_action_shown(AuditAction::Logout, shown, recorded);
+        self.session = None;
+        self.user = audit::os_user();
+        self.undo.clear();
+    }
+
+    // Sounds the horn for each new unacknowledged alarm, and stops it once every alarm
+    // has been acknowledged. Called once per tick of the main loop.
+    fn poll_horn(&mut self) {
+        let annunciator = match &mut self.annunciator {
+            Some(annunciator) => annunciator,
+            None => return,
+        };
+        let newest = self.alarms.iter().filter(|alarm| !alarm.acknowledged).map(|alarm| alarm.id).max().unwrap_or(0);
+        if newest > self.annunciated_alarm {
+            self.annunciated_alarm = newest;
+            annunciator.sound(Tone::Alarm);
+        } else if annunciator.is_sounding() && self.alarms.unacknowledged() == 0 {
+            annunciator.silence();
+        }
+    }
+
+    // Sends each new alarm out, and records how every delivery attempt went. Called once
+    // per tick of the main loop.
+    fn poll_notifications(&mut self) {
+        while let Ok(outcome) = self.notify_outcomes.try_recv() {
+            let message = match outcome {
+                Ok(message) => {
+                    self.log(&message);
+                    self.toast(toasts::Severity::Info, &message);
+                    message
+                }
+                Err(message) => {
+                    self.notify_warn(&message);
+                    message
+                }
+            };
+            self.audit.record(&self.user, AuditAction::Notification, &message);
+        }
+        let new_alarms: Vec<(u32, String, String)> = self.alarms.iter()
+            .filter(|alarm| alarm.id > self.notified_alarm && alarm.is_active())
+            .map(|alarm| (alarm.id, alarm.source.clone(), alarm.message.clone()))
+            .collect();
+        for (id, source, message) in new_alarms {
+            self.notified_alarm = self.notified_alarm.max(id);
+            self.notify(NotificationKind::Alarm, &source, &format!("Alarm #{}: {}", id, message));
+        }
+    }
+
+    // Alarms, trips and shutdowns are named after the first device's system ID; a source
+    // notified within the repeat interval is skipped
+    fn notify(&mut self, kind: NotificationKind, source: &str, message: &str) {
+        let notification = Notification::new(kind, &self.devices[0].name, source, message);
+        if let Some(notifier) = &mut self.notifier {
+            notifier.send(notification);
+        }
+    }
+
+    fn print_notifications(&self) {
+        match &self.notifier {
+            Some(notifier) => {
+                println!("\n{}", tr("notify.title"));
+                for sink in notifier.sinks() {
+                    println!("  {}", sink);
+                }
+                println!("{}", tr_args("notify.policy", &[&(notifier.repeat_interval().as_secs() / 60)]));
+                println!("{}", tr("notify.audit_hint"));
+            }
+            None => println!("{}", tr("notify.off")),
+        }
+    }
+
+    fn send_test_notification(&mut self) {
+        if self.notifier.is_none() {
+            println!("{}", tr("notify.off"));
+            return;
+        }
+        self.log_action_shown(AuditAction::Notification, tr("notify.test_queued"), "Test notification queued");
+        self.notify(NotificationKind::Test, "test", &format!("Test notification from {}", self.user));
+    }
+
+    fn silence_horn(&mut self) {
+        match &mut self.annunciator {
+            Some(annunciator) if annunciator.is_sounding() => {
+                annunciator.silence();
+                self.log(tr("horn.silenced"));
+            }
+            Some(_) => println!("{}", tr("horn.not_sounding")),
+            None => println!("{}", tr("horn.disabled")),
+        }
+    }
+
+    // Logs an idle user out; the simulation and everything else keeps running.
+    // Called once per tick of the main loop.
+    fn poll_session(&mut self) {
+        let (name, idle) = match &self.session {
+            Some(session) => (session.name.clone(), session.last_activity.elapsed()),
+            None => return,
+        };
+        if idle >= self.session_timeout {
+            let idle = format_span(self.session_timeout);
+            self.logout(
+                &tr_args("login.timed_out", &[&name, &idle]),
+                &format!("{} logged out after {} of inactivity - type 'login NAME' to take control", name, idle),
+            );
+        }
+    }
+
+    // Returns false when the operator asked to exit
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        if let Some(session) = &mut self.session {
+            session.last_activity = Instant::now();
+        }
+        // The PIN is consumed here so it never reaches the log or the command parser
+        if let Some(name) = self.pending_login.take() {
+            self.complete_login(&name, line.trim());
+            return true;
+        }
+        if let Some((device, reason)) = self.pending_estop.take() {
+            // The estop shortcut confirms rather than cancels
+            let confirmed = is_yes(line.trim()) || matches!(expand_shortcut(line.trim()), Some((_, command)) if command.starts_with("estop!"));
+            match (confirmed, device) {
+                (true, Some(index)) => {
+                    let _ = self.execute(Command::EmergencyShutdown { device: index, reason }, &Actor::Console);
+                }
+                (true, None) => {
+                    for index in 0..self.devices.len() {
+                        let _ = self.execute(Command::EmergencyShutdown { device: index, reason: reason.clone() }, &Actor::Console);
+                    }
+                }
+                _ => println!("{}", tr("estop.cancelled")),
+            }
+            return true;
+        }
+        if self.pending_undo {
+            self.pending_undo = false;
+            // Ctrl+Z confirms, as the estop shortcut does
+            match line.trim() {
+                line if is_yes(line) => self.undo_last(),
+                line if matches!(expand_shortcut(line), Some((_, command)) if command == "undo") => self.undo_last(),
+                _ => println!("{}", tr("undo.cancelled")),
+            }
+            return true;
+        }
+        if let Some((path, bundle)) = self.pending_import.take() {
+            match line.trim() {
+                line if is_yes(line) => self.apply_import(&path, &bundle),
+                _ => println!("{}", tr("import.cancelled")),
+            }
+            return true;
+        }
+        // The line after Ctrl+F is the ID; a scanner types it and presses Enter
+        if self.pending_find {
+            self.pending_find = false;
+            match line.trim() {
+                "" => println!("{}", tr("find.cancelled")),
+                id => self.find_asset(id),
+            }
+            return true;
+        }
+        if let Some(id) = self.pending_asset_note.take() {
+            match line.trim() {
+                "" => println!("{}", tr("note.cancelled")),
+                text => match self.check_role(Role::Operator) {
+                    Ok(()) => self.add_note(NoteSubject::Asset(&id), text),
+                    Err(reason) => print_colored(StatusColor::Yellow, &tr_args("command.note_locked", &[&reason])),
+                },
+            }
+            return true;
+        }
+        // Any line ends a channel watch; Enter alone does nothing more
+        if let Some(watch) = self.watching.take() {
+            println!("{}", tr_args("command.stopped_watching", &[&self.device_label(watch.device), &watch.kind, &watch.number]));
+            if line.trim().is_empty() {
+                return true;
+            }
+        }
+
+        // A shortcut key stands for its command, which is checked and run as if typed
+        let shortcut = expand_shortcut(line.trim());
+        if let Some((key, command)) = &shortcut {
+            println!("{}: {}", key, command);
+        }
+        let line = shortcut.as_ref().map_or(line, |(_, command)| command.as_str());
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let Some(command) = Command::parse(&words, self.selected) {
+            match command {
+                Ok(command) => {
+                    let _ = self.execute(command, &Actor::Console);
+                }
+                Err(usage) => println!("{}", usage),
+            }
+            return true;
+        }
+        if let Some(role) = required_role(&words) {
+            if let Err(reason) = self.check_role(role) {
+                let hint = if self.session.is_none() { tr("command.login_hint") } else { "" };
+                print_colored(StatusColor::Yellow, &tr_args("command.locked", &[&words[0], &reason, &hint]));
+                return true;
+            }
+        }
+        match words.as_slice() {
+            [] => {}
+            ["login", name] => self.begin_login(name),
+            ["logout"] => match self.session.as_ref().map(|session| session.name.clone()) {
+                Some(name) => self.logout(&tr_args("login.logged_out", &[&name]), &format!("{} logged out", name)),
+                None => println!("{}", tr("command.nobody_logged_in")),
+            },
+            ["status"] => self.print_status(),
+            ["devices"] => self.print_devices(),
+            ["perf"] => self.print_performance(),
+            ["link"] => self.print_link(),
+            ["device", target] => self.select_device(target),
+            ["diag"] => self.run_diagnostic(DiagnosticTrigger::Manual),
+            ["diag", "history"] => self.print_diagnostic_history(),
+            ["log"] => self.print_log(),
+            ["log", "save"] => self.save_log(None),
+            ["log", "level"] => self.print_log_level(),
+            ["log", "level", name] => match LogLevel::parse(name) {
+                Ok(level) => self.set_log_level(level),
+                Err(_) => println!("{}", tr_args("log.unknown_level", &[name])),
+            },
+            ["log", "save", path @ ..] => self.save_log(Some(&path.join(" "))),
+            ["audit"] => self.print_audit(None),
+            ["undo"] => self.request_undo(),
+            ["undo", "list"] => self.print_undo(),
+            ["audit", action] => match AuditAction::parse(action) {
+                Some(action) => self.print_audit(Some(action)),
+                None => {
+                    let names: Vec<&str> = AuditAction::ALL.iter().map(|action| action.name()).collect();
+                    println!("{}", tr_args("command.unknown_action", &[&action, &names.join(", ")]));
+                }
+            },
+            // Stopping every device always asks first, even with estop!
+            ["estop" | "estop!", "all", reason @ ..] => self.request_emergency_shutdown(None, reason.join(" ")),
+            ["estop", reason @ ..] => self.request_emergency_shutdown(Some(self.selected), reason.join(" ")),
+            // Skips the confirmation for a real emergency
+            ["estop!", reason @ ..] => {
+                let _ = self.execute(Command::EmergencyShutdown { device: self.selected, reason: reason.join(" ") }, &Actor::Console);
+            }
+            ["mode"] => self.print_mode(),
+            ["mode", to] => self.change_mode(to),
+            ["restart", "acquisition"] => self.restart_acquisition(),
+            ["motor", motor] => match motor.parse() {
+                Ok(motor) => self.print_motor(motor),
+                Err(_) => println!("{}", tr_args("command.invalid_motor", &[&motor])),
+            },
+            ["maintenance", motor] => match motor.parse() {
+                Ok(motor) => self.record_maintenance(motor),
+                Err(_) => println!("{}", tr_args("command.invalid_motor", &[&motor])),
+            },
+            ["limits"] => self.print_limits(),
+            ["channels"] => self.print_channel_setup(),
+            ["channel", kind, number, field @ ("tag" | "description" | "asset"), value @ ..] if !value.is_empty() => {
+                self.set_channel_tagging(kind, number, field, &value.join(" "));
+            }
+            ["channel", kind, number] | ["channel", kind, number, "watch"] => match number.parse() {
+                Ok(number) => self.show_channel_detail(kind, number, words.len() == 4),
+                Err(_) => println!("{}", tr("command.usage_channel")),
+            },
+            ["channel", ..] => println!("{}", tr("command.usage_channel_temp")),
+            ["find"] => {
+                println!("{}", tr("command.asset_prompt"));
+                self.pending_find = true;
+            }
+            ["find", id] => self.find_asset(id),
+            ["stats"] => self.print_statistics(),
+            ["stats", "reset"] => self.reset_statistics(),
+            ["shift"] => self.print_shift_summary(),
+            ["shift", "close"] => self.close_shift_now(),
+            ["notes"] => self.print_notes(None),
+            ["notes", span] => match parse_span(span) {
+                Some(window) => self.print_notes(Some(window)),
+                None => println!("{}", tr("command.usage_notes")),
+            },
+            // Without a number after it, the first word is just the start of the text
+            ["note", "correct", number, text @ ..] if !text.is_empty() => match number.trim_start_matches('#').parse() {
+                Ok(id) => self.add_note(NoteSubject::Correction(id), &text.join(" ")),
+                Err(_) => self.add_note(NoteSubject::None, &words[1..].join(" ")),
+            },
+            ["note", "alarm", number, text @ ..] if !text.is_empty() => match number.trim_start_matches('#').parse() {
+                Ok(id) => self.add_note(NoteSubject::Alarm(id), &text.join(" ")),
+                Err(_) => self.add_note(NoteSubject::None, &words[1..].join(" ")),
+            },
+            ["note", kind @ ("temp" | "pressure"), number, text @ ..] if !text.is_empty() => match number.parse() {
+                Ok(number) => self.add_note(NoteSubject::Channel(kind, number), &text.join(" ")),
+                Err(_) => self.add_note(NoteSubject::None, &words[1..].join(" ")),
+            },
+            ["note", text @ ..] if !text.is_empty() => self.add_note(NoteSubject::None, &text.join(" ")),
+            ["note"] => println!("{}", tr("command.usage_note")),
+            ["stats", kind, channel] => match channel.parse() {
+                Ok(channel) => self.print_channel_statistics(kind, channel),
+                Err(_) => println!("{}", tr("command.usage_stats")),
+            },
+            ["calibration"] => self.print_calibrations(),
+            ["calibration", kind, channel] => match channel.parse() {
+                Ok(channel) => self.print_channel_calibration(kind, channel),
+                Err(_) => println!("{}", tr("command.usage_calibration")),
+            },
+            ["forces"] => self.print_forces(),
+            ["bypass"] => self.print_bypasses(),
+            ["bypass", "clear", "all"] => self.rearm_interlocks(None),
+            ["bypass", "clear", number] => match number.parse() {
+                Ok(number) => self.rearm_interlocks(Some(number)),
+                Err(_) => println!("{}", tr("command.usage_bypass")),
+            },
+            ["bypass", number, minutes, reason @ ..] => match number.parse() {
+                Ok(number) => self.bypass_interlock(number, minutes, reason),
+                Err(_) => println!("{}", tr("command.usage_bypass_n")),
+            },
+            ["force", "clear", "all"] => self.clear_all_forces(),
+            ["force", "clear", kind, channel] => match channel.parse() {
+                Ok(channel) => self.force_channel(kind, channel, None),
+                Err(_) => println!("{}", tr("command.usage_force")),
+            },
+            ["force", kind, channel, value] => match (channel.parse(), value.parse()) {
+                (Ok(channel), Ok(value)) => self.force_channel(kind, channel, Some(value)),
+                _ => println!("{}", tr("command.usage_force_temp")),
+            },
+            ["calibrate", kind, channel, rest @ ..] => match channel.parse() {
+                Ok(channel) => self.calibrate_channel(kind, channel, rest),
+                Err(_) => println!("{}", tr("calibration.usage_calibrate")),
+            },
+            ["alarms"] => self.print_alarms(),
+            ["alarm-log"] => self.print_alarm_log(),
+            ["ack", target] => self.acknowledge_alarm(target),
+            ["silence"] => self.silence_horn(),
+            ["notify"] => self.print_notifications(),
+            ["notify", "test"] => self.send_test_notification(),
+            ["energy", "reset"] => self.reset_energy(),
+            ["vibration", "limit", limit] => match limit.parse() {
+                Ok(limit) => self.set_vibration_limit(limit),
+                Err(_) => println!("{}", tr("command.usage_vibration")),
+            },
+            ["vibration", rest @ ..] if rest.len() <= 1 => match rest.first().map_or(Ok(1), |motor| motor.parse()) {
+                Ok(motor) => self.print_vibration(motor),
+                Err(_) => println!("{}", tr("command.usage_vibration_motor")),
+            },
+            ["energy", rest @ ..] if rest.len() <= 1 => match rest.first().map_or(Ok(15), |minutes| minutes.parse()) {
+                Ok(minutes @ (1 | 5 | 15)) => self.print_energy(minutes),
+                _ => println!("{}", tr("command.usage_energy")),
+            },
+            ["trend", "all", rest @ ..] if rest.len() <= 1 => match rest.first().map_or(Ok(1), |minutes| minutes.parse()) {
+                Ok(minutes @ (1 | 5 | 15)) => self.print_pen_trend(minutes),
+                _ => println!("{}", tr("command.usage_trend")),
+            },
+            ["trend", kind, channel, rest @ ..] if rest.len() <= 1 => {
+                let minutes = rest.first().map_or(Ok(1), |minutes| minutes.parse());
+                match (channel.parse(), minutes) {
+                    (Ok(channel), Ok(minutes @ (1 | 5 | 15))) => self.print_trend(kind, channel, minutes),
+                    _ => println!("{}", tr("command.usage_trend_temp")),
+                }
+            }
+            ["timeline", "event", number] => match number.parse() {
+                Ok(number) => self.print_timeline_event(number),
+                Err(_) => println!("{}", tr("command.usage_timeline")),
+            },
+            ["timeline", rest @ ..] if rest.len() <= 3 => match parse_timeline_window(rest) {
+                Some((window, at)) => self.print_timeline(window, at),
+                None => println!("{}", tr("command.usage_timeline_span")),
+            },
+            ["pens"] => self.print_pens(),
+            ["pen", "axis", axis, range @ ..] => self.set_pen_axis(axis, range),
+            ["pen", kind, channel, change @ ..] if !change.is_empty() => match channel.parse() {
+                Ok(channel) => self.set_pen(kind, channel, change),
+                Err(_) => println!("{}", tr("command.usage_pen")),
+            },
+            ["cursor"] => self.print_cursors(),
+            ["cursor", "clear"] => self.set_cursors(Vec::new()),
+            ["cursor", times @ ..] if times.len() <= 2 => match times.iter().map(|time| parse_cursor(time)).collect() {
+                Some(cursors) => self.set_cursors(cursors),
+                None => println!("{}", tr("command.usage_cursor")),
+            },
+            ["history", kind, channel, span] => match (channel.parse(), parse_span(span)) {
+                (Ok(channel), Some(window)) => self.print_history(kind, channel, window),
+                _ => println!("{}", tr("command.usage_history")),
+            },
+            ["export", path @ ..] if !path.is_empty() => self.export_csv(&path.join(" ")),
+            ["report"] => self.generate_report(),
+            ["trips"] => self.print_trips(),
+            ["trip", number] => match number.parse() {
+                Ok(number) => self.print_trip(number, None),
+                Err(_) => println!("{}", tr("command.usage_trip")),
+            },
+            ["trip", number, kind, channel] => match (number.parse(), channel.parse()) {
+                (Ok(number), Ok(channel)) => self.print_trip(number, Some((kind, channel))),
+                _ => println!("{}", tr("command.usage_trip")),
+            },
+            ["compare"] => self.print_comparison(),
+            ["compare", "export", path @ ..] if !path.is_empty() => self.export_comparison(&path.join(" ")),
+            ["compare", path @ ..] => self.load_baseline(&path.join(" ")),
+            ["save", path @ ..] if !path.is_empty() => self.save_snapshot(&path.join(" ")),
+            ["load", path @ ..] if !path.is_empty() => self.load_snapshot(&path.join(" ")),
+            ["faults"] => self.print_faults(),
+            ["fault", "clear", "all"] => self.clear_fault(None),
+            ["fault", "clear", kind @ ("input" | "output"), number] => match (IoKind::parse(kind), number.parse()) {
+                (Ok(kind), Ok(number)) => self.release_point(kind, number),
+                _ => println!("{}", tr_args("command.invalid_number", &[&kind, &number])),
+            },
+            ["fault", kind @ ("input" | "output"), number, state @ ("on" | "off")] => match (IoKind::parse(kind), number.parse()) {
+                (Ok(kind), Ok(number)) => self.force_point(kind, number, *state == "on"),
+                _ => println!("{}", tr_args("command.invalid_number", &[&kind, &number])),
+            },
+            ["fault", "clear", kind, channel @ ..] if channel.len() <= 1 => match FaultTarget::parse(kind, channel.first().copied()) {
+                Ok(target) => self.clear_fault(Some(target)),
+                Err(reason) => println!("{}", reason),
+            },
+            ["fault", "plc", "drop", seconds] => match seconds.parse::<u64>() {
+                Ok(seconds @ 1..) => self.inject_fault(FaultTarget::PlcLink, FaultKind::OpenCircuit, Some(Duration::from_secs(seconds))),
+                _ => println!("{}", tr("command.usage_fault")),
+            },
+            ["fault", kind, rest @ ..] => {
+                // The PLC link has no channel number
+                let (channel, fault) = match rest {
+                    [channel, fault @ ..] if *kind != "plc" => (Some(*channel), fault),
+                    fault => (None, fault),
+                };
+                match FaultTarget::parse(kind, channel).and_then(|target| Ok((target, FaultKind::parse(fault)?))) {
+                    Ok((target, fault)) => self.inject_fault(target, fault, None),
+                    Err(reason) => println!("{}", tr_args("command.usage_fault_temp", &[&reason])),
+                }
+            }
+            ["recipes"] => self.print_recipes(),
+            ["recipe", action @ ("show" | "save" | "load" | "delete"), name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                match *action {
+                    "show" => self.print_recipe(&name),
+                    "save" => self.save_recipe(&name),
+                    "load" => self.load_recipe(&name),
+                    _ => self.delete_recipe(&name),
+                }
+            }
+            ["recipe", ..] => println!("{}", tr("command.usage_recipe")),
+            ["loop"] => self.print_loop(),
+            ["loop", mode @ ("auto" | "manual")] => match LoopMode::parse(mode) {
+                Ok(mode) => self.set_loop_mode(mode),
+                Err(e) => println!("{}", e),
+            },
+            ["loop", parameter @ ("sp" | "kp" | "ki" | "kd"), value] => match value.parse() {
+                Ok(value) => self.set_loop_parameter(parameter, value),
+                Err(_) => println!("{}", tr_args("command.invalid_value", &[&value])),
+            },
+            ["loop", ..] => println!("{}", tr("command.usage_loop")),
+            ["io"] => self.print_io(),
+            ["output", output, state @ ("on" | "off")] => match output.parse() {
+                Ok(output) => self.set_output(output, *state == "on"),
+                Err(_) => println!("{}", tr_args("command.invalid_output", &[&output])),
+            },
+            ["output", ..] => println!("{}", tr("command.usage_output")),
+            ["ports"] => print_serial_ports(),
+            ["units"] => println!("{}", tr_args("settings.units", &[&self.units])),
+            ["units", name] => match Unit::parse(name) {
+                Ok(unit) => {
+                    self.units.set(unit);
+                    self.log(&tr_args("command.units_changed", &[&self.units]));
+                    self.settings.units = Some(self.units);
+                    self.save_settings();
+                }
+                Err(reason) => println!("{}", reason),
+            },
+            ["notices"] => self.print_toasts(),
+            ["dismiss"] => self.dismiss_toast("all"),
+            ["dismiss", which] => self.dismiss_toast(which),
+            ["theme"] => print_theme(),
+            ["theme", name] => match Theme::parse(name) {
+                Ok(theme) => {
+                    platform::set_theme(theme);
+                    self.log(&tr_args("command.theme_changed", &[&theme]));
+                    print_theme();
+                    self.settings.theme = Some(theme);
+                    self.save_settings();
+                }
+                Err(_) => println!("{}", tr_args("command.unknown_theme", &[name, &Theme::ALL.map(|theme| theme.name()).join(", ")])),
+            },
+            ["language"] => print_language(),
+            ["language", code] => match Language::parse(code) {
+                Ok(language) => {
+                    i18n::set_language(language);
+                    self.log(&tr_args("language.changed", &[&language]));
+                    print_language();
+                    self.settings.language = Some(language);
+                    self.save_settings();
+                }
+                Err(_) => println!("{}", tr_args("language.unknown", &[code])),
+            },
+            ["view"] => println!("{}", tr_args("settings.view", &[&if self.gauges { tr("settings.gauges") } else { tr("settings.list") }])),
+            ["view", view @ ("list" | "gauges")] => {
+                self.gauges = *view == "gauges";
+                self.log(&tr_args("settings.view_changed", &[&if self.gauges { tr("settings.gauges") } else { tr("settings.list") }]));
+                self.settings.gauges = Some(self.gauges);
+                self.save_settings();
+            }
+            ["settings"] => self.print_settings(),
+            ["config", "export", path] => self.export_configuration(path),
+            ["config", "import", path] => self.import_configuration(path),
+            ["config", ..] => println!("{}", tr("command.usage_config")),
+            ["settings", "reset"] => self.reset_settings(),
+            ["interval"] => println!("{}", tr_args("settings.update_interval", &[&self.device().simulation_thread.update_interval_ms()])),
+            ["interval", interval_ms] => match interval_ms.parse() {
+                Ok(interval_ms) => self.set_update_interval(interval_ms),
+                Err(_) => println!("{}", tr("command.usage_interval")),
+            },
+            ["scenario"] => match self.scenario_status() {
+                Some(line) => println!("{}", line),
+                None => println!("{}", tr("command.no_scenario")),
+            },
+            ["scenario", "load", path @ ..] if !path.is_empty() => self.load_scenario(&path.join(" ")),
+            ["scenario", action @ ("start" | "pause" | "reset")] => self.control_scenario(action),
+            ["source"] => self.print_source(),
+            ["source", choice] => self.swap_source(choice),
+            ["replay"] => match self.replay_status() {
+                Some(line) => println!("{}", line),
+                None => println!("{}", tr("command.no_replay")),
+            },
+            ["replay", "load", path @ ..] if !path.is_empty() => self.load_replay(&path.join(" ")),
+            ["replay", "stop"] => self.end_replay(),
+            ["replay", rest @ ..] => self.control_replay(rest),
+            ["pause"] => self.toggle_pause(),
+            ["resume"] => self.resume_simulation(),
+            ["ambient"] => self.print_ambient(),
+            ["ambient", "auto"] => self.release_ambient(),
+            ["ambient", kind @ ("temp" | "pressure"), value] => match (ChannelKind::parse(kind), value.parse::<f32>()) {
+                (Ok(kind), Ok(value)) if value.is_finite() => self.set_ambient(kind, value),
+                _ => println!("{}", tr("command.usage_ambient")),
+            },
+            ["ambient", ..] => println!("{}", tr("command.usage_ambient_temp")),
+            ["help"] => self.print_help(),
+            ["keys" | "?"] => self.print_shortcuts(),
+            ["quit"] | ["exit"] => return false,
+            _ => println!("{}", tr_args("command.unknown_command", &[&line.trim()])),
+        }
+        true
+    }
+
+    // One card per device with what needs attention; the selected one is marked
+    fn print_devices(&self) {
+        println!("\n{}", tr_args("devices.title", &[&self.devices.len()]));
+        for (index, device) in self.devices.iter().enumerate() {
+            let marker = if index == self.selected { ">" } else { " " };
+            let data = match device.sensor_data.lock() {
+                Ok(data) => data.clone(),
+                Err(_) => {
+                    print_colored(StatusColor::Red, &tr_args("devices.sensor_data_lost", &[&marker, &(index + 1), &device.name]));
+                    continue;
+                }
+            };
+            let stale = data.staleness(SystemTime::now(), device.update_interval()).is_some();
+            let (color, connection) = match (&data.connection, data.link.health()) {
+                (ConnectionStatus::Connecting, _) => (StatusColor::Yellow, tr("devices.connecting")),
+                (_, LinkHealth::Offline) => (StatusColor::Red, tr("devices.offline")),
+                (_, LinkHealth::Degraded) => (StatusColor::Yellow, tr("devices.degraded")),
+                (_, LinkHealth::Connected) if stale => (StatusColor::Yellow, tr("devices.not_updating")),
+                (_, LinkHealth::Connected) => (StatusColor::Green, tr("devices.online")),
+            };
+            let label = self.device_label(index);
+            let alarms = self.alarms.iter().filter(|alarm| alarm.is_active() && alarm.source.starts_with(&label)).count();
+            let running = data.motors.iter().filter(|motor| motor.running).count();
+            let (color, state) = if data.emergency_shutdown {
+                (StatusColor::Red, tr("devices.shut_down").to_string())
+            } else if alarms > 0 {
+                (StatusColor::Red, tr_args("devices.alarms", &[&alarms]))
+            } else {
+                (color, tr("devices.no_alarms").to_string())
+            };
+            print_colored(color, &format!("{} {}. {} - {} ({})", marker, index + 1, device.name, connection, device.source_name));
+            print_colored(color, &tr_args("devices.motors_running", &[&running, &data.motors.len(), &state]));
+        }
+        if self.devices.len() > 1 {
+            println!("{}", tr("devices.hint"));
+        }
+    }
+
+    fn select_device(&mut self, target: &str) {
+        let found = match target.parse::<usize>() {
+            Ok(number) => number.checked_sub(1).filter(|index| *index < self.devices.len()),
+            Err(_) => self.devices.iter().position(|device| device.name.eq_ignore_ascii_case(target)),
+        };
+        match found {
+            Some(index) => {
+                self.selected = index;
+                self.log(&tr_args("devices.selected", &[&self.devices[index].name, &(index + 1), &self.devices.len()]));
+            }
+            None => println!("{}", tr_args("devices.unknown", &[&target])),
+        }
+    }
+
+    fn print_audit(&self, filter: Option<AuditAction>) {
+        let events: Vec<_> = self.audit.events().iter()
+            .filter(|event| match filter {
+                Some(action) => event.action == action,
+                None => true,
+            })
+            .collect();
+        let saved = if self.audit.is_writing() { tr_args("audit.saved_to", &[&self.audit.path().display()]) } else { tr("audit.not_saved").to_string() };
+        println!("\n{}", tr_args("audit.title", &[&events.len(), &saved]));
+        for event in events {
+            println!("[{}] {:<12} {:<12} {}", format_time(event.timestamp), event.user, event.action.name(), event.detail);
+        }
+    }
+
+    fn print_diagnostic_history(&self) {
+        println!("\n{}", tr_args("diag.history_title", &[&self.diagnostics.history().len()]));
+        for run in self.diagnostics.history().iter().rev() {
+            let (color, outcome) = diagnostic_outcome(&run.result);
+            print_colored(color, &tr_args("diag.history_run", &[&format_datetime(run.started), &format!("{:<11}", run.trigger.label()), &format!("{:>6}", run.elapsed.as_millis()), &outcome]));
+        }
+        if let Some(running) = self.diagnostics.running() {
+            println!("{}", tr_args("diag.running", &[&running.label()]));
+        }
+        match (self.diagnostic_interval, self.next_scheduled_diagnostic) {
+            (Some(interval), Some(due)) => println!("{}", tr_args("diag.schedule", &[
+                &format_span(interval),
+                &format_span(due.saturating_duration_since(Instant::now())),
+            ])),
+            _ => println!("{}", tr("diag.no_schedule")),
+        }
+    }
+
+    // The AND of all interlocks, then each with what it protects
+    fn print_interlocks(&self, data: &SensorData) {
+        let dropped = data.interlocks.iter().filter(|interlock| !interlock.satisfied).count();
+        match (data.interlocks.len(), dropped) {
+            (0, _) => println!("{}", tr("interlocks.none")),
+            (count, 0) => print_colored(StatusColor::Green, &tr_args("interlocks.satisfied", &[&count])),
+            (count, dropped) => print_colored(StatusColor::Red, &tr_args("interlocks.not_satisfied", &[&dropped, &count])),
+        }
+        for interlock in &data.interlocks {
+            let protects = if interlock.motors.is_empty() {
+                tr("interlocks.all_motors").to_string()
+            } else {
+                interlock.motors.iter().filter_map(|motor| data.motors.get(*motor)).map(Motor::label).collect::<Vec<_>>().join(", ")
+            };
+            if let Some(bypass) = &interlock.bypass {
+                let remaining = format_elapsed(bypass.remaining(SystemTime::now()));
+                let state = if interlock.satisfied { "interlocks.bypassed" } else { "interlocks.bypassed_violated" };
+                print_colored(StatusColor::Yellow, &format!("  ⚠ {}", tr_args(state, &[&interlock.name, &interlock.condition, &protects, &remaining])));
+            } else if interlock.satisfied {
+                print_colored(StatusColor::Green, &format!("  ✓ {}", tr_args("interlocks.holds", &[&interlock.name, &interlock.condition, &protects])));
+            } else {
+                print_colored(StatusColor::Red, &format!("  ✗ {}", tr_args("interlocks.holding", &[&interlock.name, &interlock.condition, &protects])));
+            }
+        }
+        // One line per kind of point; 'io' has the full grid
+        let kinds = [(tr("io.inputs"), IoKind::Input), (tr("io.outputs"), IoKind::Output)];
+        let width = kinds.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0) + 1;
+        for (label, kind) in kinds {
+            let points = data.digital_points(kind);
+            if !points.is_empty() {
+                let points: Vec<String> = points.iter().map(|point| format!("{} {}{}", led(point.state), point.name, if point.forced { " (F)" } else { "" })).collect();
+                println!("{:<width$} {}", format!("{}:", label), points.join("  "));
+            }
+        }
+    }
+
+    // Digital I/O with LED-style indicators, numbered for 'output' and 'fault input|output'
+    fn print_io(&self) {
+        let data = match self.read_sensors() {
+            Some(data) => data,
+            None => return,
+        };
+
+        println!("\n{}", tr("io.title"));
+        for (title, kind) in [(tr("io.inputs"), IoKind::Input), (tr("io.outputs"), IoKind::Output)] {
+            let points = data.digital_points(kind);
+            println!("{}:", title);
+            if points.is_empty() {
+                println!("{}", tr("io.none"));
+            }
+            for (i, point) in points.iter().enumerate() {
+                let line = format!("  {:>2}  {} {:<3}  {}", i + 1, led(point.state), on_off(point.state), point.name);
+                if point.forced {
+                    print_colored(StatusColor::Yellow, &tr_args("io.forced_would", &[&line, &on_off(point.live)]));
+                } else if point.state {
+                    print_colored(StatusColor::Green, &line);
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+
+    fn print_log(&self) {
+        println!("\n{}", tr_args("log.title", &[&self.diagnostic_log.len()]));
+        for entry in self.diagnostic_log.iter() {
+            println!("{}", entry);
+        }
+    }
+
+    // Commands the current user can't use are shown with the reason
+    fn print_shortcuts(&self) {
+        println!("\n{}", tr("shortcuts.title"));
+        println!("{}", tr("shortcuts.usage"));
+        let keys = SHORTCUTS.iter().map(|(key, _, command)| (*key, *command)).chain([("1-9", "device N")]);
+        for (key, command) in keys {
+            let line = format!("  {:<8} {}", key, command);
+            let words: Vec<&str> = command.split_whitespace().collect();
+            match required_role(&words).map(|role| self.check_role(role)) {
+                Some(Err(reason)) => print_colored(StatusColor::Yellow, &tr_args("shortcuts.locked", &[&line, &reason])),
+                _ => println!("{}", line),
+            }
+        }
+        println!("{}", tr("shortcuts.ctrl_shift"));
+        println!("{}", tr("shortcuts.ctrl_z"));
+    }
+
+    fn print_help(&self) {
+        println!("{}", tr("help.title"));
+        for (role, key) in HELP {
+            match role.map(|role| self.check_role(role)) {
+                Some(Err(reason)) => print_colored(StatusColor::Yellow, &format!("{}  [{}]", tr(key), tr_args("help.locked", &[&reason]))),
+                _ => println!("{}", tr(key)),
+            }
+        }
+    }
+}
+
+// How often the motor runtime counters are written out while running
+const MAINTENANCE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// Time between motors when restarting after a shutdown, so they don't all draw inrush current at once
+const RESTART_STAGGER: Duration = Duration::from_secs(2);
+
+// Lines the diagnostic log keeps, the oldest dropped first; a headless console can run for months
+const DIAGNOSTIC_LOG_LINES: usize = 10_000;
+
+// Wrong PINs in a row before login is refused for LOGIN_LOCKOUT
+const MAX_LOGIN_ATTEMPTS: u32 = 3;
+const LOGIN_LOCKOUT: Duration = Duration::from_secs(60);
+// Alarm source for failed scheduled diagnostics
+const DIAGNOSTIC_ALARM_SOURCE: &str = "Diagnostic";
+// Alarm source for a data source that has gone offline
+const LINK_ALARM_SOURCE: &str = "PLC link";
+// Alarm source for a sensor thread the watchdog found hung or stopped
+const WATCHDOG_ALARM_SOURCE: &str = "Data acquisition";
+
+// Keys for the critical actions, and for looking up a scanned asset, as the terminal
+// sends them: function keys as escape sequences (xterm, then the Linux console),
+// Ctrl+letter as a control character. On a line of its own each stands for its command;
+// the estop skips the confirmation.
+const SHORTCUTS: &[(&str, &[&str], &str)] = &[
+    ("F1", &["\x1bOP", "\x1b[11~", "\x1b[[A"], "diag"),
+    ("F5", &["\x1b[15~", "\x1b[[E"], "ack all"),
+    ("Ctrl+E", &["\x05"], "estop! keyboard shortcut"),
+    ("Ctrl+R", &["\x12"], "reset"),
+    ("Ctrl+F", &["\x06"], "find"),
+    ("Ctrl+Z", &["\x1a"], "undo"),
+];
+
+// An answer that confirms: 'yes' always does, as does the word for it in the console's language
+fn is_yes(answer: &str) -> bool {
+    ["yes", tr("confirm.yes")].iter().any(|word| answer == *word || answer == word.to_uppercase())
+}
+
+// The key and command a line stands for, if it is a shortcut; a digit alone selects that device
+fn expand_shortcut(line: &str) -> Option<(String, String)> {
+    if let Some((key, _, command)) = SHORTCUTS.iter().find(|(_, sequences, _)| sequences.contains(&line)) {
+        return Some((key.to_string(), command.to_string()));
+    }
+    match line.as_bytes() {
+        [digit @ b'1'..=b'9'] => Some((line.to_string(), format!("device {}", *digit as char))),
+        _ => None,
+    }
+}
+
+// The role a command needs when login is configured. Viewing, the estop and login
+// itself are open to anyone at the console.
+fn required_role(words: &[&str]) -> Option<Role> {
+    if let Some(Ok(command)) = Command::parse(words, 0) {
+        return command.required_role();
+    }
+    match words {
+        ["diag"] | ["ack", ..] | ["note", ..] | ["maintenance", ..] | ["restart", "acquisition"] | ["notify", "test"] | ["stats", "reset"] => Some(Role::Operator),
+        ["load", ..] | ["fault", ..] | ["interval", _]
+        | ["scenario", _, ..] | ["replay", _, ..] | ["pause"] | ["resume"] | ["settings", "reset"] | ["output", ..] | ["loop", _, ..]
+        | ["recipe", "save" | "load" | "delete", ..] | ["calibrate", ..] | ["force", ..] | ["channel", _, _, _, _, ..] | ["shift", "close"] | ["source", _] | ["mode", _] | ["bypass", _, ..] | ["config", ..]
+        | ["ambient", _, ..] | ["energy", "reset"] | ["vibration", "limit", ..] => Some(Role::Supervisor),
+        _ => None,
+    }
+}
+
+// What a 'note' is about, as typed
+enum NoteSubject<'a> {
+    None,
+    // Kind as typed and number from 1, on the selected device
+    Channel(&'a str, usize),
+    Alarm(u32),
+    // The note it corrects
+    Correction(u64),
+    // An asset ID 'find' didn't know
+    Asset(&'a str),
+}
+
+// The summary line of a finished diagnostic
+fn diagnostic_outcome(result: &Result<DiagnosticSummary, String>) -> (StatusColor, String) {
+    match result {
+        Ok(DiagnosticSummary { warnings: 0, failures: 0 }) => (StatusColor::Green, tr("diag.outcome_ok").to_string()),
+        Ok(DiagnosticSummary { warnings, failures: 0 }) => (StatusColor::Yellow, tr_args("diag.outcome_warnings", &[warnings])),
+        Ok(DiagnosticSummary { warnings, failures }) => (StatusColor::Red, tr_args("diag.outcome_failures", &[failures, warnings])),
+        Err(e) => (StatusColor::Red, tr_args("diag.outcome_aborted", &[e])),
+    }
+}
+
+// diagnostic_outcome's text in English, for trip records
+fn recorded_outcome(result: &Result<DiagnosticSummary, String>) -> String {
+    match result {
+        Ok(DiagnosticSummary { warnings: 0, failures: 0 }) => "All Systems Operational".to_string(),
+        Ok(DiagnosticSummary { warnings, failures: 0 }) => format!("warnings: {}", warnings),
+        Ok(DiagnosticSummary { warnings, failures }) => format!("failures: {}, warnings: {}", failures, warnings),
+        Err(e) => format!("aborted: {}", e),
+    }
+}
+
+fn motor_status(motor: &Motor) -> &'static str {
+    if motor.overloaded {
+        return tr("motor.overload_trip");
+    }
+    match (motor.running, motor.speed) {
+        (false, 0) => tr("motor.stopped"),
+        (false, _) => tr("motor.stopping"),
+        (true, speed) if speed.abs_diff(motor.setpoint) > MOTOR_SPEED_TOLERANCE => tr("motor.ramping"),
+        (true, _) => tr("motor.running"),
+    }
+}
+
+// A motor by its number from 1, name, tag or asset ID
+fn find_motor(data: &SensorData, id: &str) -> Option<usize> {
+    if let Ok(number) = id.parse::<usize>() {
+        return Some(number).filter(|number| (1..=data.motors.len()).contains(number));
+    }
+    data.motors.iter()
+        .position(|motor| motor.name.eq_ignore_ascii_case(id) || motor.tagging.tag.eq_ignore_ascii_case(id) || motor.tagging.has_asset(id))
+        .map(|index| index + 1)
+}
+
+// An analog channel by its name, tag or asset ID, as its kind and number from 1
+fn find_channel(data: &SensorData, id: &str) -> Option<(ChannelKind, usize)> {
+    let channel = data.channels.iter()
+        .find(|channel| channel.name.eq_ignore_ascii_case(id) || (!channel.tagging.tag.is_empty() && channel.tagging.tag.eq_ignore_ascii_case(id)) || channel.tagging.has_asset(id))?;
+    let number = data.channels_of(channel.kind).position(|found| found.name == channel.name)?;
+    Some((channel.kind, number + 1))
+}
+
+// A channel kind as typed in commands
+fn kind_word(kind: ChannelKind) -> &'static str {
+    match kind {
+        ChannelKind::Temperature => "temp",
+        ChannelKind::Pressure => "pressure",
+    }
+}
+
+// A calibration offset (SI) as a signed difference in the display unit
+fn format_offset(unit: Unit, offset: f32) -> String {
+    format!("{:+.*}{}", unit.decimals() + 1, unit.from_si(offset) - unit.from_si(0.0), unit.symbol())
+}
+
+// The offset, gain and technician's initials of 'calibrate'; the initials are required
+fn parse_calibration(args: &[&str]) -> Result<(Option<f32>, Option<f32>, String), String> {
+    let (mut offset, mut gain, mut technician) = (None, None, None);
+    for pair in args.chunks(2) {
+        match pair {
+            ["offset", value] => offset = Some(value.parse::<f32>().map_err(|_| tr_args("calibration.invalid_offset", &[value]))?),
+            ["gain", value] => gain = Some(value.parse::<f32>().map_err(|_| tr_args("calibration.invalid_gain", &[value]))?),
+            ["by", initials] if (1..=4).contains(&initials.len()) && initials.chars().all(|c| c.is_ascii_alphabetic()) => {
+                technician = Some(initials.to_ascii_uppercase());
+            }
+            ["by", initials] => return Err(tr_args("calibration.invalid_initials", &[initials])),
+            _ => return Err(tr_args("calibration.unexpected", &[&pair.join(" ")])),
+        }
+    }
+    let technician = technician.ok_or(tr("calibration.initials_required"))?;
+    Ok((offset, gain, technician))
+}
+
+fn format_hours(duration: Duration) -> String {
+    format!("{:.1} h", duration.as_secs_f64() / 3600.0)
+}
+
+const TREND_WIDTH: usize = 60;
+const CURSOR_NAMES: [char; 2] = ['A', 'B'];
+// Trend history in an incident report; as much as is kept at the default update rate
+const REPORT_WINDOW: Duration = Duration::from_secs(15 * 60);
+// Sparkline of the channel detail view
+const CHANNEL_DETAIL_WINDOW: Duration = Duration::from_secs(5 * 60);
+const DAY_SECONDS: u64 = 24 * 60 * 60;
+const TIMELINE_WINDOW: Duration = Duration::from_secs(DAY_SECONDS);
+// Events listed under the timeline; earlier ones are only marked
+const TIMELINE_LIST: usize = 30;
+// Chart shown with a timeline event
+const TIMELINE_CHART: Duration = Duration::from_secs(30 * 60);
+const TREND_HEIGHT: usize = 12;
+
+// Plots samples as an ASCII chart, one column per time slice, with the alarm and
+// warning limits drawn as horizontal lines. Samples and limits are SI, drawn in `unit`.
+// Markers are vertical lines at an instant, headed by their character; with any, the
+// axis is labelled with clock times.
+fn render_trend(samples: &[Sample], limits: &Limits, unit: Unit, window: Duration, now: SystemTime, markers: &[(SystemTime, char)]) -> Vec<String> {
+    let limits = unit.limits_from_si(limits);
+    let mut columns = vec![(0.0f32, 0u32); TREND_WIDTH];
+    for sample in samples {
+        let age = now.duration_since(sample.time).unwrap_or_default().as_secs_f32();
+        let slot = (age / window.as_secs_f32() * TREND_WIDTH as f32) as usize;
+        // Open-circuit samples leave a gap rather than skewing the column average
+        if slot < TREND_WIDTH && sample.value.is_finite() {
+            let column = &mut columns[TREND_WIDTH - 1 - slot];
+            column.0 += unit.from_si(sample.value);
+            column.1 += 1;
+        }
+    }
+    let values: Vec<Option<f32>> = columns.iter()
+        .map(|(sum, count)| if *count > 0 { Some(sum / *count as f32) } else { None })
+        .collect();
+
+    let mut low = limits.low_alarm;
+    let mut high = limits.high_alarm;
+    for value in values.iter().flatten() {
+        low = low.min(*value);
+        high = high.max(*value);
+    }
+    // At least one displayed step, so a flat line still gets a scale
+    let margin = ((high - low) * 0.05).max(0.1f32.powi(unit.decimals() as i32));
+    low -= margin;
+    high += margin;
+
+    let row_of = |value: f32| ((high - value) / (high - low) * (TREND_HEIGHT - 1) as f32).round() as usize;
+    let mut grid = vec![vec![' '; TREND_WIDTH]; TREND_HEIGHT];
+    for (limit, mark) in [
+        (limits.low_warning, '-'),
+        (limits.high_warning, '-'),
+        (limits.low_alarm, '='),
+        (limits.high_alarm, '='),
+    ] {
+        grid[row_of(limit)].fill(mark);
+    }
+    for (column, value) in values.iter().enumerate() {
+        if let Some(value) = value {
+            grid[row_of(*value)][column] = '*';
+        }
+    }
+    for (marker, mark) in markers {
+        let age = now.duration_since(*marker).unwrap_or_default().as_secs_f32();
+        let slot = (age / window.as_secs_f32() * TREND_WIDTH as f32) as usize;
+        if slot < TREND_WIDTH {
+            for cells in grid.iter_mut() {
+                let cell = &mut cells[TREND_WIDTH - 1 - slot];
+                if *cell == ' ' {
+                    *cell = '|';
+                }
+            }
+            grid[0][TREND_WIDTH - 1 - slot] = *mark;
+        }
+    }
+
+    let mut lines: Vec<String> = grid.iter().enumerate().map(|(row, cells)| {
+        let value = high - row as f32 * (high - low) / (TREND_HEIGHT - 1) as f32;
+        format!("{:>8.*} {:<3}|{}", unit.decimals(), value, unit, cells.iter().collect::<String>())
+    }).collect();
+    let (start, end) = match markers {
+        [] => (format!("-{}", format_span(window)), tr("trend.now").to_string()),
+        _ => (format_time(now.checked_sub(window).unwrap_or(UNIX_EPOCH)), format_time(now)),
+    };
+    lines.push(format!("{:>13}+{}", "", "-".repeat(TREND_WIDTH)));
+    lines.push(format!("{:>13}{}{:>width$}", "", start, end, width = TREND_WIDTH - start.len()));
+    lines
+}
+
+// A channel 'channel temp|pressure N watch' keeps on screen
+struct ChannelWatch {
+    device: usize,
+    kind: ChannelKind,
+    // From 1, as typed
+    number: usize,
+    next: Instant,
+}
+
+// A channel of the selected device with its pen on the 'trend all' chart
+struct PenChannel {
+    // Where the pen is stored in settings.json
+    key: String,
+    label: String,
+    kind: ChannelKind,
+    // Number within its kind, from 0, as the history buffer is indexed
+    index: usize,
+    pen: Pen,
+}
+
+// Plots several channels' samples on one chart, each with its pen's mark, scaled against
+// the left or right axis. Samples are SI, drawn in each pen's unit; an axis without a
+// fixed range is scaled to the pens on it. Where pens cross the later one is drawn.
+// Markers are drawn and label the axis as in render_trend.
+fn render_pens(series: &[(Vec<Sample>, Pen, Unit)], ranges: [Option<(f32, f32)>; 2], window: Duration, now: SystemTime, markers: &[(SystemTime, char)]) -> Vec<String> {
+    let columns: Vec<Vec<Option<f32>>> = series.iter().map(|(samples, _, unit)| {
+        let mut columns = vec![(0.0f32, 0u32); TREND_WIDTH];
+        for sample in samples {
+            let age = now.duration_since(sample.time).unwrap_or_default().as_secs_f32();
+            let slot = (age / window.as_secs_f32() * TREND_WIDTH as f32) as usize;
+            if slot < TREND_WIDTH && sample.value.is_finite() {
+                let column = &mut columns[TREND_WIDTH - 1 - slot];
+                column.0 += unit.from_si(sample.value);
+                column.1 += 1;
+            }
+        }
+        columns.iter().map(|(sum, count)| if *count > 0 { Some(sum / *count as f32) } else { None }).collect()
+    }).collect();
+
+    // Each axis's low and high, and the unit and decimals its labels are shown with
+    let scales: Vec<Option<(f32, f32, String, usize)>> = Axis::ALL.iter().zip(ranges).map(|(axis, range)| {
+        let on_axis: Vec<usize> = (0..series.len()).filter(|pen| series[*pen].1.axis == *axis).collect();
+        let first = *on_axis.first()?;
+        let unit = series[first].2;
+        let decimals = on_axis.iter().map(|pen| series[*pen].2.decimals()).max().unwrap_or_default();
+        let symbol = if on_axis.iter().all(|pen| series[*pen].2 == unit) { unit.to_string() } else { String::new() };
+        let (low, high) = match range {
+            Some(range) => range,
+            None => {
+                let values = on_axis.iter().flat_map(|pen| columns[*pen].iter().flatten());
+                let (low, high) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| (low.min(*value), high.max(*value)));
+                if low > high {
+                    (0.0, 1.0)
+                } else {
+                    let margin = ((high - low) * 0.05).max(0.1f32.powi(decimals as i32));
+                    (low - margin, high + margin)
+                }
+            }
+        };
+        Some((low, high, symbol, decimals))
+    }).collect();
+
+    let mut grid = vec![vec![' '; TREND_WIDTH]; TREND_HEIGHT];
+    for ((_, pen, _), values) in series.iter().zip(&columns) {
+        let (low, high) = match &scales[Axis::ALL.iter().position(|axis| *axis == pen.axis).unwrap_or_default()] {
+            Some((low, high, _, _)) => (*low, *high),
+            None => continue,
+        };
+        for (column, value) in values.iter().enumerate() {
+            // Beyond a fixed range a pen is pinned to the edge rather than left out
+            if let Some(value) = value {
+                let row = ((high - value) / (high - low) * (TREND_HEIGHT - 1) as f32).round().clamp(0.0, (TREND_HEIGHT - 1) as f32) as usize;
+                grid[row][column] = pen.mark;
+            }
+        }
+    }
+    for (marker, mark) in markers {
+        let age = now.duration_since(*marker).unwrap_or_default().as_secs_f32();
+        let slot = (age / window.as_secs_f32() * TREND_WIDTH as f32) as usize;
+        if slot < TREND_WIDTH {
+            for cells in grid.iter_mut() {
+                let cell = &mut cells[TREND_WIDTH - 1 - slot];
+                if *cell == ' ' {
+                    *cell = '|';
+                }
+            }
+            grid[0][TREND_WIDTH - 1 - slot] = *mark;
+        }
+    }
+
+    let label = |scale: &Option<(f32, f32, String, usize)>, row: usize| match scale {
+        Some((low, high, symbol, decimals)) => format!("{:>8.*} {:<3}", *decimals, high - row as f32 * (high - low) / (TREND_HEIGHT - 1) as f32, symbol),
+        None => " ".repeat(12),
+    };
+    let mut lines: Vec<String> = grid.iter().enumerate()
+        .map(|(row, cells)| format!("{}|{}|{}", label(&scales[0], row), cells.iter().collect::<String>(), label(&scales[1], row).trim_end()))
+        .collect();
+    lines.push(format!("{:>13}+{}+", "", "-".repeat(TREND_WIDTH)));
+    let (start, end) = match markers {
+        [] => (format!("-{}", format_span(window)), tr("trend.now").to_string()),
+        _ => (format_time(now.checked_sub(window).unwrap_or(UNIX_EPOCH)), format_time(now)),
+    };
+    lines.push(format!("{:>13}{}{:>width$}", "", start, end, width = TREND_WIDTH - start.len()));
+    lines
+}
+
+// Plots each series stacked on the ones before it, from zero, so the top of a column is
+// their sum; each band is drawn with its series' mark. Every series is in kW.
+fn render_stacked(series: &[Vec<Sample>], window: Duration, now: SystemTime) -> Vec<String> {
+    let columns: Vec<Vec<f32>> = series.iter().map(|samples| {
+        let mut columns = vec![(0.0f32, 0u32); TREND_WIDTH];
+        for sample in samples {
+            let age = now.duration_since(sample.time).unwrap_or_default().as_secs_f32();
+            let slot = (age / window.as_secs_f32() * TREND_WIDTH as f32) as usize;
+            if slot < TREND_WIDTH && sample.value.is_finite() {
+                let column = &mut columns[TREND_WIDTH - 1 - slot];
+                column.0 += sample.value;
+                column.1 += 1;
+            }
+        }
+        columns.iter().map(|(sum, count)| if *count > 0 { sum / *count as f32 } else { 0.0 }).collect()
+    }).collect();
+    // Where each series' band ends in each column
+    let stacks: Vec<Vec<f32>> = (0..TREND_WIDTH).map(|column| {
+        columns.iter().scan(0.0, |top, values| {
+            *top += values[column];
+            Some(*top)
+        }).collect()
+    }).collect();
+    let high = stacks.iter().filter_map(|stack| stack.last()).fold(0.0f32, |high, top| high.max(*top));
+    let high = if high > 0.0 { high * 1.05 } else { 1.0 };
+
+    // Each row stands for the middle of its slice of 0..high
+    let level = |row: usize| high * (2 * (TREND_HEIGHT - row) - 1) as f32 / (2 * TREND_HEIGHT) as f32;
+    let mut lines: Vec<String> = (0..TREND_HEIGHT).map(|row| {
+        let cells: String = stacks.iter().map(|stack| match stack.iter().position(|top| *top >= level(row)) {
+            Some(series) => stack_mark(series),
+            None => ' ',
+        }).collect();
+        format!("{:>8.1} {:<3}|{}|", level(row), "kW", cells)
+    }).collect();
+    lines.push(format!("{:>12}+{}+", "", "-".repeat(TREND_WIDTH)));
+    let start = format!("-{}", format_span(window));
+    lines.push(format!("{:>13}{}{:>width$}", "", start, tr("trend.now"), width = TREND_WIDTH - start.len()));
+    lines
+}
+
+// Amplitude (mm/s RMS) against frequency as bars, from 0 to five times running speed, or
+// 50 Hz for a stopped motor, with running speed and twice it marked underneath
+fn render_spectrum(spectrum: &[f32], running_hz: f32) -> Vec<String> {
+    let top_hz = (running_hz * 5.0).clamp(50.0, vibration::SAMPLE_RATE as f32 / 2.0);
+    let bin_of = |hz: f32| (hz / vibration::BIN_HZ) as usize;
+    // Each column shows the highest bin it covers, so a narrow peak is never lost
+    let columns: Vec<f32> = (0..TREND_WIDTH).map(|column| {
+        let from = bin_of(top_hz * column as f32 / TREND_WIDTH as f32);
+        let to = bin_of(top_hz * (column + 1) as f32 / TREND_WIDTH as f32).max(from + 1);
+        spectrum.get(from..to.min(spectrum.len())).unwrap_or_default().iter().fold(0.0f32, |high, amplitude| high.max(*amplitude))
+    }).collect();
+    // Scaled to the highest peak, but never so far that the noise floor fills the chart
+    let high = columns.iter().fold(0.5f32, |high, amplitude| high.max(*amplitude)) * 1.05;
+
+    let level = |row: usize| high * (2 * (TREND_HEIGHT - row) - 1) as f32 / (2 * TREND_HEIGHT) as f32;
+    let mut lines: Vec<String> = (0..TREND_HEIGHT).map(|row| {
+        let cells: String = columns.iter().map(|amplitude| if *amplitude >= level(row) { '#' } else { ' ' }).collect();
+        format!("{:>8.2} {:<4}|{}|", level(row), "mm/s", cells)
+    }).collect();
+    lines.push(format!("{:>13}+{}+", "", "-".repeat(TREND_WIDTH)));
+    let mut markers = vec![' '; TREND_WIDTH];
+    for (order, mark) in [(1.0, '1'), (2.0, '2')] {
+        let column = (running_hz * order / top_hz * TREND_WIDTH as f32) as usize;
+        if running_hz > 0.0 && column < TREND_WIDTH {
+            markers[column] = mark;
+        }
+    }
+    lines.push(format!("{:>14}{}", "", markers.into_iter().collect::<String>()));
+    let top = format!("{:.0} Hz", top_hz);
+    lines.push(format!("{:>14}{}{:>width$}", "", "0 Hz", top, width = TREND_WIDTH - 4));
+    lines
+}
+
+// A stacked series' mark: its number from 1, then letters
+fn stack_mark(series: usize) -> char {
+    std::char::from_digit(series as u32 + 1, 36).unwrap_or('*')
+}
+
+const GAUGE_WIDTH: usize = 40;
+const GAUGE_MARKER: char = '█';
+
+// One reading as a horizontal bar: the alarm bands at either end, the warning bands inside
+// them and the normal range in the middle, with the reading marked. A reading off the scale
+// is shown as '<' or '>' at the end; one that can't be trusted crosses the gauge out.
+fn render_gauge(value: f32, limits: &Limits, unit: Unit) -> String {
+    if !value.is_finite() {
+        return format!("[{:x^width$}]", format!(" {} ", tr("reading.fault")), width = GAUGE_WIDTH);
+    }
+    // Scaled so the alarm bands get a quarter of the alarm span each, and the bands beyond
+    // any trip limits a tenth
+    let span = unit.from_si(limits.high_alarm) - unit.from_si(limits.low_alarm);
+    let low = limits.low_trip.map_or(f32::INFINITY, |trip| unit.from_si(trip) - span * 0.1).min(unit.from_si(limits.low_alarm) - span * 0.25);
+    let high = limits.high_trip.map_or(f32::NEG_INFINITY, |trip| unit.from_si(trip) + span * 0.1).max(unit.from_si(limits.high_alarm) + span * 0.25);
+    let step = (high - low) / GAUGE_WIDTH as f32;
+
+    let mut cells: Vec<char> = (0..GAUGE_WIDTH).map(|cell| {
+        let cell_value = unit.to_si(low + (cell as f32 + 0.5) * step);
+        if limits.trip_limit(cell_value).is_some() {
+            '#'
+        } else {
+            match limits.classify(cell_value) {
+                AlarmLevel::Normal => '-',
+                AlarmLevel::Warning => '~',
+                AlarmLevel::Alarm => '!',
+            }
+        }
+    }).collect();
+    let position = (unit.from_si(value) - low) / step;
+    if position < 0.0 {
+        cells[0] = '<';
+    } else if position >= GAUGE_WIDTH as f32 {
+        cells[GAUGE_WIDTH - 1] = '>';
+    } else {
+        cells[position as usize] = GAUGE_MARKER;
+    }
+    format!("[{}]", cells.into_iter().collect::<String>())
+}
+
+// A motor's speed on a 0 to MAX_MOTOR_SETPOINT scale, with its setpoint marked and the
+// range above rated speed red-lined
+fn render_speed_gauge(motor: &Motor) -> String {
+    let cell_of = |rpm: u16| (rpm as usize * GAUGE_WIDTH / (MAX_MOTOR_SETPOINT as usize + 1)).min(GAUGE_WIDTH - 1);
+    let mut cells: Vec<char> = (0..GAUGE_WIDTH).map(|cell| if cell > cell_of(RATED_MOTOR_SPEED) { '!' } else { '-' }).collect();
+    cells[cell_of(motor.setpoint)] = '|';
+    cells[cell_of(motor.speed)] = GAUGE_MARKER;
+    format!("[{}]", cells.into_iter().collect::<String>())
+}
+
+// A temperature difference in the display unit, signed; unlike a reading it has no offset
+fn format_error(unit: Unit, error: f32) -> String {
+    format!("{:+.*}{}", unit.decimals(), unit.from_si(error) - unit.from_si(0.0), unit.symbol())
+}
+
+// Round trip and loss in a few words, e.g. for the status line
+// Cycles by how far they were from the target, as bars scaled to the fullest bin
+fn print_jitter_histogram(scan: &ScanStats) {
+    let histogram = scan.histogram();
+    let fullest = histogram.iter().map(|(_, count)| *count).max().unwrap_or_default().max(1);
+    for (label, count) in histogram {
+        println!("  {:>14} |{:<40} {}", label, "#".repeat((count * 40 + fullest - 1) / fullest), count);
+    }
+}
+
+fn link_summary(link: &LinkStats) -> String {
+    let latency = link.last_latency().map_or("--".to_string(), |latency| format!("{} ms", latency.as_millis()));
+    tr_args("link.summary", &[&latency, &decimal(link.loss_percent(), 0)])
+}
+
+fn on_off(state: bool) -> &'static str {
+    if state { "ON" } else { "OFF" }
+}
+
+fn led(state: bool) -> &'static str {
+    if state { "●" } else { "○" }
+}
+
+// The last of `changes` (UTC times of day) at or before `now`; midnight UTC without any,
+// so the first shift summary covers the day so far
+fn previous_shift_change(changes: &[Duration], now: SystemTime) -> SystemTime {
+    let seconds = now.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let midnight = UNIX_EPOCH + Duration::from_secs(seconds / DAY_SECONDS * DAY_SECONDS);
+    changes.iter()
+        .map(|change| midnight + *change)
+        .map(|change| if change > now { change - Duration::from_secs(DAY_SECONDS) } else { change })
+        .max()
+        .unwrap_or(midnight)
+}
+
+// YYYYMMDD_HHMMSS in UTC, for file names
+
+fn file_stamp(time: SystemTime) -> String {
+    let datetime = format_datetime(time);
+    format!("{}_{}", datetime[..10].replace('-', ""), datetime[11..19].replace(':', ""))
+}
+
+// "15 min", "12 h" or "7 d", whichever unit the span is a whole number of
+fn format_span(window: Duration) -> String {
+    let minutes = window.as_secs() / 60;
+    match (minutes / (24 * 60), minutes / 60) {
+        (days, _) if days > 0 && days * 24 * 60 == minutes => format!("{} d", days),
+        (_, hours) if hours > 0 && hours * 60 == minutes => format!("{} h", hours),
+        _ => format!("{} min", minutes),
+    }
+}
+
+// A rate of change in SI per second, in the display unit, e.g. 3.2 kPa/s
+fn format_rate(unit: Unit, rate: f32) -> String {
+    format!("{}{}/s", decimal(unit.from_si(rate) - unit.from_si(0.0), unit.decimals()), unit.symbol())
+}
+
+// Parses 30m, 12h or 7d
+fn parse_span(text: &str) -> Option<Duration> {
+    let (count, unit) = [('m', 60), ('h', 60 * 60), ('d', 24 * 60 * 60)]
+        .iter()
+        .find_map(|(suffix, unit)| Some((text.strip_suffix(*suffix)?, *unit)))?;
+    let count: u64 = count.parse().ok().filter(|count| *count > 0)?;
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+// `timeline` arguments: an optional span, 24 hours by default, then optionally 'at HH:MM'
+// for a window centered on that UTC time today, or yesterday if it is still to come
+fn parse_timeline_window(words: &[&str]) -> Option<(Duration, Option<SystemTime>)> {
+    let (window, rest) = match words.split_first() {
+        Some((span, rest)) if *span != "at" => (parse_span(span)?, rest),
+        _ => (TIMELINE_WINDOW, words),
+    };
+    match rest {
+        [] => Some((window, None)),
+        ["at", clock] => Some((window, Some(recent_time_of_day(parse_time_of_day(clock)?)?))),
+        _ => None,
+    }
+}
+
+// The last time it was `time_of_day` UTC: today, or yesterday if that is still to come
+fn recent_time_of_day(time_of_day: Duration) -> Option<SystemTime> {
+    let now = SystemTime::now();
+    let today = now.duration_since(UNIX_EPOCH).ok()?.as_secs() / DAY_SECONDS * DAY_SECONDS;
+    let mut at = UNIX_EPOCH + Duration::from_secs(today) + time_of_day;
+    if at > now {
+        at -= Duration::from_secs(DAY_SECONDS);
+    }
+    Some(at)
+}
+
+// A cursor position: HH:MM or HH:MM:SS UTC, within the last day
+fn parse_cursor(text: &str) -> Option<SystemTime> {
+    let time_of_day = match (text.get(..5), text.get(5..)) {
+        (Some(clock), Some(seconds)) if !seconds.is_empty() => {
+            let seconds = seconds.strip_prefix(':').filter(|seconds| seconds.len() == 2)?;
+            let seconds: u64 = seconds.parse().ok().filter(|seconds| *seconds < 60)?;
+            parse_time_of_day(clock)? + Duration::from_secs(seconds)
+        }
+        _ => parse_time_of_day(text)?,
+    };
+    recent_time_of_day(time_of_day)
+}
+
+// Cursors as chart markers, labelled A and B
+fn cursor_markers(cursors: &[(SystemTime, Option<Sample>)]) -> Vec<(SystemTime, char)> {
+    cursors.iter().zip(CURSOR_NAMES).filter_map(|((_, sample), name)| sample.map(|sample| (sample.time, name))).collect()
+}
+
+// The legend under a chart: each cursor's sample time and value, and with two the
+// difference between them
+fn print_cursor_readout(cursors: &[(SystemTime, Option<Sample>)], unit: Unit) {
+    for ((placed, sample), name) in cursors.iter().zip(CURSOR_NAMES) {
+        match sample {
+            Some(sample) => println!("{}", tr_args("trend.cursor_reading", &[&name, &format_datetime(sample.time), &unit.format(sample.value)])),
+            None => println!("{}", tr_args("trend.cursor_off_chart", &[&name, &format_datetime(*placed)])),
+        }
+    }
+    if let [(_, Some(a)), (_, Some(b))] = cursors {
+        let (sign, span) = match b.time.duration_since(a.time) {
+            Ok(span) => ("", span),
+            Err(e) => ("-", e.duration()),
+        };
+        let seconds = span.as_secs();
+        println!("  B - A     Δt {}{:02}:{:02}:{:02}  Δ {}", sign, seconds / 3600, seconds / 60 % 60, seconds % 60, format_offset(unit, b.value - a.value));
+    }
+}
+
+// A point in a replay: MM:SS from its start, or a percentage of its length
+fn parse_replay_position(text: &str, duration: Duration) -> Option<Duration> {
+    if let Some(percent) = text.strip_suffix('%') {
+        let percent: f64 = percent.parse().ok().filter(|percent| (0.0..=100.0).contains(percent))?;
+        return Some(duration.mul_f64(percent / 100.0));
+    }
+    let (minutes, seconds) = text.split_once(':')?;
+    let (minutes, seconds): (u64, u64) = (minutes.parse().ok()?, seconds.parse().ok()?);
+    if seconds > 59 {
+        return None;
+    }
+    Some(Duration::from_secs(minutes * 60 + seconds))
+}
+
+// Reads operator commands on a separate thread so the main loop can keep
+// servicing background work between keystrokes.
+fn spawn_input_reader() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+// Shown instead of readings, and for refused commands, once the sensor data lock is poisoned
+fn print_sensor_data_lost() {
+    print_colored(StatusColor::Red, tr("sensor_lost.title"));
+    print_colored(StatusColor::Red, tr("sensor_lost.reason"));
+    print_colored(StatusColor::Red, tr("sensor_lost.hint"));
+}
+
+fn print_language() {
+    let language = i18n::language();
+    let available: Vec<String> = Language::ALL.iter().map(|language| format!("{} ({})", language.code(), language)).collect();
+    println!("{}", tr_args("language.current", &[&language, &available.join(", ")]));
+    let missing = language.missing_keys();
+    if !missing.is_empty() {
+        print_colored(StatusColor::Yellow, &tr_args("language.incomplete", &[&missing.len(), &language]));
+    }
+}
+
+// The status colors of the current theme, as they appear throughout the console
+// With a capital letter, for a message that is usually part of a longer one
+fn sentence(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn print_deviation(deviation: Deviation, line: &str) {
+    match deviation {
+        Deviation::Same | Deviation::Within => println!("{}", line),
+        Deviation::Beyond => print_colored(StatusColor::Yellow, line),
+        Deviation::FarBeyond => print_colored(StatusColor::Red, line),
+    }
+}
+
+fn print_toast(toast: &toasts::Toast) {
+    let dismiss = if toast.expires_at.is_none() { format!(" {}", tr_args("status.dismiss", &[&toast.id])) } else { String::new() };
+    print_colored(toast.severity.color(), &format!("  [{}] {}{}", toast.id, toast.message, dismiss));
+}
+
+fn print_theme() {
+    let names: Vec<&str> = Theme::ALL.iter().map(|theme| theme.name()).collect();
+    println!("{}", tr_args("theme.current", &[&platform::theme(), &names.join(", ")]));
+    print_colored(StatusColor::Green, tr("theme.ok"));
+    print_colored(StatusColor::Yellow, tr("theme.warning"));
+    print_colored(StatusColor::Red, tr("theme.alarm"));
+    print_colored(StatusColor::Gray, tr("theme.stale"));
+    print_colored(StatusColor::Blue, tr("theme.forced"));
+}
+
+fn print_serial_ports() {
+    let ports = serial::available_ports();
+    if ports.is_empty() {
+        println!("{}", tr("serial.none"));
+        return;
+    }
+    println!("{}", tr("serial.title"));
+    for port in ports {
+        println!("  {}", port);
+    }
+}
+
+// Catalog keys of the help lines and the role each command needs; continuation lines need none
+const HELP: &[(Option<Role>, &str)] = &[
+    (None, "help.status"),
+    (None, "help.link"),
+    (None, "help.perf"),
+    (None, "help.devices"),
+    (None, "help.device"),
+    (Some(Role::Operator), "help.diag"),
+    (None, "help.diag_history"),
+    (None, "help.log"),
+    (None, "help.log_save"),
+    (None, "help.log_level"),
+    (None, "help.audit"),
+    (Some(Role::Operator), "help.start"),
+    (Some(Role::Operator), "help.stop"),
+    (None, "help.motor"),
+    (Some(Role::Operator), "help.reset_motor"),
+    (Some(Role::Operator), "help.maintenance"),
+    (None, "help.energy"),
+    (Some(Role::Supervisor), "help.energy_reset"),
+    (None, "help.vibration"),
+    (Some(Role::Supervisor), "help.vibration_limit"),
+    (Some(Role::Supervisor), "help.setpoint"),
+    (None, "help.recipes"),
+    (None, "help.recipe_show"),
+    (Some(Role::Supervisor), "help.recipe_save"),
+    (Some(Role::Supervisor), "help.recipe_load"),
+    (Some(Role::Supervisor), "help.recipe_delete"),
+    (None, "help.loop"),
+    (Some(Role::Supervisor), "help.loop_mode"),
+    (Some(Role::Supervisor), "help.loop_tune"),
+    (None, "help.io"),
+    (Some(Role::Supervisor), "help.output"),
+    (None, "help.limits"),
+    (None, "help.channels"),
+    (Some(Role::Supervisor), "help.channel_tag"),
+    (None, "help.find"),
+    (None, "help.channel"),
+    (None, "help.stats"),
+    (None, "help.stats_channel"),
+    (Some(Role::Operator), "help.stats_reset"),
+    (None, "help.shift"),
+    (Some(Role::Supervisor), "help.shift_close"),
+    (None, "help.notes"),
+    (Some(Role::Operator), "help.note"),
+    (Some(Role::Operator), "help.note_correct"),
+    (None, "help.calibration"),
+    (Some(Role::Supervisor), "help.calibrate"),
+    (None, "help.forces"),
+    (None, "help.bypass"),
+    (Some(Role::Supervisor), "help.bypass_set"),
+    (Some(Role::Supervisor), "help.bypass_clear"),
+    (Some(Role::Supervisor), "help.force"),
+    (Some(Role::Supervisor), "help.force_clear"),
+    (Some(Role::Supervisor), "help.limit"),
+    (None, "help.limit_detail"),
+    (None, "help.alarms"),
+    (Some(Role::Operator), "help.ack"),
+    (None, "help.silence"),
+    (None, "help.notify"),
+    (Some(Role::Operator), "help.notify_test"),
+    (None, "help.alarm_log"),
+    (None, "help.trend"),
+    (None, "help.trend_detail"),
+    (None, "help.history"),
+    (None, "help.history_detail"),
+    (None, "help.trend_all"),
+    (None, "help.pens"),
+    (None, "help.pen"),
+    (None, "help.pen_detail"),
+    (None, "help.pen_axis"),
+    (None, "help.cursor"),
+    (None, "help.cursor_detail"),
+    (None, "help.cursor_clear"),
+    (None, "help.timeline"),
+    (None, "help.timeline_detail"),
+    (None, "help.timeline_event"),
+    (None, "help.export"),
+    (None, "help.report"),
+    (None, "help.trips"),
+    (None, "help.trip"),
+    (None, "help.compare"),
+    (None, "help.compare_export"),
+    (None, "help.save"),
+    (Some(Role::Supervisor), "help.load"),
+    (None, "help.faults"),
+    (Some(Role::Supervisor), "help.fault"),
+    (Some(Role::Supervisor), "help.fault_motor"),
+    (None, "help.fault_detail"),
+    (Some(Role::Supervisor), "help.fault_clear"),
+    (Some(Role::Supervisor), "help.fault_digital"),
+    (Some(Role::Supervisor), "help.fault_clear_digital"),
+    (Some(Role::Supervisor), "help.interval"),
+    (None, "help.ports"),
+    (None, "help.units"),
+    (None, "help.theme"),
+    (None, "help.language"),
+    (None, "help.view"),
+    (None, "help.settings"),
+    (Some(Role::Supervisor), "help.config_export"),
+    (Some(Role::Supervisor), "help.config_import"),
+    (Some(Role::Supervisor), "help.settings_reset"),
+    (Some(Role::Supervisor), "help.scenario_load"),
+    (Some(Role::Supervisor), "help.scenario"),
+    (None, "help.source"),
+    (Some(Role::Supervisor), "help.source_switch"),
+    (Some(Role::Supervisor), "help.replay_load"),
+    (Some(Role::Supervisor), "help.replay"),
+    (Some(Role::Supervisor), "help.replay_speed"),
+    (Some(Role::Supervisor), "help.replay_seek"),
+    (Some(Role::Supervisor), "help.pause"),
+    (Some(Role::Supervisor), "help.resume"),
+    (None, "help.ambient"),
+    (Some(Role::Supervisor), "help.ambient_hold"),
+    (None, "help.estop"),
+    (None, "help.estop_now"),
+    (None, "help.estop_all"),
+    (Some(Role::Supervisor), "help.reset"),
+    (None, "help.mode"),
+    (Some(Role::Supervisor), "help.mode_change"),
+    (Some(Role::Supervisor), "help.restart"),
+    (Some(Role::Operator), "help.restart_acquisition"),
+    (None, "help.login"),
+    (None, "help.logout"),
+    (None, "help.help"),
+    (None, "help.keys"),
+    (None, "help.undo"),
+    (None, "help.undo_list"),
+    (None, "help.notices"),
+    (None, "help.dismiss"),
+    (None, "help.quit"),
+];
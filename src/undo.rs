@@ -0,0 +1,103 @@
+// Undo for configuration changes made at the console: setpoints, alarm limits, channel
+// tagging and trend pens. Each change keeps the value it replaced, exactly as it was
+// stored, so undoing it puts that value back rather than an approximation of it. It also
+// keeps the value it set, and undo is refused once that no longer holds, so whatever
+// changed it since (the REST API, a recipe) is never overwritten. Setpoints and limits go
+// back as commands, through the same checks as any other. Only the last changes of the
+// session are kept, and never starts, stops or emergency shutdowns: undoing those is a
+// control action of its own, not a correction.
+use crate::alarms::Limits;
+use crate::auth::Role;
+use crate::commands::Command;
+use crate::pens::{Axis, Pen};
+use crate::sensors::{ChannelKind, Tagging};
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+// Changes kept; the oldest is dropped to make room
+pub const DEPTH: usize = 50;
+
+// The value a change replaced. Devices are indexes into the console's list; motors and
+// channels are numbered from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Previous {
+    Setpoint { device: usize, motor: usize, rpm: u16 },
+    // All of the channel's limits, in SI units
+    Limits { device: usize, kind: ChannelKind, channel: usize, limits: Limits },
+    // None for a motor
+    Tagging { device: usize, kind: Option<ChannelKind>, number: usize, tagging: Tagging },
+    // Keyed like the pens in settings.json
+    Pen { key: String, label: String, pen: Pen },
+    PenRange { axis: Axis, range: Option<(f32, f32)> },
+}
+
+impl Previous {
+    // The role the change needed, and so undoing it needs
+    pub fn required_role(&self) -> Option<Role> {
+        match self {
+            Previous::Setpoint { .. } | Previous::Limits { .. } | Previous::Tagging { .. } => Some(Role::Supervisor),
+            Previous::Pen { .. } | Previous::PenRange { .. } => None,
+        }
+    }
+
+    // The command that puts a setpoint or limits back; None for what isn't a control command
+    pub fn command(&self) -> Option<Command> {
+        match self {
+            Previous::Setpoint { device, motor, rpm } => Some(Command::SetSetpoint { device: *device, motor: *motor, rpm: *rpm }),
+            Previous::Limits { device, kind, channel, limits } => Some(Command::RestoreLimits { device: *device, kind: *kind, channel: *channel, limits: *limits }),
+            Previous::Tagging { .. } | Previous::Pen { .. } | Previous::PenRange { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub time: SystemTime,
+    pub user: String,
+    // As it was logged and audited
+    pub detail: String,
+    pub previous: Previous,
+    // What the change set, in the same form
+    pub applied: Previous,
+}
+
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    changes: VecDeque<Change>,
+}
+
+impl UndoStack {
+    pub fn push(&mut self, change: Change) {
+        if self.changes.len() == DEPTH {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+
+    // The change 'undo' would revert
+    pub fn last(&self) -> Option<&Change> {
+        self.changes.back()
+    }
+
+    pub fn pop(&mut self) -> Option<Change> {
+        self.changes.pop_back()
+    }
+
+    // Newest first
+    pub fn iter(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+}
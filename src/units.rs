@@ -0,0 +1,229 @@
+// Engineering units for what the operator sees and types. Readings, limits, snapshots,
+// telemetry and the historian stay in SI (°C and kPa); values are converted on the
+// way to the screen or an export and back when a limit is entered.
+use crate::alarms::Limits;
+use crate::i18n::{decimal, tr};
+use crate::sensors::ChannelKind;
+
+use std::fmt;
+
+const KPA_PER_PSI: f32 = 6.894_757;
+const KPA_PER_BAR: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kilopascal,
+    Psi,
+    Bar,
+}
+
+impl Unit {
+    // As typed in commands and forlenza.toml, in any case
+    pub fn parse(name: &str) -> Result<Unit, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "c" => Ok(Unit::Celsius),
+            "f" => Ok(Unit::Fahrenheit),
+            "kpa" => Ok(Unit::Kilopascal),
+            "psi" => Ok(Unit::Psi),
+            "bar" => Ok(Unit::Bar),
+            _ => Err(format!("unknown unit '{}' (use C or F for temperature, kPa, psi or bar for pressure)", name)),
+        }
+    }
+
+    // The unit readings of a kind are stored in
+    pub fn si(kind: ChannelKind) -> Unit {
+        match kind {
+            ChannelKind::Temperature => Unit::Celsius,
+            ChannelKind::Pressure => Unit::Kilopascal,
+        }
+    }
+
+    pub fn kind(self) -> ChannelKind {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit => ChannelKind::Temperature,
+            Unit::Kilopascal | Unit::Psi | Unit::Bar => ChannelKind::Pressure,
+        }
+    }
+
+    // Appended straight to a value, hence the space before the pressure units
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kilopascal => " kPa",
+            Unit::Psi => " psi",
+            Unit::Bar => " bar",
+        }
+    }
+
+    // Last part of an export column name, e.g. temperature_1_f
+    pub fn column_suffix(self) -> &'static str {
+        match self {
+            Unit::Celsius => "c",
+            Unit::Fahrenheit => "f",
+            Unit::Kilopascal => "kpa",
+            Unit::Psi => "psi",
+            Unit::Bar => "bar",
+        }
+    }
+
+    // Enough places to tell the warning band from the alarm band: a bar is 100 kPa
+    pub fn decimals(self) -> usize {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit | Unit::Kilopascal => 1,
+            Unit::Psi => 2,
+            Unit::Bar => 3,
+        }
+    }
+
+    pub fn from_si(self, value: f32) -> f32 {
+        match self {
+            Unit::Celsius | Unit::Kilopascal => value,
+            Unit::Fahrenheit => value * 1.8 + 32.0,
+            Unit::Psi => value / KPA_PER_PSI,
+            Unit::Bar => value / KPA_PER_BAR,
+        }
+    }
+
+    pub fn to_si(self, value: f32) -> f32 {
+        match self {
+            Unit::Celsius | Unit::Kilopascal => value,
+            Unit::Fahrenheit => (value - 32.0) / 1.8,
+            Unit::Psi => value * KPA_PER_PSI,
+            Unit::Bar => value * KPA_PER_BAR,
+        }
+    }
+
+    // Every conversion is increasing, so the converted limits keep their order
+    pub fn limits_from_si(self, limits: &Limits) -> Limits {
+        Limits::new(
+            self.from_si(limits.low_alarm),
+            self.from_si(limits.low_warning),
+            self.from_si(limits.high_warning),
+            self.from_si(limits.high_alarm),
+        ).with_trips(limits.low_trip.map(|trip| self.from_si(trip)), limits.high_trip.map(|trip| self.from_si(trip)))
+    }
+
+    // An SI value converted and rounded for display, without the symbol
+    pub fn number(self, value: f32) -> String {
+        decimal(self.from_si(value), self.decimals())
+    }
+
+    // An SI reading as shown to the operator; readings that can't be trusted (open circuit) show as FAULT
+    pub fn format(self, value: f32) -> String {
+        if value.is_finite() {
+            format!("{}{}", self.number(value), self.symbol())
+        } else {
+            tr("reading.fault").to_string()
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.symbol().trim())
+    }
+}
+
+// The display unit chosen for each kind of channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Units {
+    pub temperature: Unit,
+    pub pressure: Unit,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Units { temperature: Unit::Celsius, pressure: Unit::Kilopascal }
+    }
+}
+
+impl Units {
+    pub fn get(self, kind: ChannelKind) -> Unit {
+        match kind {
+            ChannelKind::Temperature => self.temperature,
+            ChannelKind::Pressure => self.pressure,
+        }
+    }
+
+    // Replaces the unit for whichever kind of channel `unit` measures
+    pub fn set(&mut self, unit: Unit) {
+        match unit.kind() {
+            ChannelKind::Temperature => self.temperature = unit,
+            ChannelKind::Pressure => self.pressure = unit,
+        }
+    }
+
+    pub fn format(self, kind: ChannelKind, value: f32) -> String {
+        self.get(kind).format(value)
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "temperature in {}, pressure in {}", self.temperature, self.pressure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Unit; 5] = [Unit::Celsius, Unit::Fahrenheit, Unit::Kilopascal, Unit::Psi, Unit::Bar];
+
+    #[test]
+    fn conversions_round_trip_across_the_clamps() {
+        for unit in ALL {
+            let (low, high) = match unit.kind() {
+                ChannelKind::Temperature => (20.0, 30.0),
+                ChannelKind::Pressure => (95.0, 105.0),
+            };
+            for step in 0..=100 {
+                let value = low + (high - low) * step as f32 / 100.0;
+                let back = unit.to_si(unit.from_si(value));
+                assert!((back - value).abs() < 1e-4, "{} {} came back as {}", unit, value, back);
+            }
+        }
+    }
+
+    #[test]
+    fn known_values_convert() {
+        assert_eq!(Unit::Fahrenheit.from_si(20.0), 68.0);
+        assert_eq!(Unit::Fahrenheit.from_si(30.0), 86.0);
+        assert!((Unit::Psi.from_si(101.325) - 14.696).abs() < 0.001);
+        assert!((Unit::Bar.from_si(105.0) - 1.05).abs() < 1e-6);
+        assert_eq!(Unit::Fahrenheit.to_si(77.0), 25.0);
+    }
+
+    #[test]
+    fn readings_are_shown_with_enough_places() {
+        let units = Units { temperature: Unit::Fahrenheit, pressure: Unit::Bar };
+        assert_eq!(units.format(ChannelKind::Temperature, 25.0), "77.0°F");
+        // 95 and 96 kPa stay apart in bar
+        assert_eq!(units.format(ChannelKind::Pressure, 95.0), "0.950 bar");
+        assert_eq!(units.format(ChannelKind::Pressure, 96.0), "0.960 bar");
+        assert_eq!(Unit::Psi.format(f32::NAN), "FAULT");
+    }
+
+    #[test]
+    fn limits_keep_their_order() {
+        let limits = Limits::new(96.0, 98.0, 103.0, 104.0).with_trips(Some(95.0), Some(105.0));
+        for unit in [Unit::Kilopascal, Unit::Psi, Unit::Bar] {
+            let converted = unit.limits_from_si(&limits);
+            let order = [converted.low_trip.unwrap(), converted.low_alarm, converted.low_warning, converted.high_warning, converted.high_alarm, converted.high_trip.unwrap()];
+            assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{}: {:?}", unit, order);
+        }
+    }
+
+    #[test]
+    fn units_parse_in_any_case() {
+        assert_eq!(Unit::parse("KPA"), Ok(Unit::Kilopascal));
+        assert_eq!(Unit::parse("f"), Ok(Unit::Fahrenheit));
+        assert!(Unit::parse("kelvin").is_err());
+        let mut units = Units::default();
+        units.set(Unit::Psi);
+        assert_eq!(units, Units { temperature: Unit::Celsius, pressure: Unit::Psi });
+    }
+}
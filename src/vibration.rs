@@ -0,0 +1,174 @@
+// Vibration of the motors, for monitoring rotating equipment. Each motor has a velocity
+// channel (mm/s) sampled at SAMPLE_RATE, far faster than the other channels: sinusoids
+// at once and twice running speed, from residual unbalance and misalignment, plus noise.
+// A worn bearing adds a tone at its outer-race defect frequency, and everything grows as
+// the wear progresses. The sensor thread keeps the last BUFFER_SECONDS of samples and,
+// after each poll, works out the overall RMS and the spectrum of the latest FRAME
+// samples, so the console only has to draw them.
+use crate::fft;
+use crate::history::RingBuffer;
+use crate::rand::Rng;
+use crate::sensors::RATED_MOTOR_SPEED;
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+// Samples a second
+pub const SAMPLE_RATE: usize = 1024;
+// Samples in each spectrum: a second, so the bins are 1 Hz apart
+pub const FRAME: usize = 1024;
+const BUFFER_SECONDS: usize = 4;
+pub const BIN_HZ: f32 = SAMPLE_RATE as f32 / FRAME as f32;
+
+// Peak amplitudes at rated speed (mm/s), in proportion to speed below and above it
+const ONCE_PER_REV: f32 = 1.8;
+const TWICE_PER_REV: f32 = 0.7;
+// Of the noise, at any speed, and its share of a running motor's
+const NOISE: f32 = 0.1;
+const RUNNING_NOISE: f32 = 0.6;
+// Outer-race defect frequency as a multiple of running speed, typical of a deep-groove
+// ball bearing, and its peak amplitude once the bearing is fully worn
+const BEARING_ORDER: f64 = 3.58;
+const BEARING_TONE: f32 = 6.0;
+// A bearing goes from new to fully worn in this long while the fault is on
+const WEAR_TIME: Duration = Duration::from_secs(120);
+// An alarm clears once the RMS is this much below the limit, so it doesn't chatter
+const DEADBAND: f32 = 0.1;
+// ISO 10816-3, group 2 machines on rigid foundations: the zone C/D boundary
+pub const DEFAULT_LIMIT: f32 = 4.5;
+
+// The simulated plant's accelerometers, integrated to velocity. Kept by the data source.
+pub struct VibrationModel {
+    rng: Rng,
+    shafts: Vec<Shaft>,
+    // Samples owed to the next poll, so odd intervals keep the rate exact
+    owed: f64,
+}
+
+impl VibrationModel {
+    pub fn new(seed: u64) -> Self {
+        VibrationModel { rng: Rng::new(seed), shafts: Vec::new(), owed: 0.0 }
+    }
+
+    // `motors` is each motor's speed (RPM) and whether its bearing is wearing; returns
+    // each motor's new samples
+    pub fn generate(&mut self, motors: &[(f32, bool)], dt: Duration) -> Vec<Vec<f32>> {
+        self.shafts.resize(motors.len(), Shaft::default());
+        self.owed += dt.as_secs_f64() * SAMPLE_RATE as f64;
+        let count = self.owed as usize;
+        self.owed -= count as f64;
+
+        let rng = &mut self.rng;
+        self.shafts.iter_mut().zip(motors).map(|(shaft, (speed, wearing))| {
+            // Wear builds while the fault is on; a new bearing goes in when it is cleared
+            shaft.wear = if *wearing { (shaft.wear + dt.as_secs_f32() / WEAR_TIME.as_secs_f32()).min(1.0) } else { 0.0 };
+            let wear = shaft.wear;
+            let revolutions = *speed as f64 / 60.0 / SAMPLE_RATE as f64;
+            let scale = *speed / RATED_MOTOR_SPEED as f32;
+            let noise = NOISE + RUNNING_NOISE * scale.min(1.0) * (1.0 + 3.0 * wear);
+            (0..count).map(|_| {
+                shaft.angle = (shaft.angle + revolutions).fract();
+                shaft.defect = (shaft.defect + revolutions * BEARING_ORDER).fract();
+                let (angle, defect) = (shaft.angle * TAU, shaft.defect * TAU);
+                let harmonics = scale * (1.0 + wear) * (ONCE_PER_REV * angle.sin() as f32 + TWICE_PER_REV * (2.0 * angle + 0.7).sin() as f32);
+                let bearing = scale.min(1.0) * wear * BEARING_TONE * defect.sin() as f32;
+                // Near enough to normal for a noise floor
+                let random = rng.random_f32() + rng.random_f32() - 1.0;
+                harmonics + bearing + noise * random
+            }).collect()
+        }).collect()
+    }
+}
+
+// Phases are in revolutions, kept apart so each stays a pure tone as it wraps
+#[derive(Clone, Copy, Debug, Default)]
+struct Shaft {
+    angle: f64,
+    // Of the outer-race defect frequency
+    defect: f64,
+    // From 0 (new) to 1 (fully worn)
+    wear: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    // Overall velocity, mm/s RMS, of the latest frame
+    pub rms: f32,
+    // Amplitude of each BIN_HZ bin up to half the sample rate, mm/s RMS
+    pub spectrum: Vec<f32>,
+}
+
+impl Analysis {
+    pub fn of(frame: &[f32]) -> Analysis {
+        let rms = (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len().max(1) as f32).sqrt();
+        let spectrum = fft::amplitude_spectrum(frame).into_iter().map(|peak| peak / 2f32.sqrt()).collect();
+        Analysis { rms, spectrum }
+    }
+}
+
+#[derive(Clone)]
+struct MotorVibration {
+    samples: RingBuffer<f32>,
+    analysis: Option<Analysis>,
+    alarm: bool,
+}
+
+// Shared between the sensor thread, which adds the samples and analyses them, and the
+// console, which shows the analyses and raises alarms from them
+#[derive(Clone)]
+pub struct Vibration {
+    motors: Vec<MotorVibration>,
+    // mm/s RMS
+    limit: f32,
+}
+
+impl Vibration {
+    pub fn new(motors: usize, limit: f32) -> Self {
+        let motors = (0..motors).map(|_| MotorVibration { samples: RingBuffer::new(BUFFER_SECONDS * SAMPLE_RATE), analysis: None, alarm: false }).collect();
+        Vibration { motors, limit }
+    }
+
+    pub fn limit(&self) -> f32 {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: f32) -> Result<(), String> {
+        if !(0.1..=100.0).contains(&limit) {
+            return Err("the vibration limit must be between 0.1 and 100 mm/s".to_string());
+        }
+        self.limit = limit;
+        Ok(())
+    }
+
+    // Adds each motor's new samples and returns its latest frame, once it has a full one
+    pub fn record(&mut self, samples: Vec<Vec<f32>>) -> Vec<Option<Vec<f32>>> {
+        self.motors.iter_mut().zip(samples).map(|(motor, samples)| {
+            for sample in samples {
+                motor.samples.push(sample);
+            }
+            let mut frame: Vec<f32> = motor.samples.iter().rev().take(FRAME).copied().collect();
+            frame.reverse();
+            (frame.len() == FRAME).then_some(frame)
+        }).collect()
+    }
+
+    // Takes the analyses of the frames `record` returned, worked out without the lock held
+    pub fn analysed(&mut self, analyses: Vec<Option<Analysis>>) {
+        let limit = self.limit;
+        for (motor, analysis) in self.motors.iter_mut().zip(analyses) {
+            if let Some(analysis) = analysis {
+                motor.alarm = if motor.alarm { analysis.rms >= limit * (1.0 - DEADBAND) } else { analysis.rms > limit };
+                motor.analysis = Some(analysis);
+            }
+        }
+    }
+
+    // None for a motor not yet analysed, or on a source without vibration channels
+    pub fn analysis(&self, motor: usize) -> Option<&Analysis> {
+        self.motors.get(motor)?.analysis.as_ref()
+    }
+
+    pub fn in_alarm(&self, motor: usize) -> bool {
+        self.motors.get(motor).is_some_and(|motor| motor.alarm)
+    }
+}
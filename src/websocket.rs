@@ -0,0 +1,165 @@
+// WebSocket (RFC 6455) push of live telemetry to dashboards. Each client has a
+// small queue of its own; a client that can't keep up misses updates instead of
+// slowing down the others or the simulation.
+use crate::sha1::sha1;
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Updates a client may fall behind by before it starts missing them
+const CLIENT_QUEUE: usize = 4;
+// Clients only ever send close, ping and pong, all of them small
+const MAX_CLIENT_FRAME: usize = 1024;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Default)]
+pub struct Broadcast {
+    clients: Mutex<Vec<SyncSender<Arc<[u8]>>>>,
+    connected: AtomicUsize,
+}
+
+impl Broadcast {
+    pub fn connected(&self) -> usize {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    // Frames the message once and queues it for every client
+    pub fn send(&self, text: &str) {
+        let frame: Arc<[u8]> = encode_frame(OPCODE_TEXT, text.as_bytes()).into();
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| !matches!(client.try_send(Arc::clone(&frame)), Err(TrySendError::Disconnected(_))));
+        }
+    }
+
+    fn subscribe(&self) -> Receiver<Arc<[u8]>> {
+        let (sender, receiver) = mpsc::sync_channel(CLIENT_QUEUE);
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.push(sender);
+        }
+        receiver
+    }
+}
+
+// Value for the Sec-WebSocket-Accept header
+pub fn accept_key(key: &str) -> String {
+    let digest = sha1(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key).as_bytes());
+    base64(&digest)
+}
+
+// Runs one client after the handshake until it closes, goes away, or the server stops
+pub fn serve(mut stream: TcpStream, broadcast: &Broadcast, stop: &AtomicBool) -> io::Result<()> {
+    let frames = broadcast.subscribe();
+    broadcast.connected.fetch_add(1, Ordering::Relaxed);
+    let result = run_client(&mut stream, &frames, stop);
+    broadcast.connected.fetch_sub(1, Ordering::Relaxed);
+    result
+}
+
+fn run_client(stream: &mut TcpStream, frames: &Receiver<Arc<[u8]>>, stop: &AtomicBool) -> io::Result<()> {
+    let mut incoming = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        match frames.recv_timeout(Duration::from_millis(200)) {
+            Ok(frame) => stream.write_all(&frame)?,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Whatever the client sent in the meantime, without waiting for more
+        stream.set_nonblocking(true)?;
+        let mut buf = [0u8; 512];
+        let read = stream.read(&mut buf);
+        stream.set_nonblocking(false)?;
+        match read {
+            Ok(0) => return Ok(()),
+            Ok(n) => incoming.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+
+        while let Some((opcode, payload, used)) = decode_client_frame(&incoming)? {
+            incoming.drain(..used);
+            match opcode {
+                OPCODE_CLOSE => {
+                    // Echo the close to complete the closing handshake
+                    stream.write_all(&encode_frame(OPCODE_CLOSE, &payload))?;
+                    return Ok(());
+                }
+                OPCODE_PING => stream.write_all(&encode_frame(OPCODE_PONG, &payload))?,
+                _ => {}
+            }
+        }
+    }
+
+    // 1001: going away
+    stream.write_all(&encode_frame(OPCODE_CLOSE, &1001u16.to_be_bytes()))
+}
+
+// Server frames are never masked or fragmented
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// Returns the opcode, unmasked payload and bytes consumed once a whole frame has arrived
+fn decode_client_frame(bytes: &[u8]) -> io::Result<Option<(u8, Vec<u8>, usize)>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let (opcode, second) = match bytes {
+        [first, second, ..] => (first & 0x0F, *second),
+        _ => return Ok(None),
+    };
+    if second & 0x80 == 0 {
+        return Err(invalid("client frames must be masked"));
+    }
+    let (length, header) = match second & 0x7F {
+        126 if bytes.len() >= 4 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+        127 => return Err(invalid("client frame too large")),
+        126 => return Ok(None),
+        length => (length as usize, 2),
+    };
+    if length > MAX_CLIENT_FRAME {
+        return Err(invalid("client frame too large"));
+    }
+    let total = header + 4 + length;
+    if bytes.len() < total {
+        return Ok(None);
+    }
+    let mask = &bytes[header..header + 4];
+    let payload = bytes[header + 4..total].iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]).collect();
+    Ok(Some((opcode, payload, total)))
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| group | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,85 @@
+// Runs the console headless for a few seconds, as a service manager would, and checks
+// that it recorded samples and stopped cleanly on SIGTERM.
+#![cfg(unix)]
+
+use forlenza_industrial::historian;
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn headless_runs_records_and_stops_on_sigterm() {
+    let directory = std::env::temp_dir().join(format!("forlenza-headless-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    // Everything the console writes goes under the one directory
+    let config = directory.join("forlenza.toml");
+    fs::write(&config, format!("\
+[historian]
+enabled = true
+directory = \"{dir}/history\"
+
+[audit]
+file = \"{dir}/audit.jsonl\"
+
+[logging]
+enabled = true
+directory = \"{dir}/logs\"
+
+[maintenance]
+file = \"{dir}/maintenance.json\"
+
+[energy]
+file = \"{dir}/energy.json\"
+
+[recipes]
+file = \"{dir}/recipes.json\"
+
+[calibration]
+file = \"{dir}/calibration.json\"
+
+[reports]
+directory = \"{dir}/reports\"
+
+[trips]
+directory = \"{dir}/trips\"
+
+[settings]
+file = \"{dir}/settings.json\"
+", dir = directory.display())).unwrap();
+
+    let started = SystemTime::now();
+    let child = Command::new(env!("CARGO_BIN_EXE_forlenza_industrial"))
+        .args(["--headless", "--force-compatible", "--seed", "7", "--config"])
+        .arg(&config)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(4));
+    let status = Command::new("kill").args(["-TERM", &child.id().to_string()]).status().unwrap();
+    assert!(status.success());
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}{}", output.status, stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Running headless"), "{}", stdout);
+    assert!(stdout.contains("Stopped cleanly"), "{}", stdout);
+    assert!(!stdout.contains("\x1b["), "headless output is plain");
+
+    let mut samples = 0;
+    let mut columns = 0;
+    historian::scan_samples(&directory.join("history"), started, SystemTime::now(), |header, _, fields| {
+        samples += 1;
+        columns = header.len();
+        assert!(fields[1..].iter().all(|field| !field.is_empty()), "{:?}", fields);
+    }).unwrap();
+    // One a second at the default update interval
+    assert!(samples >= 2, "{} samples recorded", samples);
+    assert!(columns > 1);
+    assert!(fs::read_dir(directory.join("logs")).unwrap().next().is_some(), "nothing logged");
+
+    let _ = fs::remove_dir_all(&directory);
+}